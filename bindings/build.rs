@@ -0,0 +1,5 @@
+fn main() {
+    // Node addons resolve the node symbols when loaded
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
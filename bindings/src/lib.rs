@@ -0,0 +1,202 @@
+use anyhow::{anyhow, Context};
+use libreofficekit::Office;
+use office_convert_server::{
+    config::{Backend, OfficeLogLevel, QueueConfig, TrimConfig, WatchdogConfig},
+    convert::{ConvertOptions, DocumentInput, DocumentPassword, LibreOfficeBackend},
+    formats::TargetFormat,
+    runner::{create_office_runner, OfficeHandle},
+    soffice::SofficeBackend,
+    stub::StubBackend,
+};
+use std::{env::temp_dir, path::PathBuf};
+use tokio::runtime::Runtime;
+
+#[cfg(feature = "node")]
+mod node;
+#[cfg(feature = "python")]
+mod python;
+
+/// Options the engine is started with
+#[derive(Debug, Default, Clone)]
+pub struct EngineOptions {
+    /// Path to the office install, defaults to the "LIBREOFFICE_SDK_PATH"
+    /// environment variable or the common install paths
+    pub office_path: Option<PathBuf>,
+    /// Directory to write documents to while converting, defaults to the
+    /// system temp directory
+    pub work_dir: Option<PathBuf>,
+    /// Backend used to perform conversions
+    pub backend: Backend,
+}
+
+impl EngineOptions {
+    /// Parses the name of a backend ("libreoffice", "soffice" or "stub")
+    pub fn parse_backend(name: &str) -> anyhow::Result<Backend> {
+        match name.to_ascii_lowercase().as_str() {
+            "libreoffice" => Ok(Backend::Libreoffice),
+            "soffice" => Ok(Backend::Soffice),
+            "stub" => Ok(Backend::Stub),
+            _ => Err(anyhow!("unknown backend {name}")),
+        }
+    }
+}
+
+/// Options a document is converted with
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Format to convert the document to (i.e "pdf", "docx", "png"),
+    /// defaults to "pdf"
+    pub format: Option<String>,
+    /// Password to open an encrypted document with
+    pub password: Option<String>,
+    /// Export each sheet of a spreadsheet as a separate PDF, bundled
+    /// together in a zip archive
+    pub split_sheets: bool,
+    /// Render a thumbnail of the first page along with the PDF, bundled
+    /// together in a zip archive
+    pub with_thumbnail: bool,
+    /// Extract the text of each page along with the PDF, bundled together
+    /// in a zip archive
+    pub with_text: bool,
+}
+
+impl Options {
+    /// Creates the options the runner converts the document with
+    fn into_convert_options(self) -> anyhow::Result<ConvertOptions> {
+        let format = self.format.as_deref();
+        let target_format = TargetFormat::parse(format, None)?;
+
+        // CSV and spreadsheet exports rely on options of the server endpoints
+        if let Some(format) =
+            format.filter(|format| target_format.is_none() && !format.eq_ignore_ascii_case("pdf"))
+        {
+            return Err(anyhow!(
+                "{format} output is only available through the server"
+            ));
+        }
+
+        Ok(ConvertOptions {
+            target_format,
+            password: self.password.map(DocumentPassword),
+            split_sheets: self.split_sheets,
+            with_thumbnail: self.with_thumbnail,
+            with_text: self.with_text,
+            ..Default::default()
+        })
+    }
+}
+
+/// Converted document
+#[derive(Debug)]
+pub struct Converted {
+    /// The converted file bytes
+    pub bytes: Vec<u8>,
+    /// Mime type of the converted file
+    pub content_type: String,
+    /// Non-fatal issues observed while converting
+    pub warnings: Vec<String>,
+}
+
+/// Conversion engine embedded in the application, runs the office runner
+/// on a runtime of its own so the bindings can convert from any thread.
+///
+/// Office only supports one instance per process so only one engine using
+/// the libreoffice backend can be started
+pub struct Engine {
+    /// Runtime the runner is driven by
+    runtime: Runtime,
+    /// Handle to the runner performing the conversions
+    office: OfficeHandle,
+}
+
+impl Engine {
+    /// Starts office and the runner using the provided `options`
+    pub fn start(options: EngineOptions) -> anyhow::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .context("failed to create runtime")?;
+
+        let work_dir = options.work_dir.unwrap_or_else(temp_dir);
+        let office_path = options
+            .office_path
+            .or_else(|| std::env::var_os("LIBREOFFICE_SDK_PATH").map(PathBuf::from))
+            .or_else(Office::find_install_path);
+
+        let trim_config = TrimConfig::default();
+        let queue = QueueConfig::default();
+        let watchdog = WatchdogConfig::default();
+
+        let startup = async {
+            if options.backend == Backend::Stub {
+                return create_office_runner(
+                    |_| Ok(StubBackend),
+                    trim_config,
+                    queue,
+                    watchdog,
+                    None,
+                )
+                .await;
+            }
+
+            let office_path = office_path.context("no office install path provided")?;
+
+            if options.backend == Backend::Soffice {
+                return create_office_runner(
+                    move |_| {
+                        SofficeBackend::new(&office_path, &work_dir, None, OfficeLogLevel::Off)
+                    },
+                    trim_config,
+                    queue,
+                    watchdog,
+                    None,
+                )
+                .await;
+            }
+
+            create_office_runner(
+                move |activity| {
+                    LibreOfficeBackend::new(
+                        &office_path,
+                        &work_dir,
+                        None,
+                        trim_config.target,
+                        OfficeLogLevel::Off,
+                        activity,
+                    )
+                },
+                trim_config,
+                queue,
+                watchdog,
+                None,
+            )
+            .await
+        };
+
+        let (_, office) = runtime.block_on(startup)?;
+
+        Ok(Self { runtime, office })
+    }
+
+    /// Converts the document `bytes` with the provided `options`, blocks
+    /// until the conversion is complete
+    pub fn convert(&self, bytes: Vec<u8>, options: Options) -> anyhow::Result<Converted> {
+        let options = options.into_convert_options()?;
+        let input = DocumentInput::Bytes(bytes.into());
+
+        let converted = self
+            .runtime
+            .block_on(self.office.convert_document(input, options))?;
+
+        Ok(Converted {
+            bytes: converted.bytes.into(),
+            content_type: converted.content_type.to_string(),
+            warnings: converted
+                .warnings
+                .into_iter()
+                .map(|warning| warning.message)
+                .collect(),
+        })
+    }
+}
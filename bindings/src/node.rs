@@ -0,0 +1,133 @@
+use crate::{Engine, EngineOptions, Options};
+use napi::{bindgen_prelude::*, Env, Task};
+use napi_derive::napi;
+use std::{path::PathBuf, sync::Arc};
+
+/// Options the engine is started with
+#[napi(object, js_name = "EngineOptions")]
+pub struct JsEngineOptions {
+    /// Path to the office install
+    pub office_path: Option<String>,
+    /// Directory to write documents to while converting
+    pub work_dir: Option<String>,
+    /// Backend used to perform conversions ("libreoffice", "soffice" or "stub")
+    pub backend: Option<String>,
+}
+
+/// Options a document is converted with
+#[napi(object, js_name = "ConvertOptions")]
+pub struct JsConvertOptions {
+    /// Format to convert the document to, defaults to "pdf"
+    pub format: Option<String>,
+    /// Password to open an encrypted document with
+    pub password: Option<String>,
+    /// Export each sheet of a spreadsheet as a separate PDF
+    pub split_sheets: Option<bool>,
+    /// Render a thumbnail of the first page along with the PDF
+    pub with_thumbnail: Option<bool>,
+    /// Extract the text of each page along with the PDF
+    pub with_text: Option<bool>,
+}
+
+/// Converted document
+#[napi(object, js_name = "Converted")]
+pub struct JsConverted {
+    /// The converted file bytes
+    pub bytes: Buffer,
+    /// Mime type of the converted file
+    pub content_type: String,
+    /// Non-fatal issues observed while converting
+    pub warnings: Vec<String>,
+}
+
+/// Conversion engine exposed to Node as `Engine`
+#[napi(js_name = "Engine")]
+pub struct JsEngine {
+    engine: Arc<Engine>,
+}
+
+#[napi]
+impl JsEngine {
+    /// Starts office, use `{ backend: "stub" }` to convert without an install
+    #[napi(factory)]
+    pub fn start(options: Option<JsEngineOptions>) -> Result<Self> {
+        let options = match options {
+            Some(options) => EngineOptions {
+                office_path: options.office_path.map(PathBuf::from),
+                work_dir: options.work_dir.map(PathBuf::from),
+                backend: options
+                    .backend
+                    .as_deref()
+                    .map(EngineOptions::parse_backend)
+                    .transpose()
+                    .map_err(to_js_err)?
+                    .unwrap_or_default(),
+            },
+            None => EngineOptions::default(),
+        };
+
+        let engine = Engine::start(options).map_err(to_js_err)?;
+
+        Ok(Self {
+            engine: Arc::new(engine),
+        })
+    }
+
+    /// Converts the document `data`, resolving once converted. Conversions
+    /// run on the libuv thread pool so the event loop is not blocked
+    #[napi(ts_return_type = "Promise<Converted>")]
+    pub fn convert(
+        &self,
+        data: Buffer,
+        options: Option<JsConvertOptions>,
+    ) -> AsyncTask<ConvertTask> {
+        let options = options.map(|options| Options {
+            format: options.format,
+            password: options.password,
+            split_sheets: options.split_sheets.unwrap_or_default(),
+            with_thumbnail: options.with_thumbnail.unwrap_or_default(),
+            with_text: options.with_text.unwrap_or_default(),
+        });
+
+        AsyncTask::new(ConvertTask {
+            engine: self.engine.clone(),
+            data: Some(data.to_vec()),
+            options: options.unwrap_or_default(),
+        })
+    }
+}
+
+/// Conversion performed on the libuv thread pool
+pub struct ConvertTask {
+    /// Engine performing the conversion
+    engine: Arc<Engine>,
+    /// Document to convert, taken when the conversion starts
+    data: Option<Vec<u8>>,
+    /// Options the document is converted with
+    options: Options,
+}
+
+impl Task for ConvertTask {
+    type Output = crate::Converted;
+    type JsValue = JsConverted;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let data = self.data.take().unwrap_or_default();
+        self.engine
+            .convert(data, self.options.clone())
+            .map_err(to_js_err)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(JsConverted {
+            bytes: output.bytes.into(),
+            content_type: output.content_type,
+            warnings: output.warnings,
+        })
+    }
+}
+
+/// Converts an error from the engine into a JavaScript error
+fn to_js_err(err: anyhow::Error) -> Error {
+    Error::from_reason(format!("{err:#}"))
+}
@@ -0,0 +1,100 @@
+use crate::{Engine, EngineOptions, Options};
+use pyo3::{exceptions::PyRuntimeError, prelude::*, types::PyBytes};
+use std::path::PathBuf;
+
+/// Converted document provided to Python
+#[pyclass(name = "Converted", frozen, get_all)]
+struct PyConverted {
+    /// The converted file bytes
+    bytes: Py<PyBytes>,
+    /// Mime type of the converted file
+    content_type: String,
+    /// Non-fatal issues observed while converting
+    warnings: Vec<String>,
+}
+
+/// Conversion engine exposed to Python as `office_convert.Engine`
+#[pyclass(name = "Engine", frozen)]
+struct PyEngine {
+    engine: Engine,
+}
+
+#[pymethods]
+impl PyEngine {
+    /// Starts office, use `backend="stub"` to convert without an install
+    #[new]
+    #[pyo3(signature = (office_path = None, work_dir = None, backend = None))]
+    fn new(
+        py: Python<'_>,
+        office_path: Option<PathBuf>,
+        work_dir: Option<PathBuf>,
+        backend: Option<&str>,
+    ) -> PyResult<Self> {
+        let options = EngineOptions {
+            office_path,
+            work_dir,
+            backend: backend
+                .map(EngineOptions::parse_backend)
+                .transpose()
+                .map_err(to_py_err)?
+                .unwrap_or_default(),
+        };
+
+        let engine = py
+            .allow_threads(|| Engine::start(options))
+            .map_err(to_py_err)?;
+
+        Ok(Self { engine })
+    }
+
+    /// Converts the document `data`, the GIL is released while converting
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (
+        data,
+        format = None,
+        password = None,
+        split_sheets = false,
+        with_thumbnail = false,
+        with_text = false,
+    ))]
+    fn convert(
+        &self,
+        py: Python<'_>,
+        data: Vec<u8>,
+        format: Option<String>,
+        password: Option<String>,
+        split_sheets: bool,
+        with_thumbnail: bool,
+        with_text: bool,
+    ) -> PyResult<PyConverted> {
+        let options = Options {
+            format,
+            password,
+            split_sheets,
+            with_thumbnail,
+            with_text,
+        };
+
+        let converted = py
+            .allow_threads(|| self.engine.convert(data, options))
+            .map_err(to_py_err)?;
+
+        Ok(PyConverted {
+            bytes: PyBytes::new(py, &converted.bytes).unbind(),
+            content_type: converted.content_type,
+            warnings: converted.warnings,
+        })
+    }
+}
+
+/// Converts an error from the engine into a Python `RuntimeError`
+fn to_py_err(err: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{err:#}"))
+}
+
+#[pymodule]
+fn office_convert(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyEngine>()?;
+    module.add_class::<PyConverted>()?;
+    Ok(())
+}
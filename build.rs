@@ -0,0 +1,83 @@
+use std::{
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    println!("cargo:rustc-env=LO_NATIVE_GIT_COMMIT={}", git_commit());
+    println!(
+        "cargo:rustc-env=LO_NATIVE_BUILD_TIMESTAMP={}",
+        format_timestamp(build_time())
+    );
+}
+
+/// Provides the commit being built, the "GIT_COMMIT" environment variable
+/// takes priority for builds outside of a git checkout (i.e docker)
+fn git_commit() -> String {
+    if let Some(commit) = std::env::var("GIT_COMMIT")
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+    {
+        return commit.trim().to_string();
+    }
+
+    Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .filter(|commit| !commit.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Provides the build time in seconds since the unix epoch, the
+/// "SOURCE_DATE_EPOCH" environment variable is used for reproducible builds
+fn build_time() -> u64 {
+    if let Some(epoch) = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+    {
+        return epoch;
+    }
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default()
+}
+
+/// Formats seconds since the unix epoch as a RFC 3339 UTC timestamp
+fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86_400) as i64;
+    let time = seconds % 86_400;
+
+    // Converts days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_portion = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_portion + 2) / 5 + 1;
+    let month = if month_portion < 10 {
+        month_portion + 3
+    } else {
+        month_portion - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3_600,
+        (time % 3_600) / 60,
+        time % 60
+    )
+}
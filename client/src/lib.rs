@@ -1,17 +1,220 @@
 use async_trait::async_trait;
 use bytes::Bytes;
-use reqwest::multipart::{Form, Part};
+use futures_util::{Stream, StreamExt};
+use reqwest::{
+    multipart::{Form, Part},
+    Method, RequestBuilder, Response,
+};
 use serde::Deserialize;
-use std::{sync::Arc, time::Duration};
+use sha2::{Digest, Sha256};
+use std::{pin::Pin, sync::Arc, time::Duration};
 use thiserror::Error;
+#[cfg(feature = "native")]
+use tokio::net::TcpStream;
+use tokio::sync::{Semaphore, SemaphorePermit};
+#[cfg(feature = "native")]
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, http::HeaderValue, Message},
+    MaybeTlsStream, WebSocketStream,
+};
 
+#[cfg(feature = "native")]
 pub mod load;
+pub mod service;
 
-pub use load::{LoadBalanceError, OfficeConvertLoadBalancer};
+#[cfg(feature = "native")]
+pub use load::{
+    BackendStats, ConvertHints, JobLocation, LoadBalanceError, LoadBalanceStrategy,
+    OfficeConvertLoadBalancer, ResultLocation,
+};
+pub use reqwest::StatusCode;
+pub use service::ConvertRequest;
+
+/// Converted file stored in the output directory of the server that
+/// converted it rather than being sent back
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoredOutput {
+    /// Path of the file relative to the output directory of the server
+    pub path: String,
+    /// Mime type of the converted file
+    pub content_type: String,
+    /// Size of the converted file in bytes
+    pub size: u64,
+    /// SHA-256 hex digest of the converted file
+    pub sha256: String,
+    /// JWS signing the converted file when result signing is enabled
+    pub signature: Option<String>,
+    /// Expiring pre-signed URL to download the file (Relative to the server
+    /// unless the server is behind a trusted proxy), [None] when pre-signed
+    /// URLs are not enabled on the server
+    pub url: Option<String>,
+}
+
+/// State of a background conversion job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// Waiting for earlier jobs
+    Queued,
+    /// Being converted
+    Running,
+    /// Conversion failed
+    Failed,
+    /// Converted, the result can be downloaded
+    Done,
+}
+
+impl JobState {
+    /// Whether the job has finished (Either failed or done)
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobState::Failed | JobState::Done)
+    }
+}
+
+/// Non-fatal issue observed while converting
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConvertWarning {
+    /// Code identifying the kind of issue
+    pub code: String,
+    /// Description of the issue
+    pub message: String,
+}
+
+/// Status of a file converting in the background on the server
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobStatus {
+    /// Unique ID of the job
+    pub id: String,
+    /// Current state of the job
+    pub state: JobState,
+    /// Name of the uploaded file
+    #[serde(default)]
+    pub file_name: Option<String>,
+    /// When the job was submitted (Seconds since the unix epoch)
+    pub created_at: u64,
+    /// When the job finished (Seconds since the unix epoch)
+    #[serde(default)]
+    pub finished_at: Option<u64>,
+    /// When the result of the job is removed (Seconds since the unix epoch)
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// When the status of the job is removed (Seconds since the unix epoch)
+    #[serde(default)]
+    pub status_expires_at: Option<u64>,
+    /// Whether the result was removed, either after expiring or by a client
+    #[serde(default)]
+    pub result_expired: bool,
+    /// Time the job waited before converting in milliseconds
+    #[serde(default)]
+    pub queued_ms: Option<u64>,
+    /// Time the job took to convert in milliseconds
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    /// Mime type of the result
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Size of the result in bytes
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// SHA-256 hex digest of the result
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Non-fatal issues observed while converting
+    #[serde(default)]
+    pub warnings: Vec<ConvertWarning>,
+    /// Error code when the job failed
+    #[serde(default)]
+    pub error_code: Option<String>,
+    /// Error reason when the job failed
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Progress of a converting job reported by a [JobEvent::Progress]
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobProgress {
+    /// Phase office is processing the job in ("loading", "converting" or
+    /// "saving"), "idle" while the job waits for the converter
+    pub phase: String,
+    /// Progress percentage office reported for the phase
+    #[serde(default)]
+    pub progress: Option<u8>,
+}
+
+/// Change to the state of a background job pushed by the server
+#[derive(Debug, Clone)]
+pub enum JobEvent {
+    /// Job is waiting for earlier jobs
+    Queued(JobStatus),
+    /// Job is converting
+    Progress(JobProgress),
+    /// Job converted, the result can be downloaded
+    Done(JobStatus),
+    /// Job failed to convert
+    Failed(JobStatus),
+}
+
+impl JobEvent {
+    /// Parses a server-sent event `block` (The lines of one event), [None]
+    /// for comments (i.e keep alive messages) and unknown events
+    fn parse(block: &str) -> Option<Result<JobEvent, serde_json::Error>> {
+        let mut name = None;
+        let mut data: Option<String> = None;
+
+        for line in block.lines() {
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+
+            match field {
+                "event" => name = Some(value),
+                "data" => match &mut data {
+                    Some(data) => {
+                        data.push('\n');
+                        data.push_str(value);
+                    }
+                    None => data = Some(value.to_string()),
+                },
+                _ => {}
+            }
+        }
+
+        let data = data?;
+        let event = match name? {
+            "queued" => serde_json::from_str(&data).map(JobEvent::Queued),
+            "progress" => serde_json::from_str(&data).map(JobEvent::Progress),
+            "done" => serde_json::from_str(&data).map(JobEvent::Done),
+            "failed" => serde_json::from_str(&data).map(JobEvent::Failed),
+            _ => return None,
+        };
+
+        Some(event)
+    }
+}
+
+/// Stream of the events of a background job, streams are not [Send] on
+/// WASM where requests are made through the browser
+#[cfg(not(target_arch = "wasm32"))]
+pub type JobEventStream = Pin<Box<dyn Stream<Item = Result<JobEvent, RequestError>> + Send>>;
+
+/// Stream of the events of a background job, streams are not [Send] on
+/// WASM where requests are made through the browser
+#[cfg(target_arch = "wasm32")]
+pub type JobEventStream = Pin<Box<dyn Stream<Item = Result<JobEvent, RequestError>>>>;
+
+impl JobStatus {
+    /// Parses the error code of a failed job
+    pub fn code(&self) -> Option<ErrorCode> {
+        self.error_code.as_deref().map(ErrorCode::parse)
+    }
+}
 
 /// Trait implement by entities that can convert office files into
 /// PDF files.
-#[async_trait]
+///
+/// Futures are not required to be [Send] on WASM where requests are made
+/// through the browser
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait ConvertOffice {
     /// Converts the provided office file format bytes into a
     /// PDF returning the PDF file bytes
@@ -27,6 +230,12 @@ pub struct OfficeConvertClient {
     http: reqwest::Client,
     /// Host the office convert server is running on
     host: Arc<str>,
+    /// Whether to verify the checksum of converted files
+    verify_checksum: bool,
+    /// Limits the number of uploads in flight at once, shared by clones
+    in_flight: Option<Arc<Semaphore>>,
+    /// API key sent in the "X-Api-Key" header of every request
+    api_key: Option<Arc<str>>,
 }
 
 /// Errors that can occur during setup
@@ -40,29 +249,515 @@ pub enum CreateError {
 /// Errors that can occur during a request
 #[derive(Debug, Error)]
 pub enum RequestError {
-    /// Failed to request the server
-    #[error(transparent)]
-    RequestFailed(reqwest::Error),
+    /// Failed to establish a connection to the server
+    #[error("failed to connect to server (request {request_id}): {source}")]
+    Connect {
+        request_id: String,
+        source: reqwest::Error,
+    },
+
+    /// Request to the server timed out
+    #[error("request {request_id} timed out: {source}")]
+    Timeout {
+        request_id: String,
+        source: reqwest::Error,
+    },
+
+    /// Other network failure while requesting the server
+    #[error("request {request_id} failed: {source}")]
+    RequestFailed {
+        request_id: String,
+        source: reqwest::Error,
+    },
 
     /// Response from the server was invalid
+    #[error("invalid response for request {request_id}: {source}")]
+    InvalidResponse {
+        request_id: String,
+        source: reqwest::Error,
+    },
+
+    /// Failure on the state subscription WebSocket
+    #[cfg(feature = "native")]
+    #[error(transparent)]
+    WebSocket(tokio_tungstenite::tungstenite::Error),
+
+    /// State message from the server was invalid
     #[error(transparent)]
-    InvalidResponse(reqwest::Error),
+    InvalidStateMessage(serde_json::Error),
+
+    /// Job event from the server was invalid
+    #[error("invalid job event: {0}")]
+    InvalidJobEvent(serde_json::Error),
+
+    /// Server rejected the request (4xx status)
+    #[error("{0}")]
+    ClientError(ErrorResponse),
+
+    /// Server failed to handle the request (5xx status)
+    #[error("{0}")]
+    ServerError(ErrorResponse),
 
-    /// Reached timeout when trying to connect
-    #[error("server connection timed out")]
-    ServerConnectTimeout,
+    /// Load balancer could not handle the request
+    #[cfg(feature = "native")]
+    #[error(transparent)]
+    LoadBalance(#[from] LoadBalanceError),
 
-    /// Error message from the convert server reply
-    #[error("{reason}")]
-    ErrorResponse {
-        reason: String,
-        backtrace: Option<String>,
+    /// Checksum of the converted file did not match the checksum provided
+    /// by the server, the response was truncated or corrupted
+    #[error(
+        "response checksum mismatch for request {request_id} (expected {expected}, got {actual})"
+    )]
+    ChecksumMismatch {
+        request_id: String,
+        expected: String,
+        actual: String,
     },
+
+    /// File was empty (or only whitespace), rejected without sending it
+    /// to the server
+    #[error("file to convert is empty")]
+    EmptyFile,
+
+    /// Stored output has no download URL, the server does not have
+    /// pre-signed URLs enabled
+    #[error("stored output {path} has no download url")]
+    NoDownloadUrl { path: String },
+}
+
+/// Whether sending a request failed to connect to the server, browsers don't
+/// report connection failures separately so they are never identified on WASM
+#[cfg(not(target_arch = "wasm32"))]
+fn is_connect(source: &reqwest::Error) -> bool {
+    source.is_connect()
+}
+
+/// Whether sending a request failed to connect to the server, browsers don't
+/// report connection failures separately so they are never identified on WASM
+#[cfg(target_arch = "wasm32")]
+fn is_connect(_source: &reqwest::Error) -> bool {
+    false
+}
+
+impl RequestError {
+    /// Creates a [RequestError] from an error sending a request, categorizing
+    /// the network error
+    fn from_send(source: reqwest::Error, request_id: &str) -> Self {
+        let request_id = request_id.to_string();
+
+        if source.is_timeout() {
+            RequestError::Timeout { request_id, source }
+        } else if is_connect(&source) {
+            RequestError::Connect { request_id, source }
+        } else {
+            RequestError::RequestFailed { request_id, source }
+        }
+    }
+
+    /// Creates a [RequestError] from an error reading a response
+    fn invalid_response(source: reqwest::Error, request_id: &str) -> Self {
+        RequestError::InvalidResponse {
+            request_id: request_id.to_string(),
+            source,
+        }
+    }
+
+    /// Provides the ID of the request that failed, sent to the server in
+    /// the "X-Request-Id" header and included in the server logs
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            RequestError::Connect { request_id, .. }
+            | RequestError::Timeout { request_id, .. }
+            | RequestError::RequestFailed { request_id, .. }
+            | RequestError::InvalidResponse { request_id, .. }
+            | RequestError::ChecksumMismatch { request_id, .. } => Some(request_id),
+            RequestError::ClientError(response) | RequestError::ServerError(response) => {
+                Some(&response.request_id)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the request may succeed if attempted again (On the same or
+    /// another server)
+    ///
+    /// Network failures, timeouts, and "queue full" or overloaded responses
+    /// are retryable. Encrypted or corrupted files and rejected requests
+    /// will fail again so they are not
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            RequestError::Connect { .. }
+            | RequestError::Timeout { .. }
+            | RequestError::RequestFailed { .. }
+            | RequestError::ChecksumMismatch { .. } => true,
+            #[cfg(feature = "native")]
+            RequestError::WebSocket(_) => true,
+            // Failing to read the body is a network failure, failing to decode it is not
+            RequestError::InvalidResponse { source, .. } => !source.is_decode(),
+            #[cfg(feature = "native")]
+            RequestError::LoadBalance(_) => false,
+            RequestError::InvalidStateMessage(_)
+            | RequestError::InvalidJobEvent(_)
+            | RequestError::EmptyFile
+            | RequestError::NoDownloadUrl { .. } => false,
+            RequestError::ClientError(response) | RequestError::ServerError(response) => {
+                response.is_retryable()
+            }
+        }
+    }
+
+    /// Whether the error is a network level failure where no response
+    /// was received from the server
+    pub fn is_network(&self) -> bool {
+        matches!(
+            self,
+            RequestError::Connect { .. }
+                | RequestError::Timeout { .. }
+                | RequestError::RequestFailed { .. }
+        )
+    }
+
+    /// Provides the error response from the server if the server
+    /// responded with an error
+    pub fn response(&self) -> Option<&ErrorResponse> {
+        match self {
+            RequestError::ClientError(response) | RequestError::ServerError(response) => {
+                Some(response)
+            }
+            _ => None,
+        }
+    }
+
+    /// Provides the HTTP status of the server error response
+    pub fn status(&self) -> Option<StatusCode> {
+        self.response().map(|response| response.status)
+    }
+
+    /// Provides the parsed error code from the server error response
+    pub fn code(&self) -> Option<&ErrorCode> {
+        self.response().and_then(|response| response.code.as_ref())
+    }
+}
+
+/// Error response from the convert server
+#[derive(Debug, Error)]
+#[error("{reason} (request {request_id})")]
+pub struct ErrorResponse {
+    /// ID of the request
+    pub request_id: String,
+    /// HTTP status of the response
+    pub status: StatusCode,
+    /// Error code provided by the server (Older servers don't provide codes)
+    pub code: Option<ErrorCode>,
+    /// Server reason for the error
+    pub reason: String,
+    /// Structured details about the error if available (i.e the limit
+    /// that was exceeded)
+    pub details: Option<serde_json::Value>,
+    /// Server backtrace if available
+    pub backtrace: Option<String>,
+    /// Tail of the LibreOffice log output for failed conversions, empty
+    /// unless the server collects the office log
+    pub log: Vec<String>,
+}
+
+impl ErrorResponse {
+    /// Whether the request may succeed if attempted again, known error codes
+    /// take priority over the response status
+    pub fn is_retryable(&self) -> bool {
+        match &self.code {
+            Some(
+                ErrorCode::Timeout
+                | ErrorCode::QueueFull
+                | ErrorCode::Restarting
+                | ErrorCode::ChecksumMismatch
+                | ErrorCode::RateLimited
+                | ErrorCode::TooManyConversions
+                | ErrorCode::AuthorizerUnavailable
+                | ErrorCode::DiskFull,
+            ) => true,
+            Some(
+                ErrorCode::Encrypted
+                | ErrorCode::WrongPassword
+                | ErrorCode::Corrupted
+                | ErrorCode::ConverterRestarted
+                | ErrorCode::EmbeddedLimit
+                | ErrorCode::EmptyFile
+                | ErrorCode::FileTooSmall
+                | ErrorCode::FormatMismatch
+                | ErrorCode::Unauthorized
+                | ErrorCode::Forbidden
+                | ErrorCode::FilterMissing,
+            ) => false,
+            _ => matches!(
+                self.status,
+                StatusCode::REQUEST_TIMEOUT
+                    | StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+        }
+    }
+
+    /// Time the server asked the client to wait before retrying when the
+    /// API key of the client was rate limited
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.details
+            .as_ref()?
+            .get("retry_after_ms")?
+            .as_u64()
+            .map(Duration::from_millis)
+    }
+}
+
+/// Known error codes provided by the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// File is encrypted with a password
+    Encrypted,
+    /// Password provided for an encrypted file was not correct
+    WrongPassword,
+    /// File is malformed or corrupted
+    Corrupted,
+    /// Server timed out processing the request
+    Timeout,
+    /// Server queue is full
+    QueueFull,
+    /// Office is being started or recycled on the server
+    Restarting,
+    /// Office failed while processing the file and was restarted, the file
+    /// may have caused the failure
+    ConverterRestarted,
+    /// Server is running in degraded mode as office failed to start
+    Degraded,
+    /// Functionality is not supported by the server conversion backend
+    Unsupported,
+    /// Uploaded file did not match its checksum, the upload was truncated
+    ChecksumMismatch,
+    /// File exceeded the server limits on embedded objects or images
+    EmbeddedLimit,
+    /// Uploaded file was empty
+    EmptyFile,
+    /// Uploaded file was smaller than the server minimum upload size
+    FileTooSmall,
+    /// Uploaded file content did not match its extension
+    FormatMismatch,
+    /// API key of the client was missing or not known by the server
+    Unauthorized,
+    /// API key of the client used up its rate of conversions, the retry
+    /// delay is provided by [ErrorResponse::retry_after]
+    RateLimited,
+    /// API key of the client already has the maximum number of conversions
+    /// running on the server
+    TooManyConversions,
+    /// Request was denied by the external authorizer of the server
+    Forbidden,
+    /// External authorizer of the server could not be reached
+    AuthorizerUnavailable,
+    /// Office on the server has no filter for the requested format
+    FilterMissing,
+    /// Server ran out of disk space while converting
+    DiskFull,
+    /// Server was not started with background jobs enabled
+    JobsDisabled,
+    /// Job is not known by the server or has expired
+    JobNotFound,
+    /// Job has not finished converting (or failed) so has no result
+    JobNotDone,
+    /// Result of the job was removed after expiring (or by a client)
+    JobResultExpired,
+    /// Error code not known by this client
+    Other(String),
+}
+
+impl ErrorCode {
+    /// Parses an error code from the server
+    pub fn parse(code: &str) -> Self {
+        match code {
+            "encrypted" => ErrorCode::Encrypted,
+            "wrong_password" => ErrorCode::WrongPassword,
+            "corrupted" => ErrorCode::Corrupted,
+            "timeout" => ErrorCode::Timeout,
+            "queue_full" => ErrorCode::QueueFull,
+            "restarting" => ErrorCode::Restarting,
+            "converter_restarted" => ErrorCode::ConverterRestarted,
+            "degraded" => ErrorCode::Degraded,
+            "unsupported" => ErrorCode::Unsupported,
+            "checksum_mismatch" => ErrorCode::ChecksumMismatch,
+            "embedded_limit" => ErrorCode::EmbeddedLimit,
+            "empty_file" => ErrorCode::EmptyFile,
+            "file_too_small" => ErrorCode::FileTooSmall,
+            "format_mismatch" => ErrorCode::FormatMismatch,
+            "unauthorized" => ErrorCode::Unauthorized,
+            "rate_limited" => ErrorCode::RateLimited,
+            "too_many_conversions" => ErrorCode::TooManyConversions,
+            "forbidden" => ErrorCode::Forbidden,
+            "authorizer_unavailable" => ErrorCode::AuthorizerUnavailable,
+            "filter_missing" => ErrorCode::FilterMissing,
+            "disk_full" => ErrorCode::DiskFull,
+            "jobs_disabled" => ErrorCode::JobsDisabled,
+            "job_not_found" => ErrorCode::JobNotFound,
+            "job_not_done" => ErrorCode::JobNotDone,
+            "job_result_expired" => ErrorCode::JobResultExpired,
+            other => ErrorCode::Other(other.to_string()),
+        }
+    }
+
+    /// Provides the string form of the code
+    pub fn as_str(&self) -> &str {
+        match self {
+            ErrorCode::Encrypted => "encrypted",
+            ErrorCode::WrongPassword => "wrong_password",
+            ErrorCode::Corrupted => "corrupted",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::QueueFull => "queue_full",
+            ErrorCode::Restarting => "restarting",
+            ErrorCode::ConverterRestarted => "converter_restarted",
+            ErrorCode::Degraded => "degraded",
+            ErrorCode::Unsupported => "unsupported",
+            ErrorCode::ChecksumMismatch => "checksum_mismatch",
+            ErrorCode::EmbeddedLimit => "embedded_limit",
+            ErrorCode::EmptyFile => "empty_file",
+            ErrorCode::FileTooSmall => "file_too_small",
+            ErrorCode::FormatMismatch => "format_mismatch",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::TooManyConversions => "too_many_conversions",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::AuthorizerUnavailable => "authorizer_unavailable",
+            ErrorCode::FilterMissing => "filter_missing",
+            ErrorCode::DiskFull => "disk_full",
+            ErrorCode::JobsDisabled => "jobs_disabled",
+            ErrorCode::JobNotFound => "job_not_found",
+            ErrorCode::JobNotDone => "job_not_done",
+            ErrorCode::JobResultExpired => "job_result_expired",
+            ErrorCode::Other(code) => code,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct StatusResponse {
     pub is_busy: bool,
+    /// Whether office is running on the server, [None] for servers that do
+    /// not report it
+    #[serde(default)]
+    pub warm: Option<bool>,
+    /// Whether the server is ready to accept work, [None] for servers that
+    /// do not report it
+    #[serde(default)]
+    pub ready: Option<bool>,
+    /// Number of requests waiting for the server, [None] for servers that
+    /// do not report it
+    #[serde(default)]
+    pub queue_depth: Option<usize>,
+    /// Detailed status of each worker, empty for servers that do not
+    /// report it
+    #[serde(default)]
+    pub workers: Vec<WorkerStatus>,
+    /// Totals of the work processed by the server, [None] for servers
+    /// that do not report it
+    #[serde(default)]
+    pub totals: Option<WorkTotals>,
+}
+
+/// Detailed status of a server worker
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkerStatus {
+    /// ID of the worker
+    pub id: usize,
+    /// State of the worker ("idle", "converting" or "restarting")
+    pub state: String,
+    /// Whether office is running for the worker
+    pub warm: bool,
+    /// Whether a spare office is ready to replace the running office,
+    /// `false` for servers that do not report it
+    #[serde(default)]
+    pub standby: bool,
+    /// Phase of the current work ("idle", "loading", "converting", "saving")
+    pub phase: String,
+    /// Approximate completion percentage of the current phase
+    pub progress: Option<u8>,
+    /// Time the current work has been running for in milliseconds
+    pub job_age_ms: Option<u64>,
+}
+
+/// Totals of the work processed by a server
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct WorkTotals {
+    /// Work that completed successfully
+    pub completed: u64,
+    /// Work that failed
+    pub failed: u64,
+    /// Failures by class (not reported by older servers)
+    #[serde(default)]
+    pub failures: Option<FailureTotals>,
+    /// Self tests run after repeated failures
+    #[serde(default)]
+    pub selftests: u64,
+    /// Times office was recycled after a failed self test
+    #[serde(default)]
+    pub recoveries: u64,
+}
+
+/// Totals of the failures reported by a server for each class of failure
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct FailureTotals {
+    /// File was encrypted with a password
+    pub encrypted: u64,
+    /// File was malformed or corrupted
+    pub corrupted: u64,
+    /// Work timed out waiting for the server
+    pub timeout: u64,
+    /// Functionality was not supported by the server
+    pub unsupported: u64,
+    /// Office failed to start or stopped
+    pub worker_crash: u64,
+    /// Any other failure
+    pub other: u64,
+}
+
+/// State of the server pushed over a state subscription
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ServerState {
+    /// Whether the server is busy converting a document
+    pub is_busy: bool,
+    /// Whether the server is currently converting a document
+    pub converting: bool,
+    /// Number of requests waiting for the server
+    pub queue_depth: usize,
+}
+
+/// Subscription to the state of a server, receives the current state
+/// when connected followed by every state transition
+#[cfg(feature = "native")]
+pub struct StateSubscription {
+    /// Underlying WebSocket connection
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+#[cfg(feature = "native")]
+impl StateSubscription {
+    /// Waits for the next state from the server, [None] is returned
+    /// once the connection is closed
+    pub async fn next(&mut self) -> Option<Result<ServerState, RequestError>> {
+        loop {
+            let message = match self.socket.next().await? {
+                Ok(value) => value,
+                Err(err) => return Some(Err(RequestError::WebSocket(err))),
+            };
+
+            let text = match message {
+                Message::Text(value) => value,
+                Message::Close(_) => return None,
+                // Other messages are not used for state
+                _ => continue,
+            };
+
+            return Some(serde_json::from_str(&text).map_err(RequestError::InvalidStateMessage));
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -73,6 +768,30 @@ pub struct SupportedFormat {
     pub mime: String,
 }
 
+/// Supported file formats of a single document family
+#[derive(Debug, Deserialize)]
+pub struct SupportedFormatGroup {
+    /// Family of the formats (text, spreadsheet, presentation, graphics, other)
+    pub family: String,
+    /// File extensions documents of the family can be exported to
+    pub export_targets: Vec<String>,
+    /// Formats within the family
+    pub formats: Vec<GroupedFormat>,
+}
+
+/// File format within a [SupportedFormatGroup]
+#[derive(Debug, Deserialize)]
+pub struct GroupedFormat {
+    /// Name of the file format
+    pub name: String,
+    /// Mime type of the format
+    pub mime: String,
+    /// Common file extensions of the format
+    pub extensions: Vec<String>,
+    /// Whether documents of the family can be exported to this format
+    pub export_target: bool,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct VersionResponse {
     /// Major version of LibreOffice
@@ -83,22 +802,166 @@ pub struct VersionResponse {
     pub build_id: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ServerVersionResponse {
+    /// Version of the server
+    pub version: String,
+    /// Git commit the server was built from ("unknown" when not known)
+    pub git_commit: String,
+    /// Time the server was built at (RFC 3339, UTC)
+    pub build_timestamp: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DocumentStats {
+    /// Type of document (text, spreadsheet, presentation, drawing, other)
+    pub document_type: String,
+    /// Number of pages in the document
+    pub page_count: Option<u64>,
+    /// Number of words in the document
+    pub word_count: Option<u64>,
+    /// Number of characters in the document
+    pub character_count: Option<u64>,
+    /// Number of non-whitespace characters in the document
+    pub non_whitespace_character_count: Option<u64>,
+    /// Number of paragraphs in the document
+    pub paragraph_count: Option<u64>,
+    /// Number of tables in the document (For spreadsheets this is
+    /// the number of sheets)
+    pub table_count: Option<u64>,
+    /// Number of used cells (Spreadsheets only)
+    pub cell_count: Option<u64>,
+    /// Number of images in the document
+    pub image_count: Option<u64>,
+    /// Number of embedded objects in the document
+    pub object_count: Option<u64>,
+    /// Number of slides or pages (Presentations and drawings only)
+    pub slide_count: Option<u64>,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct ErrorResponse {
+struct RawErrorResponse {
     /// Server reason for the error
     reason: String,
+    /// Server error code if available
+    code: Option<String>,
+    /// Structured error details if available
+    #[serde(default)]
+    details: Option<serde_json::Value>,
     /// Server backtrace if available
     backtrace: Option<String>,
+    /// Tail of the office log output if collected
+    #[serde(default)]
+    log: Vec<String>,
+}
+
+/// Header used to correlate requests with the server logs
+const REQUEST_ID: &str = "x-request-id";
+
+/// Header used to provide the API key of the client
+const API_KEY: &str = "x-api-key";
+
+/// Creates a new unique request ID
+pub fn new_request_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Checks if the `file` is empty (or only whitespace and null bytes), the
+/// server would reject it so it is not worth uploading or taking a place in
+/// the load balancer
+fn is_empty_file(file: &[u8]) -> bool {
+    file.iter()
+        .all(|byte| byte.is_ascii_whitespace() || *byte == 0)
+}
+
+/// Converts the `files` using the `converter` with at most `max_concurrency`
+/// conversions running at once, providing the result of each file in the
+/// same order as the `files`
+async fn convert_concurrently<C>(
+    converter: &C,
+    files: Vec<Vec<u8>>,
+    max_concurrency: usize,
+) -> Vec<Result<Bytes, RequestError>>
+where
+    C: ConvertOffice + Sync + ?Sized,
+{
+    futures_util::stream::iter(files)
+        .map(|file| converter.convert(file))
+        .buffered(max_concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Checks the `response` status, creating a [RequestError] from the error
+/// response when the server responded with an error
+async fn check_response(response: Response, request_id: &str) -> Result<Response, RequestError> {
+    let status = response.status();
+
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(response);
+    }
+
+    let body = response
+        .bytes()
+        .await
+        .map_err(|err| RequestError::invalid_response(err, request_id))?;
+
+    let request_id = request_id.to_string();
+
+    // Responses not from the server itself (i.e proxies) may not be JSON
+    let response = match serde_json::from_slice::<RawErrorResponse>(&body) {
+        Ok(body) => ErrorResponse {
+            request_id,
+            status,
+            code: body.code.as_deref().map(ErrorCode::parse),
+            reason: body.reason,
+            details: body.details,
+            backtrace: body.backtrace,
+            log: body.log,
+        },
+        Err(_) => ErrorResponse {
+            request_id,
+            status,
+            code: None,
+            reason: match String::from_utf8_lossy(&body).trim() {
+                "" => status.to_string(),
+                body => body.to_string(),
+            },
+            details: None,
+            backtrace: None,
+            log: Vec::new(),
+        },
+    };
+
+    Err(if status.is_client_error() {
+        RequestError::ClientError(response)
+    } else {
+        RequestError::ServerError(response)
+    })
 }
 
 #[derive(Debug, Clone)]
 pub struct ClientOptions {
     /// Connection timeout used when checking the status of the server
+    /// (Not available on WASM)
     pub connect_timeout: Option<Duration>,
 
-    /// Timeout when reading responses from the server
+    /// Timeout when reading responses from the server (Not available on WASM)
     pub read_timeout: Option<Duration>,
+
+    /// Verify converted files against the SHA-256 checksum provided by the
+    /// server to detect truncation or corruption (i.e over flaky proxies),
+    /// uploads are also sent with their checksum for the server to verify
+    pub verify_checksum: bool,
+
+    /// Maximum number of uploads (conversions and extractions) in flight at
+    /// once, further uploads wait for one to finish before being sent
+    pub max_in_flight: Option<usize>,
+
+    /// API key sent in the "X-Api-Key" header of every request, required by
+    /// servers configured with API keys
+    pub api_key: Option<String>,
 }
 
 impl Default for ClientOptions {
@@ -107,6 +970,9 @@ impl Default for ClientOptions {
             // Allow the connection to fail if not established in 700ms
             connect_timeout: Some(Duration::from_millis(700)),
             read_timeout: None,
+            verify_checksum: false,
+            max_in_flight: None,
+            api_key: None,
         }
     }
 }
@@ -132,18 +998,30 @@ impl OfficeConvertClient {
     where
         T: Into<Arc<str>>,
     {
-        let mut builder = reqwest::Client::builder();
+        let builder = reqwest::Client::builder();
 
-        if let Some(connect_timeout) = options.connect_timeout {
-            builder = builder.connect_timeout(connect_timeout);
-        }
+        // Timeouts are left to the browser (or runtime) on WASM
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = {
+            let mut builder = builder;
 
-        if let Some(connect_timeout) = options.read_timeout {
-            builder = builder.read_timeout(connect_timeout);
-        }
+            if let Some(connect_timeout) = options.connect_timeout {
+                builder = builder.connect_timeout(connect_timeout);
+            }
+
+            if let Some(connect_timeout) = options.read_timeout {
+                builder = builder.read_timeout(connect_timeout);
+            }
+
+            builder
+        };
 
         let client = builder.build().map_err(CreateError::Builder)?;
-        Self::from_client(host, client)
+        let client = Self::from_client(host, client)?;
+        Ok(client
+            .with_verify_checksum(options.verify_checksum)
+            .with_max_in_flight(options.max_in_flight)
+            .with_api_key(options.api_key))
     }
 
     /// Create an office convert client from an existing [reqwest::Client] if
@@ -159,73 +1037,187 @@ impl OfficeConvertClient {
         Ok(Self {
             http: client,
             host: host.into(),
+            verify_checksum: false,
+            in_flight: None,
+            api_key: None,
         })
     }
 
+    /// Sets whether converted files are verified against the SHA-256 checksum
+    /// provided by the server (Servers that don't provide a checksum are not verified)
+    /// and whether uploads are sent with their checksum for the server to verify
+    pub fn with_verify_checksum(mut self, verify_checksum: bool) -> Self {
+        self.verify_checksum = verify_checksum;
+        self
+    }
+
+    /// Sets the maximum number of uploads (conversions and extractions) in
+    /// flight at once, further uploads wait for one to finish before being
+    /// sent. The limit is shared with clones of the client, [None] removes it
+    pub fn with_max_in_flight(mut self, max_in_flight: Option<usize>) -> Self {
+        self.in_flight = max_in_flight.map(|max| Arc::new(Semaphore::new(max.max(1))));
+        self
+    }
+
+    /// Sets the API key sent in the "X-Api-Key" header of every request,
+    /// [None] sends requests without a key
+    pub fn with_api_key<T>(mut self, api_key: Option<T>) -> Self
+    where
+        T: Into<Arc<str>>,
+    {
+        self.api_key = api_key.map(Into::into);
+        self
+    }
+
+    /// Creates a request to the `route` with the `request_id` and the API
+    /// key of the client
+    fn request(&self, method: Method, route: String, request_id: &str) -> RequestBuilder {
+        let request = self
+            .http
+            .request(method, route)
+            .header(REQUEST_ID, request_id);
+
+        match self.api_key.as_deref() {
+            Some(api_key) => request.header(API_KEY, api_key),
+            None => request,
+        }
+    }
+
+    /// Waits for a place for an upload when the number of uploads in flight
+    /// is limited, the place is held until the permit is dropped
+    async fn acquire_in_flight(&self) -> Option<SemaphorePermit<'_>> {
+        match &self.in_flight {
+            Some(in_flight) => in_flight.acquire().await.ok(),
+            None => None,
+        }
+    }
+
+    /// Host the office convert server is running on
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
     /// Obtains the current status of the converter server
     pub async fn get_status(&self) -> Result<StatusResponse, RequestError> {
         let route = format!("{}/status", self.host);
+        let request_id = new_request_id();
         let response = self
-            .http
-            .get(route)
+            .request(Method::GET, route, &request_id)
             .send()
             .await
-            .map_err(RequestError::RequestFailed)?;
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
+
+        // Handle error responses
+        let response = check_response(response, &request_id).await?;
+
+        // Extract the response message
+        let response: StatusResponse = response
+            .json()
+            .await
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
 
-        let status = response.status();
+        Ok(response)
+    }
+
+    /// Obtains the current status of the converter server, when the server
+    /// is busy the server will wait up to `wait` for it to become available
+    /// before responding (long-polling)
+    ///
+    /// Servers that don't support long-polling will respond immediately
+    ///
+    /// ## Arguments
+    /// * `wait` - Maximum duration for the server to wait
+    pub async fn get_status_wait(&self, wait: Duration) -> Result<StatusResponse, RequestError> {
+        let route = format!("{}/status", self.host);
+        let request_id = new_request_id();
+        let response = self
+            .request(Method::GET, route, &request_id)
+            .query(&[("wait", format!("{}ms", wait.as_millis()))])
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
 
         // Handle error responses
-        if status.is_client_error() || status.is_server_error() {
-            let body: ErrorResponse = response
-                .json()
-                .await
-                .map_err(RequestError::InvalidResponse)?;
-
-            return Err(RequestError::ErrorResponse {
-                reason: body.reason,
-                backtrace: body.backtrace,
-            });
-        }
+        let response = check_response(response, &request_id).await?;
 
         // Extract the response message
         let response: StatusResponse = response
             .json()
             .await
-            .map_err(RequestError::InvalidResponse)?;
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
 
         Ok(response)
     }
 
+    /// Subscribes to the server state over a WebSocket, the server will push
+    /// every busy/free and queue depth transition instead of requiring polling
+    #[cfg(feature = "native")]
+    pub async fn subscribe_state(&self) -> Result<StateSubscription, RequestError> {
+        let host = if let Some(host) = self.host.strip_prefix("https://") {
+            format!("wss://{host}")
+        } else if let Some(host) = self.host.strip_prefix("http://") {
+            format!("ws://{host}")
+        } else {
+            self.host.to_string()
+        };
+
+        let mut request = format!("{host}/ws/state")
+            .into_client_request()
+            .map_err(RequestError::WebSocket)?;
+
+        if let Some(api_key) = self.api_key.as_deref() {
+            let value = HeaderValue::from_str(api_key)
+                .map_err(|err| RequestError::WebSocket(err.into()))?;
+            request.headers_mut().insert(API_KEY, value);
+        }
+
+        let (socket, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .map_err(RequestError::WebSocket)?;
+
+        Ok(StateSubscription { socket })
+    }
+
     /// Obtains the LibreOffice version that the server is using
     pub async fn get_office_version(&self) -> Result<VersionResponse, RequestError> {
         let route = format!("{}/office-version", self.host);
+        let request_id = new_request_id();
         let response = self
-            .http
-            .get(route)
+            .request(Method::GET, route, &request_id)
             .send()
             .await
-            .map_err(RequestError::RequestFailed)?;
-
-        let status = response.status();
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
 
         // Handle error responses
-        if status.is_client_error() || status.is_server_error() {
-            let body: ErrorResponse = response
-                .json()
-                .await
-                .map_err(RequestError::InvalidResponse)?;
-
-            return Err(RequestError::ErrorResponse {
-                reason: body.reason,
-                backtrace: body.backtrace,
-            });
-        }
+        let response = check_response(response, &request_id).await?;
 
         // Extract the response message
         let response: VersionResponse = response
             .json()
             .await
-            .map_err(RequestError::InvalidResponse)?;
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
+
+        Ok(response)
+    }
+
+    /// Obtains the build of the server itself (Independent of the LibreOffice version)
+    pub async fn get_server_version(&self) -> Result<ServerVersionResponse, RequestError> {
+        let route = format!("{}/version", self.host);
+        let request_id = new_request_id();
+        let response = self
+            .request(Method::GET, route, &request_id)
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
+
+        // Handle error responses
+        let response = check_response(response, &request_id).await?;
+
+        // Extract the response message
+        let response: ServerVersionResponse = response
+            .json()
+            .await
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
 
         Ok(response)
     }
@@ -235,33 +1227,47 @@ impl OfficeConvertClient {
     /// available file types
     pub async fn get_supported_formats(&self) -> Result<Vec<SupportedFormat>, RequestError> {
         let route = format!("{}/supported-formats", self.host);
+        let request_id = new_request_id();
         let response = self
-            .http
-            .get(route)
+            .request(Method::GET, route, &request_id)
             .send()
             .await
-            .map_err(RequestError::RequestFailed)?;
-
-        let status = response.status();
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
 
         // Handle error responses
-        if status.is_client_error() || status.is_server_error() {
-            let body: ErrorResponse = response
-                .json()
-                .await
-                .map_err(RequestError::InvalidResponse)?;
-
-            return Err(RequestError::ErrorResponse {
-                reason: body.reason,
-                backtrace: body.backtrace,
-            });
-        }
+        let response = check_response(response, &request_id).await?;
 
         // Extract the response message
         let response: Vec<SupportedFormat> = response
             .json()
             .await
-            .map_err(RequestError::InvalidResponse)?;
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
+
+        Ok(response)
+    }
+
+    /// Obtains the supported file formats from the server grouped by document
+    /// family, will give back an error if the version of LibreOffice does not
+    /// support querying the available file types
+    pub async fn get_supported_format_groups(
+        &self,
+    ) -> Result<Vec<SupportedFormatGroup>, RequestError> {
+        let route = format!("{}/supported-formats?grouped=true", self.host);
+        let request_id = new_request_id();
+        let response = self
+            .request(Method::GET, route, &request_id)
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
+
+        // Handle error responses
+        let response = check_response(response, &request_id).await?;
+
+        // Extract the response message
+        let response: Vec<SupportedFormatGroup> = response
+            .json()
+            .await
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
 
         Ok(response)
     }
@@ -272,68 +1278,519 @@ impl OfficeConvertClient {
         Ok(status.is_busy)
     }
 
+    /// Extracts the embedded images and objects from the provided file,
+    /// returning the bytes of a zip archive containing the assets
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to extract from
+    pub async fn extract_assets(&self, file: Vec<u8>) -> Result<Bytes, RequestError> {
+        let _in_flight = self.acquire_in_flight().await;
+        let route = format!("{}/extract-assets", self.host);
+        let request_id = new_request_id();
+        let form = Form::new().part("file", Part::bytes(file));
+        let response = self
+            .request(Method::POST, route, &request_id)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
+
+        // Handle error responses
+        let response = check_response(response, &request_id).await?;
+
+        let response = response
+            .bytes()
+            .await
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
+
+        Ok(response)
+    }
+
+    /// Converts the provided file into a PDF along with a thumbnail of the first
+    /// page in a single request, returning the bytes of a zip archive containing
+    /// the PDF ("document.pdf") and the thumbnail ("thumbnail.png")
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    pub async fn convert_with_thumbnail(&self, file: Vec<u8>) -> Result<Bytes, RequestError> {
+        self.convert_bundle(file, "with_thumbnail").await
+    }
+
+    /// Converts the provided file into a PDF along with the text of each page
+    /// in a single request, returning the bytes of a zip archive containing the
+    /// PDF ("document.pdf") and a JSON array of the page text ("text.json")
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    pub async fn convert_with_text(&self, file: Vec<u8>) -> Result<Bytes, RequestError> {
+        self.convert_bundle(file, "with_text").await
+    }
+
+    /// Converts the provided file with the `option` field enabled to produce
+    /// an artifact alongside the PDF, returning the bytes of the zip archive
+    async fn convert_bundle(&self, file: Vec<u8>, option: &str) -> Result<Bytes, RequestError> {
+        if is_empty_file(&file) {
+            return Err(RequestError::EmptyFile);
+        }
+
+        let _in_flight = self.acquire_in_flight().await;
+        let route = format!("{}/convert", self.host);
+        let request_id = new_request_id();
+        let form = Form::new()
+            .part("file", Part::bytes(file))
+            .text(option.to_string(), "true");
+        let response = self
+            .request(Method::POST, route, &request_id)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
+
+        // Handle error responses
+        let response = check_response(response, &request_id).await?;
+
+        let response = response
+            .bytes()
+            .await
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
+
+        Ok(response)
+    }
+
+    /// Extracts the document statistics (word counts, sheet counts, slide counts)
+    /// from the provided file
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to extract from
+    pub async fn extract_stats(&self, file: Vec<u8>) -> Result<DocumentStats, RequestError> {
+        let _in_flight = self.acquire_in_flight().await;
+        let route = format!("{}/stats-extract", self.host);
+        let request_id = new_request_id();
+        let form = Form::new().part("file", Part::bytes(file));
+        let response = self
+            .request(Method::POST, route, &request_id)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
+
+        // Handle error responses
+        let response = check_response(response, &request_id).await?;
+
+        // Extract the response message
+        let response: DocumentStats = response
+            .json()
+            .await
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
+
+        Ok(response)
+    }
+
     /// Tells the converter server to collect garbage
     pub async fn collect_garbage(&self) -> Result<(), RequestError> {
         let route = format!("{}/collect-garbage", self.host);
+        let request_id = new_request_id();
         let response = self
-            .http
-            .post(route)
+            .request(Method::POST, route, &request_id)
             .send()
             .await
-            .map_err(RequestError::RequestFailed)?;
-
-        let status = response.status();
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
 
         // Handle error responses
-        if status.is_client_error() || status.is_server_error() {
-            let body: ErrorResponse = response
-                .json()
-                .await
-                .map_err(RequestError::InvalidResponse)?;
-
-            return Err(RequestError::ErrorResponse {
-                reason: body.reason,
-                backtrace: body.backtrace,
-            });
-        }
+        check_response(response, &request_id).await?;
 
         Ok(())
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl ConvertOffice for OfficeConvertClient {
     async fn convert(&self, file: Vec<u8>) -> Result<Bytes, RequestError> {
+        self.convert_with_request_id(file, &new_request_id()).await
+    }
+}
+
+impl OfficeConvertClient {
+    /// Converts multiple office files into PDFs concurrently, providing the
+    /// result of each file in the same order as the `files`. Each file is
+    /// converted separately so a failed file doesn't fail the others
+    ///
+    /// ## Arguments
+    /// * `files` - The bytes of each file to convert
+    /// * `max_concurrency` - Maximum number of conversions to run at once
+    pub async fn convert_many(
+        &self,
+        files: Vec<Vec<u8>>,
+        max_concurrency: usize,
+    ) -> Vec<Result<Bytes, RequestError>> {
+        convert_concurrently(self, files, max_concurrency).await
+    }
+
+    /// Converts the provided office file format bytes into a PDF sending
+    /// the provided `request_id` in the "X-Request-Id" header to correlate
+    /// the request with the server logs
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    /// * `request_id` - ID of the request
+    pub async fn convert_with_request_id(
+        &self,
+        file: Vec<u8>,
+        request_id: &str,
+    ) -> Result<Bytes, RequestError> {
+        if is_empty_file(&file) {
+            return Err(RequestError::EmptyFile);
+        }
+
+        let _in_flight = self.acquire_in_flight().await;
+        let route = format!("{}/convert", self.host);
+        let upload_checksum = self
+            .verify_checksum
+            .then(|| format!("{:x}", Sha256::digest(&file)));
+        let form = Form::new().part("file", Part::bytes(file));
+
+        let mut request = self.request(Method::POST, route, request_id);
+        if let Some(upload_checksum) = upload_checksum {
+            request = request.header("x-content-sha256", upload_checksum);
+        }
+
+        let response = request
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, request_id))?;
+
+        // Handle error responses
+        let response = check_response(response, request_id).await?;
+
+        let expected_checksum = match self.verify_checksum {
+            true => response
+                .headers()
+                .get("x-content-sha256")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            false => None,
+        };
+
+        let response = response
+            .bytes()
+            .await
+            .map_err(|err| RequestError::invalid_response(err, request_id))?;
+
+        if let Some(expected) = expected_checksum {
+            let actual = format!("{:x}", Sha256::digest(&response));
+
+            if !actual.eq_ignore_ascii_case(&expected) {
+                return Err(RequestError::ChecksumMismatch {
+                    request_id: request_id.to_string(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Converts the provided office file format bytes into a PDF stored in
+    /// the output directory of the server instead of being sent back, the
+    /// server must be started with an output directory
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    /// * `request_id` - ID of the request
+    pub async fn convert_stored_with_request_id(
+        &self,
+        file: Vec<u8>,
+        request_id: &str,
+    ) -> Result<StoredOutput, RequestError> {
+        if is_empty_file(&file) {
+            return Err(RequestError::EmptyFile);
+        }
+
+        let _in_flight = self.acquire_in_flight().await;
         let route = format!("{}/convert", self.host);
+        let form = Form::new()
+            .part("file", Part::bytes(file))
+            .text("store", "true");
+        let response = self
+            .request(Method::POST, route, request_id)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, request_id))?;
+
+        // Handle error responses
+        let response = check_response(response, request_id).await?;
+
+        let response: StoredOutput = response
+            .json()
+            .await
+            .map_err(|err| RequestError::invalid_response(err, request_id))?;
+
+        Ok(response)
+    }
+
+    /// Provides the absolute URL the stored `output` can be downloaded from,
+    /// [None] when the server does not have pre-signed URLs enabled
+    pub fn stored_output_url(&self, output: &StoredOutput) -> Option<String> {
+        let url = output.url.as_deref()?;
+
+        if url.starts_with('/') {
+            Some(format!("{}{url}", self.host))
+        } else {
+            Some(url.to_string())
+        }
+    }
+
+    /// Downloads the stored `output` from its pre-signed URL, verifying its
+    /// checksum when checksum verification is enabled
+    pub async fn download_stored(&self, output: &StoredOutput) -> Result<Bytes, RequestError> {
+        let route = self
+            .stored_output_url(output)
+            .ok_or_else(|| RequestError::NoDownloadUrl {
+                path: output.path.clone(),
+            })?;
+
+        let request_id = new_request_id();
+        let response = self
+            .request(Method::GET, route, &request_id)
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
+
+        // Handle error responses
+        let response = check_response(response, &request_id).await?;
+
+        let response = response
+            .bytes()
+            .await
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
+
+        if self.verify_checksum {
+            let actual = format!("{:x}", Sha256::digest(&response));
+
+            if !actual.eq_ignore_ascii_case(&output.sha256) {
+                return Err(RequestError::ChecksumMismatch {
+                    request_id,
+                    expected: output.sha256.clone(),
+                    actual,
+                });
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Submits the provided file to be converted in the background, the
+    /// server must be started with jobs enabled. Provides the queued job,
+    /// poll it with [OfficeConvertClient::job_status] until it finishes
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    pub async fn submit_job(&self, file: Vec<u8>) -> Result<JobStatus, RequestError> {
+        self.submit_job_with_request_id(file, &new_request_id())
+            .await
+    }
+
+    /// Submits the provided file to be converted in the background sending
+    /// the provided `request_id` in the "X-Request-Id" header
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    /// * `request_id` - ID of the request
+    pub async fn submit_job_with_request_id(
+        &self,
+        file: Vec<u8>,
+        request_id: &str,
+    ) -> Result<JobStatus, RequestError> {
+        if is_empty_file(&file) {
+            return Err(RequestError::EmptyFile);
+        }
+
+        let _in_flight = self.acquire_in_flight().await;
+        let route = format!("{}/jobs", self.host);
         let form = Form::new().part("file", Part::bytes(file));
         let response = self
-            .http
-            .post(route)
+            .request(Method::POST, route, request_id)
             .multipart(form)
             .send()
             .await
-            .map_err(RequestError::RequestFailed)?;
+            .map_err(|err| RequestError::from_send(err, request_id))?;
 
-        let status = response.status();
+        // Handle error responses
+        let response = check_response(response, request_id).await?;
+
+        let response: JobStatus = response
+            .json()
+            .await
+            .map_err(|err| RequestError::invalid_response(err, request_id))?;
+
+        Ok(response)
+    }
+
+    /// Gets the current status of the job with the provided `id`
+    ///
+    /// ## Arguments
+    /// * `id` - ID of the job
+    pub async fn job_status(&self, id: &str) -> Result<JobStatus, RequestError> {
+        let route = format!("{}/jobs/{id}", self.host);
+        let request_id = new_request_id();
+        let response = self
+            .request(Method::GET, route, &request_id)
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
 
         // Handle error responses
-        if status.is_client_error() || status.is_server_error() {
-            let body: ErrorResponse = response
-                .json()
-                .await
-                .map_err(RequestError::InvalidResponse)?;
-
-            return Err(RequestError::ErrorResponse {
-                reason: body.reason,
-                backtrace: body.backtrace,
-            });
+        let response = check_response(response, &request_id).await?;
+
+        let response: JobStatus = response
+            .json()
+            .await
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
+
+        Ok(response)
+    }
+
+    /// Polls the status of the job with the provided `id` every
+    /// `poll_interval` until it has finished, providing the finished status.
+    /// Jobs that failed are provided with their [JobStatus::error_code]
+    ///
+    /// ## Arguments
+    /// * `id` - ID of the job
+    /// * `poll_interval` - Time to wait between status requests
+    #[cfg(feature = "native")]
+    pub async fn wait_for_job(
+        &self,
+        id: &str,
+        poll_interval: Duration,
+    ) -> Result<JobStatus, RequestError> {
+        loop {
+            let status = self.job_status(id).await?;
+            if status.state.is_finished() {
+                return Ok(status);
+            }
+
+            tokio::time::sleep(poll_interval).await;
         }
+    }
+
+    /// Subscribes to the events of the job with the provided `id`, the server
+    /// pushes an event whenever the state or progress of the job changes
+    /// instead of requiring polling. The stream ends after the
+    /// [JobEvent::Done] or [JobEvent::Failed] event
+    ///
+    /// ## Arguments
+    /// * `id` - ID of the job
+    pub async fn job_events(&self, id: &str) -> Result<JobEventStream, RequestError> {
+        let route = format!("{}/jobs/{id}/events", self.host);
+        let request_id = new_request_id();
+        let response = self
+            .request(Method::GET, route, &request_id)
+            .header(reqwest::header::ACCEPT, "text/event-stream")
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
+
+        // Handle error responses
+        let response = check_response(response, &request_id).await?;
+
+        let state = (response.bytes_stream(), Vec::new(), request_id);
+        let stream =
+            futures_util::stream::unfold(state, |(mut bytes, mut buffer, request_id)| async move {
+                loop {
+                    // Events are separated by a blank line
+                    if let Some(end) = buffer.windows(2).position(|window| window == b"\n\n") {
+                        let block: Vec<u8> = buffer.drain(..end + 2).collect();
+
+                        match JobEvent::parse(&String::from_utf8_lossy(&block)) {
+                            Some(event) => {
+                                let event = event.map_err(RequestError::InvalidJobEvent);
+                                return Some((event, (bytes, buffer, request_id)));
+                            }
+                            None => continue,
+                        }
+                    }
+
+                    match bytes.next().await {
+                        Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                        Some(Err(err)) => {
+                            let err = RequestError::invalid_response(err, &request_id);
+                            return Some((Err(err), (bytes, buffer, request_id)));
+                        }
+                        None => return None,
+                    }
+                }
+            });
+
+        Ok(Box::pin(stream))
+    }
+
+    /// Downloads the result of the finished job with the provided `id`,
+    /// verifying its checksum when checksum verification is enabled
+    ///
+    /// ## Arguments
+    /// * `id` - ID of the job
+    pub async fn download_result(&self, id: &str) -> Result<Bytes, RequestError> {
+        let route = format!("{}/jobs/{id}/result", self.host);
+        let request_id = new_request_id();
+        let response = self
+            .request(Method::GET, route, &request_id)
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
+
+        // Handle error responses
+        let response = check_response(response, &request_id).await?;
+
+        let expected_checksum = match self.verify_checksum {
+            true => response
+                .headers()
+                .get("x-content-sha256")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            false => None,
+        };
 
         let response = response
             .bytes()
             .await
-            .map_err(RequestError::InvalidResponse)?;
+            .map_err(|err| RequestError::invalid_response(err, &request_id))?;
+
+        if let Some(expected) = expected_checksum {
+            let actual = format!("{:x}", Sha256::digest(&response));
+
+            if !actual.eq_ignore_ascii_case(&expected) {
+                return Err(RequestError::ChecksumMismatch {
+                    request_id,
+                    expected,
+                    actual,
+                });
+            }
+        }
 
         Ok(response)
     }
+
+    /// Removes the result of the finished job with the provided `id` ahead
+    /// of the retention period, the status of the job remains available
+    ///
+    /// ## Arguments
+    /// * `id` - ID of the job
+    pub async fn delete_job_result(&self, id: &str) -> Result<(), RequestError> {
+        let route = format!("{}/jobs/{id}/result", self.host);
+        let request_id = new_request_id();
+        let response = self
+            .request(Method::DELETE, route, &request_id)
+            .send()
+            .await
+            .map_err(|err| RequestError::from_send(err, &request_id))?;
+
+        // Handle error responses
+        check_response(response, &request_id).await?;
+
+        Ok(())
+    }
 }
@@ -1,19 +1,30 @@
-use crate::{ConvertOffice, OfficeConvertClient, RequestError};
+use crate::{
+    convert_concurrently, is_empty_file, new_request_id, ConvertOffice, JobStatus,
+    OfficeConvertClient, RequestError, StoredOutput,
+};
 use async_trait::async_trait;
+use bytes::Bytes;
 use std::{
-    sync::{atomic::AtomicUsize, Arc},
+    collections::hash_map::DefaultHasher,
+    future::Future,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
     time::Duration,
 };
 use thiserror::Error;
 use tokio::{
     sync::{Mutex, Notify},
+    task::JoinSet,
     time::{sleep, timeout, Instant},
 };
-use tracing::{debug, error};
+use tracing::{debug, debug_span, error, field::Empty, info_span, Instrument, Span};
 
-/// Round robbin load balancer, will pass convert jobs
-/// around to the next available client, connections
-/// will wait until there is an available client
+/// Load balancer, will pass convert jobs around to the
+/// next available client chosen by its [LoadBalanceStrategy],
+/// connections will wait until there is an available client
 #[derive(Clone)]
 pub struct OfficeConvertLoadBalancer {
     /// Inner portion of the load balancer
@@ -42,20 +53,57 @@ impl OfficeConvertLoadBalancer {
     where
         I: IntoIterator<Item = OfficeConvertClient>,
     {
+        Self::new_with_strategy(clients, Default::default(), timing)
+    }
+
+    /// Creates a load balancer from the provided collection of clients
+    /// using the `strategy` to choose which server converts are attempted
+    /// on first
+    ///
+    /// ## Arguments
+    /// * `clients` - The clients to load balance amongst
+    /// * `strategy` - Strategy for choosing servers
+    /// * `timing` - Timing configuration
+    pub fn new_with_strategy<I>(
+        clients: I,
+        strategy: LoadBalanceStrategy,
+        timing: LoadBalancerTiming,
+    ) -> Self
+    where
+        I: IntoIterator<Item = OfficeConvertClient>,
+    {
+        let clients = clients.into_iter().collect::<Vec<_>>();
+        let hosts = clients.iter().map(|client| client.host.clone()).collect();
+
         let clients = clients
             .into_iter()
             .map(|client| {
                 Mutex::new(LoadBalancedClient {
                     client,
                     busy_externally_at: None,
+                    unhealthy: false,
+                    slow_start: SlowStart::started(Instant::now()),
                 })
             })
             .collect::<Vec<_>>();
 
+        let pushed_states = clients.iter().map(|_| PushedState::default()).collect();
+        let backends = clients.iter().map(|_| BackendState::default()).collect();
+
         let inner = OfficeConvertLoadBalancerInner {
             clients,
+            hosts,
+            pushed_states,
+            backends,
+            strategy,
+            next_server: AtomicUsize::new(0),
+            created: Instant::now(),
+            health_checking: AtomicBool::new(false),
             free_notify: Notify::new(),
             active: AtomicUsize::new(0),
+            idle_notify: Notify::new(),
+            shutting_down: AtomicBool::new(false),
+            shutdown_notify: Notify::new(),
             timing,
         };
 
@@ -64,11 +112,152 @@ impl OfficeConvertLoadBalancer {
         }
     }
 
+    /// Subscribes to the state of every server over WebSockets, replacing
+    /// polling based busy checks with the state pushed by the servers.
+    ///
+    /// Servers that cannot be subscribed to (Older servers or connection
+    /// failures) continue to use polling until a subscription succeeds,
+    /// subscriptions are reconnected automatically.
+    ///
+    /// Must be called from within a tokio runtime, subscriptions stop once
+    /// the load balancer is dropped
+    pub fn subscribe_states(&self) {
+        for (index, client) in self.inner.clients.iter().enumerate() {
+            let client = match client.try_lock() {
+                Ok(value) => value.client.clone(),
+                Err(_) => continue,
+            };
+
+            tokio::spawn(run_state_subscription(
+                Arc::downgrade(&self.inner),
+                index,
+                client,
+            ));
+        }
+    }
+
+    /// Checks the health of every server in the background, probing the
+    /// `/status` and `/office-version` of each server every
+    /// [LoadBalancerTiming::health_check_interval].
+    ///
+    /// Unreachable servers are ejected and skipped by converts until a probe
+    /// succeeds again, servers that fail a busy check or a convert attempt
+    /// are ejected until their next successful probe rather than being
+    /// checked again by every convert.
+    ///
+    /// Must be called from within a tokio runtime, health checks stop once
+    /// the load balancer is dropped
+    pub fn check_health(&self) {
+        if self.inner.health_checking.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        for (index, client) in self.inner.clients.iter().enumerate() {
+            let client = match client.try_lock() {
+                Ok(value) => value.client.clone(),
+                Err(_) => continue,
+            };
+
+            tokio::spawn(run_health_checks(
+                Arc::downgrade(&self.inner),
+                index,
+                client,
+            ));
+        }
+    }
+
+    /// Provides the statistics of each server, in the same order as the
+    /// clients the load balancer was created with
+    pub fn stats(&self) -> Vec<BackendStats> {
+        let inner = &*self.inner;
+
+        inner
+            .hosts
+            .iter()
+            .zip(&inner.backends)
+            .map(|(host, backend)| BackendStats {
+                host: host.to_string(),
+                successes: backend.successes.load(Ordering::SeqCst),
+                failures: backend.failures.load(Ordering::SeqCst),
+                ejected: backend.ejected.load(Ordering::SeqCst),
+                ejections: backend.ejections.load(Ordering::SeqCst),
+            })
+            .collect()
+    }
+
+    /// Shuts down the load balancer, new converts fail with
+    /// [LoadBalanceError::ShuttingDown] and converts waiting for a server
+    /// are woken to fail with the same error. Waits up to the `deadline`
+    /// for conversions in-flight on a server to finish.
+    ///
+    /// Returns whether all in-flight conversions finished before the deadline
+    pub async fn shutdown(&self, deadline: Duration) -> bool {
+        let inner = &*self.inner;
+
+        inner.shutting_down.store(true, Ordering::SeqCst);
+        inner.shutdown_notify.notify_waiters();
+
+        let drained = timeout(deadline, async {
+            loop {
+                let idle = inner.idle_notify.notified();
+                tokio::pin!(idle);
+
+                // Register before checking so a finish in-between isn't missed
+                idle.as_mut().enable();
+                if inner.active.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+
+                idle.await;
+            }
+        })
+        .await
+        .is_ok();
+
+        if !drained {
+            debug!(
+                active = inner.active.load(Ordering::SeqCst),
+                "shutdown deadline reached with conversions in-flight"
+            );
+        }
+
+        drained
+    }
+
+    /// Whether [OfficeConvertLoadBalancer::shutdown] has been called
+    pub fn is_shutting_down(&self) -> bool {
+        self.inner.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Waits for the `wait` future to complete, stopping early when the
+    /// load balancer is shut down
+    async fn wait_unless_shutdown<F: Future>(&self, wait: F) {
+        let inner = &*self.inner;
+
+        let shutdown = inner.shutdown_notify.notified();
+        tokio::pin!(shutdown);
+
+        shutdown.as_mut().enable();
+        if self.is_shutting_down() {
+            return;
+        }
+
+        tokio::select! {
+            _ = wait => {}
+            _ = shutdown => {}
+        }
+    }
+
     /// Checks if all client connections are blocked externally, used
     /// to handle the case when to not wait on notifiers
     pub async fn is_externally_blocked(&self) -> bool {
         let inner = &*self.inner;
-        for client in inner.clients.iter() {
+        for (index, client) in inner.clients.iter().enumerate() {
+            // Ejected servers won't notify until they recover
+            if inner.backends[index].ejected.load(Ordering::SeqCst) {
+                continue;
+            }
+
             let client = match timeout(Duration::from_secs(1), client.lock()).await {
                 Ok(value) => value,
                 // Couldn't obtain the lock, this client is likely in use so we can
@@ -84,6 +273,173 @@ impl OfficeConvertLoadBalancer {
 
         true
     }
+
+    /// Waits for any of the servers to report as available by long-polling
+    /// their status for up to the [LoadBalancerTiming::retry_single_external]
+    /// duration. Falls back to sleeping for the remaining duration when the servers
+    /// respond early (Long-polling unsupported or servers unreachable)
+    async fn wait_externally_available(&self) {
+        let inner = &*self.inner;
+        let wait = inner.timing.retry_single_external;
+        let started = Instant::now();
+
+        let mut requests = JoinSet::new();
+
+        for (index, client) in inner.clients.iter().enumerate() {
+            // Ejected servers become available through their health checks
+            if inner.backends[index].ejected.load(Ordering::SeqCst) {
+                continue;
+            }
+
+            // Clients that are in use will notify when they are free
+            let client = match client.try_lock() {
+                Ok(value) => value.client.clone(),
+                Err(_) => continue,
+            };
+
+            requests.spawn(async move { (index, client.get_status_wait(wait).await) });
+        }
+
+        while let Some(result) = requests.join_next().await {
+            if let Ok((index, Ok(status))) = result {
+                if status.is_busy {
+                    continue;
+                }
+
+                debug!("server at {index} became available");
+
+                // Clear the external busy state so the server is checked right away
+                if let Ok(mut client) = inner.clients[index].try_lock() {
+                    client.busy_externally_at = None;
+                }
+
+                return;
+            }
+        }
+
+        let elapsed = started.elapsed();
+        if elapsed < wait {
+            sleep(wait - elapsed).await;
+        }
+    }
+}
+
+/// Routing hints for a convert through the load balancer, servers are
+/// identified by the host their client was created with
+#[derive(Debug, Default, Clone)]
+pub struct ConvertHints {
+    /// Host of the server to try first
+    pub prefer_backend: Option<String>,
+    /// Hosts of servers that must not be used
+    pub exclude_backends: Vec<String>,
+    /// Key used to consistently route related converts (i.e revisions of
+    /// the same document) to the same warm server while it is available
+    pub sticky_key: Option<String>,
+    /// ID sent in the "X-Request-Id" header of every attempt to correlate the
+    /// convert with the server logs (Generated when not provided)
+    pub request_id: Option<String>,
+}
+
+impl OfficeConvertLoadBalancerInner {
+    /// Determines the order servers should be attempted in for the
+    /// provided `hints`, excluded servers are omitted
+    fn server_order(&self, hints: &ConvertHints) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.hosts.len())
+            .filter(|index| {
+                let host = &*self.hosts[*index];
+                !hints.exclude_backends.iter().any(|exclude| exclude == host)
+            })
+            .collect();
+
+        match self.strategy {
+            LoadBalanceStrategy::InOrder => {}
+            LoadBalanceStrategy::RoundRobin => {
+                if !order.is_empty() {
+                    let start = self.next_server.fetch_add(1, Ordering::SeqCst) % order.len();
+                    order.rotate_left(start);
+                }
+            }
+            LoadBalanceStrategy::LeastRecentlyUsed => {
+                order.sort_by_key(|index| self.backends[*index].last_used.load(Ordering::SeqCst));
+            }
+            LoadBalanceStrategy::LeastPending => {
+                order.sort_by_key(|index| self.backends[*index].queue_depth.load(Ordering::SeqCst));
+            }
+        }
+
+        // Rendezvous hashing, only servers that are added or removed change
+        // which keys they receive
+        if let Some(sticky_key) = &hints.sticky_key {
+            order.sort_by_cached_key(|index| {
+                let mut hasher = DefaultHasher::new();
+                sticky_key.hash(&mut hasher);
+                self.hosts[*index].hash(&mut hasher);
+                std::cmp::Reverse(hasher.finish())
+            });
+        }
+
+        if let Some(prefer_backend) = &hints.prefer_backend {
+            if let Some(position) = order
+                .iter()
+                .position(|index| &*self.hosts[*index] == prefer_backend)
+            {
+                let index = order.remove(position);
+                order.insert(0, index);
+            }
+        }
+
+        order
+    }
+
+    /// Ejects the server at `index` until its next successful health check,
+    /// only when health checks are running to bring the server back
+    fn eject(&self, index: usize) -> bool {
+        if !self.health_checking.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        let backend = &self.backends[index];
+        if !backend.ejected.swap(true, Ordering::SeqCst) {
+            debug!("ejected server at {index}");
+            backend.ejections.fetch_add(1, Ordering::SeqCst);
+        }
+
+        true
+    }
+}
+
+/// Strategy for choosing which server a convert is attempted on first,
+/// servers that are unavailable are skipped in the same order
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LoadBalanceStrategy {
+    /// Servers are attempted in the order they were provided
+    #[default]
+    InOrder,
+    /// Each convert starts on the server after the one the previous
+    /// convert started on
+    RoundRobin,
+    /// Server that was least recently attempted first
+    LeastRecentlyUsed,
+    /// Server with the fewest requests waiting in its queue first, queue
+    /// depths are reported by busy checks, health checks and state
+    /// subscriptions
+    LeastPending,
+}
+
+/// Statistics of a server used by the load balancer
+#[derive(Debug, Clone)]
+pub struct BackendStats {
+    /// Host the client of the server was created with
+    pub host: String,
+    /// Convert attempts that succeeded
+    pub successes: u64,
+    /// Convert attempts that failed (Including failures caused by the
+    /// document such as encrypted files)
+    pub failures: u64,
+    /// Whether the server is currently ejected by health checks
+    pub ejected: bool,
+    /// Number of times the server was ejected
+    pub ejections: u64,
 }
 
 pub struct LoadBalancerTiming {
@@ -93,6 +449,15 @@ pub struct LoadBalancerTiming {
     pub retry_single_external: Duration,
     /// Timeout to wait on the notifier for
     pub notify_timeout: Duration,
+    /// Maximum number of servers to attempt a convert on when servers
+    /// fail with a retryable error
+    pub max_attempts: usize,
+    /// Duration newly added or recovered servers receive a reduced share of
+    /// traffic for while they warm up, [Duration::ZERO] disables slow start
+    pub slow_start: Duration,
+    /// Time in-between health checks of each server when health checking
+    /// is enabled, probes that take longer fail
+    pub health_check_interval: Duration,
 }
 
 impl Default for LoadBalancerTiming {
@@ -101,6 +466,9 @@ impl Default for LoadBalancerTiming {
             retry_busy_check_after: Duration::from_secs(5),
             retry_single_external: Duration::from_secs(1),
             notify_timeout: Duration::from_secs(120),
+            max_attempts: 3,
+            slow_start: Duration::from_secs(30),
+            health_check_interval: Duration::from_secs(10),
         }
     }
 }
@@ -109,12 +477,45 @@ struct OfficeConvertLoadBalancerInner {
     /// Available clients the load balancer can use
     clients: Vec<Mutex<LoadBalancedClient>>,
 
+    /// Hosts of the clients, indexes match the indexes of `clients`
+    hosts: Vec<Arc<str>>,
+
+    /// States pushed by servers through state subscriptions, indexes
+    /// match the indexes of `clients`
+    pushed_states: Vec<PushedState>,
+
+    /// Routing and health state of each server, indexes match the
+    /// indexes of `clients`
+    backends: Vec<BackendState>,
+
+    /// Strategy for choosing servers
+    strategy: LoadBalanceStrategy,
+
+    /// Server the next round robin convert starts on
+    next_server: AtomicUsize,
+
+    /// When the load balancer was created, last used times are relative to this
+    created: Instant,
+
+    /// Whether health checks are running
+    health_checking: AtomicBool,
+
     /// Number of active in use clients
     active: AtomicUsize,
 
     /// Notifier for connections that are no longer busy
     free_notify: Notify,
 
+    /// Notifier for when the last active conversion finishes
+    idle_notify: Notify,
+
+    /// Whether the load balancer is shutting down and no longer
+    /// accepting conversions
+    shutting_down: AtomicBool,
+
+    /// Notifier for waiters to stop waiting when shutting down
+    shutdown_notify: Notify,
+
     /// Timing for various actions
     timing: LoadBalancerTiming,
 }
@@ -125,24 +526,516 @@ struct LoadBalancedClient {
 
     /// Last time the server reported as busy externally
     busy_externally_at: Option<Instant>,
+
+    /// Whether the server has failed and has not yet recovered
+    unhealthy: bool,
+
+    /// Slow start state for newly added or recovered servers
+    slow_start: SlowStart,
+}
+
+/// Slow start state of a server, servers that are warming up receive
+/// a share of traffic that increases over the slow start window
+#[derive(Default)]
+struct SlowStart {
+    /// When the server started warming up, [None] when the server is warm
+    started_at: Option<Instant>,
+
+    /// Accumulated share of traffic, the server is used once this reaches 1
+    credit: f64,
+}
+
+impl SlowStart {
+    /// Creates a slow start state that starts warming up at `now`
+    fn started(now: Instant) -> Self {
+        Self {
+            started_at: Some(now),
+            credit: 0.0,
+        }
+    }
+
+    /// Checks if the server should be used for a convert at `now`
+    /// based on its share of traffic
+    fn admit(&mut self, now: Instant, window: Duration) -> bool {
+        let started_at = match self.started_at {
+            Some(value) => value,
+            None => return true,
+        };
+
+        let elapsed = now.duration_since(started_at);
+        if elapsed >= window {
+            // Server has finished warming up
+            *self = Self::default();
+            return true;
+        }
+
+        // Share grows linearly with a minimum so the server still warms up
+        let share = (elapsed.as_secs_f64() / window.as_secs_f64()).max(0.1);
+
+        self.credit += share;
+        if self.credit >= 1.0 {
+            self.credit -= 1.0;
+            return true;
+        }
+
+        false
+    }
+}
+
+/// State of a server received through a state subscription
+#[derive(Default)]
+struct PushedState {
+    /// Whether the subscription is currently connected
+    connected: AtomicBool,
+
+    /// Last busy state pushed by the server
+    is_busy: AtomicBool,
+
+    /// Whether the subscription reconnected after being lost, the
+    /// server may have restarted
+    recovered: AtomicBool,
+}
+
+/// Routing and health state of a server
+#[derive(Default)]
+struct BackendState {
+    /// Convert attempts that succeeded
+    successes: AtomicU64,
+
+    /// Convert attempts that failed
+    failures: AtomicU64,
+
+    /// When the server was last attempted in milliseconds since the load
+    /// balancer was created, zero when never attempted
+    last_used: AtomicU64,
+
+    /// Number of requests last reported waiting in the server queue
+    queue_depth: AtomicUsize,
+
+    /// Whether the server was ejected and is skipped until a health check succeeds
+    ejected: AtomicBool,
+
+    /// Number of times the server was ejected
+    ejections: AtomicU64,
+
+    /// Whether the server was ejected and has since recovered
+    recovered: AtomicBool,
+}
+
+/// Probes the health of the client at `index` every health check interval,
+/// ejecting the server while it is unreachable, until the load balancer is
+/// dropped
+async fn run_health_checks(
+    inner: Weak<OfficeConvertLoadBalancerInner>,
+    index: usize,
+    client: OfficeConvertClient,
+) {
+    loop {
+        let interval = match inner.upgrade() {
+            Some(inner) if !inner.shutting_down.load(Ordering::SeqCst) => {
+                inner.timing.health_check_interval
+            }
+            _ => return,
+        };
+
+        let status = timeout(interval, async {
+            let status = client.get_status().await?;
+
+            // Servers report a missing version before office has started, only
+            // failing to reach the server is unhealthy
+            match client.get_office_version().await {
+                Err(err) if err.is_network() => Err(err),
+                _ => Ok(status),
+            }
+        })
+        .await;
+
+        let inner = match inner.upgrade() {
+            Some(value) => value,
+            None => return,
+        };
+        let backend = &inner.backends[index];
+
+        match status {
+            Ok(Ok(status)) => {
+                if let Some(queue_depth) = status.queue_depth {
+                    backend.queue_depth.store(queue_depth, Ordering::SeqCst);
+                }
+
+                if backend.ejected.swap(false, Ordering::SeqCst) {
+                    debug!("server at {index} passed health check, no longer ejected");
+
+                    backend.recovered.store(true, Ordering::SeqCst);
+                    inner.free_notify.notify_waiters();
+                }
+            }
+            Ok(Err(err)) => {
+                error!("health check failed for server at {index}: {err}");
+                inner.eject(index);
+            }
+            Err(_) => {
+                error!("health check timed out for server at {index}");
+                inner.eject(index);
+            }
+        }
+
+        drop(inner);
+        sleep(interval).await;
+    }
+}
+
+/// Maintains a state subscription for the client at `index`, reconnecting
+/// when the subscription is lost until the load balancer is dropped
+async fn run_state_subscription(
+    inner: Weak<OfficeConvertLoadBalancerInner>,
+    index: usize,
+    client: OfficeConvertClient,
+) {
+    let mut lost = false;
+
+    loop {
+        match client.subscribe_state().await {
+            Ok(mut subscription) => {
+                debug!("subscribed to server state at {index}");
+
+                if lost {
+                    if let Some(inner) = inner.upgrade() {
+                        inner.pushed_states[index]
+                            .recovered
+                            .store(true, Ordering::SeqCst);
+                    }
+                }
+
+                loop {
+                    let next = subscription.next().await;
+
+                    let inner = match inner.upgrade() {
+                        Some(value) => value,
+                        None => return,
+                    };
+                    let pushed = &inner.pushed_states[index];
+
+                    let state = match next {
+                        Some(Ok(value)) => value,
+                        Some(Err(err)) => {
+                            error!("state subscription failed at {index}: {err}");
+                            pushed.connected.store(false, Ordering::SeqCst);
+                            break;
+                        }
+                        None => {
+                            pushed.connected.store(false, Ordering::SeqCst);
+                            break;
+                        }
+                    };
+
+                    let was_busy = pushed.is_busy.swap(state.is_busy, Ordering::SeqCst);
+                    inner.backends[index]
+                        .queue_depth
+                        .store(state.queue_depth, Ordering::SeqCst);
+                    pushed.connected.store(true, Ordering::SeqCst);
+
+                    // Wake waiters when the server becomes free
+                    if was_busy && !state.is_busy {
+                        inner.free_notify.notify_waiters();
+                    }
+                }
+            }
+            Err(err) => debug!("failed to subscribe to server state at {index}: {err}"),
+        }
+
+        lost = true;
+
+        let retry_after = match inner.upgrade() {
+            Some(inner) if !inner.shutting_down.load(Ordering::SeqCst) => {
+                inner.timing.retry_busy_check_after
+            }
+            _ => return,
+        };
+
+        sleep(retry_after).await;
+    }
+}
+
+/// Checks if a failed convert should be attempted again on another server.
+///
+/// Responses that were successful but failed while being read are not
+/// retried as the server has already completed the conversion
+fn should_retry(err: &RequestError) -> bool {
+    !matches!(err, RequestError::InvalidResponse { .. }) && err.is_retryable()
+}
+
+/// Converted file stored on the server that converted it, the result can be
+/// handed to end users by its [ResultLocation::url] without proxying the
+/// bytes through the load balancer
+#[derive(Clone)]
+pub struct ResultLocation {
+    /// Host of the server the result is stored on
+    pub backend: String,
+    /// Details of the stored file
+    pub output: StoredOutput,
+    /// Client for the server the result is stored on
+    client: OfficeConvertClient,
+}
+
+impl ResultLocation {
+    /// Provides the absolute URL the result can be downloaded from, [None]
+    /// when the server does not have pre-signed URLs enabled
+    pub fn url(&self) -> Option<String> {
+        self.client.stored_output_url(&self.output)
+    }
+
+    /// Downloads the result through this process, for callers that need the
+    /// bytes rather than the location
+    pub async fn download(&self) -> Result<Bytes, RequestError> {
+        self.client.download_stored(&self.output).await
+    }
+}
+
+/// Job submitted to the server chosen by the load balancer, jobs are only
+/// known by the server they were submitted to (unless the servers share
+/// their job storage) so the status and result are always requested from
+/// that server. The [JobLocation::backend] and job ID can be persisted to
+/// poll the job later with an [OfficeConvertClient] for the backend
+#[derive(Clone)]
+pub struct JobLocation {
+    /// Host of the server the job was submitted to
+    pub backend: String,
+    /// Status of the job when it was submitted
+    pub status: JobStatus,
+    /// Client for the server the job was submitted to
+    client: OfficeConvertClient,
+}
+
+impl JobLocation {
+    /// ID of the job
+    pub fn id(&self) -> &str {
+        &self.status.id
+    }
+
+    /// Gets the current status of the job from the server it was submitted to
+    pub async fn status(&self) -> Result<JobStatus, RequestError> {
+        self.client.job_status(&self.status.id).await
+    }
+
+    /// Polls the status of the job every `poll_interval` until it has finished
+    pub async fn wait(&self, poll_interval: Duration) -> Result<JobStatus, RequestError> {
+        self.client
+            .wait_for_job(&self.status.id, poll_interval)
+            .await
+    }
+
+    /// Downloads the result of the finished job
+    pub async fn download(&self) -> Result<Bytes, RequestError> {
+        self.client.download_result(&self.status.id).await
+    }
+
+    /// Removes the result of the finished job ahead of the retention period
+    pub async fn delete_result(&self) -> Result<(), RequestError> {
+        self.client.delete_job_result(&self.status.id).await
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum LoadBalanceError {
     #[error("no servers available for load balancing")]
     NoServers,
+
+    /// Load balancer was shut down
+    #[error("load balancer is shutting down")]
+    ShuttingDown,
 }
 
 #[async_trait]
 impl ConvertOffice for OfficeConvertLoadBalancer {
     async fn convert(&self, file: Vec<u8>) -> Result<bytes::Bytes, RequestError> {
+        self.convert_with_hints(file, &ConvertHints::default())
+            .await
+    }
+}
+
+impl OfficeConvertLoadBalancer {
+    /// Converts multiple office files into PDFs concurrently across the
+    /// servers, providing the result of each file in the same order as the
+    /// `files`. Each file is converted separately so a failed file doesn't
+    /// fail the others
+    ///
+    /// ## Arguments
+    /// * `files` - The bytes of each file to convert
+    /// * `max_concurrency` - Maximum number of conversions to run at once
+    pub async fn convert_many(
+        &self,
+        files: Vec<Vec<u8>>,
+        max_concurrency: usize,
+    ) -> Vec<Result<bytes::Bytes, RequestError>> {
+        convert_concurrently(self, files, max_concurrency).await
+    }
+
+    /// Converts the provided office file format bytes into a PDF using
+    /// the provided routing `hints` to choose the server
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    /// * `hints` - Routing hints for choosing the server
+    ///
+    /// The conversion is recorded in a "load_balancer_convert" span with the
+    /// number of attempts, the total time spent waiting for a free server and
+    /// the chosen backend. Busy checks, waits and attempts each have their own
+    /// span so time waiting for a server can be told apart from a slow server
+    pub async fn convert_with_hints(
+        &self,
+        file: Vec<u8>,
+        hints: &ConvertHints,
+    ) -> Result<bytes::Bytes, RequestError> {
+        self.convert_traced(file, hints, |client, file, request_id| async move {
+            client.convert_with_request_id(file, &request_id).await
+        })
+        .await
+    }
+
+    /// Converts the provided office file format bytes into a PDF stored on
+    /// the server that converted it, providing where the result is stored
+    /// instead of the bytes so large results aren't held in memory by the
+    /// load balancer. The servers must be started with an output directory
+    /// (and pre-signed URLs for the result to be downloaded)
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    /// * `hints` - Routing hints for choosing the server
+    pub async fn convert_stored_with_hints(
+        &self,
+        file: Vec<u8>,
+        hints: &ConvertHints,
+    ) -> Result<ResultLocation, RequestError> {
+        self.convert_traced(file, hints, |client, file, request_id| async move {
+            let output = client
+                .convert_stored_with_request_id(file, &request_id)
+                .await?;
+
+            Ok(ResultLocation {
+                backend: client.host().to_string(),
+                output,
+                client,
+            })
+        })
+        .await
+    }
+
+    /// Submits the `file` as a background job on the first available server
+    /// chosen with the `hints`, the provided [JobLocation] routes polling and
+    /// the download to the server that owns the job
+    pub async fn submit_job_with_hints(
+        &self,
+        file: Vec<u8>,
+        hints: &ConvertHints,
+    ) -> Result<JobLocation, RequestError> {
+        self.convert_traced(file, hints, |client, file, request_id| async move {
+            let status = client.submit_job_with_request_id(file, &request_id).await?;
+
+            Ok(JobLocation {
+                backend: client.host().to_string(),
+                status,
+                client,
+            })
+        })
+        .await
+    }
+
+    /// Converts the `file` with the `convert` request, recording the
+    /// conversion in a "load_balancer_convert" span
+    async fn convert_traced<T, F, Fut>(
+        &self,
+        file: Vec<u8>,
+        hints: &ConvertHints,
+        convert: F,
+    ) -> Result<T, RequestError>
+    where
+        F: Fn(OfficeConvertClient, Vec<u8>, String) -> Fut,
+        Fut: Future<Output = Result<T, RequestError>>,
+    {
+        // Empty files are rejected before waiting for a server
+        if is_empty_file(&file) {
+            return Err(RequestError::EmptyFile);
+        }
+
+        let request_id = match hints.request_id.as_deref() {
+            Some(value) => value.to_string(),
+            None => new_request_id(),
+        };
+
+        let span = info_span!(
+            "load_balancer_convert",
+            %request_id,
+            attempts = 0,
+            wait_ms = 0,
+            backend_index = Empty,
+            backend_url = Empty,
+        );
+
+        self.convert_attempts(file, hints, &request_id, convert)
+            .instrument(span)
+            .await
+    }
+
+    /// Attempts the conversion on the available servers until it succeeds or
+    /// fails with an error that should not be retried, recording progress on
+    /// the current span
+    async fn convert_attempts<T, F, Fut>(
+        &self,
+        mut file: Vec<u8>,
+        hints: &ConvertHints,
+        request_id: &str,
+        convert: F,
+    ) -> Result<T, RequestError>
+    where
+        F: Fn(OfficeConvertClient, Vec<u8>, String) -> Fut,
+        Fut: Future<Output = Result<T, RequestError>>,
+    {
         let inner = &*self.inner;
+        let span = Span::current();
+
+        let order = inner.server_order(hints);
+        if order.is_empty() {
+            return Err(LoadBalanceError::NoServers.into());
+        }
+
+        let multiple_clients = order.len() > 1;
 
-        let total_clients = inner.clients.len();
-        let multiple_clients = total_clients > 1;
+        // Total time spent waiting for a server to become available
+        let mut waited = Duration::ZERO;
+
+        let max_attempts = inner.timing.max_attempts.max(1);
+        let mut attempts = 0;
+
+        // Servers that have failed with a retryable error during this convert,
+        // indexes match the indexes of `order`
+        let mut failed = vec![false; order.len()];
+
+        // Whether servers that are warming up can be used regardless of their share
+        let mut allow_warming = false;
 
         loop {
-            for (index, client) in inner.clients.iter().enumerate() {
+            if self.is_shutting_down() {
+                return Err(LoadBalanceError::ShuttingDown.into());
+            }
+
+            // Whether a warming up server was skipped this pass
+            let mut skipped_warming = false;
+
+            for (position, &index) in order.iter().enumerate() {
+                // Skip servers that have failed while there are others to try
+                if failed[position] && failed.iter().any(|failed| !failed) {
+                    continue;
+                }
+
+                let backend = &inner.backends[index];
+
+                // Skip servers ejected by health checks
+                if backend.ejected.load(Ordering::SeqCst) {
+                    continue;
+                }
+
+                let client = &inner.clients[index];
+
                 let mut client = match client.try_lock() {
                     Ok(value) => value,
                     // Server is already in use
@@ -153,24 +1046,75 @@ impl ConvertOffice for OfficeConvertLoadBalancer {
 
                 let now = Instant::now();
 
-                if let Some(busy_externally_at) = client.busy_externally_at {
-                    let since_check = now.duration_since(busy_externally_at);
+                let pushed = &inner.pushed_states[index];
 
-                    // Don't check this server if the busy check timeout hasn't passed (only if we have multiple choices)
-                    if since_check < inner.timing.retry_busy_check_after && multiple_clients {
-                        continue;
-                    }
+                // Server reconnected after being lost or passed a health check
+                // after being ejected, warm it up again
+                let pushed_recovered = pushed.recovered.swap(false, Ordering::SeqCst);
+                if backend.recovered.swap(false, Ordering::SeqCst) | pushed_recovered {
+                    client.slow_start = SlowStart::started(now);
+                }
+
+                // Give servers that are warming up a reduced share of traffic
+                if multiple_clients
+                    && !allow_warming
+                    && !client.slow_start.admit(now, inner.timing.slow_start)
+                {
+                    skipped_warming = true;
+                    continue;
                 }
 
                 // Check if the server is busy externally (Busy outside of our control)
-                let externally_busy = match client.client.is_busy().await {
-                    Ok(value) => value,
-                    Err(err) => {
-                        error!("failed to perform server busy check at {index}: {err}");
+                let externally_busy = if pushed.connected.load(Ordering::SeqCst) {
+                    // Pushed states are always up to date
+                    pushed.is_busy.load(Ordering::SeqCst)
+                } else {
+                    if let Some(busy_externally_at) = client.busy_externally_at {
+                        let since_check = now.duration_since(busy_externally_at);
 
-                        // Mark erroneous servers as busy
-                        true
+                        // Don't check this server if the busy check timeout hasn't passed (only if we have multiple choices)
+                        if since_check < inner.timing.retry_busy_check_after && multiple_clients {
+                            continue;
+                        }
                     }
+
+                    let busy_span = debug_span!(
+                        "busy_check",
+                        backend_index = index,
+                        backend_url = %inner.hosts[index],
+                        is_busy = Empty,
+                    );
+
+                    let status = client
+                        .client
+                        .get_status()
+                        .instrument(busy_span.clone())
+                        .await;
+
+                    let is_busy = match status {
+                        Ok(status) => {
+                            if let Some(queue_depth) = status.queue_depth {
+                                backend.queue_depth.store(queue_depth, Ordering::SeqCst);
+                            }
+
+                            status.is_busy
+                        }
+                        Err(err) => {
+                            error!("failed to perform server busy check at {index}: {err}");
+
+                            // Mark erroneous servers as busy, skipping them until they
+                            // pass a health check when health checks are running
+                            client.unhealthy = true;
+                            if inner.eject(index) {
+                                continue;
+                            }
+
+                            true
+                        }
+                    };
+
+                    busy_span.record("is_busy", is_busy);
+                    is_busy
                 };
 
                 // Store the busy state if busy
@@ -184,27 +1128,112 @@ impl ConvertOffice for OfficeConvertLoadBalancer {
                 // Clear external busy state
                 client.busy_externally_at = None;
 
-                debug!("obtained available server {index} for convert");
+                // Server has recovered, warm it up again
+                if client.unhealthy {
+                    debug!("server at {index} recovered, starting slow start");
+
+                    client.unhealthy = false;
+                    client.slow_start = SlowStart::started(now);
+                }
+
+                debug!(%request_id, "obtained available server {index} for convert");
 
                 // Increase active counter
-                inner
-                    .active
-                    .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                inner.active.fetch_add(1, Ordering::SeqCst);
+
+                // Checked after counting as active so shutdown either waits
+                // for this conversion or it is never started
+                if self.is_shutting_down() {
+                    if inner.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+                        inner.idle_notify.notify_waiters();
+                    }
+
+                    return Err(LoadBalanceError::ShuttingDown.into());
+                }
+
+                attempts += 1;
+                let last_attempt = attempts >= max_attempts;
+
+                span.record("attempts", attempts);
+                span.record("backend_index", index);
+                span.record("backend_url", &*inner.hosts[index]);
+
+                // Only keep a copy of the file when it may be needed for another attempt
+                let body = if last_attempt {
+                    std::mem::take(&mut file)
+                } else {
+                    file.clone()
+                };
+
+                let attempt_span = debug_span!(
+                    "attempt",
+                    attempt = attempts,
+                    backend_index = index,
+                    backend_url = %inner.hosts[index],
+                );
 
-                let response = client.client.convert(file).await;
+                let started = Instant::now();
+                backend.last_used.store(
+                    started.duration_since(inner.created).as_millis() as u64 + 1,
+                    Ordering::SeqCst,
+                );
+
+                let response = convert(client.client.clone(), body, request_id.to_string())
+                    .instrument(attempt_span)
+                    .await;
+
+                debug!(
+                    backend_index = index,
+                    duration_ms = started.elapsed().as_millis() as u64,
+                    success = response.is_ok(),
+                    "conversion attempt finished"
+                );
+
+                match &response {
+                    Ok(_) => backend.successes.fetch_add(1, Ordering::SeqCst),
+                    Err(_) => backend.failures.fetch_add(1, Ordering::SeqCst),
+                };
 
                 // Notify waiters that this server is now free
                 inner.free_notify.notify_waiters();
 
                 // Decrease active counter
-                inner
-                    .active
-                    .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+                if inner.active.fetch_sub(1, Ordering::SeqCst) == 1 {
+                    inner.idle_notify.notify_waiters();
+                }
+
+                match response {
+                    Err(err) if !last_attempt && should_retry(&err) => {
+                        error!(%request_id, "failed to convert on server at {index}, retrying: {err}");
+
+                        // Avoid the server until its next busy check (or health check)
+                        client.busy_externally_at = Some(Instant::now());
+                        client.unhealthy = true;
+
+                        // Unreachable servers are left to the health checks
+                        if err.is_network() {
+                            inner.eject(index);
+                        }
+                        failed[position] = true;
+
+                        // Every server has failed, allow them to be attempted again
+                        if failed.iter().all(|failed| *failed) {
+                            failed.fill(false);
+                        }
+                    }
+                    response => return response,
+                }
+            }
 
-                return response;
+            // Servers that are warming up were skipped, use them rather than waiting
+            if skipped_warming && !allow_warming {
+                allow_warming = true;
+                continue;
             }
 
-            let active_counter = inner.active.load(std::sync::atomic::Ordering::SeqCst);
+            allow_warming = false;
+
+            let active_counter = inner.active.load(Ordering::SeqCst);
 
             // Handle case where all clients are blocked externally, we won't be woken by any clients
             // in this case, so instead of waiting for the notifier we wait a short duration
@@ -212,17 +1241,193 @@ impl ConvertOffice for OfficeConvertLoadBalancer {
             // If number of active connections are zero we can assume we are blocked for some reason
             // likely an external factor, we would never get notified so we must poll instead?
             let externally_blocked = self.is_externally_blocked().await;
+            let wait_started = Instant::now();
+
             if externally_blocked || active_counter < 1 {
-                debug!("all servers are externally blocked, delaying next attempt");
-                sleep(inner.timing.retry_single_external).await;
-                continue;
+                debug!("all servers are externally blocked, waiting for availability");
+                self.wait_unless_shutdown(self.wait_externally_available())
+                    .instrument(debug_span!("wait", reason = "externally_blocked"))
+                    .await;
+            } else {
+                debug!("no available servers, waiting until one is available");
+
+                // All servers are in use, wait for the free notifier, this has a timeout
+                // incase a complication occurs
+                let notified = timeout(inner.timing.notify_timeout, inner.free_notify.notified());
+                self.wait_unless_shutdown(notified)
+                    .instrument(debug_span!("wait", reason = "all_busy"))
+                    .await;
             }
 
-            debug!("no available servers, waiting until one is available");
+            waited += wait_started.elapsed();
+            span.record("wait_ms", waited.as_millis() as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Provides the host of a server that refuses connections
+    async fn down_host() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+
+        format!("http://{address}")
+    }
+
+    /// Creates a load balancer across `count` servers that are all down
+    async fn all_down(count: usize) -> OfficeConvertLoadBalancer {
+        let mut clients = Vec::new();
+        for _ in 0..count {
+            clients.push(OfficeConvertClient::new(down_host().await).unwrap());
+        }
+
+        OfficeConvertLoadBalancer::new_with_timing(
+            clients,
+            LoadBalancerTiming {
+                retry_busy_check_after: Duration::from_millis(20),
+                retry_single_external: Duration::from_millis(20),
+                notify_timeout: Duration::from_millis(100),
+                max_attempts: 3,
+                slow_start: Duration::ZERO,
+                health_check_interval: Duration::from_millis(20),
+            },
+        )
+    }
+
+    fn is_shutting_down(result: &Result<Bytes, RequestError>) -> bool {
+        matches!(
+            result,
+            Err(RequestError::LoadBalance(LoadBalanceError::ShuttingDown))
+        )
+    }
+
+    #[tokio::test]
+    async fn all_servers_down_waits_until_shutdown() {
+        let load_balancer = all_down(2).await;
+
+        let convert = tokio::spawn({
+            let load_balancer = load_balancer.clone();
+            async move { load_balancer.convert(b"document".to_vec()).await }
+        });
 
-            // All servers are in use, wait for the free notifier, this has a timeout
-            // incase a complication occurs
-            _ = timeout(inner.timing.notify_timeout, inner.free_notify.notified()).await;
+        // Unreachable servers are treated as busy rather than failing the convert
+        sleep(Duration::from_millis(200)).await;
+        assert!(!convert.is_finished());
+        assert!(load_balancer.is_externally_blocked().await);
+
+        // No attempts were made on the servers
+        let stats = load_balancer.stats();
+        assert!(stats.iter().all(|stats| stats.successes == 0));
+        assert!(stats.iter().all(|stats| stats.failures == 0));
+        assert!(stats.iter().all(|stats| !stats.ejected));
+
+        assert!(load_balancer.shutdown(Duration::from_secs(1)).await);
+        let result = timeout(Duration::from_secs(1), convert)
+            .await
+            .expect("convert was not woken by the shutdown")
+            .unwrap();
+        assert!(is_shutting_down(&result));
+    }
+
+    #[tokio::test]
+    async fn all_servers_down_are_ejected_by_health_checks() {
+        let load_balancer = all_down(2).await;
+        load_balancer.check_health();
+
+        let convert = tokio::spawn({
+            let load_balancer = load_balancer.clone();
+            async move { load_balancer.convert(b"document".to_vec()).await }
+        });
+
+        let mut ejected = false;
+        for _ in 0..100 {
+            ejected = load_balancer.stats().iter().all(|stats| stats.ejected);
+            if ejected {
+                break;
+            }
+
+            sleep(Duration::from_millis(10)).await;
         }
+        assert!(ejected);
+
+        // Ejected servers are left to the health checks while the convert waits
+        sleep(Duration::from_millis(100)).await;
+        assert!(!convert.is_finished());
+        assert!(load_balancer
+            .stats()
+            .iter()
+            .all(|stats| stats.ejections == 1 && stats.failures == 0));
+
+        assert!(load_balancer.shutdown(Duration::from_secs(1)).await);
+        let result = timeout(Duration::from_secs(1), convert)
+            .await
+            .expect("convert was not woken by the shutdown")
+            .unwrap();
+        assert!(is_shutting_down(&result));
+    }
+
+    #[tokio::test]
+    async fn converts_without_servers_fail() {
+        let load_balancer = OfficeConvertLoadBalancer::new(Vec::new());
+        assert!(matches!(
+            load_balancer.convert(b"document".to_vec()).await,
+            Err(RequestError::LoadBalance(LoadBalanceError::NoServers))
+        ));
+
+        // Excluding every server leaves none to convert on
+        let load_balancer = all_down(2).await;
+        let hints = ConvertHints {
+            exclude_backends: load_balancer
+                .stats()
+                .into_iter()
+                .map(|stats| stats.host)
+                .collect(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            load_balancer
+                .convert_with_hints(b"document".to_vec(), &hints)
+                .await,
+            Err(RequestError::LoadBalance(LoadBalanceError::NoServers))
+        ));
+    }
+
+    #[tokio::test]
+    async fn shut_down_load_balancers_reject_converts() {
+        let load_balancer = all_down(1).await;
+        assert!(load_balancer.shutdown(Duration::from_secs(1)).await);
+
+        let result = load_balancer.convert(b"document".to_vec()).await;
+        assert!(is_shutting_down(&result));
+    }
+
+    #[test]
+    fn slow_start_grows_the_share_of_traffic() {
+        let window = Duration::from_secs(10);
+        let started = Instant::now();
+
+        // Servers that just started receive the minimum share
+        let mut slow_start = SlowStart::started(started);
+        let admitted = (0..20)
+            .filter(|_| slow_start.admit(started, window))
+            .count();
+        assert!((1..=2).contains(&admitted));
+
+        // Halfway through the window the share is half the traffic
+        let mut slow_start = SlowStart::started(started);
+        let halfway = started + window / 2;
+        let admitted = (0..20)
+            .filter(|_| slow_start.admit(halfway, window))
+            .count();
+        assert_eq!(admitted, 10);
+
+        // Warm servers receive all traffic
+        let mut slow_start = SlowStart::started(started);
+        assert!((0..20).all(|_| slow_start.admit(started + window, window)));
+        assert!(slow_start.started_at.is_none());
     }
 }
@@ -0,0 +1,68 @@
+use crate::{new_request_id, OfficeConvertClient, RequestError};
+use bytes::Bytes;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower::Service;
+
+/// Future of a conversion made through the [Service] of the client, futures
+/// are not [Send] on WASM where requests are made through the browser
+#[cfg(not(target_arch = "wasm32"))]
+pub type ConvertFuture = Pin<Box<dyn Future<Output = Result<Bytes, RequestError>> + Send>>;
+
+/// Future of a conversion made through the [Service] of the client, futures
+/// are not [Send] on WASM where requests are made through the browser
+#[cfg(target_arch = "wasm32")]
+pub type ConvertFuture = Pin<Box<dyn Future<Output = Result<Bytes, RequestError>>>>;
+
+/// File to convert through the [Service] of an [OfficeConvertClient]
+#[derive(Debug, Clone)]
+pub struct ConvertRequest {
+    /// The file bytes to convert
+    pub file: Vec<u8>,
+    /// ID of the request sent in the "X-Request-Id" header, a new ID is
+    /// generated when not provided
+    pub request_id: Option<String>,
+}
+
+impl ConvertRequest {
+    /// Creates a request converting the `file` bytes
+    pub fn new(file: Vec<u8>) -> Self {
+        Self {
+            file,
+            request_id: None,
+        }
+    }
+
+    /// Sets the ID of the request sent in the "X-Request-Id" header
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+}
+
+/// Converts files into PDFs, allowing tower middleware (timeouts, retries,
+/// rate limits, load shedding) to be composed around conversions. The client
+/// is always ready, uploads are held by its `max_in_flight` limit when set
+impl Service<ConvertRequest> for OfficeConvertClient {
+    type Response = Bytes;
+    type Error = RequestError;
+    type Future = ConvertFuture;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ConvertRequest) -> Self::Future {
+        let client = self.clone();
+
+        Box::pin(async move {
+            let request_id = request.request_id.unwrap_or_else(new_request_id);
+            client
+                .convert_with_request_id(request.file, &request_id)
+                .await
+        })
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{
+    dirs,
+    runner::{OfficeHandle, WorkTotals},
+};
+use serde::Serialize;
+use std::{collections::HashSet, path::PathBuf, time::Duration};
+use tracing::{debug, warn};
+use url::Url;
+
+/// Number of consecutive checks the queue must be saturated for before
+/// the saturation is alerted
+const SATURATION_CHECKS: u32 = 4;
+
+/// Available disk space percentage below which a directory is alerted
+const LOW_DISK_PERCENT: u64 = 5;
+
+/// Maximum time to wait for the webhook to accept an alert
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Operational event an alert is sent for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertEvent {
+    /// Office was restarted after failing or failed to start
+    WorkerRestart,
+    /// Requests have been waiting for the runner across several checks
+    QueueSaturated,
+    /// Conversions failed repeatedly triggering the self test
+    RepeatedFailures,
+    /// Directory used by the server is running out of space
+    DiskSpaceLow,
+}
+
+/// Body of an alert, the "text" field makes the body compatible with
+/// Slack incoming webhooks
+#[derive(Debug, Serialize)]
+struct AlertBody<'a> {
+    /// Human readable alert message
+    text: String,
+    /// Event the alert was sent for
+    event: AlertEvent,
+    /// Host name of the server
+    host: Option<&'a str>,
+}
+
+/// Monitors the server for operational events, posting an alert to the
+/// webhook when they happen
+pub struct Alerts {
+    /// Handle to the office runner
+    pub office: OfficeHandle,
+    /// URL of the webhook alerts are posted to
+    pub webhook: Url,
+    /// Interval between checks
+    pub interval: Duration,
+    /// Directories checked for available disk space
+    pub dirs: Vec<PathBuf>,
+}
+
+impl Alerts {
+    /// Checks for events at the monitoring interval forever
+    pub async fn monitor(self) {
+        let client = reqwest::Client::new();
+        let host = std::env::var("HOSTNAME").ok();
+
+        let mut last = self.office.totals();
+        let mut saturated_checks: u32 = 0;
+        let mut low_disk: HashSet<PathBuf> = HashSet::new();
+
+        loop {
+            tokio::time::sleep(self.interval).await;
+
+            let totals = self.office.totals();
+            let mut alerts: Vec<(AlertEvent, String)> = Vec::new();
+
+            alerts.extend(runner_alerts(&last, &totals));
+
+            // Requests timing out waiting also indicate saturation
+            let timeouts = totals.failures.timeout - last.failures.timeout;
+            if self.office.state().queue_depth > 0 || timeouts > 0 {
+                saturated_checks += 1;
+
+                if saturated_checks == SATURATION_CHECKS {
+                    alerts.push((
+                        AlertEvent::QueueSaturated,
+                        format!(
+                            "requests have been waiting for the converter for {:?} ({timeouts} timed out since the last check)",
+                            self.interval * SATURATION_CHECKS
+                        ),
+                    ));
+                }
+            } else {
+                saturated_checks = 0;
+            }
+
+            for dir in &self.dirs {
+                let Some(space) = dirs::disk_space(dir) else {
+                    continue;
+                };
+
+                if space.available_percent() >= LOW_DISK_PERCENT {
+                    low_disk.remove(dir);
+                    continue;
+                }
+
+                // Only alert when the directory first runs low
+                if low_disk.insert(dir.clone()) {
+                    alerts.push((
+                        AlertEvent::DiskSpaceLow,
+                        format!(
+                            "{} has {}% ({} bytes) of disk space available",
+                            dir.display(),
+                            space.available_percent(),
+                            space.available_bytes
+                        ),
+                    ));
+                }
+            }
+
+            last = totals;
+
+            for (event, message) in alerts {
+                let body = AlertBody {
+                    text: format!("office-convert-server: {message}"),
+                    event,
+                    host: host.as_deref(),
+                };
+
+                send_alert(&client, &self.webhook, &body).await;
+            }
+        }
+    }
+}
+
+/// Provides the alerts for events the runner recorded between the `last`
+/// and current `totals`
+fn runner_alerts(last: &WorkTotals, totals: &WorkTotals) -> Vec<(AlertEvent, String)> {
+    let mut alerts = Vec::new();
+
+    let selftests = totals.selftests - last.selftests;
+    let recoveries = totals.recoveries - last.recoveries;
+    let crashes = totals.failures.worker_crash - last.failures.worker_crash;
+
+    if selftests > 0 {
+        let outcome = if recoveries > 0 {
+            "the self test failed"
+        } else {
+            "the self test passed, the failures were caused by the documents"
+        };
+
+        alerts.push((
+            AlertEvent::RepeatedFailures,
+            format!("conversions failed repeatedly, {outcome}"),
+        ));
+    }
+
+    if recoveries > 0 {
+        alerts.push((
+            AlertEvent::WorkerRestart,
+            format!("office was recycled {recoveries} time(s) after failing the self test"),
+        ));
+    }
+
+    if crashes > 0 {
+        alerts.push((
+            AlertEvent::WorkerRestart,
+            format!("office failed to start or stopped {crashes} time(s)"),
+        ));
+    }
+
+    alerts
+}
+
+/// Posts the alert `body` to the `webhook`
+async fn send_alert(client: &reqwest::Client, webhook: &Url, body: &AlertBody<'_>) {
+    debug!(event = ?body.event, "sending alert");
+
+    let result = client
+        .post(webhook.clone())
+        .timeout(WEBHOOK_TIMEOUT)
+        .json(body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+
+    if let Err(cause) = result {
+        warn!(%cause, event = ?body.event, "failed to send alert");
+    }
+}
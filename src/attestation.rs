@@ -0,0 +1,166 @@
+use crate::error::HttpError;
+use anyhow::{anyhow, Context};
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use thiserror::Error;
+use tracing::info;
+
+/// DER prefix of an Ed25519 "SubjectPublicKeyInfo", followed by the 32 byte key
+const ED25519_SPKI_PREFIX: &[u8] = &[
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+/// Length of an Ed25519 public key
+const ED25519_KEY_LEN: usize = 32;
+
+/// Public key of an upstream service (i.e a scanning gateway) that signs
+/// the documents it has checked
+#[derive(Debug, Clone)]
+pub struct AttestationKey {
+    /// Raw Ed25519 public key
+    key: [u8; ED25519_KEY_LEN],
+    /// Identifier of the key recorded in the audit log, the first 16 hex
+    /// characters of the SHA-256 digest of the key
+    id: String,
+}
+
+/// Errors from upload signatures that could not be verified
+#[derive(Debug, Error)]
+pub enum AttestationError {
+    /// Signature was provided without a configured key
+    #[error("upload signatures are not enabled on this server")]
+    Disabled,
+
+    /// Signature was required but not provided
+    #[error("a signature is required for each uploaded file")]
+    Missing,
+
+    /// Number of signatures did not match the number of files
+    #[error("expected a signature field for each uploaded file")]
+    Count,
+
+    /// Signature was not a base64 encoded Ed25519 signature
+    #[error("invalid signature, expected a base64 encoded Ed25519 signature")]
+    Invalid,
+
+    /// Signature did not match the uploaded file
+    #[error("signature does not match the uploaded file")]
+    Mismatch,
+}
+
+impl HttpError for AttestationError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AttestationError::Mismatch => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            AttestationError::Disabled => "signatures_disabled",
+            AttestationError::Missing => "missing_signature",
+            AttestationError::Count | AttestationError::Invalid | AttestationError::Mismatch => {
+                "invalid_signature"
+            }
+        })
+    }
+}
+
+impl AttestationKey {
+    /// Loads the key from the file at `path`, either a PEM encoded public
+    /// key ("-----BEGIN PUBLIC KEY-----") or the base64 encoded raw key
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read attestation key {}", path.display()))?;
+
+        Self::parse(&contents)
+            .with_context(|| format!("invalid attestation key {}", path.display()))
+    }
+
+    /// Parses a PEM encoded public key or base64 encoded raw key
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        let encoded: String = value
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .flat_map(|line| line.trim().chars())
+            .collect();
+
+        let decoded = STANDARD
+            .decode(encoded)
+            .context("key is not base64 encoded")?;
+
+        let key = decoded
+            .strip_prefix(ED25519_SPKI_PREFIX)
+            .unwrap_or(&decoded);
+
+        let key: [u8; ED25519_KEY_LEN] = key
+            .try_into()
+            .map_err(|_| anyhow!("expected an Ed25519 public key"))?;
+
+        let id = format!("{:x}", Sha256::digest(key))[..16].to_string();
+
+        Ok(Self { key, id })
+    }
+
+    /// Identifier of the key recorded in the audit log
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Verifies the base64 encoded `signature` of the `contents`
+    fn verify(&self, contents: &[u8], signature: &str) -> Result<(), AttestationError> {
+        let signature = STANDARD
+            .decode(signature.trim())
+            .map_err(|_| AttestationError::Invalid)?;
+
+        UnparsedPublicKey::new(&ED25519, &self.key)
+            .verify(contents, &signature)
+            .map_err(|_| AttestationError::Mismatch)
+    }
+}
+
+/// Verifies the detached `signatures` of the uploaded `files` (name and
+/// contents) against the `key`, one signature per file in the same order.
+/// Verified files are recorded in the audit log
+pub fn verify_uploads(
+    key: Option<&AttestationKey>,
+    required: bool,
+    signatures: &[String],
+    files: &[(Option<&str>, &[u8])],
+) -> Result<(), AttestationError> {
+    let Some(key) = key else {
+        return match signatures.is_empty() {
+            true => Ok(()),
+            false => Err(AttestationError::Disabled),
+        };
+    };
+
+    if signatures.is_empty() {
+        return match required {
+            true => Err(AttestationError::Missing),
+            false => Ok(()),
+        };
+    }
+
+    if signatures.len() != files.len() {
+        return Err(AttestationError::Count);
+    }
+
+    for (signature, (file_name, contents)) in signatures.iter().zip(files) {
+        key.verify(contents, signature)?;
+
+        info!(
+            target: "audit",
+            key_id = key.id(),
+            file_name = file_name.unwrap_or_default(),
+            sha256 = %format!("{:x}", Sha256::digest(contents)),
+            "upload attestation verified"
+        );
+    }
+
+    Ok(())
+}
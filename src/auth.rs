@@ -0,0 +1,813 @@
+use crate::{config::parse_duration, error::HttpError};
+use anyhow::Context;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use parking_lot::Mutex;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{error, warn};
+use url::Url;
+
+/// Number of conversions allowed within a period, up to the full number
+/// can be used at once as a burst
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    /// Conversions allowed within the period
+    pub count: u32,
+    /// Period the conversions are allowed within
+    pub period: Duration,
+}
+
+/// Limits applied to the conversions of an API key
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct KeyLimits {
+    /// Rate conversions are allowed at, [None] is unlimited
+    pub rate: Option<Rate>,
+    /// Maximum number of conversions running at once, [None] is unlimited
+    pub max_concurrent: Option<u32>,
+}
+
+/// API key accepted by the server, limits not set by the key use the
+/// server defaults
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    /// API key provided in the "X-Api-Key" header
+    pub key: String,
+    /// Limits of the key
+    pub limits: KeyLimits,
+}
+
+/// Parses a rate in the form "count/period" where the period is a unit or
+/// a duration, i.e "10/s", "600/m" or "100/30s"
+pub fn rate_arg(value: &str) -> Result<Rate, String> {
+    let parse = || -> Option<Rate> {
+        let (count, period) = value.split_once('/')?;
+        let count: u32 = count.trim().parse().ok()?;
+
+        let period = period.trim();
+        let period = match period.starts_with(|char: char| char.is_ascii_digit()) {
+            true => parse_duration(period)?,
+            false => parse_duration(&format!("1{period}"))?,
+        };
+
+        (count > 0 && !period.is_zero()).then_some(Rate { count, period })
+    };
+
+    parse().ok_or_else(|| "expected a rate like \"10/s\", \"600/m\" or \"100/30s\"".to_string())
+}
+
+/// Parses an API key in the form "key,limit=value,..." with the limits
+/// "rate" (i.e "10/s") and "max-concurrent", i.e "web-app,rate=10/s"
+pub fn api_key_arg(value: &str) -> Result<ApiKey, String> {
+    let mut parts = value.split(',');
+    let key = parts.next().unwrap_or_default().trim();
+
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err("expected an api key like \"web-app,rate=10/s,max-concurrent=2\"".to_string());
+    }
+
+    let mut limits = KeyLimits::default();
+
+    for part in parts {
+        let (name, value) = part
+            .split_once('=')
+            .ok_or_else(|| format!("expected a limit like \"rate=10/s\", got \"{part}\""))?;
+
+        match name.trim() {
+            "rate" => limits.rate = Some(rate_arg(value)?),
+            "max-concurrent" => {
+                let max = value
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|max| *max > 0)
+                    .ok_or_else(|| "expected a positive max-concurrent".to_string())?;
+                limits.max_concurrent = Some(max);
+            }
+            name => return Err(format!("unknown api key limit \"{name}\"")),
+        }
+    }
+
+    Ok(ApiKey {
+        key: key.to_string(),
+        limits,
+    })
+}
+
+/// Parses the API keys separated by whitespace in `value` (i.e the
+/// "API_KEYS" environment variable)
+pub fn parse_api_keys(value: &str) -> Result<Vec<ApiKey>, String> {
+    value.split_whitespace().map(api_key_arg).collect()
+}
+
+/// Loads the API keys from the file at `path`, one key per line in the
+/// [api_key_arg] format. Empty lines and lines starting with "#" are ignored
+pub fn load_api_keys(path: &Path) -> anyhow::Result<Vec<ApiKey>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read api keys file {}", path.display()))?;
+
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| {
+            api_key_arg(line)
+                .map_err(|err| anyhow::anyhow!("{}:{}: {err}", path.display(), index + 1))
+        })
+        .collect()
+}
+
+/// Errors from requests rejected by their API key or client limits
+#[derive(Debug, Error)]
+pub enum AuthError {
+    /// Request did not provide a known API key
+    #[error("missing or invalid api key")]
+    Unauthorized,
+
+    /// API key or client used up its rate of conversions
+    #[error("exceeded the rate of {} conversions per {}ms", rate.count, rate.period.as_millis())]
+    RateLimited {
+        /// Rate of the key
+        rate: Rate,
+        /// Time until the next conversion is allowed
+        retry_after: Duration,
+    },
+
+    /// API key or client already has its maximum number of conversions running
+    #[error("already running the maximum of {max} conversions")]
+    TooManyConversions {
+        /// Maximum conversions of the key
+        max: u32,
+    },
+
+    /// External authorizer denied the request
+    #[error("request denied by the authorizer")]
+    Forbidden,
+
+    /// External authorizer could not be reached or failed to make a decision
+    #[error("authorizer unavailable: {reason}")]
+    AuthorizerUnavailable {
+        /// Reason the authorizer failed
+        reason: String,
+    },
+}
+
+impl HttpError for AuthError {
+    fn log(&self) {
+        match self {
+            AuthError::AuthorizerUnavailable { .. } => error!("{self}"),
+            // Rejecting callers is expected, it is not a server failure
+            _ => warn!("{self}"),
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            AuthError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AuthError::RateLimited { .. } | AuthError::TooManyConversions { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            AuthError::Forbidden => StatusCode::FORBIDDEN,
+            AuthError::AuthorizerUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        if let AuthError::RateLimited { retry_after, .. } = self {
+            // Retry-After is in whole seconds, rounded up
+            let seconds = retry_after.as_millis().div_ceil(1000).max(1);
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(seconds as u64));
+        }
+
+        headers
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            AuthError::Unauthorized => "unauthorized",
+            AuthError::RateLimited { .. } => "rate_limited",
+            AuthError::TooManyConversions { .. } => "too_many_conversions",
+            AuthError::Forbidden => "forbidden",
+            AuthError::AuthorizerUnavailable { .. } => "authorizer_unavailable",
+        })
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            AuthError::Unauthorized
+            | AuthError::Forbidden
+            | AuthError::AuthorizerUnavailable { .. } => None,
+            AuthError::RateLimited { rate, retry_after } => Some(json!({
+                "count": rate.count,
+                "period_ms": rate.period.as_millis() as u64,
+                "retry_after_ms": retry_after.as_millis() as u64,
+            })),
+            AuthError::TooManyConversions { max } => Some(json!({ "max": max })),
+        }
+    }
+}
+
+/// Token bucket refilled at the rate of a key
+#[derive(Debug)]
+struct TokenBucket {
+    /// Conversions currently available
+    tokens: f64,
+    /// Last time the bucket was refilled
+    refilled: Instant,
+}
+
+/// State of the limits of an API key or client
+#[derive(Debug)]
+struct KeyState {
+    /// Limits of the key
+    limits: KeyLimits,
+    /// Bucket of conversions when the key has a rate
+    bucket: Mutex<TokenBucket>,
+    /// Conversions running for the key when the key has a maximum
+    running: Option<Arc<Semaphore>>,
+}
+
+impl KeyState {
+    fn new(limits: KeyLimits, now: Instant) -> Self {
+        Self {
+            limits,
+            bucket: Mutex::new(TokenBucket {
+                tokens: limits
+                    .rate
+                    .map(|rate| rate.count as f64)
+                    .unwrap_or_default(),
+                refilled: now,
+            }),
+            running: limits
+                .max_concurrent
+                .map(|max| Arc::new(Semaphore::new(max as usize))),
+        }
+    }
+
+    /// Admits a conversion when it is within the limits, a conversion
+    /// rejected for running too many conversions doesn't use up the rate
+    fn admit(&self) -> Result<Admission, AuthError> {
+        let permit = match (&self.running, self.limits.max_concurrent) {
+            (Some(running), Some(max)) => Some(
+                running
+                    .clone()
+                    .try_acquire_owned()
+                    .map_err(|_| AuthError::TooManyConversions { max })?,
+            ),
+            _ => None,
+        };
+
+        if let Some(rate) = self.limits.rate {
+            let mut bucket = self.bucket.lock();
+            let per_second = rate.count as f64 / rate.period.as_secs_f64();
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * per_second).min(rate.count as f64);
+            bucket.refilled = now;
+
+            if bucket.tokens < 1.0 {
+                let retry_after = Duration::from_secs_f64((1.0 - bucket.tokens) / per_second);
+                return Err(AuthError::RateLimited { rate, retry_after });
+            }
+
+            bucket.tokens -= 1.0;
+        }
+
+        Ok(Admission { _permit: permit })
+    }
+
+    /// Whether the state is the same as a new state at `now`, idle states
+    /// can be removed without changing the limits
+    fn is_idle(&self, now: Instant) -> bool {
+        let running = match (&self.running, self.limits.max_concurrent) {
+            (Some(running), Some(max)) => running.available_permits() < max as usize,
+            _ => false,
+        };
+
+        let refilled = match self.limits.rate {
+            Some(rate) => {
+                let bucket = self.bucket.lock();
+                let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+                let per_second = rate.count as f64 / rate.period.as_secs_f64();
+                bucket.tokens + elapsed * per_second >= rate.count as f64
+            }
+            None => true,
+        };
+
+        !running && refilled
+    }
+}
+
+/// Conversion admitted by the limits of its API key, the conversion counts
+/// as running until the admission is dropped
+#[derive(Debug)]
+pub struct Admission {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Admission of a conversion request shared with its handler through the
+/// request extensions. Handlers that keep converting after responding (i.e
+/// background jobs) take the admission so the conversion counts as running
+/// until it finishes rather than until the response is created
+#[derive(Debug, Clone, Default)]
+pub struct SharedAdmission(Arc<Mutex<Option<Admission>>>);
+
+impl SharedAdmission {
+    pub fn new(admission: Option<Admission>) -> Self {
+        Self(Arc::new(Mutex::new(admission)))
+    }
+
+    /// Takes the admission, leaving the request without one
+    pub fn take(&self) -> Option<Admission> {
+        self.0.lock().take()
+    }
+}
+
+/// API keys accepted by the server along with the state of their limits
+#[derive(Debug)]
+pub struct ApiKeys {
+    keys: HashMap<String, KeyState>,
+}
+
+impl ApiKeys {
+    /// Creates the accepted `keys`, limits not set by a key use the `defaults`.
+    /// Keys provided more than once use the last limits provided
+    pub fn new(keys: Vec<ApiKey>, defaults: KeyLimits) -> Self {
+        let now = Instant::now();
+
+        let keys = keys
+            .into_iter()
+            .map(|key| {
+                let limits = KeyLimits {
+                    rate: key.limits.rate.or(defaults.rate),
+                    max_concurrent: key.limits.max_concurrent.or(defaults.max_concurrent),
+                };
+
+                (key.key, KeyState::new(limits, now))
+            })
+            .collect();
+
+        Self { keys }
+    }
+
+    /// Number of accepted keys
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether no keys are accepted
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Provides the known API key from the request `headers`
+    pub fn authenticate<'a>(&self, headers: &'a HeaderMap) -> Result<&'a str, AuthError> {
+        headers
+            .get("x-api-key")
+            .and_then(|value| value.to_str().ok())
+            .filter(|key| self.keys.contains_key(*key))
+            .ok_or(AuthError::Unauthorized)
+    }
+
+    /// Admits a conversion for the `key` when it is within the limits of the
+    /// key, a conversion rejected for running too many conversions doesn't
+    /// use up the rate of the key
+    pub fn admit(&self, key: &str) -> Result<Admission, AuthError> {
+        self.keys.get(key).ok_or(AuthError::Unauthorized)?.admit()
+    }
+}
+
+/// Number of clients tracked before idle clients are removed
+const MAX_IDLE_CLIENTS: usize = 10_000;
+
+/// Limits applied to the conversions of each client IP address when API
+/// keys are not configured
+#[derive(Debug)]
+pub struct ClientLimits {
+    /// Limits of each client
+    limits: KeyLimits,
+    /// State of the clients with recent conversions
+    clients: Mutex<HashMap<IpAddr, Arc<KeyState>>>,
+}
+
+impl ClientLimits {
+    /// Creates the client limits, [None] when there are no `limits`
+    pub fn new(limits: KeyLimits) -> Option<Self> {
+        if limits.rate.is_none() && limits.max_concurrent.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            limits,
+            clients: Default::default(),
+        })
+    }
+
+    /// Admits a conversion for the client at `ip` when it is within the limits
+    pub fn admit(&self, ip: IpAddr) -> Result<Admission, AuthError> {
+        let state = {
+            let mut clients = self.clients.lock();
+            let now = Instant::now();
+
+            if clients.len() >= MAX_IDLE_CLIENTS && !clients.contains_key(&ip) {
+                clients.retain(|_, state| !state.is_idle(now));
+            }
+
+            clients
+                .entry(ip)
+                .or_insert_with(|| Arc::new(KeyState::new(self.limits, now)))
+                .clone()
+        };
+
+        state.admit()
+    }
+}
+
+/// Header providing the tenant of a request, forwarded to the authorizer
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+/// Number of decisions cached before expired decisions are removed
+const MAX_CACHED_DECISIONS: usize = 10_000;
+
+/// Headers of requests forwarded to the authorizer by default, the
+/// credentials a client may provide
+const FORWARDED_HEADERS: [HeaderName; 4] = [
+    HeaderName::from_static("x-api-key"),
+    HeaderName::from_static(TENANT_HEADER),
+    header::AUTHORIZATION,
+    header::COOKIE,
+];
+
+/// Details of a request the authorizer decides on, decisions are cached by
+/// the details they were made for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DecisionKey {
+    method: Method,
+    /// Path and query of the request
+    uri: String,
+    /// Forwarded headers provided by the request in the order they are
+    /// forwarded
+    headers: Vec<(HeaderName, HeaderValue)>,
+    client_ip: Option<IpAddr>,
+}
+
+/// Decision made by the authorizer
+#[derive(Debug, Clone, Copy)]
+enum Decision {
+    Allow,
+    /// Authorizer responded with a 401, the credentials were not known
+    Unauthorized,
+    /// Authorizer responded with any other client error status
+    Forbidden,
+}
+
+/// External HTTP endpoint authorization decisions are delegated to instead
+/// of static API keys (Forward-auth). The authorizer is sent a GET request
+/// with the method ("X-Forwarded-Method"), path and query ("X-Forwarded-Uri")
+/// and client address ("X-Forwarded-For") of the request along with its
+/// credential headers ("X-Api-Key", "X-Tenant-Id", "Authorization" and
+/// "Cookie" and any extra forwarded headers), any successful status allows
+/// the request
+#[derive(Debug)]
+pub struct Authorizer {
+    client: reqwest::Client,
+    /// URL of the authorizer endpoint
+    url: Url,
+    /// Headers of the request forwarded to the authorizer
+    headers: Vec<HeaderName>,
+    /// Time decisions are cached for
+    ttl: Duration,
+    /// Recent decisions along with when they expire
+    decisions: Mutex<HashMap<DecisionKey, (Decision, Instant)>>,
+}
+
+impl Authorizer {
+    /// Creates an authorizer for the endpoint at the `url`, requests taking
+    /// longer than the `timeout` fail. Decisions are cached for the `ttl`
+    pub fn new(url: Url, ttl: Duration, timeout: Duration) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("failed to create authorizer client")?;
+
+        Ok(Self {
+            client,
+            url,
+            headers: FORWARDED_HEADERS.to_vec(),
+            ttl,
+            decisions: Default::default(),
+        })
+    }
+
+    /// Forwards the `headers` of requests to the authorizer along with the
+    /// credential headers forwarded by default
+    pub fn with_forwarded_headers(mut self, headers: Vec<HeaderName>) -> Self {
+        for header in headers {
+            if !self.headers.contains(&header) {
+                self.headers.push(header);
+            }
+        }
+
+        self
+    }
+
+    /// Asks the authorizer whether the request with the `method`, `uri` (Path
+    /// and query) and `headers` from the client at `client_ip` is allowed,
+    /// using a cached decision for the same details when available. Failures
+    /// to reach the authorizer are not cached
+    pub async fn authorize(
+        &self,
+        method: &Method,
+        uri: &str,
+        headers: &HeaderMap,
+        client_ip: Option<IpAddr>,
+    ) -> Result<(), AuthError> {
+        let key = DecisionKey {
+            method: method.clone(),
+            uri: uri.to_string(),
+            headers: self
+                .headers
+                .iter()
+                .flat_map(|name| {
+                    headers
+                        .get_all(name)
+                        .iter()
+                        .map(|value| (name.clone(), value.clone()))
+                })
+                .collect(),
+            client_ip,
+        };
+
+        let cached = self
+            .decisions
+            .lock()
+            .get(&key)
+            .filter(|(_, expires)| *expires > Instant::now())
+            .map(|(decision, _)| *decision);
+
+        let decision = match cached {
+            Some(decision) => decision,
+            None => {
+                let decision = self.request(&key).await?;
+                self.cache(key, decision);
+                decision
+            }
+        };
+
+        match decision {
+            Decision::Allow => Ok(()),
+            Decision::Unauthorized => Err(AuthError::Unauthorized),
+            Decision::Forbidden => Err(AuthError::Forbidden),
+        }
+    }
+
+    /// Requests a decision for the request `key` from the authorizer
+    async fn request(&self, key: &DecisionKey) -> Result<Decision, AuthError> {
+        let mut request = self
+            .client
+            .get(self.url.clone())
+            .header("x-forwarded-method", key.method.as_str())
+            .header("x-forwarded-uri", &key.uri);
+
+        for (name, value) in &key.headers {
+            request = request.header(name, value);
+        }
+
+        if let Some(client_ip) = key.client_ip {
+            request = request.header("x-forwarded-for", client_ip.to_string());
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|err| AuthError::AuthorizerUnavailable {
+                reason: err.without_url().to_string(),
+            })?;
+
+        let status = response.status();
+        if status.is_success() {
+            Ok(Decision::Allow)
+        } else if status == StatusCode::UNAUTHORIZED {
+            Ok(Decision::Unauthorized)
+        } else if status.is_client_error() {
+            Ok(Decision::Forbidden)
+        } else {
+            Err(AuthError::AuthorizerUnavailable {
+                reason: format!("authorizer responded with {status}"),
+            })
+        }
+    }
+
+    /// Caches the `decision` for the request `key`
+    fn cache(&self, key: DecisionKey, decision: Decision) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let decisions = &mut *self.decisions.lock();
+        let now = Instant::now();
+
+        if decisions.len() >= MAX_CACHED_DECISIONS {
+            decisions.retain(|_, (_, expires)| *expires > now);
+
+            // Every decision is still fresh, the next request asks again
+            if decisions.len() >= MAX_CACHED_DECISIONS {
+                return;
+            }
+        }
+
+        decisions.insert(key, (decision, now + self.ttl));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(rate: Option<&str>, max_concurrent: Option<u32>) -> KeyLimits {
+        KeyLimits {
+            rate: rate.map(|rate| rate_arg(rate).unwrap()),
+            max_concurrent,
+        }
+    }
+
+    /// Moves the last refill of the `state` back by `elapsed`, as if the
+    /// time had passed since the bucket was last used
+    fn rewind(state: &KeyState, elapsed: Duration) {
+        let mut bucket = state.bucket.lock();
+        bucket.refilled = bucket.refilled.checked_sub(elapsed).unwrap();
+    }
+
+    #[test]
+    fn rates_parse_units_and_durations() {
+        let rate = rate_arg("10/s").unwrap();
+        assert_eq!(rate.count, 10);
+        assert_eq!(rate.period, Duration::from_secs(1));
+
+        assert_eq!(rate_arg("600/m").unwrap().period, Duration::from_secs(60));
+        assert_eq!(rate_arg("100/30s").unwrap().period, Duration::from_secs(30));
+
+        assert!(rate_arg("0/s").is_err());
+        assert!(rate_arg("10/0s").is_err());
+        assert!(rate_arg("10").is_err());
+        assert!(rate_arg("ten/s").is_err());
+    }
+
+    #[test]
+    fn api_keys_parse_limits() {
+        let key = api_key_arg("web-app,rate=10/s,max-concurrent=2").unwrap();
+        assert_eq!(key.key, "web-app");
+        assert_eq!(key.limits, limits(Some("10/s"), Some(2)));
+
+        assert_eq!(api_key_arg("batch").unwrap().limits, KeyLimits::default());
+
+        assert!(api_key_arg("").is_err());
+        assert!(api_key_arg("web app").is_err());
+        assert!(api_key_arg("web-app,max-concurrent=0").is_err());
+        assert!(api_key_arg("web-app,burst=10").is_err());
+        assert!(api_key_arg("web-app,rate").is_err());
+    }
+
+    #[test]
+    fn token_bucket_allows_bursts_then_limits() {
+        let state = KeyState::new(limits(Some("2/s"), None), Instant::now());
+
+        assert!(state.admit().is_ok());
+        assert!(state.admit().is_ok());
+
+        match state.admit() {
+            Err(AuthError::RateLimited { rate, retry_after }) => {
+                assert_eq!(rate.count, 2);
+                assert!(!retry_after.is_zero());
+                assert!(retry_after <= Duration::from_millis(500));
+            }
+            result => panic!("expected a rate limit, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn token_bucket_refills_at_the_rate() {
+        let state = KeyState::new(limits(Some("2/s"), None), Instant::now());
+        assert!(state.admit().is_ok());
+        assert!(state.admit().is_ok());
+
+        // Half the period refills one conversion
+        rewind(&state, Duration::from_millis(500));
+        assert!(state.admit().is_ok());
+        assert!(matches!(state.admit(), Err(AuthError::RateLimited { .. })));
+
+        // Refills are capped at the burst of the rate
+        rewind(&state, Duration::from_secs(10));
+        assert!(state.admit().is_ok());
+        assert!(state.admit().is_ok());
+        assert!(matches!(state.admit(), Err(AuthError::RateLimited { .. })));
+    }
+
+    #[test]
+    fn concurrent_limit_is_released_with_the_admission() {
+        let state = KeyState::new(limits(Some("2/m"), Some(1)), Instant::now());
+
+        let admission = state.admit().unwrap();
+        assert!(matches!(
+            state.admit(),
+            Err(AuthError::TooManyConversions { max: 1 })
+        ));
+
+        // The rejected conversion didn't use up the rate
+        drop(admission);
+        drop(state.admit().unwrap());
+        assert!(matches!(state.admit(), Err(AuthError::RateLimited { .. })));
+    }
+
+    #[test]
+    fn key_states_are_idle_once_released_and_refilled() {
+        let now = Instant::now();
+
+        let state = KeyState::new(KeyLimits::default(), now);
+        assert!(state.is_idle(now));
+
+        let state = KeyState::new(limits(Some("2/s"), Some(2)), now);
+        assert!(state.is_idle(now));
+
+        // Running conversions and a partly used bucket keep the state
+        let admission = state.admit().unwrap();
+        assert!(!state.is_idle(Instant::now()));
+
+        drop(admission);
+        assert!(!state.is_idle(Instant::now()));
+
+        rewind(&state, Duration::from_secs(1));
+        assert!(state.is_idle(Instant::now()));
+    }
+
+    #[test]
+    fn api_keys_use_default_limits() {
+        let keys = ApiKeys::new(
+            vec![
+                api_key_arg("web-app,max-concurrent=2").unwrap(),
+                api_key_arg("batch").unwrap(),
+            ],
+            limits(None, Some(1)),
+        );
+        assert_eq!(keys.len(), 2);
+
+        let web_app = [
+            keys.admit("web-app").unwrap(),
+            keys.admit("web-app").unwrap(),
+        ];
+        assert!(keys.admit("web-app").is_err());
+        drop(web_app);
+
+        let _batch = keys.admit("batch").unwrap();
+        assert!(matches!(
+            keys.admit("batch"),
+            Err(AuthError::TooManyConversions { max: 1 })
+        ));
+
+        assert!(matches!(
+            keys.admit("unknown"),
+            Err(AuthError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn api_keys_authenticate_known_keys() {
+        let keys = ApiKeys::new(vec![api_key_arg("web-app").unwrap()], KeyLimits::default());
+
+        let mut headers = HeaderMap::new();
+        assert!(matches!(
+            keys.authenticate(&headers),
+            Err(AuthError::Unauthorized)
+        ));
+
+        headers.insert("x-api-key", HeaderValue::from_static("other"));
+        assert!(keys.authenticate(&headers).is_err());
+
+        headers.insert("x-api-key", HeaderValue::from_static("web-app"));
+        assert_eq!(keys.authenticate(&headers).unwrap(), "web-app");
+    }
+
+    #[test]
+    fn client_limits_track_each_client() {
+        assert!(ClientLimits::new(KeyLimits::default()).is_none());
+
+        let clients = ClientLimits::new(limits(Some("1/m"), None)).unwrap();
+        let first: IpAddr = "198.51.100.1".parse().unwrap();
+        let second: IpAddr = "198.51.100.2".parse().unwrap();
+
+        assert!(clients.admit(first).is_ok());
+        assert!(clients.admit(first).is_err());
+        assert!(clients.admit(second).is_ok());
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::Context;
+use clap::Args;
+use office_convert_client::{ConvertOffice, OfficeConvertClient, RequestError};
+use office_convert_server::config::duration_arg;
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{task::JoinSet, time::Instant};
+
+/// Arguments for the bench subcommand
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// URL of the server to benchmark (i.e http://localhost:8080)
+    #[arg(long)]
+    server: String,
+
+    /// File to convert repeatedly
+    #[arg(long)]
+    file: PathBuf,
+
+    /// Number of concurrent requests to keep in flight, defaults to 8
+    #[arg(long, default_value_t = 8)]
+    concurrency: usize,
+
+    /// Duration to run the benchmark for (i.e "60s", "500ms", "2m"), defaults to 60s
+    #[arg(long, default_value = "60s", value_parser = duration_arg)]
+    duration: Duration,
+}
+
+/// Result of a single convert request
+struct Sample {
+    /// Time taken for the request
+    latency: Duration,
+    /// Error category when the request failed
+    error: Option<String>,
+}
+
+/// Drives the convert endpoint of a server using the client with the provided
+/// concurrency for the benchmark duration, then prints the throughput, latency
+/// percentiles, and error breakdown
+pub async fn run(args: BenchArgs) -> anyhow::Result<()> {
+    let file = tokio::fs::read(&args.file)
+        .await
+        .with_context(|| format!("failed to read {}", args.file.display()))?;
+    let file: Arc<[u8]> = file.into();
+
+    let client = OfficeConvertClient::new(args.server.as_str())?;
+    let concurrency = args.concurrency.max(1);
+
+    println!(
+        "benchmarking {} with {} concurrent requests for {:?}",
+        args.server, concurrency, args.duration
+    );
+
+    let started = Instant::now();
+    let deadline = started + args.duration;
+
+    let mut workers = JoinSet::new();
+
+    for _ in 0..concurrency {
+        let client = client.clone();
+        let file = file.clone();
+
+        workers.spawn(async move {
+            let mut samples = Vec::new();
+
+            while Instant::now() < deadline {
+                let start = Instant::now();
+                let result = client.convert(file.to_vec()).await;
+
+                samples.push(Sample {
+                    latency: start.elapsed(),
+                    error: result.err().map(|err| error_category(&err)),
+                });
+            }
+
+            samples
+        });
+    }
+
+    let mut samples = Vec::new();
+    while let Some(result) = workers.join_next().await {
+        samples.extend(result.context("benchmark worker failed")?);
+    }
+
+    let elapsed = started.elapsed();
+
+    report(&samples, elapsed);
+
+    Ok(())
+}
+
+/// Provides a short category for an error used to group errors in the report
+fn error_category(err: &RequestError) -> String {
+    match err {
+        RequestError::Connect { .. } => "connect".to_string(),
+        RequestError::Timeout { .. } => "timeout".to_string(),
+        RequestError::RequestFailed { .. } => "network".to_string(),
+        RequestError::InvalidResponse { .. } => "invalid response".to_string(),
+        RequestError::ClientError(response) | RequestError::ServerError(response) => {
+            match &response.code {
+                Some(code) => format!("{} ({})", response.status.as_u16(), code.as_str()),
+                None => response.status.as_u16().to_string(),
+            }
+        }
+        err => err.to_string(),
+    }
+}
+
+/// Prints the benchmark report for the collected `samples`
+fn report(samples: &[Sample], elapsed: Duration) {
+    let mut latencies: Vec<Duration> = samples
+        .iter()
+        .filter(|sample| sample.error.is_none())
+        .map(|sample| sample.latency)
+        .collect();
+    latencies.sort_unstable();
+
+    let mut errors: BTreeMap<&str, usize> = BTreeMap::new();
+    for error in samples.iter().filter_map(|sample| sample.error.as_deref()) {
+        *errors.entry(error).or_default() += 1;
+    }
+
+    let succeeded = latencies.len();
+    let failed = samples.len() - succeeded;
+    let seconds = elapsed.as_secs_f64();
+
+    println!();
+    println!(
+        "requests:   {} ({succeeded} succeeded, {failed} failed)",
+        samples.len()
+    );
+    println!("elapsed:    {seconds:.2}s");
+    println!(
+        "throughput: {:.2} conversions/s",
+        succeeded as f64 / seconds
+    );
+
+    if !latencies.is_empty() {
+        println!();
+        println!("latency:");
+        for (label, percentile) in [("p50", 0.50), ("p90", 0.90), ("p95", 0.95), ("p99", 0.99)] {
+            println!("  {label}: {:?}", percentile_of(&latencies, percentile));
+        }
+        println!("  max: {:?}", latencies[latencies.len() - 1]);
+    }
+
+    if !errors.is_empty() {
+        println!();
+        println!("errors:");
+        for (error, count) in errors {
+            println!("  {error}: {count}");
+        }
+    }
+}
+
+/// Provides the `percentile` (0.0-1.0) of the sorted `latencies` using the
+/// nearest rank method
+fn percentile_of(latencies: &[Duration], percentile: f64) -> Duration {
+    let rank = (percentile * latencies.len() as f64).ceil() as usize;
+    latencies[rank.clamp(1, latencies.len()) - 1]
+}
@@ -0,0 +1,179 @@
+use super::CacheMetadata;
+use anyhow::Context;
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{path::Path, sync::Arc};
+
+/// Name of the index database within the cache directory
+pub(super) const INDEX_FILE: &str = "index.sqlite";
+
+/// Version of the index schema, stored as the user version of the database
+const SCHEMA_VERSION: i32 = 1;
+
+/// Persistent index of the entries of a cache kept on the local disk, maps
+/// the key of each entry (Hash of the input and options) to the metadata of
+/// its stored result.
+///
+/// The index is a SQLite database in WAL mode, each change is a single
+/// transaction so the index is never left half updated by a crash. Results
+/// are stored before they are added to the index and removed from the index
+/// before they are deleted, so the index only refers to complete results
+#[derive(Clone)]
+pub(super) struct CacheIndex {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl CacheIndex {
+    /// Opens the index database at the `path`, creating it when missing.
+    /// Provides the index along with whether it was created, the version of
+    /// created indexes is only stored by [CacheIndex::finish_creating] so an
+    /// index interrupted while being filled is filled again
+    pub fn open(path: &Path) -> anyhow::Result<(Self, bool)> {
+        let connection = Connection::open(path).context("failed to open cache index")?;
+
+        connection
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+            .context("failed to enable cache index journal")?;
+        connection
+            .pragma_update(None, "synchronous", "NORMAL")
+            .context("failed to configure cache index")?;
+
+        let version: i32 = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .context("failed to read cache index version")?;
+
+        let created = version == 0;
+        if created {
+            connection
+                .execute_batch(
+                    "CREATE TABLE IF NOT EXISTS entries (
+                        key TEXT PRIMARY KEY NOT NULL,
+                        content_type TEXT NOT NULL,
+                        compressed INTEGER NOT NULL,
+                        sha256 TEXT,
+                        size INTEGER,
+                        stored_sha256 TEXT
+                    );",
+                )
+                .context("failed to create cache index")?;
+        } else if version != SCHEMA_VERSION {
+            anyhow::bail!("cache index has unknown version {version}");
+        }
+
+        let index = Self {
+            connection: Arc::new(Mutex::new(connection)),
+        };
+
+        Ok((index, created))
+    }
+
+    /// Stores the version of a created index once it has been filled
+    pub fn finish_creating(&self) -> anyhow::Result<()> {
+        self.connection
+            .lock()
+            .pragma_update(None, "user_version", SCHEMA_VERSION)
+            .context("failed to store cache index version")
+    }
+
+    /// Provides the metadata of the entry with the `key`
+    pub async fn get(&self, key: &str) -> anyhow::Result<Option<CacheMetadata>> {
+        let key = key.to_string();
+        self.run(move |connection| {
+            connection
+                .query_row(
+                    "SELECT content_type, compressed, sha256, size, stored_sha256
+                    FROM entries WHERE key = ?1",
+                    params![key],
+                    |row| {
+                        Ok(CacheMetadata {
+                            content_type: row.get(0)?,
+                            compressed: row.get(1)?,
+                            sha256: row.get(2)?,
+                            size: row.get(3)?,
+                            stored_sha256: row.get(4)?,
+                        })
+                    },
+                )
+                .optional()
+        })
+        .await
+    }
+
+    /// Adds the entry with the `key` and `meta`, replacing any entry stored
+    /// under the same key
+    pub async fn insert(&self, key: &str, meta: CacheMetadata) -> anyhow::Result<()> {
+        let key = key.to_string();
+        self.run(move |connection| insert(connection, &key, &meta))
+            .await
+    }
+
+    /// Removes the entry with the `key`, provides whether it was indexed
+    pub async fn remove(&self, key: &str) -> anyhow::Result<bool> {
+        let key = key.to_string();
+        self.run(move |connection| {
+            connection
+                .execute("DELETE FROM entries WHERE key = ?1", params![key])
+                .map(|removed| removed > 0)
+        })
+        .await
+    }
+
+    /// Provides the number of indexed entries and their total stored size in
+    /// bytes
+    pub async fn totals(&self) -> anyhow::Result<(u64, u64)> {
+        self.run(|connection| {
+            connection.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM entries",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+        })
+        .await
+    }
+
+    /// Provides the keys of every indexed entry
+    pub async fn keys(&self) -> anyhow::Result<Vec<String>> {
+        self.run(|connection| {
+            let mut statement = connection.prepare("SELECT key FROM entries")?;
+            let keys = statement.query_map([], |row| row.get(0))?;
+            keys.collect()
+        })
+        .await
+    }
+
+    /// Adds the entry with the `key` and `meta` while blocking, used while
+    /// importing entries before the cache is used
+    pub fn insert_blocking(&self, key: &str, meta: &CacheMetadata) -> anyhow::Result<()> {
+        insert(&self.connection.lock(), key, meta).context("failed to update cache index")
+    }
+
+    /// Runs the `action` on the database connection on the blocking pool
+    async fn run<T, F>(&self, action: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || action(&connection.lock()))
+            .await
+            .context("cache index task failed")?
+            .context("failed to query cache index")
+    }
+}
+
+fn insert(connection: &Connection, key: &str, meta: &CacheMetadata) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO entries (key, content_type, compressed, sha256, size, stored_sha256)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            key,
+            meta.content_type,
+            meta.compressed,
+            meta.sha256,
+            meta.size,
+            meta.stored_sha256
+        ],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,670 @@
+use crate::{
+    storage::{LocalStorage, Storage},
+    tempfiles::random_id,
+};
+use anyhow::Context;
+use axum::http::HeaderValue;
+use bytes::Bytes;
+use index::{CacheIndex, INDEX_FILE};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::OwnedMutexGuard;
+use tracing::{debug, warn};
+
+mod index;
+
+/// Key of the lease held by the server cleaning up the storage
+const CLEANUP_LEASE: &str = "cleanup.lease";
+
+/// Time the cleanup lease is held for, servers sharing the storage that
+/// start maintenance at the same time skip the cleanup
+const CLEANUP_LEASE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Content addressed cache for converted documents, entries are keyed
+/// by the hash of the input document and the conversion options so a cached
+/// entry will never change once stored.
+///
+/// Caches on the local disk keep the metadata of each entry in a persistent
+/// index (See [CacheIndex]), lookups, size accounting and purges query the
+/// index instead of the stored files. Caches on shared storage store the
+/// metadata of each entry alongside its result so every server sharing the
+/// storage sees it. Either way results are stored before their metadata (and
+/// metadata is removed first) so metadata only exists for complete entries,
+/// and entries are validated against their metadata the first time they are
+/// served by the process so results damaged by a disk failure are never served
+pub struct ResultCache {
+    /// Storage the cache entries are kept in
+    storage: Arc<dyn Storage>,
+    /// Index of the entries of a cache on the local disk, [None] when the
+    /// metadata is stored alongside the results
+    index: Option<CacheIndex>,
+    /// Unique ID of the cache the cleanup lease is held as
+    instance: String,
+    /// Max age clients and CDNs are allowed to cache results for
+    max_age: Duration,
+    /// Zstd compression level to store results with, [None] stores
+    /// results uncompressed
+    compression_level: Option<i32>,
+    /// Locks for keys that are currently being converted, used to coalesce
+    /// identical conversions into a single conversion
+    inflight: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    /// Keys of entries that have been validated or stored by this process,
+    /// only skips repeated validation and is rebuilt after restarts
+    verified: Mutex<HashSet<String>>,
+    /// Usage statistics
+    stats: CacheCounters,
+}
+
+/// Counters tracking cache usage
+#[derive(Default)]
+struct CacheCounters {
+    /// Number of requests served from the cache
+    hits: AtomicU64,
+    /// Number of requests that had to be converted
+    misses: AtomicU64,
+    /// Number of requests that waited on an identical in-flight conversion
+    /// and were served its result
+    coalesced: AtomicU64,
+    /// Number of entries removed for failing validation
+    invalid: AtomicU64,
+}
+
+/// Snapshot of the cache statistics
+#[derive(Debug, Serialize)]
+pub struct CacheStats {
+    /// Number of entries stored in the cache
+    pub entries: u64,
+    /// Total size of the stored entries in bytes
+    pub size_bytes: u64,
+    /// Number of requests served from the cache
+    pub hits: u64,
+    /// Number of requests that had to be converted
+    pub misses: u64,
+    /// Number of requests served the result of an identical in-flight conversion
+    pub coalesced: u64,
+    /// Number of entries removed because their stored result was missing or
+    /// did not match their metadata
+    pub invalid: u64,
+    /// Number of keys currently being converted
+    pub inflight: usize,
+}
+
+/// Cache usage outcomes that can be recorded
+pub enum CacheOutcome {
+    /// Served from the cache
+    Hit,
+    /// Converted
+    Miss,
+    /// Served the result of an identical in-flight conversion
+    Coalesced,
+}
+
+/// Guard held while converting a key, identical conversions will wait on
+/// this guard to be released before checking the cache again
+pub struct InflightGuard {
+    /// Cache the guard belongs to
+    cache: Arc<ResultCache>,
+    /// Key being converted
+    key: String,
+    /// Lock for the key
+    guard: Option<OwnedMutexGuard<()>>,
+    /// Whether another conversion of this key was in-flight when acquired
+    waited: bool,
+}
+
+impl InflightGuard {
+    /// Whether another conversion of the same key was in-flight and had to
+    /// be waited on, the cache should be checked again when this is true
+    pub fn waited(&self) -> bool {
+        self.waited
+    }
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        // Release the lock before checking for other users
+        drop(self.guard.take());
+
+        let inflight = &mut *self.cache.inflight.lock();
+
+        // Remove the lock if nobody else is waiting on it
+        if inflight
+            .get(&self.key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            inflight.remove(&self.key);
+        }
+    }
+}
+
+/// Cached conversion result
+pub struct CachedResult {
+    /// The converted file bytes
+    pub bytes: Bytes,
+    /// Mime type of the converted file
+    pub content_type: String,
+    /// Whether the bytes are zstd compressed
+    pub compressed: bool,
+    /// SHA-256 hex digest of the uncompressed bytes (Entries stored by older
+    /// versions don't have a digest)
+    pub sha256: Option<String>,
+}
+
+impl CachedResult {
+    /// Decompresses the result bytes if they are compressed
+    pub async fn decompress(self) -> anyhow::Result<CachedResult> {
+        if !self.compressed {
+            return Ok(self);
+        }
+
+        let bytes = self.bytes;
+        let bytes = tokio::task::spawn_blocking(move || zstd::decode_all(bytes.as_ref()))
+            .await
+            .context("decompress task failed")?
+            .context("failed to decompress cache entry")?;
+
+        Ok(CachedResult {
+            bytes: Bytes::from(bytes),
+            content_type: self.content_type,
+            compressed: false,
+            sha256: self.sha256,
+        })
+    }
+}
+
+/// Metadata of a cached result
+#[derive(Serialize, Deserialize)]
+struct CacheMetadata {
+    /// Mime type of the converted file
+    content_type: String,
+    /// Whether the stored file is zstd compressed
+    #[serde(default)]
+    compressed: bool,
+    /// SHA-256 hex digest of the uncompressed file
+    #[serde(default)]
+    sha256: Option<String>,
+    /// Size of the stored file in bytes (Entries stored by older versions
+    /// don't have a size)
+    #[serde(default)]
+    size: Option<u64>,
+    /// SHA-256 hex digest of the stored file, differs from `sha256` when
+    /// compressed (Entries stored by older versions don't have a digest)
+    #[serde(default)]
+    stored_sha256: Option<String>,
+}
+
+impl CacheMetadata {
+    /// Checks the stored file `bytes` match the metadata, entries stored by
+    /// older versions are checked against what they recorded
+    fn matches(&self, bytes: &[u8]) -> bool {
+        if self.size.is_some_and(|size| size != bytes.len() as u64) {
+            return false;
+        }
+
+        let expected = match (&self.stored_sha256, &self.sha256) {
+            (Some(digest), _) => digest,
+            (None, Some(digest)) if !self.compressed => digest,
+            _ => return true,
+        };
+
+        format!("{:x}", Sha256::digest(bytes)) == *expected
+    }
+}
+
+impl ResultCache {
+    /// Creates a new cache storing entries in the provided `dir` indexed by
+    /// a database in the directory, the directory will be created if it
+    /// doesn't exist. Metadata stored alongside the results by earlier
+    /// versions is moved into the index when the index is created
+    pub fn new(
+        dir: PathBuf,
+        max_age: Duration,
+        compression_level: Option<i32>,
+    ) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir).context("failed to create cache directory")?;
+
+        let (index, created) = CacheIndex::open(&dir.join(INDEX_FILE))?;
+        if created {
+            let imported = import_metadata(&dir, &index)?;
+            if imported > 0 {
+                debug!(imported, "moved cache metadata into the index");
+            }
+            index.finish_creating()?;
+        }
+
+        Ok(Self {
+            index: Some(index),
+            ..Self::with_storage(Arc::new(LocalStorage::new(dir)), max_age, compression_level)
+        })
+    }
+
+    /// Creates a new cache keeping entries in the `storage`, caches sharing
+    /// a storage serve each others results
+    pub fn with_storage(
+        storage: Arc<dyn Storage>,
+        max_age: Duration,
+        compression_level: Option<i32>,
+    ) -> Self {
+        Self {
+            storage,
+            index: None,
+            instance: random_id(),
+            max_age,
+            compression_level,
+            inflight: Default::default(),
+            verified: Default::default(),
+            stats: Default::default(),
+        }
+    }
+
+    /// Records a cache usage outcome in the statistics
+    pub fn record(&self, outcome: CacheOutcome) {
+        let counter = match outcome {
+            CacheOutcome::Hit => &self.stats.hits,
+            CacheOutcome::Miss => &self.stats.misses,
+            CacheOutcome::Coalesced => &self.stats.coalesced,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Acquires the in-flight lock for the provided `key`, waiting for any
+    /// identical conversion that is already in-flight
+    pub async fn lock_inflight(self: &Arc<Self>, key: &str) -> InflightGuard {
+        let lock = self
+            .inflight
+            .lock()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+
+        let (guard, waited) = match lock.clone().try_lock_owned() {
+            Ok(guard) => (guard, false),
+            Err(_) => (lock.lock_owned().await, true),
+        };
+
+        InflightGuard {
+            cache: self.clone(),
+            key: key.to_string(),
+            guard: Some(guard),
+            waited,
+        }
+    }
+
+    /// Collects the current cache statistics
+    pub async fn stats(&self) -> anyhow::Result<CacheStats> {
+        let (entries, size_bytes) = match self.index.as_ref() {
+            Some(index) => index.totals().await?,
+            None => {
+                let mut entries = 0;
+                let mut size_bytes = 0;
+
+                let objects = self
+                    .storage
+                    .list()
+                    .await
+                    .context("failed to list cache entries")?;
+
+                for object in objects {
+                    match object.key.rsplit_once('.') {
+                        Some((_, "json")) => entries += 1,
+                        Some((_, "bin")) => size_bytes += object.size,
+                        _ => {}
+                    }
+                }
+
+                (entries, size_bytes)
+            }
+        };
+
+        Ok(CacheStats {
+            entries,
+            size_bytes,
+            hits: self.stats.hits.load(Ordering::Relaxed),
+            misses: self.stats.misses.load(Ordering::Relaxed),
+            coalesced: self.stats.coalesced.load(Ordering::Relaxed),
+            invalid: self.stats.invalid.load(Ordering::Relaxed),
+            inflight: self.inflight.lock().len(),
+        })
+    }
+
+    /// Removes the entry for the provided `key` from the cache, returns
+    /// whether an entry was removed
+    pub async fn evict(&self, key: &str) -> anyhow::Result<bool> {
+        // Metadata is removed first so the entry is no longer considered complete
+        let removed = match self.index.as_ref() {
+            Some(index) => index.remove(key).await?,
+            None => self
+                .storage
+                .delete(&format!("{key}.json"))
+                .await
+                .context("failed to remove cache entry")?,
+        };
+        self.storage
+            .delete(&format!("{key}.bin"))
+            .await
+            .context("failed to remove cache entry")?;
+        self.verified.lock().remove(key);
+
+        Ok(removed)
+    }
+
+    /// Removes all entries from the cache, returns the number of entries removed
+    pub async fn purge(&self) -> anyhow::Result<u64> {
+        let mut removed = 0;
+
+        for key in self.entry_keys().await? {
+            if self.evict(&key).await? {
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Provides the keys of the complete entries of the cache
+    async fn entry_keys(&self) -> anyhow::Result<Vec<String>> {
+        if let Some(index) = self.index.as_ref() {
+            return index.keys().await;
+        }
+
+        let objects = self
+            .storage
+            .list()
+            .await
+            .context("failed to list cache entries")?;
+
+        Ok(objects
+            .into_iter()
+            .filter_map(|object| {
+                let key = object.key.strip_suffix(".json")?;
+                Self::is_valid_key(key).then(|| key.to_string())
+            })
+            .collect())
+    }
+
+    /// Removes files left behind by interrupted writes (temporary files and
+    /// data without metadata) along with indexed entries whose data is gone,
+    /// returns the number of files and entries removed. Only the cache
+    /// holding the cleanup lease of the storage cleans it up
+    pub async fn cleanup(&self) -> anyhow::Result<u64> {
+        let mut removed = 0;
+
+        let is_leader = self
+            .storage
+            .acquire_lease(CLEANUP_LEASE, &self.instance, CLEANUP_LEASE_TTL)
+            .await
+            .context("failed to acquire cleanup lease")?;
+
+        if !is_leader {
+            debug!("skipping cache cleanup, another server holds the cleanup lease");
+            return Ok(removed);
+        }
+
+        let objects = self
+            .storage
+            .list()
+            .await
+            .context("failed to list cache entries")?;
+        let keys: HashSet<&str> = objects.iter().map(|object| object.key.as_str()).collect();
+        let indexed: HashSet<String> = match self.index.as_ref() {
+            Some(index) => index.keys().await?.into_iter().collect(),
+            None => HashSet::new(),
+        };
+
+        for object in &objects {
+            let Some((key, extension)) = object.key.split_once('.') else {
+                continue;
+            };
+
+            // Entries being stored are written as temporary files (Local storage
+            // only) with the data stored before the metadata
+            if self.inflight.lock().contains_key(key) {
+                continue;
+            }
+
+            let is_leftover = match extension {
+                "bin.tmp" | "json.tmp" => true,
+                "bin" if self.index.is_some() => !indexed.contains(key),
+                "bin" => !keys.contains(format!("{key}.json").as_str()),
+                _ => false,
+            };
+
+            let is_removed = is_leftover
+                && self
+                    .storage
+                    .delete(&object.key)
+                    .await
+                    .context("failed to remove cache entry")?;
+
+            if is_removed {
+                removed += 1;
+            }
+        }
+
+        // Entries whose data was removed outside the server
+        for key in &indexed {
+            if self.inflight.lock().contains_key(key.as_str())
+                || keys.contains(format!("{key}.bin").as_str())
+            {
+                continue;
+            }
+
+            if self.evict(key).await? {
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Creates the cache key for the provided `input` document and the
+    /// `fingerprint` of the options used to convert it
+    pub fn key(input: &[u8], fingerprint: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(input);
+        Self::hasher_key(hasher, fingerprint)
+    }
+
+    /// Creates the cache key from a `hasher` that has already hashed the
+    /// input, for inputs that were hashed as they were received
+    pub(crate) fn hasher_key(mut hasher: Sha256, fingerprint: &str) -> String {
+        hasher.update([0]);
+        hasher.update(fingerprint.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Checks if the provided `key` is a valid cache key
+    pub fn is_valid_key(key: &str) -> bool {
+        key.len() == 64 && key.chars().all(|char| char.is_ascii_hexdigit())
+    }
+
+    /// Whether results are stored compressed
+    pub fn is_compressed(&self) -> bool {
+        self.compression_level.is_some()
+    }
+
+    /// Provides the `Cache-Control` header value for cached results
+    pub fn cache_control(&self) -> HeaderValue {
+        let value = format!("public, max-age={}, immutable", self.max_age.as_secs());
+        HeaderValue::from_str(&value).expect("cache control header should be valid")
+    }
+
+    /// Gets the cached result for the provided `key` if one is stored, entries
+    /// not yet validated by this process are checked against their metadata
+    /// and evicted when their stored result is missing or damaged
+    pub async fn get(&self, key: &str) -> anyhow::Result<Option<CachedResult>> {
+        let Some(meta) = self.metadata(key).await? else {
+            return Ok(None);
+        };
+
+        let bytes = match self
+            .storage
+            .get(&format!("{key}.bin"))
+            .await
+            .context("failed to read cache entry")?
+        {
+            Some(value) => value,
+            None => {
+                warn!(%key, "cache entry missing its stored result, evicting");
+                self.evict_invalid(key).await?;
+                return Ok(None);
+            }
+        };
+
+        let (meta, bytes) = if self.verified.lock().contains(key) {
+            (meta, bytes)
+        } else {
+            let (valid, meta, bytes) =
+                tokio::task::spawn_blocking(move || (meta.matches(&bytes), meta, bytes))
+                    .await
+                    .context("validate task failed")?;
+
+            if !valid {
+                warn!(%key, "cache entry does not match its metadata, evicting");
+                self.evict_invalid(key).await?;
+                return Ok(None);
+            }
+
+            self.verified.lock().insert(key.to_string());
+            (meta, bytes)
+        };
+
+        Ok(Some(CachedResult {
+            bytes,
+            content_type: meta.content_type,
+            compressed: meta.compressed,
+            sha256: meta.sha256,
+        }))
+    }
+
+    /// Stores the converted `bytes` in the cache under the provided `key`,
+    /// compressing them if compression is enabled
+    pub async fn put(
+        &self,
+        key: &str,
+        bytes: Bytes,
+        content_type: &str,
+        sha256: &str,
+    ) -> anyhow::Result<()> {
+        let (bytes, compressed) = match self.compression_level {
+            Some(level) => {
+                let bytes =
+                    tokio::task::spawn_blocking(move || zstd::encode_all(bytes.as_ref(), level))
+                        .await
+                        .context("compress task failed")?
+                        .context("failed to compress cache entry")?;
+
+                (Bytes::from(bytes), true)
+            }
+            None => (bytes, false),
+        };
+
+        let stored_sha256 = if compressed {
+            format!("{:x}", Sha256::digest(&bytes))
+        } else {
+            sha256.to_string()
+        };
+
+        let meta = CacheMetadata {
+            content_type: content_type.to_string(),
+            compressed,
+            sha256: Some(sha256.to_string()),
+            size: Some(bytes.len() as u64),
+            stored_sha256: Some(stored_sha256),
+        };
+
+        // Data is written first so metadata only exists for complete entries
+        self.storage
+            .put(&format!("{key}.bin"), bytes)
+            .await
+            .context("failed to store cache entry")?;
+
+        match self.index.as_ref() {
+            Some(index) => index.insert(key, meta).await?,
+            None => {
+                let meta =
+                    serde_json::to_vec(&meta).context("failed to serialize cache metadata")?;
+                self.storage
+                    .put(&format!("{key}.json"), Bytes::from(meta))
+                    .await
+                    .context("failed to store cache metadata")?;
+            }
+        }
+
+        self.verified.lock().insert(key.to_string());
+
+        Ok(())
+    }
+
+    /// Reads the metadata of the entry for the `key`, [None] when the cache
+    /// has no complete entry for the key
+    async fn metadata(&self, key: &str) -> anyhow::Result<Option<CacheMetadata>> {
+        if let Some(index) = self.index.as_ref() {
+            return index.get(key).await;
+        }
+
+        let Some(meta) = self
+            .storage
+            .get(&format!("{key}.json"))
+            .await
+            .context("failed to read cache metadata")?
+        else {
+            return Ok(None);
+        };
+
+        let meta = serde_json::from_slice(&meta).context("failed to parse cache metadata")?;
+        Ok(Some(meta))
+    }
+
+    /// Evicts the entry for the `key` after it failed validation
+    async fn evict_invalid(&self, key: &str) -> anyhow::Result<()> {
+        self.evict(key).await?;
+        self.stats.invalid.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Moves the metadata files stored alongside the results in the `dir` into
+/// the `index`, returns the number of entries moved. Metadata is removed once
+/// indexed so the index is the only record of each entry
+fn import_metadata(dir: &Path, index: &CacheIndex) -> anyhow::Result<u64> {
+    let mut imported = 0;
+
+    for entry in std::fs::read_dir(dir).context("failed to read cache directory")? {
+        let path = entry.context("failed to read cache directory")?.path();
+
+        let Some(key) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".json"))
+            .filter(|key| ResultCache::is_valid_key(key))
+        else {
+            continue;
+        };
+
+        let meta = std::fs::read(&path).context("failed to read cache metadata")?;
+        match serde_json::from_slice::<CacheMetadata>(&meta) {
+            Ok(meta) => {
+                index.insert_blocking(key, &meta)?;
+                imported += 1;
+            }
+            // Entry is treated as data without metadata by the next cleanup
+            Err(cause) => warn!(?cause, %key, "skipping unreadable cache metadata"),
+        }
+
+        std::fs::remove_file(&path).context("failed to remove cache metadata")?;
+    }
+
+    Ok(imported)
+}
@@ -0,0 +1,245 @@
+use anyhow::anyhow;
+use clap::Args;
+use libreofficekit::Office;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Arguments for the check subcommand
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[cfg(target_os = "windows")]
+const TARGET_LIBS: &[&str] = &["sofficeapp.dll", "mergedlo.dll"];
+#[cfg(target_os = "linux")]
+const TARGET_LIBS: &[&str] = &["libsofficeapp.so", "libmergedlo.so"];
+#[cfg(target_os = "macos")]
+const TARGET_LIBS: &[&str] = &["libsofficeapp.dylib", "libmergedlo.dylib"];
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Result of a single check in the report
+#[derive(Debug, Serialize)]
+struct CheckResult {
+    /// Name of the check
+    name: &'static str,
+    /// Outcome of the check
+    status: CheckStatus,
+    /// Details about the outcome
+    detail: String,
+}
+
+impl CheckResult {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Report of all the checks
+#[derive(Debug, Serialize)]
+struct CheckReport {
+    /// Office install path that was checked
+    office_path: Option<PathBuf>,
+    /// Whether all the checks passed (warnings are allowed)
+    ok: bool,
+    /// Results of the individual checks
+    checks: Vec<CheckResult>,
+}
+
+/// Verifies the LibreOffice install at `office_path` can be used by the
+/// server, printing a report of each check
+pub async fn run(args: CheckArgs, office_path: Option<PathBuf>) -> anyhow::Result<()> {
+    let report = {
+        let office_path = office_path.clone();
+        tokio::task::spawn_blocking(move || run_checks(office_path.as_deref())).await?
+    };
+
+    let report = CheckReport {
+        ok: report.iter().all(|check| check.status != CheckStatus::Fail),
+        office_path,
+        checks: report,
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+
+    if !report.ok {
+        return Err(anyhow!("office install failed the preflight check"));
+    }
+
+    Ok(())
+}
+
+fn run_checks(office_path: Option<&Path>) -> Vec<CheckResult> {
+    let mut checks = Vec::new();
+
+    // Install path, nothing else can be checked without it
+    let office_path = match office_path {
+        Some(value) if value.is_dir() => {
+            checks.push(CheckResult::pass(
+                "install path",
+                value.display().to_string(),
+            ));
+            value
+        }
+        Some(value) => {
+            checks.push(CheckResult::fail(
+                "install path",
+                format!("{} is not a directory", value.display()),
+            ));
+            return checks;
+        }
+        None => {
+            checks.push(CheckResult::fail(
+                "install path",
+                "no office install path provided or found",
+            ));
+            return checks;
+        }
+    };
+
+    // LibreOfficeKit library
+    let library = TARGET_LIBS
+        .iter()
+        .map(|name| office_path.join(name))
+        .find(|path| path.is_file());
+
+    let Some(library) = library else {
+        checks.push(CheckResult::fail(
+            "library",
+            format!(
+                "none of {} found in the install path",
+                TARGET_LIBS.join(", ")
+            ),
+        ));
+        return checks;
+    };
+
+    checks.push(CheckResult::pass("library", library.display().to_string()));
+
+    #[cfg(target_os = "linux")]
+    checks.push(check_shared_libraries(&library));
+
+    #[cfg(unix)]
+    checks.push(check_fonts());
+
+    // Loading the library and creating office
+    checks.push(match Office::new(office_path) {
+        Ok(office) => match office.get_version_info() {
+            Ok(version) => CheckResult::pass(
+                "office",
+                format!(
+                    "LibreOffice {}.{} ({})",
+                    version.product_version.major, version.product_version.minor, version.build_id
+                ),
+            ),
+            Err(err) => CheckResult::warn("office", format!("created but version unknown: {err}")),
+        },
+        Err(err) => CheckResult::fail("office", format!("failed to create office: {err}")),
+    });
+
+    checks
+}
+
+/// Checks the shared libraries the office `library` links against can all be
+/// resolved, missing system libraries are the most common cause of failures
+/// in minimal container images
+#[cfg(target_os = "linux")]
+fn check_shared_libraries(library: &Path) -> CheckResult {
+    const NAME: &str = "shared libraries";
+
+    let output = match std::process::Command::new("ldd").arg(library).output() {
+        Ok(value) => value,
+        Err(err) => return CheckResult::warn(NAME, format!("unable to run ldd: {err}")),
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let missing: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.contains("not found"))
+        .filter_map(|line| line.split_whitespace().next())
+        .collect();
+
+    if !missing.is_empty() {
+        return CheckResult::fail(NAME, format!("missing: {}", missing.join(", ")));
+    }
+
+    if !output.status.success() {
+        return CheckResult::warn(NAME, "ldd was unable to inspect the library");
+    }
+
+    CheckResult::pass(NAME, format!("{} resolved", stdout.lines().count()))
+}
+
+/// Checks fonts are installed, without fonts documents convert with
+/// missing or substituted glyphs
+#[cfg(unix)]
+fn check_fonts() -> CheckResult {
+    const NAME: &str = "fonts";
+
+    let output = match std::process::Command::new("fc-list").output() {
+        Ok(value) => value,
+        Err(err) => return CheckResult::warn(NAME, format!("unable to run fc-list: {err}")),
+    };
+
+    let count = String::from_utf8_lossy(&output.stdout).lines().count();
+
+    if count == 0 {
+        return CheckResult::warn(NAME, "no fonts installed, output may have missing glyphs");
+    }
+
+    CheckResult::pass(NAME, format!("{count} fonts installed"))
+}
+
+/// Prints the human readable form of the `report`
+fn print_report(report: &CheckReport) {
+    for check in &report.checks {
+        let status = match check.status {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        };
+
+        println!("{status} {:<16} {}", check.name, check.detail);
+    }
+
+    println!();
+    if report.ok {
+        println!("office install is ready");
+    } else {
+        println!("office install is not ready");
+    }
+}
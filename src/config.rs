@@ -0,0 +1,358 @@
+use crate::{
+    attestation::AttestationKey,
+    auth::{ApiKeys, Authorizer, ClientLimits},
+    detect::MismatchPolicy,
+    diagnostics::LogToggle,
+    embedded::EmbeddedLimits,
+    jobs::JobStore,
+    presign::UrlSigner,
+    priority::PriorityTier,
+    proxy::TrustedProxies,
+    quarantine::Quarantine,
+    signing::SigningKey,
+    throttle::ThrottleWindow,
+};
+use axum::http::HeaderMap;
+use clap::ValueEnum;
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
+
+/// Server configuration shared with request handlers
+#[derive(Debug)]
+pub struct ServerConfig {
+    /// Token required for admin only functionality
+    pub admin_token: Option<String>,
+    /// Whether running macros is allowed
+    pub allow_macros: bool,
+    /// Key upload signatures are verified against
+    pub attestation_key: Option<AttestationKey>,
+    /// Whether uploads without a signature are rejected
+    pub require_attestation: bool,
+    /// Key conversion results are signed with
+    pub signing_key: Option<SigningKey>,
+    /// Uploads smaller than this are rejected before queueing
+    pub min_upload_size: Option<usize>,
+    /// Uploads of at least this size are spilled to disk while waiting
+    pub spill_threshold: Option<usize>,
+    /// Limits the uploads written to disk at once, when set every upload is
+    /// written to disk before queueing instead of on the runner thread
+    pub io_limit: Option<Arc<Semaphore>>,
+    /// Directory documents are written to while converting
+    pub work_dir: PathBuf,
+    /// Time a client may go without reading a download before it is expired
+    pub download_idle_timeout: Option<Duration>,
+    /// Downloads of at least this size are sent from disk instead of memory
+    pub download_spill_threshold: Option<usize>,
+    /// Limits on the embedded content of uploaded documents
+    pub embedded_limits: EmbeddedLimits,
+    /// Handling of uploads with content that doesn't match their extension
+    pub format_mismatch: MismatchPolicy,
+    /// Whether the standard PDF fonts are embedded when not specified by the request
+    pub embed_standard_fonts: bool,
+    /// Directory converted files are written to for store requests
+    pub output_dir: Option<PathBuf>,
+    /// Signer for pre-signed URLs to stored outputs
+    pub url_signer: Option<UrlSigner>,
+    /// Quarantine for the inputs of failed conversions
+    pub quarantine: Option<Arc<Quarantine>>,
+    /// Store for background conversion jobs
+    pub jobs: Option<Arc<JobStore>>,
+    /// Priority tiers of requests by their API key
+    pub api_key_tiers: HashMap<String, PriorityTier>,
+    /// API keys required to use the server along with their limits, [None]
+    /// allows requests without a key
+    pub api_keys: Option<ApiKeys>,
+    /// External authorizer deciding which requests are allowed instead of
+    /// API keys
+    pub authorizer: Option<Authorizer>,
+    /// Limits on the conversions of each client address when API keys are
+    /// not configured
+    pub client_limits: Option<ClientLimits>,
+    /// Proxies trusted to provide the client address and scheme
+    pub trusted_proxies: TrustedProxies,
+    /// Endpoints that are not routed, requests for them respond with a 404
+    pub disabled_endpoints: Vec<EndpointPattern>,
+    /// Details about how the server was started
+    pub info: ServerInfo,
+    /// Toggle for debug logging, [None] when logging can't be changed
+    pub log_toggle: Option<LogToggle>,
+    /// Verbosity of the office log output attached to failed conversions
+    pub office_log: OfficeLogLevel,
+}
+
+/// Details about how the server was started, reported by "/admin/info"
+/// for auditing deployments
+#[derive(Debug, Default, Clone)]
+pub struct ServerInfo {
+    /// Path to the office install
+    pub office_path: Option<PathBuf>,
+    /// Directory of the office user profile
+    pub profile_dir: Option<PathBuf>,
+    /// Backend used to perform conversions
+    pub backend: Backend,
+    /// Maximum time requests wait for the runner
+    pub max_queue_wait: Option<Duration>,
+    /// Time without progress before a conversion is treated as hung
+    pub hang_timeout: Option<Duration>,
+    /// Maximum time a single conversion may take
+    pub convert_timeout: Option<Duration>,
+    /// Consecutive failures after which the self test is run
+    pub recover_after_failures: Option<u64>,
+    /// Memory usage percentage at which the runner pauses taking work
+    pub memory_pause_threshold: Option<u64>,
+    /// Time without conversions after which office is shut down
+    pub idle_shutdown: Option<Duration>,
+    /// Maximum number of uploads written to disk at once
+    pub io_concurrency: Option<usize>,
+    /// Time stored outputs are kept for
+    pub output_retention: Option<Duration>,
+    /// Time the results of finished background jobs are kept for
+    pub job_retention: Option<Duration>,
+    /// Time the status of finished background jobs is kept for
+    pub job_status_retention: Option<Duration>,
+    /// Daily windows with their own queue limits
+    pub throttle_windows: Vec<ThrottleWindow>,
+}
+
+impl ServerConfig {
+    /// Checks if the request `headers` contain the configured admin token
+    pub fn is_admin(&self, headers: &HeaderMap) -> bool {
+        let admin_token = match self.admin_token.as_deref() {
+            Some(value) => value,
+            None => return false,
+        };
+
+        headers
+            .get("x-admin-token")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| {
+                // Compared in constant time so the token can't be guessed from timings
+                ring::constant_time::verify_slices_are_equal(
+                    value.as_bytes(),
+                    admin_token.as_bytes(),
+                )
+                .is_ok()
+            })
+    }
+
+    /// Checks if the endpoint at `path` (i.e "/jobs/{id}") is disabled
+    pub fn is_endpoint_disabled(&self, path: &str) -> bool {
+        self.disabled_endpoints
+            .iter()
+            .any(|pattern| pattern.matches(path))
+    }
+
+    /// Provides the priority tier of a request from the API key in its
+    /// `headers`, requests without a known key are interactive
+    pub fn priority_tier(&self, headers: &HeaderMap) -> PriorityTier {
+        headers
+            .get("x-api-key")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| self.api_key_tiers.get(value))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Backend used to perform conversions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// Convert using the in-process LibreOfficeKit office instance
+    #[default]
+    Libreoffice,
+    /// Convert by running a `soffice --convert-to` process for each conversion
+    Soffice,
+    /// Respond with a placeholder PDF without using LibreOffice (Development only)
+    Stub,
+}
+
+/// Verbosity of the log output LibreOffice produces while converting, the
+/// tail of the output is attached to failed conversions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OfficeLogLevel {
+    /// Don't collect the log output of office
+    #[default]
+    Off,
+    /// Collect warnings and errors
+    Warn,
+    /// Collect informational output along with warnings and errors
+    Info,
+}
+
+impl OfficeLogLevel {
+    /// Provides the value of the `SAL_LOG` environment variable selecting
+    /// the log output of office, [None] leaves the default output of office
+    pub fn sal_log(&self) -> Option<&'static str> {
+        match self {
+            OfficeLogLevel::Off => None,
+            OfficeLogLevel::Warn => Some("+WARN"),
+            OfficeLogLevel::Info => Some("+INFO+WARN"),
+        }
+    }
+
+    /// Whether the log output of office is collected
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self, OfficeLogLevel::Off)
+    }
+}
+
+/// Policy for when office memory is trimmed after conversions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TrimPolicy {
+    /// Trim after every conversion
+    Always,
+    /// Trim after every N conversions
+    EveryN,
+    /// Trim once no conversions have happened for a period of time
+    Idle,
+    /// Never trim after conversions (Only when garbage collection is requested)
+    Never,
+}
+
+/// Configuration for when and how aggressively office memory is trimmed
+#[derive(Debug, Clone, Copy)]
+pub struct TrimConfig {
+    /// When to trim after conversions
+    pub policy: TrimPolicy,
+    /// Trim target used after conversions
+    pub target: i32,
+    /// Trim target used when garbage collection is requested
+    pub gc_target: i32,
+    /// Number of conversions between trims for [TrimPolicy::EveryN]
+    pub every: u32,
+    /// Duration without conversions before trimming for [TrimPolicy::Idle]
+    pub idle_after: Duration,
+    /// Memory usage percentage at which the runner pauses before taking work
+    pub memory_pause_threshold: Option<u64>,
+    /// Time without conversions after which office is shut down, it is
+    /// started again by the next conversion
+    pub idle_shutdown: Option<Duration>,
+}
+
+impl Default for TrimConfig {
+    /// Defaults matching the server arguments
+    fn default() -> Self {
+        Self {
+            policy: TrimPolicy::Always,
+            target: 1000,
+            gc_target: 2000,
+            every: 10,
+            idle_after: Duration::from_secs(30),
+            memory_pause_threshold: None,
+            idle_shutdown: None,
+        }
+    }
+}
+
+/// Configuration for detecting conversions that will never finish
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WatchdogConfig {
+    /// Time without office reporting progress before a conversion is hung
+    pub hang_timeout: Option<Duration>,
+    /// Maximum time a single conversion may take
+    pub convert_timeout: Option<Duration>,
+}
+
+/// Default number of messages the runner queue holds ahead of the runner,
+/// one for each worker
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1;
+
+/// Default largest document in bytes written to the memory work directory
+pub const DEFAULT_MEMORY_REQUEST_LIMIT: u64 = 16 * 1024 * 1024;
+
+/// Default total size in bytes of the files in the memory work directory
+pub const DEFAULT_MEMORY_TOTAL_LIMIT: u64 = 256 * 1024 * 1024;
+
+/// Configuration for the queue of work waiting for the runner
+#[derive(Debug, Clone, Copy)]
+pub struct QueueConfig {
+    /// Number of messages the queue holds ahead of the runner, once full
+    /// requests wait to enter the queue by their priority tier. Work in the
+    /// queue has already been admitted so larger queues weaken priorities
+    pub capacity: usize,
+    /// Maximum time requests wait to enter the queue
+    pub max_queue_wait: Option<Duration>,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_QUEUE_CAPACITY,
+            max_queue_wait: None,
+        }
+    }
+}
+
+/// Endpoints matched by a pattern, either the path of an endpoint (i.e
+/// "/jobs/{id}") or a prefix of paths ending in "*" (i.e "/admin/*")
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointPattern {
+    /// Endpoint with exactly the path
+    Exact(String),
+    /// Endpoints with paths starting with the prefix
+    Prefix(String),
+}
+
+impl EndpointPattern {
+    /// Checks if the pattern matches the endpoint at `path`
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            EndpointPattern::Exact(value) => path == value,
+            EndpointPattern::Prefix(prefix) => path.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+impl std::fmt::Display for EndpointPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EndpointPattern::Exact(value) => f.write_str(value),
+            EndpointPattern::Prefix(prefix) => write!(f, "{prefix}*"),
+        }
+    }
+}
+
+/// Parses an endpoint pattern, i.e "/collect-garbage", "/jobs/{id}" or "/admin/*"
+pub fn endpoint_arg(value: &str) -> Result<EndpointPattern, String> {
+    let value = value.trim();
+
+    if !value.starts_with('/') || value.contains(char::is_whitespace) {
+        return Err(
+            "expected an endpoint path like \"/collect-garbage\" or \"/admin/*\"".to_string(),
+        );
+    }
+
+    Ok(match value.strip_suffix('*') {
+        Some(prefix) => EndpointPattern::Prefix(prefix.to_string()),
+        None => EndpointPattern::Exact(value.to_string()),
+    })
+}
+
+/// Parses a duration argument
+pub fn duration_arg(value: &str) -> Result<Duration, String> {
+    parse_duration(value).ok_or_else(|| "expected a duration like 60s, 500ms, 2m or 1h".to_string())
+}
+
+/// Parses a duration in the form "30s", "500ms", "1m", "2h", "7d" or a plain
+/// number of seconds
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+
+    let (number, unit) = match value.find(|char: char| !char.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+
+    let number: u64 = number.parse().ok()?;
+
+    let duration = match unit {
+        "ms" => Duration::from_millis(number),
+        "s" => Duration::from_secs(number),
+        "m" => Duration::from_secs(number.checked_mul(60)?),
+        "h" => Duration::from_secs(number.checked_mul(60 * 60)?),
+        "d" => Duration::from_secs(number.checked_mul(24 * 60 * 60)?),
+        _ => return None,
+    };
+
+    Some(duration)
+}
@@ -0,0 +1,1717 @@
+use crate::{
+    config::OfficeLogLevel,
+    csv::{self, CsvOptions},
+    detect,
+    error::HttpError,
+    formats::{FormatFamily, TargetFormat},
+    odf, office_errors,
+    pdf_export::{self, PdfExportOptions},
+    pipeline::{self, PipelineOptions, PipelineStep},
+    redact::{self, RedactOptions, RedactionReport, BUNDLE_REDACTIONS_NAME},
+    runner::{OfficeDetails, RunnerActivity, RunnerPhase},
+    settings::RenderSettings,
+    tempfiles::{random_id, ConvertTempFiles, MemoryWorkDir, TempFile},
+    workbook::{self, SpreadsheetExport},
+};
+use anyhow::{anyhow, Context};
+use axum::http::StatusCode;
+use bytes::Bytes;
+use libreofficekit::{
+    CallbackType, DocUrl, Document, DocumentType, Office, OfficeError, OfficeOptionalFeatures,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    ffi::CStr,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    time::Instant,
+};
+use thiserror::Error;
+use tracing::{debug, error, info, warn};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+/// Input document provided to the runner
+pub enum DocumentInput {
+    /// Document bytes held in memory
+    Bytes(Bytes),
+
+    /// Document that was spilled to disk while waiting for the runner
+    Spilled(TempFile),
+}
+
+impl DocumentInput {
+    /// Reads the document bytes, reading spilled documents from disk
+    pub fn into_bytes(self) -> anyhow::Result<Bytes> {
+        match self {
+            DocumentInput::Bytes(bytes) => Ok(bytes),
+            DocumentInput::Spilled(spilled) => std::fs::read(&spilled.path)
+                .map(Bytes::from)
+                .context("failed to read spilled input"),
+        }
+    }
+
+    /// Size of the document in bytes, [None] when the size of a spilled
+    /// document could not be read
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            DocumentInput::Bytes(bytes) => Some(bytes.len() as u64),
+            DocumentInput::Spilled(spilled) => std::fs::metadata(&spilled.path)
+                .ok()
+                .map(|metadata| metadata.len()),
+        }
+    }
+}
+
+/// Options controlling how a document is converted
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ConvertOptions {
+    /// Export each sheet of a spreadsheet as a separate PDF, bundled
+    /// together in a zip archive
+    pub split_sheets: bool,
+
+    /// Name of a macro to run on the document before exporting
+    pub run_macro: Option<String>,
+
+    /// Render a thumbnail of the first page along with the PDF, bundled
+    /// together in a zip archive
+    pub with_thumbnail: bool,
+
+    /// Extract the text of each page along with the PDF, bundled together
+    /// in a zip archive
+    pub with_text: bool,
+
+    /// Rendering settings adjusted for this conversion
+    pub settings: RenderSettings,
+
+    /// Export the sheets of a spreadsheet as CSV instead of PDF
+    pub csv: Option<CsvOptions>,
+
+    /// Export a spreadsheet to another spreadsheet format instead of PDF
+    pub spreadsheet: Option<SpreadsheetExport>,
+
+    /// Export the document to another format instead of PDF
+    pub target_format: Option<TargetFormat>,
+
+    /// Password to open an encrypted document with
+    #[serde(skip)]
+    pub password: Option<DocumentPassword>,
+
+    /// Options for the exported PDF
+    pub pdf_export: PdfExportOptions,
+
+    /// Text and page regions to redact from the PDF, bundled together with
+    /// a report of the redactions in a zip archive
+    pub redact: Option<RedactOptions>,
+
+    /// Leave the converted file on disk to be streamed to the client rather
+    /// than reading it into memory, only used for single file outputs
+    #[serde(skip)]
+    pub stream_output: bool,
+}
+
+/// Password of an encrypted document, never logged or serialized
+#[derive(Clone)]
+pub struct DocumentPassword(pub String);
+
+impl std::fmt::Debug for DocumentPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DocumentPassword(..)")
+    }
+}
+
+impl ConvertOptions {
+    /// Creates a fingerprint of the options for use in cache keys, conversions
+    /// with different options must produce different fingerprints
+    pub(crate) fn cache_fingerprint(&self) -> String {
+        let mut fingerprint = format!(
+            "split_sheets={};run_macro={}",
+            self.split_sheets,
+            self.run_macro.as_deref().unwrap_or_default()
+        );
+
+        // Only included when enabled so existing cache keys remain valid
+        if self.with_thumbnail {
+            fingerprint.push_str(";with_thumbnail=true");
+        }
+
+        if self.with_text {
+            fingerprint.push_str(";with_text=true");
+        }
+
+        if !self.settings.is_empty() {
+            fingerprint.push_str(";settings=");
+            fingerprint.push_str(&self.settings.cache_fingerprint());
+        }
+
+        if let Some(csv) = &self.csv {
+            fingerprint.push_str(";csv=");
+            fingerprint.push_str(&csv.cache_fingerprint());
+        }
+
+        if let Some(spreadsheet) = &self.spreadsheet {
+            fingerprint.push_str(";spreadsheet=");
+            fingerprint.push_str(&spreadsheet.cache_fingerprint());
+        }
+
+        if let Some(target_format) = &self.target_format {
+            fingerprint.push_str(";target_format=");
+            fingerprint.push_str(target_format.extension);
+        }
+
+        if !self.pdf_export.is_empty() {
+            fingerprint.push_str(";pdf_export=");
+            fingerprint.push_str(&self.pdf_export.cache_fingerprint());
+        }
+
+        if let Some(redact) = &self.redact {
+            fingerprint.push_str(";redact=");
+            fingerprint.push_str(&redact.cache_fingerprint());
+        }
+
+        fingerprint
+    }
+
+    /// Whether artifacts are produced alongside the PDF, bundling the
+    /// output into a zip archive
+    pub(crate) fn is_bundled(&self) -> bool {
+        self.with_thumbnail || self.with_text || self.redact.is_some()
+    }
+}
+
+/// Name of the converted PDF within bundled conversions
+pub const BUNDLE_DOCUMENT_NAME: &str = "document.pdf";
+
+/// Name of the first page thumbnail within bundled conversions
+pub const BUNDLE_THUMBNAIL_NAME: &str = "thumbnail.png";
+
+/// Name of the per page text within bundled conversions
+pub const BUNDLE_TEXT_NAME: &str = "text.json";
+
+/// Artifacts produced alongside the converted PDF
+#[derive(Default)]
+pub(crate) struct BundleArtifacts {
+    /// Thumbnail of the first page
+    pub(crate) thumbnail: Option<Vec<u8>>,
+    /// Text of each page
+    pub(crate) text: Option<Vec<odf::PageText>>,
+    /// Report of the redactions
+    pub(crate) redactions: Option<RedactionReport>,
+}
+
+/// Bundles the converted `pdf` and the `artifacts` produced alongside
+/// it into a zip archive
+pub(crate) fn bundle(pdf: &[u8], artifacts: BundleArtifacts) -> anyhow::Result<ConvertedDocument> {
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+
+    let text = artifacts
+        .text
+        .map(|text| serde_json::to_vec_pretty(&text))
+        .transpose()
+        .context("failed to serialize page text")?;
+
+    let redactions = artifacts
+        .redactions
+        .map(|redactions| serde_json::to_vec_pretty(&redactions))
+        .transpose()
+        .context("failed to serialize redaction report")?;
+
+    let entries = [
+        (BUNDLE_DOCUMENT_NAME, Some(pdf)),
+        (BUNDLE_THUMBNAIL_NAME, artifacts.thumbnail.as_deref()),
+        (BUNDLE_TEXT_NAME, text.as_deref()),
+        (BUNDLE_REDACTIONS_NAME, redactions.as_deref()),
+    ];
+
+    for (name, bytes) in entries {
+        let Some(bytes) = bytes else {
+            continue;
+        };
+
+        archive
+            .start_file(name, SimpleFileOptions::default())
+            .context("failed to create zip entry")?;
+        archive
+            .write_all(bytes)
+            .context("failed to write zip entry")?;
+    }
+
+    let archive = archive.finish().context("failed to finish zip")?;
+
+    Ok(ConvertedDocument {
+        bytes: Bytes::from(archive.into_inner()),
+        file: None,
+        content_type: "application/zip",
+        warnings: Vec::new(),
+    })
+}
+
+/// File extensions of converted outputs and their mime types, PDF is
+/// used for unknown types
+const OUTPUT_TYPES: &[(&str, &str)] = &[
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("csv", "text/csv"),
+    ("tsv", "text/tab-separated-values"),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+    ("xls", "application/vnd.ms-excel"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("doc", "application/msword"),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("rtf", "application/rtf"),
+    ("txt", "text/plain"),
+    ("html", "text/html"),
+    ("epub", "application/epub+zip"),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    ("odp", "application/vnd.oasis.opendocument.presentation"),
+    ("odg", "application/vnd.oasis.opendocument.graphics"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("svg", "image/svg+xml"),
+];
+
+/// Provides the file extension for converted outputs of the `content_type`
+pub fn output_extension(content_type: &str) -> &'static str {
+    OUTPUT_TYPES
+        .iter()
+        .find(|(_, value)| *value == content_type)
+        .map_or("pdf", |(extension, _)| extension)
+}
+
+/// Counts the pages of a PDF, the page objects written by LibreOffice are
+/// not compressed so they can be counted directly
+pub fn pdf_page_count(bytes: &[u8]) -> usize {
+    let needle = b"/Type";
+    let mut count = 0;
+    let mut index = 0;
+
+    while let Some(offset) = bytes[index..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+    {
+        index += offset + needle.len();
+
+        let rest = &bytes[index..];
+        let rest = &rest[rest
+            .iter()
+            .take_while(|byte| byte.is_ascii_whitespace())
+            .count()..];
+
+        // Match "/Page" but not "/Pages"
+        if rest.starts_with(b"/Page")
+            && !rest.get(5).is_some_and(|byte| byte.is_ascii_alphanumeric())
+        {
+            count += 1;
+        }
+    }
+
+    count
+}
+
+/// Provides the mime type for converted outputs with the `extension`
+pub fn output_content_type(extension: &str) -> &'static str {
+    OUTPUT_TYPES
+        .iter()
+        .find(|(value, _)| *value == extension)
+        .map_or("application/pdf", |(_, content_type)| content_type)
+}
+
+/// Output of a successful conversion
+pub struct ConvertedDocument {
+    /// The converted file bytes, empty when the output is left in `file`
+    pub bytes: Bytes,
+
+    /// Converted file left on disk to be streamed to the client when
+    /// [ConvertOptions::stream_output] was requested
+    pub file: Option<TempFile>,
+
+    /// Mime type of the converted file
+    pub content_type: &'static str,
+
+    /// Non-fatal issues observed while converting
+    pub warnings: Vec<ConvertWarning>,
+}
+
+impl ConvertedDocument {
+    /// Creates the output from the converted `file`, the file is left on
+    /// disk when `stream` is set and read into memory otherwise
+    fn from_file(
+        file: TempFile,
+        content_type: &'static str,
+        warnings: Vec<ConvertWarning>,
+        stream: bool,
+    ) -> anyhow::Result<Self> {
+        if stream {
+            return Ok(ConvertedDocument {
+                bytes: Bytes::new(),
+                file: Some(file),
+                content_type,
+                warnings,
+            });
+        }
+
+        let bytes = std::fs::read(&file.path).context("failed to read converted file")?;
+
+        Ok(ConvertedDocument {
+            bytes: Bytes::from(bytes),
+            file: None,
+            content_type,
+            warnings,
+        })
+    }
+
+    /// Size of the output in bytes, [None] when the size of a streamed
+    /// output could not be read
+    pub fn size(&self) -> Option<u64> {
+        match &self.file {
+            Some(file) => std::fs::metadata(&file.path)
+                .ok()
+                .map(|metadata| metadata.len()),
+            None => Some(self.bytes.len() as u64),
+        }
+    }
+}
+
+/// Diagnostics of a failed conversion for reproducing the failure
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConvertDiagnostics {
+    /// Time spent converting before failing in milliseconds
+    pub duration_ms: u64,
+    /// Callbacks office made while converting
+    pub events: Vec<OfficeEvent>,
+    /// Tail of the log output of office while converting, empty unless the
+    /// office log is enabled
+    #[serde(default)]
+    pub log: Vec<String>,
+}
+
+/// Callback office made while converting a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OfficeEvent {
+    /// Time since the conversion started in milliseconds
+    pub at_ms: u64,
+    /// Type of callback
+    pub kind: String,
+    /// Payload of the callback, truncated to [MAX_EVENT_PAYLOAD] bytes
+    pub payload: Option<String>,
+}
+
+/// Maximum number of office callbacks kept for a single conversion
+const MAX_EVENTS: usize = 500;
+
+/// Maximum length of the payload kept for an office callback
+const MAX_EVENT_PAYLOAD: usize = 1024;
+
+/// Maximum number of office log lines kept for a single conversion
+pub const MAX_LOG_LINES: usize = 50;
+
+/// Provides the last [MAX_LOG_LINES] non-empty lines of the log `output`
+pub fn log_tail(output: &str) -> Vec<String> {
+    let lines: Vec<&str> = output
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+
+    lines[lines.len().saturating_sub(MAX_LOG_LINES)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Non-fatal issue observed while converting a document, the document was
+/// still converted but the output may not be what the caller expected
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConvertWarning {
+    /// Code identifying the kind of issue
+    pub code: Cow<'static, str>,
+    /// Description of the issue
+    pub message: String,
+}
+
+impl ConvertWarning {
+    pub fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code: Cow::Borrowed(code),
+            message: message.into(),
+        }
+    }
+}
+
+/// Statistics extracted from a document
+#[derive(Serialize)]
+pub struct DocumentStats {
+    /// Type of document (text, spreadsheet, presentation, drawing, other)
+    pub document_type: &'static str,
+
+    /// Statistics reported by LibreOffice
+    #[serde(flatten)]
+    pub statistics: odf::DocumentStatistics,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RunnerState {
+    password_requested: bool,
+    /// Password to provide when office asks for the document password
+    password: Option<DocumentPassword>,
+    /// Number of times office asked for the document password
+    password_attempts: usize,
+    /// Number of dialogs office dismissed while processing the document
+    dialogs_dismissed: usize,
+    /// Whether documents are loaded with their macros enabled, only while
+    /// running a requested document macro
+    macros_enabled: bool,
+    /// When the current conversion started, callbacks are only recorded
+    /// while converting
+    started: Option<Instant>,
+    /// Callbacks office made during the current conversion
+    events: Vec<OfficeEvent>,
+    /// URL of the document being loaded, used to provide its password
+    input_url: Option<DocUrl>,
+    /// Office log output during the current conversion
+    log: VecDeque<String>,
+    /// Extension the document was loaded as after office could not detect
+    /// its format
+    loaded_as: Option<&'static str>,
+    /// Copy of the input named with the extension it was loaded as, kept
+    /// until the next document is loaded
+    fallback_input: Option<TempFile>,
+}
+
+/// Backend performing the document work for the office runner, backends are
+/// created on the runner thread and only used from it so they are not
+/// required to be [Send]
+pub trait ConvertBackend {
+    /// Provides details about the backend for the version and supported
+    /// formats endpoints
+    fn details(&self) -> OfficeDetails;
+
+    /// Converts the document into PDF format
+    fn convert(
+        &mut self,
+        input: DocumentInput,
+        options: ConvertOptions,
+    ) -> anyhow::Result<ConvertedDocument>;
+
+    /// Runs a conversion pipeline on the document, providing a zip archive
+    /// of the artifacts
+    fn pipeline(
+        &mut self,
+        input: DocumentInput,
+        steps: Vec<PipelineStep>,
+        options: PipelineOptions,
+    ) -> anyhow::Result<Bytes>;
+
+    /// Extracts the embedded images and objects from the document, providing
+    /// a zip archive of the assets
+    fn extract_assets(&mut self, input: DocumentInput) -> anyhow::Result<Bytes>;
+
+    /// Extracts the statistics from the document
+    fn extract_stats(&mut self, input: DocumentInput) -> anyhow::Result<DocumentStats>;
+
+    /// Trims the memory used by the backend, see [Office::trim_memory]
+    /// for the meaning of `target`
+    fn trim_memory(&mut self, target: i32) -> anyhow::Result<()>;
+
+    /// Provides the callbacks office made during the last conversion, for
+    /// diagnosing failed conversions
+    fn take_events(&mut self) -> Vec<OfficeEvent> {
+        Vec::new()
+    }
+
+    /// Provides the tail of the office log output during the last
+    /// conversion, for diagnosing failed conversions
+    fn take_log(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Whether the backend can be shut down and created again within the
+    /// process, the runner exits the process to be restarted by its
+    /// supervisor when it cannot
+    fn restarts_in_process(&self) -> bool {
+        true
+    }
+}
+
+/// [ConvertBackend] using an in-process LibreOfficeKit office instance
+pub struct LibreOfficeBackend {
+    /// The office instance
+    office: Office,
+    /// Random ID included in the names of the temporary files
+    random_id: String,
+    /// Directory documents are written to while converting
+    work_dir: PathBuf,
+    /// RAM-backed directory small documents are written to instead
+    memory_dir: Option<MemoryWorkDir>,
+    /// State shared with the office callback
+    runner_state: Rc<Mutex<RunnerState>>,
+    /// Activity of the runner, updated from the office callback
+    activity: Arc<RunnerActivity>,
+    /// Trim target used between exports of a single document
+    trim_target: i32,
+    /// Callbacks office made during the last conversion
+    events: Vec<OfficeEvent>,
+    /// Tail of the office log output during the last conversion
+    log: Vec<String>,
+}
+
+impl LibreOfficeBackend {
+    /// Creates the office instance from the install at `path`, documents
+    /// are written to `work_dir` while converting, or `memory_dir` when
+    /// they fit within its limits. Office log output of the `log_level` is
+    /// collected from error callbacks while converting
+    pub fn new(
+        path: &Path,
+        work_dir: &Path,
+        memory_dir: Option<MemoryWorkDir>,
+        trim_target: i32,
+        log_level: OfficeLogLevel,
+        activity: Arc<RunnerActivity>,
+    ) -> anyhow::Result<Self> {
+        // Select the log output office writes to stderr, unless chosen by the environment
+        if let (Some(sal_log), None) = (log_level.sal_log(), std::env::var_os("SAL_LOG")) {
+            std::env::set_var("SAL_LOG", sal_log);
+        }
+
+        // Create office instance
+        let office = Office::new(path).context("failed to create office instance")?;
+
+        // Generate random ID for the path name
+        let random_id = random_id();
+
+        let runner_state = Rc::new(Mutex::new(RunnerState::default()));
+
+        // Allow prompting for passwords
+        office
+            .set_optional_features(OfficeOptionalFeatures::DOCUMENT_PASSWORD)
+            .context("failed to set optional features")?;
+
+        office
+            .register_callback({
+                let runner_state = runner_state.clone();
+                let activity = activity.clone();
+
+                move |office, ty, payload| {
+                    debug!(?ty, "callback invoked");
+
+                    if activity.dumps_callbacks() && !payload.is_null() {
+                        let payload = unsafe { CStr::from_ptr(payload) };
+                        info!(?ty, payload = %payload.to_string_lossy(), "office callback");
+                    }
+
+                    // Any callback shows office is still making progress
+                    activity.heartbeat();
+
+                    match ty {
+                        CallbackType::StatusIndicatorStart => activity.set_progress(0),
+                        CallbackType::StatusIndicatorSetValue if !payload.is_null() => {
+                            let payload = unsafe { CStr::from_ptr(payload) };
+                            if let Some(progress) = payload
+                                .to_str()
+                                .ok()
+                                .and_then(|value| value.trim().parse::<u8>().ok())
+                            {
+                                activity.set_progress(progress);
+                            }
+                        }
+                        CallbackType::StatusIndicatorFinish => activity.set_progress(100),
+                        _ => {}
+                    }
+
+                    let state = &mut *runner_state.lock();
+
+                    // Errors are logged at every level, other callbacks only at info
+                    let logged = match log_level {
+                        OfficeLogLevel::Off => false,
+                        OfficeLogLevel::Warn => matches!(ty, CallbackType::Error),
+                        OfficeLogLevel::Info => true,
+                    };
+
+                    if logged && state.started.is_some() {
+                        if state.log.len() >= MAX_LOG_LINES {
+                            state.log.pop_front();
+                        }
+
+                        let payload = event_payload(payload).unwrap_or_default();
+                        state.log.push_back(format!("{ty:?}: {payload}"));
+                    }
+
+                    if let Some(started) = state.started {
+                        if state.events.len() < MAX_EVENTS {
+                            state.events.push(OfficeEvent {
+                                at_ms: started.elapsed().as_millis() as u64,
+                                kind: format!("{ty:?}"),
+                                payload: event_payload(payload),
+                            });
+                        }
+                    }
+
+                    if let CallbackType::DocumentPassword = ty {
+                        state.password_requested = true;
+                        state.password_attempts += 1;
+
+                        // Office asks again when the password is wrong, no password is
+                        // provided the second time so loading fails
+                        let password = state
+                            .password
+                            .as_ref()
+                            .filter(|_| state.password_attempts == 1)
+                            .map(|password| password.0.as_str());
+
+                        if let Some(input_url) = state.input_url.as_ref() {
+                            if let Err(cause) = office.set_document_password(input_url, password) {
+                                error!(?cause, "failed to set document password");
+                            }
+                        }
+                    }
+
+                    // Panics cannot unwind out of the callback into office (The process aborts)
+                    // so malformed dialog requests are only logged
+                    if let CallbackType::JSDialog = ty {
+                        state.dialogs_dismissed += 1;
+
+                        if !payload.is_null() {
+                            let payload = unsafe { CStr::from_ptr(payload) };
+                            match serde_json::from_slice::<serde_json::Value>(payload.to_bytes()) {
+                                Ok(value) => debug!(?value, "js dialog request"),
+                                Err(cause) => debug!(%cause, "invalid js dialog request"),
+                            }
+                        }
+                    }
+                }
+            })
+            .context("failed to register office callback")?;
+
+        Ok(Self {
+            office,
+            random_id,
+            work_dir: work_dir.to_path_buf(),
+            memory_dir,
+            runner_state,
+            activity,
+            trim_target,
+            events: Vec::new(),
+            log: Vec::new(),
+        })
+    }
+
+    /// Provides the temporary files for processing the `input` document,
+    /// removed once the document has been processed. Documents that fit
+    /// within the limits of the memory work directory are written there
+    fn temp_files(&self, input: &DocumentInput) -> ConvertTempFiles {
+        let dir = match (self.memory_dir.as_ref(), input.size()) {
+            (Some(memory_dir), Some(size)) if memory_dir.fits(size) => &memory_dir.path,
+            _ => &self.work_dir,
+        };
+
+        let random_id = &self.random_id;
+
+        ConvertTempFiles {
+            input: TempFile {
+                path: dir.join(format!("lo_native_input_{random_id}")),
+            },
+            output: TempFile {
+                path: dir.join(format!("lo_native_output_{random_id}.pdf")),
+            },
+            package: TempFile {
+                path: dir.join(format!("lo_native_package_{random_id}")),
+            },
+        }
+    }
+
+    /// Resets the runner state after processing a document
+    fn reset_state(&self) {
+        *self.runner_state.lock() = RunnerState::default();
+    }
+}
+
+impl ConvertBackend for LibreOfficeBackend {
+    fn details(&self) -> OfficeDetails {
+        // Load supported filters and office version details
+        OfficeDetails {
+            filter_types: self.office.get_filter_types().ok(),
+            version: self.office.get_version_info().ok(),
+        }
+    }
+
+    fn convert(
+        &mut self,
+        input: DocumentInput,
+        options: ConvertOptions,
+    ) -> anyhow::Result<ConvertedDocument> {
+        self.runner_state.lock().started = Some(Instant::now());
+
+        let result = convert_document(
+            &self.office,
+            self.temp_files(&input),
+            input,
+            options,
+            self.trim_target,
+            &self.runner_state,
+            &self.activity,
+        );
+
+        {
+            let state = &mut *self.runner_state.lock();
+            self.events = std::mem::take(&mut state.events);
+            self.log = std::mem::take(&mut state.log).into();
+        }
+        self.reset_state();
+        result
+    }
+
+    fn pipeline(
+        &mut self,
+        input: DocumentInput,
+        steps: Vec<PipelineStep>,
+        options: PipelineOptions,
+    ) -> anyhow::Result<Bytes> {
+        let result = pipeline::run_pipeline(
+            &self.office,
+            self.temp_files(&input),
+            input,
+            steps,
+            options,
+            &self.runner_state,
+            &self.activity,
+        );
+        self.reset_state();
+        result
+    }
+
+    fn extract_assets(&mut self, input: DocumentInput) -> anyhow::Result<Bytes> {
+        let result = extract_document_assets(
+            &self.office,
+            self.temp_files(&input),
+            input,
+            &self.runner_state,
+            &self.activity,
+        );
+        self.reset_state();
+        result
+    }
+
+    fn extract_stats(&mut self, input: DocumentInput) -> anyhow::Result<DocumentStats> {
+        let result = extract_document_stats(
+            &self.office,
+            self.temp_files(&input),
+            input,
+            &self.runner_state,
+            &self.activity,
+        );
+        self.reset_state();
+        result
+    }
+
+    fn trim_memory(&mut self, target: i32) -> anyhow::Result<()> {
+        self.office.trim_memory(target)?;
+        Ok(())
+    }
+
+    fn take_events(&mut self) -> Vec<OfficeEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn take_log(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.log)
+    }
+
+    /// LibreOfficeKit cannot be started again within a process once it has
+    /// been destroyed
+    fn restarts_in_process(&self) -> bool {
+        false
+    }
+}
+
+/// Reads the `payload` of an office callback for an [OfficeEvent]
+fn event_payload(payload: *const std::ffi::c_char) -> Option<String> {
+    if payload.is_null() {
+        return None;
+    }
+
+    let payload = unsafe { CStr::from_ptr(payload) };
+    let mut payload = payload.to_string_lossy().into_owned();
+
+    if payload.len() > MAX_EVENT_PAYLOAD {
+        let mut end = MAX_EVENT_PAYLOAD;
+        while !payload.is_char_boundary(end) {
+            end -= 1;
+        }
+        payload.truncate(end);
+    }
+
+    Some(payload)
+}
+
+/// Converts the provided document bytes into PDF format returning
+/// the converted bytes
+fn convert_document(
+    office: &Office,
+
+    temp_files: ConvertTempFiles,
+
+    input: DocumentInput,
+    options: ConvertOptions,
+    trim_target: i32,
+
+    runner_state: &Rc<Mutex<RunnerState>>,
+    activity: &RunnerActivity,
+) -> anyhow::Result<ConvertedDocument> {
+    let ConvertTempFiles {
+        input: temp_in,
+        output: temp_out,
+        package: temp_package,
+    } = temp_files;
+
+    let out_url = temp_out.doc_url()?;
+
+    {
+        let state = &mut *runner_state.lock();
+        state.password = options.password.clone();
+        state.macros_enabled = options.run_macro.is_some();
+    }
+
+    let mut doc = load_document(
+        office,
+        &temp_in,
+        input,
+        &options.settings,
+        runner_state,
+        activity,
+    )?;
+
+    // Run the requested macro before exporting
+    if let Some(macro_name) = options.run_macro.as_deref() {
+        debug!(%macro_name, "running document macro");
+
+        let loaded_path = runner_state
+            .lock()
+            .fallback_input
+            .as_ref()
+            .map_or_else(|| temp_in.path.clone(), |fallback| fallback.path.clone());
+
+        if !office.run_macro(&document_macro_url(&loaded_path, macro_name))? {
+            return Err(anyhow!("failed to run macro {macro_name}"));
+        }
+
+        // Documents loaded again while exporting (i.e to redact) don't run macros
+        runner_state.lock().macros_enabled = false;
+    }
+
+    // Redact the terms from the document before it is exported
+    let mut redacted_matches = Vec::new();
+    if let Some(redact) = options
+        .redact
+        .as_ref()
+        .filter(|redact| !redact.terms.is_empty())
+    {
+        (doc, redacted_matches) = redact_document(
+            office,
+            doc,
+            &temp_in,
+            &temp_package,
+            redact,
+            runner_state,
+            activity,
+        )?;
+    }
+
+    activity.set_phase(RunnerPhase::Saving);
+
+    if let Some(csv) = options.csv.as_ref() {
+        let converted = export_csv(&mut doc, &temp_out, &temp_package, csv)?;
+
+        return Ok(ConvertedDocument {
+            warnings: collect_warnings(runner_state, Vec::new()),
+            ..converted
+        });
+    }
+
+    // Export spreadsheets to another spreadsheet format
+    if let Some(export) = options.spreadsheet.as_ref() {
+        if !matches!(doc.get_document_type(), Ok(DocumentType::Spreadsheet)) {
+            return Err(ConvertError::NotSpreadsheet.into());
+        }
+
+        if export.is_sanitized() {
+            doc = sanitize_spreadsheet(
+                office,
+                doc,
+                &temp_in,
+                &temp_package,
+                export,
+                runner_state,
+                activity,
+            )?;
+        }
+
+        let extension = export.format.extension();
+        let output = TempFile {
+            path: temp_out
+                .path
+                .with_file_name(format!("lo_native_export_{}.{extension}", random_id())),
+        };
+
+        let result = doc
+            .save_as(&output.doc_url()?, extension, None)
+            .map_err(|err| office_errors::map_error(err, false))?;
+
+        if !result {
+            return Err(anyhow!("failed to export {extension}"));
+        }
+
+        return ConvertedDocument::from_file(
+            output,
+            export.format.content_type(),
+            collect_warnings(runner_state, Vec::new()),
+            options.stream_output,
+        );
+    }
+
+    // Export to another document format
+    if let Some(target) = options.target_format {
+        let family = FormatFamily::of_document(doc.get_document_type()?);
+        if !target.is_target_of(family) {
+            return Err(ConvertError::UnsupportedTarget(target.extension).into());
+        }
+
+        let extension = target.extension;
+        let output = TempFile {
+            path: temp_out
+                .path
+                .with_file_name(format!("lo_native_export_{}.{extension}", random_id())),
+        };
+
+        let result = doc
+            .save_as(&output.doc_url()?, extension, None)
+            .map_err(|err| office_errors::map_error(err, false))?;
+
+        if !result {
+            return Err(anyhow!("failed to export {extension}"));
+        }
+
+        return ConvertedDocument::from_file(
+            output,
+            target.content_type,
+            collect_warnings(runner_state, Vec::new()),
+            options.stream_output,
+        );
+    }
+
+    // Split spreadsheets into a PDF per sheet when requested
+
+    if options.split_sheets && matches!(doc.get_document_type(), Ok(DocumentType::Spreadsheet)) {
+        let bytes = convert_sheets(
+            office,
+            &mut doc,
+            &temp_out,
+            &temp_package,
+            &options.settings,
+            &options.pdf_export,
+            trim_target,
+        )?;
+
+        return Ok(ConvertedDocument {
+            bytes,
+            file: None,
+            content_type: "application/zip",
+            warnings: collect_warnings(runner_state, Vec::new()),
+        });
+    }
+
+    let mut warnings = Vec::new();
+
+    if options.split_sheets {
+        warnings.push(ConvertWarning::new(
+            "split_sheets_ignored",
+            "split_sheets was requested but the document is not a spreadsheet",
+        ));
+    }
+
+    // Convert document
+    let filter_options = options
+        .settings
+        .pdf_filter_options(&options.pdf_export.filter_properties());
+    let result = doc
+        .save_as(&out_url, "pdf", filter_options.as_deref())
+        .map_err(|err| office_errors::map_error(err, false))?;
+
+    if !result {
+        return Err(anyhow!("failed to convert file"));
+    }
+
+    if options.pdf_export.is_rewritten() {
+        pdf_export::rewrite(&temp_out.path, &options.pdf_export)?;
+    }
+
+    if let Some(redact) = options
+        .redact
+        .as_ref()
+        .filter(|redact| !redact.regions.is_empty())
+    {
+        redact::redact_regions(&temp_out.path, &redact.regions)?;
+    }
+
+    // Terms office renders without them being in the package text (i.e fields
+    // and generated text) fail the redaction rather than being left in the PDF
+    if let Some(redact) = options
+        .redact
+        .as_ref()
+        .filter(|redact| !redact.terms.is_empty())
+    {
+        redact::verify_redacted(&temp_out.path, redact)?;
+    }
+
+    // Plain PDFs are moved out of the reused output file to be streamed
+    if options.stream_output && !options.is_bundled() && !options.pdf_export.is_encrypted() {
+        let output = TempFile {
+            path: temp_out
+                .path
+                .with_file_name(format!("lo_native_result_{}.pdf", random_id())),
+        };
+
+        std::fs::rename(&temp_out.path, &output.path).context("failed to move converted file")?;
+
+        return ConvertedDocument::from_file(
+            output,
+            "application/pdf",
+            collect_warnings(runner_state, warnings),
+            true,
+        );
+    }
+
+    // Read document context
+    let bytes = std::fs::read(&temp_out.path).context("failed to read temp out file")?;
+
+    // Office silently ignores options it does not understand, ensure the
+    // output was actually protected
+    if options.pdf_export.is_encrypted() && !is_encrypted_pdf(&bytes) {
+        return Err(ConvertError::Unsupported("pdf encryption").into());
+    }
+
+    // Produce the requested artifacts alongside the PDF in the same runner slot
+    if options.is_bundled() {
+        let mut artifacts = BundleArtifacts {
+            redactions: options
+                .redact
+                .as_ref()
+                .map(|redact| redact.report(&redacted_matches)),
+            ..Default::default()
+        };
+
+        if options.with_thumbnail {
+            let thumbnail = TempFile {
+                path: temp_out
+                    .path
+                    .with_file_name(format!("lo_native_thumbnail_{}.png", random_id())),
+            };
+
+            if !doc.save_as(&thumbnail.doc_url()?, "png", None)? {
+                return Err(anyhow!("failed to render thumbnail"));
+            }
+
+            artifacts.thumbnail =
+                Some(std::fs::read(&thumbnail.path).context("failed to read thumbnail")?);
+        }
+
+        if options.with_text {
+            // Save as the native ODF package to read the text of each page
+            let document_type = doc.get_document_type()?;
+            if !doc.save_as(
+                &temp_package.doc_url()?,
+                odf::package_format(document_type),
+                None,
+            )? {
+                return Err(anyhow!("failed to export document package"));
+            }
+
+            artifacts.text = Some(odf::read_page_text(&temp_package.path)?);
+        }
+
+        let mut bundled = bundle(&bytes, artifacts)?;
+        bundled.warnings = collect_warnings(runner_state, warnings);
+        return Ok(bundled);
+    }
+
+    Ok(ConvertedDocument {
+        bytes: Bytes::from(bytes),
+        file: None,
+        content_type: "application/pdf",
+        warnings: collect_warnings(runner_state, warnings),
+    })
+}
+
+/// Checks if the PDF `bytes` are encrypted, encrypted PDFs reference their
+/// encryption dictionary from the trailer
+fn is_encrypted_pdf(bytes: &[u8]) -> bool {
+    bytes
+        .windows(b"/Encrypt".len())
+        .any(|window| window == b"/Encrypt")
+}
+
+/// Adds the warnings observed by the office callback while processing the
+/// document to the `warnings`
+fn collect_warnings(
+    runner_state: &Rc<Mutex<RunnerState>>,
+    mut warnings: Vec<ConvertWarning>,
+) -> Vec<ConvertWarning> {
+    let (dialogs_dismissed, loaded_as) = {
+        let state = &*runner_state.lock();
+        (state.dialogs_dismissed, state.loaded_as)
+    };
+
+    if let Some(extension) = loaded_as {
+        warnings.push(ConvertWarning::new(
+            "load_fallback",
+            format!("office could not detect the format of the file, it was loaded as {extension}"),
+        ));
+    }
+
+    if dialogs_dismissed > 0 {
+        warnings.push(ConvertWarning::new(
+            "dialogs_dismissed",
+            format!(
+                "office dismissed {dialogs_dismissed} dialog(s) while converting, the document may have been repaired or content skipped"
+            ),
+        ));
+    }
+
+    warnings
+}
+
+/// Extracts the embedded images and objects from the provided document
+/// bytes returning a zip archive containing them
+fn extract_document_assets(
+    office: &Office,
+
+    temp_files: ConvertTempFiles,
+
+    input: DocumentInput,
+
+    runner_state: &Rc<Mutex<RunnerState>>,
+    activity: &RunnerActivity,
+) -> anyhow::Result<Bytes> {
+    let ConvertTempFiles {
+        input: temp_in,
+        package: temp_package,
+        ..
+    } = temp_files;
+
+    let package_url = temp_package.doc_url()?;
+
+    let mut doc = load_document(
+        office,
+        &temp_in,
+        input,
+        &RenderSettings::default(),
+        runner_state,
+        activity,
+    )?;
+    activity.set_phase(RunnerPhase::Saving);
+
+    // Save as the native ODF package to access the embedded files
+    let document_type = doc.get_document_type()?;
+    if !doc.save_as(&package_url, odf::package_format(document_type), None)? {
+        return Err(anyhow!("failed to export document package"));
+    }
+
+    let bytes = odf::extract_assets(&temp_package.path)?;
+
+    Ok(Bytes::from(bytes))
+}
+
+/// Extracts the statistics (word count, sheet count, slide count, etc) from
+/// the provided document bytes
+fn extract_document_stats(
+    office: &Office,
+
+    temp_files: ConvertTempFiles,
+
+    input: DocumentInput,
+
+    runner_state: &Rc<Mutex<RunnerState>>,
+    activity: &RunnerActivity,
+) -> anyhow::Result<DocumentStats> {
+    let ConvertTempFiles {
+        input: temp_in,
+        package: temp_package,
+        ..
+    } = temp_files;
+
+    let package_url = temp_package.doc_url()?;
+
+    let mut doc = load_document(
+        office,
+        &temp_in,
+        input,
+        &RenderSettings::default(),
+        runner_state,
+        activity,
+    )?;
+    activity.set_phase(RunnerPhase::Saving);
+
+    // Save as the native ODF package to read the statistics
+    let document_type = doc.get_document_type()?;
+    if !doc.save_as(&package_url, odf::package_format(document_type), None)? {
+        return Err(anyhow!("failed to export document package"));
+    }
+
+    let mut statistics = odf::read_statistics(&temp_package.path)?;
+
+    if matches!(
+        document_type,
+        DocumentType::Presentation | DocumentType::Drawing
+    ) {
+        statistics.slide_count = Some(odf::count_slides(&temp_package.path)?);
+    }
+
+    let document_type = match document_type {
+        DocumentType::Text => "text",
+        DocumentType::Spreadsheet => "spreadsheet",
+        DocumentType::Presentation => "presentation",
+        DocumentType::Drawing => "drawing",
+        DocumentType::Other(_) => "other",
+    };
+
+    Ok(DocumentStats {
+        document_type,
+        statistics,
+    })
+}
+
+/// Writes the provided document bytes to the temp input file and
+/// loads the document, spilled documents are loaded directly from disk
+pub(crate) fn load_document(
+    office: &Office,
+    temp_in: &TempFile,
+    input: DocumentInput,
+    settings: &RenderSettings,
+    runner_state: &Rc<Mutex<RunnerState>>,
+    activity: &RunnerActivity,
+) -> anyhow::Result<Document> {
+    activity.set_phase(RunnerPhase::Loading);
+    {
+        let state = &mut *runner_state.lock();
+        state.dialogs_dismissed = 0;
+        state.password_attempts = 0;
+        state.loaded_as = None;
+        state.fallback_input = None;
+    }
+
+    let in_url = match input {
+        DocumentInput::Bytes(bytes) => {
+            // Write to temp file
+            std::fs::write(&temp_in.path, bytes).context("failed to write temp input")?;
+            temp_in.doc_url()?
+        }
+        DocumentInput::Spilled(spilled) => {
+            // Move the spilled file into place, it will be removed with the input file.
+            // Spilled files are copied into the memory work directory as it is another
+            // filesystem
+            std::fs::rename(&spilled.path, &temp_in.path)
+                .or_else(|_| std::fs::copy(&spilled.path, &temp_in.path).map(|_| ()))
+                .context("failed to move spilled input")?;
+            temp_in.doc_url()?
+        }
+    };
+
+    runner_state.lock().input_url = Some(in_url.clone());
+
+    // Load document
+    let macros_enabled = runner_state.lock().macros_enabled;
+    let doc =
+        match office.document_load_with_options(&in_url, &settings.load_options(macros_enabled)) {
+            Ok(value) => value,
+            Err(err) => {
+                if let OfficeError::OfficeError(err) = &err {
+                    error!(%err, "failed to load document");
+                }
+
+                let password_rejected = {
+                    let state = &*runner_state.lock();
+                    state.password.is_some() && state.password_requested
+                };
+                let err = office_errors::map_error(err, password_rejected);
+
+                if !matches!(err.downcast_ref(), Some(ConvertError::Corrupted)) {
+                    return Err(err);
+                }
+
+                let Some((doc, extension, fallback_input)) =
+                    load_with_candidates(office, temp_in, settings, runner_state)?
+                else {
+                    return Err(err);
+                };
+
+                let state = &mut *runner_state.lock();
+                state.loaded_as = Some(extension);
+                state.fallback_input = Some(fallback_input);
+
+                doc
+            }
+        };
+
+    debug!("document loaded");
+    activity.set_phase(RunnerPhase::Converting);
+
+    Ok(doc)
+}
+
+/// Creates the URL of the `macro_name` (Library.Module.Macro) stored within
+/// the document loaded from `path`. Office finds the document by its title
+/// (The file name), "macro:///" would run the application ("My Macros")
+/// Basic library of the same name instead
+pub fn document_macro_url(path: &Path, macro_name: &str) -> String {
+    let title = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+
+    format!("macro://{title}/{macro_name}")
+}
+
+/// Retries loading the document at `temp_in` office could not detect the
+/// format of as each of the [detect::load_candidates] of its content,
+/// providing the document along with the extension it was loaded as and the
+/// copy of the input it was loaded from
+fn load_with_candidates(
+    office: &Office,
+    temp_in: &TempFile,
+    settings: &RenderSettings,
+    runner_state: &Rc<Mutex<RunnerState>>,
+) -> anyhow::Result<Option<(Document, &'static str, TempFile)>> {
+    let bytes = std::fs::read(&temp_in.path).context("failed to read temp input")?;
+
+    for &extension in detect::load_candidates(&bytes) {
+        let candidate = TempFile {
+            path: temp_in.path.with_extension(extension),
+        };
+        std::fs::write(&candidate.path, &bytes).context("failed to write temp input")?;
+
+        let candidate_url = candidate.doc_url()?;
+        let macros_enabled = {
+            let state = &mut *runner_state.lock();
+            state.input_url = Some(candidate_url.clone());
+            state.macros_enabled
+        };
+
+        match office
+            .document_load_with_options(&candidate_url, &settings.load_options(macros_enabled))
+        {
+            Ok(doc) => {
+                warn!(
+                    extension,
+                    "document loaded after office could not detect its format"
+                );
+                return Ok(Some((doc, extension, candidate)));
+            }
+            Err(err) => debug!(%err, extension, "failed to load document as candidate format"),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Converts each sheet of the loaded spreadsheet into its own PDF file
+/// returning a zip archive containing the PDFs.
+///
+/// Sheets are exported one at a time (and memory trimmed in-between)
+/// to keep the memory usage of LibreOffice bounded for large spreadsheets
+fn convert_sheets(
+    office: &Office,
+    doc: &mut Document,
+    temp_out: &TempFile,
+    temp_package: &TempFile,
+    settings: &RenderSettings,
+    pdf_export: &PdfExportOptions,
+    trim_target: i32,
+) -> anyhow::Result<Bytes> {
+    let out_url = temp_out.doc_url()?;
+    let package_url = temp_package.doc_url()?;
+
+    // Save as an ODF package to determine the number of sheets
+    if !doc.save_as(&package_url, "ods", None)? {
+        return Err(anyhow!("failed to read spreadsheet sheets"));
+    }
+
+    let statistics = odf::read_statistics(&temp_package.path)?;
+    let sheet_count = statistics
+        .table_count
+        .context("spreadsheet is missing sheet count")?;
+
+    debug!(sheet_count, "splitting spreadsheet sheets");
+
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+
+    for sheet in 1..=sheet_count {
+        // Each sheet becomes a single page, so a page range selects one sheet
+        let mut properties = pdf_export.filter_properties();
+        properties.extend([
+            (
+                "SinglePageSheets",
+                json!({ "type": "boolean", "value": "true" }),
+            ),
+            (
+                "PageRange",
+                json!({ "type": "string", "value": sheet.to_string() }),
+            ),
+        ]);
+
+        let filter_options = settings.pdf_filter_options(&properties);
+
+        let result = doc
+            .save_as(&out_url, "pdf", filter_options.as_deref())
+            .map_err(|err| office_errors::map_error(err, false))?;
+
+        // Attempt to free up some memory
+        _ = office.trim_memory(trim_target);
+
+        if !result {
+            return Err(anyhow!("failed to convert sheet {sheet}"));
+        }
+
+        if pdf_export.is_rewritten() {
+            pdf_export::rewrite(&temp_out.path, pdf_export)?;
+        }
+
+        let bytes = std::fs::read(&temp_out.path).context("failed to read temp out file")?;
+
+        if pdf_export.is_encrypted() && !is_encrypted_pdf(&bytes) {
+            return Err(ConvertError::Unsupported("pdf encryption").into());
+        }
+
+        archive
+            .start_file(format!("sheet-{sheet}.pdf"), SimpleFileOptions::default())
+            .context("failed to create zip entry")?;
+        archive
+            .write_all(&bytes)
+            .context("failed to write zip entry")?;
+    }
+
+    let archive = archive.finish().context("failed to finish zip")?;
+
+    Ok(Bytes::from(archive.into_inner()))
+}
+
+/// Exports the sheets of the loaded spreadsheet selected by the `options` as
+/// CSV, multiple sheets are bundled together in a zip archive
+fn export_csv(
+    doc: &mut Document,
+    temp_out: &TempFile,
+    temp_package: &TempFile,
+    options: &CsvOptions,
+) -> anyhow::Result<ConvertedDocument> {
+    let mut sheets = csv::export_sheets(doc, temp_out, temp_package, options)?;
+
+    if !options.is_bundled() {
+        let (_, bytes) = sheets.remove(0);
+
+        return Ok(ConvertedDocument {
+            bytes: Bytes::from(bytes),
+            file: None,
+            content_type: options.content_type(),
+            warnings: Vec::new(),
+        });
+    }
+
+    csv_bundle(options, sheets)
+}
+
+/// Sanitizes the loaded spreadsheet, the spreadsheet is saved as an ODF
+/// package, sanitized and loaded again providing the sanitized document
+fn sanitize_spreadsheet(
+    office: &Office,
+    mut doc: Document,
+    temp_in: &TempFile,
+    temp_package: &TempFile,
+    export: &SpreadsheetExport,
+    runner_state: &Rc<Mutex<RunnerState>>,
+    activity: &RunnerActivity,
+) -> anyhow::Result<Document> {
+    if !doc.save_as(&temp_package.doc_url()?, "ods", None)? {
+        return Err(anyhow!("failed to export document package"));
+    }
+
+    let sanitized = workbook::sanitize_package(&temp_package.path, export)?;
+
+    drop(doc);
+    let doc = load_document(
+        office,
+        temp_in,
+        DocumentInput::Bytes(Bytes::from(sanitized)),
+        &RenderSettings::default(),
+        runner_state,
+        activity,
+    )
+    .context("sanitized spreadsheet failed to load")?;
+    activity.set_phase(RunnerPhase::Saving);
+
+    Ok(doc)
+}
+
+/// Redacts the terms of the `redact` options from the loaded document, the
+/// document is saved as its native ODF package, redacted and loaded again
+/// providing the redacted document and the number of matches of each term
+fn redact_document(
+    office: &Office,
+    mut doc: Document,
+    temp_in: &TempFile,
+    temp_package: &TempFile,
+    redact: &RedactOptions,
+    runner_state: &Rc<Mutex<RunnerState>>,
+    activity: &RunnerActivity,
+) -> anyhow::Result<(Document, Vec<u64>)> {
+    let document_type = doc.get_document_type()?;
+    if !doc.save_as(
+        &temp_package.doc_url()?,
+        odf::package_format(document_type),
+        None,
+    )? {
+        return Err(anyhow!("failed to export document package"));
+    }
+
+    let (redacted, matches) = redact::redact_package(&temp_package.path, redact)?;
+
+    drop(doc);
+    let doc = load_document(
+        office,
+        temp_in,
+        DocumentInput::Bytes(Bytes::from(redacted)),
+        &RenderSettings::default(),
+        runner_state,
+        activity,
+    )
+    .context("redacted document failed to load")?;
+    activity.set_phase(RunnerPhase::Saving);
+
+    Ok((doc, matches))
+}
+
+/// Bundles the exported CSV `sheets` (sheet number and contents) into a zip
+/// archive
+pub(crate) fn csv_bundle(
+    options: &CsvOptions,
+    sheets: Vec<(u64, Vec<u8>)>,
+) -> anyhow::Result<ConvertedDocument> {
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+
+    for (sheet, bytes) in sheets {
+        archive
+            .start_file(
+                format!("sheet-{sheet}.{}", options.extension()),
+                SimpleFileOptions::default(),
+            )
+            .context("failed to create zip entry")?;
+        archive
+            .write_all(&bytes)
+            .context("failed to write zip entry")?;
+    }
+
+    let archive = archive.finish().context("failed to finish zip")?;
+
+    Ok(ConvertedDocument {
+        bytes: Bytes::from(archive.into_inner()),
+        file: None,
+        content_type: "application/zip",
+        warnings: Vec::new(),
+    })
+}
+
+/// Errors from converting documents that have a known cause
+#[derive(Debug, Error)]
+pub enum ConvertError {
+    /// File was encrypted with a password
+    #[error("file is encrypted")]
+    Encrypted,
+
+    /// Password provided for an encrypted file was not correct
+    #[error("incorrect password for the encrypted file")]
+    WrongPassword,
+
+    /// File is malformed or corrupted
+    #[error("file is corrupted")]
+    Corrupted,
+
+    /// Functionality is not supported by the configured backend
+    #[error("{0} is not supported by the conversion backend")]
+    Unsupported(&'static str),
+
+    /// Spreadsheet export was requested for another type of document
+    #[error("file is not a spreadsheet")]
+    NotSpreadsheet,
+
+    /// Requested sheet is not within the spreadsheet
+    #[error("sheet {sheet} does not exist, the spreadsheet has {sheet_count} sheet(s)")]
+    InvalidSheet { sheet: u64, sheet_count: u64 },
+
+    /// Page of an annotation is beyond the last page of the exported PDF
+    #[error("page {page} does not exist, the pdf has {page_count} page(s)")]
+    InvalidPage { page: u64, page_count: u64 },
+
+    /// Requested format cannot be exported to from this type of document
+    #[error("this type of document cannot be exported as {0}")]
+    UnsupportedTarget(&'static str),
+
+    /// Office install has no filter for the requested format
+    #[error("office has no filter for the requested format")]
+    FilterMissing,
+
+    /// Disk ran out of space while converting
+    #[error("not enough disk space to convert the file")]
+    DiskFull,
+
+    /// Redacted terms were still found in the text of the converted PDF
+    #[error("{remaining} match(es) of the redacted terms remain in the converted pdf")]
+    RedactionIncomplete { remaining: usize },
+}
+
+impl HttpError for ConvertError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ConvertError::Encrypted
+            | ConvertError::WrongPassword
+            | ConvertError::Corrupted
+            | ConvertError::NotSpreadsheet
+            | ConvertError::InvalidSheet { .. }
+            | ConvertError::InvalidPage { .. }
+            | ConvertError::UnsupportedTarget(_)
+            | ConvertError::RedactionIncomplete { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ConvertError::Unsupported(_) | ConvertError::FilterMissing => {
+                StatusCode::NOT_IMPLEMENTED
+            }
+            ConvertError::DiskFull => StatusCode::INSUFFICIENT_STORAGE,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            ConvertError::Encrypted => "encrypted",
+            ConvertError::WrongPassword => "wrong_password",
+            ConvertError::Corrupted => "corrupted",
+            ConvertError::Unsupported(_) => "unsupported",
+            ConvertError::NotSpreadsheet => "not_spreadsheet",
+            ConvertError::InvalidSheet { .. } => "invalid_sheet",
+            ConvertError::InvalidPage { .. } => "invalid_page",
+            ConvertError::UnsupportedTarget(_) => "unsupported_target",
+            ConvertError::FilterMissing => "filter_missing",
+            ConvertError::DiskFull => "disk_full",
+            ConvertError::RedactionIncomplete { .. } => "redaction_incomplete",
+        })
+    }
+}
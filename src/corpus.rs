@@ -0,0 +1,382 @@
+use crate::diff_output::{self, PageReport, PageStatus};
+use anyhow::{anyhow, Context};
+use clap::{Args, Subcommand, ValueEnum};
+use libreofficekit::Office;
+use office_convert_server::tempfiles::{random_id, TempFile};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    env::temp_dir,
+    path::{Path, PathBuf},
+};
+
+/// Arguments for the corpus subcommand
+#[derive(Args, Debug)]
+pub struct CorpusArgs {
+    #[command(subcommand)]
+    command: CorpusCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum CorpusCommand {
+    /// Convert a directory of reference documents and compare the outputs
+    /// against their stored baselines
+    Run(RunArgs),
+}
+
+/// Arguments for running the corpus
+#[derive(Args, Debug)]
+struct RunArgs {
+    /// Directory of reference documents, documents in nested directories are
+    /// also converted
+    #[arg(long)]
+    dir: PathBuf,
+
+    /// Directory of baseline outputs, the baseline of "letters/a.docx" is
+    /// "letters/a.docx.pdf"
+    #[arg(long)]
+    baseline: PathBuf,
+
+    /// How outputs are compared to their baselines, defaults to "visual"
+    #[arg(long, value_enum, default_value_t = CompareMode::Visual)]
+    compare: CompareMode,
+
+    /// Difference between the channels of a pixel ignored as rendering noise
+    /// (0-255), defaults to 16
+    #[arg(long, default_value_t = 16)]
+    tolerance: u8,
+
+    /// Percentage of changed pixels above which a page is reported as
+    /// different, defaults to 0.1
+    #[arg(long, default_value_t = 0.1)]
+    threshold: f64,
+
+    /// Directory to write images highlighting the changed pixels of the
+    /// different pages of each document to
+    #[arg(long)]
+    diff_dir: Option<PathBuf>,
+
+    /// Write the outputs of new and different documents as their baselines
+    #[arg(long)]
+    update: bool,
+
+    /// Print the report as JSON and exit with a failure when any document
+    /// differs or fails to convert
+    #[arg(long)]
+    ci: bool,
+}
+
+/// How outputs are compared to their baselines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CompareMode {
+    /// Outputs must be byte for byte the same as their baselines
+    Hash,
+    /// Outputs must render the same as their baselines, outputs with the
+    /// same bytes are not rendered
+    Visual,
+}
+
+/// Outcome of converting a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum DocumentStatus {
+    /// Output matches the baseline
+    Same,
+    /// Output differs from the baseline
+    Different,
+    /// Document has no baseline
+    New,
+    /// Document failed to convert
+    Failed,
+}
+
+/// Outcome of a single document in the report
+#[derive(Debug, Serialize)]
+struct DocumentReport {
+    /// Path of the document within the corpus directory
+    document: PathBuf,
+    /// Outcome of converting the document
+    status: DocumentStatus,
+    /// SHA-256 hex digest of the output
+    sha256: Option<String>,
+    /// SHA-256 hex digest of the baseline
+    baseline_sha256: Option<String>,
+    /// Pages that render differently from the baseline (Visual comparisons only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pages: Vec<PageReport>,
+    /// Whether the output was written as the new baseline
+    updated: bool,
+    /// Reason the document failed
+    error: Option<String>,
+}
+
+/// Report of running the corpus
+#[derive(Debug, Serialize)]
+struct CorpusReport {
+    /// Version of LibreOffice the corpus was converted with
+    office_version: Option<String>,
+    /// How outputs were compared to their baselines
+    compare: CompareMode,
+    /// Whether no document differs or failed to convert
+    ok: bool,
+    /// Outcome of each document
+    documents: Vec<DocumentReport>,
+}
+
+/// Runs the corpus subcommand using the office install at `office_path`
+pub async fn run(args: CorpusArgs, office_path: PathBuf) -> anyhow::Result<()> {
+    match args.command {
+        CorpusCommand::Run(args) => {
+            tokio::task::spawn_blocking(move || run_blocking(args, office_path))
+                .await
+                .context("corpus run failed")?
+        }
+    }
+}
+
+fn run_blocking(args: RunArgs, office_path: PathBuf) -> anyhow::Result<()> {
+    let office = Office::new(&office_path).context("failed to create office instance")?;
+
+    let office_version = office.get_version_info().ok().map(|version| {
+        format!(
+            "{}.{} ({})",
+            version.product_version.major, version.product_version.minor, version.build_id
+        )
+    });
+
+    let mut documents = Vec::new();
+    collect_documents(&args.dir, Path::new(""), &mut documents)?;
+    documents.sort();
+
+    if documents.is_empty() {
+        return Err(anyhow!("no documents found in {}", args.dir.display()));
+    }
+
+    let documents = documents
+        .into_iter()
+        .map(|document| {
+            run_document(&office, &args, &document).unwrap_or_else(|err| DocumentReport {
+                document,
+                status: DocumentStatus::Failed,
+                sha256: None,
+                baseline_sha256: None,
+                pages: Vec::new(),
+                updated: false,
+                error: Some(format!("{err:#}")),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let count = |status: DocumentStatus| {
+        documents
+            .iter()
+            .filter(|document| document.status == status)
+            .count()
+    };
+    let (same, different, new, failed) = (
+        count(DocumentStatus::Same),
+        count(DocumentStatus::Different),
+        count(DocumentStatus::New),
+        count(DocumentStatus::Failed),
+    );
+
+    let report = CorpusReport {
+        office_version,
+        compare: args.compare,
+        ok: different == 0 && failed == 0,
+        documents,
+    };
+
+    if args.ci {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+
+        if !report.ok {
+            return Err(anyhow!(
+                "{different} documents differ from their baselines, {failed} failed"
+            ));
+        }
+
+        return Ok(());
+    }
+
+    if let Some(version) = report.office_version.as_deref() {
+        println!("converted with LibreOffice {version}");
+    }
+
+    for document in &report.documents {
+        let status = match document.status {
+            DocumentStatus::Same => "SAME",
+            DocumentStatus::Different => "DIFF",
+            DocumentStatus::New => "NEW",
+            DocumentStatus::Failed => "FAIL",
+        };
+
+        print!("{status:<5} {}", document.document.display());
+
+        let pages = document
+            .pages
+            .iter()
+            .map(|page| page.page.to_string())
+            .collect::<Vec<_>>();
+        if !pages.is_empty() {
+            print!(" (pages {})", pages.join(", "));
+        }
+        if document.updated {
+            print!(" (baseline written)");
+        }
+        if let Some(error) = document.error.as_deref() {
+            print!(": {error}");
+        }
+        println!();
+    }
+
+    println!();
+    println!(
+        "{} documents, {same} same, {different} different, {new} new, {failed} failed",
+        report.documents.len()
+    );
+
+    Ok(())
+}
+
+/// Collects the paths of the documents in `dir` relative to the corpus
+/// directory (`relative` being the path of `dir` within it), hidden files
+/// and directories are skipped
+fn collect_documents(
+    dir: &Path,
+    relative: &Path,
+    documents: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?;
+
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to read {}", dir.display()))?;
+        let name = entry.file_name();
+
+        if name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        let path = entry.path();
+        let relative = relative.join(&name);
+
+        if entry.file_type()?.is_dir() {
+            collect_documents(&path, &relative, documents)?;
+        } else {
+            documents.push(relative);
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts the `document` of the corpus and compares the output to its
+/// baseline
+fn run_document(
+    office: &Office,
+    args: &RunArgs,
+    document: &Path,
+) -> anyhow::Result<DocumentReport> {
+    let output = convert_document(office, &args.dir.join(document))?;
+    let output_bytes = std::fs::read(&output.path).context("failed to read output")?;
+    let sha256 = format!("{:x}", Sha256::digest(&output_bytes));
+
+    let mut baseline_name = document.as_os_str().to_owned();
+    baseline_name.push(".pdf");
+    let baseline = args.baseline.join(baseline_name);
+
+    let mut report = DocumentReport {
+        document: document.to_path_buf(),
+        status: DocumentStatus::New,
+        sha256: Some(sha256.clone()),
+        baseline_sha256: None,
+        pages: Vec::new(),
+        updated: false,
+        error: None,
+    };
+
+    if baseline.exists() {
+        let baseline_bytes = std::fs::read(&baseline)
+            .with_context(|| format!("failed to read {}", baseline.display()))?;
+        let baseline_sha256 = format!("{:x}", Sha256::digest(&baseline_bytes));
+
+        report.status = if baseline_sha256 == sha256 {
+            DocumentStatus::Same
+        } else {
+            match args.compare {
+                CompareMode::Hash => DocumentStatus::Different,
+                CompareMode::Visual => {
+                    let old = diff_output::render_pages(office, &baseline)?;
+                    let new = diff_output::render_pages(office, &output.path)?;
+                    let diff_dir = args.diff_dir.as_ref().map(|dir| dir.join(document));
+
+                    report.pages = diff_output::compare_pages(
+                        &old,
+                        &new,
+                        args.tolerance,
+                        args.threshold,
+                        diff_dir.as_deref(),
+                    )?;
+                    report.pages.retain(|page| page.status != PageStatus::Same);
+
+                    match report.pages.is_empty() {
+                        true => DocumentStatus::Same,
+                        false => DocumentStatus::Different,
+                    }
+                }
+            }
+        };
+        report.baseline_sha256 = Some(baseline_sha256);
+    }
+
+    if args.update
+        && matches!(
+            report.status,
+            DocumentStatus::New | DocumentStatus::Different
+        )
+    {
+        if let Some(parent) = baseline.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+
+        std::fs::write(&baseline, &output_bytes)
+            .with_context(|| format!("failed to write {}", baseline.display()))?;
+        report.updated = true;
+    }
+
+    Ok(report)
+}
+
+/// Converts the document at `path` to a PDF
+fn convert_document(office: &Office, path: &Path) -> anyhow::Result<TempFile> {
+    let random_id = random_id();
+    let tmp_dir = temp_dir();
+
+    // Office picks the import filter from the extension
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let input = TempFile {
+        path: tmp_dir.join(format!("lo_native_corpus_{random_id}{extension}")),
+    };
+    let output = TempFile {
+        path: tmp_dir.join(format!("lo_native_corpus_{random_id}_output.pdf")),
+    };
+
+    std::fs::copy(path, &input.path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    let mut doc = office
+        .document_load_with_options(&input.doc_url()?, "InteractionHandler=0,Batch=1")
+        .context("failed to load document")?;
+
+    if !doc.save_as(&output.doc_url()?, "pdf", None)? {
+        return Err(anyhow!("failed to convert document"));
+    }
+
+    Ok(output)
+}
@@ -0,0 +1,331 @@
+use crate::{convert::ConvertError, error::HttpError, formats, odf, tempfiles::TempFile};
+use anyhow::{anyhow, Context};
+use axum::http::StatusCode;
+use libreofficekit::{Document, DocumentType};
+use serde::Serialize;
+use serde_json::json;
+use thiserror::Error;
+
+/// Maximum number of sheets that can be selected individually
+const MAX_SELECTED_SHEETS: usize = 256;
+
+/// Options for exporting spreadsheets as CSV instead of PDF
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CsvOptions {
+    /// Sheets to export
+    pub sheets: CsvSheets,
+    /// Character separating fields
+    pub delimiter: char,
+    /// Quote every text cell instead of only those that require quoting
+    pub quote_all: bool,
+    /// Character encoding of the exported text
+    pub encoding: CsvEncoding,
+}
+
+/// Sheets of a spreadsheet to export as CSV
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvSheets {
+    /// Specific sheets (Starting at 1)
+    Selected(Vec<u64>),
+    /// Every sheet of the spreadsheet
+    All,
+}
+
+/// Character encoding of exported CSV files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CsvEncoding {
+    #[default]
+    Utf8,
+    Utf16,
+    Windows1252,
+    Iso88591,
+}
+
+/// Errors from invalid CSV export options
+#[derive(Debug, Error)]
+pub enum CsvError {
+    /// Option was provided without exporting as CSV
+    #[error("{0} is only used when exporting as csv or tsv")]
+    NotCsv(&'static str),
+
+    /// Option had a value that is not allowed
+    #[error("invalid value for csv option {0}")]
+    InvalidValue(&'static str),
+}
+
+impl HttpError for CsvError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("invalid_csv_option")
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            CsvError::NotCsv(option) | CsvError::InvalidValue(option) => {
+                Some(json!({ "option": option }))
+            }
+        }
+    }
+}
+
+/// Raw CSV export options provided by a request
+#[derive(Debug, Default)]
+pub struct CsvRequest<'a> {
+    /// Output format (i.e "pdf", "csv" or "docx")
+    pub format: Option<&'a str>,
+    /// Comma separated sheet numbers or "all"
+    pub sheets: Option<&'a str>,
+    /// Single character separating fields
+    pub delimiter: Option<&'a str>,
+    /// Quoting of text cells ("minimal" or "all")
+    pub quote: Option<&'a str>,
+    /// Name of the character encoding
+    pub encoding: Option<&'a str>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            sheets: CsvSheets::Selected(vec![1]),
+            delimiter: ',',
+            quote_all: false,
+            encoding: CsvEncoding::Utf8,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Parses the CSV options from a request, provides [None] when the
+    /// request is not exporting as CSV
+    pub fn parse(request: CsvRequest<'_>) -> Result<Option<Self>, CsvError> {
+        let format = request.format.map(str::to_ascii_lowercase);
+        let delimiter = match format.as_deref() {
+            Some("csv") => Some(','),
+            Some("tsv") => Some('\t'),
+            // Other formats are handled by their own options
+            None => None,
+            Some(format) if formats::is_export_target(format) => None,
+            Some(_) => return Err(CsvError::InvalidValue("format")),
+        };
+
+        let Some(delimiter) = delimiter else {
+            let options = [
+                ("sheets", request.sheets),
+                ("delimiter", request.delimiter),
+                ("quote", request.quote),
+                ("encoding", request.encoding),
+            ];
+
+            return match options.into_iter().find(|(_, value)| value.is_some()) {
+                Some((option, _)) => Err(CsvError::NotCsv(option)),
+                None => Ok(None),
+            };
+        };
+
+        let mut options = CsvOptions {
+            delimiter,
+            ..Default::default()
+        };
+
+        if let Some(sheets) = request.sheets {
+            options.sheets = parse_sheets(sheets).ok_or(CsvError::InvalidValue("sheets"))?;
+        }
+
+        if let Some(delimiter) = request.delimiter {
+            options.delimiter =
+                parse_delimiter(delimiter).ok_or(CsvError::InvalidValue("delimiter"))?;
+        }
+
+        if let Some(quote) = request.quote {
+            options.quote_all = match quote.to_ascii_lowercase().as_str() {
+                "minimal" => false,
+                "all" => true,
+                _ => return Err(CsvError::InvalidValue("quote")),
+            };
+        }
+
+        if let Some(encoding) = request.encoding {
+            options.encoding =
+                CsvEncoding::parse(encoding).ok_or(CsvError::InvalidValue("encoding"))?;
+        }
+
+        Ok(Some(options))
+    }
+
+    /// Whether multiple files are exported, bundling the output into a
+    /// zip archive
+    pub fn is_bundled(&self) -> bool {
+        match &self.sheets {
+            CsvSheets::Selected(sheets) => sheets.len() > 1,
+            CsvSheets::All => true,
+        }
+    }
+
+    /// Mime type of the exported files
+    pub fn content_type(&self) -> &'static str {
+        match self.delimiter {
+            '\t' => "text/tab-separated-values",
+            _ => "text/csv",
+        }
+    }
+
+    /// File extension of the exported files
+    pub fn extension(&self) -> &'static str {
+        match self.delimiter {
+            '\t' => "tsv",
+            _ => "csv",
+        }
+    }
+
+    /// Creates the CSV filter options to export the `sheet` (Starting at 1)
+    pub fn filter_options(&self, sheet: u64) -> String {
+        // Field separator, text delimiter (double quote), character set, first line,
+        // cell format, language, quote all text cells, detect special numbers, save
+        // as shown, export formulas, remove spaces, sheet to export
+        format!(
+            "{},34,{},1,,0,{},true,false,false,false,{sheet}",
+            self.delimiter as u32,
+            self.encoding.charset(),
+            self.quote_all
+        )
+    }
+
+    /// Creates a fingerprint of the options for use in cache keys
+    pub(crate) fn cache_fingerprint(&self) -> String {
+        let sheets = match &self.sheets {
+            CsvSheets::Selected(sheets) => sheets
+                .iter()
+                .map(|sheet| sheet.to_string())
+                .collect::<Vec<_>>()
+                .join(" "),
+            CsvSheets::All => "all".to_string(),
+        };
+
+        format!(
+            "sheets={sheets} delimiter={} quote_all={} encoding={}",
+            self.delimiter as u32,
+            self.quote_all,
+            self.encoding.charset()
+        )
+    }
+}
+
+impl CsvEncoding {
+    /// Parses the name of an encoding (i.e "utf-8", "windows-1252")
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.to_ascii_lowercase().replace(['-', '_'], "");
+
+        Some(match value.as_str() {
+            "utf8" => CsvEncoding::Utf8,
+            "utf16" => CsvEncoding::Utf16,
+            "windows1252" | "cp1252" => CsvEncoding::Windows1252,
+            "iso88591" | "latin1" => CsvEncoding::Iso88591,
+            _ => return None,
+        })
+    }
+
+    /// Office text encoding identifier of the encoding
+    fn charset(&self) -> u32 {
+        match self {
+            CsvEncoding::Utf8 => 76,
+            CsvEncoding::Utf16 => 65535,
+            CsvEncoding::Windows1252 => 1,
+            CsvEncoding::Iso88591 => 12,
+        }
+    }
+}
+
+/// Parses a comma separated list of sheet numbers (i.e "1,3") or "all"
+fn parse_sheets(value: &str) -> Option<CsvSheets> {
+    if value.trim().eq_ignore_ascii_case("all") {
+        return Some(CsvSheets::All);
+    }
+
+    let mut sheets: Vec<u64> = value
+        .split(',')
+        .map(|sheet| sheet.trim().parse().ok().filter(|sheet| *sheet > 0))
+        .collect::<Option<_>>()?;
+
+    sheets.dedup();
+
+    if sheets.is_empty() || sheets.len() > MAX_SELECTED_SHEETS {
+        return None;
+    }
+
+    Some(CsvSheets::Selected(sheets))
+}
+
+/// Parses a delimiter, either a single printable ASCII character or "\t"
+fn parse_delimiter(value: &str) -> Option<char> {
+    if value == "\\t" {
+        return Some('\t');
+    }
+
+    let mut chars = value.chars();
+    let delimiter = chars.next()?;
+
+    let is_valid = chars.next().is_none()
+        && (delimiter == '\t' || delimiter.is_ascii_punctuation() || delimiter == ' ')
+        && delimiter != '"';
+
+    is_valid.then_some(delimiter)
+}
+
+/// Exports the sheets of the loaded spreadsheet selected by the `options` as
+/// CSV, providing the number and contents of each sheet
+pub(crate) fn export_sheets(
+    doc: &mut Document,
+    temp_out: &TempFile,
+    temp_package: &TempFile,
+    options: &CsvOptions,
+) -> anyhow::Result<Vec<(u64, Vec<u8>)>> {
+    if !matches!(doc.get_document_type(), Ok(DocumentType::Spreadsheet)) {
+        return Err(ConvertError::NotSpreadsheet.into());
+    }
+
+    // Save as an ODF package to determine the number of sheets
+    if !doc.save_as(&temp_package.doc_url()?, "ods", None)? {
+        return Err(anyhow!("failed to read spreadsheet sheets"));
+    }
+
+    let statistics = odf::read_statistics(&temp_package.path)?;
+    let sheet_count = statistics
+        .table_count
+        .context("spreadsheet is missing sheet count")?;
+
+    let sheets = match &options.sheets {
+        CsvSheets::Selected(sheets) => {
+            if let Some(sheet) = sheets.iter().find(|sheet| **sheet > sheet_count) {
+                return Err(ConvertError::InvalidSheet {
+                    sheet: *sheet,
+                    sheet_count,
+                }
+                .into());
+            }
+
+            sheets.clone()
+        }
+        CsvSheets::All => (1..=sheet_count).collect(),
+    };
+
+    let out_url = temp_out.doc_url()?;
+
+    sheets
+        .into_iter()
+        .map(|sheet| {
+            let filter_options = options.filter_options(sheet);
+
+            if !doc.save_as(&out_url, "csv", Some(&filter_options))? {
+                return Err(anyhow!("failed to export sheet {sheet}"));
+            }
+
+            let bytes = std::fs::read(&temp_out.path).context("failed to read temp out file")?;
+            Ok((sheet, bytes))
+        })
+        .collect()
+}
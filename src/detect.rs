@@ -0,0 +1,306 @@
+use crate::{error::HttpError, mhtml};
+use axum::http::StatusCode;
+use clap::ValueEnum;
+use serde_json::json;
+use std::io::{Cursor, Read};
+use thiserror::Error;
+use zip::ZipArchive;
+
+/// Maximum size of a package entry read while detecting the format
+const MAX_ENTRY_SIZE: u64 = 64 * 1024;
+
+/// Format detected from the content of an upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectedFormat {
+    Pdf,
+    /// OOXML word processing document
+    Docx,
+    /// OOXML spreadsheet
+    Xlsx,
+    /// OOXML presentation
+    Pptx,
+    /// ODF text document
+    Odt,
+    /// ODF spreadsheet
+    Ods,
+    /// ODF presentation
+    Odp,
+    /// ODF drawing
+    Odg,
+    /// OLE compound file (Legacy office formats and encrypted OOXML documents)
+    Ole,
+    Rtf,
+    Html,
+    /// MIME web archive
+    Mhtml,
+    Png,
+    Jpeg,
+    Gif,
+}
+
+/// Every detectable format, used to determine the extensions that are known
+const FORMATS: &[DetectedFormat] = &[
+    DetectedFormat::Pdf,
+    DetectedFormat::Docx,
+    DetectedFormat::Xlsx,
+    DetectedFormat::Pptx,
+    DetectedFormat::Odt,
+    DetectedFormat::Ods,
+    DetectedFormat::Odp,
+    DetectedFormat::Odg,
+    DetectedFormat::Ole,
+    DetectedFormat::Rtf,
+    DetectedFormat::Html,
+    DetectedFormat::Mhtml,
+    DetectedFormat::Png,
+    DetectedFormat::Jpeg,
+    DetectedFormat::Gif,
+];
+
+impl DetectedFormat {
+    /// Name of the format
+    pub fn name(&self) -> &'static str {
+        match self {
+            DetectedFormat::Pdf => "pdf",
+            DetectedFormat::Docx => "docx",
+            DetectedFormat::Xlsx => "xlsx",
+            DetectedFormat::Pptx => "pptx",
+            DetectedFormat::Odt => "odt",
+            DetectedFormat::Ods => "ods",
+            DetectedFormat::Odp => "odp",
+            DetectedFormat::Odg => "odg",
+            DetectedFormat::Ole => "ole",
+            DetectedFormat::Rtf => "rtf",
+            DetectedFormat::Html => "html",
+            DetectedFormat::Mhtml => "mhtml",
+            DetectedFormat::Png => "png",
+            DetectedFormat::Jpeg => "jpeg",
+            DetectedFormat::Gif => "gif",
+        }
+    }
+
+    /// Extensions files of the format are expected to have, the first
+    /// extension is suggested for files with a mismatched extension
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            DetectedFormat::Pdf => &["pdf"],
+            DetectedFormat::Docx => &["docx", "docm", "dotx", "dotm"],
+            DetectedFormat::Xlsx => &["xlsx", "xlsm", "xltx", "xltm"],
+            DetectedFormat::Pptx => &["pptx", "pptm", "potx", "potm", "ppsx", "ppsm"],
+            DetectedFormat::Odt => &["odt", "ott"],
+            DetectedFormat::Ods => &["ods", "ots"],
+            DetectedFormat::Odp => &["odp", "otp"],
+            DetectedFormat::Odg => &["odg", "otg"],
+            // Encrypted OOXML documents are also stored in compound files
+            DetectedFormat::Ole => &[
+                "doc", "dot", "xls", "xlt", "xlw", "ppt", "pot", "pps", "msg", "vsd", "pub", "wps",
+                "docx", "docm", "xlsx", "xlsm", "pptx", "pptm",
+            ],
+            // Word and Excel commonly save RTF and HTML with their own extensions
+            DetectedFormat::Rtf => &["rtf", "doc"],
+            DetectedFormat::Html => &["html", "htm", "xhtml", "doc", "xls"],
+            DetectedFormat::Mhtml => &["mht", "mhtml", "doc", "xls"],
+            DetectedFormat::Png => &["png"],
+            DetectedFormat::Jpeg => &["jpg", "jpeg"],
+            DetectedFormat::Gif => &["gif"],
+        }
+    }
+}
+
+/// Extensions to retry loading a document as when office could not detect
+/// its format, ranked from the format detected from its content `bytes`.
+/// Office picks its import filter from the extension when the content alone
+/// isn't enough, one extension is provided for each likely filter
+pub fn load_candidates(bytes: &[u8]) -> &'static [&'static str] {
+    let Some(format) = detect(bytes) else {
+        // Content that isn't recognized is most often delimited text
+        return &["txt", "csv"];
+    };
+
+    match format {
+        // Compound files hold the legacy formats of every office application
+        DetectedFormat::Ole => &["doc", "xls", "ppt", "vsd", "pub"],
+        // Excel commonly saves spreadsheets as HTML
+        DetectedFormat::Html => &["html", "xls"],
+        DetectedFormat::Mhtml => &["mht"],
+        format => &format.extensions()[..1],
+    }
+}
+
+/// Detects the format of a document from its content `bytes`, [None] when
+/// the format could not be confidently detected
+pub fn detect(bytes: &[u8]) -> Option<DetectedFormat> {
+    const OLE_MAGIC: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+    if bytes.starts_with(b"%PDF-") {
+        return Some(DetectedFormat::Pdf);
+    }
+
+    if bytes.starts_with(b"PK\x03\x04") {
+        return detect_package(bytes);
+    }
+
+    if bytes.starts_with(OLE_MAGIC) {
+        return Some(DetectedFormat::Ole);
+    }
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(DetectedFormat::Png);
+    }
+
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(DetectedFormat::Jpeg);
+    }
+
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some(DetectedFormat::Gif);
+    }
+
+    // Text formats may start with a byte order mark or whitespace
+    let text = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let start = text
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(text.len());
+    let text = &text[start..];
+
+    if text.starts_with(b"{\\rtf") {
+        return Some(DetectedFormat::Rtf);
+    }
+
+    let start = &text[..text.len().min(64)];
+    let start = String::from_utf8_lossy(start).to_ascii_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        return Some(DetectedFormat::Html);
+    }
+
+    if mhtml::is_mhtml(text) {
+        return Some(DetectedFormat::Mhtml);
+    }
+
+    None
+}
+
+/// Detects the format of a zip package from its content types (OOXML)
+/// or mimetype (ODF)
+fn detect_package(bytes: &[u8]) -> Option<DetectedFormat> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).ok()?;
+
+    if let Some(mimetype) = read_entry(&mut archive, "mimetype") {
+        return match mimetype
+            .trim()
+            .strip_prefix("application/vnd.oasis.opendocument.")?
+        {
+            "text" | "text-template" => Some(DetectedFormat::Odt),
+            "spreadsheet" | "spreadsheet-template" => Some(DetectedFormat::Ods),
+            "presentation" | "presentation-template" => Some(DetectedFormat::Odp),
+            "graphics" | "graphics-template" => Some(DetectedFormat::Odg),
+            _ => None,
+        };
+    }
+
+    let content_types = read_entry(&mut archive, "[Content_Types].xml")?;
+
+    if content_types.contains("wordprocessingml") {
+        Some(DetectedFormat::Docx)
+    } else if content_types.contains("spreadsheetml") {
+        Some(DetectedFormat::Xlsx)
+    } else if content_types.contains("presentationml") {
+        Some(DetectedFormat::Pptx)
+    } else {
+        None
+    }
+}
+
+/// Reads a small text entry from the package `archive`
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Option<String> {
+    let entry = archive.by_name(name).ok()?;
+
+    let mut value = String::new();
+    entry.take(MAX_ENTRY_SIZE).read_to_string(&mut value).ok()?;
+
+    Some(value)
+}
+
+/// Provides the lowercase extension of the `file_name`
+fn extension(file_name: &str) -> Option<String> {
+    let (_, extension) = file_name.rsplit_once('.')?;
+    Some(extension.to_ascii_lowercase())
+}
+
+/// Policy for uploads with content that doesn't match their extension
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MismatchPolicy {
+    /// Convert without checking the content
+    #[default]
+    Ignore,
+    /// Convert but report the mismatch in the "X-Format-Mismatch" header
+    Warn,
+    /// Reject the upload naming the detected format
+    Reject,
+}
+
+/// Upload content did not match the format claimed by its extension
+#[derive(Debug, Error)]
+#[error(
+    "file claims to be {claimed} but the content is {}, try the .{} extension",
+    detected.name(),
+    detected.extensions()[0]
+)]
+pub struct FormatMismatch {
+    /// Extension of the uploaded file name
+    pub claimed: String,
+    /// Format detected from the content
+    pub detected: DetectedFormat,
+}
+
+impl FormatMismatch {
+    /// Value of the "X-Format-Mismatch" header used to warn about the mismatch
+    pub fn header_value(&self) -> String {
+        format!(
+            "claimed={}; detected={}; suggested={}",
+            self.claimed,
+            self.detected.name(),
+            self.detected.extensions()[0]
+        )
+    }
+}
+
+impl HttpError for FormatMismatch {
+    fn status(&self) -> StatusCode {
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("format_mismatch")
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        Some(json!({
+            "claimed": self.claimed,
+            "detected": self.detected.name(),
+            "suggested_extension": self.detected.extensions()[0],
+        }))
+    }
+}
+
+/// Checks the content `bytes` match the format claimed by the extension of
+/// the `file_name`. Files without a known extension, or with content that
+/// could not be detected, are not checked
+pub fn check_extension(file_name: Option<&str>, bytes: &[u8]) -> Option<FormatMismatch> {
+    let claimed = extension(file_name?)?;
+
+    let is_known = FORMATS
+        .iter()
+        .any(|format| format.extensions().contains(&claimed.as_str()));
+    if !is_known {
+        return None;
+    }
+
+    let detected = detect(bytes)?;
+    if detected.extensions().contains(&claimed.as_str()) {
+        return None;
+    }
+
+    Some(FormatMismatch { claimed, detected })
+}
@@ -0,0 +1,61 @@
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Function switching logging to debug logging when `true` and back to the
+/// configured filter when `false`
+type ApplyLogging = dyn Fn(bool) -> anyhow::Result<()> + Send + Sync;
+
+/// Toggles debug logging at runtime, so production issues can be
+/// investigated without restarting and losing the problematic state
+#[derive(Clone)]
+pub struct LogToggle {
+    /// Whether debug logging is enabled
+    enabled: Arc<AtomicBool>,
+    /// Applies the logging to the subscriber
+    apply: Arc<ApplyLogging>,
+}
+
+impl LogToggle {
+    /// Creates a toggle applying changes to the logging with `apply`
+    pub fn new<F>(apply: F) -> Self
+    where
+        F: Fn(bool) -> anyhow::Result<()> + Send + Sync + 'static,
+    {
+        Self {
+            enabled: Arc::new(AtomicBool::new(false)),
+            apply: Arc::new(apply),
+        }
+    }
+
+    /// Provides whether debug logging is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Enables or disables debug logging
+    pub fn set(&self, enabled: bool) -> anyhow::Result<()> {
+        (self.apply)(enabled)?;
+        self.enabled.store(enabled, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Switches debug logging, providing whether it is now enabled
+    pub fn toggle(&self) -> anyhow::Result<bool> {
+        let enabled = !self.is_enabled();
+        self.set(enabled)?;
+        Ok(enabled)
+    }
+}
+
+impl Debug for LogToggle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LogToggle")
+            .field("enabled", &self.is_enabled())
+            .finish_non_exhaustive()
+    }
+}
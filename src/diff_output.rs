@@ -0,0 +1,278 @@
+use anyhow::{anyhow, Context};
+use clap::Args;
+use libreofficekit::Office;
+use office_convert_server::{
+    convert,
+    tempfiles::{random_id, TempFile},
+    visual_diff::{self, Raster},
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    env::temp_dir,
+    path::{Path, PathBuf},
+};
+
+/// Arguments for the diff-output subcommand
+#[derive(Args, Debug)]
+pub struct DiffOutputArgs {
+    /// Previously converted PDF (i.e from the current LibreOffice version)
+    old: PathBuf,
+
+    /// Newly converted PDF to compare against the old PDF
+    new: PathBuf,
+
+    /// Difference between the channels of a pixel ignored as rendering noise
+    /// (0-255), defaults to 16
+    #[arg(long, default_value_t = 16)]
+    tolerance: u8,
+
+    /// Percentage of changed pixels above which a page is reported as
+    /// different, defaults to 0.1
+    #[arg(long, default_value_t = 0.1)]
+    threshold: f64,
+
+    /// Directory to write images highlighting the changed pixels of each
+    /// different page to
+    #[arg(long)]
+    diff_dir: Option<PathBuf>,
+
+    /// Print the report as JSON and exit with a failure when any page differs
+    #[arg(long)]
+    ci: bool,
+}
+
+/// Outcome of comparing a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum PageStatus {
+    /// Page renders the same within the threshold
+    Same,
+    /// Page renders differently
+    Different,
+    /// Page only exists in the new PDF
+    Added,
+    /// Page only exists in the old PDF
+    Removed,
+}
+
+/// Comparison of a single page in the report
+#[derive(Debug, Serialize)]
+pub(crate) struct PageReport {
+    /// Page number (Starting at 1)
+    pub(crate) page: usize,
+    /// Outcome of comparing the page
+    pub(crate) status: PageStatus,
+    /// Percentage of the pixels that changed
+    changed_percent: f64,
+    /// Whether the page was rendered at a different size
+    size_changed: bool,
+    /// Image highlighting the changed pixels when written
+    diff_image: Option<PathBuf>,
+}
+
+/// Report of comparing two PDFs
+#[derive(Debug, Serialize)]
+struct DiffReport {
+    old: PathBuf,
+    new: PathBuf,
+    /// Number of pages in the old PDF
+    old_pages: usize,
+    /// Number of pages in the new PDF
+    new_pages: usize,
+    /// Whether every page renders the same
+    ok: bool,
+    /// Comparison of each page
+    pages: Vec<PageReport>,
+}
+
+/// Renders the pages of the `old` and `new` PDFs using the office install at
+/// `office_path` and reports the pages that render differently
+pub async fn run(args: DiffOutputArgs, office_path: PathBuf) -> anyhow::Result<()> {
+    tokio::task::spawn_blocking(move || run_blocking(args, office_path))
+        .await
+        .context("diff failed")?
+}
+
+fn run_blocking(args: DiffOutputArgs, office_path: PathBuf) -> anyhow::Result<()> {
+    let office = Office::new(&office_path).context("failed to create office instance")?;
+
+    let old = render_pages(&office, &args.old)?;
+    let new = render_pages(&office, &args.new)?;
+
+    let pages = compare_pages(
+        &old,
+        &new,
+        args.tolerance,
+        args.threshold,
+        args.diff_dir.as_deref(),
+    )?;
+
+    let different = pages
+        .iter()
+        .filter(|page| page.status != PageStatus::Same)
+        .count();
+
+    let report = DiffReport {
+        old: args.old,
+        new: args.new,
+        old_pages: old.len(),
+        new_pages: new.len(),
+        ok: different == 0,
+        pages,
+    };
+
+    if args.ci {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+
+        if !report.ok {
+            return Err(anyhow!("{different} pages render differently"));
+        }
+
+        return Ok(());
+    }
+
+    for page in &report.pages {
+        let status = match page.status {
+            PageStatus::Same => "SAME",
+            PageStatus::Different => "DIFF",
+            PageStatus::Added => "ADDED",
+            PageStatus::Removed => "REMOVED",
+        };
+
+        print!(
+            "{status:<7} page {:<4} {:.3}% changed",
+            page.page, page.changed_percent
+        );
+        if page.size_changed {
+            print!(", size changed");
+        }
+        if let Some(image) = page.diff_image.as_deref() {
+            print!(" ({})", image.display());
+        }
+        println!();
+    }
+
+    println!();
+    println!(
+        "{} pages compared, {different} different",
+        report.pages.len()
+    );
+
+    Ok(())
+}
+
+/// Compares the `old` and `new` renderings of each page, pages with more
+/// than `threshold` percent of their pixels changed are different. Images
+/// of the different pages are written to the `diff_dir` when provided
+pub(crate) fn compare_pages(
+    old: &[Raster],
+    new: &[Raster],
+    tolerance: u8,
+    threshold: f64,
+    diff_dir: Option<&Path>,
+) -> anyhow::Result<Vec<PageReport>> {
+    if let Some(diff_dir) = diff_dir {
+        std::fs::create_dir_all(diff_dir)
+            .with_context(|| format!("failed to create {}", diff_dir.display()))?;
+    }
+
+    let mut pages = Vec::new();
+
+    for index in 0..old.len().max(new.len()) {
+        let page = index + 1;
+
+        let (old_page, new_page) = match (old.get(index), new.get(index)) {
+            (Some(old_page), Some(new_page)) => (old_page, new_page),
+            (old_page, _) => {
+                pages.push(PageReport {
+                    page,
+                    status: match old_page {
+                        Some(_) => PageStatus::Removed,
+                        None => PageStatus::Added,
+                    },
+                    changed_percent: 100.0,
+                    size_changed: true,
+                    diff_image: None,
+                });
+                continue;
+            }
+        };
+
+        let comparison = visual_diff::compare(old_page, new_page, tolerance);
+        let changed_percent = comparison.changed_percent();
+        let is_different = comparison.size_changed || changed_percent > threshold;
+
+        let diff_image = match (diff_dir, is_different) {
+            (Some(diff_dir), true) => {
+                let path = diff_dir.join(format!("page-{page}.png"));
+                std::fs::write(&path, comparison.image.encode_png()?)
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+                Some(path)
+            }
+            _ => None,
+        };
+
+        pages.push(PageReport {
+            page,
+            status: if is_different {
+                PageStatus::Different
+            } else {
+                PageStatus::Same
+            },
+            changed_percent,
+            size_changed: comparison.size_changed,
+            diff_image,
+        });
+    }
+
+    Ok(pages)
+}
+
+/// Renders each page of the PDF at `path` to a raster
+pub(crate) fn render_pages(office: &Office, path: &Path) -> anyhow::Result<Vec<Raster>> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    if !bytes.starts_with(b"%PDF") {
+        return Err(anyhow!("{} is not a PDF", path.display()));
+    }
+
+    let random_id = random_id();
+    let tmp_dir = temp_dir();
+
+    // Office picks the PDF import from the extension
+    let input = TempFile {
+        path: tmp_dir.join(format!("lo_native_diff_{random_id}.pdf")),
+    };
+    std::fs::write(&input.path, &bytes).context("failed to write input")?;
+
+    let mut doc = office
+        .document_load_with_options(&input.doc_url()?, "InteractionHandler=0,Batch=1")
+        .with_context(|| format!("failed to load {}", path.display()))?;
+
+    let page_count = convert::pdf_page_count(&bytes).max(1);
+    let mut pages = Vec::with_capacity(page_count);
+
+    for page in 1..=page_count {
+        let output = TempFile {
+            path: tmp_dir.join(format!("lo_native_diff_{random_id}_{page}.png")),
+        };
+
+        let filter_options = json!({
+            "PageRange": { "type": "string", "value": page.to_string() }
+        })
+        .to_string();
+
+        if !doc.save_as(&output.doc_url()?, "png", Some(&filter_options))? {
+            return Err(anyhow!(
+                "failed to render page {page} of {}",
+                path.display()
+            ));
+        }
+
+        let image = std::fs::read(&output.path).context("failed to read rendered page")?;
+        pages.push(Raster::decode_png(&image)?);
+    }
+
+    Ok(pages)
+}
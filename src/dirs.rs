@@ -0,0 +1,101 @@
+use crate::tempfiles::random_id;
+use anyhow::{anyhow, Context};
+use std::path::Path;
+
+/// Ensures the directory at `path` exists and files can be written to it,
+/// `name` describes the directory and `hint` how to change it in the error
+pub fn ensure_writable(path: &Path, name: &str, hint: &str) -> anyhow::Result<()> {
+    std::fs::create_dir_all(path)
+        .with_context(|| format!("{name} {} could not be created", path.display()))?;
+
+    let probe = path.join(format!("lo_native_probe_{}", random_id()));
+
+    std::fs::write(&probe, b"probe")
+        .map_err(|err| anyhow!("{name} {} is not writable ({err}), {hint}", path.display()))?;
+
+    _ = std::fs::remove_file(&probe);
+
+    Ok(())
+}
+
+/// Ensures files within the directory at `path` can be executed, hardened
+/// containers commonly mount directories like /tmp as noexec
+#[cfg(unix)]
+pub fn ensure_executable(path: &Path, name: &str, hint: &str) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let probe = path.join(format!("lo_native_probe_{}.sh", random_id()));
+
+    std::fs::write(&probe, b"#!/bin/sh\nexit 0\n")
+        .and_then(|_| std::fs::set_permissions(&probe, std::fs::Permissions::from_mode(0o700)))
+        .with_context(|| format!("failed to create probe in {name} {}", path.display()))?;
+
+    let result = std::process::Command::new(&probe).status();
+
+    _ = std::fs::remove_file(&probe);
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => Err(anyhow!(
+            "{name} {} does not allow executing files (mounted noexec?), {hint}",
+            path.display()
+        )),
+        // Other failures (i.e missing /bin/sh) don't indicate a noexec mount
+        Err(_) => Ok(()),
+    }
+}
+
+/// Executable checks are only required on unix platforms
+#[cfg(not(unix))]
+pub fn ensure_executable(_path: &Path, _name: &str, _hint: &str) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// Space available on the filesystem of a directory
+#[derive(Debug, Clone, Copy)]
+pub struct DiskSpace {
+    /// Bytes available to the server
+    pub available_bytes: u64,
+    /// Total size of the filesystem in bytes
+    pub total_bytes: u64,
+}
+
+impl DiskSpace {
+    /// Percentage of the filesystem available to the server
+    pub fn available_percent(&self) -> u64 {
+        (self.available_bytes * 100)
+            .checked_div(self.total_bytes)
+            .unwrap_or(100)
+    }
+}
+
+/// Provides the space available on the filesystem containing `path`
+#[cfg(unix)]
+pub fn disk_space(path: &Path) -> Option<DiskSpace> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: path is nul terminated and stat is initialized when statvfs succeeds
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+
+        stat.assume_init()
+    };
+
+    let block_size = stat.f_frsize as u64;
+
+    Some(DiskSpace {
+        available_bytes: stat.f_bavail as u64 * block_size,
+        total_bytes: stat.f_blocks as u64 * block_size,
+    })
+}
+
+/// Disk space is only reported on unix platforms
+#[cfg(not(unix))]
+pub fn disk_space(_path: &Path) -> Option<DiskSpace> {
+    None
+}
@@ -0,0 +1,194 @@
+use crate::error::HttpError;
+use axum::http::StatusCode;
+use serde_json::json;
+use std::io::{Cursor, Read};
+use thiserror::Error;
+use zip::ZipArchive;
+
+/// Embedded packages larger than this (uncompressed) are counted but not
+/// inspected for further nesting
+const MAX_NESTED_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Limits on the embedded content of uploaded documents, protecting the
+/// runner from crafted documents with deeply nested objects (OLE within
+/// OLE) or thousands of images
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmbeddedLimits {
+    /// Maximum nesting depth of embedded objects (A document embedding a
+    /// spreadsheet has a depth of 1)
+    pub max_depth: Option<u32>,
+    /// Maximum number of images across the document and its embedded objects
+    pub max_images: Option<u64>,
+}
+
+impl EmbeddedLimits {
+    /// Whether any limits are configured
+    pub fn is_enabled(&self) -> bool {
+        self.max_depth.is_some() || self.max_images.is_some()
+    }
+}
+
+/// Embedded content found within a document
+#[derive(Debug, Default)]
+pub struct EmbeddedSummary {
+    /// Deepest nesting of embedded objects
+    pub depth: u32,
+    /// Number of embedded objects
+    pub objects: u64,
+    /// Number of images
+    pub images: u64,
+}
+
+/// Document exceeded one of the [EmbeddedLimits]
+#[derive(Debug, Error)]
+pub enum EmbeddedLimitError {
+    /// Embedded objects were nested too deeply
+    #[error("document embedded objects are nested deeper than the limit of {limit}")]
+    Depth { limit: u32 },
+
+    /// Document contained too many images
+    #[error("document contains {count} images, more than the limit of {limit}")]
+    Images { count: u64, limit: u64 },
+}
+
+impl HttpError for EmbeddedLimitError {
+    fn status(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("embedded_limit")
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        Some(match self {
+            EmbeddedLimitError::Depth { limit } => json!({
+                "limit": "depth",
+                "max": limit,
+            }),
+            EmbeddedLimitError::Images { count, limit } => json!({
+                "limit": "images",
+                "max": limit,
+                "count": count,
+            }),
+        })
+    }
+}
+
+/// Checks the embedded content of the document `bytes` against the `limits`.
+///
+/// Only zip based packages (OOXML and ODF) are inspected, embedded packages
+/// are inspected recursively while binary (OLE) objects count as a single
+/// level of nesting. Documents that are not valid packages are left for the
+/// backend to reject
+pub fn check(bytes: &[u8], limits: &EmbeddedLimits) -> Result<EmbeddedSummary, EmbeddedLimitError> {
+    let mut summary = EmbeddedSummary::default();
+
+    if limits.is_enabled() {
+        inspect_package(bytes, 0, limits, &mut summary)?;
+    }
+
+    Ok(summary)
+}
+
+/// Inspects the package `bytes` embedded at `depth`, adding its contents
+/// to the `summary`
+fn inspect_package(
+    bytes: &[u8],
+    depth: u32,
+    limits: &EmbeddedLimits,
+    summary: &mut EmbeddedSummary,
+) -> Result<(), EmbeddedLimitError> {
+    let Ok(mut archive) = ZipArchive::new(Cursor::new(bytes)) else {
+        return Ok(());
+    };
+
+    for index in 0..archive.len() {
+        let Ok(mut entry) = archive.by_index(index) else {
+            continue;
+        };
+
+        if entry.is_dir() {
+            continue;
+        }
+
+        let name = entry.name().to_string();
+
+        if is_image(&name) {
+            summary.images += 1;
+
+            if let Some(limit) = limits.max_images.filter(|limit| summary.images > *limit) {
+                return Err(EmbeddedLimitError::Images {
+                    count: summary.images,
+                    limit,
+                });
+            }
+
+            continue;
+        }
+
+        let Some(object_depth) = object_depth(&name) else {
+            continue;
+        };
+
+        // ODF stores nested objects as sub directories, only count each object once
+        let is_object_root = name.ends_with("/content.xml") || !name.contains('/');
+        if is_object_root || is_ooxml_embedding(&name) {
+            summary.objects += 1;
+        }
+
+        let object_depth = depth + object_depth;
+        summary.depth = summary.depth.max(object_depth);
+
+        if let Some(limit) = limits.max_depth.filter(|limit| object_depth > *limit) {
+            return Err(EmbeddedLimitError::Depth { limit });
+        }
+
+        // Embedded OOXML packages can contain further embedded objects
+        if is_ooxml_embedding(&name) && entry.size() <= MAX_NESTED_SIZE {
+            let mut nested = Vec::with_capacity(entry.size() as usize);
+            if (&mut entry)
+                .take(MAX_NESTED_SIZE)
+                .read_to_end(&mut nested)
+                .is_ok()
+                && nested.starts_with(b"PK")
+            {
+                inspect_package(&nested, object_depth, limits, summary)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the package entry `name` is an image (OOXML media or ODF pictures)
+fn is_image(name: &str) -> bool {
+    name.split('/')
+        .rev()
+        .nth(1)
+        .is_some_and(|parent| parent == "media" || parent == "Pictures")
+}
+
+/// Whether the package entry `name` is an OOXML embedded object
+/// (i.e "word/embeddings/Microsoft_Excel_Worksheet.xlsx")
+fn is_ooxml_embedding(name: &str) -> bool {
+    name.split('/').rev().nth(1) == Some("embeddings")
+}
+
+/// Provides the nesting depth of the embedded object the package entry
+/// `name` belongs to, [None] when the entry is not part of an object
+fn object_depth(name: &str) -> Option<u32> {
+    if is_ooxml_embedding(name) {
+        return Some(1);
+    }
+
+    // ODF objects are stored in "Object N" entries, nested objects within
+    // sub directories of their parent
+    let depth = name
+        .split('/')
+        .filter_map(|segment| segment.strip_prefix("Object "))
+        .filter(|number| number.parse::<u32>().is_ok())
+        .count() as u32;
+
+    (depth > 0).then_some(depth)
+}
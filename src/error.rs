@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -15,6 +15,9 @@ use tracing::error;
 pub struct DynHttpError {
     /// The dynamic error cause
     inner: Box<dyn HttpError>,
+    /// Tail of the office log output when the error is from a failed
+    /// conversion and the office log is enabled
+    log: Vec<String>,
 }
 
 impl Debug for DynHttpError {
@@ -33,6 +36,35 @@ impl Display for DynHttpError {
 
 impl Error for DynHttpError {}
 
+impl DynHttpError {
+    /// Logs the underlying error
+    pub fn log(&self) {
+        self.inner.log();
+    }
+
+    /// Provides the HTTP [StatusCode] of the underlying error
+    pub fn status(&self) -> StatusCode {
+        self.inner.status()
+    }
+
+    /// Attaches the tail of the office `log` output to the error response
+    pub fn with_log(mut self, log: Vec<String>) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Creates the JSON error body for the underlying error
+    pub fn to_raw(&self) -> RawHttpError {
+        RawHttpError {
+            reason: self.inner.reason(),
+            code: self.inner.code(),
+            details: self.inner.details(),
+            backtrace: self.inner.backtrace(),
+            log: self.log.clone(),
+        }
+    }
+}
+
 /// Handles converting the error into a response (Also logs the error before conversion)
 impl IntoResponse for DynHttpError {
     fn into_response(self) -> Response {
@@ -40,13 +72,11 @@ impl IntoResponse for DynHttpError {
         self.inner.log();
 
         // Create the response body
-        let body = Json(RawHttpError {
-            reason: self.inner.reason(),
-            backtrace: self.inner.backtrace(),
-        });
+        let body = Json(self.to_raw());
         let status = self.inner.status();
+        let headers = self.inner.headers();
 
-        (status, body).into_response()
+        (status, headers, body).into_response()
     }
 }
 
@@ -69,6 +99,24 @@ pub trait HttpError: Error + Send + Sync + 'static {
         self.to_string()
     }
 
+    /// Provides additional headers to include in the error response
+    /// (i.e "Retry-After")
+    fn headers(&self) -> HeaderMap {
+        HeaderMap::new()
+    }
+
+    /// Provides a stable machine readable code identifying the error for
+    /// clients to make decisions from (i.e "encrypted", "corrupted")
+    fn code(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Provides structured details about the error for clients (i.e the
+    /// limit that was exceeded)
+    fn details(&self) -> Option<serde_json::Value> {
+        None
+    }
+
     /// Provides the full type name for the actual error type thats been
     /// erased by dynamic typing (For better error source clarity)
     fn type_name(&self) -> &str {
@@ -109,6 +157,7 @@ impl From<anyhow::Error> for DynHttpError {
     fn from(value: anyhow::Error) -> Self {
         DynHttpError {
             inner: Box::new(AnyhowHttpError(value)),
+            log: Vec::new(),
         }
     }
 }
@@ -121,6 +170,7 @@ where
     fn from(value: E) -> Self {
         DynHttpError {
             inner: Box::new(value),
+            log: Vec::new(),
         }
     }
 }
@@ -130,5 +180,10 @@ where
 #[serde(rename_all = "camelCase")]
 pub struct RawHttpError {
     pub reason: String,
+    pub code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
     pub backtrace: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub log: Vec<String>,
 }
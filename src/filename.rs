@@ -0,0 +1,88 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Maximum length in bytes of a sanitized file name, file systems limit
+/// names to 255 bytes and outputs are named with an additional suffix
+pub const MAX_FILE_NAME_LEN: usize = 200;
+
+/// Maximum length in bytes of an extension that is kept when a file name
+/// is shortened
+const MAX_EXTENSION_LEN: usize = 16;
+
+/// Sanitizes an uploaded file name so it is safe to use in file names,
+/// headers and manifests. Directories are removed, the name is normalized
+/// (Unicode NFC), characters that aren't safe in file names are replaced,
+/// invisible formatting characters (i.e right-to-left overrides) are removed
+/// and long names are shortened keeping their extension
+pub fn sanitize(file_name: &str) -> Option<String> {
+    // Clients may send the full path of the file
+    let name = file_name.rsplit(['/', '\\']).next()?;
+
+    let name: String = name
+        .nfc()
+        .filter(|char| !is_invisible(*char))
+        .map(|char| match char {
+            '"' | '\\' | '/' | ':' | '*' | '?' | '<' | '>' | '|' => '_',
+            char if char.is_control() => '_',
+            char => char,
+        })
+        .collect();
+
+    // Leading dots hide files and trailing dots or spaces are dropped by windows
+    let name = name.trim_matches(|char: char| char.is_whitespace() || char == '.');
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(shorten(name))
+}
+
+/// Provides the name of an uploaded file without its directories or
+/// extension, sanitized using [sanitize]
+pub fn stem(file_name: &str) -> Option<String> {
+    let name = sanitize(file_name)?;
+
+    match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => Some(stem.to_string()),
+        _ => Some(name),
+    }
+}
+
+/// Shortens the `name` to at most [MAX_FILE_NAME_LEN] bytes, keeping the
+/// extension when it is short enough
+fn shorten(name: &str) -> String {
+    if name.len() <= MAX_FILE_NAME_LEN {
+        return name.to_string();
+    }
+
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && extension.len() <= MAX_EXTENSION_LEN => {
+            (stem, Some(extension))
+        }
+        _ => (name, None),
+    };
+
+    let max_stem = match extension {
+        Some(extension) => MAX_FILE_NAME_LEN - extension.len() - 1,
+        None => MAX_FILE_NAME_LEN,
+    };
+
+    // Cut on a character boundary
+    let mut end = max_stem;
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    match extension {
+        Some(extension) => format!("{}.{extension}", &stem[..end]),
+        None => stem[..end].to_string(),
+    }
+}
+
+/// Checks for invisible formatting characters that can disguise a name
+/// (i.e "report\u{202E}fdp.exe" displays as "reportexe.pdf")
+fn is_invisible(char: char) -> bool {
+    matches!(
+        char,
+        '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2069}' | '\u{FEFF}'
+    )
+}
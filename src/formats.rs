@@ -0,0 +1,257 @@
+use crate::{convert, error::HttpError};
+use axum::http::StatusCode;
+use libreofficekit::{DocumentType, FilterTypes};
+use serde::Serialize;
+use serde_json::json;
+use thiserror::Error;
+
+/// Family of documents a file format belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FormatFamily {
+    Text,
+    Spreadsheet,
+    Presentation,
+    Graphics,
+    Other,
+}
+
+impl FormatFamily {
+    /// All families in the order they are reported
+    const ALL: [FormatFamily; 5] = [
+        FormatFamily::Text,
+        FormatFamily::Spreadsheet,
+        FormatFamily::Presentation,
+        FormatFamily::Graphics,
+        FormatFamily::Other,
+    ];
+
+    /// Determines the family of a filter type from the office module
+    /// prefix of its name (i.e "calc_MS_Excel_97")
+    fn from_filter_name(name: &str) -> Self {
+        let module = name.split('_').next().unwrap_or_default();
+
+        match module {
+            "writer" | "writer8" | "writerweb8" | "writerglobal8" | "writerweb"
+            | "writerglobal" => FormatFamily::Text,
+            "calc" | "calc8" => FormatFamily::Spreadsheet,
+            "impress" | "impress8" => FormatFamily::Presentation,
+            "draw" | "draw8" | "graphic" | "image" | "png" | "jpg" | "gif" | "bmp" | "svg"
+            | "tif" | "webp" | "emf" | "wmf" => FormatFamily::Graphics,
+            _ => FormatFamily::Other,
+        }
+    }
+
+    /// Determines the family of a loaded document from its `document_type`
+    pub fn of_document(document_type: DocumentType) -> Self {
+        match document_type {
+            DocumentType::Text => FormatFamily::Text,
+            DocumentType::Spreadsheet => FormatFamily::Spreadsheet,
+            DocumentType::Presentation => FormatFamily::Presentation,
+            DocumentType::Drawing => FormatFamily::Graphics,
+            DocumentType::Other(_) => FormatFamily::Other,
+        }
+    }
+
+    /// File extensions documents of this family can be exported to
+    pub fn export_targets(&self) -> &'static [&'static str] {
+        match self {
+            FormatFamily::Text => &[
+                "pdf", "docx", "doc", "odt", "rtf", "txt", "html", "epub", "png",
+            ],
+            FormatFamily::Spreadsheet => &["pdf", "xlsx", "xls", "ods", "csv", "html", "png"],
+            FormatFamily::Presentation => &["pdf", "pptx", "ppt", "odp", "html", "png", "svg"],
+            FormatFamily::Graphics => &["pdf", "odg", "png", "jpg", "svg"],
+            FormatFamily::Other => &["pdf"],
+        }
+    }
+}
+
+/// Format to export documents to, other than the formats with their own
+/// export options (PDF, CSV and spreadsheet exports)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TargetFormat {
+    /// File extension of the format, also the name of the filter the
+    /// document is saved with
+    pub extension: &'static str,
+    /// Mime type of the format
+    pub content_type: &'static str,
+}
+
+/// Error from a requested format that cannot be exported to
+#[derive(Debug, Error)]
+#[error("unsupported output format {0}")]
+pub struct UnsupportedFormatError(pub String);
+
+impl HttpError for UnsupportedFormatError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("unsupported_output_format")
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        Some(json!({ "format": self.0 }))
+    }
+}
+
+impl TargetFormat {
+    /// Parses the requested output `format`, provides [None] for formats
+    /// handled by their own options. Formats are checked against the filter
+    /// `types` supported by office when the backend reports them
+    pub fn parse(
+        format: Option<&str>,
+        types: Option<&FilterTypes>,
+    ) -> Result<Option<Self>, UnsupportedFormatError> {
+        let Some(format) = format.map(str::to_ascii_lowercase) else {
+            return Ok(None);
+        };
+
+        if matches!(format.as_str(), "pdf" | "csv" | "tsv" | "xlsx" | "ods") {
+            return Ok(None);
+        }
+
+        let Some(extension) = export_target(&format) else {
+            return Err(UnsupportedFormatError(format));
+        };
+
+        let content_type = convert::output_content_type(extension);
+
+        // Backends without a list of filters fail while converting instead
+        if types.is_some_and(|types| types.get_by_mime(content_type).is_none()) {
+            return Err(UnsupportedFormatError(format));
+        }
+
+        Ok(Some(Self {
+            extension,
+            content_type,
+        }))
+    }
+
+    /// Checks if documents of the `family` can be exported to the format
+    pub fn is_target_of(&self, family: FormatFamily) -> bool {
+        family.export_targets().contains(&self.extension)
+    }
+}
+
+/// Checks if documents of any family can be exported to the `extension`
+pub fn is_export_target(extension: &str) -> bool {
+    export_target(extension).is_some()
+}
+
+/// Finds the export target matching the `extension`
+fn export_target(extension: &str) -> Option<&'static str> {
+    FormatFamily::ALL
+        .iter()
+        .flat_map(|family| family.export_targets())
+        .find(|target| **target == extension)
+        .copied()
+}
+
+/// Common file extensions for a `mime` type
+fn extensions(mime: &str) -> &'static [&'static str] {
+    match mime {
+        "application/pdf" => &["pdf"],
+        "application/msword" => &["doc", "dot"],
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => &["docx"],
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.template" => &["dotx"],
+        "application/vnd.oasis.opendocument.text" => &["odt"],
+        "application/vnd.oasis.opendocument.text-template" => &["ott"],
+        "application/rtf" | "text/rtf" => &["rtf"],
+        "text/plain" => &["txt"],
+        "text/html" => &["html", "htm"],
+        "application/epub+zip" => &["epub"],
+        "application/vnd.ms-excel" => &["xls", "xlt"],
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => &["xlsx"],
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.template" => &["xltx"],
+        "application/vnd.oasis.opendocument.spreadsheet" => &["ods"],
+        "application/vnd.oasis.opendocument.spreadsheet-template" => &["ots"],
+        "text/csv" => &["csv"],
+        "application/vnd.ms-powerpoint" => &["ppt", "pps", "pot"],
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation" => &["pptx"],
+        "application/vnd.openxmlformats-officedocument.presentationml.slideshow" => &["ppsx"],
+        "application/vnd.openxmlformats-officedocument.presentationml.template" => &["potx"],
+        "application/vnd.oasis.opendocument.presentation" => &["odp"],
+        "application/vnd.oasis.opendocument.presentation-template" => &["otp"],
+        "application/vnd.oasis.opendocument.graphics" => &["odg"],
+        "application/vnd.oasis.opendocument.graphics-template" => &["otg"],
+        "image/png" => &["png"],
+        "image/jpeg" => &["jpg", "jpeg"],
+        "image/gif" => &["gif"],
+        "image/bmp" => &["bmp"],
+        "image/tiff" => &["tif", "tiff"],
+        "image/webp" => &["webp"],
+        "image/svg+xml" => &["svg"],
+        "image/x-emf" | "image/emf" => &["emf"],
+        "image/x-wmf" | "image/wmf" => &["wmf"],
+        _ => &[],
+    }
+}
+
+/// File formats of a single family
+#[derive(Debug, Serialize)]
+pub struct FormatGroup {
+    /// Family of the formats
+    pub family: FormatFamily,
+    /// Extensions documents of the family can be exported to
+    pub export_targets: &'static [&'static str],
+    /// Formats within the family
+    pub formats: Vec<GroupedFormat>,
+}
+
+/// File format within a [FormatGroup]
+#[derive(Debug, Serialize)]
+pub struct GroupedFormat {
+    /// Name of the file format
+    pub name: String,
+    /// Mime type of the format
+    pub mime: String,
+    /// Common file extensions of the format
+    pub extensions: &'static [&'static str],
+    /// Whether documents of the family can be exported to this format
+    pub export_target: bool,
+}
+
+/// Groups the supported filter `types` by document family, families
+/// without any formats are omitted
+pub fn group(types: &FilterTypes) -> Vec<FormatGroup> {
+    let mut groups: Vec<FormatGroup> = FormatFamily::ALL
+        .into_iter()
+        .map(|family| FormatGroup {
+            family,
+            export_targets: family.export_targets(),
+            formats: Vec::new(),
+        })
+        .collect();
+
+    for (name, value) in &types.values {
+        let family = FormatFamily::from_filter_name(name);
+        let extensions = extensions(&value.media_type);
+        let export_target = extensions
+            .iter()
+            .any(|extension| family.export_targets().contains(extension));
+
+        let group = groups
+            .iter_mut()
+            .find(|group| group.family == family)
+            .expect("all families have a group");
+
+        group.formats.push(GroupedFormat {
+            name: name.to_string(),
+            mime: value.media_type.to_string(),
+            extensions,
+            export_target,
+        });
+    }
+
+    groups.retain(|group| !group.formats.is_empty());
+
+    // Filter types are unordered, sort for a stable response
+    for group in &mut groups {
+        group.formats.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    groups
+}
@@ -0,0 +1,867 @@
+use crate::{
+    convert::{ConvertWarning, ConvertedDocument},
+    error::DynHttpError,
+    pdf_export::{self, PageFormat},
+    priority::{self, PriorityTier},
+    runner::{self, WorkProgress},
+    storage::{LocalStorage, Storage, StoredReader},
+    tempfiles::{random_id, TempFile},
+};
+use anyhow::Context;
+use bytes::Bytes;
+use futures_util::StreamExt;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{watch, OnceCell, Semaphore},
+};
+use tracing::{debug, error};
+
+/// Longest interval between checks for expired jobs
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Key of the lease held by the server sweeping the storage
+const CLEANUP_LEASE: &str = "cleanup.lease";
+
+/// Number of check intervals the cleanup lease is held for without being
+/// renewed, before another server sharing the storage takes over
+const LEASE_INTERVALS: u32 = 3;
+
+/// Weight of the latest job duration in the average job duration
+const AVERAGE_WEIGHT: f64 = 0.2;
+
+/// Maximum number of Ghostscript processes counting or extracting the pages
+/// of job results at once
+const MAX_PAGE_EXTRACTIONS: usize = 2;
+
+/// Maximum time Ghostscript may take to count or extract the pages of a job
+/// result before it is stopped
+const PAGE_EXTRACTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Converts uploads in the background for clients that can't hold a request
+/// open while large documents convert, results are stored and kept until the
+/// result retention period passes so clients can download them later. The
+/// status of a job outlives its result for the status retention period, so
+/// clients polling late learn the result expired rather than the job missing.
+///
+/// Finished jobs are stored along with their result so servers sharing the
+/// storage can report them after the server that ran them is gone
+#[derive(Debug)]
+pub struct JobStore {
+    /// Storage job results and the status of finished jobs are kept in
+    storage: Arc<dyn Storage>,
+    /// Unique ID of the store the cleanup lease is held as
+    instance: String,
+    /// Time the results of finished jobs are kept for
+    pub result_max_age: Duration,
+    /// Time the status of finished jobs is kept for, at least the
+    /// `result_max_age`
+    pub status_max_age: Duration,
+    /// Known jobs by ID
+    jobs: Mutex<HashMap<String, Job>>,
+    /// Exponentially weighted average time finished jobs took, used to
+    /// estimate when queued jobs start
+    average_duration: Mutex<Option<Duration>>,
+    /// Jobs convert one at a time in the order they were submitted, leaving
+    /// the runner queue for synchronous requests
+    slots: Semaphore,
+    /// Result PDFs pages are extracted from by job ID, kept until the job
+    /// expires so each page request doesn't read the result again
+    page_sources: Mutex<HashMap<String, Arc<PageSource>>>,
+    /// Limits the Ghostscript processes extracting pages at once
+    page_slots: Semaphore,
+    /// Notified whenever the state of a job known to this server changes
+    changes: watch::Sender<()>,
+}
+
+/// Result PDF of a job that pages are extracted from
+#[derive(Debug)]
+pub struct PageSource {
+    /// Path of the result PDF on the local disk
+    path: PathBuf,
+    /// Copy of a result from remote storage, removed along with the source
+    _copy: Option<TempFile>,
+    /// Number of pages of the result, counted by the first page request
+    page_count: OnceCell<u64>,
+    /// When the source was created
+    created: Instant,
+}
+
+impl PageSource {
+    /// Path of the result PDF on the local disk
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Job along with when each of its states was reached
+#[derive(Debug)]
+struct Job {
+    status: JobStatus,
+    /// Size of the uploaded file in bytes
+    size: u64,
+    /// Priority tier of the request that submitted the job
+    tier: PriorityTier,
+    created: Instant,
+    started: Option<Instant>,
+    finished: Option<Instant>,
+    /// Progress reported by office while the job converts
+    progress: Arc<WorkProgress>,
+}
+
+/// State of a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// Waiting for earlier jobs
+    Queued,
+    /// Being converted
+    Running,
+    /// Conversion failed
+    Failed,
+    /// Converted, the result can be downloaded
+    Done,
+}
+
+/// Details of a job reported to clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobStatus {
+    /// Unique ID of the job
+    pub id: String,
+    /// Current state of the job
+    pub state: JobState,
+    /// Name of the uploaded file
+    pub file_name: Option<String>,
+    /// When the job was submitted (Seconds since the unix epoch)
+    pub created_at: u64,
+    /// When the job finished (Seconds since the unix epoch)
+    pub finished_at: Option<u64>,
+    /// When the result of the job is removed (Seconds since the unix epoch)
+    pub expires_at: Option<u64>,
+    /// When the status of the job is removed (Seconds since the unix epoch)
+    #[serde(default)]
+    pub status_expires_at: Option<u64>,
+    /// Whether the result was removed, either after expiring or by a client
+    #[serde(default)]
+    pub result_expired: bool,
+    /// Time the job waited before converting in milliseconds
+    pub queued_ms: Option<u64>,
+    /// Time the job took to convert in milliseconds
+    pub duration_ms: Option<u64>,
+    /// Mime type of the result
+    pub content_type: Option<Cow<'static, str>>,
+    /// Size of the result in bytes
+    pub size: Option<u64>,
+    /// SHA-256 hex digest of the result
+    pub sha256: Option<String>,
+    /// Non-fatal issues observed while converting
+    pub warnings: Vec<ConvertWarning>,
+    /// Error code when the job failed
+    pub error_code: Option<Cow<'static, str>>,
+    /// Error reason when the job failed
+    pub error: Option<String>,
+}
+
+/// Job waiting for or holding the conversion slot
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedJob {
+    /// Unique ID of the job
+    pub id: String,
+    /// Either queued or running
+    pub state: JobState,
+    /// Priority tier of the request that submitted the job
+    pub tier: PriorityTier,
+    /// Name of the uploaded file
+    pub file_name: Option<String>,
+    /// Size of the uploaded file in bytes
+    pub size: u64,
+    /// When the job was submitted (Seconds since the unix epoch)
+    pub created_at: u64,
+    /// When the job started converting or is estimated to start based on
+    /// the average job duration (Seconds since the unix epoch), [None] for
+    /// queued jobs before any job has finished
+    pub estimated_start_at: Option<u64>,
+}
+
+/// Snapshot of the jobs waiting for or holding the conversion slot
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueSnapshot {
+    /// Jobs in the order they convert, the running job first
+    pub jobs: Vec<QueuedJob>,
+    /// Average time jobs take to convert in milliseconds, [None] before
+    /// any job has finished
+    pub average_duration_ms: Option<u64>,
+}
+
+impl JobStore {
+    /// Creates a store writing results to `dir` that keeps finished jobs
+    /// and their results for the `max_age`
+    pub fn new(dir: PathBuf, max_age: Duration) -> Self {
+        Self::with_storage(Arc::new(LocalStorage::new(dir)), max_age)
+    }
+
+    /// Creates a store keeping results in the `storage` that keeps finished
+    /// jobs and their results for the `max_age`
+    pub fn with_storage(storage: Arc<dyn Storage>, max_age: Duration) -> Self {
+        Self {
+            storage,
+            instance: random_id(),
+            result_max_age: max_age,
+            status_max_age: max_age,
+            jobs: Default::default(),
+            average_duration: Default::default(),
+            slots: Semaphore::new(1),
+            page_sources: Default::default(),
+            page_slots: Semaphore::new(MAX_PAGE_EXTRACTIONS),
+            changes: watch::Sender::new(()),
+        }
+    }
+
+    /// Keeps the status of finished jobs for the `status_max_age` after their
+    /// result is removed, never less than the result retention
+    pub fn with_status_max_age(mut self, status_max_age: Duration) -> Self {
+        self.status_max_age = status_max_age.max(self.result_max_age);
+        self
+    }
+
+    /// Adds a queued job for the upload named `file_name` of `size` bytes, the
+    /// job keeps the priority tier of the current request
+    pub fn create(&self, file_name: Option<String>, size: u64) -> JobStatus {
+        let status = JobStatus {
+            id: random_id(),
+            state: JobState::Queued,
+            file_name,
+            created_at: unix_secs(),
+            finished_at: None,
+            expires_at: None,
+            status_expires_at: None,
+            result_expired: false,
+            queued_ms: None,
+            duration_ms: None,
+            content_type: None,
+            size: None,
+            sha256: None,
+            warnings: Vec::new(),
+            error_code: None,
+            error: None,
+        };
+
+        self.jobs.lock().insert(
+            status.id.clone(),
+            Job {
+                status: status.clone(),
+                size,
+                tier: priority::current(),
+                created: Instant::now(),
+                started: None,
+                finished: None,
+                progress: Default::default(),
+            },
+        );
+
+        status
+    }
+
+    /// Adds a queued job like [JobStore::create] storing its status so that
+    /// other servers sharing the storage can report the job before it finishes
+    pub async fn submit(&self, file_name: Option<String>, size: u64) -> anyhow::Result<JobStatus> {
+        let status = self.create(file_name, size);
+
+        if let Err(err) = self.write_status(&status.id).await {
+            self.jobs.lock().remove(&status.id);
+            return Err(err);
+        }
+
+        Ok(status)
+    }
+
+    /// Runs the `convert` future for the job with the `id` once earlier jobs
+    /// have finished, storing its result
+    pub async fn run<F>(&self, id: &str, convert: F)
+    where
+        F: Future<Output = Result<ConvertedDocument, DynHttpError>>,
+    {
+        let _slot = self.slots.acquire().await;
+
+        self.update(id, |job| {
+            let now = Instant::now();
+            job.status.state = JobState::Running;
+            job.status.queued_ms = Some(now.duration_since(job.created).as_millis() as u64);
+            job.started = Some(now);
+        });
+
+        if let Err(cause) = self.write_status(id).await {
+            error!(?cause, "failed to store job status");
+        }
+
+        let progress = self.progress(id).unwrap_or_default();
+        let result = match runner::track_progress(progress, convert).await {
+            Ok(converted) => self.write_result(id, &converted).await.map(|_| converted),
+            Err(err) => Err(err),
+        };
+
+        self.update(id, |job| {
+            let now = Instant::now();
+            let finished_at = unix_secs();
+
+            job.status.finished_at = Some(finished_at);
+            job.status.expires_at = Some(finished_at + self.result_max_age.as_secs());
+            job.status.status_expires_at = Some(finished_at + self.status_max_age.as_secs());
+            job.status.duration_ms = job
+                .started
+                .map(|started| now.duration_since(started).as_millis() as u64);
+            job.finished = Some(now);
+
+            if let Some(started) = job.started {
+                let duration = now.duration_since(started);
+                let average = &mut *self.average_duration.lock();
+                *average = Some(match *average {
+                    Some(average) => {
+                        average.mul_f64(1.0 - AVERAGE_WEIGHT) + duration.mul_f64(AVERAGE_WEIGHT)
+                    }
+                    None => duration,
+                });
+            }
+
+            match result {
+                Ok(converted) => {
+                    job.status.state = JobState::Done;
+                    job.status.content_type = Some(Cow::Borrowed(converted.content_type));
+                    job.status.size = Some(converted.bytes.len() as u64);
+                    job.status.sha256 = Some(format!("{:x}", Sha256::digest(&converted.bytes)));
+                    job.status.warnings = converted.warnings;
+                }
+                Err(err) => {
+                    err.log();
+
+                    let raw = err.to_raw();
+                    job.status.state = JobState::Failed;
+                    job.status.error_code = raw.code.map(Cow::Borrowed);
+                    job.status.error = Some(raw.reason);
+                }
+            }
+        });
+
+        if let Err(cause) = self.write_status(id).await {
+            error!(?cause, "failed to store job status");
+        }
+    }
+
+    /// Provides the status of the job with the `id` known to this server
+    pub fn get(&self, id: &str) -> Option<JobStatus> {
+        self.jobs
+            .lock()
+            .get(id)
+            .map(|job| with_expiry(job.status.clone()))
+    }
+
+    /// Provides the status of the job with the `id`, jobs unknown to this
+    /// server are read from the storage when finished by another server
+    pub async fn find(&self, id: &str) -> anyhow::Result<Option<JobStatus>> {
+        if let Some(status) = self.get(id) {
+            return Ok(Some(status));
+        }
+
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+
+        let Some(status) = self
+            .storage
+            .get(&status_key(id))
+            .await
+            .context("failed to read job status")?
+        else {
+            return Ok(None);
+        };
+
+        let status: JobStatus =
+            serde_json::from_slice(&status).context("failed to parse job status")?;
+
+        // Expired jobs are removed by the next check
+        if status_expired(&status) {
+            return Ok(None);
+        }
+
+        Ok(Some(with_expiry(status)))
+    }
+
+    /// Removes the result of the finished job with the `id` ahead of the
+    /// retention period, keeping its status marked with the expired result.
+    /// Provides the updated status, [None] when the job is unknown
+    pub async fn delete_result(&self, id: &str) -> anyhow::Result<Option<JobStatus>> {
+        let Some(mut status) = self.find(id).await? else {
+            return Ok(None);
+        };
+
+        self.page_sources.lock().remove(id);
+        self.storage
+            .delete(id)
+            .await
+            .context("failed to remove job result")?;
+
+        status.result_expired = true;
+
+        if self.get(id).is_some() {
+            self.update(id, |job| job.status.result_expired = true);
+            self.write_status(id).await?;
+        } else {
+            // Jobs finished by other servers are marked in the storage
+            let stored = serde_json::to_vec(&status).context("failed to serialize job status")?;
+            self.storage
+                .put(&status_key(id), Bytes::from(stored))
+                .await?;
+        }
+
+        Ok(Some(status))
+    }
+
+    /// Provides the jobs waiting for or holding the conversion slot in the
+    /// order they convert, along with estimates of when queued jobs start
+    pub fn queue(&self) -> QueueSnapshot {
+        let average = *self.average_duration.lock();
+        let now = Instant::now();
+        let now_secs = unix_secs();
+
+        let mut pending: Vec<QueuedJob> = Vec::new();
+        // Time until the slot is expected to be free, [None] when unknown
+        let mut offset = Some(Duration::ZERO);
+
+        let jobs = self.jobs.lock();
+        let mut active: Vec<&Job> = jobs
+            .values()
+            .filter(|job| matches!(job.status.state, JobState::Queued | JobState::Running))
+            .collect();
+
+        // Jobs wait for the slot in the order they were submitted
+        active.sort_by_key(|job| (job.status.state != JobState::Running, job.created));
+
+        for job in active {
+            let estimated_start_at = match job.started {
+                Some(started) => {
+                    let elapsed = now.duration_since(started);
+                    offset = average.map(|average| average.saturating_sub(elapsed));
+                    Some(now_secs.saturating_sub(elapsed.as_secs()))
+                }
+                None => {
+                    let start = offset.map(|offset| now_secs + offset.as_secs());
+                    offset = offset
+                        .zip(average)
+                        .map(|(offset, average)| offset + average);
+                    start
+                }
+            };
+
+            pending.push(QueuedJob {
+                id: job.status.id.clone(),
+                state: job.status.state,
+                tier: job.tier,
+                file_name: job.status.file_name.clone(),
+                size: job.size,
+                created_at: job.status.created_at,
+                estimated_start_at,
+            });
+        }
+
+        QueueSnapshot {
+            jobs: pending,
+            average_duration_ms: average.map(|average| average.as_millis() as u64),
+        }
+    }
+
+    /// Opens the stored result of the job with the `id`, [None] once the
+    /// result was removed
+    pub async fn open_result(&self, id: &str) -> anyhow::Result<Option<StoredReader>> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+
+        self.storage
+            .open(id)
+            .await
+            .context("failed to open job result")
+    }
+
+    /// Provides the result PDF of the job with the `id` to extract pages from,
+    /// [None] once the result was removed. Results in local storage are read
+    /// in place, others are copied into `work_dir` once per job
+    pub async fn page_source(
+        &self,
+        id: &str,
+        work_dir: &Path,
+    ) -> anyhow::Result<Option<Arc<PageSource>>> {
+        if let Some(source) = self.page_sources.lock().get(id) {
+            return Ok(Some(source.clone()));
+        }
+
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+
+        let source = match self.storage.local_path(id) {
+            Some(path) => {
+                if !tokio::fs::try_exists(&path)
+                    .await
+                    .context("failed to check job result")?
+                {
+                    return Ok(None);
+                }
+
+                PageSource {
+                    path,
+                    _copy: None,
+                    page_count: OnceCell::new(),
+                    created: Instant::now(),
+                }
+            }
+            None => {
+                let Some(mut result) = self.open_result(id).await? else {
+                    return Ok(None);
+                };
+
+                let copy = TempFile {
+                    path: work_dir.join(format!("lo_native_page_{}.pdf", random_id())),
+                };
+
+                let mut file = tokio::fs::File::create(&copy.path)
+                    .await
+                    .context("failed to create page input")?;
+                while let Some(chunk) = result.stream.next().await {
+                    let chunk = chunk.context("failed to read job result")?;
+                    file.write_all(&chunk)
+                        .await
+                        .context("failed to write page input")?;
+                }
+                file.flush().await.context("failed to write page input")?;
+
+                PageSource {
+                    path: copy.path.clone(),
+                    _copy: Some(copy),
+                    page_count: OnceCell::new(),
+                    created: Instant::now(),
+                }
+            }
+        };
+
+        // Concurrent first requests keep the source stored first
+        let source = self
+            .page_sources
+            .lock()
+            .entry(id.to_string())
+            .or_insert_with(|| Arc::new(source))
+            .clone();
+
+        Ok(Some(source))
+    }
+
+    /// Provides the number of pages of the `source`, counted once per job
+    pub async fn page_count(&self, source: &PageSource) -> anyhow::Result<u64> {
+        let page_count = source
+            .page_count
+            .get_or_try_init(|| async {
+                let _permit = self
+                    .page_slots
+                    .acquire()
+                    .await
+                    .context("page extraction closed")?;
+
+                pdf_export::count_pages(&source.path, PAGE_EXTRACTION_TIMEOUT).await
+            })
+            .await?;
+
+        Ok(*page_count)
+    }
+
+    /// Writes the `page` (Starting at 1) of the `source` to `output` in the
+    /// `format`, PNG renders are rendered at the `resolution` (DPI)
+    pub async fn extract_page(
+        &self,
+        source: &PageSource,
+        page: u64,
+        format: PageFormat,
+        resolution: u32,
+        output: &Path,
+    ) -> anyhow::Result<()> {
+        let _permit = self
+            .page_slots
+            .acquire()
+            .await
+            .context("page extraction closed")?;
+
+        pdf_export::extract_page(
+            &source.path,
+            page,
+            format,
+            resolution,
+            output,
+            PAGE_EXTRACTION_TIMEOUT,
+        )
+        .await
+    }
+
+    /// Stores the result of the job with the `id`
+    async fn write_result(
+        &self,
+        id: &str,
+        converted: &ConvertedDocument,
+    ) -> Result<(), DynHttpError> {
+        self.storage
+            .put(id, converted.bytes.clone())
+            .await
+            .context("failed to store job result")?;
+
+        Ok(())
+    }
+
+    /// Stores the current status of the job with the `id`
+    async fn write_status(&self, id: &str) -> anyhow::Result<()> {
+        let Some(status) = self.get(id) else {
+            return Ok(());
+        };
+
+        let status = serde_json::to_vec(&status).context("failed to serialize job status")?;
+        self.storage.put(&status_key(id), Bytes::from(status)).await
+    }
+
+    fn update(&self, id: &str, action: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.jobs.lock().get_mut(id) {
+            action(job);
+        }
+
+        self.changes.send_replace(());
+    }
+
+    /// Subscribes to changes of the state of the jobs known to this server,
+    /// jobs known through the storage are not reported
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    /// Provides the progress of the job with the `id` known to this server
+    pub fn progress(&self, id: &str) -> Option<Arc<WorkProgress>> {
+        self.jobs.lock().get(id).map(|job| job.progress.clone())
+    }
+
+    /// Removes expired jobs at an interval based on the retention forever
+    pub async fn schedule(&self) {
+        loop {
+            tokio::time::sleep(self.check_interval()).await;
+
+            match self.remove_expired().await {
+                Ok(0) => {}
+                Ok(removed) => debug!(removed, "removed expired jobs"),
+                Err(cause) => error!(?cause, "failed to remove expired jobs"),
+            }
+        }
+    }
+
+    fn check_interval(&self) -> Duration {
+        (self.result_max_age / 4).clamp(Duration::from_secs(1), MAX_CHECK_INTERVAL)
+    }
+
+    /// Removes the results of jobs that finished longer than the result
+    /// retention period ago, keeping their status marked with the expired
+    /// result until the status retention period passes too. Results and
+    /// statuses left behind by previous runs of the server (Or other servers
+    /// sharing the storage) are removed once they expire. Returns the number
+    /// of jobs removed
+    ///
+    /// Only the server holding the cleanup lease of the storage removes the
+    /// results left behind, so servers sharing the storage don't all sweep it
+    pub async fn remove_expired(&self) -> anyhow::Result<u64> {
+        let (expired_results, expired): (Vec<String>, Vec<String>) = {
+            let mut jobs = self.jobs.lock();
+            let finished_for = |job: &Job, max_age: Duration| {
+                job.finished
+                    .is_some_and(|finished| finished.elapsed() >= max_age)
+            };
+
+            let expired_results: Vec<String> = jobs
+                .iter()
+                .filter(|(_, job)| {
+                    !job.status.result_expired && finished_for(job, self.result_max_age)
+                })
+                .map(|(id, _)| id.clone())
+                .collect();
+            let expired: Vec<String> = jobs
+                .iter()
+                .filter(|(_, job)| finished_for(job, self.status_max_age))
+                .map(|(id, _)| id.clone())
+                .collect();
+
+            for id in &expired_results {
+                if let Some(job) = jobs.get_mut(id) {
+                    job.status.result_expired = true;
+                }
+            }
+
+            for id in &expired {
+                jobs.remove(id);
+            }
+
+            (expired_results, expired)
+        };
+
+        // Sources of jobs finished by other servers are not known to expire so
+        // they are removed after the result retention period
+        self.page_sources.lock().retain(|id, source| {
+            !expired_results.contains(id)
+                && !expired.contains(id)
+                && source.created.elapsed() < self.result_max_age
+        });
+
+        for id in &expired_results {
+            self.storage
+                .delete(id)
+                .await
+                .context("failed to remove job result")?;
+
+            if let Err(cause) = self.write_status(id).await {
+                error!(?cause, "failed to store job status");
+            }
+        }
+
+        for id in &expired {
+            self.storage
+                .delete(id)
+                .await
+                .context("failed to remove job result")?;
+            self.storage
+                .delete(&status_key(id))
+                .await
+                .context("failed to remove job status")?;
+        }
+
+        if !expired_results.is_empty() {
+            self.changes.send_replace(());
+        }
+
+        let is_leader = self
+            .storage
+            .acquire_lease(
+                CLEANUP_LEASE,
+                &self.instance,
+                self.check_interval() * LEASE_INTERVALS,
+            )
+            .await
+            .context("failed to acquire cleanup lease")?;
+
+        if !is_leader {
+            debug!("skipping job storage sweep, another server holds the cleanup lease");
+            return Ok(expired.len() as u64);
+        }
+
+        let objects = self
+            .storage
+            .list()
+            .await
+            .context("failed to list job results")?;
+
+        for object in objects {
+            if object.key == CLEANUP_LEASE {
+                continue;
+            }
+
+            let is_known = object
+                .key
+                .split('.')
+                .next()
+                .is_some_and(|id| self.jobs.lock().contains_key(id));
+
+            if is_known {
+                continue;
+            }
+
+            let age = object.modified.and_then(|modified| modified.elapsed().ok());
+
+            let expired = if object.key.ends_with(".json") {
+                self.stored_status_expired(&object.key, age).await?
+            } else {
+                age.is_some_and(|age| age >= self.result_max_age)
+            };
+
+            if !expired {
+                continue;
+            }
+
+            self.storage
+                .delete(&object.key)
+                .await
+                .context("failed to remove job result")?;
+        }
+
+        Ok(expired.len() as u64)
+    }
+
+    /// Checks whether the status stored under the `key` of a job unknown to
+    /// this server expired, statuses of finished jobs know when they expire
+    /// while others expire the status retention period after they were
+    /// stored (`age`)
+    async fn stored_status_expired(
+        &self,
+        key: &str,
+        age: Option<Duration>,
+    ) -> anyhow::Result<bool> {
+        let stored = self
+            .storage
+            .get(key)
+            .await
+            .context("failed to read job status")?;
+
+        let status = stored.and_then(|stored| serde_json::from_slice::<JobStatus>(&stored).ok());
+
+        Ok(match status {
+            Some(status) if status.finished_at.is_some() => status_expired(&status),
+            _ => age.is_some_and(|age| age >= self.status_max_age),
+        })
+    }
+}
+
+/// Marks the result of the `status` expired once its expiry passed, results
+/// are only removed by the next check
+fn with_expiry(mut status: JobStatus) -> JobStatus {
+    if status
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= unix_secs())
+    {
+        status.result_expired = true;
+    }
+
+    status
+}
+
+/// Checks whether the `status` of a finished job expired, statuses without
+/// a status expiry expire along with the result
+fn status_expired(status: &JobStatus) -> bool {
+    status
+        .status_expires_at
+        .or(status.expires_at)
+        .is_some_and(|expires_at| expires_at <= unix_secs())
+}
+
+/// Key the status of the finished job with the `id` is stored under
+fn status_key(id: &str) -> String {
+    format!("{id}.json")
+}
+
+/// Checks the `id` could be the ID of a job
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|char| char.is_ascii_alphanumeric())
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,43 @@
+pub mod alerts;
+pub mod attestation;
+pub mod auth;
+pub mod cache;
+pub mod config;
+pub mod convert;
+pub mod csv;
+pub mod detect;
+pub mod diagnostics;
+pub mod dirs;
+pub mod embedded;
+pub mod error;
+pub mod filename;
+pub mod formats;
+pub mod jobs;
+pub mod maintenance;
+pub mod memory;
+pub mod metrics;
+pub mod mhtml;
+pub mod odf;
+pub mod office_errors;
+pub mod pdf_export;
+pub mod pipeline;
+pub mod presign;
+pub mod priority;
+pub mod profile;
+pub mod proxy;
+pub mod quarantine;
+pub mod redact;
+pub mod retention;
+pub mod runner;
+pub mod server;
+pub mod service;
+pub mod settings;
+pub mod signing;
+pub mod soffice;
+pub mod storage;
+pub mod stub;
+pub mod tempfiles;
+pub mod throttle;
+pub mod version;
+pub mod visual_diff;
+pub mod workbook;
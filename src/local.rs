@@ -0,0 +1,279 @@
+use anyhow::{anyhow, Context};
+use clap::Args;
+use office_convert_server::{
+    config::{duration_arg, Backend, OfficeLogLevel},
+    convert::{ConvertBackend, ConvertOptions, DocumentInput, LibreOfficeBackend},
+    formats::TargetFormat,
+    runner::RunnerActivity,
+    soffice::SofficeBackend,
+    stub::StubBackend,
+};
+use std::{
+    collections::HashMap,
+    env::temp_dir,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// Arguments for the convert subcommand
+#[derive(Args, Debug)]
+pub struct ConvertArgs {
+    /// File to convert
+    file: PathBuf,
+
+    /// Path to write the converted file to, defaults to the file with the
+    /// extension of the output format
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+
+    #[command(flatten)]
+    converter: ConverterArgs,
+}
+
+/// Arguments for the watch subcommand
+#[derive(Args, Debug)]
+pub struct WatchArgs {
+    /// Directory to watch for files to convert
+    #[arg(long)]
+    dir: PathBuf,
+
+    /// Directory converted files are written to, the output of "letter.docx"
+    /// is "letter.pdf"
+    #[arg(long)]
+    output_dir: PathBuf,
+
+    /// Interval to check the directory for new and changed files at (i.e
+    /// "2s", "1m"), files are converted once unchanged between two checks
+    #[arg(long, value_parser = duration_arg, default_value = "2s")]
+    interval: Duration,
+
+    #[command(flatten)]
+    converter: ConverterArgs,
+}
+
+/// Arguments shared by the local conversion subcommands
+#[derive(Args, Debug)]
+struct ConverterArgs {
+    /// Format to convert files to (i.e "pdf", "docx", "png"), defaults to "pdf"
+    #[arg(long, default_value = "pdf")]
+    format: String,
+
+    /// Backend used to perform conversions, defaults to "libreoffice"
+    #[arg(long, value_enum, default_value_t = Backend::Libreoffice)]
+    backend: Backend,
+
+    /// Directory to write documents to while converting, defaults to the
+    /// system temp directory
+    #[arg(long)]
+    work_dir: Option<PathBuf>,
+}
+
+/// Backend converting files outside of the server
+struct Converter {
+    /// Backend performing the conversions
+    backend: Box<dyn ConvertBackend>,
+    /// Options files are converted with
+    options: ConvertOptions,
+    /// File extension of the converted files
+    extension: &'static str,
+}
+
+impl Converter {
+    /// Creates the backend from the `args` using the office install at
+    /// `office_path`
+    fn new(args: &ConverterArgs, office_path: Option<PathBuf>) -> anyhow::Result<Self> {
+        let (target_format, extension) = match TargetFormat::parse(Some(&args.format), None)? {
+            Some(target_format) => (Some(target_format), target_format.extension),
+            None if args.format.eq_ignore_ascii_case("pdf") => (None, "pdf"),
+            None => {
+                return Err(anyhow!(
+                    "{} output is only available through the server",
+                    args.format
+                ))
+            }
+        };
+
+        let work_dir = args.work_dir.clone().unwrap_or_else(temp_dir);
+
+        let backend: Box<dyn ConvertBackend> = match args.backend {
+            Backend::Stub => Box::new(StubBackend),
+            backend => {
+                let office_path = office_path.context("no office install path provided")?;
+
+                match backend {
+                    Backend::Soffice => Box::new(SofficeBackend::new(
+                        &office_path,
+                        &work_dir,
+                        None,
+                        OfficeLogLevel::Off,
+                    )?),
+                    _ => Box::new(LibreOfficeBackend::new(
+                        &office_path,
+                        &work_dir,
+                        None,
+                        1000,
+                        OfficeLogLevel::Off,
+                        Arc::new(RunnerActivity::default()),
+                    )?),
+                }
+            }
+        };
+
+        Ok(Self {
+            backend,
+            options: ConvertOptions {
+                target_format,
+                ..Default::default()
+            },
+            extension,
+        })
+    }
+
+    /// Converts the file at `input` writing the converted file to `output`
+    fn convert_file(&mut self, input: &Path, output: &Path) -> anyhow::Result<()> {
+        let bytes =
+            std::fs::read(input).with_context(|| format!("failed to read {}", input.display()))?;
+
+        let converted = self
+            .backend
+            .convert(DocumentInput::Bytes(bytes.into()), self.options.clone())?;
+
+        for warning in &converted.warnings {
+            eprintln!("{}: {}", input.display(), warning.message);
+        }
+
+        std::fs::write(output, &converted.bytes)
+            .with_context(|| format!("failed to write {}", output.display()))?;
+
+        Ok(())
+    }
+
+    /// Provides the path the file at `input` is converted to within `dir`
+    fn output_path(&self, input: &Path, dir: &Path) -> PathBuf {
+        let stem = input.file_stem().unwrap_or(input.as_os_str());
+        dir.join(stem).with_extension(self.extension)
+    }
+}
+
+/// Runs the convert subcommand using the office install at `office_path`
+pub async fn run_convert(args: ConvertArgs, office_path: Option<PathBuf>) -> anyhow::Result<()> {
+    tokio::task::spawn_blocking(move || {
+        let mut converter = Converter::new(&args.converter, office_path)?;
+        let output = match args.output {
+            Some(output) => output,
+            None => args.file.with_extension(converter.extension),
+        };
+
+        if output == args.file {
+            return Err(anyhow!(
+                "output would replace {}, use --output to write elsewhere",
+                args.file.display()
+            ));
+        }
+
+        converter.convert_file(&args.file, &output)?;
+        println!("{} -> {}", args.file.display(), output.display());
+
+        Ok(())
+    })
+    .await
+    .context("convert failed")?
+}
+
+/// Runs the watch subcommand using the office install at `office_path`
+pub async fn run_watch(args: WatchArgs, office_path: Option<PathBuf>) -> anyhow::Result<()> {
+    tokio::task::spawn_blocking(move || watch_blocking(args, office_path))
+        .await
+        .context("watch failed")?
+}
+
+/// Last modified time and size of a file, files are converted again when
+/// either changes
+type FileStamp = (SystemTime, u64);
+
+fn watch_blocking(args: WatchArgs, office_path: Option<PathBuf>) -> anyhow::Result<()> {
+    std::fs::create_dir_all(&args.output_dir)
+        .with_context(|| format!("failed to create {}", args.output_dir.display()))?;
+
+    let dir = args
+        .dir
+        .canonicalize()
+        .with_context(|| format!("failed to read {}", args.dir.display()))?;
+
+    // Outputs written to the watched directory would be converted again
+    if args.output_dir.canonicalize()? == dir {
+        return Err(anyhow!(
+            "--output-dir must be a different directory to --dir"
+        ));
+    }
+
+    let mut converter = Converter::new(&args.converter, office_path)?;
+
+    // Stamps of the files that were converted (or failed to convert)
+    let mut converted: HashMap<PathBuf, FileStamp> = HashMap::new();
+    // Stamps of the files seen during the last check that are waiting to be
+    // unchanged before they are converted
+    let mut pending: HashMap<PathBuf, FileStamp> = HashMap::new();
+
+    println!(
+        "watching {} for files to convert to {}",
+        dir.display(),
+        converter.extension
+    );
+
+    loop {
+        let entries =
+            std::fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?;
+
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+
+            // Skip hidden files, often partial uploads or editor lock files
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+
+            if !metadata.is_file() {
+                continue;
+            }
+
+            let stamp = (metadata.modified()?, metadata.len());
+
+            if converted.get(&path) == Some(&stamp) {
+                continue;
+            }
+
+            // Files still being written change between checks
+            if pending.insert(path.clone(), stamp) != Some(stamp) {
+                continue;
+            }
+
+            pending.remove(&path);
+            converted.insert(path.clone(), stamp);
+
+            let output = converter.output_path(&path, &args.output_dir);
+
+            // Outputs newer than their file are left from an earlier run
+            let is_current = std::fs::metadata(&output)
+                .and_then(|output| output.modified())
+                .is_ok_and(|modified| modified >= stamp.0);
+
+            if is_current {
+                continue;
+            }
+
+            match converter.convert_file(&path, &output) {
+                Ok(()) => println!("{} -> {}", path.display(), output.display()),
+                Err(err) => eprintln!("failed to convert {}: {err:#}", path.display()),
+            }
+        }
+
+        std::thread::sleep(args.interval);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{
+    cache::ResultCache,
+    profile::{self, ProfileSettings},
+    runner::OfficeHandle,
+};
+use anyhow::Context;
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, error, info};
+
+/// Seconds in a day
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Daily time (UTC) maintenance starts at
+#[derive(Debug, Clone, Copy)]
+pub struct MaintenanceWindow {
+    /// Start of the window in seconds since midnight
+    start_secs: u64,
+}
+
+impl MaintenanceWindow {
+    /// Provides the time until the next start of the window from `now`
+    /// (Seconds since midnight)
+    fn until_next(&self, now: u64) -> Duration {
+        let secs = (self.start_secs + DAY_SECS - now) % DAY_SECS;
+
+        // Window that just started begins again tomorrow
+        Duration::from_secs(if secs == 0 { DAY_SECS } else { secs })
+    }
+}
+
+/// Parses a maintenance window in the form "HH:MM" (UTC)
+pub fn window_arg(value: &str) -> Result<MaintenanceWindow, String> {
+    let parse = || -> Option<MaintenanceWindow> {
+        let (hours, minutes) = value.split_once(':')?;
+        let hours: u64 = hours.parse().ok()?;
+        let minutes: u64 = minutes.parse().ok()?;
+
+        (hours < 24 && minutes < 60).then_some(MaintenanceWindow {
+            start_secs: hours * 60 * 60 + minutes * 60,
+        })
+    };
+
+    parse().ok_or_else(|| "expected a UTC time like 03:30".to_string())
+}
+
+/// Resources maintenance is performed on
+pub struct Maintenance {
+    /// Handle to the office runner
+    pub office: OfficeHandle,
+    /// Result cache to clean up
+    pub result_cache: Option<Arc<ResultCache>>,
+    /// Office profile to replace with a fresh profile
+    pub profile_dir: Option<PathBuf>,
+    /// Settings applied to the fresh profile
+    pub profile_settings: ProfileSettings,
+}
+
+impl Maintenance {
+    /// Runs maintenance at the start of each of the `windows` forever
+    pub async fn schedule(self, windows: Vec<MaintenanceWindow>) {
+        loop {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|value| value.as_secs() % DAY_SECS)
+                .unwrap_or_default();
+
+            let Some(next) = windows.iter().map(|window| window.until_next(now)).min() else {
+                return;
+            };
+
+            debug!(?next, "waiting for next maintenance window");
+            tokio::time::sleep(next).await;
+
+            if let Err(cause) = self.run().await {
+                error!(?cause, "maintenance failed");
+            }
+        }
+    }
+
+    /// Cleans up the result cache, drains the server and replaces office with
+    /// a fresh instance (Fresh profile and memory) before resuming.
+    ///
+    /// Backends that cannot be started again within the process exit the
+    /// server to be restarted by its supervisor instead, so the cache is
+    /// cleaned up first
+    pub async fn run(&self) -> anyhow::Result<()> {
+        info!("maintenance started");
+
+        if let Some(cache) = self.result_cache.as_ref() {
+            let removed = cache.cleanup().await?;
+            debug!(removed, "cleaned up result cache");
+        }
+
+        // Health checks fail while draining so load balancers stop sending work
+        self.office.set_maintenance(true);
+        let result = self.recycle().await;
+        self.office.set_maintenance(false);
+
+        result?;
+
+        info!("maintenance finished");
+        Ok(())
+    }
+
+    async fn recycle(&self) -> anyhow::Result<()> {
+        let profile_dir = self.profile_dir.clone();
+        let profile_settings = self.profile_settings.clone();
+
+        self.office
+            .recycle(Box::new(move || match profile_dir {
+                Some(profile_dir) => profile::reset(&profile_dir, &profile_settings),
+                None => Ok(()),
+            }))
+            .await
+            .context("failed to recycle office")
+    }
+}
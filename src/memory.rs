@@ -0,0 +1,123 @@
+use std::path::Path;
+
+/// Memory usage of the container (or system when not limited)
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryUsage {
+    /// Memory currently in use in bytes
+    pub used: u64,
+    /// Memory available to use in bytes
+    pub limit: u64,
+}
+
+impl MemoryUsage {
+    /// Provides the percentage of the limit currently in use
+    pub fn percent(&self) -> u64 {
+        if self.limit == 0 {
+            return 0;
+        }
+
+        self.used.saturating_mul(100) / self.limit
+    }
+}
+
+/// Provides the current memory usage, the cgroup memory limit is used when
+/// running within a limited container (cgroup v2 then v1) falling back to the
+/// system memory.
+///
+/// Inactive file cache is excluded from the usage as the kernel reclaims it
+/// before invoking the OOM killer
+pub fn current() -> Option<MemoryUsage> {
+    cgroup_v2().or_else(cgroup_v1).or_else(system)
+}
+
+fn cgroup_v2() -> Option<MemoryUsage> {
+    let root = Path::new("/sys/fs/cgroup");
+
+    // "max" is used when the memory is not limited
+    let limit = read_u64(&root.join("memory.max"))?;
+    let used = read_u64(&root.join("memory.current"))?;
+    let inactive = read_stat(&root.join("memory.stat"), "inactive_file").unwrap_or_default();
+
+    Some(MemoryUsage {
+        used: used.saturating_sub(inactive),
+        limit,
+    })
+}
+
+fn cgroup_v1() -> Option<MemoryUsage> {
+    /// Limits above this are treated as unlimited, v1 reports unlimited
+    /// as a very large page aligned value
+    const UNLIMITED: u64 = 1 << 60;
+
+    let root = Path::new("/sys/fs/cgroup/memory");
+
+    let limit = read_u64(&root.join("memory.limit_in_bytes"))?;
+    if limit >= UNLIMITED {
+        return None;
+    }
+
+    let used = read_u64(&root.join("memory.usage_in_bytes"))?;
+    let inactive = read_stat(&root.join("memory.stat"), "total_inactive_file").unwrap_or_default();
+
+    Some(MemoryUsage {
+        used: used.saturating_sub(inactive),
+        limit,
+    })
+}
+
+fn system() -> Option<MemoryUsage> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+
+    // Values are reported in kB
+    let field = |name: &str| -> Option<u64> {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+            .map(|value: u64| value * 1024)
+    };
+
+    let limit = field("MemTotal:")?;
+    let available = field("MemAvailable:")?;
+
+    Some(MemoryUsage {
+        used: limit.saturating_sub(available),
+        limit,
+    })
+}
+
+/// Provides the resident memory of the server process in bytes, office
+/// runs within the server process so this includes the office memory
+pub fn process_rss() -> Option<u64> {
+    read_status("VmRSS:")
+}
+
+/// Provides the peak resident memory of the server process in bytes
+pub fn process_peak_rss() -> Option<u64> {
+    read_status("VmHWM:")
+}
+
+/// Reads a memory value (Reported in kB) from the process status
+fn read_status(name: &str) -> Option<u64> {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+        .map(|value: u64| value * 1024)
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Reads the value of the `key` from a cgroup memory.stat file
+fn read_stat(path: &Path, key: &str) -> Option<u64> {
+    std::fs::read_to_string(path)
+        .ok()?
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(' ')?;
+            (name == key).then(|| value.trim().parse().ok())?
+        })
+}
@@ -0,0 +1,354 @@
+use crate::runner::{FailureTotals, OfficeHandle, RunnerPhase};
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Upper bounds of the duration histogram buckets in seconds
+const DURATION_BUCKETS: &[f64] = &[
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+/// Upper bounds of the document size histogram buckets in bytes
+const SIZE_BUCKETS: &[f64] = &[
+    10_000.0,
+    100_000.0,
+    1_000_000.0,
+    5_000_000.0,
+    10_000_000.0,
+    50_000_000.0,
+    100_000_000.0,
+    500_000_000.0,
+];
+
+/// Kind of work processed by the runner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkKind {
+    Convert = 0,
+    Pipeline = 1,
+    ExtractAssets = 2,
+    ExtractStats = 3,
+}
+
+impl WorkKind {
+    /// Number of kinds of work
+    const COUNT: usize = 4;
+
+    const ALL: [WorkKind; WorkKind::COUNT] = [
+        WorkKind::Convert,
+        WorkKind::Pipeline,
+        WorkKind::ExtractAssets,
+        WorkKind::ExtractStats,
+    ];
+
+    /// Name of the kind used as a metric label
+    fn name(self) -> &'static str {
+        match self {
+            WorkKind::Convert => "convert",
+            WorkKind::Pipeline => "pipeline",
+            WorkKind::ExtractAssets => "extract_assets",
+            WorkKind::ExtractStats => "extract_stats",
+        }
+    }
+}
+
+/// Phases of work timed separately along with their metric label, the
+/// phases office spends its time in
+const TIMED_PHASES: [(RunnerPhase, &str); 3] = [
+    (RunnerPhase::Loading, "loading"),
+    (RunnerPhase::Converting, "converting"),
+    (RunnerPhase::Saving, "saving"),
+];
+
+/// Histogram of observed values with cumulative buckets
+#[derive(Debug)]
+pub struct Histogram {
+    /// Upper bounds of the buckets
+    bounds: &'static [f64],
+    /// Observations within each bucket (Not cumulative)
+    buckets: Vec<AtomicU64>,
+    /// Sum of the observed values (Bits of an f64)
+    sum: AtomicU64,
+    /// Number of observed values
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            // Additional bucket for values above the last bound
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0f64.to_bits()),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// Records an observed `value`
+    pub fn observe(&self, value: f64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        _ = self
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                Some((f64::from_bits(sum) + value).to_bits())
+            });
+    }
+
+    /// Writes the histogram samples of the metric `name` with the `labels`
+    /// (i.e `work="convert"`) in the prometheus text format
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            _ = writeln!(
+                out,
+                "{name}_bucket{{{labels}{separator}le=\"{bound}\"}} {cumulative}"
+            );
+        }
+
+        let count = self.count.load(Ordering::Relaxed);
+        let sum = f64::from_bits(self.sum.load(Ordering::Relaxed));
+
+        _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{separator}le=\"+Inf\"}} {count}"
+        );
+
+        let labels = match labels {
+            "" => String::new(),
+            labels => format!("{{{labels}}}"),
+        };
+
+        _ = writeln!(out, "{name}_sum{labels} {sum}");
+        _ = writeln!(out, "{name}_count{labels} {count}");
+    }
+}
+
+/// Telemetry of the work processed by the runner, recorded by the runner
+/// as work is processed so it reflects what office actually did
+#[derive(Debug)]
+pub struct RunnerMetrics {
+    /// Time taken by each kind of work
+    durations: [Histogram; WorkKind::COUNT],
+    /// Time spent in each of the [TIMED_PHASES]
+    phases: [Histogram; TIMED_PHASES.len()],
+    /// Time work waited to reach the runner
+    queue_wait: Histogram,
+    /// Size of documents processed by the runner
+    input_bytes: Histogram,
+    /// Size of the outputs produced by the runner
+    output_bytes: Histogram,
+    /// Number of times office was started
+    office_starts: AtomicU64,
+}
+
+impl Default for RunnerMetrics {
+    fn default() -> Self {
+        Self {
+            durations: std::array::from_fn(|_| Histogram::new(DURATION_BUCKETS)),
+            phases: std::array::from_fn(|_| Histogram::new(DURATION_BUCKETS)),
+            queue_wait: Histogram::new(DURATION_BUCKETS),
+            input_bytes: Histogram::new(SIZE_BUCKETS),
+            output_bytes: Histogram::new(SIZE_BUCKETS),
+            office_starts: AtomicU64::new(0),
+        }
+    }
+}
+
+impl RunnerMetrics {
+    /// Records the `duration` of a piece of work of the `kind`
+    pub fn record_work(&self, kind: WorkKind, duration: Duration) {
+        self.durations[kind as usize].observe(duration.as_secs_f64());
+    }
+
+    /// Records the `duration` spent in the `phase`, idle time is not recorded
+    pub fn record_phase(&self, phase: RunnerPhase, duration: Duration) {
+        if let Some(index) = TIMED_PHASES.iter().position(|(timed, _)| *timed == phase) {
+            self.phases[index].observe(duration.as_secs_f64());
+        }
+    }
+
+    /// Records the time a piece of work waited to reach the runner
+    pub fn record_queue_wait(&self, wait: Duration) {
+        self.queue_wait.observe(wait.as_secs_f64());
+    }
+
+    /// Records the size of a document processed by the runner
+    pub fn record_input(&self, size: u64) {
+        self.input_bytes.observe(size as f64);
+    }
+
+    /// Records the size of an output produced by the runner
+    pub fn record_output(&self, size: u64) {
+        self.output_bytes.observe(size as f64);
+    }
+
+    /// Records that office was started
+    pub fn record_office_start(&self) {
+        self.office_starts.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Renders the metrics of the `office` runner in the prometheus text format
+pub(crate) fn render(office: &OfficeHandle) -> String {
+    let mut out = String::new();
+    let state = office.state();
+    let totals = office.totals();
+    let metrics = office.metrics();
+
+    let FailureTotals {
+        encrypted,
+        corrupted,
+        timeout,
+        unsupported,
+        worker_crash,
+        other,
+    } = totals.failures;
+
+    header(
+        &mut out,
+        "office_convert_work_total",
+        "counter",
+        "Pieces of work processed by outcome",
+    );
+    for (outcome, value) in [
+        ("completed", totals.completed),
+        ("encrypted", encrypted),
+        ("corrupted", corrupted),
+        ("timeout", timeout),
+        ("unsupported", unsupported),
+        ("worker_crash", worker_crash),
+        ("other", other),
+    ] {
+        _ = writeln!(
+            out,
+            "office_convert_work_total{{outcome=\"{outcome}\"}} {value}"
+        );
+    }
+
+    header(
+        &mut out,
+        "office_convert_work_duration_seconds",
+        "histogram",
+        "Time taken by office to process each kind of work",
+    );
+    for kind in WorkKind::ALL {
+        metrics.durations[kind as usize].write(
+            &mut out,
+            "office_convert_work_duration_seconds",
+            &format!("work=\"{}\"", kind.name()),
+        );
+    }
+
+    header(
+        &mut out,
+        "office_convert_phase_duration_seconds",
+        "histogram",
+        "Time office spent loading, processing and saving documents",
+    );
+    for ((_, phase), histogram) in TIMED_PHASES.iter().zip(&metrics.phases) {
+        histogram.write(
+            &mut out,
+            "office_convert_phase_duration_seconds",
+            &format!("phase=\"{phase}\""),
+        );
+    }
+
+    header(
+        &mut out,
+        "office_convert_queue_wait_seconds",
+        "histogram",
+        "Time work waited to reach the converter",
+    );
+    metrics
+        .queue_wait
+        .write(&mut out, "office_convert_queue_wait_seconds", "");
+
+    header(
+        &mut out,
+        "office_convert_input_bytes",
+        "histogram",
+        "Size of the documents processed by office",
+    );
+    metrics
+        .input_bytes
+        .write(&mut out, "office_convert_input_bytes", "");
+
+    header(
+        &mut out,
+        "office_convert_output_bytes",
+        "histogram",
+        "Size of the outputs produced by office",
+    );
+    metrics
+        .output_bytes
+        .write(&mut out, "office_convert_output_bytes", "");
+
+    let starts = metrics.office_starts.load(Ordering::Relaxed);
+
+    for (name, kind, help, value) in [
+        (
+            "office_convert_office_starts_total",
+            "counter",
+            "Times office was started",
+            starts,
+        ),
+        (
+            "office_convert_runner_restarts_total",
+            "counter",
+            "Times office was started again after the first start (idle shutdowns, crashes and recycles)",
+            starts.saturating_sub(1),
+        ),
+        (
+            "office_convert_selftests_total",
+            "counter",
+            "Self tests run after repeated failures",
+            totals.selftests,
+        ),
+        (
+            "office_convert_recoveries_total",
+            "counter",
+            "Times office was recycled after a failed self test",
+            totals.recoveries,
+        ),
+        (
+            "office_convert_queue_depth",
+            "gauge",
+            "Requests waiting for the converter",
+            state.queue_depth as u64,
+        ),
+        (
+            "office_convert_converting",
+            "gauge",
+            "Whether the converter is processing work",
+            state.converting as u64,
+        ),
+        (
+            "office_convert_office_warm",
+            "gauge",
+            "Whether office is running",
+            state.warm as u64,
+        ),
+    ] {
+        header(&mut out, name, kind, help);
+        _ = writeln!(out, "{name} {value}");
+    }
+
+    out
+}
+
+/// Writes the help and type lines of a metric
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    _ = writeln!(out, "# HELP {name} {help}");
+    _ = writeln!(out, "# TYPE {name} {kind}");
+}
@@ -0,0 +1,336 @@
+use crate::error::HttpError;
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use thiserror::Error;
+
+/// Number of bytes searched for the MIME headers of an archive
+pub(crate) const HEADER_SEARCH_LEN: usize = 8 * 1024;
+
+/// Magic bytes of binary property lists, Safari web archives are stored
+/// as binary property lists
+const BPLIST_MAGIC: &[u8] = b"bplist00";
+
+/// Errors from web archive inputs that cannot be converted
+#[derive(Debug, Error)]
+pub enum WebArchiveError {
+    /// Archive did not contain an HTML document
+    #[error("web archive does not contain an HTML document")]
+    MissingDocument,
+
+    /// Safari web archives cannot be read
+    #[error("safari web archives (.webarchive) are not supported, save the page as a web archive (.mht) or HTML instead")]
+    SafariArchive,
+}
+
+impl HttpError for WebArchiveError {
+    fn status(&self) -> StatusCode {
+        match self {
+            WebArchiveError::MissingDocument => StatusCode::UNPROCESSABLE_ENTITY,
+            WebArchiveError::SafariArchive => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            WebArchiveError::MissingDocument => "corrupted",
+            WebArchiveError::SafariArchive => "unsupported_format",
+        })
+    }
+}
+
+/// Checks if the `bytes` are a MIME web archive (MHTML), archives start with
+/// MIME headers declaring a "multipart/related" content type
+pub fn is_mhtml(bytes: &[u8]) -> bool {
+    let header = &bytes[..bytes.len().min(HEADER_SEARCH_LEN)];
+    let Some((headers, _)) = split_headers(header) else {
+        return false;
+    };
+
+    let headers = parse_headers(headers);
+
+    headers.iter().any(|(name, _)| name == "mime-version")
+        && header_value(&headers, "content-type").is_some_and(|value| {
+            value
+                .to_ascii_lowercase()
+                .trim_start()
+                .starts_with("multipart/related")
+        })
+}
+
+/// Checks if the `bytes` are a Safari web archive
+pub fn is_safari_archive(bytes: &[u8]) -> bool {
+    bytes.starts_with(BPLIST_MAGIC)
+        && bytes
+            .windows(b"WebMainResource".len())
+            .any(|window| window == b"WebMainResource")
+}
+
+/// Checks if an upload starting with the `header` may be a web archive,
+/// uploads that may be web archives are read in full to be unpacked
+pub fn may_be_web_archive(header: &[u8]) -> bool {
+    header.starts_with(BPLIST_MAGIC) || is_mhtml(header)
+}
+
+/// Converts a MIME web archive into a single HTML document that office can
+/// import, resources of the archive (images, stylesheets) are inlined into
+/// the document as data URLs
+pub fn to_html(bytes: &[u8]) -> Result<Vec<u8>, WebArchiveError> {
+    let (headers, body) = split_headers(bytes).ok_or(WebArchiveError::MissingDocument)?;
+    let headers = parse_headers(headers);
+
+    let content_type =
+        header_value(&headers, "content-type").ok_or(WebArchiveError::MissingDocument)?;
+    let boundary = parameter(content_type, "boundary").ok_or(WebArchiveError::MissingDocument)?;
+    let start = parameter(content_type, "start");
+
+    let parts: Vec<Part> = split_parts(body, &boundary)
+        .into_iter()
+        .filter_map(Part::parse)
+        .collect();
+
+    // The root document is identified by the "start" parameter, falling
+    // back to the first HTML part
+    let root = start
+        .as_deref()
+        .and_then(|start| {
+            parts
+                .iter()
+                .position(|part| part.content_id.as_deref() == Some(trim_angles(start)))
+        })
+        .or_else(|| parts.iter().position(|part| part.is_html()))
+        .ok_or(WebArchiveError::MissingDocument)?;
+
+    let mut document = parts[root].body.clone();
+
+    // Replace longer references first so references that prefix another are not broken
+    let mut references: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    for (index, part) in parts.iter().enumerate() {
+        if index == root {
+            continue;
+        }
+
+        let data_url = format!(
+            "data:{};base64,{}",
+            part.content_type,
+            STANDARD.encode(&part.body)
+        )
+        .into_bytes();
+
+        if let Some(location) = part.location.as_deref() {
+            references.push((location.as_bytes().to_vec(), data_url.clone()));
+        }
+
+        if let Some(content_id) = part.content_id.as_deref() {
+            references.push((format!("cid:{content_id}").into_bytes(), data_url));
+        }
+    }
+
+    references.sort_by_key(|(reference, _)| std::cmp::Reverse(reference.len()));
+
+    for (reference, data_url) in references {
+        document = replace_all(&document, &reference, &data_url);
+    }
+
+    Ok(document)
+}
+
+/// Part of a MIME web archive
+struct Part {
+    /// Media type of the part
+    content_type: String,
+    /// "Content-ID" of the part without angle brackets
+    content_id: Option<String>,
+    /// "Content-Location" of the part
+    location: Option<String>,
+    /// Decoded body of the part
+    body: Vec<u8>,
+}
+
+impl Part {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let (headers, body) = split_headers(bytes)?;
+        let headers = parse_headers(headers);
+
+        let content_type = header_value(&headers, "content-type")
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .unwrap_or_else(|| "text/plain".to_string());
+
+        let encoding = header_value(&headers, "content-transfer-encoding")
+            .map(|value| value.trim().to_ascii_lowercase());
+
+        let body = match encoding.as_deref() {
+            Some("base64") => {
+                let encoded: Vec<u8> = body
+                    .iter()
+                    .copied()
+                    .filter(|byte| !byte.is_ascii_whitespace())
+                    .collect();
+                STANDARD.decode(encoded).ok()?
+            }
+            Some("quoted-printable") => decode_quoted_printable(body),
+            _ => body.to_vec(),
+        };
+
+        Some(Self {
+            content_type,
+            content_id: header_value(&headers, "content-id")
+                .map(|value| trim_angles(value).to_string()),
+            location: header_value(&headers, "content-location")
+                .map(|value| value.trim().to_string()),
+            body,
+        })
+    }
+
+    fn is_html(&self) -> bool {
+        self.content_type == "text/html"
+    }
+}
+
+/// Splits the `bytes` into the header block and body at the first blank line
+fn split_headers(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    // Parts may have a leading line break after the boundary
+    let bytes = bytes
+        .strip_prefix(b"\r\n")
+        .or_else(|| bytes.strip_prefix(b"\n"))
+        .unwrap_or(bytes);
+
+    [&b"\r\n\r\n"[..], b"\n\n"]
+        .iter()
+        .filter_map(|separator| {
+            find(bytes, separator).map(|index| (index, index + separator.len()))
+        })
+        .min()
+        .map(|(end, body)| (&bytes[..end], &bytes[body..]))
+}
+
+/// Parses the `headers` block into lowercase names and values, unfolding
+/// values that continue over multiple lines
+fn parse_headers(headers: &[u8]) -> Vec<(String, String)> {
+    let headers = String::from_utf8_lossy(headers);
+    let mut parsed: Vec<(String, String)> = Vec::new();
+
+    for line in headers.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = parsed.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            parsed.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    parsed
+}
+
+fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Provides the value of a header parameter (i.e the boundary of a content type)
+fn parameter(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn trim_angles(value: &str) -> &str {
+    value.trim().trim_start_matches('<').trim_end_matches('>')
+}
+
+/// Splits a multipart `body` into its parts using the `boundary`
+fn split_parts<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{boundary}").into_bytes();
+
+    let mut parts = Vec::new();
+    let mut rest = match find(body, &delimiter) {
+        Some(index) => &body[index + delimiter.len()..],
+        None => return parts,
+    };
+
+    // The closing delimiter is followed by "--"
+    while !rest.starts_with(b"--") {
+        let end = find(rest, &delimiter).unwrap_or(rest.len());
+        parts.push(&rest[..end]);
+
+        if end == rest.len() {
+            break;
+        }
+
+        rest = &rest[end + delimiter.len()..];
+    }
+
+    parts
+}
+
+/// Decodes a quoted-printable `body`
+fn decode_quoted_printable(body: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(body.len());
+    let mut index = 0;
+
+    while index < body.len() {
+        let byte = body[index];
+
+        if byte != b'=' {
+            decoded.push(byte);
+            index += 1;
+            continue;
+        }
+
+        let rest = &body[index + 1..];
+
+        // Soft line breaks
+        if rest.starts_with(b"\r\n") {
+            index += 3;
+        } else if rest.starts_with(b"\n") {
+            index += 2;
+        } else if let Some(value) = rest
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(value);
+            index += 3;
+        } else {
+            decoded.push(byte);
+            index += 1;
+        }
+    }
+
+    decoded
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return None;
+    }
+
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Replaces every occurrence of `from` within the `bytes` with `to`
+fn replace_all(bytes: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+
+    while let Some(index) = find(rest, from) {
+        output.extend_from_slice(&rest[..index]);
+        output.extend_from_slice(to);
+        rest = &rest[index + from.len()..];
+    }
+
+    output.extend_from_slice(rest);
+    output
+}
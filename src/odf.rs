@@ -0,0 +1,288 @@
+use anyhow::Context;
+use libreofficekit::DocumentType;
+use quick_xml::{events::Event, Reader};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{Cursor, Read},
+    path::Path,
+};
+use zip::{ZipArchive, ZipWriter};
+
+/// Provides the native ODF package format for the provided document type
+pub fn package_format(document_type: DocumentType) -> &'static str {
+    match document_type {
+        DocumentType::Spreadsheet => "ods",
+        DocumentType::Presentation => "odp",
+        DocumentType::Drawing => "odg",
+        DocumentType::Text | DocumentType::Other(_) => "odt",
+    }
+}
+
+/// Statistics stored by LibreOffice in the `meta.xml` file of an
+/// ODF package
+#[derive(Debug, Default, Serialize)]
+pub struct DocumentStatistics {
+    /// Number of pages in the document
+    pub page_count: Option<u64>,
+    /// Number of words in the document
+    pub word_count: Option<u64>,
+    /// Number of characters in the document
+    pub character_count: Option<u64>,
+    /// Number of non-whitespace characters in the document
+    pub non_whitespace_character_count: Option<u64>,
+    /// Number of paragraphs in the document
+    pub paragraph_count: Option<u64>,
+    /// Number of tables in the document (For spreadsheets this is
+    /// the number of sheets)
+    pub table_count: Option<u64>,
+    /// Number of used cells (Spreadsheets only)
+    pub cell_count: Option<u64>,
+    /// Number of images in the document
+    pub image_count: Option<u64>,
+    /// Number of embedded objects in the document
+    pub object_count: Option<u64>,
+    /// Number of slides or pages (Presentations and drawings only)
+    pub slide_count: Option<u64>,
+}
+
+/// Reads the document statistics from the ODF package at the
+/// provided `path`
+pub fn read_statistics(path: &Path) -> anyhow::Result<DocumentStatistics> {
+    let meta = read_package_file(path, "meta.xml")?;
+
+    let mut reader = Reader::from_str(&meta);
+    let mut statistics = DocumentStatistics::default();
+
+    loop {
+        match reader.read_event().context("failed to parse meta.xml")? {
+            Event::Start(element) | Event::Empty(element)
+                if element.name().as_ref() == b"meta:document-statistic" =>
+            {
+                for attribute in element.attributes() {
+                    let attribute = attribute.context("invalid meta.xml attribute")?;
+                    let value = attribute
+                        .unescape_value()
+                        .context("invalid meta.xml attribute value")?;
+
+                    let field = match attribute.key.as_ref() {
+                        b"meta:page-count" => &mut statistics.page_count,
+                        b"meta:word-count" => &mut statistics.word_count,
+                        b"meta:character-count" => &mut statistics.character_count,
+                        b"meta:non-whitespace-character-count" => {
+                            &mut statistics.non_whitespace_character_count
+                        }
+                        b"meta:paragraph-count" => &mut statistics.paragraph_count,
+                        b"meta:table-count" => &mut statistics.table_count,
+                        b"meta:cell-count" => &mut statistics.cell_count,
+                        b"meta:image-count" => &mut statistics.image_count,
+                        b"meta:object-count" => &mut statistics.object_count,
+                        _ => continue,
+                    };
+
+                    *field = value.parse().ok();
+                }
+
+                break;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(statistics)
+}
+
+/// Counts the number of slides (draw pages) in the ODF package at the
+/// provided `path`, used for presentations and drawings which don't store
+/// the count in their statistics
+pub fn count_slides(path: &Path) -> anyhow::Result<u64> {
+    let content = read_package_file(path, "content.xml")?;
+
+    let mut reader = Reader::from_str(&content);
+    let mut count = 0;
+
+    loop {
+        match reader.read_event().context("failed to parse content.xml")? {
+            Event::Start(element) | Event::Empty(element)
+                if element.name().as_ref() == b"draw:page" =>
+            {
+                count += 1;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(count)
+}
+
+/// Maximum number of spaces a single `text:s` element expands to
+const MAX_SPACE_RUN: usize = 1024;
+
+/// Text of a single page of a document, the pages of spreadsheets are their
+/// sheets so they are numbered by `sheet` instead of `page`
+#[derive(Debug, Serialize)]
+pub struct PageText {
+    /// Number of the page (Starting at 1), not set for spreadsheets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    /// Number of the sheet (Starting at 1), only set for spreadsheets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sheet: Option<usize>,
+    /// Text content of the page
+    pub text: String,
+}
+
+/// Reads the text of each page from the ODF package at the provided `path`.
+///
+/// Text documents are split at the soft page breaks LibreOffice writes from
+/// its layout, presentations and drawings by their slides (draw pages) and
+/// spreadsheets by their sheets
+pub fn read_page_text(path: &Path) -> anyhow::Result<Vec<PageText>> {
+    let content = read_package_file(path, "content.xml")?;
+
+    let mut reader = Reader::from_str(&content);
+
+    // Element starting a new page, text documents use soft page breaks
+    let mut page_element: Option<&[u8]> = None;
+    let mut pages: Vec<String> = Vec::new();
+    let mut current: Option<String> = None;
+    let mut is_spreadsheet = false;
+
+    loop {
+        let event = reader.read_event().context("failed to parse content.xml")?;
+
+        match &event {
+            Event::Start(element) | Event::Empty(element) => {
+                let name = element.name();
+                let name = name.as_ref();
+
+                match name {
+                    b"office:text" => {
+                        page_element = Some(b"text:soft-page-break");
+                        current = Some(String::new());
+                    }
+                    b"office:presentation" | b"office:drawing" => {
+                        page_element = Some(b"draw:page");
+                    }
+                    b"office:spreadsheet" => {
+                        page_element = Some(b"table:table");
+                        is_spreadsheet = true;
+                    }
+                    _ if page_element == Some(name) => {
+                        pages.extend(current.replace(String::new()));
+                    }
+                    _ => {}
+                }
+
+                let Some(text) = current.as_mut() else {
+                    continue;
+                };
+
+                match name {
+                    b"text:s" => {
+                        let count = element
+                            .try_get_attribute("text:c")
+                            .ok()
+                            .flatten()
+                            .and_then(|attribute| {
+                                String::from_utf8_lossy(&attribute.value).parse().ok()
+                            })
+                            .unwrap_or(1)
+                            // Guard against crafted documents with huge space runs
+                            .min(MAX_SPACE_RUN);
+
+                        text.extend(std::iter::repeat_n(' ', count));
+                    }
+                    b"text:tab" => text.push('\t'),
+                    b"text:line-break" => text.push('\n'),
+                    _ => {}
+                }
+            }
+            Event::Text(value) => {
+                if let Some(text) = current.as_mut() {
+                    text.push_str(&value.unescape().context("invalid content.xml text")?);
+                }
+            }
+            Event::End(element) => {
+                if let (Some(text), b"text:p" | b"text:h") =
+                    (current.as_mut(), element.name().as_ref())
+                {
+                    text.push('\n');
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    pages.extend(current);
+
+    Ok(pages
+        .into_iter()
+        .enumerate()
+        .map(|(index, text)| PageText {
+            page: (!is_spreadsheet).then_some(index + 1),
+            sheet: is_spreadsheet.then_some(index + 1),
+            text: text.trim_end().to_string(),
+        })
+        .collect())
+}
+
+/// Reads the file at `name` within the ODF package at `path` as a string
+pub(crate) fn read_package_file(path: &Path, name: &str) -> anyhow::Result<String> {
+    let file = File::open(path).context("failed to open odf package")?;
+    let mut archive = ZipArchive::new(file).context("failed to read odf package")?;
+
+    let mut entry = archive
+        .by_name(name)
+        .with_context(|| format!("odf package missing {name}"))?;
+
+    let mut value = String::new();
+    entry
+        .read_to_string(&mut value)
+        .with_context(|| format!("failed to read {name} from odf package"))?;
+
+    Ok(value)
+}
+
+/// Extracts the images and embedded objects from the ODF package at the
+/// provided `path` into a new zip archive.
+///
+/// Images are placed in the "images/" directory and embedded objects (OLE
+/// attachments and embedded documents) are placed in the "objects/" directory
+pub fn extract_assets(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let file = File::open(path).context("failed to open odf package")?;
+    let mut archive = ZipArchive::new(file).context("failed to read odf package")?;
+
+    let mut output = ZipWriter::new(Cursor::new(Vec::new()));
+
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index_raw(index)
+            .context("failed to read odf package entry")?;
+
+        if entry.is_dir() {
+            continue;
+        }
+
+        let name = entry.name();
+
+        let output_name = if let Some(name) = name.strip_prefix("Pictures/") {
+            format!("images/{name}")
+        } else if name.starts_with("Object ") {
+            format!("objects/{name}")
+        } else {
+            continue;
+        };
+
+        output
+            .raw_copy_file_rename(entry, output_name)
+            .context("failed to copy asset")?;
+    }
+
+    let output = output.finish().context("failed to finish zip")?;
+
+    Ok(output.into_inner())
+}
@@ -0,0 +1,68 @@
+use crate::convert::ConvertError;
+use libreofficekit::OfficeError;
+
+/// Failure identified from the message of an error reported by office
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KnownFailure {
+    /// Office reports encrypted documents as an unsupported URL once the
+    /// password prompt is declined or the provided password is rejected
+    UnsupportedUrl,
+    /// Office could not load the document with any of its import filters
+    LoadFailed,
+    /// Office has no export filter for the requested format
+    FilterMissing,
+    /// Disk ran out of space while office was writing a file
+    DiskFull,
+    /// Office rejected the password of the document
+    WrongPassword,
+}
+
+/// Known error messages and the failures they identify, matched ignoring
+/// case in order. Entries are added here as new messages are seen so errors
+/// are classified in one place
+const KNOWN_MESSAGES: &[(&str, KnownFailure)] = &[
+    ("unsupported url", KnownFailure::UnsupportedUrl),
+    (
+        "loadcomponentfromurl returned an empty reference",
+        KnownFailure::LoadFailed,
+    ),
+    // Reported by soffice, encrypted documents also fail to load as no
+    // password can be provided
+    ("could not be loaded", KnownFailure::LoadFailed),
+    ("no output filter found", KnownFailure::FilterMissing),
+    ("no space left on device", KnownFailure::DiskFull),
+    ("not enough space", KnownFailure::DiskFull),
+    ("wrong password", KnownFailure::WrongPassword),
+];
+
+/// Classifies the `message` of an error reported by office, [None] when the
+/// message is not known. `password_rejected` is whether office asked for the
+/// password of the document after the provided password was used
+pub fn classify(message: &str, password_rejected: bool) -> Option<ConvertError> {
+    let message = message.to_ascii_lowercase();
+
+    let (_, failure) = KNOWN_MESSAGES
+        .iter()
+        .find(|(pattern, _)| message.contains(pattern))?;
+
+    Some(match failure {
+        KnownFailure::UnsupportedUrl if password_rejected => ConvertError::WrongPassword,
+        KnownFailure::UnsupportedUrl => ConvertError::Encrypted,
+        KnownFailure::LoadFailed => ConvertError::Corrupted,
+        KnownFailure::FilterMissing => ConvertError::FilterMissing,
+        KnownFailure::DiskFull => ConvertError::DiskFull,
+        KnownFailure::WrongPassword => ConvertError::WrongPassword,
+    })
+}
+
+/// Maps an error from office to a [ConvertError] when its message is known,
+/// other errors are kept as they are
+pub fn map_error(err: OfficeError, password_rejected: bool) -> anyhow::Error {
+    match err {
+        OfficeError::OfficeError(message) => match classify(&message, password_rejected) {
+            Some(err) => err.into(),
+            None => OfficeError::OfficeError(message).into(),
+        },
+        err => err.into(),
+    }
+}
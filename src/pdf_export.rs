@@ -0,0 +1,887 @@
+use crate::{
+    convert::{self, ConvertError, ConvertOptions, DocumentPassword},
+    error::HttpError,
+    tempfiles::{random_id, TempFile},
+};
+use anyhow::{anyhow, Context};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    ffi::OsString,
+    path::Path,
+    process::{Command, Output},
+    time::Duration,
+};
+use thiserror::Error;
+use tracing::debug;
+
+#[cfg(target_os = "windows")]
+const GHOSTSCRIPT_BINARY: &str = "gswin64c.exe";
+#[cfg(not(target_os = "windows"))]
+const GHOSTSCRIPT_BINARY: &str = "gs";
+
+/// Maximum length of a page range
+const MAX_PAGE_RANGE_LEN: usize = 256;
+
+/// Image resolutions (DPI) images can be reduced to, the choices offered
+/// by the office PDF export dialog
+const IMAGE_RESOLUTIONS: &[u64] = &[75, 150, 300, 600, 1200];
+
+/// Resolution (DPI) images are reduced to for draft quality exports
+const DRAFT_IMAGE_RESOLUTION: u64 = 75;
+
+/// JPEG quality of images for draft quality exports
+const DRAFT_QUALITY: u64 = 10;
+
+/// Maximum number of annotations that can be added to a PDF
+const MAX_ANNOTATIONS: usize = 1000;
+
+/// Maximum length of the contents of an annotation
+const MAX_ANNOTATION_CONTENTS_LEN: usize = 4096;
+
+/// Number of source pages that can be imposed onto each page, with the
+/// columns and rows of the Ghostscript N-up layout
+const PAGES_PER_SHEET: &[(u64, &str)] =
+    &[(2, "2x1"), (4, "2x2"), (6, "3x2"), (9, "3x3"), (16, "4x4")];
+
+/// Options for the exported PDF, translated into the properties of the
+/// office PDF export filter
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PdfExportOptions {
+    /// PDF/A conformance level to export for archival
+    pub pdfa: Option<PdfALevel>,
+
+    /// Pages to export (i.e "1-3,5")
+    pub page_range: Option<String>,
+
+    /// JPEG quality of exported images (1-100)
+    pub quality: Option<u8>,
+
+    /// Resolution (DPI) to reduce images above to
+    pub max_image_resolution: Option<u64>,
+
+    /// Color space the colors of the exported PDF are converted to
+    pub color: Option<PdfColor>,
+
+    /// Number of source pages imposed onto each page of the exported PDF
+    pub pages_per_sheet: Option<u64>,
+
+    /// Annotations added to the pages of the exported PDF
+    pub annotations: Vec<Annotation>,
+
+    /// Export a draft quality preview, images are reduced to the lowest
+    /// resolution and quality
+    pub draft: bool,
+
+    /// Password required to open the exported PDF
+    #[serde(skip)]
+    pub open_password: Option<DocumentPassword>,
+
+    /// Password required to change the permissions of the exported PDF,
+    /// printing and editing are restricted when set
+    #[serde(skip)]
+    pub permission_password: Option<DocumentPassword>,
+}
+
+/// PDF/A conformance level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PdfALevel {
+    #[serde(rename = "1b")]
+    A1b,
+    #[serde(rename = "2b")]
+    A2b,
+    #[serde(rename = "3b")]
+    A3b,
+}
+
+/// Annotation added to a page of the exported PDF, i.e
+/// {"page": 1, "type": "highlight", "rect": [72, 700, 300, 720], "contents": "Check this"}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Annotation {
+    /// Page the annotation is on (Starting at 1)
+    pub page: u64,
+
+    /// Type of annotation
+    #[serde(rename = "type")]
+    pub kind: AnnotationKind,
+
+    /// Area of the annotation in points from the bottom left corner of the
+    /// page (left, bottom, right, top)
+    pub rect: [f64; 4],
+
+    /// Text of the annotation, shown as a popup by PDF viewers
+    #[serde(default)]
+    pub contents: Option<String>,
+
+    /// RGB color of the annotation (Each 0-1), defaults to yellow
+    #[serde(default)]
+    pub color: Option<[f64; 3]>,
+}
+
+/// Type of annotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnotationKind {
+    /// Highlights the area of the page
+    Highlight,
+    /// Sticky note icon placed in the area of the page
+    Note,
+}
+
+/// Color space the exported PDF is converted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PdfColor {
+    /// Shades of gray only, for black and white printing
+    Grayscale,
+    /// CMYK process colors, for print shops
+    Cmyk,
+    /// RGB colors, for screens
+    Rgb,
+}
+
+/// Errors from invalid PDF export options
+#[derive(Debug, Error)]
+pub enum ExportOptionsError {
+    /// Export options were not a JSON object
+    #[error("export_options must be a JSON object")]
+    Malformed,
+
+    /// Option is not one of the known options
+    #[error("unknown export option {0}")]
+    Unknown(String),
+
+    /// Option had a value of the wrong type or format
+    #[error("invalid value for export option {0}")]
+    InvalidValue(&'static str),
+
+    /// Option cannot be used along with another option
+    #[error("export option {0} cannot be combined with {1}")]
+    Conflict(&'static str, &'static str),
+
+    /// Options were provided without exporting as PDF
+    #[error("export_options are only used when exporting as pdf")]
+    NotPdf,
+}
+
+impl HttpError for ExportOptionsError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("invalid_export_option")
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            ExportOptionsError::Malformed | ExportOptionsError::NotPdf => None,
+            ExportOptionsError::Unknown(option) => Some(json!({ "option": option })),
+            ExportOptionsError::InvalidValue(option) => Some(json!({ "option": option })),
+            ExportOptionsError::Conflict(option, conflicts_with) => {
+                Some(json!({ "option": option, "conflicts_with": conflicts_with }))
+            }
+        }
+    }
+}
+
+impl PdfExportOptions {
+    /// Parses the options from a JSON object of option names and values
+    /// (i.e {"pdfa": "2b", "page_range": "1-3"})
+    pub fn parse(value: &str) -> Result<Self, ExportOptionsError> {
+        let options: Map<String, Value> =
+            serde_json::from_str(value).map_err(|_| ExportOptionsError::Malformed)?;
+
+        let mut output = PdfExportOptions::default();
+
+        for (name, value) in options {
+            match name.as_str() {
+                "pdfa" => {
+                    output.pdfa = Some(
+                        value
+                            .as_str()
+                            .and_then(PdfALevel::parse)
+                            .ok_or(ExportOptionsError::InvalidValue("pdfa"))?,
+                    );
+                }
+                "page_range" => {
+                    output.page_range = Some(
+                        value
+                            .as_str()
+                            .filter(|value| is_valid_page_range(value))
+                            .ok_or(ExportOptionsError::InvalidValue("page_range"))?
+                            .replace(' ', ""),
+                    );
+                }
+                "quality" => {
+                    output.quality = Some(
+                        value
+                            .as_u64()
+                            .filter(|value| (1..=100).contains(value))
+                            .ok_or(ExportOptionsError::InvalidValue("quality"))?
+                            as u8,
+                    );
+                }
+                "max_image_resolution" => {
+                    output.max_image_resolution = Some(
+                        value
+                            .as_u64()
+                            .filter(|value| IMAGE_RESOLUTIONS.contains(value))
+                            .ok_or(ExportOptionsError::InvalidValue("max_image_resolution"))?,
+                    );
+                }
+                "color" => {
+                    output.color = Some(
+                        value
+                            .as_str()
+                            .and_then(PdfColor::parse)
+                            .ok_or(ExportOptionsError::InvalidValue("color"))?,
+                    );
+                }
+                "pages_per_sheet" => {
+                    output.pages_per_sheet = Some(
+                        value
+                            .as_u64()
+                            .filter(|value| PAGES_PER_SHEET.iter().any(|(pages, _)| pages == value))
+                            .ok_or(ExportOptionsError::InvalidValue("pages_per_sheet"))?,
+                    );
+                }
+                "annotations" => {
+                    output.annotations = serde_json::from_value::<Vec<Annotation>>(value)
+                        .ok()
+                        .filter(|annotations| {
+                            annotations.len() <= MAX_ANNOTATIONS
+                                && annotations.iter().all(Annotation::is_valid)
+                        })
+                        .ok_or(ExportOptionsError::InvalidValue("annotations"))?;
+                }
+                "draft" => {
+                    output.draft = value
+                        .as_bool()
+                        .ok_or(ExportOptionsError::InvalidValue("draft"))?;
+                }
+                "open_password" => {
+                    output.open_password = Some(parse_password(value, "open_password")?);
+                }
+                "permission_password" => {
+                    output.permission_password =
+                        Some(parse_password(value, "permission_password")?);
+                }
+                _ => return Err(ExportOptionsError::Unknown(name)),
+            }
+        }
+
+        // PDF/A forbids encryption, office would silently drop one of them
+        if output.pdfa.is_some() {
+            if output.open_password.is_some() {
+                return Err(ExportOptionsError::Conflict("pdfa", "open_password"));
+            }
+
+            if output.permission_password.is_some() {
+                return Err(ExportOptionsError::Conflict("pdfa", "permission_password"));
+            }
+        }
+
+        // Draft exports choose the image quality themselves
+        if output.draft {
+            if output.quality.is_some() {
+                return Err(ExportOptionsError::Conflict("draft", "quality"));
+            }
+
+            if output.max_image_resolution.is_some() {
+                return Err(ExportOptionsError::Conflict(
+                    "draft",
+                    "max_image_resolution",
+                ));
+            }
+        }
+
+        // Rewriting the PDF drops its encryption and PDF/A conformance
+        let rewritten = if output.color.is_some() {
+            Some("color")
+        } else if output.pages_per_sheet.is_some() {
+            Some("pages_per_sheet")
+        } else if !output.annotations.is_empty() {
+            Some("annotations")
+        } else {
+            None
+        };
+
+        // Annotation pages refer to the pages before they are imposed
+        if !output.annotations.is_empty() && output.pages_per_sheet.is_some() {
+            return Err(ExportOptionsError::Conflict(
+                "annotations",
+                "pages_per_sheet",
+            ));
+        }
+
+        if let Some(option) = rewritten {
+            if output.pdfa.is_some() {
+                return Err(ExportOptionsError::Conflict(option, "pdfa"));
+            }
+
+            if output.open_password.is_some() {
+                return Err(ExportOptionsError::Conflict(option, "open_password"));
+            }
+
+            if output.permission_password.is_some() {
+                return Err(ExportOptionsError::Conflict(option, "permission_password"));
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Checks the options can be used with the other `options` of the
+    /// conversion
+    pub fn check_compatible(&self, options: &ConvertOptions) -> Result<(), ExportOptionsError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let is_export = options.csv.is_some()
+            || options.spreadsheet.is_some()
+            || options.target_format.is_some();
+        if is_export {
+            return Err(ExportOptionsError::NotPdf);
+        }
+
+        // Split sheets select each sheet with their own page range
+        if options.split_sheets && self.page_range.is_some() {
+            return Err(ExportOptionsError::Conflict("page_range", "split_sheets"));
+        }
+
+        // Each sheet is exported as its own PDF so pages cannot be annotated
+        if options.split_sheets && !self.annotations.is_empty() {
+            return Err(ExportOptionsError::Conflict("annotations", "split_sheets"));
+        }
+
+        // Page text and thumbnails are created from the whole document rather
+        // than only the selected pages
+        if self.page_range.is_some() {
+            if options.with_text {
+                return Err(ExportOptionsError::Conflict("page_range", "with_text"));
+            }
+
+            if options.with_thumbnail {
+                return Err(ExportOptionsError::Conflict("page_range", "with_thumbnail"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether no options were provided
+    pub fn is_empty(&self) -> bool {
+        self.pdfa.is_none()
+            && self.page_range.is_none()
+            && self.quality.is_none()
+            && self.max_image_resolution.is_none()
+            && self.color.is_none()
+            && self.pages_per_sheet.is_none()
+            && self.annotations.is_empty()
+            && !self.draft
+            && !self.is_encrypted()
+    }
+
+    /// Whether the exported PDF is protected with a password
+    pub fn is_encrypted(&self) -> bool {
+        self.open_password.is_some() || self.permission_password.is_some()
+    }
+
+    /// Creates a fingerprint of the options for use in cache keys, encrypted
+    /// outputs are never cached so the passwords are not included
+    pub(crate) fn cache_fingerprint(&self) -> String {
+        let pdfa = self.pdfa.map(PdfALevel::name).unwrap_or_default();
+        let page_range = self.page_range.as_deref().unwrap_or_default();
+        let quality = self
+            .quality
+            .map(|value| value.to_string())
+            .unwrap_or_default();
+        let resolution = self
+            .max_image_resolution
+            .map(|value| value.to_string())
+            .unwrap_or_default();
+
+        let mut fingerprint = format!(
+            "pdfa={pdfa} page_range={page_range} quality={quality} max_image_resolution={resolution}"
+        );
+
+        // Only included when set so existing cache keys remain valid
+        if let Some(color) = self.color {
+            fingerprint.push_str(" color=");
+            fingerprint.push_str(color.name());
+        }
+
+        if let Some(pages) = self.pages_per_sheet {
+            fingerprint.push_str(&format!(" pages_per_sheet={pages}"));
+        }
+
+        if !self.annotations.is_empty() {
+            fingerprint.push_str(" annotations=");
+            fingerprint.push_str(&serde_json::to_string(&self.annotations).unwrap_or_default());
+        }
+
+        if self.draft {
+            fingerprint.push_str(" draft=true");
+        }
+
+        fingerprint
+    }
+
+    /// Whether the exported PDF is rewritten by Ghostscript, office has no
+    /// options for converting colors, imposing pages or adding annotations
+    pub(crate) fn is_rewritten(&self) -> bool {
+        self.color.is_some() || self.pages_per_sheet.is_some() || !self.annotations.is_empty()
+    }
+
+    /// PDF export filter data properties for the options
+    pub(crate) fn filter_properties(&self) -> Vec<(&'static str, Value)> {
+        let mut properties = Vec::new();
+
+        if let Some(pdfa) = self.pdfa {
+            properties.push(("SelectPdfVersion", long(pdfa.pdf_version())));
+        }
+
+        if let Some(page_range) = self.page_range.as_deref() {
+            properties.push((
+                "PageRange",
+                json!({ "type": "string", "value": page_range }),
+            ));
+        }
+
+        if let Some(quality) = self.quality {
+            properties.push(("UseLosslessCompression", boolean(false)));
+            properties.push(("Quality", long(quality as u64)));
+        }
+
+        if let Some(resolution) = self.max_image_resolution {
+            properties.push(("ReduceImageResolution", boolean(true)));
+            properties.push(("MaxImageResolution", long(resolution)));
+        }
+
+        if self.draft {
+            properties.push(("UseLosslessCompression", boolean(false)));
+            properties.push(("Quality", long(DRAFT_QUALITY)));
+            properties.push(("ReduceImageResolution", boolean(true)));
+            properties.push(("MaxImageResolution", long(DRAFT_IMAGE_RESOLUTION)));
+        }
+
+        if let Some(password) = &self.open_password {
+            properties.push(("EncryptFile", boolean(true)));
+            properties.push((
+                "DocumentOpenPassword",
+                json!({ "type": "string", "value": password.0 }),
+            ));
+        }
+
+        if let Some(password) = &self.permission_password {
+            properties.push(("RestrictPermissions", boolean(true)));
+            properties.push((
+                "PermissionPassword",
+                json!({ "type": "string", "value": password.0 }),
+            ));
+        }
+
+        properties
+    }
+}
+
+impl PdfColor {
+    /// Parses a color space (i.e "grayscale" or "cmyk")
+    fn parse(value: &str) -> Option<Self> {
+        Some(match value.to_ascii_lowercase().as_str() {
+            "grayscale" | "gray" => PdfColor::Grayscale,
+            "cmyk" => PdfColor::Cmyk,
+            "rgb" => PdfColor::Rgb,
+            _ => return None,
+        })
+    }
+
+    /// Name of the color space
+    fn name(self) -> &'static str {
+        match self {
+            PdfColor::Grayscale => "grayscale",
+            PdfColor::Cmyk => "cmyk",
+            PdfColor::Rgb => "rgb",
+        }
+    }
+
+    /// Ghostscript color conversion strategy and process color model for
+    /// the color space
+    fn ghostscript_strategy(self) -> (&'static str, &'static str) {
+        match self {
+            PdfColor::Grayscale => ("Gray", "/DeviceGray"),
+            PdfColor::Cmyk => ("CMYK", "/DeviceCMYK"),
+            PdfColor::Rgb => ("RGB", "/DeviceRGB"),
+        }
+    }
+}
+
+impl Annotation {
+    /// Checks the annotation is on a page with an area within the page
+    /// coordinates and a color of valid components
+    fn is_valid(&self) -> bool {
+        let [left, bottom, right, top] = self.rect;
+
+        self.page > 0
+            && self
+                .rect
+                .iter()
+                .all(|value| value.is_finite() && *value >= 0.0)
+            && left < right
+            && bottom < top
+            && self
+                .contents
+                .as_ref()
+                .is_none_or(|contents| contents.chars().count() <= MAX_ANNOTATION_CONTENTS_LEN)
+            && self
+                .color
+                .is_none_or(|color| color.iter().all(|value| (0.0..=1.0).contains(value)))
+    }
+
+    /// Creates the Ghostscript pdfmark adding the annotation to its page
+    fn pdfmark(&self) -> String {
+        let [left, bottom, right, top] = self.rect;
+        let [red, green, blue] = self.color.unwrap_or([1.0, 1.0, 0.0]);
+
+        let mut mark = format!(
+            "[ /SrcPg {} /Rect [{left} {bottom} {right} {top}] /Color [{red} {green} {blue}] /F 4",
+            self.page
+        );
+
+        match self.kind {
+            AnnotationKind::Highlight => mark.push_str(&format!(
+                " /Subtype /Highlight /QuadPoints [{left} {top} {right} {top} {left} {bottom} {right} {bottom}]"
+            )),
+            AnnotationKind::Note => mark.push_str(" /Subtype /Text /Open false"),
+        }
+
+        if let Some(contents) = self.contents.as_deref() {
+            mark.push_str(" /Contents ");
+            mark.push_str(&pdf_text_string(contents));
+        }
+
+        mark.push_str(" /ANN pdfmark\n");
+        mark
+    }
+}
+
+/// Encodes `value` as a PDF text string, UTF-16BE written in hex so the
+/// text needs no escaping
+fn pdf_text_string(value: &str) -> String {
+    let mut output = String::from("<FEFF");
+
+    for unit in value.encode_utf16() {
+        output.push_str(&format!("{unit:04X}"));
+    }
+
+    output.push('>');
+    output
+}
+
+/// Rewrites the PDF at `path` in place using Ghostscript to apply the
+/// `options` office cannot, fails as unsupported when Ghostscript is not
+/// installed
+pub(crate) fn rewrite(path: &Path, options: &PdfExportOptions) -> anyhow::Result<()> {
+    let mut args = vec![OsString::from("-sDEVICE=pdfwrite")];
+
+    if let Some(color) = options.color {
+        let (strategy, color_model) = color.ghostscript_strategy();
+        args.push(format!("-sColorConversionStrategy={strategy}").into());
+        args.push(format!("-dProcessColorModel={color_model}").into());
+    }
+
+    if let Some(layout) = options.pages_per_sheet.and_then(|value| {
+        PAGES_PER_SHEET
+            .iter()
+            .find(|(pages, _)| *pages == value)
+            .map(|(_, layout)| *layout)
+    }) {
+        args.push(format!("-sNupControl={layout}").into());
+    }
+
+    args.push(path.as_os_str().to_os_string());
+
+    // The annotation pdfmarks are processed after the pages they refer to
+    let mut marks = None;
+    if !options.annotations.is_empty() {
+        let bytes = std::fs::read(path).context("failed to read exported pdf")?;
+        let page_count = convert::pdf_page_count(&bytes) as u64;
+
+        if let Some(annotation) = options
+            .annotations
+            .iter()
+            .find(|annotation| annotation.page > page_count)
+        {
+            return Err(ConvertError::InvalidPage {
+                page: annotation.page,
+                page_count,
+            }
+            .into());
+        }
+
+        let file = TempFile {
+            path: path.with_file_name(format!("lo_native_annotations_{}.ps", random_id())),
+        };
+
+        let pdfmarks: String = options
+            .annotations
+            .iter()
+            .map(Annotation::pdfmark)
+            .collect();
+        std::fs::write(&file.path, pdfmarks).context("failed to write annotations")?;
+
+        args.push(file.path.clone().into_os_string());
+        marks = Some(file);
+    }
+
+    debug!(
+        color = ?options.color,
+        pages_per_sheet = ?options.pages_per_sheet,
+        annotations = options.annotations.len(),
+        "rewriting pdf"
+    );
+
+    let result = run_ghostscript(path, args);
+    drop(marks);
+    result
+}
+
+/// Runs Ghostscript with the `args` (Device options and input files)
+/// replacing the PDF at `path` with its output, fails as unsupported when
+/// Ghostscript is not installed
+pub(crate) fn run_ghostscript(path: &Path, args: Vec<OsString>) -> anyhow::Result<()> {
+    let output = path.with_file_name(format!("lo_native_rewrite_{}.pdf", random_id()));
+
+    let mut command_args = vec![OsString::from(format!("-sOutputFile={}", output.display()))];
+    command_args.extend(args);
+
+    if let Err(err) = ghostscript(command_args) {
+        _ = std::fs::remove_file(&output);
+        return Err(err);
+    }
+
+    std::fs::rename(&output, path).context("failed to replace rewritten pdf")?;
+
+    Ok(())
+}
+
+/// Counts the pages of the PDF at `path` using Ghostscript, PDFs rewritten
+/// by Ghostscript compress their page objects so they can't be counted
+/// directly. Ghostscript is stopped after the `timeout`
+pub(crate) async fn count_pages(path: &Path, timeout: Duration) -> anyhow::Result<u64> {
+    let file = path.display().to_string();
+    let escaped = file
+        .replace('\\', "\\\\")
+        .replace('(', "\\(")
+        .replace(')', "\\)");
+
+    let output = ghostscript_with_timeout(
+        vec![
+            OsString::from("-dNODISPLAY"),
+            OsString::from(format!("--permit-file-read={file}")),
+            OsString::from("-c"),
+            OsString::from(format!(
+                "({escaped}) (r) file runpdfbegin pdfpagecount = quit"
+            )),
+        ],
+        timeout,
+    )
+    .await?;
+
+    String::from_utf8_lossy(&output)
+        .trim()
+        .parse()
+        .context("ghostscript reported an invalid page count")
+}
+
+/// Extracts the text of the PDF at `path` using Ghostscript
+pub(crate) fn extract_text(path: &Path) -> anyhow::Result<String> {
+    let output = ghostscript(vec![
+        OsString::from("-sDEVICE=txtwrite"),
+        OsString::from("-sOutputFile=-"),
+        path.as_os_str().to_os_string(),
+    ])?;
+
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Format a single page of a PDF is provided in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageFormat {
+    /// PDF containing only the page
+    #[default]
+    Pdf,
+    /// PNG render of the page
+    Png,
+}
+
+impl PageFormat {
+    /// Mime type of pages in the format
+    pub fn content_type(self) -> &'static str {
+        match self {
+            PageFormat::Pdf => "application/pdf",
+            PageFormat::Png => "image/png",
+        }
+    }
+}
+
+/// Writes the `page` (Starting at 1) of the PDF at `path` to `output` in
+/// the `format`, PNG renders are rendered at the `resolution` (DPI).
+/// Ghostscript is stopped after the `timeout`
+pub(crate) async fn extract_page(
+    path: &Path,
+    page: u64,
+    format: PageFormat,
+    resolution: u32,
+    output: &Path,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let mut args = vec![OsString::from(format!("-sOutputFile={}", output.display()))];
+
+    match format {
+        PageFormat::Pdf => args.push(OsString::from("-sDEVICE=pdfwrite")),
+        PageFormat::Png => {
+            args.push(OsString::from("-sDEVICE=png16m"));
+            args.push(OsString::from(format!("-r{resolution}")));
+        }
+    }
+
+    args.push(OsString::from(format!("-dFirstPage={page}")));
+    args.push(OsString::from(format!("-dLastPage={page}")));
+    args.push(path.as_os_str().to_os_string());
+
+    ghostscript_with_timeout(args, timeout).await?;
+
+    Ok(())
+}
+
+/// Arguments Ghostscript is always run with, quiet and without prompting
+const GHOSTSCRIPT_ARGS: [&str; 4] = ["-q", "-dSAFER", "-dBATCH", "-dNOPAUSE"];
+
+/// Runs Ghostscript with the `args` providing its output, fails as
+/// unsupported when Ghostscript is not installed
+fn ghostscript(args: Vec<OsString>) -> anyhow::Result<Vec<u8>> {
+    let result = Command::new(GHOSTSCRIPT_BINARY)
+        .args(GHOSTSCRIPT_ARGS)
+        .args(args)
+        .output();
+
+    ghostscript_output(result)
+}
+
+/// Runs Ghostscript with the `args` providing its output, Ghostscript is
+/// killed when it runs longer than the `timeout`
+async fn ghostscript_with_timeout(
+    args: Vec<OsString>,
+    timeout: Duration,
+) -> anyhow::Result<Vec<u8>> {
+    let output = tokio::process::Command::new(GHOSTSCRIPT_BINARY)
+        .args(GHOSTSCRIPT_ARGS)
+        .args(args)
+        .kill_on_drop(true)
+        .output();
+
+    let result = tokio::time::timeout(timeout, output)
+        .await
+        .map_err(|_| anyhow!("ghostscript did not finish within {timeout:?}"))?;
+
+    ghostscript_output(result)
+}
+
+/// Provides the output of a Ghostscript run, fails as unsupported when
+/// Ghostscript is not installed
+fn ghostscript_output(result: std::io::Result<Output>) -> anyhow::Result<Vec<u8>> {
+    let result = match result {
+        Ok(value) => value,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(ConvertError::Unsupported("pdf processing").into());
+        }
+        Err(err) => return Err(anyhow::Error::new(err).context("failed to run ghostscript")),
+    };
+
+    if !result.status.success() {
+        return Err(anyhow!(
+            "ghostscript failed: {}",
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+
+    Ok(result.stdout)
+}
+
+impl PdfALevel {
+    /// Parses a conformance level (i.e "2b" or "PDF/A-2b")
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.to_ascii_lowercase();
+        let value = value.strip_prefix("pdf/a-").unwrap_or(&value);
+
+        Some(match value {
+            "1b" => PdfALevel::A1b,
+            "2b" => PdfALevel::A2b,
+            "3b" => PdfALevel::A3b,
+            _ => return None,
+        })
+    }
+
+    /// Name of the conformance level
+    fn name(self) -> &'static str {
+        match self {
+            PdfALevel::A1b => "1b",
+            PdfALevel::A2b => "2b",
+            PdfALevel::A3b => "3b",
+        }
+    }
+
+    /// Value of the "SelectPdfVersion" filter property for the level
+    fn pdf_version(self) -> u64 {
+        match self {
+            PdfALevel::A1b => 1,
+            PdfALevel::A2b => 2,
+            PdfALevel::A3b => 3,
+        }
+    }
+}
+
+/// Checks the `value` is a page range of page numbers (Starting at 1) and
+/// ascending ranges separated by commas (i.e "1-3,5")
+fn is_valid_page_range(value: &str) -> bool {
+    let value = value.replace(' ', "");
+
+    let is_valid_page = |page: &str| page.parse::<u64>().is_ok_and(|page| page > 0);
+
+    !value.is_empty()
+        && value.len() <= MAX_PAGE_RANGE_LEN
+        && value.split(',').all(|part| match part.split_once('-') {
+            Some((start, end)) => {
+                is_valid_page(start)
+                    && is_valid_page(end)
+                    && start.parse::<u64>().ok() <= end.parse::<u64>().ok()
+            }
+            None => is_valid_page(part),
+        })
+}
+
+/// Parses a password option, passwords must not be empty
+fn parse_password(
+    value: Value,
+    option: &'static str,
+) -> Result<DocumentPassword, ExportOptionsError> {
+    value
+        .as_str()
+        .filter(|value| !value.is_empty())
+        .map(|value| DocumentPassword(value.to_string()))
+        .ok_or(ExportOptionsError::InvalidValue(option))
+}
+
+fn boolean(value: bool) -> Value {
+    json!({ "type": "boolean", "value": value.to_string() })
+}
+
+fn long(value: u64) -> Value {
+    json!({ "type": "long", "value": value.to_string() })
+}
@@ -0,0 +1,294 @@
+use crate::{
+    convert::{load_document, ConvertError, DocumentInput, RunnerState},
+    csv::{self, CsvOptions, CsvSheets},
+    odf,
+    runner::{RunnerActivity, RunnerPhase},
+    settings::RenderSettings,
+    tempfiles::{random_id, ConvertTempFiles, TempFile},
+};
+use anyhow::{anyhow, Context};
+use bytes::Bytes;
+use libreofficekit::Office;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    io::{Read, Write},
+    path::Path,
+    rc::Rc,
+    time::Instant,
+};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+/// Maximum number of steps allowed in a pipeline
+const MAX_STEPS: usize = 8;
+
+/// Formats that can be protected with a password when exported
+pub const PASSWORD_FORMATS: &[&str] = &["docx", "xlsx", "pptx", "odt", "ods", "odp", "odg"];
+
+/// Magic bytes of OLE compound files, encrypted OOXML documents are stored
+/// within a compound file instead of a zip package
+const COMPOUND_FILE_MAGIC: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// Step of a conversion pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineStep {
+    /// Export the current document to a format (file extension), the
+    /// exported document becomes the input of the next step
+    Export(String),
+    /// Export each sheet of a spreadsheet as a CSV file, must be the last
+    /// step as it produces multiple outputs
+    CsvSheets,
+}
+
+impl PipelineStep {
+    /// Name of the step used in the manifest
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            PipelineStep::Export(format) => format,
+            PipelineStep::CsvSheets => "csv-sheets",
+        }
+    }
+}
+
+/// Options applied to a pipeline
+#[derive(Default)]
+pub struct PipelineOptions {
+    /// Password to protect the output of the last step with, the last step
+    /// must export one of the [PASSWORD_FORMATS]
+    pub output_password: Option<String>,
+}
+
+/// Checks if the output of the last of the `steps` can be protected
+/// with a password
+pub fn supports_output_password(steps: &[PipelineStep]) -> bool {
+    matches!(
+        steps.last(),
+        Some(PipelineStep::Export(format)) if PASSWORD_FORMATS.contains(&format.as_str())
+    )
+}
+
+/// Parses a pipeline definition of steps separated by "->"
+/// (i.e "pdf -> png" or "csv-sheets")
+pub fn parse(value: &str) -> Option<Vec<PipelineStep>> {
+    let steps: Vec<PipelineStep> = value
+        .split("->")
+        .map(|step| {
+            let step = step.trim().to_ascii_lowercase();
+
+            if step == "csv-sheets" {
+                return Some(PipelineStep::CsvSheets);
+            }
+
+            let is_valid_format = !step.is_empty()
+                && step.len() <= 10
+                && step.chars().all(|char| char.is_ascii_alphanumeric());
+
+            is_valid_format.then_some(PipelineStep::Export(step))
+        })
+        .collect::<Option<_>>()?;
+
+    if steps.is_empty() || steps.len() > MAX_STEPS {
+        return None;
+    }
+
+    // Steps producing multiple outputs cannot be chained
+    let (_, chained) = steps.split_last()?;
+    if chained.contains(&PipelineStep::CsvSheets) {
+        return None;
+    }
+
+    Some(steps)
+}
+
+/// Manifest describing the artifacts produced by each step
+#[derive(Default, Serialize)]
+pub(crate) struct PipelineManifest {
+    pub(crate) steps: Vec<StepManifest>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct StepManifest {
+    /// Position of the step in the pipeline (Starting at 1)
+    pub(crate) step: usize,
+    /// Name of the step
+    pub(crate) name: String,
+    /// Names of the artifacts produced by the step within the zip
+    pub(crate) outputs: Vec<String>,
+    /// Time taken to run the step in milliseconds
+    pub(crate) duration_ms: u128,
+}
+
+/// Runs the pipeline `steps` against the provided document within a single
+/// runner slot, returning a zip archive of the artifacts from every step
+/// along with a manifest
+pub(crate) fn run_pipeline(
+    office: &Office,
+
+    temp_files: ConvertTempFiles,
+
+    input: DocumentInput,
+    steps: Vec<PipelineStep>,
+    options: PipelineOptions,
+
+    runner_state: &Rc<Mutex<RunnerState>>,
+    activity: &RunnerActivity,
+) -> anyhow::Result<Bytes> {
+    let ConvertTempFiles {
+        input: temp_in,
+        output: temp_out,
+        package: temp_package,
+    } = temp_files;
+
+    let mut artifacts: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut manifest = PipelineManifest::default();
+
+    let mut doc = load_document(
+        office,
+        &temp_in,
+        input,
+        &RenderSettings::default(),
+        runner_state,
+        activity,
+    )?;
+
+    for (index, step) in steps.iter().enumerate() {
+        let number = index + 1;
+        let started = Instant::now();
+        let is_last = number == steps.len();
+
+        activity.set_phase(RunnerPhase::Saving);
+
+        let outputs = match step {
+            PipelineStep::Export(format) => {
+                let name = format!("step-{number}.{format}");
+
+                // Intermediate outputs are kept beside the output file
+                let step_out = TempFile {
+                    path: temp_out
+                        .path
+                        .with_file_name(format!("lo_native_step_{}.{format}", random_id())),
+                };
+
+                // The password only protects the final output, protected intermediate
+                // outputs could not be loaded by the next step
+                let filter_options = options
+                    .output_password
+                    .as_deref()
+                    .filter(|_| is_last)
+                    .map(password_filter_options);
+
+                if !doc.save_as(&step_out.doc_url()?, format, filter_options.as_deref())? {
+                    return Err(anyhow!("failed to export {format}"))
+                        .with_context(|| format!("pipeline step {number} failed"));
+                }
+
+                // Office silently ignores options it does not understand, ensure
+                // the output was actually protected
+                if filter_options.is_some() && !is_password_protected(&step_out.path)? {
+                    return Err(ConvertError::Unsupported("output passwords").into());
+                }
+
+                let bytes = std::fs::read(&step_out.path).context("failed to read step output")?;
+                artifacts.push((name.clone(), bytes));
+
+                // Load the exported document as the input of the next step
+                if !is_last {
+                    drop(doc);
+                    doc = load_document(
+                        office,
+                        &temp_in,
+                        DocumentInput::Spilled(step_out),
+                        &RenderSettings::default(),
+                        runner_state,
+                        activity,
+                    )
+                    .with_context(|| format!("pipeline step {number} output failed to load"))?;
+                }
+
+                vec![name]
+            }
+            PipelineStep::CsvSheets => {
+                let options = CsvOptions {
+                    sheets: CsvSheets::All,
+                    ..Default::default()
+                };
+
+                let sheets = csv::export_sheets(&mut doc, &temp_out, &temp_package, &options)
+                    .with_context(|| format!("pipeline step {number} failed"))?;
+
+                sheets
+                    .into_iter()
+                    .map(|(sheet, bytes)| {
+                        let name = format!("step-{number}-sheet-{sheet}.csv");
+                        artifacts.push((name.clone(), bytes));
+                        name
+                    })
+                    .collect()
+            }
+        };
+
+        manifest.steps.push(StepManifest {
+            step: number,
+            name: step.name().to_string(),
+            outputs,
+            duration_ms: started.elapsed().as_millis(),
+        });
+    }
+
+    bundle(&manifest, artifacts)
+}
+
+/// Creates the JSON filter options to save a document protected
+/// with the `password`
+fn password_filter_options(password: &str) -> String {
+    json!({
+        "Password": { "type": "string", "value": password }
+    })
+    .to_string()
+}
+
+/// Checks if the exported document at `path` is protected with a password,
+/// protected OOXML documents are stored in a compound file and protected
+/// ODF documents describe their encryption in the package manifest
+fn is_password_protected(path: &Path) -> anyhow::Result<bool> {
+    let mut file = std::fs::File::open(path).context("failed to open step output")?;
+
+    let mut magic = [0u8; COMPOUND_FILE_MAGIC.len()];
+    if file.read_exact(&mut magic).is_ok() && magic == COMPOUND_FILE_MAGIC {
+        return Ok(true);
+    }
+
+    Ok(odf::read_package_file(path, "META-INF/manifest.xml")
+        .is_ok_and(|manifest| manifest.contains("encryption-data")))
+}
+
+/// Bundles the pipeline `artifacts` into a zip archive along with the
+/// `manifest` describing them
+pub(crate) fn bundle(
+    manifest: &PipelineManifest,
+    artifacts: Vec<(String, Vec<u8>)>,
+) -> anyhow::Result<Bytes> {
+    let mut archive = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+
+    let manifest = serde_json::to_vec_pretty(manifest).context("failed to serialize manifest")?;
+    archive
+        .start_file("manifest.json", SimpleFileOptions::default())
+        .context("failed to create zip entry")?;
+    archive
+        .write_all(&manifest)
+        .context("failed to write zip entry")?;
+
+    for (name, bytes) in artifacts {
+        archive
+            .start_file(name, SimpleFileOptions::default())
+            .context("failed to create zip entry")?;
+        archive
+            .write_all(&bytes)
+            .context("failed to write zip entry")?;
+    }
+
+    let archive = archive.finish().context("failed to finish zip")?;
+
+    Ok(Bytes::from(archive.into_inner()))
+}
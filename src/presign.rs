@@ -0,0 +1,226 @@
+use crate::error::HttpError;
+use axum::http::StatusCode;
+use ring::hmac;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// Creates and verifies expiring pre-signed URLs for stored outputs, allowing
+/// result links to be handed to end users without proxying the bytes
+/// through the calling application
+#[derive(Debug)]
+pub struct UrlSigner {
+    /// HMAC-SHA256 key URLs are signed with
+    key: hmac::Key,
+    /// Time signed URLs remain valid for
+    ttl: Duration,
+}
+
+/// Errors from pre-signed URLs that cannot be used
+#[derive(Debug, Error)]
+pub enum PresignError {
+    /// Signature was missing or did not match the URL
+    #[error("invalid url signature")]
+    InvalidSignature,
+
+    /// URL has expired
+    #[error("url has expired")]
+    Expired,
+}
+
+impl HttpError for PresignError {
+    fn status(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            PresignError::InvalidSignature => "invalid_url_signature",
+            PresignError::Expired => "url_expired",
+        })
+    }
+}
+
+impl UrlSigner {
+    /// Creates a signer using the `secret` with URLs valid for the `ttl`
+    pub fn new(secret: &[u8], ttl: Duration) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret),
+            ttl,
+        }
+    }
+
+    /// Creates a pre-signed URL (relative to the server) for the stored
+    /// output at `path` within the output directory
+    pub fn sign(&self, path: &str) -> String {
+        let expires = unix_secs() + self.ttl.as_secs();
+        let signature = self.signature(path, expires);
+
+        format!(
+            "/outputs/{}?expires={expires}&signature={signature}",
+            encode_path_segment(path)
+        )
+    }
+
+    /// Verifies the `signature` of the URL for the stored output at `path`
+    /// expiring at `expires` (Seconds since the unix epoch)
+    pub fn verify(&self, path: &str, expires: u64, signature: &str) -> Result<(), PresignError> {
+        let signature = decode_hex(signature).ok_or(PresignError::InvalidSignature)?;
+
+        hmac::verify(&self.key, &message(path, expires), &signature)
+            .map_err(|_| PresignError::InvalidSignature)?;
+
+        // Expiry is checked after the signature so it cannot be extended
+        if unix_secs() > expires {
+            return Err(PresignError::Expired);
+        }
+
+        Ok(())
+    }
+
+    fn signature(&self, path: &str, expires: u64) -> String {
+        hmac::sign(&self.key, &message(path, expires))
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+}
+
+/// Message signed for the URL of the output at `path`
+fn message(path: &str, expires: u64) -> Vec<u8> {
+    format!("{path}\n{expires}").into_bytes()
+}
+
+/// Percent encodes the `value` for use as a URL path segment
+fn encode_path_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(value.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Provides the expiry and signature from the query of a signed `url`
+    fn parse_signed(url: &str) -> (u64, String) {
+        let (_, query) = url.split_once('?').unwrap();
+        let mut expires = None;
+        let mut signature = None;
+
+        for pair in query.split('&') {
+            match pair.split_once('=').unwrap() {
+                ("expires", value) => expires = Some(value.parse().unwrap()),
+                ("signature", value) => signature = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        (expires.unwrap(), signature.unwrap())
+    }
+
+    fn signer() -> UrlSigner {
+        UrlSigner::new(b"secret", Duration::from_secs(60))
+    }
+
+    #[test]
+    fn signed_urls_verify() {
+        let signer = signer();
+        let url = signer.sign("reports/q1 report.pdf");
+        assert!(url.starts_with("/outputs/reports%2Fq1%20report.pdf?"));
+
+        let (expires, signature) = parse_signed(&url);
+        assert!(expires > unix_secs());
+        assert!(signer
+            .verify("reports/q1 report.pdf", expires, &signature)
+            .is_ok());
+    }
+
+    #[test]
+    fn tampered_signatures_are_rejected() {
+        let signer = signer();
+        let (expires, signature) = parse_signed(&signer.sign("report.pdf"));
+
+        // Changing a single character of the signature
+        let mut tampered = signature.clone().into_bytes();
+        tampered[0] = if tampered[0] == b'0' { b'1' } else { b'0' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        assert!(matches!(
+            signer.verify("report.pdf", expires, &tampered),
+            Err(PresignError::InvalidSignature)
+        ));
+
+        // Reusing the signature for another output or a later expiry
+        assert!(matches!(
+            signer.verify("other.pdf", expires, &signature),
+            Err(PresignError::InvalidSignature)
+        ));
+        assert!(matches!(
+            signer.verify("report.pdf", expires + 3600, &signature),
+            Err(PresignError::InvalidSignature)
+        ));
+
+        // Truncated and malformed signatures
+        assert!(matches!(
+            signer.verify("report.pdf", expires, &signature[..signature.len() - 1]),
+            Err(PresignError::InvalidSignature)
+        ));
+        assert!(matches!(
+            signer.verify("report.pdf", expires, "not-hex"),
+            Err(PresignError::InvalidSignature)
+        ));
+        assert!(matches!(
+            signer.verify("report.pdf", expires, ""),
+            Err(PresignError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn signatures_from_another_secret_are_rejected() {
+        let other = UrlSigner::new(b"other secret", Duration::from_secs(60));
+        let (expires, signature) = parse_signed(&other.sign("report.pdf"));
+
+        assert!(matches!(
+            signer().verify("report.pdf", expires, &signature),
+            Err(PresignError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn expired_urls_are_rejected() {
+        let signer = signer();
+        let expires = unix_secs() - 1;
+        let signature = signer.signature("report.pdf", expires);
+
+        assert!(matches!(
+            signer.verify("report.pdf", expires, &signature),
+            Err(PresignError::Expired)
+        ));
+
+        // Expired URLs with a bad signature report the signature
+        assert!(matches!(
+            signer.verify("report.pdf", expires, &"0".repeat(signature.len())),
+            Err(PresignError::InvalidSignature)
+        ));
+    }
+}
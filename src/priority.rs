@@ -0,0 +1,239 @@
+use clap::ValueEnum;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    future::Future,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use tokio::sync::Notify;
+
+/// Number of interactive requests admitted to the runner queue in a row
+/// while batch requests are waiting before a batch request is admitted
+pub const DEFAULT_MAX_SKIPS: u32 = 8;
+
+tokio::task_local! {
+    /// Priority tier of the request being handled
+    static TIER: PriorityTier;
+}
+
+/// Priority tier of a caller, higher tiers are admitted to the runner queue
+/// before lower tiers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriorityTier {
+    /// Background and bulk conversions
+    Batch,
+    /// User-facing conversions
+    #[default]
+    Interactive,
+}
+
+/// API key assigned to a priority tier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyTier {
+    /// API key provided in the "X-Api-Key" header
+    pub key: String,
+    /// Tier of requests using the key
+    pub tier: PriorityTier,
+}
+
+/// Parses an API key tier argument in the form "key=tier"
+pub fn api_key_tier_arg(value: &str) -> Result<ApiKeyTier, String> {
+    let parse = || -> Option<ApiKeyTier> {
+        let (key, tier) = value.rsplit_once('=')?;
+        let (key, tier) = (key.trim(), tier.trim());
+
+        if key.is_empty() {
+            return None;
+        }
+
+        Some(ApiKeyTier {
+            key: key.to_string(),
+            tier: PriorityTier::from_str(tier, true).ok()?,
+        })
+    };
+
+    parse().ok_or_else(|| "expected an api key tier like \"team-key=batch\"".to_string())
+}
+
+/// Runs the `future` with the priority `tier`, work sent to the runner by
+/// the future is admitted to the queue according to the tier
+pub async fn scope<F: Future>(tier: PriorityTier, future: F) -> F::Output {
+    TIER.scope(tier, future).await
+}
+
+/// Priority tier of the current request, [PriorityTier::Interactive] outside
+/// of a [scope]
+pub fn current() -> PriorityTier {
+    TIER.try_with(|tier| *tier).unwrap_or_default()
+}
+
+/// Admits requests to the runner queue one at a time, higher tiers first
+/// and in order within a tier. Lower tiers are admitted after `max_skips`
+/// higher tier requests in a row so they are never starved
+#[derive(Debug)]
+pub struct PriorityGate {
+    state: Mutex<GateState>,
+    notify: Notify,
+    max_skips: AtomicU32,
+}
+
+#[derive(Debug, Default)]
+struct GateState {
+    /// Whether a request is currently admitted
+    busy: bool,
+    /// Waiting batch requests in arrival order
+    batch: VecDeque<u64>,
+    /// Waiting interactive requests in arrival order
+    interactive: VecDeque<u64>,
+    /// Interactive requests admitted in a row while batch requests waited
+    skips: u32,
+    /// ID of the next waiting request
+    next_id: u64,
+}
+
+/// Admission to the runner queue, the next request is admitted once dropped
+pub struct PriorityPermit<'a> {
+    gate: &'a PriorityGate,
+}
+
+/// Place of a waiting request, removed from the gate if the request stops
+/// waiting before it is admitted
+struct Waiter<'a> {
+    gate: &'a PriorityGate,
+    tier: PriorityTier,
+    id: u64,
+    admitted: bool,
+}
+
+impl PriorityGate {
+    pub fn new(max_skips: u32) -> Self {
+        Self {
+            state: Default::default(),
+            notify: Notify::new(),
+            max_skips: AtomicU32::new(max_skips),
+        }
+    }
+
+    /// Changes the number of higher tier requests admitted in a row before a
+    /// lower tier request, [None] only admits lower tiers when no higher tier
+    /// requests are waiting
+    pub fn set_max_skips(&self, max_skips: Option<u32>) {
+        self.max_skips
+            .store(max_skips.unwrap_or(u32::MAX), Ordering::Relaxed);
+
+        // Waiting requests may now be admitted
+        self.notify.notify_waiters();
+    }
+
+    /// Provides the number of higher tier requests admitted in a row before
+    /// a lower tier request
+    pub fn max_skips(&self) -> Option<u32> {
+        match self.max_skips.load(Ordering::Relaxed) {
+            u32::MAX => None,
+            max_skips => Some(max_skips),
+        }
+    }
+
+    /// Waits until a request of the `tier` may enter the runner queue
+    pub async fn admit(&self, tier: PriorityTier) -> PriorityPermit<'_> {
+        let mut waiter = {
+            let state = &mut *self.state.lock();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.queue(tier).push_back(id);
+
+            Waiter {
+                gate: self,
+                tier,
+                id,
+                admitted: false,
+            }
+        };
+
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.try_admit(&mut waiter) {
+                return PriorityPermit { gate: self };
+            }
+
+            notified.await;
+        }
+    }
+
+    fn try_admit(&self, waiter: &mut Waiter<'_>) -> bool {
+        let state = &mut *self.state.lock();
+
+        if state.busy || state.queue(waiter.tier).front() != Some(&waiter.id) {
+            return false;
+        }
+
+        let max_skips = self.max_skips.load(Ordering::Relaxed);
+        let batch_starved = !state.batch.is_empty() && state.skips >= max_skips;
+        let is_turn = match waiter.tier {
+            PriorityTier::Interactive => !batch_starved,
+            PriorityTier::Batch => state.interactive.is_empty() || batch_starved,
+        };
+
+        if !is_turn {
+            return false;
+        }
+
+        state.queue(waiter.tier).pop_front();
+
+        match waiter.tier {
+            PriorityTier::Interactive if !state.batch.is_empty() => {
+                state.skips = state.skips.saturating_add(1)
+            }
+            PriorityTier::Interactive => {}
+            PriorityTier::Batch => state.skips = 0,
+        }
+
+        state.busy = true;
+        waiter.admitted = true;
+        true
+    }
+}
+
+impl GateState {
+    fn queue(&mut self, tier: PriorityTier) -> &mut VecDeque<u64> {
+        match tier {
+            PriorityTier::Batch => &mut self.batch,
+            PriorityTier::Interactive => &mut self.interactive,
+        }
+    }
+}
+
+impl Drop for PriorityPermit<'_> {
+    fn drop(&mut self) {
+        self.gate.state.lock().busy = false;
+        self.gate.notify.notify_waiters();
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if self.admitted {
+            return;
+        }
+
+        // Request stopped waiting (i.e timed out), the requests behind it may now be admitted
+        {
+            let state = &mut *self.gate.state.lock();
+            let queue = state.queue(self.tier);
+            if let Some(index) = queue.iter().position(|id| *id == self.id) {
+                queue.remove(index);
+            }
+
+            if state.batch.is_empty() {
+                state.skips = 0;
+            }
+        }
+
+        self.gate.notify.notify_waiters();
+    }
+}
@@ -0,0 +1,530 @@
+use crate::settings;
+use anyhow::{anyhow, Context};
+use std::path::Path;
+use tracing::debug;
+use url::Url;
+
+/// Closing element of the registry modifications
+const REGISTRY_END: &str = "</oor:items>";
+
+/// File in the profile directory listing the registry properties set by the
+/// [RegistrySetting]s of the last start (One "path/prop" per line), so
+/// settings that are no longer configured are removed from the profile
+const APPLIED_SETTINGS: &str = "lo_native_registry_settings";
+
+/// Registry paths whose properties are managed by the [ProfileSettings],
+/// items for these paths are replaced every time office starts
+const MANAGED_PATHS: &[&str] = &[
+    "/org.openoffice.Office.Linguistic/General\"><prop oor:name=\"DefaultLocale_CTL\"",
+    "/org.openoffice.Office.Common/I18N/CTL\"><prop oor:name=\"CTLFont\"",
+    "/org.openoffice.Office.Writer/DefaultFont\"><prop oor:name=\"",
+    "/org.openoffice.VCL/DefaultFonts/",
+    "/org.openoffice.Office.Common/Font/Substitution",
+    "/org.openoffice.Office.Linguistic/ServiceManager/Dictionaries\"><node oor:name=\"lo_native_",
+];
+
+/// Writer default font properties for complex text
+const WRITER_CTL_FONTS: &[&str] = &[
+    "StandardComplex",
+    "HeadingComplex",
+    "ListComplex",
+    "CaptionComplex",
+    "IndexComplex",
+];
+
+/// Default font types for complex text used by all applications when a
+/// document does not specify a font
+const VCL_CTL_FONTS: &[&str] = &[
+    "CTL_DISPLAY",
+    "CTL_HEADING",
+    "CTL_PRESENTATION",
+    "CTL_SPREADSHEET",
+    "CTL_TEXT",
+];
+
+/// Settings for rendering complex text layout (CTL) scripts such as Arabic
+/// and Hebrew applied to the office profile, minimal containers often lack
+/// the fonts documents ask for so this sets fonts that are installed
+#[derive(Debug, Default, Clone)]
+pub struct ProfileSettings {
+    /// Default locale (BCP 47 tag, i.e "ar-SA") of complex text, determines
+    /// the default direction of documents that don't specify one (i.e text
+    /// and HTML imports)
+    pub ctl_locale: Option<String>,
+    /// Default font of complex text for documents that don't specify one
+    pub ctl_font: Option<String>,
+    /// Fonts that are replaced when rendering
+    pub font_replacements: Vec<FontReplacement>,
+    /// Registry properties set in the profile (i.e the default measurement
+    /// unit or autocorrect options)
+    pub registry_settings: Vec<RegistrySetting>,
+    /// Spelling and hyphenation dictionaries registered with office
+    pub dictionaries: Vec<Dictionary>,
+}
+
+/// Kind of dictionary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DictionaryKind {
+    /// Hunspell spelling dictionary (".aff" and ".dic" files)
+    Spelling,
+    /// Hyphenation patterns ("hyph_" prefixed ".dic" files)
+    Hyphenation,
+}
+
+/// Dictionary found in a dictionary directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    /// Name of the dictionary from its file name (i.e "hyph_de_DE")
+    pub name: String,
+    pub kind: DictionaryKind,
+    /// Locale the dictionary is used for (BCP 47 tag, i.e "de-DE")
+    pub locale: String,
+    /// File URLs of the dictionary files
+    pub locations: Vec<Url>,
+}
+
+/// Registry property of the office configuration set to a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistrySetting {
+    /// Path of the node the property belongs to (i.e
+    /// "/org.openoffice.Office.Writer/Layout/Other")
+    pub path: String,
+    /// Name of the property (i.e "MeasureUnit")
+    pub prop: String,
+    /// Value of the property
+    pub value: String,
+}
+
+/// Font that is replaced by another font when rendering
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FontReplacement {
+    /// Name of the font documents use
+    pub font: String,
+    /// Name of the installed font to render with instead
+    pub replacement: String,
+}
+
+impl ProfileSettings {
+    /// Whether any settings are configured
+    pub fn is_empty(&self) -> bool {
+        self.ctl_locale.is_none()
+            && self.ctl_font.is_none()
+            && self.font_replacements.is_empty()
+            && self.registry_settings.is_empty()
+            && self.dictionaries.is_empty()
+    }
+
+    /// Creates the registry items for the settings
+    fn registry_items(&self) -> Vec<String> {
+        let mut items = Vec::new();
+
+        if self.ctl_locale.is_some() || self.ctl_font.is_some() {
+            items.push(registry_item(
+                "/org.openoffice.Office.Common/I18N/CTL",
+                "CTLFont",
+                "true",
+            ));
+        }
+
+        if let Some(locale) = self.ctl_locale.as_deref() {
+            items.push(registry_item(
+                "/org.openoffice.Office.Linguistic/General",
+                "DefaultLocale_CTL",
+                locale,
+            ));
+        }
+
+        if let Some(font) = self.ctl_font.as_deref() {
+            for prop in WRITER_CTL_FONTS {
+                items.push(registry_item(
+                    "/org.openoffice.Office.Writer/DefaultFont",
+                    prop,
+                    font,
+                ));
+            }
+
+            // Default fonts are looked up for the document language falling back to "en"
+            let mut languages = vec!["en"];
+            if let Some(language) = self
+                .ctl_locale
+                .as_deref()
+                .and_then(|locale| locale.split('-').next())
+                .filter(|language| *language != "en")
+            {
+                languages.push(language);
+            }
+
+            for language in languages {
+                let path = format!(
+                    "/org.openoffice.VCL/DefaultFonts/org.openoffice.VCL:LocalizedDefaultFonts['{language}']"
+                );
+
+                for prop in VCL_CTL_FONTS {
+                    items.push(registry_item(&path, prop, font));
+                }
+            }
+        }
+
+        if !self.font_replacements.is_empty() {
+            items.push(registry_item(
+                "/org.openoffice.Office.Common/Font/Substitution",
+                "Replacement",
+                "true",
+            ));
+
+            for (index, FontReplacement { font, replacement }) in
+                self.font_replacements.iter().enumerate()
+            {
+                items.push(format!(
+                    "<item oor:path=\"/org.openoffice.Office.Common/Font/Substitution/FontPairs\"><node oor:name=\"_{index}\" oor:op=\"replace\">{}{}{}{}</node></item>",
+                    registry_prop("Always", "true"),
+                    registry_prop("OnScreenOnly", "false"),
+                    registry_prop("ReplaceFont", font),
+                    registry_prop("SubstituteFont", replacement),
+                ));
+            }
+        }
+
+        for Dictionary {
+            name,
+            kind,
+            locale,
+            locations,
+        } in &self.dictionaries
+        {
+            let format = match kind {
+                DictionaryKind::Spelling => "DICT_SPELL",
+                DictionaryKind::Hyphenation => "DICT_HYPH",
+            };
+
+            items.push(format!(
+                "<item oor:path=\"/org.openoffice.Office.Linguistic/ServiceManager/Dictionaries\"><node oor:name=\"lo_native_{name}\" oor:op=\"replace\">{}{}{}</node></item>",
+                registry_prop("Format", format),
+                registry_list_prop("Locales", [locale.as_str()]),
+                registry_list_prop("Locations", locations.iter().map(Url::as_str)),
+            ));
+        }
+
+        // Set last so they take priority over the other settings
+        for RegistrySetting { path, prop, value } in &self.registry_settings {
+            items.push(registry_item(&escape_xml(path), &escape_xml(prop), value));
+        }
+
+        items
+    }
+}
+
+/// Parses a CTL locale argument (BCP 47 language tag)
+pub fn locale_arg(value: &str) -> Result<String, String> {
+    match settings::is_valid_language(value) {
+        true => Ok(value.to_string()),
+        false => Err("expected a language tag like ar-SA or he-IL".to_string()),
+    }
+}
+
+/// Parses a font replacement argument in the form "Font=Replacement"
+pub fn font_replacement_arg(value: &str) -> Result<FontReplacement, String> {
+    let parse = || -> Option<FontReplacement> {
+        let (font, replacement) = value.split_once('=')?;
+        let (font, replacement) = (font.trim(), replacement.trim());
+
+        (!font.is_empty() && !replacement.is_empty()).then(|| FontReplacement {
+            font: font.to_string(),
+            replacement: replacement.to_string(),
+        })
+    };
+
+    parse().ok_or_else(|| "expected a font replacement like \"Arial=Noto Sans\"".to_string())
+}
+
+/// Parses a registry setting argument in the form "/node/path/Prop=value",
+/// i.e "/org.openoffice.Office.Writer/Layout/Other/MeasureUnit=2"
+pub fn registry_setting_arg(value: &str) -> Result<RegistrySetting, String> {
+    let parse = || -> Option<RegistrySetting> {
+        let (key, value) = value.split_once('=')?;
+        let (path, prop) = key.trim().rsplit_once('/')?;
+
+        (path.starts_with("/org.openoffice.") && !prop.is_empty()).then(|| RegistrySetting {
+            path: path.to_string(),
+            prop: prop.to_string(),
+            value: value.trim().to_string(),
+        })
+    };
+
+    parse().ok_or_else(|| {
+        "expected a registry setting like \"/org.openoffice.Office.Writer/Layout/Other/MeasureUnit=2\""
+            .to_string()
+    })
+}
+
+/// Loads the registry settings from the file at `path`, one setting per line
+/// in the [registry_setting_arg] format. Empty lines and lines starting with
+/// "#" are ignored
+pub fn load_registry_settings(path: &Path) -> anyhow::Result<Vec<RegistrySetting>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read profile settings file {}", path.display()))?;
+
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| {
+            registry_setting_arg(line)
+                .map_err(|err| anyhow!("{}:{}: {err}", path.display(), index + 1))
+        })
+        .collect()
+}
+
+/// Finds the spelling dictionaries ("de_DE.aff" and "de_DE.dic") and
+/// hyphenation patterns ("hyph_de_DE.dic") in the `dir`
+pub fn find_dictionaries(dir: &Path) -> anyhow::Result<Vec<Dictionary>> {
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("failed to resolve dictionary directory {}", dir.display()))?;
+    let entries = std::fs::read_dir(&dir)
+        .with_context(|| format!("failed to read dictionary directory {}", dir.display()))?;
+
+    let mut dictionaries = Vec::new();
+
+    for entry in entries {
+        let path = entry
+            .with_context(|| format!("failed to read dictionary directory {}", dir.display()))?
+            .path();
+
+        let Some(name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".dic"))
+        else {
+            continue;
+        };
+
+        // Names are used in the registry, they must be simple
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '-')
+        {
+            continue;
+        }
+
+        let (kind, locale, locations) = match name.strip_prefix("hyph_") {
+            Some(locale) => (DictionaryKind::Hyphenation, locale, vec![path.clone()]),
+            None => {
+                // Spelling dictionaries are only usable with their affix file
+                let affix = path.with_extension("aff");
+                if !affix.is_file() {
+                    continue;
+                }
+
+                (DictionaryKind::Spelling, name, vec![affix, path.clone()])
+            }
+        };
+
+        let Some(locale) = dictionary_locale(locale) else {
+            continue;
+        };
+
+        let locations = locations
+            .iter()
+            .map(|path| {
+                Url::from_file_path(path)
+                    .map_err(|_| anyhow!("invalid dictionary path {}", path.display()))
+            })
+            .collect::<anyhow::Result<Vec<Url>>>()?;
+
+        dictionaries.push(Dictionary {
+            name: name.to_string(),
+            kind,
+            locale,
+            locations,
+        });
+    }
+
+    // Directory order isn't stable, the profile is the same on every node
+    dictionaries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(dictionaries)
+}
+
+/// Creates the locale of a dictionary from its file name (without the kind
+/// prefix), i.e "de_DE_frami" is used for "de-DE"
+fn dictionary_locale(name: &str) -> Option<String> {
+    let mut parts = name.split('_');
+    let language = parts
+        .next()
+        .filter(|language| (2..=3).contains(&language.len()))
+        .filter(|language| language.chars().all(|char| char.is_ascii_lowercase()))?;
+
+    Some(
+        match parts.next().filter(|region| {
+            region.len() == 2 && region.chars().all(|char| char.is_ascii_uppercase())
+        }) {
+            Some(region) => format!("{language}-{region}"),
+            None => language.to_string(),
+        },
+    )
+}
+
+/// Registry modifications the user profile is pre-seeded with, disables
+/// functionality that is unused when converting headless (autosave, recovery,
+/// update checks, first run dialogs) and never updates external links
+const REGISTRY_DEFAULTS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<oor:items xmlns:oor="http://openoffice.org/2001/registry" xmlns:xs="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+<item oor:path="/org.openoffice.Office.Common/Save/Document"><prop oor:name="AutoSave" oor:op="fuse"><value>false</value></prop></item>
+<item oor:path="/org.openoffice.Office.Common/Save/Document"><prop oor:name="CreateBackup" oor:op="fuse"><value>false</value></prop></item>
+<item oor:path="/org.openoffice.Office.Common/Save/Document"><prop oor:name="WarnAlienFormat" oor:op="fuse"><value>false</value></prop></item>
+<item oor:path="/org.openoffice.Office.Recovery/AutoSave"><prop oor:name="Enabled" oor:op="fuse"><value>false</value></prop></item>
+<item oor:path="/org.openoffice.Office.Recovery/RecoveryInfo"><prop oor:name="Enabled" oor:op="fuse"><value>false</value></prop></item>
+<item oor:path="/org.openoffice.Office.Jobs/Jobs/org.openoffice.Office.Jobs:Job['UpdateCheck']/Arguments"><prop oor:name="AutoCheckEnabled" oor:op="fuse"><value>false</value></prop></item>
+<item oor:path="/org.openoffice.Office.Common/Misc"><prop oor:name="FirstRun" oor:op="fuse"><value>false</value></prop></item>
+<item oor:path="/org.openoffice.Office.Common/Misc"><prop oor:name="ShowTipOfTheDay" oor:op="fuse"><value>false</value></prop></item>
+<item oor:path="/org.openoffice.Office.Common/Misc"><prop oor:name="CollectUsageInformation" oor:op="fuse"><value>false</value></prop></item>
+<item oor:path="/org.openoffice.Office.Common/Misc"><prop oor:name="UseOpenCL" oor:op="fuse"><value>false</value></prop></item>
+<item oor:path="/org.openoffice.Office.Common/Load"><prop oor:name="UpdateDocMode" oor:op="fuse"><value>0</value></prop></item>
+</oor:items>
+"#;
+
+/// Prepares the LibreOffice user profile in `profile_dir` and tells office
+/// to use it, must be called before office is created.
+///
+/// On first start the profile is created and pre-seeded with the
+/// [REGISTRY_DEFAULTS] so the first conversion doesn't pay for creating
+/// the profile. The `settings` are applied on every start
+pub fn bootstrap(profile_dir: &Path, settings: &ProfileSettings) -> anyhow::Result<()> {
+    let user_dir = profile_dir.join("user");
+    let registry = user_dir.join("registrymodifications.xcu");
+
+    if !registry.exists() {
+        std::fs::create_dir_all(&user_dir).context("failed to create office profile")?;
+        std::fs::write(&registry, REGISTRY_DEFAULTS)
+            .context("failed to write office profile defaults")?;
+
+        debug!("created office profile in: {}", profile_dir.display());
+    }
+
+    let applied_path = profile_dir.join(APPLIED_SETTINGS);
+    let applied = match std::fs::read_to_string(&applied_path) {
+        Ok(value) => value,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).context("failed to read applied profile settings"),
+    };
+
+    let modifications =
+        std::fs::read_to_string(&registry).context("failed to read office profile")?;
+    let modifications = apply_settings(&modifications, settings, &applied)?;
+    std::fs::write(&registry, modifications).context("failed to write office profile settings")?;
+
+    let applied: String = settings
+        .registry_settings
+        .iter()
+        .map(|setting| format!("{}/{}\n", setting.path, setting.prop))
+        .collect();
+    std::fs::write(&applied_path, applied).context("failed to write applied profile settings")?;
+
+    let profile_dir = profile_dir
+        .canonicalize()
+        .context("failed to resolve office profile path")?;
+    let profile_url = Url::from_directory_path(&profile_dir)
+        .map_err(|_| anyhow!("invalid office profile path"))?;
+
+    // Office reads bootstrap variables from the environment
+    std::env::set_var("UserInstallation", profile_url.as_str());
+
+    Ok(())
+}
+
+/// Replaces the profile in `profile_dir` with a freshly seeded profile, must
+/// be called while office is not running
+pub fn reset(profile_dir: &Path, settings: &ProfileSettings) -> anyhow::Result<()> {
+    if profile_dir.exists() {
+        std::fs::remove_dir_all(profile_dir).context("failed to remove office profile")?;
+    }
+
+    bootstrap(profile_dir, settings)
+}
+
+/// Replaces the items managed by the `settings` within the registry
+/// `modifications`, office writes one item per line. Registry settings are
+/// managed along with the `applied` properties ("path/prop" lines) of the
+/// previous start
+fn apply_settings(
+    modifications: &str,
+    settings: &ProfileSettings,
+    applied: &str,
+) -> anyhow::Result<String> {
+    let end = modifications
+        .rfind(REGISTRY_END)
+        .context("office profile registry is malformed")?;
+
+    let registry_settings: Vec<String> = settings
+        .registry_settings
+        .iter()
+        .map(|setting| (setting.path.as_str(), setting.prop.as_str()))
+        .chain(applied.lines().filter_map(|line| line.rsplit_once('/')))
+        .map(|(path, prop)| {
+            format!(
+                "{}\"><prop oor:name=\"{}\"",
+                escape_xml(path),
+                escape_xml(prop)
+            )
+        })
+        .collect();
+
+    let mut output: String = modifications[..end]
+        .lines()
+        .filter(|line| {
+            !MANAGED_PATHS
+                .iter()
+                .copied()
+                .chain(registry_settings.iter().map(String::as_str))
+                .any(|path| line.contains(&format!("oor:path=\"{path}")))
+        })
+        .flat_map(|line| [line, "\n"])
+        .collect();
+
+    for item in settings.registry_items() {
+        output.push_str(&item);
+        output.push('\n');
+    }
+
+    output.push_str(&modifications[end..]);
+
+    Ok(output)
+}
+
+/// Creates a registry item setting the `prop` at `path` to the `value`
+fn registry_item(path: &str, prop: &str, value: &str) -> String {
+    format!(
+        "<item oor:path=\"{path}\">{}</item>",
+        registry_prop(prop, value)
+    )
+}
+
+/// Creates a registry property with the `value`
+fn registry_prop(prop: &str, value: &str) -> String {
+    format!(
+        "<prop oor:name=\"{prop}\" oor:op=\"fuse\"><value>{}</value></prop>",
+        escape_xml(value)
+    )
+}
+
+/// Creates a registry string list property with the `values`
+fn registry_list_prop<'a>(prop: &str, values: impl IntoIterator<Item = &'a str>) -> String {
+    let values: String = values
+        .into_iter()
+        .map(|value| format!("<it>{}</it>", escape_xml(value)))
+        .collect();
+
+    format!("<prop oor:name=\"{prop}\" oor:op=\"fuse\"><value>{values}</value></prop>")
+}
+
+/// Escapes the `value` for use as XML text
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
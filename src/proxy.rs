@@ -0,0 +1,290 @@
+use axum::http::HeaderMap;
+use std::{future::Future, net::IpAddr};
+
+tokio::task_local! {
+    /// Origin of the request being handled as seen by the client
+    static PUBLIC_ORIGIN: Option<String>;
+}
+
+/// Range of IP addresses in CIDR notation (i.e "10.0.0.0/8")
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    /// Network address of the range
+    network: IpAddr,
+    /// Number of leading bits of the network address in the range
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Checks if the range contains the `ip`, IPv4 addresses mapped to IPv6
+    /// are treated as IPv4
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses an IP range in CIDR notation, a plain address is a range of
+/// just that address, i.e "10.0.0.0/8", "fd00::/8" or "192.168.1.10"
+pub fn cidr_arg(value: &str) -> Result<Cidr, String> {
+    let parse = || -> Option<Cidr> {
+        let value = value.trim();
+        let (network, prefix) = match value.split_once('/') {
+            Some((network, prefix)) => (network, Some(prefix)),
+            None => (value, None),
+        };
+
+        let network: IpAddr = network.parse().ok()?;
+        let network = network.to_canonical();
+        let max_prefix = match network {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().ok().filter(|prefix| *prefix <= max_prefix)?,
+            None => max_prefix,
+        };
+
+        Some(Cidr { network, prefix })
+    };
+
+    parse().ok_or_else(|| "expected an ip range like \"10.0.0.0/8\" or \"fd00::/8\"".to_string())
+}
+
+/// Proxies (i.e an ingress) trusted to provide the client address and
+/// scheme in the "X-Forwarded-For" and "X-Forwarded-Proto" headers
+#[derive(Debug, Default, Clone)]
+pub struct TrustedProxies {
+    ranges: Vec<Cidr>,
+}
+
+impl TrustedProxies {
+    /// Creates the trusted proxies from their address `ranges`
+    pub fn new(ranges: Vec<Cidr>) -> Self {
+        Self { ranges }
+    }
+
+    /// Whether no proxies are trusted
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Checks if the `ip` is a trusted proxy
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    /// Provides the address of the client of a request from the `peer` it
+    /// was received from. Requests from trusted proxies use the address
+    /// before the last trusted proxy in the "X-Forwarded-For" `headers`, the
+    /// addresses before it could have been set by the client
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer.to_canonical();
+
+        if !self.is_trusted(client) {
+            return client;
+        }
+
+        let forwarded = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>();
+
+        for address in forwarded.into_iter().rev() {
+            let Ok(address) = address.trim().parse::<IpAddr>() else {
+                break;
+            };
+
+            client = address.to_canonical();
+            if !self.is_trusted(client) {
+                break;
+            }
+        }
+
+        client
+    }
+
+    /// Provides the origin (i.e "https://convert.example.com") clients used
+    /// to reach the server when a request from the `peer` was forwarded by a
+    /// trusted proxy providing the "X-Forwarded-Proto" `headers`. The host is
+    /// taken from "X-Forwarded-Host" falling back to "Host"
+    pub fn public_origin(&self, peer: IpAddr, headers: &HeaderMap) -> Option<String> {
+        if !self.is_trusted(peer.to_canonical()) {
+            return None;
+        }
+
+        // Proxies chaining the headers add their own value last, the first
+        // value is from the proxy the client connected to
+        let first = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+
+        let proto = first("x-forwarded-proto")?.to_ascii_lowercase();
+        if proto != "http" && proto != "https" {
+            return None;
+        }
+
+        let host = first("x-forwarded-host").or_else(|| first("host"))?;
+        if host.contains(['/', '\\', '@', '?', '#', ' ']) {
+            return None;
+        }
+
+        Some(format!("{proto}://{host}"))
+    }
+}
+
+/// Runs the `future` with the public `origin` of the request, URLs generated
+/// by the future are made absolute using the origin
+pub async fn scope<F: Future>(origin: Option<String>, future: F) -> F::Output {
+    PUBLIC_ORIGIN.scope(origin, future).await
+}
+
+/// Makes the server relative `path` absolute when the public origin of the
+/// current request is known, otherwise the path is kept relative
+pub fn public_url(path: String) -> String {
+    PUBLIC_ORIGIN
+        .try_with(|origin| origin.as_deref().map(|origin| format!("{origin}{path}")))
+        .ok()
+        .flatten()
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn proxies(ranges: &[&str]) -> TrustedProxies {
+        TrustedProxies::new(
+            ranges
+                .iter()
+                .map(|range| cidr_arg(range).unwrap())
+                .collect(),
+        )
+    }
+
+    fn forwarded_for(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_matches_ipv4_ranges() {
+        let range = cidr_arg("10.0.0.0/8").unwrap();
+        assert!(range.contains(ip("10.0.0.1")));
+        assert!(range.contains(ip("10.255.255.255")));
+        assert!(!range.contains(ip("11.0.0.1")));
+        assert!(!range.contains(ip("9.255.255.255")));
+
+        // Mapped addresses are matched as IPv4
+        assert!(range.contains(ip("::ffff:10.1.2.3")));
+        assert!(!range.contains(ip("fd00::1")));
+
+        let range = cidr_arg("192.168.1.10").unwrap();
+        assert!(range.contains(ip("192.168.1.10")));
+        assert!(!range.contains(ip("192.168.1.11")));
+
+        let range = cidr_arg("0.0.0.0/0").unwrap();
+        assert!(range.contains(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn cidr_matches_ipv6_ranges() {
+        let range = cidr_arg("fd00::/8").unwrap();
+        assert!(range.contains(ip("fd12:3456::1")));
+        assert!(!range.contains(ip("fe80::1")));
+        assert!(!range.contains(ip("10.0.0.1")));
+
+        let range = cidr_arg("::/0").unwrap();
+        assert!(range.contains(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn cidr_rejects_invalid_ranges() {
+        assert!(cidr_arg("10.0.0.0/33").is_err());
+        assert!(cidr_arg("fd00::/129").is_err());
+        assert!(cidr_arg("10.0.0.0/").is_err());
+        assert!(cidr_arg("proxy.internal").is_err());
+    }
+
+    #[test]
+    fn client_ip_ignores_headers_from_untrusted_peers() {
+        let proxies = proxies(&["10.0.0.0/8"]);
+        let headers = forwarded_for(&["198.51.100.1"]);
+
+        assert_eq!(
+            proxies.client_ip(ip("203.0.113.7"), &headers),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn client_ip_uses_the_rightmost_untrusted_hop() {
+        let proxies = proxies(&["10.0.0.0/8"]);
+
+        // The client prepended a spoofed address before connecting to the
+        // first proxy, which appended the real client address
+        let headers = forwarded_for(&["1.1.1.1, 198.51.100.1, 10.0.0.5"]);
+        assert_eq!(
+            proxies.client_ip(ip("10.0.0.1"), &headers),
+            ip("198.51.100.1")
+        );
+
+        // Values from several headers are walked as a single list
+        let headers = forwarded_for(&["1.1.1.1, 198.51.100.1", "10.0.0.5"]);
+        assert_eq!(
+            proxies.client_ip(ip("10.0.0.1"), &headers),
+            ip("198.51.100.1")
+        );
+    }
+
+    #[test]
+    fn client_ip_stops_at_unparseable_hops() {
+        let proxies = proxies(&["10.0.0.0/8"]);
+
+        // Only proxies are known, the last trusted address is used
+        let headers = forwarded_for(&["unknown, 10.0.0.5"]);
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &headers), ip("10.0.0.5"));
+
+        // Without a header the trusted peer is the client
+        assert_eq!(
+            proxies.client_ip(ip("10.0.0.1"), &HeaderMap::new()),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn client_ip_walks_past_every_trusted_proxy() {
+        let proxies = proxies(&["10.0.0.0/8", "fd00::/8"]);
+        let headers = forwarded_for(&["198.51.100.1, fd00::5, 10.0.0.5"]);
+
+        assert_eq!(
+            proxies.client_ip(ip("::ffff:10.0.0.1"), &headers),
+            ip("198.51.100.1")
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{
+    convert::{ConvertDiagnostics, ConvertError, ConvertOptions},
+    detect,
+    error::HttpError,
+    runner::FailureClass,
+    tempfiles::random_id,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::fs;
+use tracing::{debug, error};
+
+/// Name of the file containing the details of the failure within an entry
+const FAILURE_FILE: &str = "failure.json";
+
+/// Name of the file containing the uploaded input within an entry
+const INPUT_FILE: &str = "input";
+
+/// Longest interval between checks for expired entries
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Preserves the inputs of failed conversions along with the error reported
+/// by office so failures reported against a specific file can be reproduced
+#[derive(Debug)]
+pub struct Quarantine {
+    /// Directory quarantined inputs are written to
+    pub dir: PathBuf,
+    /// Time quarantined inputs are kept for
+    pub max_age: Duration,
+}
+
+/// Details of a quarantined conversion failure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    /// Unique ID of the entry
+    pub id: String,
+    /// Name of the uploaded file
+    pub file_name: Option<String>,
+    /// Size of the uploaded file in bytes
+    pub size: u64,
+    /// Format detected from the contents of the uploaded file
+    pub detected_format: Option<String>,
+    /// Options the file was converted with
+    pub options: serde_json::Value,
+    /// Error code of the failure, [None] for unexpected failures
+    pub code: Option<String>,
+    /// Error reported for the failure including its causes
+    pub error: String,
+    /// Time from the request reaching the converter queue until the
+    /// failure in milliseconds
+    pub elapsed_ms: u64,
+    /// Diagnostics reported by the converter, [None] when the failure
+    /// happened before the document reached office
+    pub diagnostics: Option<ConvertDiagnostics>,
+    /// When the failure happened (Seconds since the unix epoch)
+    pub created_at: u64,
+}
+
+/// Conversion failure to quarantine
+pub struct FailedConversion<'a> {
+    /// Uploaded input that failed to convert
+    pub input: &'a [u8],
+    /// Name of the uploaded file
+    pub file_name: Option<&'a str>,
+    /// Options the file was converted with
+    pub options: &'a ConvertOptions,
+    /// Error the conversion failed with
+    pub error: &'a anyhow::Error,
+    /// Diagnostics reported by the converter
+    pub diagnostics: Option<ConvertDiagnostics>,
+    /// Time from the request reaching the converter queue until the failure
+    pub elapsed: Duration,
+}
+
+impl Quarantine {
+    /// Checks if the conversion failure `err` is quarantined, failures caused
+    /// by the request (i.e encrypted files or unsupported options) are not
+    pub fn is_quarantined(err: &anyhow::Error) -> bool {
+        matches!(
+            FailureClass::of(err),
+            FailureClass::Corrupted | FailureClass::Other
+        )
+    }
+
+    /// Quarantines the input of the `failure`, provides the ID of the entry
+    pub async fn add(&self, failure: FailedConversion<'_>) -> anyhow::Result<String> {
+        let FailedConversion {
+            input,
+            file_name,
+            options,
+            error,
+            diagnostics,
+            elapsed,
+        } = failure;
+
+        let id = random_id();
+        let entry_dir = self.dir.join(&id);
+
+        let entry = QuarantineEntry {
+            id: id.clone(),
+            file_name: file_name.map(str::to_string),
+            size: input.len() as u64,
+            detected_format: detect::detect(input).map(|format| format.name().to_string()),
+            options: serde_json::to_value(options).context("failed to serialize options")?,
+            code: error
+                .downcast_ref::<ConvertError>()
+                .and_then(|err| err.code())
+                .map(str::to_string),
+            error: format!("{error:#}"),
+            elapsed_ms: elapsed.as_millis() as u64,
+            diagnostics,
+            created_at: unix_secs(),
+        };
+
+        fs::create_dir_all(&entry_dir)
+            .await
+            .context("failed to create quarantine entry")?;
+        fs::write(entry_dir.join(INPUT_FILE), input)
+            .await
+            .context("failed to write quarantined input")?;
+
+        // Details are written last, entries without details are incomplete and not listed
+        let details = serde_json::to_vec_pretty(&entry).context("failed to serialize entry")?;
+        fs::write(entry_dir.join(FAILURE_FILE), details)
+            .await
+            .context("failed to write quarantine details")?;
+
+        Ok(id)
+    }
+
+    /// Lists the quarantined entries, newest first
+    pub async fn list(&self) -> anyhow::Result<Vec<QuarantineEntry>> {
+        let mut entries = Vec::new();
+
+        let mut dir = match fs::read_dir(&self.dir).await {
+            Ok(value) => value,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+            Err(err) => return Err(err).context("failed to read quarantine directory"),
+        };
+
+        while let Some(entry) = dir
+            .next_entry()
+            .await
+            .context("failed to read quarantine directory")?
+        {
+            if !entry.file_type().await.is_ok_and(|value| value.is_dir()) {
+                continue;
+            }
+
+            if let Some(entry) = read_entry(&entry.path()).await? {
+                entries.push(entry);
+            }
+        }
+
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.created_at));
+
+        Ok(entries)
+    }
+
+    /// Reads the entry with the `id` and its quarantined input, [None] when
+    /// there is no entry with the `id`
+    pub async fn get(&self, id: &str) -> anyhow::Result<Option<(QuarantineEntry, Vec<u8>)>> {
+        if id.is_empty() || !id.chars().all(|char| char.is_ascii_alphanumeric()) {
+            return Ok(None);
+        }
+
+        let entry_dir = self.dir.join(id);
+
+        let Some(entry) = read_entry(&entry_dir).await? else {
+            return Ok(None);
+        };
+
+        let input = match fs::read(entry_dir.join(INPUT_FILE)).await {
+            Ok(value) => value,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).context("failed to read quarantined input"),
+        };
+
+        Ok(Some((entry, input)))
+    }
+
+    /// Removes expired entries at an interval based on the retention forever
+    pub async fn schedule(&self) {
+        let interval = (self.max_age / 4).clamp(Duration::from_secs(1), MAX_CHECK_INTERVAL);
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            match self.remove_expired().await {
+                Ok(0) => {}
+                Ok(removed) => debug!(removed, "removed expired quarantine entries"),
+                Err(cause) => error!(?cause, "failed to remove expired quarantine entries"),
+            }
+        }
+    }
+
+    /// Removes the entries that were created longer than the retention period
+    /// ago, returns the number of entries removed
+    pub async fn remove_expired(&self) -> anyhow::Result<u64> {
+        let mut removed = 0;
+
+        let mut dir = match fs::read_dir(&self.dir).await {
+            Ok(value) => value,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err).context("failed to read quarantine directory"),
+        };
+
+        while let Some(entry) = dir
+            .next_entry()
+            .await
+            .context("failed to read quarantine directory")?
+        {
+            let metadata = match entry.metadata().await {
+                Ok(value) => value,
+                // Entry was removed while reading the directory
+                Err(_) => continue,
+            };
+
+            if !metadata.is_dir() {
+                continue;
+            }
+
+            let expired = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age >= self.max_age);
+
+            if !expired {
+                continue;
+            }
+
+            match fs::remove_dir_all(entry.path()).await {
+                Ok(_) => removed += 1,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err).context("failed to remove quarantine entry"),
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Reads the details of the entry in `entry_dir`, [None] when the entry is
+/// incomplete or missing
+async fn read_entry(entry_dir: &Path) -> anyhow::Result<Option<QuarantineEntry>> {
+    let details = match fs::read(entry_dir.join(FAILURE_FILE)).await {
+        Ok(value) => value,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).context("failed to read quarantine details"),
+    };
+
+    let entry = serde_json::from_slice(&details).context("invalid quarantine details")?;
+
+    Ok(Some(entry))
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default()
+}
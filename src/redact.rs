@@ -0,0 +1,724 @@
+use crate::{
+    convert::{self, ConvertError},
+    error::HttpError,
+    pdf_export,
+    tempfiles::{random_id, TempFile},
+};
+use anyhow::Context;
+use axum::http::StatusCode;
+use quick_xml::{
+    events::{BytesStart, BytesText, Event},
+    Reader, Writer,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{Cursor, Read, Write},
+    path::Path,
+};
+use thiserror::Error;
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+/// Maximum number of terms that can be redacted
+const MAX_TERMS: usize = 100;
+
+/// Maximum length of a redacted term
+const MAX_TERM_LEN: usize = 256;
+
+/// Maximum number of page regions that can be redacted
+const MAX_REGIONS: usize = 1000;
+
+/// Resolution (DPI) pages are rasterized at when redacting regions
+const REGION_RESOLUTION: u32 = 150;
+
+/// Character redacted text is replaced with
+const REDACTED_CHAR: char = '█';
+
+/// Files of an ODF package containing the text of the document, also
+/// matched within the packages of embedded objects (i.e "Object 1/content.xml")
+const TEXT_FILES: &[&str] = &["content.xml", "styles.xml", "meta.xml"];
+
+/// Folders of an ODF package holding renderings of the unredacted document
+/// (The first page thumbnail and the replacement images of embedded objects)
+const RENDERED_FOLDERS: &[&str] = &["Thumbnails/", "ObjectReplacements/"];
+
+/// Manifest listing the files of an ODF package
+const MANIFEST_FILE: &str = "META-INF/manifest.xml";
+
+/// Soft hyphen, invisible unless a word is broken at it so it is ignored
+/// when matching terms
+const SOFT_HYPHEN: char = '\u{ad}';
+
+/// Name of the redaction report within redacted conversions
+pub const BUNDLE_REDACTIONS_NAME: &str = "redactions.json";
+
+/// Text and page regions to redact from the converted PDF
+#[derive(Clone, Default, Serialize)]
+pub struct RedactOptions {
+    /// Terms replaced wherever they appear in the text of the document,
+    /// never logged or serialized
+    #[serde(skip)]
+    pub terms: Vec<String>,
+
+    /// Whether terms only match text of the same case
+    pub match_case: bool,
+
+    /// Regions of pages removed from the converted PDF
+    pub regions: Vec<RedactRegion>,
+}
+
+impl std::fmt::Debug for RedactOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedactOptions")
+            .field("terms", &self.terms.len())
+            .field("match_case", &self.match_case)
+            .field("regions", &self.regions)
+            .finish()
+    }
+}
+
+/// Region of a page removed from the converted PDF, i.e
+/// {"page": 1, "rect": [72, 700, 300, 720]}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactRegion {
+    /// Page the region is on (Starting at 1)
+    pub page: u64,
+
+    /// Area of the region in points from the bottom left corner of the
+    /// page (left, bottom, right, top)
+    pub rect: [f64; 4],
+}
+
+/// Report of what was redacted from a converted PDF
+#[derive(Debug, Serialize)]
+pub struct RedactionReport {
+    /// Number of matches of each term
+    pub terms: Vec<TermRedactions>,
+    /// Number of page regions removed
+    pub regions: usize,
+    /// Whether the pages were rasterized to remove the regions, rasterized
+    /// pages have no selectable text
+    pub rasterized: bool,
+}
+
+/// Number of matches of a redacted term
+#[derive(Debug, Serialize)]
+pub struct TermRedactions {
+    /// Term that was redacted
+    pub term: String,
+    /// Number of times the term was replaced
+    pub matches: u64,
+}
+
+/// Errors from invalid redaction requests
+#[derive(Debug, Error)]
+pub enum RedactError {
+    /// Neither terms nor regions were provided
+    #[error("at least one term or region must be provided to redact")]
+    Missing,
+
+    /// A term was empty or too long
+    #[error("terms must not be empty or longer than {MAX_TERM_LEN} characters")]
+    InvalidTerm,
+
+    /// Too many terms were provided
+    #[error("at most {MAX_TERMS} terms can be redacted")]
+    TooManyTerms,
+
+    /// Regions were not a valid JSON array of regions
+    #[error("regions must be a JSON array of up to {MAX_REGIONS} page regions")]
+    InvalidRegions,
+}
+
+impl HttpError for RedactError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("invalid_redaction")
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            RedactError::Missing => None,
+            RedactError::InvalidTerm | RedactError::TooManyTerms => {
+                Some(json!({ "field": "terms" }))
+            }
+            RedactError::InvalidRegions => Some(json!({ "field": "regions" })),
+        }
+    }
+}
+
+impl RedactOptions {
+    /// Parses the redaction options from the `terms` and the JSON array of
+    /// `regions` (i.e [{"page": 1, "rect": [72, 700, 300, 720]}])
+    pub fn parse(
+        terms: Vec<String>,
+        regions: Option<&str>,
+        match_case: bool,
+    ) -> Result<Self, RedactError> {
+        if terms.len() > MAX_TERMS {
+            return Err(RedactError::TooManyTerms);
+        }
+
+        if terms
+            .iter()
+            .any(|term| term.trim().is_empty() || term.chars().count() > MAX_TERM_LEN)
+        {
+            return Err(RedactError::InvalidTerm);
+        }
+
+        let regions = match regions {
+            Some(value) => serde_json::from_str::<Vec<RedactRegion>>(value)
+                .ok()
+                .filter(|regions| {
+                    regions.len() <= MAX_REGIONS && regions.iter().all(RedactRegion::is_valid)
+                })
+                .ok_or(RedactError::InvalidRegions)?,
+            None => Vec::new(),
+        };
+
+        if terms.is_empty() && regions.is_empty() {
+            return Err(RedactError::Missing);
+        }
+
+        Ok(Self {
+            terms,
+            match_case,
+            regions,
+        })
+    }
+
+    /// Creates a fingerprint of the options for use in cache keys
+    pub(crate) fn cache_fingerprint(&self) -> String {
+        let regions = serde_json::to_string(&self.regions).unwrap_or_default();
+        let terms = serde_json::to_string(&self.terms).unwrap_or_default();
+
+        format!(
+            "terms={terms} match_case={} regions={regions}",
+            self.match_case
+        )
+    }
+
+    /// Creates the report of the redactions with the number of `matches` of
+    /// each term
+    pub(crate) fn report(&self, matches: &[u64]) -> RedactionReport {
+        RedactionReport {
+            terms: self
+                .terms
+                .iter()
+                .enumerate()
+                .map(|(index, term)| TermRedactions {
+                    term: term.clone(),
+                    matches: matches.get(index).copied().unwrap_or_default(),
+                })
+                .collect(),
+            regions: self.regions.len(),
+            rasterized: !self.regions.is_empty(),
+        }
+    }
+}
+
+impl RedactRegion {
+    /// Checks the region is on a page with an area within the page coordinates
+    fn is_valid(&self) -> bool {
+        let [left, bottom, right, top] = self.rect;
+
+        self.page > 0
+            && self
+                .rect
+                .iter()
+                .all(|value| value.is_finite() && *value >= 0.0)
+            && left < right
+            && bottom < top
+    }
+}
+
+/// Redacts the terms of the `options` from the text of the ODF package at
+/// `path`, creating a new package and providing the number of matches of
+/// each term.
+///
+/// Terms are matched across the runs of text within each paragraph and
+/// heading, a term split across differently formatted text, spaces
+/// elements, soft hyphens or tracked changes is matched
+pub fn redact_package(path: &Path, options: &RedactOptions) -> anyhow::Result<(Vec<u8>, Vec<u64>)> {
+    let file = File::open(path).context("failed to open odf package")?;
+    let mut archive = ZipArchive::new(file).context("failed to read odf package")?;
+
+    let mut output = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut matches = vec![0; options.terms.len()];
+
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .context("failed to read odf package entry")?;
+
+        let name = entry.name().to_string();
+
+        // Renderings are removed from the package along with their manifest
+        // entries, office renders embedded objects again when loading
+        if is_rendered_file(&name) {
+            continue;
+        }
+
+        if name == MANIFEST_FILE {
+            let mut value = String::new();
+            entry
+                .read_to_string(&mut value)
+                .context("failed to read odf package manifest")?;
+
+            let bytes = remove_rendered_entries(&value).context("invalid odf package manifest")?;
+
+            output
+                .start_file(name, SimpleFileOptions::default())
+                .context("failed to create zip entry")?;
+            output
+                .write_all(&bytes)
+                .context("failed to write zip entry")?;
+            continue;
+        }
+
+        let file_name = name.rsplit('/').next().unwrap_or_default();
+        if TEXT_FILES.contains(&file_name) {
+            let mut value = String::new();
+            entry
+                .read_to_string(&mut value)
+                .with_context(|| format!("failed to read {name} from odf package"))?;
+
+            let bytes = redact_xml(&value, options, &mut matches)
+                .with_context(|| format!("invalid {name}"))?;
+
+            output
+                .start_file(name, SimpleFileOptions::default())
+                .context("failed to create zip entry")?;
+            output
+                .write_all(&bytes)
+                .context("failed to write zip entry")?;
+            continue;
+        }
+
+        drop(entry);
+
+        // The "mimetype" entry must be stored first and uncompressed, raw
+        // copies keep the order and compression of the original package
+        let entry = archive
+            .by_index_raw(index)
+            .context("failed to read odf package entry")?;
+        output
+            .raw_copy_file(entry)
+            .context("failed to copy odf package entry")?;
+    }
+
+    let output = output.finish().context("failed to finish zip")?;
+
+    Ok((output.into_inner(), matches))
+}
+
+/// Whether the package file `name` is a rendering of the unredacted document
+fn is_rendered_file(name: &str) -> bool {
+    let name = name.trim_start_matches("./");
+    RENDERED_FOLDERS
+        .iter()
+        .any(|folder| name.starts_with(folder))
+}
+
+/// Removes the entries of the rendered files from the package manifest
+fn remove_rendered_entries(value: &str) -> anyhow::Result<Vec<u8>> {
+    let mut reader = Reader::from_str(value);
+    let mut writer = Writer::new(Vec::new());
+
+    loop {
+        match reader.read_event().context("failed to parse xml")? {
+            Event::Eof => break,
+            Event::Empty(start)
+                if start.name().as_ref() == b"manifest:file-entry"
+                    && start
+                        .try_get_attribute("manifest:full-path")?
+                        .and_then(|value| value.unescape_value().ok())
+                        .is_some_and(|path| is_rendered_file(&path)) => {}
+            event => writer.write_event(event)?,
+        }
+    }
+
+    Ok(writer.into_inner())
+}
+
+/// Redacts the terms from the text of an ODF package xml file, text within
+/// paragraphs and headings is buffered until the paragraph ends so terms
+/// are matched across its runs
+fn redact_xml(
+    value: &str,
+    options: &RedactOptions,
+    matches: &mut [u64],
+) -> anyhow::Result<Vec<u8>> {
+    let mut reader = Reader::from_str(value);
+    let mut writer = Writer::new(Vec::new());
+    let mut paragraph = ParagraphBuffer::default();
+
+    loop {
+        let event = reader.read_event().context("failed to parse xml")?;
+
+        match event {
+            Event::Eof => break,
+            Event::Start(start) if is_paragraph(&start) => {
+                paragraph.open();
+                paragraph.events.push(Event::Start(start));
+            }
+            Event::End(end) if paragraph.is_open() && is_paragraph_name(end.name().as_ref()) => {
+                paragraph.events.push(Event::End(end));
+
+                if paragraph.close() {
+                    paragraph.write(&mut writer, options, matches)?;
+                }
+            }
+            Event::Text(text) if paragraph.is_open() => {
+                let value = text.unescape().context("invalid xml text")?;
+                paragraph.push_text(&value);
+                paragraph.events.push(Event::Text(text));
+            }
+            Event::Empty(start) if paragraph.is_open() => {
+                paragraph.push_element(&start)?;
+                paragraph.events.push(Event::Empty(start));
+            }
+            Event::Start(start) if paragraph.is_open() => {
+                paragraph.push_element(&start)?;
+                paragraph.events.push(Event::Start(start));
+            }
+            event if paragraph.is_open() => paragraph.events.push(event),
+
+            // Text outside of paragraphs (i.e document metadata)
+            Event::Text(text) => {
+                let value = text.unescape().context("invalid xml text")?;
+
+                match redact_text(&value, options, matches) {
+                    Some(redacted) => writer.write_event(Event::Text(BytesText::new(&redacted)))?,
+                    None => writer.write_event(Event::Text(text))?,
+                }
+            }
+            event => writer.write_event(event)?,
+        }
+    }
+
+    Ok(writer.into_inner())
+}
+
+/// Whether the element starts a paragraph or heading
+fn is_paragraph(start: &BytesStart<'_>) -> bool {
+    is_paragraph_name(start.name().as_ref())
+}
+
+/// Whether the element `name` is a paragraph or heading
+fn is_paragraph_name(name: &[u8]) -> bool {
+    matches!(name, b"text:p" | b"text:h")
+}
+
+/// Character within the text of a paragraph
+struct ParagraphChar {
+    /// The character
+    value: char,
+    /// Buffered text and character index the character is from, [None] for
+    /// characters of elements (i.e "text:s" spaces)
+    source: Option<(usize, usize)>,
+}
+
+/// Events of a paragraph buffered until the paragraph ends, paragraphs
+/// nested within it (i.e annotations and notes) have their own text
+#[derive(Default)]
+struct ParagraphBuffer<'a> {
+    /// Buffered events of the outermost paragraph
+    events: Vec<Event<'a>>,
+    /// Characters of the buffered text events along with the index of the
+    /// event and whether they were redacted
+    texts: Vec<(usize, Vec<char>, bool)>,
+    /// Text of each buffered paragraph
+    paragraphs: Vec<Vec<ParagraphChar>>,
+    /// Paragraphs that are open, innermost last
+    open: Vec<usize>,
+}
+
+impl<'a> ParagraphBuffer<'a> {
+    /// Whether a paragraph is being buffered
+    fn is_open(&self) -> bool {
+        !self.open.is_empty()
+    }
+
+    /// Opens a paragraph
+    fn open(&mut self) {
+        self.paragraphs.push(Vec::new());
+        self.open.push(self.paragraphs.len() - 1);
+    }
+
+    /// Closes the innermost paragraph, provides whether the outermost
+    /// paragraph was closed
+    fn close(&mut self) -> bool {
+        self.open.pop();
+        self.open.is_empty()
+    }
+
+    /// Adds the `value` of a text event about to be buffered to the
+    /// innermost paragraph
+    fn push_text(&mut self, value: &str) {
+        let index = self.texts.len();
+        let chars: Vec<char> = value.chars().collect();
+
+        if let Some(paragraph) = self.open.last().map(|open| &mut self.paragraphs[*open]) {
+            paragraph.extend(
+                chars
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, value)| **value != SOFT_HYPHEN)
+                    .map(|(position, value)| ParagraphChar {
+                        value: *value,
+                        source: Some((index, position)),
+                    }),
+            );
+        }
+
+        self.texts.push((self.events.len(), chars, false));
+    }
+
+    /// Adds the characters an element within the innermost paragraph stands
+    /// for (Spaces, tabs and line breaks)
+    fn push_element(&mut self, start: &BytesStart<'_>) -> anyhow::Result<()> {
+        let (value, count) = match start.name().as_ref() {
+            b"text:s" => {
+                let count = start
+                    .try_get_attribute("text:c")?
+                    .and_then(|value| value.unescape_value().ok()?.parse().ok())
+                    .unwrap_or(1usize);
+                (' ', count.min(MAX_TERM_LEN))
+            }
+            b"text:tab" => ('\t', 1),
+            b"text:line-break" => ('\n', 1),
+            _ => return Ok(()),
+        };
+
+        if let Some(paragraph) = self.open.last().map(|open| &mut self.paragraphs[*open]) {
+            paragraph.extend((0..count).map(|_| ParagraphChar {
+                value,
+                source: None,
+            }));
+        }
+
+        Ok(())
+    }
+
+    /// Redacts the terms from the text of the buffered paragraphs and writes
+    /// the buffered events, clearing the buffer
+    fn write(
+        &mut self,
+        writer: &mut Writer<Vec<u8>>,
+        options: &RedactOptions,
+        matches: &mut [u64],
+    ) -> anyhow::Result<()> {
+        for paragraph in self.paragraphs.drain(..) {
+            let chars: Vec<char> = paragraph.iter().map(|value| value.value).collect();
+
+            for (start, length, term) in find_terms(&chars, options) {
+                matches[term] += 1;
+
+                for (text, position) in paragraph[start..start + length]
+                    .iter()
+                    .filter_map(|value| value.source)
+                {
+                    let (_, chars, redacted) = &mut self.texts[text];
+                    chars[position] = REDACTED_CHAR;
+                    *redacted = true;
+                }
+            }
+        }
+
+        let mut texts = self.texts.drain(..).peekable();
+
+        for (index, event) in self.events.drain(..).enumerate() {
+            let redacted = texts
+                .next_if(|(event, _, _)| *event == index)
+                .filter(|(_, _, redacted)| *redacted);
+
+            match redacted {
+                Some((_, chars, _)) => {
+                    let value: String = chars.into_iter().collect();
+                    writer.write_event(Event::Text(BytesText::new(&value)))?
+                }
+                None => writer.write_event(event)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces the terms within `value`, longer terms are matched first.
+/// Provides [None] when nothing was replaced
+fn redact_text(value: &str, options: &RedactOptions, matches: &mut [u64]) -> Option<String> {
+    let mut chars: Vec<char> = value.chars().collect();
+    let found = find_terms(&chars, options);
+
+    for (start, length, term) in &found {
+        chars[*start..*start + *length].fill(REDACTED_CHAR);
+        matches[*term] += 1;
+    }
+
+    (!found.is_empty()).then(|| chars.into_iter().collect())
+}
+
+/// Finds the terms within `chars`, longer terms are matched first. Provides
+/// the start, length and term index of each match
+fn find_terms(chars: &[char], options: &RedactOptions) -> Vec<(usize, usize, usize)> {
+    let terms: Vec<Vec<char>> = options
+        .terms
+        .iter()
+        .map(|term| term.chars().collect())
+        .collect();
+
+    let mut order: Vec<usize> = (0..terms.len()).collect();
+    order.sort_by_key(|index| std::cmp::Reverse(terms[*index].len()));
+
+    let is_same = |a: char, b: char| {
+        if options.match_case {
+            a == b
+        } else {
+            a == b || a.to_lowercase().eq(b.to_lowercase())
+        }
+    };
+
+    let mut found = Vec::new();
+    let mut position = 0;
+
+    while position < chars.len() {
+        let matched = order.iter().copied().find(|index| {
+            let term = &terms[*index];
+
+            chars.len() - position >= term.len()
+                && chars[position..position + term.len()]
+                    .iter()
+                    .zip(term)
+                    .all(|(a, b)| is_same(*a, *b))
+        });
+
+        match matched {
+            Some(index) => {
+                let length = terms[index].len();
+                found.push((position, length, index));
+                position += length;
+            }
+            None => position += 1,
+        }
+    }
+
+    found
+}
+
+/// Checks none of the terms of the `options` remain in the text of the
+/// converted PDF at `path`, failing closed when a term was not redacted
+/// (i.e text office generated while exporting such as fields)
+pub(crate) fn verify_redacted(path: &Path, options: &RedactOptions) -> anyhow::Result<()> {
+    let text = pdf_export::extract_text(path)?;
+
+    // Text extracted from a PDF is laid out with spaces and line breaks
+    // between the runs of text, so whitespace is compared collapsed
+    let collapse = |value: &str| {
+        value
+            .replace(SOFT_HYPHEN, "")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let options = RedactOptions {
+        terms: options.terms.iter().map(|term| collapse(term)).collect(),
+        match_case: options.match_case,
+        regions: Vec::new(),
+    };
+
+    let chars: Vec<char> = collapse(&text).chars().collect();
+    let remaining = find_terms(&chars, &options).len();
+
+    if remaining > 0 {
+        return Err(ConvertError::RedactionIncomplete { remaining }.into());
+    }
+
+    Ok(())
+}
+
+/// Removes the `regions` from the PDF at `path` in place, the pages are
+/// rasterized by Ghostscript with the regions filled in black so nothing
+/// beneath the regions remains in the PDF
+pub(crate) fn redact_regions(path: &Path, regions: &[RedactRegion]) -> anyhow::Result<()> {
+    let bytes = std::fs::read(path).context("failed to read exported pdf")?;
+    let page_count = convert::pdf_page_count(&bytes) as u64;
+
+    if let Some(region) = regions.iter().find(|region| region.page > page_count) {
+        return Err(ConvertError::InvalidPage {
+            page: region.page,
+            page_count,
+        }
+        .into());
+    }
+
+    let prelude = TempFile {
+        path: path.with_file_name(format!("lo_native_redact_{}.ps", random_id())),
+    };
+    std::fs::write(&prelude.path, region_prelude(regions))
+        .context("failed to write redaction regions")?;
+
+    pdf_export::run_ghostscript(
+        path,
+        vec![
+            OsString::from("-sDEVICE=pdfimage24"),
+            OsString::from(format!("-r{REGION_RESOLUTION}")),
+            prelude.path.clone().into_os_string(),
+            path.as_os_str().to_os_string(),
+        ],
+    )
+}
+
+/// Creates the PostScript filling the `regions` of each page in black as
+/// the page is rendered
+fn region_prelude(regions: &[RedactRegion]) -> String {
+    let mut pages: Vec<(u64, Vec<&RedactRegion>)> = Vec::new();
+    for region in regions {
+        match pages.iter_mut().find(|(page, _)| *page == region.page) {
+            Some((_, regions)) => regions.push(region),
+            None => pages.push((region.page, vec![region])),
+        }
+    }
+
+    let mut prelude = String::from("/lo_native_regions <<\n");
+
+    for (page, regions) in pages {
+        prelude.push_str(&format!("  {page} ["));
+
+        for region in regions {
+            let [left, bottom, right, top] = region.rect;
+            prelude.push_str(&format!(
+                " [{left} {bottom} {} {}]",
+                right - left,
+                top - bottom
+            ));
+        }
+
+        prelude.push_str(" ]\n");
+    }
+
+    // EndPage is given the number of pages shown before this one and the
+    // reason, regions are filled unless the device is being deactivated
+    prelude.push_str(
+        ">> def
+<< /EndPage {
+  dup 2 eq { pop pop false } {
+    pop 1 add lo_native_regions exch 2 copy known {
+      get gsave initmatrix 0 setgray { aload pop rectfill } forall grestore
+    } { pop pop } ifelse
+    true
+  } ifelse
+} bind >> setpagedevice
+",
+    );
+
+    prelude
+}
@@ -0,0 +1,78 @@
+use anyhow::Context;
+use std::{path::PathBuf, time::Duration};
+use tokio::fs;
+use tracing::{debug, error};
+
+/// Longest interval between checks for expired outputs
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Removes stored outputs once they are older than the retention period,
+/// keeping the disk usage of the output directory bounded
+pub struct OutputRetention {
+    /// Directory stored outputs are written to
+    pub output_dir: PathBuf,
+    /// Time stored outputs are kept for
+    pub max_age: Duration,
+}
+
+impl OutputRetention {
+    /// Removes expired outputs at an interval based on the retention forever
+    pub async fn schedule(self) {
+        let interval = (self.max_age / 4).clamp(Duration::from_secs(1), MAX_CHECK_INTERVAL);
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            match self.remove_expired().await {
+                Ok(0) => {}
+                Ok(removed) => debug!(removed, "removed expired outputs"),
+                Err(cause) => error!(?cause, "failed to remove expired outputs"),
+            }
+        }
+    }
+
+    /// Removes the outputs (and partially written outputs) that were last
+    /// modified longer than the retention period ago, returns the number of
+    /// files removed
+    pub async fn remove_expired(&self) -> anyhow::Result<u64> {
+        let mut removed = 0;
+
+        let mut dir = fs::read_dir(&self.output_dir)
+            .await
+            .context("failed to read output directory")?;
+
+        while let Some(entry) = dir
+            .next_entry()
+            .await
+            .context("failed to read output directory")?
+        {
+            let metadata = match entry.metadata().await {
+                Ok(value) => value,
+                // File was removed while reading the directory
+                Err(_) => continue,
+            };
+
+            if !metadata.is_file() {
+                continue;
+            }
+
+            let expired = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age >= self.max_age);
+
+            if !expired {
+                continue;
+            }
+
+            match fs::remove_file(entry.path()).await {
+                Ok(_) => removed += 1,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err).context("failed to remove expired output"),
+            }
+        }
+
+        Ok(removed)
+    }
+}
@@ -0,0 +1,2151 @@
+use crate::{
+    config::{QueueConfig, ServerConfig, TrimConfig, TrimPolicy, WatchdogConfig},
+    convert::{
+        ConvertBackend, ConvertDiagnostics, ConvertError, ConvertOptions, ConvertedDocument,
+        DocumentInput, DocumentStats,
+    },
+    embedded,
+    error::{DynHttpError, HttpError},
+    memory,
+    metrics::{RunnerMetrics, WorkKind},
+    mhtml::{self, WebArchiveError},
+    pipeline::{PipelineOptions, PipelineStep},
+    priority::{self, PriorityGate},
+    tempfiles::{random_id, TempFile},
+    throttle::QueueLimits,
+};
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use bytes::Bytes;
+use libreofficekit::{FilterTypes, OfficeVersionInfo};
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    time::Instant,
+};
+use tracing::{debug, error, warn, Span};
+
+/// Message sent to the office runner along with the span of the request
+/// that sent it, so runner logs can be correlated with the request
+pub struct RunnerMsg {
+    /// The message to process
+    msg: OfficeMsg,
+    /// Span of the request that sent the message
+    span: Span,
+    /// When the message started waiting for the runner
+    queued: Instant,
+    /// Progress of the work tracked by the task that sent the message
+    progress: Option<Arc<WorkProgress>>,
+}
+
+tokio::task_local! {
+    /// Progress of the work sent to the runner by the current task
+    static PROGRESS: Arc<WorkProgress>;
+}
+
+/// Runs the `future` tracking the progress office reports for the work it
+/// sends to the runner in `progress`
+pub async fn track_progress<F: Future>(progress: Arc<WorkProgress>, future: F) -> F::Output {
+    PROGRESS.scope(progress, future).await
+}
+
+/// Progress reported by office for a piece of work while the runner is
+/// processing it
+#[derive(Debug)]
+pub struct WorkProgress {
+    /// Current [RunnerPhase] of the work
+    phase: AtomicU8,
+    /// Progress percentage reported by office for the current phase
+    /// ([NO_PROGRESS] when not reported)
+    progress: AtomicU8,
+}
+
+impl Default for WorkProgress {
+    fn default() -> Self {
+        Self {
+            phase: AtomicU8::new(RunnerPhase::Idle as u8),
+            progress: AtomicU8::new(NO_PROGRESS),
+        }
+    }
+}
+
+impl WorkProgress {
+    /// Provides the current phase of the work, [RunnerPhase::Idle] until
+    /// the runner starts processing it
+    pub fn phase(&self) -> RunnerPhase {
+        RunnerPhase::from_u8(self.phase.load(Ordering::Relaxed))
+    }
+
+    /// Provides the progress percentage of the current phase if reported
+    pub fn progress(&self) -> Option<u8> {
+        match self.progress.load(Ordering::Relaxed) {
+            NO_PROGRESS => None,
+            progress => Some(progress),
+        }
+    }
+}
+
+/// Messages the office runner can process
+pub enum OfficeMsg {
+    /// Message to convert a file
+    Convert {
+        /// The file to convert
+        input: DocumentInput,
+
+        /// Options for the conversion
+        options: Box<ConvertOptions>,
+
+        /// The return channel for sending back the result
+        tx: oneshot::Sender<anyhow::Result<ConvertedDocument>>,
+
+        /// Return channel for the diagnostics of the conversion, only sent
+        /// when the conversion fails
+        diagnostics: Option<oneshot::Sender<ConvertDiagnostics>>,
+    },
+
+    /// Message to run a conversion pipeline on a file
+    Pipeline {
+        /// The file to run the pipeline on
+        input: DocumentInput,
+
+        /// Steps of the pipeline
+        steps: Vec<PipelineStep>,
+
+        /// Options applied to the pipeline
+        options: PipelineOptions,
+
+        /// The return channel for sending back the zip of artifacts
+        tx: oneshot::Sender<anyhow::Result<Bytes>>,
+    },
+
+    /// Message to extract the embedded images and objects from a file
+    ExtractAssets {
+        /// The file to extract from
+        input: DocumentInput,
+
+        /// The return channel for sending back the zip of assets
+        tx: oneshot::Sender<anyhow::Result<Bytes>>,
+    },
+
+    /// Message to extract the document statistics from a file
+    ExtractStats {
+        /// The file to extract from
+        input: DocumentInput,
+
+        /// The return channel for sending back the statistics
+        tx: oneshot::Sender<anyhow::Result<DocumentStats>>,
+    },
+
+    /// Message to query the details of the office install
+    RefreshDetails {
+        /// The return channel for sending back the details, [None] when
+        /// office has been shut down while idle
+        tx: oneshot::Sender<Option<OfficeDetails>>,
+    },
+
+    /// Message to replace the backend with a fresh instance
+    Recycle {
+        /// Runs after the current instance has shut down and before the
+        /// new instance is created (i.e resetting the profile)
+        prepare: Box<dyn FnOnce() -> anyhow::Result<()> + Send>,
+
+        /// The return channel for sending back the result
+        tx: oneshot::Sender<anyhow::Result<()>>,
+    },
+
+    /// Tells office to clean up and trim its memory usage
+    CollectGarbage,
+}
+
+impl OfficeMsg {
+    /// Kind of document work the message is, [None] for messages that don't
+    /// process a document
+    fn work(&self) -> Option<WorkKind> {
+        match self {
+            OfficeMsg::Convert { .. } => Some(WorkKind::Convert),
+            OfficeMsg::Pipeline { .. } => Some(WorkKind::Pipeline),
+            OfficeMsg::ExtractAssets { .. } => Some(WorkKind::ExtractAssets),
+            OfficeMsg::ExtractStats { .. } => Some(WorkKind::ExtractStats),
+            _ => None,
+        }
+    }
+
+    /// Whether the message is document work that requires office
+    fn is_work(&self) -> bool {
+        self.work().is_some()
+    }
+
+    /// Document processed by the message
+    fn input(&self) -> Option<&DocumentInput> {
+        match self {
+            OfficeMsg::Convert { input, .. }
+            | OfficeMsg::Pipeline { input, .. }
+            | OfficeMsg::ExtractAssets { input, .. }
+            | OfficeMsg::ExtractStats { input, .. } => Some(input),
+            _ => None,
+        }
+    }
+
+    /// Responds to the message with the error `cause`
+    fn fail(self, cause: anyhow::Error) {
+        match self {
+            OfficeMsg::Convert { tx, .. } => _ = tx.send(Err(cause)),
+            OfficeMsg::Pipeline { tx, .. } => _ = tx.send(Err(cause)),
+            OfficeMsg::ExtractAssets { tx, .. } => _ = tx.send(Err(cause)),
+            OfficeMsg::ExtractStats { tx, .. } => _ = tx.send(Err(cause)),
+            OfficeMsg::Recycle { tx, .. } => _ = tx.send(Err(cause)),
+            OfficeMsg::RefreshDetails { .. } | OfficeMsg::CollectGarbage => {}
+        }
+    }
+}
+
+/// Outcome of a conversion sent to the runner
+pub(crate) struct ConvertResult {
+    /// Result of the conversion
+    pub(crate) result: anyhow::Result<ConvertedDocument>,
+    /// Diagnostics of the failed conversion, when requested
+    pub(crate) diagnostics: Option<ConvertDiagnostics>,
+}
+
+/// Handle to send messages to the office runner
+#[derive(Clone)]
+pub struct OfficeHandle {
+    /// Sender for messages to the runner queue
+    tx: mpsc::Sender<RunnerMsg>,
+    /// Receiver for whether any worker is currently converting a document
+    pub(crate) converting: watch::Receiver<bool>,
+    /// Workers of the runner
+    workers: Arc<[WorkerHandle]>,
+    /// Number of requests waiting to send a message to the runner
+    pub(crate) waiting: Arc<watch::Sender<usize>>,
+    /// Maximum time to wait for the runner to accept a message, changed
+    /// by throttle windows
+    max_queue_wait: Arc<Mutex<Option<Duration>>>,
+    /// Activity of the first worker, the totals and telemetry of the work
+    /// are shared by every worker
+    activity: Arc<RunnerActivity>,
+    /// Whether maintenance is in progress
+    maintenance: Arc<AtomicBool>,
+    /// Admits work to the runner queue by the priority tier of the request
+    priority: Arc<PriorityGate>,
+}
+
+/// Activity of a runner worker, used to estimate how long requests will
+/// wait, report progress and detect hung conversions
+#[derive(Debug)]
+pub struct RunnerActivity {
+    /// Activity of the work processed by every worker of the runner
+    pool: Arc<PoolActivity>,
+    /// When the worker last reported activity (Milliseconds since the unix epoch)
+    last_activity_ms: AtomicU64,
+    /// Current [RunnerPhase] of the worker
+    phase: AtomicU8,
+    /// Progress percentage reported by office for the current phase
+    /// ([NO_PROGRESS] when not reported)
+    progress: AtomicU8,
+    /// Whether the backend is running, backends are shut down while idle
+    /// when an idle shutdown is configured
+    warm: AtomicBool,
+    /// Whether the backend is being started or recycled
+    restarting: AtomicBool,
+    /// Whether a spare backend has been started to replace the running
+    /// backend, see [warm_standby]
+    standby: AtomicBool,
+    /// Time taken by the last backend start in milliseconds
+    startup_ms: AtomicU64,
+    /// When the current piece of work started (Milliseconds since the unix
+    /// epoch, zero while idle)
+    job_started_ms: AtomicU64,
+    /// Number of pieces of work that have failed in a row
+    consecutive_failures: AtomicU64,
+    /// When the current phase started
+    phase_started: Mutex<std::time::Instant>,
+    /// Progress of the work being processed when tracked by the task that
+    /// sent it
+    tracked: Mutex<Option<Arc<WorkProgress>>>,
+}
+
+/// Activity shared by the workers of a runner
+#[derive(Debug, Default)]
+struct PoolActivity {
+    /// Moving average of the work duration in milliseconds
+    average_work_ms: AtomicU64,
+    /// Total pieces of work that completed successfully
+    completed: AtomicU64,
+    /// Total pieces of work that failed
+    failed: AtomicU64,
+    /// Total failures for each [FailureClass]
+    failures: [AtomicU64; FailureClass::COUNT],
+    /// Total self tests run after repeated failures
+    selftests: AtomicU64,
+    /// Total times office was recycled after a failed self test
+    recoveries: AtomicU64,
+    /// Effect of the last memory trim
+    last_trim: Mutex<Option<TrimEffect>>,
+    /// Telemetry of the processed work
+    metrics: RunnerMetrics,
+    /// Number of messages in the queue that no worker has started processing
+    queued: AtomicUsize,
+    /// Number of workers processing a piece of work
+    busy: AtomicUsize,
+    /// Whether the payloads of office callbacks are logged in full
+    dump_callbacks: AtomicBool,
+}
+
+/// Handle to a worker of the office runner
+struct WorkerHandle {
+    /// Sender for messages only processed by the worker, taken by the
+    /// worker ahead of the shared queue
+    control: mpsc::Sender<RunnerMsg>,
+    /// Activity of the worker
+    activity: Arc<RunnerActivity>,
+}
+
+/// Effect of trimming the backend memory on the process memory
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TrimEffect {
+    /// When the trim happened (Milliseconds since the unix epoch)
+    pub trimmed_at_ms: u64,
+    /// Trim target passed to the backend
+    pub target: i32,
+    /// Resident memory of the process before trimming
+    pub rss_before_bytes: Option<u64>,
+    /// Resident memory of the process after trimming
+    pub rss_after_bytes: Option<u64>,
+    /// Time taken to trim in milliseconds
+    pub duration_ms: u64,
+}
+
+/// Value of [RunnerActivity::progress] when office has not reported progress
+const NO_PROGRESS: u8 = u8::MAX;
+
+impl Default for RunnerActivity {
+    fn default() -> Self {
+        Self::with_pool(Arc::default())
+    }
+}
+
+/// Phase of the work the runner is currently processing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunnerPhase {
+    /// Not processing any work
+    Idle = 0,
+    /// Loading the document
+    Loading = 1,
+    /// Processing the loaded document (i.e running macros)
+    Converting = 2,
+    /// Saving or exporting the document
+    Saving = 3,
+}
+
+impl RunnerPhase {
+    /// Provides the phase stored as `value`
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => RunnerPhase::Loading,
+            2 => RunnerPhase::Converting,
+            3 => RunnerPhase::Saving,
+            _ => RunnerPhase::Idle,
+        }
+    }
+}
+
+impl RunnerActivity {
+    /// Creates the activity of a worker sharing the `pool` activity
+    fn with_pool(pool: Arc<PoolActivity>) -> Self {
+        Self {
+            pool,
+            last_activity_ms: AtomicU64::new(0),
+            phase: AtomicU8::new(RunnerPhase::Idle as u8),
+            progress: AtomicU8::new(NO_PROGRESS),
+            warm: AtomicBool::new(false),
+            restarting: AtomicBool::new(false),
+            standby: AtomicBool::new(false),
+            startup_ms: AtomicU64::new(0),
+            job_started_ms: AtomicU64::new(0),
+            consecutive_failures: AtomicU64::new(0),
+            phase_started: Mutex::new(std::time::Instant::now()),
+            tracked: Mutex::new(None),
+        }
+    }
+
+    /// Records the `duration` of a piece of work
+    pub(crate) fn record(&self, duration: Duration) {
+        let duration = duration.as_millis() as u64;
+        let average = self.pool.average_work_ms.load(Ordering::Relaxed);
+
+        // Weight recent work more heavily than older work
+        let average = match average {
+            0 => duration,
+            average => (average * 4 + duration) / 5,
+        };
+
+        self.pool.average_work_ms.store(average, Ordering::Relaxed);
+    }
+
+    /// Provides the average duration of a piece of work
+    pub(crate) fn average_work(&self) -> Duration {
+        Duration::from_millis(self.pool.average_work_ms.load(Ordering::Relaxed))
+    }
+
+    /// Records that the runner is making progress, called when work starts
+    /// and from office callbacks while loading and saving
+    pub fn heartbeat(&self) {
+        self.last_activity_ms
+            .store(unix_millis(), Ordering::Relaxed);
+    }
+
+    /// Moves the runner into a new `phase`, progress is reset as office
+    /// reports progress separately for each phase
+    pub fn set_phase(&self, phase: RunnerPhase) {
+        let previous = self.phase();
+        let started = std::mem::replace(&mut *self.phase_started.lock(), std::time::Instant::now());
+        self.pool.metrics.record_phase(previous, started.elapsed());
+
+        self.phase.store(phase as u8, Ordering::Relaxed);
+        self.progress.store(NO_PROGRESS, Ordering::Relaxed);
+
+        if let Some(tracked) = &*self.tracked.lock() {
+            tracked.phase.store(phase as u8, Ordering::Relaxed);
+            tracked.progress.store(NO_PROGRESS, Ordering::Relaxed);
+        }
+    }
+
+    /// Provides the current phase of the runner
+    pub fn phase(&self) -> RunnerPhase {
+        RunnerPhase::from_u8(self.phase.load(Ordering::Relaxed))
+    }
+
+    /// Records the `progress` percentage reported by office
+    pub fn set_progress(&self, progress: u8) {
+        let progress = progress.min(100);
+        self.progress.store(progress, Ordering::Relaxed);
+
+        if let Some(tracked) = &*self.tracked.lock() {
+            tracked.progress.store(progress, Ordering::Relaxed);
+        }
+    }
+
+    /// Provides the progress percentage of the current phase if reported
+    pub fn progress(&self) -> Option<u8> {
+        match self.progress.load(Ordering::Relaxed) {
+            NO_PROGRESS => None,
+            progress => Some(progress),
+        }
+    }
+
+    /// Sets whether the payloads of office callbacks are logged in full
+    pub fn set_dump_callbacks(&self, enabled: bool) {
+        self.pool.dump_callbacks.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Provides whether the payloads of office callbacks are logged in full
+    pub fn dumps_callbacks(&self) -> bool {
+        self.pool.dump_callbacks.load(Ordering::Relaxed)
+    }
+
+    /// Provides whether the backend is running
+    pub fn is_warm(&self) -> bool {
+        self.warm.load(Ordering::Relaxed)
+    }
+
+    /// Provides the time since the runner last reported activity
+    pub fn since_activity(&self) -> Duration {
+        let last_activity = self.last_activity_ms.load(Ordering::Relaxed);
+        Duration::from_millis(unix_millis().saturating_sub(last_activity))
+    }
+
+    /// Provides whether the backend is being started or recycled
+    pub fn is_restarting(&self) -> bool {
+        self.restarting.load(Ordering::Relaxed)
+    }
+
+    /// Provides whether a spare backend is ready to replace the running backend
+    pub fn has_standby(&self) -> bool {
+        self.standby.load(Ordering::Relaxed)
+    }
+
+    /// Provides the time taken by the last backend start
+    pub(crate) fn startup_duration(&self) -> Duration {
+        Duration::from_millis(self.startup_ms.load(Ordering::Relaxed))
+    }
+
+    /// Records the start of a piece of work
+    fn start_job(&self) {
+        self.job_started_ms.store(unix_millis(), Ordering::Relaxed);
+    }
+
+    /// Records the end of a piece of work
+    fn finish_job(&self) {
+        self.job_started_ms.store(0, Ordering::Relaxed);
+    }
+
+    /// Records whether the worker is `converting` in the runner state sent
+    /// through `converting_tx`, the runner is converting while any of its
+    /// workers are. Counted while sending so concurrent workers can't send
+    /// a stale state
+    fn set_converting(&self, converting_tx: &watch::Sender<bool>, converting: bool) {
+        converting_tx.send_modify(|value| {
+            let busy = match converting {
+                true => self.pool.busy.fetch_add(1, Ordering::Relaxed) + 1,
+                false => self.pool.busy.fetch_sub(1, Ordering::Relaxed) - 1,
+            };
+
+            *value = busy > 0;
+        });
+    }
+
+    /// Provides how long the current piece of work has been running for
+    pub fn job_age(&self) -> Option<Duration> {
+        match self.job_started_ms.load(Ordering::Relaxed) {
+            0 => None,
+            started => Some(Duration::from_millis(unix_millis().saturating_sub(started))),
+        }
+    }
+
+    /// Records the outcome of a piece of work
+    fn record_outcome<T>(&self, result: &anyhow::Result<T>) {
+        match result {
+            Ok(_) => {
+                self.pool.completed.fetch_add(1, Ordering::Relaxed);
+                self.consecutive_failures.store(0, Ordering::Relaxed);
+            }
+            Err(err) => self.record_failure(FailureClass::of(err)),
+        }
+    }
+
+    /// Records a piece of work that failed in the runner
+    fn record_failure(&self, class: FailureClass) {
+        self.pool.failed.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed);
+        self.record_failure_class(class);
+    }
+
+    /// Records a failure of the `class`, failures that happen before the
+    /// work reaches the runner (i.e queue timeouts) are only recorded here
+    fn record_failure_class(&self, class: FailureClass) {
+        self.pool.failures[class as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Provides the totals of the work processed by the runner
+    pub fn totals(&self) -> WorkTotals {
+        let failures =
+            |class: FailureClass| self.pool.failures[class as usize].load(Ordering::Relaxed);
+
+        WorkTotals {
+            completed: self.pool.completed.load(Ordering::Relaxed),
+            failed: self.pool.failed.load(Ordering::Relaxed),
+            failures: FailureTotals {
+                encrypted: failures(FailureClass::Encrypted),
+                corrupted: failures(FailureClass::Corrupted),
+                timeout: failures(FailureClass::Timeout),
+                unsupported: failures(FailureClass::Unsupported),
+                worker_crash: failures(FailureClass::WorkerCrash),
+                other: failures(FailureClass::Other),
+            },
+            selftests: self.pool.selftests.load(Ordering::Relaxed),
+            recoveries: self.pool.recoveries.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Provides the effect of the last memory trim
+    pub fn last_trim(&self) -> Option<TrimEffect> {
+        *self.pool.last_trim.lock()
+    }
+
+    /// Provides the telemetry of the processed work
+    pub fn metrics(&self) -> &RunnerMetrics {
+        &self.pool.metrics
+    }
+}
+
+/// Totals of the work processed by the runner
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct WorkTotals {
+    /// Pieces of work that completed successfully
+    pub completed: u64,
+    /// Pieces of work that failed
+    pub failed: u64,
+    /// Failures by class
+    pub failures: FailureTotals,
+    /// Self tests run after repeated failures
+    pub selftests: u64,
+    /// Times office was recycled after a failed self test
+    pub recoveries: u64,
+}
+
+/// Class of a failure, distinguishes failures caused by the uploaded files
+/// (encrypted, corrupted) from failures caused by an unhealthy service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureClass {
+    /// File was encrypted with a password
+    Encrypted = 0,
+    /// File was malformed or corrupted
+    Corrupted = 1,
+    /// Work timed out waiting for the runner
+    Timeout = 2,
+    /// Functionality is not supported by the backend
+    Unsupported = 3,
+    /// Office failed to start or the runner stopped
+    WorkerCrash = 4,
+    /// Any other failure
+    Other = 5,
+}
+
+impl FailureClass {
+    /// Number of failure classes
+    const COUNT: usize = 6;
+
+    /// Determines the class of the `err` from processing a piece of work
+    pub fn of(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<ConvertError>() {
+            Some(ConvertError::Encrypted | ConvertError::WrongPassword) => FailureClass::Encrypted,
+            Some(ConvertError::Corrupted) => FailureClass::Corrupted,
+            Some(
+                ConvertError::Unsupported(_)
+                | ConvertError::NotSpreadsheet
+                | ConvertError::InvalidSheet { .. }
+                | ConvertError::InvalidPage { .. }
+                | ConvertError::UnsupportedTarget(_)
+                | ConvertError::FilterMissing,
+            ) => FailureClass::Unsupported,
+            Some(ConvertError::DiskFull | ConvertError::RedactionIncomplete { .. }) | None => {
+                FailureClass::Other
+            }
+        }
+    }
+}
+
+/// Totals of the failures for each [FailureClass]
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct FailureTotals {
+    pub encrypted: u64,
+    pub corrupted: u64,
+    pub timeout: u64,
+    pub unsupported: u64,
+    pub worker_crash: u64,
+    pub other: u64,
+}
+
+/// State of a worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkerState {
+    /// Waiting for work
+    Idle,
+    /// Processing a piece of work
+    Converting,
+    /// Office is being started or recycled
+    Restarting,
+}
+
+/// Detailed status of a worker
+#[derive(Debug, Serialize)]
+pub(crate) struct WorkerStatus {
+    /// ID of the worker
+    id: usize,
+    /// Current state of the worker
+    state: WorkerState,
+    /// Whether office is running for the worker
+    warm: bool,
+    /// Whether a spare office is ready to replace the running office
+    standby: bool,
+    /// Phase of the current piece of work
+    phase: RunnerPhase,
+    /// Approximate completion percentage of the current phase, when
+    /// reported by office
+    progress: Option<u8>,
+    /// Time the current piece of work has been running for in milliseconds
+    job_age_ms: Option<u128>,
+}
+
+/// Provides the current time in milliseconds since the unix epoch
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Errors from uploads too small to contain a document
+#[derive(Debug, Error)]
+pub(crate) enum UploadSizeError {
+    /// Upload was empty or only contained whitespace
+    #[error("uploaded file is empty")]
+    Empty,
+
+    /// Upload was smaller than the configured minimum size
+    #[error("uploaded file is {size} bytes, smaller than the minimum of {min} bytes")]
+    TooSmall { size: usize, min: usize },
+}
+
+impl HttpError for UploadSizeError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            UploadSizeError::Empty => "empty_file",
+            UploadSizeError::TooSmall { .. } => "file_too_small",
+        })
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            UploadSizeError::Empty => None,
+            UploadSizeError::TooSmall { size, min } => Some(json!({
+                "size": size,
+                "min": min,
+            })),
+        }
+    }
+}
+
+/// Checks the upload `bytes` could contain a document, uploads that are
+/// empty (or only whitespace and null bytes) or smaller than the `min_size`
+/// would only fail after taking a runner slot
+pub(crate) fn check_upload_size(
+    bytes: &[u8],
+    min_size: Option<usize>,
+) -> Result<(), UploadSizeError> {
+    if bytes
+        .iter()
+        .all(|byte| byte.is_ascii_whitespace() || *byte == 0)
+    {
+        return Err(UploadSizeError::Empty);
+    }
+
+    if let Some(min) = min_size.filter(|min| bytes.len() < *min) {
+        return Err(UploadSizeError::TooSmall {
+            size: bytes.len(),
+            min,
+        });
+    }
+
+    Ok(())
+}
+
+/// Errors that can occur sending a message to the runner
+#[derive(Debug, Error)]
+pub(crate) enum RunnerSendError {
+    /// The runner has stopped
+    #[error("office runner is unavailable")]
+    Unavailable,
+
+    /// The request waited longer than the max queue wait
+    #[error("timed out waiting for the office runner")]
+    QueueFull {
+        /// Estimated time until the runner is available
+        retry_after: Duration,
+    },
+
+    /// Office is being started or recycled, work is shed instead of
+    /// queueing behind a start that may not succeed
+    #[error("office is restarting")]
+    Restarting {
+        /// Estimated time until office has started
+        retry_after: Duration,
+    },
+    /// The runner failed while processing the request, office was torn down
+    /// and is started again for the next request
+    #[error("converter restarted while processing the request")]
+    Restarted,
+}
+
+impl HttpError for RunnerSendError {
+    fn log(&self) {
+        match self {
+            RunnerSendError::Unavailable | RunnerSendError::Restarted => error!("{self}"),
+            // Shedding load is expected when the server is busy or restarting
+            RunnerSendError::QueueFull { .. } | RunnerSendError::Restarting { .. } => {
+                warn!("{self}")
+            }
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            RunnerSendError::Unavailable | RunnerSendError::Restarted => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            RunnerSendError::QueueFull { .. } | RunnerSendError::Restarting { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        }
+    }
+
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        if let RunnerSendError::QueueFull { retry_after }
+        | RunnerSendError::Restarting { retry_after } = self
+        {
+            // Retry-After is in whole seconds, rounded up
+            let seconds = retry_after.as_millis().div_ceil(1000).max(1);
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(seconds as u64));
+        }
+
+        headers
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            RunnerSendError::Unavailable => None,
+            RunnerSendError::QueueFull { .. } => Some("queue_full"),
+            RunnerSendError::Restarting { .. } => Some("restarting"),
+            RunnerSendError::Restarted => Some("converter_restarted"),
+        }
+    }
+}
+
+/// Snapshot of the office runner state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct OfficeState {
+    /// Whether the runner is currently converting a document, kept for
+    /// clients that only check whether the server is busy
+    is_busy: bool,
+    /// Whether the runner is currently converting a document
+    pub(crate) converting: bool,
+    /// Number of requests waiting for the runner
+    pub(crate) queue_depth: usize,
+    /// Phase of the current conversion
+    phase: RunnerPhase,
+    /// Approximate completion percentage of the current phase, when
+    /// reported by office
+    progress: Option<u8>,
+    /// Whether office is running, office is started on the next conversion
+    /// when it has been shut down while idle
+    pub(crate) warm: bool,
+}
+
+/// Guard decreasing the waiting counter when dropped
+struct WaitingGuard<'a>(&'a watch::Sender<usize>);
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.send_modify(|value| *value -= 1);
+    }
+}
+
+impl OfficeHandle {
+    /// Sends a message to the runner, waiting until the runner can accept it
+    ///
+    /// Fails with [RunnerSendError::QueueFull] when the max queue wait is
+    /// exceeded, and with [RunnerSendError::Restarting] for work sent while
+    /// office is being started or recycled
+    async fn send(&self, msg: OfficeMsg) -> Result<(), RunnerSendError> {
+        if msg.is_work() && !self.is_ready() {
+            return Err(RunnerSendError::Restarting {
+                retry_after: self.activity.startup_duration(),
+            });
+        }
+
+        self.waiting.send_modify(|value| *value += 1);
+        let _guard = WaitingGuard(&self.waiting);
+
+        let span = Span::current();
+        let send = async {
+            // Higher priority work takes the next place in the queue
+            let _permit = match msg.is_work() {
+                true => Some(self.priority.admit(priority::current()).await),
+                false => None,
+            };
+
+            // Counted before sending as the runner may take the message
+            // as soon as it is sent
+            let permit = self.tx.reserve().await?;
+            self.activity.pool.queued.fetch_add(1, Ordering::Relaxed);
+
+            permit.send(RunnerMsg {
+                msg,
+                span,
+                queued: Instant::now(),
+                progress: PROGRESS.try_with(Arc::clone).ok(),
+            });
+
+            Ok::<_, mpsc::error::SendError<()>>(())
+        };
+
+        let max_queue_wait = *self.max_queue_wait.lock();
+        let result = match max_queue_wait {
+            Some(max_queue_wait) => match tokio::time::timeout(max_queue_wait, send).await {
+                Ok(result) => result,
+                Err(_) => {
+                    self.activity.record_failure_class(FailureClass::Timeout);
+                    return Err(RunnerSendError::QueueFull {
+                        retry_after: self.estimated_wait(),
+                    });
+                }
+            },
+            None => send.await,
+        };
+
+        result.map_err(|_| {
+            self.activity
+                .record_failure_class(FailureClass::WorkerCrash);
+            RunnerSendError::Unavailable
+        })
+    }
+
+    /// Waits for the runner to respond through `rx`, the runner only drops
+    /// the response when it failed while processing the message
+    async fn receive<T>(
+        &self,
+        rx: oneshot::Receiver<anyhow::Result<T>>,
+    ) -> Result<anyhow::Result<T>, RunnerSendError> {
+        rx.await.map_err(|_| RunnerSendError::Restarted)
+    }
+
+    /// Sends the message created by `msg` with its return channel, waiting
+    /// for the runner to respond through it
+    async fn request<T>(
+        &self,
+        msg: impl FnOnce(oneshot::Sender<anyhow::Result<T>>) -> OfficeMsg,
+    ) -> Result<anyhow::Result<T>, RunnerSendError> {
+        let (tx, rx) = oneshot::channel();
+        self.send(msg(tx)).await?;
+        self.receive(rx).await
+    }
+
+    /// Converts the `input` document, the diagnostics of failed conversions
+    /// are only collected when requested by `diagnostics`
+    pub(crate) async fn convert(
+        &self,
+        input: DocumentInput,
+        options: ConvertOptions,
+        diagnostics: bool,
+    ) -> Result<ConvertResult, RunnerSendError> {
+        let (diagnostics_tx, mut diagnostics_rx) = match diagnostics {
+            true => {
+                let (tx, rx) = oneshot::channel();
+                (Some(tx), Some(rx))
+            }
+            false => (None, None),
+        };
+
+        let result = self
+            .request(|tx| OfficeMsg::Convert {
+                input,
+                options: Box::new(options),
+                tx,
+                diagnostics: diagnostics_tx,
+            })
+            .await?;
+
+        // Diagnostics are sent before the result when office was reached
+        let diagnostics = diagnostics_rx
+            .as_mut()
+            .and_then(|diagnostics_rx| diagnostics_rx.try_recv().ok());
+
+        Ok(ConvertResult {
+            result,
+            diagnostics,
+        })
+    }
+
+    /// Converts the `input` document for applications embedding the runner
+    /// directly rather than through the server
+    pub async fn convert_document(
+        &self,
+        input: DocumentInput,
+        options: ConvertOptions,
+    ) -> anyhow::Result<ConvertedDocument> {
+        self.convert(input, options, false).await?.result
+    }
+
+    /// Runs the pipeline `steps` on the `input` document, providing a zip
+    /// archive of the artifacts
+    pub(crate) async fn run_pipeline(
+        &self,
+        input: DocumentInput,
+        steps: Vec<PipelineStep>,
+        options: PipelineOptions,
+    ) -> Result<anyhow::Result<Bytes>, RunnerSendError> {
+        self.request(|tx| OfficeMsg::Pipeline {
+            input,
+            steps,
+            options,
+            tx,
+        })
+        .await
+    }
+
+    /// Extracts the embedded images and objects from the `input` document,
+    /// providing a zip archive of the assets
+    pub(crate) async fn extract_assets(
+        &self,
+        input: DocumentInput,
+    ) -> Result<anyhow::Result<Bytes>, RunnerSendError> {
+        self.request(|tx| OfficeMsg::ExtractAssets { input, tx })
+            .await
+    }
+
+    /// Extracts the statistics of the `input` document
+    pub(crate) async fn extract_stats(
+        &self,
+        input: DocumentInput,
+    ) -> Result<anyhow::Result<DocumentStats>, RunnerSendError> {
+        self.request(|tx| OfficeMsg::ExtractStats { input, tx })
+            .await
+    }
+
+    /// Tells office to clean up and trim its memory usage, the runner
+    /// doesn't respond once done
+    pub(crate) async fn collect_garbage(&self) -> Result<(), RunnerSendError> {
+        self.send(OfficeMsg::CollectGarbage).await
+    }
+
+    /// Estimates how long until the runner can accept another message based
+    /// on the number of waiting requests, the average work duration and the
+    /// number of workers sharing the queue
+    fn estimated_wait(&self) -> Duration {
+        let queue_depth = self.state().queue_depth.div_ceil(self.workers.len()) as u32;
+        let average_work = self.activity.average_work().max(Duration::from_secs(1));
+
+        average_work * queue_depth.max(1)
+    }
+
+    /// Provides the number of messages the queue holds ahead of the runner
+    pub(crate) fn queue_capacity(&self) -> usize {
+        self.tx.max_capacity()
+    }
+
+    /// Provides the activity of the first worker of the runner
+    pub fn activity(&self) -> &RunnerActivity {
+        &self.activity
+    }
+
+    /// Provides a snapshot of the current runner state
+    pub(crate) fn state(&self) -> OfficeState {
+        let converting = *self.converting.borrow();
+
+        // Messages sitting in the queue are also waiting
+        let queued = self.activity.pool.queued.load(Ordering::Relaxed);
+        let queue_depth = *self.waiting.borrow() + queued;
+
+        // Progress is reported for the first worker that is converting
+        let activity = self
+            .workers
+            .iter()
+            .map(|worker| &worker.activity)
+            .find(|activity| activity.job_age().is_some())
+            .unwrap_or(&self.activity);
+
+        OfficeState {
+            is_busy: self.is_busy(),
+            converting,
+            queue_depth,
+            phase: activity.phase(),
+            progress: activity.progress(),
+            warm: self.is_warm(),
+        }
+    }
+
+    /// Provides the detailed status of each worker
+    pub(crate) fn workers(&self) -> Vec<WorkerStatus> {
+        self.workers
+            .iter()
+            .enumerate()
+            .map(|(id, worker)| {
+                let activity = &worker.activity;
+
+                let state = if activity.is_restarting() {
+                    WorkerState::Restarting
+                } else if activity.job_age().is_some() {
+                    WorkerState::Converting
+                } else {
+                    WorkerState::Idle
+                };
+
+                WorkerStatus {
+                    id,
+                    state,
+                    warm: activity.is_warm(),
+                    standby: activity.has_standby(),
+                    phase: activity.phase(),
+                    progress: activity.progress(),
+                    job_age_ms: activity.job_age().map(|age| age.as_millis()),
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the runner is ready to accept work, work is shed while
+    /// office is being started or recycled for every worker
+    pub(crate) fn is_ready(&self) -> bool {
+        self.workers
+            .iter()
+            .any(|worker| !worker.activity.is_restarting())
+    }
+
+    /// Whether office is running for any of the workers
+    fn is_warm(&self) -> bool {
+        self.workers.iter().any(|worker| worker.activity.is_warm())
+    }
+
+    /// Provides the totals of the work processed by the runner
+    pub(crate) fn totals(&self) -> WorkTotals {
+        self.activity.totals()
+    }
+
+    /// Provides the effect of the last memory trim
+    pub(crate) fn last_trim(&self) -> Option<TrimEffect> {
+        self.activity.last_trim()
+    }
+
+    /// Provides the telemetry of the work processed by the runner
+    pub(crate) fn metrics(&self) -> &RunnerMetrics {
+        self.activity.metrics()
+    }
+
+    /// Checks if the runner is busy, the runner is busy while every worker
+    /// is converting a document
+    pub(crate) fn is_busy(&self) -> bool {
+        self.activity.pool.busy.load(Ordering::Relaxed) >= self.workers.len()
+    }
+
+    /// Waits until office has been started, office is only started by the
+    /// first conversion when idle shutdown is enabled
+    pub async fn wait_warm(&self) {
+        let mut converting = self.converting.clone();
+
+        while !self.is_warm() {
+            // Runner has stopped
+            if converting.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Queries the backend for the current office details and swaps them
+    /// into the shared `details`, the details are kept when office has been
+    /// shut down while idle
+    pub async fn refresh_details(&self, details: &SharedDetails) -> anyhow::Result<()> {
+        let (tx, rx) = oneshot::channel();
+
+        self.send(OfficeMsg::RefreshDetails { tx })
+            .await
+            .context("failed to send refresh request")?;
+
+        if let Some(refreshed) = rx.await.context("failed to get refreshed details")? {
+            details.store(Arc::new(refreshed));
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the backend of each worker with a fresh instance once the
+    /// current work of the worker has finished, workers are recycled one at
+    /// a time so the other workers keep processing the queue. `prepare` runs
+    /// while the instance of the first worker is shut down
+    pub async fn recycle(
+        &self,
+        prepare: Box<dyn FnOnce() -> anyhow::Result<()> + Send>,
+    ) -> anyhow::Result<()> {
+        let mut prepare = Some(prepare);
+
+        for worker in self.workers.iter() {
+            let (tx, rx) = oneshot::channel();
+            let prepare = prepare.take().unwrap_or_else(|| Box::new(|| Ok(())));
+
+            let permit = worker
+                .control
+                .reserve()
+                .await
+                .context("failed to send recycle request")?;
+            self.activity.pool.queued.fetch_add(1, Ordering::Relaxed);
+
+            permit.send(RunnerMsg {
+                msg: OfficeMsg::Recycle { prepare, tx },
+                span: Span::current(),
+                queued: Instant::now(),
+                progress: None,
+            });
+
+            rx.await.context("failed to get recycle result")??;
+        }
+
+        Ok(())
+    }
+
+    /// Applies the queue `limits`, requests already waiting keep the max
+    /// queue wait they started waiting with
+    pub fn set_limits(&self, limits: QueueLimits) {
+        *self.max_queue_wait.lock() = limits.max_queue_wait;
+        self.priority.set_max_skips(limits.batch_max_skips);
+    }
+
+    /// Provides the queue limits currently applied
+    pub fn limits(&self) -> QueueLimits {
+        QueueLimits {
+            max_queue_wait: *self.max_queue_wait.lock(),
+            batch_max_skips: self.priority.max_skips(),
+        }
+    }
+
+    /// Sets whether maintenance is in progress
+    pub fn set_maintenance(&self, maintenance: bool) {
+        self.maintenance.store(maintenance, Ordering::Relaxed);
+    }
+
+    /// Provides whether maintenance is in progress
+    pub fn in_maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::Relaxed)
+    }
+
+    /// Waits up to `wait` for the runner to become available, returns
+    /// whether the runner is still busy
+    pub(crate) async fn wait_until_available(&self, wait: Duration) -> bool {
+        let deadline = Instant::now() + wait;
+        let mut converting = self.converting.clone();
+
+        loop {
+            if !self.is_busy() {
+                return false;
+            }
+
+            // Wait for the converting state to change before checking again
+            match tokio::time::timeout_at(deadline, converting.changed()).await {
+                Ok(Ok(_)) => continue,
+                // Wait elapsed or runner stopped
+                Ok(Err(_)) | Err(_) => return self.is_busy(),
+            }
+        }
+    }
+
+    /// Prepares document bytes to be sent to the runner, empty documents and
+    /// documents exceeding the embedded content limits are rejected before
+    /// taking a place in the queue. Web archives are unpacked into a single
+    /// HTML document that office can import. When the runner is busy documents larger than the spill threshold
+    /// are written to disk so pending uploads don't have to be held in memory, with an IO limit every document
+    /// is written to disk here instead of on the runner thread
+    pub(crate) async fn prepare_input(
+        &self,
+        bytes: Bytes,
+        config: &ServerConfig,
+    ) -> Result<DocumentInput, DynHttpError> {
+        check_upload_size(&bytes, config.min_upload_size)?;
+
+        let bytes = if mhtml::is_safari_archive(&bytes) {
+            return Err(WebArchiveError::SafariArchive.into());
+        } else if mhtml::is_mhtml(&bytes) {
+            let archive = bytes.clone();
+            let document = tokio::task::spawn_blocking(move || mhtml::to_html(&archive))
+                .await
+                .context("failed to unpack web archive")??;
+
+            debug!(
+                archive = bytes.len(),
+                document = document.len(),
+                "unpacked web archive"
+            );
+
+            Bytes::from(document)
+        } else {
+            bytes
+        };
+
+        if config.embedded_limits.is_enabled() {
+            let limits = config.embedded_limits;
+            let document = bytes.clone();
+            let summary = tokio::task::spawn_blocking(move || embedded::check(&document, &limits))
+                .await
+                .context("failed to inspect embedded content")??;
+
+            debug!(
+                depth = summary.depth,
+                objects = summary.objects,
+                images = summary.images,
+                "inspected embedded content"
+            );
+        }
+
+        let should_spill = config
+            .spill_threshold
+            .is_some_and(|threshold| bytes.len() >= threshold)
+            && self.activity.pool.queued.load(Ordering::Relaxed) > 0;
+
+        if !should_spill && config.io_limit.is_none() {
+            return Ok(DocumentInput::Bytes(bytes));
+        }
+
+        // Limit the uploads being written at once so large uploads don't
+        // saturate the disk (Writes happen on the blocking pool)
+        let _permit = match config.io_limit.as_ref() {
+            Some(io_limit) => Some(io_limit.acquire().await.context("upload io limit closed")?),
+            None => None,
+        };
+
+        let spilled = TempFile {
+            path: config
+                .work_dir
+                .join(format!("lo_native_spill_{}", random_id())),
+        };
+
+        debug!(size = bytes.len(), should_spill, "writing upload to disk");
+
+        tokio::fs::write(&spilled.path, bytes)
+            .await
+            .context("failed to spill upload to disk")?;
+
+        Ok(DocumentInput::Spilled(spilled))
+    }
+}
+
+/// Creates a new office runner on its own thread providing a handle to
+/// access it via messages, the backend is created on the runner thread
+/// using `create_backend` (And recreated with it after an idle shutdown)
+pub async fn create_office_runner<B, F>(
+    create_backend: F,
+    trim_config: TrimConfig,
+    queue: QueueConfig,
+    watchdog: WatchdogConfig,
+    recover_after: Option<u64>,
+) -> anyhow::Result<(OfficeDetails, OfficeHandle)>
+where
+    B: ConvertBackend,
+    F: FnMut(Arc<RunnerActivity>) -> anyhow::Result<B> + Send + 'static,
+{
+    create_office_pool(
+        vec![create_backend],
+        trim_config,
+        queue,
+        watchdog,
+        recover_after,
+    )
+    .await
+}
+
+/// Creates an office runner with a worker thread for each of the
+/// `create_backends` sharing the work queue, each worker creates its own
+/// backend using its function. Backends that can't run alongside another
+/// instance within the process (i.e LibreOfficeKit) must use a single worker
+pub async fn create_office_pool<B, F>(
+    create_backends: Vec<F>,
+    trim_config: TrimConfig,
+    queue: QueueConfig,
+    watchdog: WatchdogConfig,
+    recover_after: Option<u64>,
+) -> anyhow::Result<(OfficeDetails, OfficeHandle)>
+where
+    B: ConvertBackend,
+    F: FnMut(Arc<RunnerActivity>) -> anyhow::Result<B> + Send + 'static,
+{
+    if create_backends.is_empty() {
+        return Err(anyhow::anyhow!(
+            "office runner requires at least one worker"
+        ));
+    }
+
+    let (tx, queue_rx) = mpsc::channel(queue.capacity.max(1));
+    let queue_rx: WorkQueue = Arc::new(tokio::sync::Mutex::new(queue_rx));
+    let (converting_tx, converting) = watch::channel(false);
+    let converting_tx = Arc::new(converting_tx);
+    let pool = Arc::new(PoolActivity::default());
+
+    let mut workers = Vec::with_capacity(create_backends.len());
+    let mut startups = Vec::with_capacity(create_backends.len());
+
+    for (id, create_backend) in create_backends.into_iter().enumerate() {
+        let activity = Arc::new(RunnerActivity::with_pool(pool.clone()));
+        let (control_tx, control_rx) = mpsc::channel(1);
+        let (startup_tx, startup_rx) = oneshot::channel();
+
+        std::thread::spawn({
+            let activity = activity.clone();
+            let queue_rx = queue_rx.clone();
+            let converting_tx = converting_tx.clone();
+
+            move || {
+                let mut backend = LazyBackend {
+                    backend: None,
+                    restarts_in_process: true,
+                    create_backend,
+                    activity: activity.clone(),
+                };
+
+                // Office is started by the first conversion when it is shut down
+                // while idle, so servers restarted after an idle shutdown stay cold
+                let details = if trim_config.idle_shutdown.is_some() {
+                    debug!(
+                        worker = id,
+                        "idle shutdown enabled, office is started by the first conversion"
+                    );
+                    OfficeDetails::default()
+                } else {
+                    match backend.get() {
+                        Ok(value) => value.details(),
+                        Err(cause) => {
+                            error!(%cause, worker = id, "failed to start office runner");
+                            _ = startup_tx.send(Err(cause));
+                            return;
+                        }
+                    }
+                };
+
+                // Report successful startup
+                _ = startup_tx.send(Ok(details));
+
+                if let Err(cause) = office_runner(
+                    backend,
+                    trim_config,
+                    recover_after,
+                    WorkerChannels {
+                        queue: queue_rx,
+                        control: control_rx,
+                    },
+                    converting_tx,
+                    activity,
+                ) {
+                    error!(%cause, worker = id, "office runner stopped");
+                }
+            }
+        });
+
+        workers.push(WorkerHandle {
+            control: control_tx,
+            activity,
+        });
+        startups.push(startup_rx);
+    }
+
+    // Wait for a successful startup of every worker, the details are
+    // provided by the first worker
+    let mut office_details = None;
+    for startup_rx in startups {
+        let details = startup_rx.await.context("startup channel unavailable")??;
+        office_details.get_or_insert(details);
+    }
+    let office_details = office_details.unwrap_or_default();
+
+    let workers: Arc<[WorkerHandle]> = workers.into();
+
+    if watchdog.hang_timeout.is_some() || watchdog.convert_timeout.is_some() {
+        std::thread::spawn({
+            let converting = converting.clone();
+            let activities = workers
+                .iter()
+                .map(|worker| worker.activity.clone())
+                .collect();
+
+            move || hang_watchdog(watchdog, converting, activities)
+        });
+    }
+
+    let office_handle = OfficeHandle {
+        tx,
+        converting,
+        activity: workers[0].activity.clone(),
+        workers,
+        waiting: Arc::new(watch::channel(0).0),
+        max_queue_wait: Arc::new(Mutex::new(queue.max_queue_wait)),
+        maintenance: Default::default(),
+        priority: Arc::new(PriorityGate::new(priority::DEFAULT_MAX_SKIPS)),
+    };
+
+    Ok((office_details, office_handle))
+}
+
+/// Queue of the work messages shared by the workers of the runner, idle
+/// workers take turns waiting on the queue so messages are only taken by a
+/// worker that is free to process them and keep their place in the queue
+type WorkQueue = Arc<tokio::sync::Mutex<mpsc::Receiver<RunnerMsg>>>;
+
+/// Channels a worker receives its messages from
+struct WorkerChannels {
+    /// Queue shared with the other workers
+    queue: WorkQueue,
+    /// Messages only processed by the worker, taken ahead of the queue
+    control: mpsc::Receiver<RunnerMsg>,
+}
+
+impl WorkerChannels {
+    /// Waits for the next message for the worker, [None] once the queue
+    /// has been closed
+    async fn recv(&mut self) -> Option<RunnerMsg> {
+        tokio::select! {
+            biased;
+            Some(msg) = self.control.recv() => Some(msg),
+            msg = async { self.queue.lock().await.recv().await } => msg,
+        }
+    }
+}
+
+/// Watches the runner workers for conversions that have stopped making
+/// progress or exceeded the convert timeout. A conversion stuck inside office
+/// cannot be interrupted and office only supports one instance per process,
+/// so the server exits allowing its supervisor (i.e container orchestrator)
+/// to restart it
+fn hang_watchdog(
+    watchdog: WatchdogConfig,
+    converting: watch::Receiver<bool>,
+    workers: Vec<Arc<RunnerActivity>>,
+) {
+    let shortest = watchdog
+        .hang_timeout
+        .into_iter()
+        .chain(watchdog.convert_timeout)
+        .min()
+        .unwrap_or(Duration::from_secs(1));
+    let interval = (shortest / 4).clamp(Duration::from_millis(100), Duration::from_secs(1));
+
+    loop {
+        std::thread::sleep(interval);
+
+        // Runner has stopped
+        if converting.has_changed().is_err() {
+            return;
+        }
+
+        if !*converting.borrow() {
+            continue;
+        }
+
+        for (worker, activity) in workers.iter().enumerate() {
+            // Worker is not converting
+            let Some(age) = activity.job_age() else {
+                continue;
+            };
+
+            let idle = activity.since_activity();
+            if watchdog
+                .hang_timeout
+                .is_some_and(|hang_timeout| idle >= hang_timeout)
+            {
+                error!(
+                    ?idle,
+                    worker, "office conversion hung without reporting progress, exiting"
+                );
+                std::process::exit(1);
+            }
+
+            if watchdog
+                .convert_timeout
+                .is_some_and(|convert_timeout| age >= convert_timeout)
+            {
+                error!(
+                    ?age,
+                    worker, "office conversion exceeded the convert timeout, exiting"
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Exits the process allowing its supervisor (i.e container orchestrator) to
+/// restart the server with a fresh office, used in place of starting the
+/// backend again when it cannot be started again within the process
+fn exit_for_restart(reason: &str) -> ! {
+    warn!(
+        reason,
+        "office cannot be started again within the process, exiting"
+    );
+    std::process::exit(1)
+}
+
+/// Office details shared with request handlers, swapped atomically when
+/// the details are refreshed
+pub type SharedDetails = Arc<ArcSwap<OfficeDetails>>;
+
+/// Details about the office install provided by the backend
+#[derive(Debug, Default)]
+pub struct OfficeDetails {
+    /// Filter types supported by office
+    pub filter_types: Option<FilterTypes>,
+    /// Version of office
+    pub version: Option<OfficeVersionInfo>,
+}
+
+/// Time given for the requests failed by a converter crash to be responded
+/// to before the process exits
+const CRASH_EXIT_GRACE: Duration = Duration::from_millis(500);
+
+/// Fails the work waiting for the runner with a restarted error then exits
+/// the process to be restarted, new work is shed while the failed requests
+/// are responded to
+fn exit_after_crash(channels: &mut WorkerChannels, activity: &RunnerActivity) -> ! {
+    activity.restarting.store(true, Ordering::Relaxed);
+
+    let deadline = Instant::now() + CRASH_EXIT_GRACE;
+    while Instant::now() < deadline {
+        // Dropping the message drops its response channel failing the request
+        while let Ok(msg) = channels.control.try_recv() {
+            drop(msg);
+        }
+
+        if let Ok(mut queue) = channels.queue.try_lock() {
+            while let Ok(msg) = queue.try_recv() {
+                drop(msg);
+            }
+        }
+
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    exit_for_restart("converter failed")
+}
+
+/// Wraps `create_backend` to keep a spare backend started in the background,
+/// a backend started again after it was recycled, crashed or failed the self
+/// test is replaced by the spare immediately while the next spare starts.
+///
+/// The spare runs alongside the running backend, so backends that only
+/// support one instance per process (i.e LibreOfficeKit) must not use it
+pub fn warm_standby<B, F>(
+    create_backend: F,
+) -> impl FnMut(Arc<RunnerActivity>) -> anyhow::Result<B> + Send + 'static
+where
+    B: ConvertBackend + Send + 'static,
+    F: FnMut(Arc<RunnerActivity>) -> anyhow::Result<B> + Send + 'static,
+{
+    let create_backend = Arc::new(Mutex::new(create_backend));
+    let mut standby: Option<std::thread::JoinHandle<anyhow::Result<B>>> = None;
+
+    move |activity: Arc<RunnerActivity>| {
+        // Waits for the spare when it is still starting, it has a head
+        // start on a backend started now
+        let backend = match standby.take().map(|spare| spare.join()) {
+            Some(Ok(Ok(backend))) => {
+                debug!("promoted warm standby backend");
+                Ok(backend)
+            }
+            Some(Ok(Err(cause))) => {
+                warn!(%cause, "warm standby failed to start, starting a new backend");
+                (create_backend.lock())(activity.clone())
+            }
+            Some(Err(_)) => {
+                warn!("warm standby panicked while starting, starting a new backend");
+                (create_backend.lock())(activity.clone())
+            }
+            None => (create_backend.lock())(activity.clone()),
+        };
+        activity.standby.store(false, Ordering::Relaxed);
+
+        // Backends that fail to start are started again by the next piece
+        // of work, along with the spare
+        if backend.is_ok() {
+            let create_backend = create_backend.clone();
+            let activity = activity.clone();
+
+            standby = Some(std::thread::spawn(move || {
+                let spare = (create_backend.lock())(activity.clone());
+                match &spare {
+                    Ok(_) => activity.standby.store(true, Ordering::Relaxed),
+                    Err(cause) => error!(%cause, "failed to start warm standby"),
+                }
+                spare
+            }));
+        }
+
+        backend
+    }
+}
+
+/// Backend that is created on demand, allowing it to be shut down while
+/// idle and started again by the next piece of work
+struct LazyBackend<B, F> {
+    /// The running backend
+    backend: Option<B>,
+    /// Whether the last started backend can be started again within the
+    /// process after it has been shut down
+    restarts_in_process: bool,
+    /// Function creating the backend
+    create_backend: F,
+    /// Activity of the runner
+    activity: Arc<RunnerActivity>,
+}
+
+impl<B, F> LazyBackend<B, F>
+where
+    B: ConvertBackend,
+    F: FnMut(Arc<RunnerActivity>) -> anyhow::Result<B>,
+{
+    /// Provides the running backend, starting the backend if it is not running
+    fn get(&mut self) -> anyhow::Result<&mut B> {
+        if self.backend.is_none() {
+            self.activity.restarting.store(true, Ordering::Relaxed);
+            let started = Instant::now();
+            let backend = (self.create_backend)(self.activity.clone());
+            self.activity
+                .startup_ms
+                .store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+            self.activity.restarting.store(false, Ordering::Relaxed);
+
+            let backend = backend?;
+            self.restarts_in_process = backend.restarts_in_process();
+            self.backend = Some(backend);
+            self.activity.warm.store(true, Ordering::Relaxed);
+            self.activity.pool.metrics.record_office_start();
+        }
+
+        Ok(self.backend.as_mut().expect("backend was started"))
+    }
+
+    /// Provides the backend if it is running
+    fn running(&mut self) -> Option<&mut B> {
+        self.backend.as_mut()
+    }
+
+    /// Shuts down the backend releasing its memory
+    fn shutdown(&mut self) {
+        self.backend = None;
+        self.activity.warm.store(false, Ordering::Relaxed);
+    }
+
+    /// Shuts down the backend so it is started again by the next piece of
+    /// work, exits the process for `reason` when the backend cannot be
+    /// started again within it
+    fn restart(&mut self, reason: &str) {
+        self.shutdown();
+
+        if !self.restarts_in_process {
+            exit_for_restart(reason);
+        }
+    }
+}
+
+/// Main event loop for an office runner worker
+fn office_runner<B, F>(
+    mut backend: LazyBackend<B, F>,
+    trim_config: TrimConfig,
+    recover_after: Option<u64>,
+    mut channels: WorkerChannels,
+    converting_tx: Arc<watch::Sender<bool>>,
+    activity: Arc<RunnerActivity>,
+) -> anyhow::Result<()>
+where
+    B: ConvertBackend,
+    F: FnMut(Arc<RunnerActivity>) -> anyhow::Result<B>,
+{
+    // Runtime used for waiting on messages with an idle timeout
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .context("failed to create runner runtime")?;
+
+    // Number of conversions since memory was last trimmed
+    let mut conversions_since_trim: u32 = 0;
+
+    // When the runner last finished a piece of work
+    let mut idle_since = Instant::now();
+
+    loop {
+        let trim_at = (trim_config.policy == TrimPolicy::Idle && conversions_since_trim > 0)
+            .then(|| idle_since + trim_config.idle_after);
+        let shutdown_at = trim_config
+            .idle_shutdown
+            .filter(|_| activity.is_warm())
+            .map(|idle_shutdown| idle_since + idle_shutdown);
+
+        // Get next message
+        let msg = match trim_at.into_iter().chain(shutdown_at).min() {
+            Some(deadline) => {
+                // Timer must be created within the runtime
+                let next = async { tokio::time::timeout_at(deadline, channels.recv()).await };
+
+                match runtime.block_on(next) {
+                    Ok(msg) => msg,
+                    // Runner has been idle
+                    Err(_) => {
+                        if shutdown_at == Some(deadline) {
+                            debug!("runner idle, shutting down office");
+                            backend.restart("idle shutdown");
+                            conversions_since_trim = 0;
+                        } else if let Some(backend) = backend.running() {
+                            debug!("runner idle, trimming memory");
+                            _ = trim_memory(backend, trim_config.target, &activity);
+                            conversions_since_trim = 0;
+                        }
+                        continue;
+                    }
+                }
+            }
+            None => runtime.block_on(channels.recv()),
+        };
+
+        let RunnerMsg {
+            msg,
+            span,
+            queued,
+            progress,
+        } = match msg {
+            Some(value) => value,
+            None => break,
+        };
+        activity.pool.queued.fetch_sub(1, Ordering::Relaxed);
+
+        // Logs while processing the message are attributed to the request
+        let _span = span.enter();
+
+        if let OfficeMsg::Recycle { prepare, tx } = msg {
+            debug!("recycling office");
+            backend.shutdown();
+
+            activity.restarting.store(true, Ordering::Relaxed);
+            let result = prepare();
+
+            if !backend.restarts_in_process {
+                if let Err(cause) = &result {
+                    error!(%cause, "failed to prepare office recycle");
+                }
+                exit_for_restart("recycle");
+            }
+
+            let result = result.and_then(|_| backend.get().map(|_| ()));
+            activity.restarting.store(false, Ordering::Relaxed);
+            _ = tx.send(result);
+
+            conversions_since_trim = 0;
+            idle_since = Instant::now();
+            continue;
+        }
+
+        let work = msg.work();
+        let is_work = work.is_some();
+
+        if is_work {
+            activity.pool.metrics.record_queue_wait(queued.elapsed());
+
+            if let Some(size) = msg.input().and_then(DocumentInput::size) {
+                activity.pool.metrics.record_input(size);
+            }
+
+            if let (Some(threshold), Some(backend)) =
+                (trim_config.memory_pause_threshold, backend.running())
+            {
+                pause_for_memory_pressure(backend, &trim_config, &activity, threshold);
+            }
+
+            activity.heartbeat();
+            activity.start_job();
+            *activity.tracked.lock() = progress;
+            activity.set_converting(&converting_tx, true);
+        }
+
+        let started = Instant::now();
+
+        // Panics while processing work drop the response, failing the request with a
+        // restarted error. Office may be left in an unknown state so it is torn down
+        // and started again by the next piece of work (Or the process exits when the
+        // backend cannot be started again within it)
+        let processed = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            // Work starts the backend when it has been shut down while idle
+            let running = if is_work {
+                backend.get().map(Some)
+            } else {
+                Ok(backend.running())
+            };
+
+            match (msg, running) {
+                (msg, Err(cause)) => {
+                    error!(%cause, "failed to start office");
+                    activity.record_failure(FailureClass::WorkerCrash);
+                    msg.fail(cause);
+                }
+
+                (
+                    OfficeMsg::Convert {
+                        input,
+                        options,
+                        tx,
+                        diagnostics,
+                    },
+                    Ok(Some(backend)),
+                ) => {
+                    // Convert document
+                    let result = backend.convert(input, *options);
+                    activity.record_outcome(&result);
+                    if let Ok(converted) = &result {
+                        if let Some(size) = converted.size() {
+                            activity.pool.metrics.record_output(size);
+                        }
+                    }
+
+                    if let (Err(_), Some(diagnostics)) = (&result, diagnostics) {
+                        _ = diagnostics.send(ConvertDiagnostics {
+                            duration_ms: started.elapsed().as_millis() as u64,
+                            events: backend.take_events(),
+                            log: backend.take_log(),
+                        });
+                    }
+
+                    trim_after_work(
+                        backend,
+                        &trim_config,
+                        &activity,
+                        &mut conversions_since_trim,
+                    );
+
+                    // Send response
+                    _ = tx.send(result);
+                }
+
+                (
+                    OfficeMsg::Pipeline {
+                        input,
+                        steps,
+                        options,
+                        tx,
+                    },
+                    Ok(Some(backend)),
+                ) => {
+                    // Run the pipeline steps
+                    let result = backend.pipeline(input, steps, options);
+                    activity.record_outcome(&result);
+                    if let Ok(bytes) = &result {
+                        activity.pool.metrics.record_output(bytes.len() as u64);
+                    }
+
+                    trim_after_work(
+                        backend,
+                        &trim_config,
+                        &activity,
+                        &mut conversions_since_trim,
+                    );
+
+                    // Send response
+                    _ = tx.send(result);
+                }
+
+                (OfficeMsg::ExtractAssets { input, tx }, Ok(Some(backend))) => {
+                    // Extract document assets
+                    let result = backend.extract_assets(input);
+                    activity.record_outcome(&result);
+                    if let Ok(bytes) = &result {
+                        activity.pool.metrics.record_output(bytes.len() as u64);
+                    }
+
+                    trim_after_work(
+                        backend,
+                        &trim_config,
+                        &activity,
+                        &mut conversions_since_trim,
+                    );
+
+                    // Send response
+                    _ = tx.send(result);
+                }
+
+                (OfficeMsg::ExtractStats { input, tx }, Ok(Some(backend))) => {
+                    // Extract document statistics
+                    let result = backend.extract_stats(input);
+                    activity.record_outcome(&result);
+
+                    trim_after_work(
+                        backend,
+                        &trim_config,
+                        &activity,
+                        &mut conversions_since_trim,
+                    );
+
+                    // Send response
+                    _ = tx.send(result);
+                }
+
+                (OfficeMsg::CollectGarbage, Ok(Some(backend))) => {
+                    if let Err(cause) = trim_memory(backend, trim_config.gc_target, &activity) {
+                        error!(%cause, "failed to collect garbage")
+                    }
+                    conversions_since_trim = 0;
+                }
+
+                (OfficeMsg::RefreshDetails { tx }, Ok(backend)) => {
+                    _ = tx.send(backend.map(|backend| backend.details()));
+                }
+
+                // Garbage collection is ignored while office is shut down
+                _ => {}
+            }
+        }));
+
+        if processed.is_err() {
+            error!("office runner failed while processing work, restarting office");
+            activity.record_failure(FailureClass::WorkerCrash);
+            backend.shutdown();
+            conversions_since_trim = 0;
+
+            if !backend.restarts_in_process {
+                exit_after_crash(&mut channels, &activity);
+            }
+        }
+
+        if let Some(work) = work {
+            activity.tracked.lock().take();
+            activity.set_phase(RunnerPhase::Idle);
+            activity.record(started.elapsed());
+            activity.pool.metrics.record_work(work, started.elapsed());
+            activity.finish_job();
+
+            let failures = activity.consecutive_failures.load(Ordering::Relaxed);
+            if recover_after.is_some_and(|recover_after| failures >= recover_after) {
+                recover_after_failures(&mut backend, &activity, failures);
+                conversions_since_trim = 0;
+            }
+
+            activity.set_converting(&converting_tx, false);
+            idle_since = Instant::now();
+        }
+    }
+
+    Ok(())
+}
+
+/// Flat ODF text document converted by the self test after repeated failures
+const SELFTEST_DOCUMENT: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0"
+    xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"
+    office:version="1.2" office:mimetype="application/vnd.oasis.opendocument.text">
+  <office:body>
+    <office:text>
+      <text:p>Office convert server self test.</text:p>
+    </office:text>
+  </office:body>
+</office:document>"#;
+
+/// Converts the built-in self test document after `failures` pieces of work
+/// failed in a row. When the self test passes the failures were caused by the
+/// documents, otherwise office is unhealthy and is recycled
+fn recover_after_failures<B, F>(
+    backend: &mut LazyBackend<B, F>,
+    activity: &RunnerActivity,
+    failures: u64,
+) where
+    B: ConvertBackend,
+    F: FnMut(Arc<RunnerActivity>) -> anyhow::Result<B>,
+{
+    warn!(failures, "work failing repeatedly, running self test");
+
+    activity.consecutive_failures.store(0, Ordering::Relaxed);
+    activity.pool.selftests.fetch_add(1, Ordering::Relaxed);
+    activity.heartbeat();
+
+    let result = backend.get().and_then(|backend| {
+        backend.convert(
+            DocumentInput::Bytes(Bytes::from_static(SELFTEST_DOCUMENT)),
+            ConvertOptions::default(),
+        )
+    });
+
+    let cause = match result {
+        Ok(_) => {
+            warn!(
+                failures,
+                "self test passed, failures were caused by the documents"
+            );
+            return;
+        }
+        Err(cause) => cause,
+    };
+
+    error!(%cause, failures, "self test failed, recycling office");
+    activity.pool.recoveries.fetch_add(1, Ordering::Relaxed);
+
+    backend.restart("failed self test");
+    if let Err(cause) = backend.get() {
+        error!(%cause, "failed to restart office after failed self test");
+    }
+}
+
+/// Maximum time the runner will pause for memory pressure, memory held
+/// outside of office may never be freed so work resumes after this
+const MAX_MEMORY_PAUSE: Duration = Duration::from_secs(30);
+
+/// Pauses the runner while the memory usage is above the `threshold`
+/// percentage, backend memory is trimmed first to try and relieve the pressure.
+///
+/// Requests continue to queue while paused (Subject to the max queue wait)
+fn pause_for_memory_pressure(
+    backend: &mut impl ConvertBackend,
+    trim_config: &TrimConfig,
+    activity: &RunnerActivity,
+    threshold: u64,
+) {
+    let is_pressured = || memory::current().is_some_and(|usage| usage.percent() >= threshold);
+
+    if !is_pressured() {
+        return;
+    }
+
+    _ = trim_memory(backend, trim_config.gc_target, activity);
+
+    if !is_pressured() {
+        debug!("memory pressure relieved by trimming");
+        return;
+    }
+
+    warn!(threshold, "memory pressure detected, pausing runner");
+
+    let started = Instant::now();
+
+    while is_pressured() {
+        if started.elapsed() >= MAX_MEMORY_PAUSE {
+            warn!("memory pressure persisted, resuming runner");
+            return;
+        }
+
+        std::thread::sleep(Duration::from_millis(250));
+    }
+
+    debug!(elapsed = ?started.elapsed(), "memory pressure relieved, resuming runner");
+}
+
+/// Attempts to free up some memory after a document has been processed,
+/// trimming according to the configured [TrimPolicy]
+fn trim_after_work(
+    backend: &mut impl ConvertBackend,
+    trim_config: &TrimConfig,
+    activity: &RunnerActivity,
+    conversions_since_trim: &mut u32,
+) {
+    *conversions_since_trim += 1;
+
+    let should_trim = match trim_config.policy {
+        TrimPolicy::Always => true,
+        TrimPolicy::EveryN => *conversions_since_trim >= trim_config.every,
+        TrimPolicy::Idle | TrimPolicy::Never => false,
+    };
+
+    if should_trim {
+        _ = trim_memory(backend, trim_config.target, activity);
+        *conversions_since_trim = 0;
+    }
+}
+
+/// Trims the backend memory to the `target`, recording the effect on the
+/// process memory so trim thresholds can be tuned from real data
+fn trim_memory(
+    backend: &mut impl ConvertBackend,
+    target: i32,
+    activity: &RunnerActivity,
+) -> anyhow::Result<()> {
+    let rss_before = memory::process_rss();
+    let started = Instant::now();
+
+    let result = backend.trim_memory(target);
+
+    *activity.pool.last_trim.lock() = Some(TrimEffect {
+        trimmed_at_ms: unix_millis(),
+        target,
+        rss_before_bytes: rss_before,
+        rss_after_bytes: memory::process_rss(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    });
+
+    result
+}
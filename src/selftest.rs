@@ -0,0 +1,241 @@
+use anyhow::{anyhow, Context};
+use libreofficekit::{DocumentType, Office};
+use office_convert_server::tempfiles::{random_id, TempFile};
+use std::{env::temp_dir, path::PathBuf, time::Instant};
+
+/// Flat ODF text document used as the source for the text formats
+const TEXT_SOURCE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0"
+    xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"
+    office:version="1.2" office:mimetype="application/vnd.oasis.opendocument.text">
+  <office:body>
+    <office:text>
+      <text:h text:outline-level="1">Self test</text:h>
+      <text:p>Document generated by the office convert server self test.</text:p>
+    </office:text>
+  </office:body>
+</office:document>"#;
+
+/// Flat ODF spreadsheet used as the source for the spreadsheet formats
+const SPREADSHEET_SOURCE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0"
+    xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0"
+    xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"
+    office:version="1.2" office:mimetype="application/vnd.oasis.opendocument.spreadsheet">
+  <office:body>
+    <office:spreadsheet>
+      <table:table table:name="Sheet1">
+        <table:table-row>
+          <table:table-cell office:value-type="string"><text:p>Name</text:p></table:table-cell>
+          <table:table-cell office:value-type="string"><text:p>Value</text:p></table:table-cell>
+        </table:table-row>
+        <table:table-row>
+          <table:table-cell office:value-type="string"><text:p>Alpha</text:p></table:table-cell>
+          <table:table-cell office:value-type="float" office:value="1"><text:p>1</text:p></table:table-cell>
+        </table:table-row>
+      </table:table>
+    </office:spreadsheet>
+  </office:body>
+</office:document>"#;
+
+/// Flat ODF presentation used as the source for the presentation formats
+const PRESENTATION_SOURCE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0"
+    xmlns:draw="urn:oasis:names:tc:opendocument:xmlns:drawing:1.0"
+    xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"
+    xmlns:svg="urn:oasis:names:tc:opendocument:xmlns:svg-compatible:1.0"
+    office:version="1.2" office:mimetype="application/vnd.oasis.opendocument.presentation">
+  <office:body>
+    <office:presentation>
+      <draw:page draw:name="Slide1">
+        <draw:frame svg:x="2cm" svg:y="2cm" svg:width="20cm" svg:height="3cm">
+          <draw:text-box><text:p>Self test</text:p></draw:text-box>
+        </draw:frame>
+      </draw:page>
+    </office:presentation>
+  </office:body>
+</office:document>"#;
+
+const CSV_SOURCE: &str = "Name,Value\nAlpha,1\nBeta,2\n";
+
+const HTML_SOURCE: &str = "<!DOCTYPE html>\n<html><head><title>Self test</title></head>\
+<body><h1>Self test</h1><p>Document generated by the office convert server self test.</p></body></html>";
+
+const RTF_SOURCE: &str = r"{\rtf1\ansi{\fonttbl\f0\fswiss Helvetica;}\f0\pard Self test\par}";
+
+/// Source a corpus document is created from
+enum Source {
+    /// Document is converted from a flat ODF source into the format
+    /// using LibreOffice (flat ODF extension, source)
+    Generated(&'static str, &'static str),
+    /// Document is used directly
+    Direct(&'static str),
+}
+
+/// Document in the built-in corpus
+struct CorpusDocument {
+    /// Format (file extension) of the document
+    format: &'static str,
+    /// Source of the document
+    source: Source,
+    /// Type of document LibreOffice is expected to load the document as
+    document_type: DocumentType,
+}
+
+/// Built-in corpus of tiny documents covering the major formats
+const CORPUS: &[CorpusDocument] = &[
+    CorpusDocument {
+        format: "doc",
+        source: Source::Generated("fodt", TEXT_SOURCE),
+        document_type: DocumentType::Text,
+    },
+    CorpusDocument {
+        format: "docx",
+        source: Source::Generated("fodt", TEXT_SOURCE),
+        document_type: DocumentType::Text,
+    },
+    CorpusDocument {
+        format: "odt",
+        source: Source::Generated("fodt", TEXT_SOURCE),
+        document_type: DocumentType::Text,
+    },
+    CorpusDocument {
+        format: "rtf",
+        source: Source::Direct(RTF_SOURCE),
+        document_type: DocumentType::Text,
+    },
+    CorpusDocument {
+        format: "html",
+        source: Source::Direct(HTML_SOURCE),
+        document_type: DocumentType::Text,
+    },
+    CorpusDocument {
+        format: "xls",
+        source: Source::Generated("fods", SPREADSHEET_SOURCE),
+        document_type: DocumentType::Spreadsheet,
+    },
+    CorpusDocument {
+        format: "xlsx",
+        source: Source::Generated("fods", SPREADSHEET_SOURCE),
+        document_type: DocumentType::Spreadsheet,
+    },
+    CorpusDocument {
+        format: "csv",
+        source: Source::Direct(CSV_SOURCE),
+        document_type: DocumentType::Spreadsheet,
+    },
+    CorpusDocument {
+        format: "ppt",
+        source: Source::Generated("fodp", PRESENTATION_SOURCE),
+        document_type: DocumentType::Presentation,
+    },
+    CorpusDocument {
+        format: "pptx",
+        source: Source::Generated("fodp", PRESENTATION_SOURCE),
+        document_type: DocumentType::Presentation,
+    },
+];
+
+/// Converts the built-in corpus using the office install at `office_path`
+/// printing a pass/fail report for each format
+pub async fn run(office_path: PathBuf) -> anyhow::Result<()> {
+    tokio::task::spawn_blocking(move || run_blocking(office_path))
+        .await
+        .context("self test failed")?
+}
+
+fn run_blocking(office_path: PathBuf) -> anyhow::Result<()> {
+    let office = Office::new(&office_path).context("failed to create office instance")?;
+
+    if let Ok(version) = office.get_version_info() {
+        println!(
+            "testing LibreOffice {}.{} ({})",
+            version.product_version.major, version.product_version.minor, version.build_id
+        );
+    }
+
+    let mut failures = 0;
+
+    for document in CORPUS {
+        let start = Instant::now();
+        let result = convert_corpus_document(&office, document);
+        let elapsed = start.elapsed();
+
+        match result {
+            Ok(()) => println!("PASS {:<5} ({elapsed:?})", document.format),
+            Err(err) => {
+                failures += 1;
+                println!("FAIL {:<5} ({elapsed:?}): {err:#}", document.format);
+            }
+        }
+    }
+
+    println!();
+    println!("{} passed, {failures} failed", CORPUS.len() - failures);
+
+    if failures > 0 {
+        return Err(anyhow!("{failures} formats failed the self test"));
+    }
+
+    Ok(())
+}
+
+/// Creates the corpus `document` and converts it to PDF
+fn convert_corpus_document(office: &Office, document: &CorpusDocument) -> anyhow::Result<()> {
+    let random_id = random_id();
+    let tmp_dir = temp_dir();
+
+    let input = TempFile {
+        path: tmp_dir.join(format!(
+            "lo_native_selftest_{random_id}.{}",
+            document.format
+        )),
+    };
+    let output = TempFile {
+        path: tmp_dir.join(format!("lo_native_selftest_{random_id}.pdf")),
+    };
+
+    match document.source {
+        Source::Generated(extension, source) => {
+            let source_file = TempFile {
+                path: tmp_dir.join(format!("lo_native_selftest_{random_id}.{extension}")),
+            };
+
+            std::fs::write(&source_file.path, source).context("failed to write source")?;
+
+            let mut doc = office
+                .document_load_with_options(&source_file.doc_url()?, "InteractionHandler=0,Batch=1")
+                .context("failed to load source")?;
+
+            if !doc.save_as(&input.doc_url()?, document.format, None)? {
+                return Err(anyhow!("failed to export {}", document.format));
+            }
+        }
+        Source::Direct(source) => {
+            std::fs::write(&input.path, source).context("failed to write document")?;
+        }
+    }
+
+    let mut doc = office
+        .document_load_with_options(&input.doc_url()?, "InteractionHandler=0,Batch=1")
+        .context("failed to load document")?;
+
+    let document_type = doc.get_document_type()?;
+    if document_type != document.document_type {
+        return Err(anyhow!(
+            "loaded as {document_type:?} expected {:?}",
+            document.document_type
+        ));
+    }
+
+    if !doc.save_as(&output.doc_url()?, "pdf", None)? {
+        return Err(anyhow!("failed to convert file"));
+    }
+
+    let bytes = std::fs::read(&output.path).context("failed to read output")?;
+    if !bytes.starts_with(b"%PDF") {
+        return Err(anyhow!("output is not a PDF"));
+    }
+
+    Ok(())
+}
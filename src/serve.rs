@@ -0,0 +1,1147 @@
+use anyhow::{anyhow, Context};
+use arc_swap::ArcSwap;
+use axum::{http::HeaderName, Router};
+use clap::{Args, ValueEnum};
+use office_convert_server::{
+    alerts::Alerts,
+    attestation::AttestationKey,
+    auth::{self, ApiKey, ApiKeys, Authorizer, ClientLimits, KeyLimits, Rate},
+    cache::ResultCache,
+    config::{
+        duration_arg, endpoint_arg, Backend, EndpointPattern, OfficeLogLevel, QueueConfig,
+        ServerConfig, ServerInfo, TrimConfig, TrimPolicy, WatchdogConfig,
+        DEFAULT_MEMORY_REQUEST_LIMIT, DEFAULT_MEMORY_TOTAL_LIMIT, DEFAULT_QUEUE_CAPACITY,
+    },
+    convert::{ConvertBackend, LibreOfficeBackend},
+    detect::MismatchPolicy,
+    diagnostics::LogToggle,
+    dirs,
+    embedded::EmbeddedLimits,
+    jobs::JobStore,
+    maintenance::{self, Maintenance, MaintenanceWindow},
+    presign::UrlSigner,
+    priority::{self, ApiKeyTier},
+    profile::{self, Dictionary, FontReplacement, ProfileSettings, RegistrySetting},
+    proxy::{self, Cidr, TrustedProxies},
+    quarantine::Quarantine,
+    retention::OutputRetention,
+    runner::{
+        create_office_pool, create_office_runner, warm_standby, OfficeHandle, RunnerActivity,
+        SharedDetails,
+    },
+    server,
+    signing::SigningKey,
+    soffice::SofficeBackend,
+    storage::{self, StorageLocation},
+    stub::StubBackend,
+    tempfiles::MemoryWorkDir,
+    throttle::{self, QueueLimits, ThrottleWindow},
+};
+use std::{env::temp_dir, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
+use tracing::{debug, error, info, warn};
+use url::Url;
+
+/// Arguments for running the server
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Preset of defaults for the logging and limits that are not provided, "dev" logs at the debug level and
+    /// keeps serving when office fails to start, "prod" logs at the info level and enables the queue, hang and
+    /// download limits (Omit to use the defaults of each argument)
+    #[arg(long, value_enum)]
+    pub profile: Option<Preset>,
+
+    /// Port to bind the server to, defaults to 8080
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Host to bind the server to, defaults to 0.0.0.0
+    #[arg(long)]
+    host: Option<String>,
+
+    /// When office memory should be trimmed after conversions, defaults to "always"
+    #[arg(long, value_enum, default_value_t = TrimPolicy::Always)]
+    trim_policy: TrimPolicy,
+
+    /// Trim target used after conversions (>= 1000 encourages maximum saving), defaults to 1000
+    #[arg(long, default_value_t = 1000)]
+    trim_target: i32,
+
+    /// Trim target used when garbage collection is requested, defaults to 2000
+    #[arg(long, default_value_t = 2000)]
+    gc_trim_target: i32,
+
+    /// Number of conversions between trims for the "every-n" trim policy, defaults to 10
+    #[arg(long, default_value_t = 10)]
+    trim_every: u32,
+
+    /// Seconds without conversions before trimming for the "idle" trim policy, defaults to 30
+    #[arg(long, default_value_t = 30)]
+    trim_idle_secs: u64,
+
+    /// Token required in the "X-Admin-Token" header for admin only functionality
+    #[arg(long)]
+    admin_token: Option<String>,
+
+    /// Allow admin requests to run a named macro before exporting documents (Requires --admin-token)
+    #[arg(long)]
+    allow_macros: bool,
+
+    /// Path to the Ed25519 public key (PEM or base64) of an upstream service (i.e a scanning gateway) that signs
+    /// uploads, upload signatures are verified against it and recorded in the audit log (Omit to disable)
+    #[arg(long)]
+    attestation_key: Option<PathBuf>,
+
+    /// Reject uploads without a signature (Requires --attestation-key)
+    #[arg(long)]
+    require_attestation: bool,
+
+    /// Path to an Ed25519 private key (PKCS#8 PEM or base64) conversion results are signed with, signatures are
+    /// provided as a JWS in the "X-Result-Signature" header (Omit to disable)
+    #[arg(long)]
+    signing_key: Option<PathBuf>,
+
+    /// Maximum nesting depth of embedded objects (OLE within OLE) in uploaded documents, deeper documents
+    /// are rejected before conversion (Omit to disable)
+    #[arg(long)]
+    max_embedded_depth: Option<u32>,
+
+    /// Maximum number of images in uploaded documents (Including embedded objects), documents with more
+    /// images are rejected before conversion (Omit to disable)
+    #[arg(long)]
+    max_embedded_images: Option<u64>,
+
+    /// Handling of uploads with content that doesn't match their file extension, defaults to "ignore"
+    #[arg(long, value_enum, default_value_t = MismatchPolicy::Ignore)]
+    format_mismatch: MismatchPolicy,
+
+    /// Embed the 14 standard PDF fonts in converted PDFs unless the request specifies otherwise (Larger output,
+    /// required by some print workflows)
+    #[arg(long)]
+    embed_standard_fonts: bool,
+
+    /// Uploads smaller than this many bytes are rejected before queueing (Empty uploads are always rejected)
+    #[arg(long)]
+    min_upload_size: Option<usize>,
+
+    /// Uploads of at least this many bytes are spilled to disk while waiting for a busy runner (Omit to disable)
+    #[arg(long)]
+    spill_threshold: Option<usize>,
+
+    /// Time a client may go without reading a download (i.e "30s", "5m") before the download is expired, releasing
+    /// the converted file and closing the connection (Omit to wait indefinitely)
+    #[arg(long, value_parser = duration_arg)]
+    download_idle_timeout: Option<Duration>,
+
+    /// Converted files of at least this many bytes are written to disk and downloaded from there instead of memory
+    /// so slow downloads don't hold them in memory (Omit to download from memory)
+    #[arg(long)]
+    download_spill_threshold: Option<usize>,
+
+    /// Write every upload to disk before queueing (at most this many at once) instead of on the converter thread,
+    /// so disk IO for large uploads doesn't hold up conversions (Omit to write uploads when converting)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    io_concurrency: Option<u64>,
+
+    /// Directory to cache converted documents in (Omit to disable caching)
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// Object storage to cache converted documents in instead of a directory, shared by servers using the same
+    /// storage (i.e "s3://bucket/cache", "gs://bucket/cache" or "azure://container/cache")
+    #[arg(long, value_parser = storage::storage_arg, conflicts_with = "cache_dir")]
+    cache_storage: Option<StorageLocation>,
+
+    /// Max age in seconds clients and CDNs may cache converted results for, defaults to 1 year
+    #[arg(long, default_value_t = 31_536_000)]
+    cache_max_age: u64,
+
+    /// Zstd compression level (1-22) to store cached results with (Omit to store uncompressed)
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..=22))]
+    cache_compression_level: Option<i32>,
+
+    /// Directory for the LibreOffice user profile, created and pre-seeded with conversion defaults on first start (Omit to use the default profile)
+    #[arg(long)]
+    profile_dir: Option<PathBuf>,
+
+    /// Default locale of complex text layout (CTL) scripts (i.e "ar-SA", "he-IL"), sets the default
+    /// direction and language of right-to-left text in documents that don't specify one (Requires --profile-dir)
+    #[arg(long, value_parser = profile::locale_arg)]
+    ctl_locale: Option<String>,
+
+    /// Default font for complex text layout (CTL) scripts such as Arabic and Hebrew (i.e "Noto Sans Arabic"),
+    /// used when a document doesn't specify a font (Requires --profile-dir)
+    #[arg(long)]
+    ctl_font: Option<String>,
+
+    /// Font to always replace with an installed font when rendering (i.e "Arial=Noto Sans"), for documents
+    /// using fonts missing from the container (Requires --profile-dir) (Can be provided multiple times)
+    #[arg(long, value_parser = profile::font_replacement_arg)]
+    font_replacement: Vec<FontReplacement>,
+
+    /// Office configuration (registry) property to set in the profile in the form "/node/path/Prop=value" (i.e
+    /// "/org.openoffice.Office.Writer/Layout/Other/MeasureUnit=2") (Requires --profile-dir) (Can be provided
+    /// multiple times)
+    #[arg(long, value_parser = profile::registry_setting_arg)]
+    profile_setting: Vec<RegistrySetting>,
+
+    /// File containing office configuration properties to set in the profile, one property per line in the same
+    /// form as --profile-setting (Requires --profile-dir)
+    #[arg(long)]
+    profile_settings_file: Option<PathBuf>,
+
+    /// Directory of spelling dictionaries ("de_DE.aff" and "de_DE.dic") and hyphenation patterns ("hyph_de_DE.dic")
+    /// registered with office, so line breaks don't depend on the dictionaries installed on the node (Requires
+    /// --profile-dir) (Can be provided multiple times)
+    #[arg(long)]
+    dictionary_dir: Vec<PathBuf>,
+
+    /// Directory converted files are written to when requests ask to store the output instead of responding with it,
+    /// for handing off to the next pipeline stage through a shared volume (Omit to disable)
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Time stored outputs are kept for before they are removed from the output directory (i.e "24h", "7d")
+    /// (Requires --output-dir) (Omit to keep stored outputs)
+    #[arg(long, value_parser = duration_arg)]
+    output_retention: Option<Duration>,
+
+    /// Secret pre-signed URLs to stored outputs are signed with (HMAC-SHA256), stored outputs include an expiring
+    /// URL that can be handed to end users (Requires --output-dir) (Omit to disable)
+    #[arg(long)]
+    url_signing_secret: Option<String>,
+
+    /// Time pre-signed URLs remain valid for (i.e "15m", "1h")
+    #[arg(long, value_parser = duration_arg, default_value = "1h")]
+    presigned_url_ttl: Duration,
+
+    /// Directory the inputs of failed conversions are preserved in along with the error reported by office,
+    /// for reproducing failures reported against specific files (Omit to disable)
+    #[arg(long)]
+    quarantine_dir: Option<PathBuf>,
+
+    /// Time quarantined inputs are kept for before they are removed (i.e "24h", "7d")
+    #[arg(long, value_parser = duration_arg, default_value = "24h")]
+    quarantine_retention: Duration,
+
+    /// Directory the results of background conversion jobs (POST /jobs) are written to, for clients that can't
+    /// hold a request open while large documents convert (Omit to disable)
+    #[arg(long)]
+    jobs_dir: Option<PathBuf>,
+
+    /// Storage the background conversion jobs are stored in instead of a directory, servers using the same
+    /// storage can report (and serve the results of) jobs accepted by each other (i.e "s3://bucket/jobs" or
+    /// "redis://redis:6379/jobs")
+    #[arg(long, value_parser = storage::storage_arg, conflicts_with = "jobs_dir")]
+    jobs_storage: Option<StorageLocation>,
+
+    /// Time the results of finished jobs are kept for before they are removed (i.e "1h", "24h")
+    #[arg(long, value_parser = duration_arg, default_value = "1h")]
+    job_retention: Duration,
+
+    /// Time the status of finished jobs is kept for before it is removed (i.e "24h", "7d"), statuses outlive the
+    /// result so clients polling late learn the result expired. Defaults to the --job-retention
+    #[arg(long, value_parser = duration_arg)]
+    job_status_retention: Option<Duration>,
+
+    /// API key assigned to a priority tier (i.e "team-key=batch"), requests providing the key in the "X-Api-Key"
+    /// header are queued by its tier ("interactive" or "batch"), other requests are interactive (Can be provided
+    /// multiple times)
+    #[arg(long, value_parser = priority::api_key_tier_arg)]
+    api_key_tier: Vec<ApiKeyTier>,
+
+    /// API key required in the "X-Api-Key" header to use the server, optionally with its own limits (i.e
+    /// "web-app,rate=10/s,max-concurrent=2"). When any key is configured requests without a known key are rejected
+    /// (Can be provided multiple times)
+    #[arg(long, value_parser = auth::api_key_arg)]
+    api_key: Vec<ApiKey>,
+
+    /// File containing the API keys required to use the server, one key per line in the same form as --api-key
+    #[arg(long)]
+    api_keys_file: Option<PathBuf>,
+
+    /// URL of an external authorizer deciding which requests are allowed instead of API keys (Forward-auth), the
+    /// authorizer is sent the method, path and query, client address and the "X-Api-Key", "X-Tenant-Id",
+    /// "Authorization" and "Cookie" headers of each request
+    #[arg(long, conflicts_with_all = ["api_key", "api_keys_file"])]
+    authorizer_url: Option<Url>,
+
+    /// Extra header of requests forwarded to the authorizer (i.e "x-session-token"), decisions are cached by the
+    /// forwarded headers (Can be provided multiple times)
+    #[arg(long)]
+    authorizer_header: Vec<HeaderName>,
+
+    /// Time decisions of the authorizer are cached for (i.e "10s", "0s" to ask for every request)
+    #[arg(long, value_parser = duration_arg, default_value = "10s")]
+    authorizer_cache_ttl: Duration,
+
+    /// Time to wait for the authorizer to respond before rejecting the request
+    #[arg(long, value_parser = duration_arg, default_value = "5s")]
+    authorizer_timeout: Duration,
+
+    /// Rate conversions are allowed at for each API key, or each client address when API keys are not configured
+    /// (i.e "10/s", "600/m"), up to the full amount can be used at once (Omit for unlimited)
+    #[arg(long, value_parser = auth::rate_arg)]
+    rate_limit: Option<Rate>,
+
+    /// Maximum number of conversions running at once for each API key, or each client address when API keys are
+    /// not configured (Omit for unlimited)
+    #[arg(long)]
+    max_concurrent_per_key: Option<u32>,
+
+    /// Address range of proxies (i.e an ingress) trusted to provide the client address and scheme in the
+    /// "X-Forwarded-For" and "X-Forwarded-Proto" headers (i.e "10.0.0.0/8") (Can be provided multiple times)
+    #[arg(long, value_parser = proxy::cidr_arg)]
+    trusted_proxy: Vec<Cidr>,
+
+    /// Endpoint to disable, requests for it respond with a 404 as if it didn't exist. Either the path of the
+    /// endpoint (i.e "/collect-garbage", "/jobs/{id}") or a prefix ending in "*" (i.e "/admin/*") (Can be provided
+    /// multiple times)
+    #[arg(long, value_parser = endpoint_arg)]
+    disable_endpoint: Vec<EndpointPattern>,
+
+    /// Directory to write documents to while converting, defaults to the system temp directory
+    #[arg(long)]
+    work_dir: Option<PathBuf>,
+
+    /// RAM-backed directory (i.e a tmpfs mount) to write small documents to while converting instead of the work
+    /// directory, documents over the limits are written to the work directory (Omit to always use the work directory)
+    #[arg(long)]
+    memory_work_dir: Option<PathBuf>,
+
+    /// Largest document in bytes written to the memory work directory, defaults to 16 MiB
+    #[arg(long, default_value_t = DEFAULT_MEMORY_REQUEST_LIMIT)]
+    memory_request_limit: u64,
+
+    /// Total size in bytes of the files the memory work directory may hold, defaults to 256 MiB
+    #[arg(long, default_value_t = DEFAULT_MEMORY_TOTAL_LIMIT)]
+    memory_total_limit: u64,
+
+    /// Verbosity of the LibreOffice log output collected while converting, the tail of the output is included in
+    /// failure responses and quarantined conversions, defaults to "off"
+    #[arg(long, value_enum, default_value_t = OfficeLogLevel::Off)]
+    office_log: OfficeLogLevel,
+
+    /// Maximum time a request may wait for the converter (i.e "30s", "500ms", "2m") before failing with 503 (Omit to wait indefinitely)
+    #[arg(long, value_parser = duration_arg)]
+    max_queue_wait: Option<Duration>,
+
+    /// Number of requests the queue holds ahead of the converter, once full requests wait to enter the queue by
+    /// their priority tier (up to --max-queue-wait). Requests in the queue have already been admitted so larger
+    /// queues weaken priority tiers, defaults to 1
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = DEFAULT_QUEUE_CAPACITY as u64)]
+    queue_capacity: u64,
+
+    /// Time without LibreOffice reporting progress while converting before the conversion is considered hung
+    /// and the server exits to be restarted (i.e "60s", "2m") (Omit to disable)
+    #[arg(long, value_parser = duration_arg)]
+    hang_timeout: Option<Duration>,
+
+    /// Maximum time a single conversion may take (i.e "5m") before it is considered hung and the server exits to
+    /// be restarted, for documents that keep office busy without finishing (Omit to disable)
+    #[arg(long, value_parser = duration_arg)]
+    convert_timeout: Option<Duration>,
+
+    /// Number of consecutive failed conversions after which the built-in self test document is converted,
+    /// office is recycled when the self test also fails, the server exits to be restarted instead with the
+    /// "libreoffice" backend (Omit to disable)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    recover_after_failures: Option<u64>,
+
+    /// Memory usage (percent of the container memory limit) at which the converter pauses taking new work
+    /// until memory is freed, avoiding the OOM killer (Omit to disable)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..=100))]
+    memory_pause_threshold: Option<u64>,
+
+    /// Interval to re-query the office version and supported formats at (i.e "10m", "1h") (Omit to only
+    /// query at startup and when requested through /admin/refresh-details)
+    #[arg(long, value_parser = duration_arg)]
+    details_refresh_interval: Option<Duration>,
+
+    /// Backend used to perform conversions, the "soffice" backend runs a `soffice --convert-to` process
+    /// per conversion and the "stub" backend responds with a placeholder PDF without requiring a LibreOffice
+    /// install (For development and CI only), defaults to "libreoffice"
+    #[arg(long, value_enum, default_value_t = Backend::Libreoffice)]
+    backend: Backend,
+
+    /// Number of workers converting documents from the shared queue at once, each worker of the "soffice"
+    /// backend runs its own soffice processes with a dedicated profile in the work directory. LibreOfficeKit
+    /// only supports one office per process so the "libreoffice" backend requires a single worker
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    workers: u64,
+
+    /// Keep a spare backend started for each worker, a backend restarted after it was recycled, crashed or failed
+    /// the self test is replaced by the spare immediately while the next spare starts in the background. Requires
+    /// the "soffice" backend as LibreOfficeKit only supports one office per process
+    #[arg(long)]
+    warm_standby: bool,
+
+    /// Time without conversions after which office is shut down to release its memory (i.e "10m", "1h"),
+    /// office is started again by the next conversion, the server exits to be restarted instead with the
+    /// "libreoffice" backend. Office is started by the first conversion rather than at startup so restarted
+    /// servers stay cold until they are used (Omit to keep office running)
+    #[arg(long, value_parser = duration_arg)]
+    idle_shutdown: Option<Duration>,
+
+    /// Daily time (UTC) to run maintenance at (i.e "03:30"), maintenance cleans up the result cache, drains the
+    /// server and restarts office with a fresh profile (Can be provided multiple times)
+    #[arg(long, value_parser = maintenance::window_arg)]
+    maintenance_window: Vec<MaintenanceWindow>,
+
+    /// Daily time window (UTC) with its own queue limits (i.e "09:00-17:00,batch-max-skips=unlimited,max-queue-wait=10s"),
+    /// "batch-max-skips" is the number of interactive requests admitted before a waiting batch request ("unlimited"
+    /// to only admit batch requests when no interactive requests are waiting), the first matching window applies
+    /// (Can be provided multiple times)
+    #[arg(long, value_parser = throttle::window_arg)]
+    throttle_window: Vec<ThrottleWindow>,
+
+    /// Webhook URL (Slack-compatible) alerts are posted to for office restarts, sustained queue saturation,
+    /// repeated failures and low disk space (Omit to disable)
+    #[arg(long)]
+    alert_webhook: Option<Url>,
+
+    /// Interval to check for alert events at (i.e "30s", "1m")
+    #[arg(long, value_parser = duration_arg, default_value = "30s")]
+    alert_interval: Duration,
+
+    /// Keep serving when office fails to start, reporting the startup error from /status, /healthz and /diagnostics
+    #[arg(long)]
+    degraded_mode: bool,
+}
+
+/// Preset of defaults for running the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// Local development, verbose logging and diagnostics are served when
+    /// office fails to start
+    Dev,
+    /// Production deployments, quieter logging and limits protecting the
+    /// server from stuck conversions and slow clients
+    Prod,
+}
+
+impl Preset {
+    /// Logging filter used when the "RUST_LOG" environment variable is not set
+    pub fn log_filter(&self) -> &'static str {
+        match self {
+            Preset::Dev => "office_convert_server=debug,info",
+            Preset::Prod => "office_convert_server=info,warn",
+        }
+    }
+}
+
+impl ServeArgs {
+    /// Fills in the defaults of the profile for the arguments that were not
+    /// provided
+    fn apply_profile(&mut self) {
+        match self.profile {
+            Some(Preset::Dev) => {
+                self.degraded_mode = true;
+            }
+            Some(Preset::Prod) => {
+                self.max_queue_wait.get_or_insert(Duration::from_secs(60));
+                self.hang_timeout.get_or_insert(Duration::from_secs(2 * 60));
+                self.convert_timeout
+                    .get_or_insert(Duration::from_secs(10 * 60));
+                self.download_idle_timeout
+                    .get_or_insert(Duration::from_secs(60));
+                self.recover_after_failures.get_or_insert(3);
+            }
+            None => {}
+        }
+    }
+}
+
+/// Checks the directories used while converting are usable, failing with a
+/// clear error at startup rather than mid-conversion
+/// Backend creation function used by a worker of the runner
+type CreateBackend<B> = Box<dyn FnMut(Arc<RunnerActivity>) -> anyhow::Result<B> + Send>;
+
+/// Wraps `create_backend` to keep a spare backend when `enabled`, see [warm_standby]
+fn standby_if<B, F>(enabled: bool, create_backend: F) -> CreateBackend<B>
+where
+    B: ConvertBackend + Send + 'static,
+    F: FnMut(Arc<RunnerActivity>) -> anyhow::Result<B> + Send + 'static,
+{
+    match enabled {
+        true => Box::new(warm_standby(create_backend)),
+        false => Box::new(create_backend),
+    }
+}
+
+fn check_directories(
+    work_dir: &std::path::Path,
+    profile_dir: Option<&std::path::Path>,
+) -> anyhow::Result<()> {
+    dirs::ensure_writable(
+        work_dir,
+        "work directory",
+        "use --work-dir to use another directory",
+    )?;
+
+    if let Some(profile_dir) = profile_dir {
+        dirs::ensure_writable(
+            profile_dir,
+            "profile directory",
+            "use --profile-dir to use another directory",
+        )?;
+    }
+
+    // Office writes its own temporary files to the system temp directory, some of
+    // which are mapped as executable when generating code at runtime
+    let temp_dir = temp_dir();
+    dirs::ensure_writable(
+        &temp_dir,
+        "temp directory",
+        "set TMPDIR to use another directory",
+    )?;
+
+    if let Err(err) = dirs::ensure_executable(
+        &temp_dir,
+        "temp directory",
+        "set TMPDIR to use another directory if conversions fail",
+    ) {
+        warn!("{err}");
+    }
+
+    Ok(())
+}
+
+/// Collects the API keys from the `args`, the "API_KEYS" environment variable
+/// and the API keys file. Keys assigned a priority tier are also accepted, the
+/// server doesn't require keys when none are configured
+fn load_api_keys(args: &ServeArgs, defaults: KeyLimits) -> anyhow::Result<Option<ApiKeys>> {
+    let mut keys = args.api_key.clone();
+
+    if let Ok(value) = std::env::var("API_KEYS") {
+        keys.extend(auth::parse_api_keys(&value).map_err(|err| anyhow!("API_KEYS: {err}"))?);
+    }
+
+    if let Some(path) = args.api_keys_file.as_deref() {
+        keys.extend(auth::load_api_keys(path)?);
+    }
+
+    if keys.is_empty() {
+        return Ok(None);
+    }
+
+    for tier in &args.api_key_tier {
+        if !keys.iter().any(|key| key.key == tier.key) {
+            keys.push(ApiKey {
+                key: tier.key.clone(),
+                limits: KeyLimits::default(),
+            });
+        }
+    }
+
+    let api_keys = ApiKeys::new(keys, defaults);
+    debug!("requiring one of {} api keys", api_keys.len());
+
+    Ok(Some(api_keys))
+}
+
+/// Runs the server using the office install at `office_path`, debug logging
+/// is switched with the `log_toggle`
+pub async fn run(
+    mut args: ServeArgs,
+    office_path: Option<PathBuf>,
+    log_toggle: Option<LogToggle>,
+) -> anyhow::Result<()> {
+    args.apply_profile();
+
+    let key_limits = KeyLimits {
+        rate: args.rate_limit,
+        max_concurrent: args.max_concurrent_per_key,
+    };
+    let endpoints = server::endpoints();
+    for pattern in &args.disable_endpoint {
+        if !endpoints.iter().any(|path| pattern.matches(path)) {
+            return Err(anyhow!(
+                "--disable-endpoint \"{pattern}\" doesn't match any endpoint"
+            ));
+        }
+    }
+
+    let api_keys = load_api_keys(&args, key_limits)?;
+    let authorizer = match args.authorizer_url.clone() {
+        Some(_) if api_keys.is_some() => {
+            return Err(anyhow!("--authorizer-url can't be used with API keys"));
+        }
+        Some(url) => {
+            debug!(%url, "delegating authorization to external authorizer");
+            Some(
+                Authorizer::new(url, args.authorizer_cache_ttl, args.authorizer_timeout)?
+                    .with_forwarded_headers(args.authorizer_header.clone()),
+            )
+        }
+        None => None,
+    };
+    let client_limits = match api_keys {
+        Some(_) => None,
+        None => ClientLimits::new(key_limits),
+    };
+
+    // Determine the address to run the server on
+    let server_address = if args.host.is_some() || args.port.is_some() {
+        let host = args.host.unwrap_or_else(|| "0.0.0.0".to_string());
+        let port = args.port.unwrap_or(8080);
+
+        format!("{host}:{port}")
+    } else {
+        std::env::var("SERVER_ADDRESS").context("missing SERVER_ADDRESS")?
+    };
+
+    if args.allow_macros && args.admin_token.is_none() {
+        return Err(anyhow!(
+            "--allow-macros requires an --admin-token to be set"
+        ));
+    }
+
+    if args.require_attestation && args.attestation_key.is_none() {
+        return Err(anyhow!(
+            "--require-attestation requires an --attestation-key to be set"
+        ));
+    }
+
+    let attestation_key = args
+        .attestation_key
+        .as_deref()
+        .map(AttestationKey::load)
+        .transpose()?;
+
+    if let Some(key) = attestation_key.as_ref() {
+        debug!("verifying upload signatures with key: {}", key.id());
+    }
+
+    let signing_key = args
+        .signing_key
+        .as_deref()
+        .map(SigningKey::load)
+        .transpose()?;
+
+    if let Some(key) = signing_key.as_ref() {
+        debug!("signing results with key: {}", key.id());
+    }
+
+    if args.output_retention.is_some() && args.output_dir.is_none() {
+        return Err(anyhow!(
+            "--output-retention requires an --output-dir to be set"
+        ));
+    }
+
+    let mut registry_settings = args.profile_setting.clone();
+    if let Some(path) = args.profile_settings_file.as_deref() {
+        registry_settings.extend(profile::load_registry_settings(path)?);
+    }
+
+    // Dictionaries in earlier directories take priority
+    let mut dictionaries: Vec<Dictionary> = Vec::new();
+    for dir in &args.dictionary_dir {
+        for dictionary in profile::find_dictionaries(dir)? {
+            if !dictionaries
+                .iter()
+                .any(|existing| existing.name == dictionary.name)
+            {
+                dictionaries.push(dictionary);
+            }
+        }
+    }
+    debug!("found {} dictionaries", dictionaries.len());
+
+    let profile_settings = ProfileSettings {
+        ctl_locale: args.ctl_locale.clone(),
+        ctl_font: args.ctl_font.clone(),
+        font_replacements: args.font_replacement.clone(),
+        registry_settings,
+        dictionaries,
+    };
+
+    if !profile_settings.is_empty() && args.profile_dir.is_none() {
+        return Err(anyhow!(
+            "--ctl-locale, --ctl-font, --font-replacement, --profile-setting and --dictionary-dir require a --profile-dir to be set"
+        ));
+    }
+
+    if args.url_signing_secret.is_some() && args.output_dir.is_none() {
+        return Err(anyhow!(
+            "--url-signing-secret requires an --output-dir to be set"
+        ));
+    }
+
+    let url_signer = args
+        .url_signing_secret
+        .as_deref()
+        .map(|secret| UrlSigner::new(secret.as_bytes(), args.presigned_url_ttl));
+
+    if args.workers > 1 && args.backend == Backend::Libreoffice {
+        return Err(anyhow!(
+            "--workers requires the soffice backend, LibreOfficeKit only supports one office per process"
+        ));
+    }
+
+    if args.warm_standby && args.backend == Backend::Libreoffice {
+        return Err(anyhow!(
+            "--warm-standby requires the soffice backend, LibreOfficeKit only supports one office per process"
+        ));
+    }
+
+    if args.warm_standby && args.idle_shutdown.is_some() {
+        return Err(anyhow!(
+            "--warm-standby cannot be combined with --idle-shutdown, the spare would keep office running while idle"
+        ));
+    }
+
+    if args.workers > 1 && args.profile_dir.is_some() {
+        return Err(anyhow!(
+            "--profile-dir cannot be used with multiple --workers, soffice processes cannot share a profile"
+        ));
+    }
+
+    if args.embed_standard_fonts && args.backend == Backend::Soffice {
+        return Err(anyhow!(
+            "--embed-standard-fonts is not supported by the soffice backend"
+        ));
+    }
+
+    let work_dir = args.work_dir.unwrap_or_else(temp_dir);
+
+    let memory_dir = match args.memory_work_dir {
+        Some(path) => {
+            if args.backend == Backend::Soffice {
+                return Err(anyhow!(
+                    "--memory-work-dir is not supported by the soffice backend"
+                ));
+            }
+
+            dirs::ensure_writable(
+                &path,
+                "memory work directory",
+                "use --memory-work-dir to use another directory",
+            )?;
+
+            Some(MemoryWorkDir {
+                path,
+                request_limit: args.memory_request_limit,
+                total_limit: args.memory_total_limit,
+            })
+        }
+        None => None,
+    };
+
+    // Directories checked for available disk space by alerts
+    let alert_dirs: Vec<PathBuf> = std::iter::once(work_dir.clone())
+        .chain(args.output_dir.clone())
+        .chain(args.cache_dir.clone())
+        .chain(args.quarantine_dir.clone())
+        .chain(args.jobs_dir.clone())
+        .chain(
+            [args.cache_storage.as_ref(), args.jobs_storage.as_ref()]
+                .into_iter()
+                .flatten()
+                .filter_map(StorageLocation::local_dir)
+                .cloned(),
+        )
+        .collect();
+
+    if let Some(output_dir) = args.output_dir.as_deref() {
+        dirs::ensure_writable(
+            output_dir,
+            "output directory",
+            "use --output-dir to use another directory",
+        )?;
+    }
+
+    let quarantine = match args.quarantine_dir {
+        Some(dir) => {
+            dirs::ensure_writable(
+                &dir,
+                "quarantine directory",
+                "use --quarantine-dir to use another directory",
+            )?;
+
+            Some(Arc::new(Quarantine {
+                dir,
+                max_age: args.quarantine_retention,
+            }))
+        }
+        None => None,
+    };
+
+    let jobs = match (args.jobs_dir, args.jobs_storage.as_ref()) {
+        (Some(dir), _) => {
+            dirs::ensure_writable(
+                &dir,
+                "jobs directory",
+                "use --jobs-dir to use another directory",
+            )?;
+
+            Some(Arc::new(
+                JobStore::new(dir, args.job_retention)
+                    .with_status_max_age(args.job_status_retention.unwrap_or_default()),
+            ))
+        }
+        (None, Some(location)) => {
+            debug!("storing job results in: {location}");
+            let storage = location.open().context("failed to open jobs storage")?;
+            Some(Arc::new(
+                JobStore::with_storage(storage, args.job_retention)
+                    .with_status_max_age(args.job_status_retention.unwrap_or_default()),
+            ))
+        }
+        (None, None) => None,
+    };
+
+    let server_config = ServerConfig {
+        admin_token: args.admin_token,
+        allow_macros: args.allow_macros,
+        attestation_key,
+        require_attestation: args.require_attestation,
+        signing_key,
+        min_upload_size: args.min_upload_size,
+        spill_threshold: args.spill_threshold,
+        io_limit: args
+            .io_concurrency
+            .map(|permits| Arc::new(Semaphore::new(permits as usize))),
+        work_dir: work_dir.clone(),
+        download_idle_timeout: args.download_idle_timeout,
+        download_spill_threshold: args.download_spill_threshold,
+        embedded_limits: EmbeddedLimits {
+            max_depth: args.max_embedded_depth,
+            max_images: args.max_embedded_images,
+        },
+        format_mismatch: args.format_mismatch,
+        embed_standard_fonts: args.embed_standard_fonts,
+        output_dir: args.output_dir.clone(),
+        url_signer,
+        quarantine: quarantine.clone(),
+        jobs: jobs.clone(),
+        api_key_tiers: args
+            .api_key_tier
+            .iter()
+            .map(|value| (value.key.clone(), value.tier))
+            .collect(),
+        api_keys,
+        authorizer,
+        client_limits,
+        trusted_proxies: TrustedProxies::new(args.trusted_proxy.clone()),
+        disabled_endpoints: args.disable_endpoint.clone(),
+        info: ServerInfo {
+            office_path: office_path.clone(),
+            profile_dir: args.profile_dir.clone(),
+            backend: args.backend,
+            max_queue_wait: args.max_queue_wait,
+            hang_timeout: args.hang_timeout,
+            convert_timeout: args.convert_timeout,
+            recover_after_failures: args.recover_after_failures,
+            memory_pause_threshold: args.memory_pause_threshold,
+            idle_shutdown: args.idle_shutdown,
+            io_concurrency: args.io_concurrency.map(|permits| permits as usize),
+            output_retention: args.output_retention,
+            job_retention: jobs.as_ref().map(|jobs| jobs.result_max_age),
+            job_status_retention: jobs.as_ref().map(|jobs| jobs.status_max_age),
+            throttle_windows: args.throttle_window.clone(),
+        },
+        log_toggle: log_toggle.clone(),
+        office_log: args.office_log,
+    };
+
+    // Create the result cache if enabled
+    let result_cache: Option<Arc<ResultCache>> = match (args.cache_dir, args.cache_storage) {
+        (Some(cache_dir), _) => {
+            debug!("caching results in: {}", cache_dir.display());
+            let cache = ResultCache::new(
+                cache_dir,
+                Duration::from_secs(args.cache_max_age),
+                args.cache_compression_level,
+            )?;
+            Some(Arc::new(cache))
+        }
+        (None, Some(location)) => {
+            debug!("caching results in: {location}");
+            let storage = location.open().context("failed to open cache storage")?;
+            let cache = ResultCache::with_storage(
+                storage,
+                Duration::from_secs(args.cache_max_age),
+                args.cache_compression_level,
+            );
+            Some(Arc::new(cache))
+        }
+        (None, None) => None,
+    };
+
+    let trim_config = TrimConfig {
+        policy: args.trim_policy,
+        target: args.trim_target,
+        gc_target: args.gc_trim_target,
+        every: args.trim_every.max(1),
+        idle_after: Duration::from_secs(args.trim_idle_secs),
+        memory_pause_threshold: args.memory_pause_threshold,
+        idle_shutdown: args.idle_shutdown,
+    };
+
+    let queue = QueueConfig {
+        capacity: args.queue_capacity as usize,
+        max_queue_wait: args.max_queue_wait,
+    };
+
+    let watchdog = WatchdogConfig {
+        hang_timeout: args.hang_timeout,
+        convert_timeout: args.convert_timeout,
+    };
+
+    let startup = async {
+        if args.backend == Backend::Stub {
+            warn!("using the stub backend, conversions produce a placeholder document");
+
+            let warm_standby = args.warm_standby;
+            let create_backends = (0..args.workers)
+                .map(|_| standby_if(warm_standby, |_| Ok(StubBackend)))
+                .collect();
+
+            return create_office_pool(
+                create_backends,
+                trim_config,
+                queue,
+                watchdog,
+                args.recover_after_failures,
+            )
+            .await;
+        }
+
+        // Check a path was provided
+        let office_path = office_path
+            .clone()
+            .context("no office install path provided, cannot start server")?;
+
+        debug!("using libreoffice install from: {}", office_path.display());
+
+        check_directories(&work_dir, args.profile_dir.as_deref())?;
+
+        // Prepare the office user profile
+        if let Some(profile_dir) = args.profile_dir.as_deref() {
+            debug!("using office profile in: {}", profile_dir.display());
+            profile::bootstrap(profile_dir, &profile_settings)?;
+        }
+
+        if args.backend == Backend::Soffice {
+            let office_log = args.office_log;
+            let workers = args.workers;
+
+            let create_backends = (0..workers)
+                .map(|worker| {
+                    let office_path = office_path.clone();
+                    let work_dir = work_dir.clone();
+
+                    // Each worker runs its processes with its own profile
+                    let profile_dir = match workers {
+                        1 => args.profile_dir.clone(),
+                        _ => Some(work_dir.join(format!("lo_native_soffice_profile_{worker}"))),
+                    };
+
+                    standby_if(args.warm_standby, move |_| {
+                        SofficeBackend::new(
+                            &office_path,
+                            &work_dir,
+                            profile_dir.as_deref(),
+                            office_log,
+                        )
+                    })
+                })
+                .collect();
+
+            return create_office_pool(
+                create_backends,
+                trim_config,
+                queue,
+                watchdog,
+                args.recover_after_failures,
+            )
+            .await;
+        }
+
+        // Create office access and get office details
+        let work_dir = work_dir.clone();
+        let trim_target = trim_config.target;
+        let office_log = args.office_log;
+
+        create_office_runner(
+            move |activity| {
+                LibreOfficeBackend::new(
+                    &office_path,
+                    &work_dir,
+                    memory_dir.clone(),
+                    trim_target,
+                    office_log,
+                    activity,
+                )
+            },
+            trim_config,
+            queue,
+            watchdog,
+            args.recover_after_failures,
+        )
+        .await
+    };
+
+    let (office_details, office_handle) = match startup.await {
+        Ok(value) => value,
+        // Keep serving in degraded mode so the failure can be diagnosed
+        Err(cause) if args.degraded_mode => {
+            error!(?cause, "failed to start office, serving in degraded mode");
+
+            let failure = server::degraded::StartupFailure::new(&cause, office_path.as_deref());
+            return serve(&server_address, server::degraded::router(failure)).await;
+        }
+        Err(cause) => return Err(cause),
+    };
+
+    let office_details: SharedDetails = Arc::new(ArcSwap::from_pointee(office_details));
+
+    // Office is started by the first conversion when idle shutdown is enabled,
+    // the details are reported once it has started
+    if args.idle_shutdown.is_some() {
+        let office_handle = office_handle.clone();
+        let office_details = office_details.clone();
+
+        tokio::spawn(async move {
+            office_handle.wait_warm().await;
+
+            if let Err(cause) = office_handle.refresh_details(&office_details).await {
+                warn!(%cause, "failed to refresh office details");
+            }
+        });
+    }
+
+    // Periodically refresh the office details to pick up office upgrades
+    if let Some(interval) = args.details_refresh_interval {
+        let office_handle = office_handle.clone();
+        let office_details = office_details.clone();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                if let Err(cause) = office_handle.refresh_details(&office_details).await {
+                    warn!(%cause, "failed to refresh office details");
+                }
+            }
+        });
+    }
+
+    if !args.maintenance_window.is_empty() {
+        let maintenance = Maintenance {
+            office: office_handle.clone(),
+            result_cache: result_cache.clone(),
+            profile_dir: args.profile_dir.clone(),
+            profile_settings,
+        };
+
+        tokio::spawn(maintenance.schedule(args.maintenance_window));
+    }
+
+    if !args.throttle_window.is_empty() {
+        let defaults = QueueLimits {
+            max_queue_wait: args.max_queue_wait,
+            batch_max_skips: Some(priority::DEFAULT_MAX_SKIPS),
+        };
+
+        tokio::spawn(throttle::schedule(
+            office_handle.clone(),
+            args.throttle_window,
+            defaults,
+        ));
+    }
+
+    if let Some(webhook) = args.alert_webhook {
+        let alerts = Alerts {
+            office: office_handle.clone(),
+            webhook,
+            interval: args.alert_interval,
+            dirs: alert_dirs,
+        };
+
+        tokio::spawn(alerts.monitor());
+    }
+
+    if let (Some(output_dir), Some(max_age)) = (args.output_dir, args.output_retention) {
+        let retention = OutputRetention {
+            output_dir,
+            max_age,
+        };
+
+        tokio::spawn(retention.schedule());
+    }
+
+    if let Some(quarantine) = quarantine {
+        tokio::spawn(async move { quarantine.schedule().await });
+    }
+
+    if let Some(jobs) = jobs {
+        tokio::spawn(async move { jobs.schedule().await });
+    }
+
+    #[cfg(unix)]
+    {
+        let office_handle = office_handle.clone();
+        tokio::spawn(async move {
+            if let Err(cause) = toggle_on_signals(office_handle, log_toggle).await {
+                warn!(%cause, "failed to listen for diagnostics signals");
+            }
+        });
+    }
+
+    let app = server::router(office_handle, office_details, server_config, result_cache);
+
+    serve(&server_address, app).await
+}
+
+/// Switches debug logging on SIGUSR1 and the logging of office callback
+/// payloads on SIGUSR2
+#[cfg(unix)]
+async fn toggle_on_signals(
+    office: OfficeHandle,
+    log_toggle: Option<LogToggle>,
+) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut usr1 = signal(SignalKind::user_defined1())?;
+    let mut usr2 = signal(SignalKind::user_defined2())?;
+
+    loop {
+        tokio::select! {
+            _ = usr1.recv() => match log_toggle.as_ref() {
+                Some(toggle) => match toggle.toggle() {
+                    Ok(debug_logging) => info!(debug_logging, "debug logging changed"),
+                    Err(cause) => warn!(%cause, "failed to change debug logging"),
+                },
+                None => warn!("debug logging cannot be toggled"),
+            },
+            _ = usr2.recv() => {
+                let activity = office.activity();
+                let dump_callbacks = !activity.dumps_callbacks();
+                activity.set_dump_callbacks(dump_callbacks);
+                info!(dump_callbacks, "office callback dumping changed");
+            }
+        }
+    }
+}
+
+/// Serves the `app` on the provided `server_address`
+async fn serve(server_address: &str, app: Router) -> anyhow::Result<()> {
+    // Create a TCP listener
+    let listener = tokio::net::TcpListener::bind(server_address)
+        .await
+        .context("failed to bind http server")?;
+
+    debug!("server started on: {server_address}");
+
+    // Serve the app from the listener
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .context("failed to serve")?;
+
+    Ok(())
+}
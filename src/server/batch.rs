@@ -0,0 +1,301 @@
+use super::{
+    download,
+    routes::{content_sha256, OutputNaming, CONTENT_SHA256},
+};
+use crate::{
+    config::ServerConfig,
+    convert::{self, ConvertWarning, ConvertedDocument},
+    error::DynHttpError,
+    filename,
+    tempfiles::random_id,
+};
+use anyhow::Context;
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, Response},
+};
+use serde::Serialize;
+use std::{collections::HashSet, io::Write, time::Duration};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+/// Outcome of converting a single file from a batch
+pub struct BatchEntry {
+    /// File name the file was uploaded with
+    pub file_name: Option<String>,
+    /// Result of converting the file
+    pub result: Result<ConvertedDocument, DynHttpError>,
+    /// Time taken to convert the file (Including waiting for the runner)
+    pub duration: Duration,
+}
+
+/// Name of the manifest within batch responses
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// Manifest describing the outcome of each file in a batch, allows callers
+/// to reconcile partial failures
+#[derive(Serialize)]
+struct Manifest {
+    /// Number of files that were converted
+    succeeded: usize,
+    /// Number of files that failed to convert
+    failed: usize,
+    /// Outcome of each file in upload order
+    files: Vec<ManifestEntry>,
+}
+
+/// Outcome of a single file in the [Manifest]
+#[derive(Serialize)]
+struct ManifestEntry {
+    /// File name the file was uploaded with
+    input: Option<String>,
+    /// Whether the file was converted
+    outcome: ManifestOutcome,
+    /// Error code when the file failed to convert
+    error_code: Option<&'static str>,
+    /// Error reason when the file failed to convert
+    error: Option<String>,
+    /// Name of the output within the response
+    output: String,
+    /// SHA-256 hex digest of the converted file
+    sha256: Option<String>,
+    /// Number of pages in the converted PDF
+    page_count: Option<usize>,
+    /// Non-fatal issues observed while converting the file
+    warnings: Vec<ConvertWarning>,
+    /// Time taken to convert the file in milliseconds
+    duration_ms: u128,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ManifestOutcome {
+    Success,
+    Failed,
+}
+
+impl Manifest {
+    /// Creates the manifest for the batch `entries` using the output `names`
+    fn new(entries: &[BatchEntry], names: &[String]) -> Self {
+        let files: Vec<ManifestEntry> = entries
+            .iter()
+            .zip(names)
+            .map(|(entry, name)| {
+                let (outcome, error_code, error, page_count, warnings) = match &entry.result {
+                    Ok(converted) => (
+                        ManifestOutcome::Success,
+                        None,
+                        None,
+                        page_count(converted),
+                        converted.warnings.clone(),
+                    ),
+                    Err(err) => {
+                        let raw = err.to_raw();
+                        (
+                            ManifestOutcome::Failed,
+                            raw.code,
+                            Some(raw.reason),
+                            None,
+                            Vec::new(),
+                        )
+                    }
+                };
+
+                ManifestEntry {
+                    input: entry.file_name.clone(),
+                    outcome,
+                    error_code,
+                    error,
+                    output: name.clone(),
+                    sha256: entry
+                        .result
+                        .as_ref()
+                        .ok()
+                        .map(|converted| content_sha256(&converted.bytes)),
+                    page_count,
+                    warnings,
+                    duration_ms: entry.duration.as_millis(),
+                }
+            })
+            .collect();
+
+        let failed = files
+            .iter()
+            .filter(|file| matches!(file.outcome, ManifestOutcome::Failed))
+            .count();
+
+        Self {
+            succeeded: files.len() - failed,
+            failed,
+            files,
+        }
+    }
+
+    fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        serde_json::to_vec_pretty(self).context("failed to serialize manifest")
+    }
+}
+
+/// Counts the pages of a converted PDF
+fn page_count(converted: &ConvertedDocument) -> Option<usize> {
+    if converted.content_type != "application/pdf" {
+        return None;
+    }
+
+    Some(convert::pdf_page_count(&converted.bytes))
+}
+
+/// Checks if the "Accept" header of a request prefers a multipart/mixed
+/// response over a zip archive
+pub fn accepts_multipart_mixed(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| {
+            let media_type = value.split(';').next().unwrap_or_default().trim();
+            media_type.eq_ignore_ascii_case("multipart/mixed")
+        })
+}
+
+/// Creates unique output names for the batch `entries` based on the uploaded
+/// file names or the output digests for [OutputNaming::Sha256], failed
+/// entries get a ".error.json" name
+fn output_names(entries: &[BatchEntry], naming: OutputNaming) -> Vec<String> {
+    let mut used = HashSet::new();
+
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let stem = match (&entry.result, naming) {
+                (Ok(converted), OutputNaming::Sha256) => Some(content_sha256(&converted.bytes)),
+                _ => entry.file_name.as_deref().and_then(filename::stem),
+            }
+            .unwrap_or_else(|| format!("file-{}", index + 1));
+
+            let extension = match &entry.result {
+                Ok(converted) => convert::output_extension(converted.content_type),
+                Err(_) => "error.json",
+            };
+
+            let mut name = format!("{stem}.{extension}");
+            let mut counter = 1;
+
+            // Multiple uploads can share the same name
+            while !used.insert(name.clone()) {
+                counter += 1;
+                name = format!("{stem}-{counter}.{extension}");
+            }
+
+            name
+        })
+        .collect()
+}
+
+/// Serializes the error body for a failed conversion
+fn error_body(err: &DynHttpError) -> anyhow::Result<Vec<u8>> {
+    serde_json::to_vec(&err.to_raw()).context("failed to serialize error")
+}
+
+/// Creates a zip archive response containing the converted files and the
+/// manifest, files that failed to convert are included as JSON error entries
+pub async fn zip_response(
+    config: &ServerConfig,
+    entries: Vec<BatchEntry>,
+    naming: OutputNaming,
+) -> anyhow::Result<Response<Body>> {
+    let names = output_names(&entries, naming);
+    let manifest = Manifest::new(&entries, &names).to_bytes()?;
+    let mut archive = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+
+    archive
+        .start_file(MANIFEST_NAME, SimpleFileOptions::default())
+        .context("failed to create zip entry")?;
+    archive
+        .write_all(&manifest)
+        .context("failed to write zip entry")?;
+
+    for (entry, name) in entries.into_iter().zip(names) {
+        let bytes = match &entry.result {
+            Ok(converted) => converted.bytes.to_vec(),
+            Err(err) => error_body(err)?,
+        };
+
+        archive
+            .start_file(name, SimpleFileOptions::default())
+            .context("failed to create zip entry")?;
+        archive
+            .write_all(&bytes)
+            .context("failed to write zip entry")?;
+    }
+
+    let archive = archive.finish().context("failed to finish zip")?;
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/zip")
+        .body(download::bytes_body(config, archive.into_inner().into()).await?)
+        .context("failed to create response")
+}
+
+/// Creates a multipart/mixed response with a part for each converted file
+/// followed by the manifest, files that failed to convert are included as
+/// JSON error parts with the error status in the "X-Status" part header
+pub async fn multipart_response(
+    config: &ServerConfig,
+    entries: Vec<BatchEntry>,
+    naming: OutputNaming,
+) -> anyhow::Result<Response<Body>> {
+    let names = output_names(&entries, naming);
+    let manifest = Manifest::new(&entries, &names).to_bytes()?;
+    let boundary = format!("lo_native_{}", random_id());
+    let mut body = Vec::new();
+
+    for (entry, name) in entries.into_iter().zip(names) {
+        write!(body, "--{boundary}\r\n")?;
+        write!(
+            body,
+            "Content-Disposition: attachment; filename=\"{name}\"\r\n"
+        )?;
+
+        let bytes = match &entry.result {
+            Ok(converted) => {
+                write!(body, "Content-Type: {}\r\n", converted.content_type)?;
+                write!(
+                    body,
+                    "{CONTENT_SHA256}: {}\r\n",
+                    content_sha256(&converted.bytes)
+                )?;
+                converted.bytes.clone()
+            }
+            Err(err) => {
+                write!(body, "Content-Type: application/json\r\n")?;
+                write!(body, "X-Status: {}\r\n", err.status().as_u16())?;
+                Bytes::from(error_body(err)?)
+            }
+        };
+
+        write!(body, "\r\n")?;
+        body.extend_from_slice(&bytes);
+        write!(body, "\r\n")?;
+    }
+
+    write!(body, "--{boundary}\r\n")?;
+    write!(
+        body,
+        "Content-Disposition: attachment; filename=\"{MANIFEST_NAME}\"\r\n"
+    )?;
+    write!(body, "Content-Type: application/json\r\n\r\n")?;
+    body.extend_from_slice(&manifest);
+    write!(body, "\r\n")?;
+
+    write!(body, "--{boundary}--\r\n")?;
+
+    Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/mixed; boundary={boundary}"),
+        )
+        .body(download::bytes_body(config, body.into()).await?)
+        .context("failed to create response")
+}
@@ -0,0 +1,90 @@
+use super::routes::server_version;
+use crate::{
+    error::{DynHttpError, HttpError},
+    version,
+};
+use axum::{http::StatusCode, routing::get, Extension, Json, Router};
+use serde::Serialize;
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+/// Details of why office failed to start
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupFailure {
+    /// Message of the startup error
+    error: String,
+    /// Chain of causes for the startup error
+    causes: Vec<String>,
+    /// Office install path used (When one was found)
+    office_path: Option<String>,
+    /// Version of the server
+    server_version: &'static str,
+    /// When office failed to start (Seconds since the unix epoch)
+    failed_at: u64,
+}
+
+impl StartupFailure {
+    /// Creates the startup failure details from the startup error `cause`
+    pub fn new(cause: &anyhow::Error, office_path: Option<&Path>) -> Self {
+        let failed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|value| value.as_secs())
+            .unwrap_or_default();
+
+        Self {
+            error: cause.to_string(),
+            causes: cause
+                .chain()
+                .skip(1)
+                .map(|cause| cause.to_string())
+                .collect(),
+            office_path: office_path.map(|path| path.display().to_string()),
+            server_version: version::VERSION,
+            failed_at,
+        }
+    }
+}
+
+/// Error responded with for requests while in degraded mode
+#[derive(Debug, Error)]
+#[error("office failed to start: {0}")]
+struct DegradedError(String);
+
+impl HttpError for DegradedError {
+    fn log(&self) {}
+
+    fn status(&self) -> StatusCode {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("degraded")
+    }
+}
+
+/// Creates the router used in degraded mode when office fails to start,
+/// `/diagnostics` reports the startup failure details and `/version` the
+/// server build while every other request fails with the startup error
+pub fn router(failure: StartupFailure) -> Router {
+    Router::new()
+        .route("/diagnostics", get(diagnostics))
+        .route("/version", get(server_version))
+        .fallback(degraded)
+        .layer(Extension(Arc::new(failure)))
+}
+
+/// GET /diagnostics
+///
+/// Reports the details of the startup failure
+async fn diagnostics(Extension(failure): Extension<Arc<StartupFailure>>) -> Json<StartupFailure> {
+    Json(failure.as_ref().clone())
+}
+
+/// Fallback for all other requests (Including `/status` and `/healthz`)
+async fn degraded(Extension(failure): Extension<Arc<StartupFailure>>) -> DynHttpError {
+    DegradedError(failure.error.clone()).into()
+}
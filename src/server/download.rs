@@ -0,0 +1,186 @@
+use super::upload::TempFileReader;
+use crate::{
+    config::ServerConfig,
+    storage::ObjectStream,
+    tempfiles::{random_id, TempFile},
+};
+use anyhow::Context;
+use axum::body::Body;
+use bytes::Bytes;
+use futures_util::stream;
+use http_body::{Frame, SizeHint};
+use parking_lot::Mutex;
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+use tokio::{io::AsyncRead, time::Instant};
+use tokio_util::io::ReaderStream;
+use tracing::{warn, Instrument, Span};
+
+/// State of a download shared with its idle watchdog
+struct DownloadState {
+    /// Contents not yet sent, [None] once sent or expired
+    stream: Option<ObjectStream>,
+    /// Last time the client took a chunk of the download
+    progress: Instant,
+    /// Whether the download was expired by the watchdog
+    expired: bool,
+}
+
+/// Response body of a converted file, when an idle timeout is configured a
+/// client that stops reading the download has its contents released (Removing
+/// any file it was sent from) and the connection closed
+struct DownloadBody {
+    state: Arc<Mutex<DownloadState>>,
+    /// Bytes not yet sent
+    remaining: u64,
+}
+
+impl http_body::Body for DownloadBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        let state = &mut *this.state.lock();
+
+        let Some(stream) = state.stream.as_mut() else {
+            return Poll::Ready(match state.expired {
+                true => Some(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "download expired while idle",
+                ))),
+                false => None,
+            });
+        };
+
+        match stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                state.progress = Instant::now();
+                this.remaining = this.remaining.saturating_sub(chunk.len() as u64);
+                Poll::Ready(Some(Ok(Frame::data(chunk))))
+            }
+            Poll::Ready(Some(Err(err))) => {
+                state.stream = None;
+                Poll::Ready(Some(Err(err)))
+            }
+            Poll::Ready(None) => {
+                state.stream = None;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+/// Creates the body of a download of the `size` bytes of the `stream`
+pub(crate) fn stream_body(config: &ServerConfig, stream: ObjectStream, size: u64) -> Body {
+    let state = Arc::new(Mutex::new(DownloadState {
+        stream: Some(stream),
+        progress: Instant::now(),
+        expired: false,
+    }));
+
+    if let Some(idle_timeout) = config.download_idle_timeout {
+        tokio::spawn(expire_idle(Arc::downgrade(&state), idle_timeout).instrument(Span::current()));
+    }
+
+    Body::new(DownloadBody {
+        state,
+        remaining: size,
+    })
+}
+
+/// Releases the contents of the download once the client hasn't taken a
+/// chunk for the `idle_timeout`, stops once the download is sent
+async fn expire_idle(state: Weak<Mutex<DownloadState>>, idle_timeout: Duration) {
+    loop {
+        let deadline = {
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            let state = state.lock();
+            if state.stream.is_none() {
+                return;
+            }
+
+            state.progress + idle_timeout
+        };
+
+        tokio::time::sleep_until(deadline).await;
+
+        let Some(state) = state.upgrade() else {
+            return;
+        };
+        let mut state = state.lock();
+        if state.progress.elapsed() < idle_timeout {
+            continue;
+        }
+
+        if state.stream.take().is_some() {
+            state.expired = true;
+            warn!(?idle_timeout, "expiring idle download");
+        }
+
+        return;
+    }
+}
+
+/// Creates the body of a download of the `bytes`. Bytes of at least the
+/// configured spill threshold are written to the work directory and sent from
+/// disk so slow clients don't hold them in memory
+pub(crate) async fn bytes_body(config: &ServerConfig, bytes: Bytes) -> anyhow::Result<Body> {
+    let size = bytes.len() as u64;
+
+    match config.download_spill_threshold {
+        Some(threshold) if bytes.len() >= threshold => {
+            let file = TempFile {
+                path: config
+                    .work_dir
+                    .join(format!("lo_native_download_{}", random_id())),
+            };
+
+            tokio::fs::write(&file.path, &bytes)
+                .await
+                .context("failed to write download")?;
+            drop(bytes);
+
+            Ok(reader_body(config, TempFileReader::open(file).await?, size))
+        }
+        _ => Ok(stream_body(
+            config,
+            Box::pin(stream::iter([Ok(bytes)])),
+            size,
+        )),
+    }
+}
+
+/// Creates the body of a download of the converted `file`, the file is
+/// removed once the download is sent or expires
+pub(crate) async fn file_body(config: &ServerConfig, file: TempFile) -> anyhow::Result<Body> {
+    let size = tokio::fs::metadata(&file.path)
+        .await
+        .context("failed to read converted file")?
+        .len();
+
+    Ok(reader_body(config, TempFileReader::open(file).await?, size))
+}
+
+/// Creates the body of a download of the `size` bytes read from the `reader`
+fn reader_body<R>(config: &ServerConfig, reader: R, size: u64) -> Body
+where
+    R: AsyncRead + Send + 'static,
+{
+    stream_body(config, Box::pin(ReaderStream::new(reader)), size)
+}
@@ -0,0 +1,109 @@
+use crate::{
+    convert::{ConvertWarning, DocumentStats},
+    error::DynHttpError,
+};
+use serde::Serialize;
+
+/// Report of a dry run, describing whether each file would be converted
+/// without performing the conversion
+#[derive(Serialize)]
+pub struct DryRunReport {
+    /// Number of files that would be converted
+    valid: usize,
+    /// Number of files that would fail to convert
+    invalid: usize,
+    /// Outcome of each file in upload order
+    files: Vec<DryRunEntry>,
+}
+
+/// Outcome of a single file in the [DryRunReport]
+#[derive(Serialize)]
+pub struct DryRunEntry {
+    /// File name the file was uploaded with
+    input: Option<String>,
+    /// Whether the file would be converted
+    outcome: DryRunOutcome,
+    /// Error code when the file would fail to convert
+    error_code: Option<&'static str>,
+    /// Error reason when the file would fail to convert
+    error: Option<String>,
+    /// Format detected from the content of the file
+    detected_format: Option<&'static str>,
+    /// Type of document (text, spreadsheet, presentation, drawing, other)
+    document_type: Option<&'static str>,
+    /// Estimated number of pages in the converted PDF
+    estimated_page_count: Option<u64>,
+    /// Non-fatal issues that would be reported when converting
+    warnings: Vec<ConvertWarning>,
+}
+
+#[derive(Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum DryRunOutcome {
+    Valid,
+    Invalid,
+}
+
+impl DryRunEntry {
+    /// Creates the entry for a file from the `result` of loading it
+    pub fn new(
+        input: Option<String>,
+        detected_format: Option<&'static str>,
+        result: Result<(DocumentStats, Vec<ConvertWarning>), DynHttpError>,
+    ) -> Self {
+        match result {
+            Ok((stats, warnings)) => Self {
+                input,
+                outcome: DryRunOutcome::Valid,
+                error_code: None,
+                error: None,
+                detected_format,
+                document_type: Some(stats.document_type),
+                estimated_page_count: estimate_page_count(&stats),
+                warnings,
+            },
+            Err(err) => {
+                err.log();
+
+                let raw = err.to_raw();
+                Self {
+                    input,
+                    outcome: DryRunOutcome::Invalid,
+                    error_code: raw.code,
+                    error: Some(raw.reason),
+                    detected_format,
+                    document_type: None,
+                    estimated_page_count: None,
+                    warnings: Vec::new(),
+                }
+            }
+        }
+    }
+}
+
+impl DryRunReport {
+    pub fn new(files: Vec<DryRunEntry>) -> Self {
+        let invalid = files
+            .iter()
+            .filter(|file| file.outcome == DryRunOutcome::Invalid)
+            .count();
+
+        Self {
+            valid: files.len() - invalid,
+            invalid,
+            files,
+        }
+    }
+}
+
+/// Estimates the number of pages the document would convert to from its
+/// statistics, spreadsheets are estimated as a page per sheet
+fn estimate_page_count(stats: &DocumentStats) -> Option<u64> {
+    let statistics = &stats.statistics;
+
+    match stats.document_type {
+        "spreadsheet" => statistics.table_count,
+        "presentation" | "drawing" => statistics.slide_count.or(statistics.page_count),
+        _ => statistics.page_count,
+    }
+}
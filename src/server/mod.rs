@@ -0,0 +1,9 @@
+mod batch;
+pub mod degraded;
+mod download;
+mod dry_run;
+pub mod routes;
+mod store;
+mod upload;
+
+pub use routes::{endpoints, router};
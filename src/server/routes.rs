@@ -0,0 +1,3497 @@
+use super::{
+    batch, download,
+    dry_run::{DryRunEntry, DryRunReport},
+    store::{self, StoredBatch, StoredBatchEntry},
+    upload::{self, Upload, UploadContents},
+};
+use crate::{
+    attestation::{self, AttestationError},
+    auth::{Admission, SharedAdmission},
+    cache::{CacheOutcome, CacheStats, CachedResult, ResultCache},
+    config::{parse_duration, ServerConfig},
+    convert::{
+        self, ConvertError, ConvertOptions, ConvertWarning, ConvertedDocument, DocumentPassword,
+        DocumentStats,
+    },
+    csv::{CsvOptions, CsvRequest},
+    detect::{self, FormatMismatch, MismatchPolicy},
+    error::{DynHttpError, HttpError},
+    filename,
+    formats::{self, TargetFormat},
+    jobs::{JobState, JobStatus, JobStore, QueueSnapshot},
+    memory, metrics,
+    pdf_export::{PageFormat, PdfExportOptions},
+    pipeline::{self, PipelineOptions},
+    presign::PresignError,
+    priority, proxy,
+    quarantine::{FailedConversion, Quarantine, QuarantineEntry},
+    redact::RedactOptions,
+    runner::{
+        self, ConvertResult, OfficeHandle, OfficeState, RunnerPhase, SharedDetails, TrimEffect,
+        WorkTotals, WorkerStatus,
+    },
+    settings::RenderSettings,
+    signing::ResultSigner,
+    tempfiles::{self, random_id, TempFile, TempUsage},
+    throttle::{QueueLimits, ThrottleWindow},
+    version,
+    workbook::SpreadsheetExport,
+};
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, DefaultBodyLimit, Path, Query, Request,
+    },
+    http::{header, HeaderMap, HeaderValue, Method, Response, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    routing::{delete, get, post, MethodRouter},
+    Extension, Json, Router,
+};
+use axum_typed_multipart::{FieldData, TryFromField, TryFromMultipart, TypedMultipart};
+use bytes::Bytes;
+use clap::ValueEnum;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    convert::Infallible,
+    io::Write,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{sync::watch, time::Instant};
+use tracing::{error, field, info, info_span, warn, Instrument};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+/// Request to convert a file
+#[derive(TryFromMultipart)]
+struct UploadAssetRequest {
+    /// The file to convert, multiple files can be provided to convert
+    /// a batch of files. Files are streamed to disk as they are received
+    #[form_data(limit = "unlimited")]
+    file: Vec<FieldData<Upload>>,
+
+    /// Export each sheet of a spreadsheet as a separate PDF, responding
+    /// with a zip archive of the PDFs
+    split_sheets: Option<bool>,
+
+    /// Name of a macro (Library.Module.Macro) to run before exporting,
+    /// only available to admins when macros are enabled
+    run_macro: Option<String>,
+
+    /// Render a thumbnail of the first page along with the PDF, responding
+    /// with a zip archive of both
+    with_thumbnail: Option<bool>,
+
+    /// Extract the text of each page along with the PDF, responding with
+    /// a zip archive of both
+    with_text: Option<bool>,
+
+    /// JSON object of rendering settings to adjust for this conversion
+    /// (i.e {"language": "ja-JP", "export_notes": true})
+    settings: Option<String>,
+
+    /// JSON object of options for the exported PDF
+    /// (i.e {"pdfa": "2b", "page_range": "1-3"})
+    export_options: Option<String>,
+
+    /// Format to convert to by file extension (i.e "pdf", "docx", "odt" or
+    /// "csv"), spreadsheets can also be exported as CSV or another spreadsheet
+    /// format, defaults to "pdf"
+    format: Option<String>,
+
+    /// Comma separated numbers of the sheets to export as CSV (Starting at 1)
+    /// or "all", multiple sheets respond with a zip archive of the sheets
+    sheets: Option<String>,
+
+    /// Character separating CSV fields, defaults to "," for "csv" and a tab
+    /// for "tsv"
+    delimiter: Option<String>,
+
+    /// Quoting of CSV text cells ("minimal" or "all")
+    quote: Option<String>,
+
+    /// Character encoding of exported CSV files ("utf-8", "utf-16",
+    /// "windows-1252" or "iso-8859-1")
+    encoding: Option<String>,
+
+    /// Replace formulas with their values and remove macros when exporting
+    /// to a spreadsheet format
+    data_only: Option<bool>,
+
+    /// Remove hidden sheets when exporting to a spreadsheet format
+    remove_hidden_sheets: Option<bool>,
+
+    /// Password to open encrypted files with
+    password: Option<String>,
+
+    /// Embed the standard PDF fonts in the output, defaults to the server
+    /// "--embed-standard-fonts" option
+    embed_standard_fonts: Option<bool>,
+
+    /// Validate the files and options without converting, responding with
+    /// a report describing each file
+    dry_run: Option<bool>,
+
+    /// Whether browsers should display the converted file inline or
+    /// download it, omitted responses don't include a "Content-Disposition"
+    disposition: Option<Disposition>,
+
+    /// Write the converted file to the server output directory responding
+    /// with its path instead of the file
+    store: Option<bool>,
+
+    /// How outputs are named within batch responses, the output directory
+    /// and the "Content-Disposition" header, defaults to "name"
+    naming: Option<OutputNaming>,
+
+    /// SHA-256 hex digests of the uploaded files, in the same order as the
+    /// files (Alternative to the "X-Content-Sha256" header)
+    sha256: Vec<String>,
+
+    /// Base64 encoded detached Ed25519 signatures of the uploaded files, in
+    /// the same order as the files
+    signature: Vec<String>,
+}
+
+/// Errors from invalid convert requests
+#[derive(Debug, Error)]
+enum ConvertRequestError {
+    /// Macros were requested but are not enabled on the server
+    #[error("running macros is disabled on this server")]
+    MacrosDisabled,
+
+    /// Macros were requested without admin access
+    #[error("running macros requires a valid admin token")]
+    MacrosForbidden,
+
+    /// Provided macro name was not valid
+    #[error("invalid macro name, expected Library.Module.Macro")]
+    InvalidMacroName,
+
+    /// Request did not include a file to convert
+    #[error("missing file to convert")]
+    MissingFile,
+
+    /// Bundled artifacts were requested along with splitting sheets
+    #[error("with_thumbnail and with_text cannot be combined with split_sheets")]
+    BundleWithSplitSheets,
+
+    /// PDF only options were requested along with exporting another format
+    #[error("split_sheets, with_thumbnail and with_text are only used when converting to pdf")]
+    PdfOptionsWithExport,
+
+    /// Storing the output was requested without an output directory
+    #[error("storing outputs is not enabled on this server")]
+    StoreDisabled,
+
+    /// Provided document password was empty
+    #[error("password must not be empty")]
+    EmptyPassword,
+}
+
+impl HttpError for ConvertRequestError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ConvertRequestError::MacrosDisabled | ConvertRequestError::MacrosForbidden => {
+                StatusCode::FORBIDDEN
+            }
+            ConvertRequestError::InvalidMacroName
+            | ConvertRequestError::MissingFile
+            | ConvertRequestError::BundleWithSplitSheets
+            | ConvertRequestError::PdfOptionsWithExport
+            | ConvertRequestError::StoreDisabled
+            | ConvertRequestError::EmptyPassword => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            ConvertRequestError::MacrosDisabled => "macros_disabled",
+            ConvertRequestError::MacrosForbidden => "macros_forbidden",
+            ConvertRequestError::InvalidMacroName => "invalid_macro_name",
+            ConvertRequestError::MissingFile => "missing_file",
+            ConvertRequestError::BundleWithSplitSheets
+            | ConvertRequestError::PdfOptionsWithExport => "invalid_options",
+            ConvertRequestError::EmptyPassword => "invalid_password",
+            ConvertRequestError::StoreDisabled => "store_disabled",
+        })
+    }
+}
+
+/// Errors from uploads that failed checksum validation
+#[derive(Debug, Error)]
+enum UploadChecksumError {
+    /// Provided checksum was not a SHA-256 hex digest
+    #[error("invalid checksum, expected a SHA-256 hex digest")]
+    Invalid,
+
+    /// Number of checksums did not match the number of files
+    #[error("expected a sha256 field for each uploaded file")]
+    Count,
+
+    /// The header checksum was used for a batch of files
+    #[error("the X-Content-Sha256 header is only used for single file uploads, provide a sha256 field for each file instead")]
+    BatchHeader,
+
+    /// Uploaded file did not match its checksum
+    #[error("uploaded file does not match its checksum (expected {expected}, received {actual}), the upload may have been truncated")]
+    Mismatch { expected: String, actual: String },
+}
+
+impl HttpError for UploadChecksumError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            UploadChecksumError::Invalid
+            | UploadChecksumError::Count
+            | UploadChecksumError::BatchHeader => "invalid_checksum",
+            UploadChecksumError::Mismatch { .. } => "checksum_mismatch",
+        })
+    }
+}
+
+/// Verifies the uploaded `files` against their client computed checksums,
+/// provided as `checksums` fields (one per file) or for single files the
+/// "X-Content-Sha256" header. Files without a checksum are not verified
+fn verify_uploads(
+    headers: &HeaderMap,
+    checksums: &[String],
+    files: &[&[u8]],
+) -> Result<(), UploadChecksumError> {
+    verify_checksums(headers, checksums, files.len(), |index| {
+        content_sha256(files[index])
+    })
+}
+
+/// Verifies `count` uploaded files against their client computed checksums
+/// like [verify_uploads], `digest` provides the SHA-256 hex digest of the
+/// file at an index for files that were hashed as they were received
+fn verify_checksums(
+    headers: &HeaderMap,
+    checksums: &[String],
+    count: usize,
+    digest: impl Fn(usize) -> String,
+) -> Result<(), UploadChecksumError> {
+    let header = headers
+        .get(CONTENT_SHA256)
+        .map(|value| value.to_str().map_err(|_| UploadChecksumError::Invalid))
+        .transpose()?;
+
+    let checksums: Vec<&str> = match (checksums.is_empty(), header) {
+        (true, None) => return Ok(()),
+        (true, Some(_)) if count > 1 => return Err(UploadChecksumError::BatchHeader),
+        (true, Some(header)) => vec![header],
+        (false, _) => checksums.iter().map(String::as_str).collect(),
+    };
+
+    if checksums.len() != count {
+        return Err(UploadChecksumError::Count);
+    }
+
+    for (index, expected) in checksums.into_iter().enumerate() {
+        let expected = expected.trim();
+        if expected.len() != 64 || !expected.chars().all(|char| char.is_ascii_hexdigit()) {
+            return Err(UploadChecksumError::Invalid);
+        }
+
+        let actual = digest(index);
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(UploadChecksumError::Mismatch {
+                expected: expected.to_ascii_lowercase(),
+                actual,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Verifies the detached `signatures` of the `uploads` (name and contents)
+/// against the configured attestation key
+fn verify_signatures(
+    config: &ServerConfig,
+    signatures: &[String],
+    uploads: &[(Option<&str>, &[u8])],
+) -> Result<(), AttestationError> {
+    attestation::verify_uploads(
+        config.attestation_key.as_ref(),
+        config.require_attestation,
+        signatures,
+        uploads,
+    )
+}
+
+/// Provides the name and contents of an uploaded `file`
+fn upload(file: &FieldData<Bytes>) -> (Option<&str>, &[u8]) {
+    (file.metadata.file_name.as_deref(), file.contents.as_ref())
+}
+
+/// Checks if the single upload of a convert request can be converted from
+/// the file it was streamed to, uploads needing checks of their whole
+/// contents (Format detection, signatures, embedded content, quarantine,
+/// web archives) and dry runs or stored outputs are read into memory
+fn can_stream(
+    config: &ServerConfig,
+    files: &[FieldData<Upload>],
+    signatures: &[String],
+    dry_run: bool,
+    store: bool,
+) -> bool {
+    let [file] = files else {
+        return false;
+    };
+
+    !dry_run
+        && !store
+        && signatures.is_empty()
+        && config.format_mismatch == MismatchPolicy::Ignore
+        && config.quarantine.is_none()
+        && !config.embedded_limits.is_enabled()
+        && !file.contents.may_be_web_archive()
+}
+
+/// Reads the streamed `uploads` into memory
+async fn read_uploads(uploads: Vec<FieldData<Upload>>) -> anyhow::Result<Vec<FieldData<Bytes>>> {
+    let mut files = Vec::with_capacity(uploads.len());
+
+    for upload in uploads {
+        files.push(FieldData {
+            metadata: upload.metadata,
+            contents: upload.contents.read().await?,
+        });
+    }
+
+    Ok(files)
+}
+
+/// Header warning that the content of the upload did not match its extension
+const FORMAT_MISMATCH: &str = "x-format-mismatch";
+
+/// How converted outputs are named
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, TryFromField)]
+#[try_from_field(rename_all = "lowercase")]
+pub(crate) enum OutputNaming {
+    /// Named after the uploaded file (i.e "report.pdf")
+    #[default]
+    Name,
+    /// Named by the SHA-256 hex digest of the output (i.e "9f86d0...a08.pdf"),
+    /// re-running a conversion produces the same name
+    Sha256,
+}
+
+/// How browsers should present a converted file
+#[derive(Debug, Clone, Copy, TryFromField, Deserialize)]
+#[try_from_field(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+enum Disposition {
+    /// Display the file within the page (i.e an iframe or object tag)
+    Inline,
+    /// Download the file
+    Attachment,
+}
+
+impl Disposition {
+    /// Creates the "Content-Disposition" header value, the `file_name` of
+    /// the upload is used to name the output with the `extension`
+    fn header_value(&self, file_name: Option<&str>, extension: &str) -> Option<HeaderValue> {
+        let disposition = match self {
+            Disposition::Inline => "inline",
+            Disposition::Attachment => "attachment",
+        };
+
+        let Some(stem) = file_name.and_then(filename::stem) else {
+            return HeaderValue::from_str(disposition).ok();
+        };
+
+        let name = format!("{stem}.{extension}");
+
+        // Non-ASCII names are provided as an encoded "filename*" (RFC 6266)
+        // alongside an ASCII fallback
+        let value = if name.is_ascii() {
+            format!("{disposition}; filename=\"{name}\"")
+        } else {
+            let fallback: String = name
+                .chars()
+                .map(|char| if char.is_ascii() { char } else { '_' })
+                .collect();
+            let encoded: String = name
+                .bytes()
+                .map(|byte| match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' => {
+                        (byte as char).to_string()
+                    }
+                    byte => format!("%{byte:02X}"),
+                })
+                .collect();
+
+            format!("{disposition}; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+        };
+
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+/// Adds the "Content-Disposition" header for the `disposition` to the
+/// `response`, the output is named after the uploaded `file_name` or the
+/// digest of the output when using [OutputNaming::Sha256]
+fn insert_disposition(
+    response: &mut Response<Body>,
+    disposition: Option<Disposition>,
+    file_name: Option<&str>,
+    naming: OutputNaming,
+) {
+    let Some(disposition) = disposition else {
+        return;
+    };
+
+    let sha256 = response
+        .headers()
+        .get(CONTENT_SHA256)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let file_name = match naming {
+        OutputNaming::Name => file_name,
+        // Responses without a digest (i.e batches) fall back to the upload name
+        OutputNaming::Sha256 => sha256.as_deref().or(file_name),
+    };
+
+    let extension = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(convert::output_extension)
+        .unwrap_or("pdf");
+
+    if let Some(value) = disposition.header_value(file_name, extension) {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_DISPOSITION, value);
+    }
+}
+
+/// Header containing the number of warnings for the conversion
+const WARNINGS_COUNT: &str = "x-warnings-count";
+
+/// Header containing the comma separated codes of the warnings for the conversion
+const WARNINGS: &str = "x-warnings";
+
+/// Adds the "X-Warnings-Count" and "X-Warnings" headers describing the
+/// `warnings` to the `response`, omitted when there are no warnings
+fn insert_warnings(response: &mut Response<Body>, warnings: &[ConvertWarning]) {
+    if warnings.is_empty() {
+        return;
+    }
+
+    let codes: Vec<&str> = warnings
+        .iter()
+        .map(|warning| warning.code.as_ref())
+        .collect();
+    let headers = response.headers_mut();
+
+    headers.insert(WARNINGS_COUNT, HeaderValue::from(warnings.len()));
+
+    if let Ok(value) = HeaderValue::from_str(&codes.join(", ")) {
+        headers.insert(WARNINGS, value);
+    }
+}
+
+/// Checks the content of the uploaded `file` matches its extension,
+/// following the configured mismatch policy. Provides the mismatch to
+/// warn about when the policy is to warn
+fn check_format(
+    config: &ServerConfig,
+    file: &FieldData<Bytes>,
+) -> Result<Option<FormatMismatch>, FormatMismatch> {
+    if config.format_mismatch == MismatchPolicy::Ignore {
+        return Ok(None);
+    }
+
+    let Some(mismatch) =
+        detect::check_extension(file.metadata.file_name.as_deref(), &file.contents)
+    else {
+        return Ok(None);
+    };
+
+    if config.format_mismatch == MismatchPolicy::Reject {
+        return Err(mismatch);
+    }
+
+    warn!(
+        claimed = %mismatch.claimed,
+        detected = mismatch.detected.name(),
+        "upload content does not match its extension"
+    );
+
+    Ok(Some(mismatch))
+}
+
+/// Request to run a conversion pipeline on a file
+#[derive(TryFromMultipart)]
+struct PipelineRequest {
+    /// The file to run the pipeline on
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
+
+    /// Steps of the pipeline separated by "->" (i.e "pdf -> png")
+    pipeline: String,
+
+    /// SHA-256 hex digest of the uploaded file (Alternative to the
+    /// "X-Content-Sha256" header)
+    sha256: Option<String>,
+
+    /// Base64 encoded detached Ed25519 signature of the uploaded file
+    signature: Option<String>,
+
+    /// Password to protect the output of the last step with
+    password: Option<String>,
+}
+
+/// Errors from invalid pipeline requests
+#[derive(Debug, Error)]
+#[error("invalid pipeline, expected steps separated by \"->\" (i.e \"pdf -> png\")")]
+struct InvalidPipelineError;
+
+impl HttpError for InvalidPipelineError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("invalid_pipeline")
+    }
+}
+
+/// Errors from output passwords that cannot be applied
+#[derive(Debug, Error)]
+enum OutputPasswordError {
+    /// Provided password was empty
+    #[error("output password must not be empty")]
+    Empty,
+
+    /// Last step does not export a format that supports passwords
+    #[error("output passwords require the last pipeline step to export one of: {}", pipeline::PASSWORD_FORMATS.join(", "))]
+    UnsupportedFormat,
+}
+
+impl HttpError for OutputPasswordError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("invalid_password")
+    }
+}
+
+/// POST /pipeline
+///
+/// Runs a multi-step conversion pipeline on the provided file within a single
+/// converter slot, responding with a zip archive of every step's artifacts
+/// and a manifest
+async fn run_pipeline(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    headers: HeaderMap,
+    TypedMultipart(PipelineRequest {
+        file,
+        pipeline,
+        sha256,
+        signature,
+        password,
+    }): TypedMultipart<PipelineRequest>,
+) -> Result<Response<Body>, DynHttpError> {
+    let steps = pipeline::parse(&pipeline).ok_or(InvalidPipelineError)?;
+
+    if let Some(password) = password.as_deref() {
+        if password.is_empty() {
+            return Err(OutputPasswordError::Empty.into());
+        }
+
+        if !pipeline::supports_output_password(&steps) {
+            return Err(OutputPasswordError::UnsupportedFormat.into());
+        }
+    }
+
+    verify_uploads(&headers, sha256.as_slice(), &[&file.contents])?;
+    verify_signatures(&config, signature.as_slice(), &[upload(&file)])?;
+    let mismatch = check_format(&config, &file)?;
+
+    // Run the pipeline
+    let input = office.prepare_input(file.contents, &config).await?;
+    let options = PipelineOptions {
+        output_password: password,
+    };
+    let archive = office
+        .run_pipeline(input, steps, options)
+        .await?
+        .map_err(runner_error)?;
+
+    // Build the response
+    let mut response = Response::builder().header(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/zip"),
+    );
+
+    if let Some(mismatch) = mismatch {
+        response = response.header(FORMAT_MISMATCH, mismatch.header_value());
+    }
+
+    let response = response
+        .body(download::bytes_body(&config, archive).await?)
+        .context("failed to create response")?;
+
+    Ok(response)
+}
+
+/// Errors for admin only functionality
+#[derive(Debug, Error)]
+enum AdminError {
+    /// Request did not provide a valid admin token
+    #[error("missing or invalid admin token")]
+    Forbidden,
+
+    /// Result caching is not enabled
+    #[error("result caching is not enabled")]
+    CacheDisabled,
+
+    /// Quarantining failed inputs is not enabled
+    #[error("quarantine is not enabled")]
+    QuarantineDisabled,
+
+    /// Logging of the server can't be changed at runtime
+    #[error("debug logging cannot be toggled")]
+    LogToggleUnavailable,
+}
+
+impl HttpError for AdminError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AdminError::Forbidden => StatusCode::FORBIDDEN,
+            AdminError::CacheDisabled | AdminError::QuarantineDisabled => StatusCode::NOT_FOUND,
+            AdminError::LogToggleUnavailable => StatusCode::CONFLICT,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            AdminError::Forbidden => "forbidden",
+            AdminError::CacheDisabled => "cache_disabled",
+            AdminError::QuarantineDisabled => "quarantine_disabled",
+            AdminError::LogToggleUnavailable => "log_toggle_unavailable",
+        })
+    }
+}
+
+impl ServerConfig {
+    /// Ensures the request `headers` contain the admin token
+    fn require_admin(&self, headers: &HeaderMap) -> Result<(), AdminError> {
+        if self.is_admin(headers) {
+            Ok(())
+        } else {
+            Err(AdminError::Forbidden)
+        }
+    }
+}
+
+/// Checks that a macro name is in the form Library.Module.Macro, preventing
+/// arbitrary macro URLs from being provided
+fn is_valid_macro_name(name: &str) -> bool {
+    let parts: Vec<&str> = name.split('.').collect();
+
+    parts.len() == 3
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || char == '_')
+        })
+}
+
+/// POST /convert
+///
+/// Converts the provided file to PDF format responding with the PDF file,
+/// when multiple files are provided they are converted as a batch
+async fn convert(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(details): Extension<SharedDetails>,
+    Extension(result_cache): Extension<Option<Arc<ResultCache>>>,
+    headers: HeaderMap,
+    TypedMultipart(request): TypedMultipart<UploadAssetRequest>,
+) -> Result<Response<Body>, DynHttpError> {
+    convert_request(
+        office,
+        config,
+        details,
+        result_cache,
+        headers,
+        request,
+        false,
+    )
+    .await
+}
+
+/// POST /convert-batch
+///
+/// Converts the provided files as a batch responding with an archive of the
+/// converted files and a manifest, even when a single file is provided
+async fn convert_batch_request(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(details): Extension<SharedDetails>,
+    Extension(result_cache): Extension<Option<Arc<ResultCache>>>,
+    headers: HeaderMap,
+    TypedMultipart(request): TypedMultipart<UploadAssetRequest>,
+) -> Result<Response<Body>, DynHttpError> {
+    convert_request(
+        office,
+        config,
+        details,
+        result_cache,
+        headers,
+        request,
+        true,
+    )
+    .await
+}
+
+/// Handles a convert `request`, multiple files or `always_batch` requests
+/// are converted as a batch
+async fn convert_request(
+    office: OfficeHandle,
+    config: Arc<ServerConfig>,
+    details: SharedDetails,
+    result_cache: Option<Arc<ResultCache>>,
+    headers: HeaderMap,
+    request: UploadAssetRequest,
+    always_batch: bool,
+) -> Result<Response<Body>, DynHttpError> {
+    let UploadAssetRequest {
+        file: mut files,
+        split_sheets,
+        run_macro,
+        with_thumbnail,
+        with_text,
+        settings,
+        export_options,
+        format,
+        sheets,
+        delimiter,
+        quote,
+        encoding,
+        data_only,
+        remove_hidden_sheets,
+        password,
+        embed_standard_fonts,
+        dry_run,
+        disposition,
+        store,
+        naming,
+        sha256,
+        signature,
+    } = request;
+
+    if files.is_empty() {
+        return Err(ConvertRequestError::MissingFile.into());
+    }
+
+    // Names are used for outputs, headers and manifests so hostile names are sanitized first
+    for file in &mut files {
+        file.metadata.file_name = file
+            .metadata
+            .file_name
+            .as_deref()
+            .and_then(filename::sanitize);
+    }
+
+    // Single uploads are converted from the file they were streamed to
+    // unless a check needs the whole upload in memory
+    let mut streamed = None;
+    if !always_batch
+        && can_stream(
+            &config,
+            &files,
+            &signature,
+            dry_run.unwrap_or_default(),
+            store.unwrap_or_default(),
+        )
+    {
+        streamed = files.pop();
+    }
+
+    let mut files = read_uploads(files).await?;
+
+    match &streamed {
+        Some(upload) => {
+            verify_checksums(&headers, &sha256, 1, |_| upload.contents.sha256())?;
+
+            // Streamed uploads have no signatures, this only checks they aren't required
+            verify_signatures(&config, &signature, &[])?;
+        }
+        None => {
+            let contents: Vec<&[u8]> = files.iter().map(|file| file.contents.as_ref()).collect();
+            verify_uploads(&headers, &sha256, &contents)?;
+
+            let uploads: Vec<(Option<&str>, &[u8])> = files.iter().map(upload).collect();
+            verify_signatures(&config, &signature, &uploads)?;
+        }
+    }
+
+    if let Some(macro_name) = run_macro.as_deref() {
+        if !config.allow_macros {
+            return Err(ConvertRequestError::MacrosDisabled.into());
+        }
+
+        if !config.is_admin(&headers) {
+            return Err(ConvertRequestError::MacrosForbidden.into());
+        }
+
+        if !is_valid_macro_name(macro_name) {
+            return Err(ConvertRequestError::InvalidMacroName.into());
+        }
+    }
+
+    let mut settings = settings
+        .as_deref()
+        .map(RenderSettings::parse)
+        .transpose()?
+        .unwrap_or_default();
+
+    settings.set_embed_standard_fonts(embed_standard_fonts.unwrap_or(config.embed_standard_fonts));
+
+    let pdf_export = export_options
+        .as_deref()
+        .map(PdfExportOptions::parse)
+        .transpose()?
+        .unwrap_or_default();
+
+    let csv = CsvOptions::parse(CsvRequest {
+        format: format.as_deref(),
+        sheets: sheets.as_deref(),
+        delimiter: delimiter.as_deref(),
+        quote: quote.as_deref(),
+        encoding: encoding.as_deref(),
+    })?;
+
+    let spreadsheet = SpreadsheetExport::parse(format.as_deref(), data_only, remove_hidden_sheets)?;
+
+    let target_format =
+        TargetFormat::parse(format.as_deref(), details.load().filter_types.as_ref())?;
+
+    if password.as_deref().is_some_and(str::is_empty) {
+        return Err(ConvertRequestError::EmptyPassword.into());
+    }
+
+    let options = ConvertOptions {
+        split_sheets: split_sheets.unwrap_or_default(),
+        run_macro,
+        with_thumbnail: with_thumbnail.unwrap_or_default(),
+        with_text: with_text.unwrap_or_default(),
+        settings,
+        csv,
+        spreadsheet,
+        target_format,
+        password: password.map(DocumentPassword),
+        pdf_export,
+        redact: None,
+        stream_output: false,
+    };
+
+    options.pdf_export.check_compatible(&options)?;
+
+    if options.split_sheets && options.is_bundled() {
+        return Err(ConvertRequestError::BundleWithSplitSheets.into());
+    }
+
+    let is_export =
+        options.csv.is_some() || options.spreadsheet.is_some() || options.target_format.is_some();
+    if is_export && (options.split_sheets || options.is_bundled()) {
+        return Err(ConvertRequestError::PdfOptionsWithExport.into());
+    }
+
+    let naming = naming.unwrap_or_default();
+
+    if dry_run.unwrap_or_default() {
+        return convert_dry_run(&office, &config, files, &options).await;
+    }
+
+    if store.unwrap_or_default() {
+        let output_dir = config
+            .output_dir
+            .as_deref()
+            .ok_or(ConvertRequestError::StoreDisabled)?;
+
+        return convert_store(&office, &config, output_dir, files, options, naming).await;
+    }
+
+    if files.len() > 1 || always_batch {
+        let mut response =
+            convert_batch(&office, &config, files, options, naming, &headers).await?;
+        insert_disposition(&mut response, disposition, None, naming);
+        return Ok(response);
+    }
+
+    let (file_name, contents, signer, mismatch) = match streamed {
+        Some(upload) => {
+            let signer = config
+                .signing_key
+                .as_ref()
+                .map(|key| ResultSigner::from_digest(key, upload.contents.sha256(), &options))
+                .transpose()?;
+
+            let contents = UploadContents::Streamed(upload.contents);
+            (upload.metadata.file_name, contents, signer, None)
+        }
+        None => {
+            let file = files.remove(0);
+            let signer = result_signer(&config, &file.contents, &options)?;
+            let mismatch = check_format(&config, &file)?;
+            let contents = UploadContents::Bytes(file.contents);
+            (file.metadata.file_name, contents, signer, mismatch)
+        }
+    };
+
+    let warnings = mismatch
+        .iter()
+        .map(|mismatch| ConvertWarning::new("format_mismatch", mismatch.to_string()))
+        .collect();
+
+    let mut response = convert_file(
+        &office,
+        &config,
+        result_cache.as_ref(),
+        file_name.as_deref(),
+        contents,
+        options,
+        warnings,
+        &headers,
+    )
+    .await?;
+
+    if let Some(value) =
+        mismatch.and_then(|mismatch| HeaderValue::from_str(&mismatch.header_value()).ok())
+    {
+        response.headers_mut().insert(FORMAT_MISMATCH, value);
+    }
+
+    insert_disposition(&mut response, disposition, file_name.as_deref(), naming);
+
+    if let Some(signer) = signer {
+        insert_signature(&mut response, &signer)?;
+    }
+
+    Ok(response)
+}
+
+/// Header containing the JWS signing the converted result
+const RESULT_SIGNATURE: &str = "x-result-signature";
+
+/// Creates the signer for the results of converting the `input` with the
+/// `options` when result signing is enabled
+fn result_signer<'a>(
+    config: &'a ServerConfig,
+    input: &[u8],
+    options: &ConvertOptions,
+) -> anyhow::Result<Option<ResultSigner<'a>>> {
+    config
+        .signing_key
+        .as_ref()
+        .map(|key| ResultSigner::new(key, input, options))
+        .transpose()
+}
+
+/// Signs the converted result of the `response` adding the signature in the
+/// "X-Result-Signature" header, the result is identified by its digest
+fn insert_signature(
+    response: &mut Response<Body>,
+    signer: &ResultSigner<'_>,
+) -> anyhow::Result<()> {
+    let headers = response.headers();
+    let (Some(sha256), Some(content_type)) = (
+        headers
+            .get(CONTENT_SHA256)
+            .and_then(|value| value.to_str().ok()),
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()),
+    ) else {
+        return Ok(());
+    };
+
+    let signature = signer.sign(sha256, content_type)?;
+    let value = HeaderValue::from_str(&signature).context("invalid signature header")?;
+    response.headers_mut().insert(RESULT_SIGNATURE, value);
+
+    Ok(())
+}
+
+/// Converts a single file, serving the result from the cache when available.
+/// The `warnings` observed before converting are reported along with the
+/// warnings from the conversion (Cached results only report the former).
+/// Results that aren't cached are streamed to the client from disk
+#[allow(clippy::too_many_arguments)]
+async fn convert_file(
+    office: &OfficeHandle,
+    config: &ServerConfig,
+    result_cache: Option<&Arc<ResultCache>>,
+    file_name: Option<&str>,
+    contents: UploadContents,
+    mut options: ConvertOptions,
+    mut warnings: Vec<ConvertWarning>,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, DynHttpError> {
+    // Serve previously converted results from the cache, results of encrypted
+    // files are not cached as they would be served without the password and
+    // encrypted outputs are not cached as the key would not include theirs
+    let is_cacheable = options.password.is_none() && !options.pdf_export.is_encrypted();
+    let cache_entry = match result_cache.filter(|_| is_cacheable) {
+        Some(cache) => {
+            let key = contents.cache_key(&options.cache_fingerprint());
+
+            if let Some(cached) = get_cached(cache, &key).await {
+                cache.record(CacheOutcome::Hit);
+                let mut response = serve_cached(config, cache, &key, cached, headers).await?;
+                insert_warnings(&mut response, &warnings);
+                return Ok(response);
+            }
+
+            // Wait for any identical conversions that are in-flight
+            let guard = cache.lock_inflight(&key).await;
+
+            if guard.waited() {
+                if let Some(cached) = get_cached(cache, &key).await {
+                    cache.record(CacheOutcome::Coalesced);
+                    let mut response = serve_cached(config, cache, &key, cached, headers).await?;
+                    insert_warnings(&mut response, &warnings);
+                    return Ok(response);
+                }
+            }
+
+            cache.record(CacheOutcome::Miss);
+
+            Some((key, guard))
+        }
+        None => None,
+    };
+
+    options.stream_output = cache_entry.is_none();
+
+    // Convert the file
+    let converted = convert_upload(office, config, contents, file_name, options).await?;
+
+    let sha256 = match &converted.file {
+        Some(file) => file_sha256(file).await?,
+        None => content_sha256(&converted.bytes),
+    };
+
+    warnings.extend(converted.warnings);
+
+    for warning in &warnings {
+        warn!(code = %warning.code, message = %warning.message, "conversion warning");
+    }
+
+    // Store the result in the cache
+    if let (Some(cache), Some((key, _guard))) = (result_cache.as_ref(), cache_entry) {
+        if let Err(cause) = cache
+            .put(
+                &key,
+                converted.bytes.clone(),
+                converted.content_type,
+                &sha256,
+            )
+            .await
+        {
+            error!(?cause, "failed to cache converted result");
+        }
+
+        let mut response = cached_response(
+            config,
+            cache,
+            &key,
+            converted.bytes,
+            converted.content_type,
+            false,
+            Some(&sha256),
+        )
+        .await?;
+        insert_warnings(&mut response, &warnings);
+        return Ok(response);
+    }
+
+    // Build the response
+    let response = Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(converted.content_type),
+        )
+        .header(CONTENT_SHA256, sha256);
+
+    let body = match converted.file {
+        Some(file) => download::file_body(config, file).await?,
+        None => download::bytes_body(config, converted.bytes).await?,
+    };
+
+    let mut response = response.body(body).context("failed to create response")?;
+
+    insert_warnings(&mut response, &warnings);
+
+    Ok(response)
+}
+
+/// Creates the SHA-256 hex digest of the converted `file` without reading
+/// it into memory
+async fn file_sha256(file: &TempFile) -> anyhow::Result<String> {
+    let path = file.path.clone();
+
+    tokio::task::spawn_blocking(move || -> std::io::Result<String> {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+        Ok(format!("{:x}", hasher.finalize()))
+    })
+    .await
+    .context("failed to hash converted file")?
+    .context("failed to hash converted file")
+}
+
+/// Number of files of a batch queued for the runner at once
+const BATCH_QUEUED_FILES: usize = 2;
+
+/// Converts each file of a batch convert request, each file takes its own
+/// place in the runner queue so other requests are not blocked for the whole
+/// batch. Failures are reported per-file instead of failing the batch.
+///
+/// Responds with a zip archive of the results, or a multipart/mixed response
+/// when preferred by the "Accept" header
+async fn convert_batch(
+    office: &OfficeHandle,
+    config: &ServerConfig,
+    files: Vec<FieldData<Bytes>>,
+    options: ConvertOptions,
+    naming: OutputNaming,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, DynHttpError> {
+    // The next file is prepared and queued while the previous file converts
+    // so the runner is not left waiting between files
+    let entries: Vec<batch::BatchEntry> = futures_util::stream::iter(files)
+        .map(|file| {
+            let options = options.clone();
+
+            async move {
+                let started = Instant::now();
+                let result = convert_checked_file(office, config, &file, options).await;
+                let duration = started.elapsed();
+
+                if let Err(err) = &result {
+                    err.log();
+                }
+
+                batch::BatchEntry {
+                    file_name: file.metadata.file_name,
+                    result,
+                    duration,
+                }
+            }
+        })
+        .buffered(BATCH_QUEUED_FILES)
+        .collect()
+        .await;
+
+    let response = if batch::accepts_multipart_mixed(headers) {
+        batch::multipart_response(config, entries, naming).await?
+    } else {
+        batch::zip_response(config, entries, naming).await?
+    };
+
+    Ok(response)
+}
+
+/// Performs a dry run of converting the `files`, each file is checked and
+/// loaded to estimate its page count but not exported
+async fn convert_dry_run(
+    office: &OfficeHandle,
+    config: &ServerConfig,
+    files: Vec<FieldData<Bytes>>,
+    options: &ConvertOptions,
+) -> Result<Response<Body>, DynHttpError> {
+    let mut entries = Vec::with_capacity(files.len());
+
+    for file in files {
+        let detected_format = detect::detect(&file.contents).map(|format| format.name());
+
+        let result = match check_format(config, &file) {
+            Ok(mismatch) => dry_run_file(office, config, file.contents)
+                .await
+                .map(|stats| {
+                    let mut warnings: Vec<ConvertWarning> = mismatch
+                        .iter()
+                        .map(|mismatch| {
+                            ConvertWarning::new("format_mismatch", mismatch.to_string())
+                        })
+                        .collect();
+
+                    if options.split_sheets && stats.document_type != "spreadsheet" {
+                        warnings.push(ConvertWarning::new(
+                            "split_sheets_ignored",
+                            "split_sheets was requested but the document is not a spreadsheet",
+                        ));
+                    }
+
+                    let is_export = options.csv.is_some() || options.spreadsheet.is_some();
+                    if is_export && stats.document_type != "spreadsheet" {
+                        warnings.push(ConvertWarning::new(
+                            "not_spreadsheet",
+                            "a spreadsheet export was requested but the document is not a spreadsheet",
+                        ));
+                    }
+
+                    (stats, warnings)
+                }),
+            Err(mismatch) => Err(mismatch.into()),
+        };
+
+        entries.push(DryRunEntry::new(
+            file.metadata.file_name,
+            detected_format,
+            result,
+        ));
+    }
+
+    Ok(Json(DryRunReport::new(entries)).into_response())
+}
+
+/// Loads a single file for a dry run providing its statistics
+async fn dry_run_file(
+    office: &OfficeHandle,
+    config: &ServerConfig,
+    bytes: Bytes,
+) -> Result<DocumentStats, DynHttpError> {
+    let input = office.prepare_input(bytes, config).await?;
+    let stats = office.extract_stats(input).await?.map_err(runner_error)?;
+
+    Ok(stats)
+}
+
+/// Writes the converted `files` to the `output_dir` responding with their
+/// paths, batches respond with the outcome of each file
+async fn convert_store(
+    office: &OfficeHandle,
+    config: &ServerConfig,
+    output_dir: &std::path::Path,
+    mut files: Vec<FieldData<Bytes>>,
+    options: ConvertOptions,
+    naming: OutputNaming,
+) -> Result<Response<Body>, DynHttpError> {
+    if files.len() == 1 {
+        let file = files.remove(0);
+        let signer = result_signer(config, &file.contents, &options)?;
+        let converted = convert_checked_file(office, config, &file, options).await?;
+        let stored = store::store_output(
+            output_dir,
+            file.metadata.file_name.as_deref(),
+            naming,
+            converted,
+            signer.as_ref(),
+            config.url_signer.as_ref(),
+        )
+        .await?;
+
+        return Ok(Json(stored).into_response());
+    }
+
+    let mut entries = Vec::with_capacity(files.len());
+
+    for file in files {
+        let file_name = file.metadata.file_name.as_deref();
+        let signer = result_signer(config, &file.contents, &options)?;
+        let result = match convert_checked_file(office, config, &file, options.clone()).await {
+            Ok(converted) => store::store_output(
+                output_dir,
+                file_name,
+                naming,
+                converted,
+                signer.as_ref(),
+                config.url_signer.as_ref(),
+            )
+            .await
+            .map_err(DynHttpError::from),
+            Err(err) => Err(err),
+        };
+
+        entries.push(StoredBatchEntry::new(
+            file.metadata.file_name.clone(),
+            result,
+        ));
+    }
+
+    Ok(Json(StoredBatch::new(entries)).into_response())
+}
+
+/// Converts a single file from a batch after checking its format, format
+/// mismatches are reported as warnings when not rejected
+async fn convert_checked_file(
+    office: &OfficeHandle,
+    config: &ServerConfig,
+    file: &FieldData<Bytes>,
+    options: ConvertOptions,
+) -> Result<ConvertedDocument, DynHttpError> {
+    let mismatch = check_format(config, file)?;
+    let mut converted = convert_upload(
+        office,
+        config,
+        UploadContents::Bytes(file.contents.clone()),
+        file.metadata.file_name.as_deref(),
+        options,
+    )
+    .await?;
+
+    if let Some(mismatch) = mismatch {
+        converted.warnings.insert(
+            0,
+            ConvertWarning::new("format_mismatch", mismatch.to_string()),
+        );
+    }
+
+    Ok(converted)
+}
+
+/// Converts the uploaded `contents` named `file_name`, inputs that fail to
+/// convert are quarantined when enabled
+async fn convert_upload(
+    office: &OfficeHandle,
+    config: &ServerConfig,
+    contents: UploadContents,
+    file_name: Option<&str>,
+    options: ConvertOptions,
+) -> Result<ConvertedDocument, DynHttpError> {
+    // Input is kept until the conversion finishes in case it needs to be
+    // quarantined (Uploads are only streamed when quarantine is disabled)
+    let retained = match (config.quarantine.as_ref(), &contents) {
+        (Some(quarantine), UploadContents::Bytes(bytes)) => {
+            Some((quarantine, bytes.clone(), options.clone()))
+        }
+        _ => None,
+    };
+
+    let started = Instant::now();
+
+    // Diagnostics are collected for quarantined inputs and the office log
+    let collect_diagnostics = retained.is_some() || config.office_log.is_enabled();
+
+    let input = contents.into_input(office, config).await?;
+    let ConvertResult {
+        result,
+        diagnostics,
+    } = office.convert(input, options, collect_diagnostics).await?;
+
+    let log = match (&diagnostics, config.office_log.is_enabled()) {
+        (Some(diagnostics), true) => diagnostics.log.clone(),
+        _ => Vec::new(),
+    };
+
+    if let (Err(err), Some((quarantine, bytes, options))) = (&result, retained) {
+        if Quarantine::is_quarantined(err) {
+            let failure = FailedConversion {
+                input: &bytes,
+                file_name,
+                options: &options,
+                error: err,
+                diagnostics,
+                elapsed: started.elapsed(),
+            };
+
+            match quarantine.add(failure).await {
+                Ok(id) => warn!(quarantine_id = %id, "quarantined failed conversion input"),
+                Err(cause) => error!(?cause, "failed to quarantine failed conversion input"),
+            }
+        }
+    }
+
+    result.map_err(|err| runner_error(err).with_log(log))
+}
+
+/// Query parameters for the cached result endpoint
+#[derive(Deserialize)]
+struct CachedResultQuery {
+    /// Whether browsers should display the result inline or download it
+    disposition: Option<Disposition>,
+}
+
+/// GET /results/:hash
+///
+/// Serves a previously converted result from the cache by its content hash,
+/// the hash is provided in the "Content-Location" header of convert responses
+async fn cached_result(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(result_cache): Extension<Option<Arc<ResultCache>>>,
+    Path(hash): Path<String>,
+    Query(query): Query<CachedResultQuery>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, DynHttpError> {
+    let cache = match result_cache.as_ref() {
+        Some(value) => value,
+        None => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+
+    if !ResultCache::is_valid_key(&hash) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let cached = match cache.get(&hash).await? {
+        Some(value) => value,
+        None => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+
+    let mut response = serve_cached(&config, cache, &hash, cached, &headers).await?;
+    insert_disposition(&mut response, query.disposition, None, OutputNaming::Name);
+
+    Ok(response)
+}
+
+/// Query parameters of a pre-signed output URL
+#[derive(Deserialize)]
+struct PresignedQuery {
+    /// When the URL expires (Seconds since the unix epoch)
+    expires: Option<u64>,
+    /// Signature of the URL
+    signature: Option<String>,
+}
+
+/// GET /outputs/:name
+///
+/// Serves a stored output from the output directory using the pre-signed
+/// URL provided when the output was stored
+async fn presigned_output(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Path(name): Path<String>,
+    Query(query): Query<PresignedQuery>,
+) -> Result<Response<Body>, DynHttpError> {
+    let (Some(output_dir), Some(url_signer)) =
+        (config.output_dir.as_deref(), config.url_signer.as_ref())
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let (Some(expires), Some(signature)) = (query.expires, query.signature.as_deref()) else {
+        return Err(PresignError::InvalidSignature.into());
+    };
+
+    url_signer.verify(&name, expires, signature)?;
+
+    if !is_output_name(&name) {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    let bytes = match tokio::fs::read(output_dir.join(&name)).await {
+        Ok(value) => value,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        }
+        Err(err) => {
+            return Err(anyhow::Error::new(err)
+                .context("failed to read output")
+                .into())
+        }
+    };
+
+    let content_type = name
+        .rsplit_once('.')
+        .map_or("application/pdf", |(_, extension)| {
+            convert::output_content_type(extension)
+        });
+
+    let response = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(CONTENT_SHA256, content_sha256(&bytes))
+        .body(download::bytes_body(&config, bytes.into()).await?)
+        .context("failed to create response")?;
+
+    Ok(response)
+}
+
+/// DELETE /outputs/:name
+///
+/// Admin only. Removes a stored output from the output directory ahead of
+/// the retention period
+async fn delete_output(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    let Some(output_dir) = config.output_dir.as_deref() else {
+        return Ok(StatusCode::NOT_FOUND);
+    };
+
+    if !is_output_name(&name) {
+        return Ok(StatusCode::NOT_FOUND);
+    }
+
+    match tokio::fs::remove_file(output_dir.join(&name)).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(StatusCode::NOT_FOUND),
+        Err(err) => Err(anyhow::Error::new(err)
+            .context("failed to remove output")
+            .into()),
+    }
+}
+
+/// Checks the `name` refers to a file directly within the output directory
+fn is_output_name(name: &str) -> bool {
+    !name.contains(['/', '\\']) && !name.starts_with('.')
+}
+
+/// Gets a result from the cache, failing to read from the cache is logged
+/// and treated as a miss
+async fn get_cached(cache: &ResultCache, key: &str) -> Option<CachedResult> {
+    match cache.get(key).await {
+        Ok(value) => value,
+        Err(cause) => {
+            error!(?cause, "failed to read cached result");
+            None
+        }
+    }
+}
+
+/// Serves a result from the cache, compressed results are served as-is to
+/// clients that accept zstd encoding otherwise they are decompressed
+async fn serve_cached(
+    config: &ServerConfig,
+    cache: &ResultCache,
+    key: &str,
+    cached: CachedResult,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, DynHttpError> {
+    let cached = if cached.compressed && !accepts_zstd(headers) {
+        cached.decompress().await?
+    } else {
+        cached
+    };
+
+    cached_response(
+        config,
+        cache,
+        key,
+        cached.bytes,
+        &cached.content_type,
+        cached.compressed,
+        cached.sha256.as_deref(),
+    )
+    .await
+}
+
+/// Checks if the "Accept-Encoding" header of a request accepts zstd
+fn accepts_zstd(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|encoding| {
+            let mut parts = encoding.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            let rejected = parts.any(|param| param.replace(' ', "") == "q=0");
+
+            name.eq_ignore_ascii_case("zstd") && !rejected
+        })
+}
+
+/// Creates a response for a content addressed result, these results will never
+/// change so they are marked as immutable for clients and CDNs
+async fn cached_response(
+    config: &ServerConfig,
+    cache: &ResultCache,
+    key: &str,
+    bytes: Bytes,
+    content_type: &str,
+    compressed: bool,
+    sha256: Option<&str>,
+) -> Result<Response<Body>, DynHttpError> {
+    // Compressed representations need a distinct entity tag
+    let etag = if compressed {
+        format!("\"{key}-zstd\"")
+    } else {
+        format!("\"{key}\"")
+    };
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, cache.cache_control())
+        .header(header::ETAG, etag)
+        .header(header::CONTENT_LOCATION, format!("/results/{key}"))
+        .header(header::VARY, "accept-encoding");
+
+    if compressed {
+        response = response.header(header::CONTENT_ENCODING, "zstd");
+    }
+
+    // Digest is always of the uncompressed content
+    if let Some(sha256) = sha256 {
+        response = response.header(CONTENT_SHA256, sha256);
+    }
+
+    let response = response
+        .body(download::bytes_body(config, bytes).await?)
+        .context("failed to create response")?;
+
+    Ok(response)
+}
+
+/// Request to extract assets or statistics from a file
+#[derive(TryFromMultipart)]
+struct ExtractAssetsRequest {
+    /// The file to extract from
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
+
+    /// SHA-256 hex digest of the uploaded file (Alternative to the
+    /// "X-Content-Sha256" header)
+    sha256: Option<String>,
+
+    /// Base64 encoded detached Ed25519 signature of the uploaded file
+    signature: Option<String>,
+}
+
+/// POST /extract-assets
+///
+/// Extracts the embedded images and objects from the provided file
+/// responding with a zip archive of the assets
+async fn extract_assets(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    headers: HeaderMap,
+    TypedMultipart(ExtractAssetsRequest {
+        file,
+        sha256,
+        signature,
+    }): TypedMultipart<ExtractAssetsRequest>,
+) -> Result<Response<Body>, DynHttpError> {
+    verify_uploads(&headers, sha256.as_slice(), &[&file.contents])?;
+    verify_signatures(&config, signature.as_slice(), &[upload(&file)])?;
+
+    // Extract the assets
+    let input = office.prepare_input(file.contents, &config).await?;
+    let archive = office.extract_assets(input).await?.map_err(runner_error)?;
+
+    // Build the response
+    let response = Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/zip"),
+        )
+        .body(download::bytes_body(&config, archive).await?)
+        .context("failed to create response")?;
+
+    Ok(response)
+}
+
+/// POST /stats-extract
+///
+/// Extracts the document statistics (word, character, paragraph counts,
+/// sheet and cell counts for spreadsheets, slide counts for presentations)
+/// from the provided file
+async fn stats_extract(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    headers: HeaderMap,
+    TypedMultipart(ExtractAssetsRequest {
+        file,
+        sha256,
+        signature,
+    }): TypedMultipart<ExtractAssetsRequest>,
+) -> Result<Json<DocumentStats>, DynHttpError> {
+    verify_uploads(&headers, sha256.as_slice(), &[&file.contents])?;
+    verify_signatures(&config, signature.as_slice(), &[upload(&file)])?;
+
+    // Extract the statistics
+    let input = office.prepare_input(file.contents, &config).await?;
+    let stats = office.extract_stats(input).await?.map_err(runner_error)?;
+
+    Ok(Json(stats))
+}
+
+/// Request to convert a file redacting text and regions from the PDF
+#[derive(TryFromMultipart)]
+struct RedactRequest {
+    /// The file to convert
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
+
+    /// Terms to redact wherever they appear in the text of the document,
+    /// can be provided multiple times
+    terms: Vec<String>,
+
+    /// JSON array of the page regions to redact
+    /// (i.e [{"page": 1, "rect": [72, 700, 300, 720]}])
+    regions: Option<String>,
+
+    /// Only redact text matching the case of the terms
+    match_case: Option<bool>,
+
+    /// Password to open encrypted files with
+    password: Option<String>,
+
+    /// SHA-256 hex digest of the uploaded file (Alternative to the
+    /// "X-Content-Sha256" header)
+    sha256: Option<String>,
+
+    /// Base64 encoded detached Ed25519 signature of the uploaded file
+    signature: Option<String>,
+}
+
+/// POST /convert-redact
+///
+/// Converts the provided file to PDF removing the terms from its text and
+/// the regions from its pages, responding with a zip archive of the PDF
+/// and a report of the redactions
+async fn convert_redact(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(result_cache): Extension<Option<Arc<ResultCache>>>,
+    headers: HeaderMap,
+    TypedMultipart(RedactRequest {
+        mut file,
+        terms,
+        regions,
+        match_case,
+        password,
+        sha256,
+        signature,
+    }): TypedMultipart<RedactRequest>,
+) -> Result<Response<Body>, DynHttpError> {
+    file.metadata.file_name = file
+        .metadata
+        .file_name
+        .as_deref()
+        .and_then(filename::sanitize);
+
+    verify_uploads(&headers, sha256.as_slice(), &[&file.contents])?;
+    verify_signatures(&config, signature.as_slice(), &[upload(&file)])?;
+
+    let redact = RedactOptions::parse(terms, regions.as_deref(), match_case.unwrap_or_default())?;
+
+    if password.as_deref().is_some_and(str::is_empty) {
+        return Err(ConvertRequestError::EmptyPassword.into());
+    }
+
+    let mut settings = RenderSettings::default();
+    settings.set_embed_standard_fonts(config.embed_standard_fonts);
+
+    let options = ConvertOptions {
+        settings,
+        password: password.map(DocumentPassword),
+        redact: Some(redact),
+        ..Default::default()
+    };
+
+    let signer = result_signer(&config, &file.contents, &options)?;
+    let mismatch = check_format(&config, &file)?;
+
+    let warnings = mismatch
+        .iter()
+        .map(|mismatch| ConvertWarning::new("format_mismatch", mismatch.to_string()))
+        .collect();
+
+    let mut response = convert_file(
+        &office,
+        &config,
+        result_cache.as_ref(),
+        file.metadata.file_name.as_deref(),
+        UploadContents::Bytes(file.contents),
+        options,
+        warnings,
+        &headers,
+    )
+    .await?;
+
+    if let Some(value) =
+        mismatch.and_then(|mismatch| HeaderValue::from_str(&mismatch.header_value()).ok())
+    {
+        response.headers_mut().insert(FORMAT_MISMATCH, value);
+    }
+
+    if let Some(signer) = signer {
+        insert_signature(&mut response, &signer)?;
+    }
+
+    Ok(response)
+}
+
+/// Request to convert a file as a background job
+#[derive(TryFromMultipart)]
+struct JobRequest {
+    /// The file to convert
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
+
+    /// Format to convert to by file extension, defaults to "pdf"
+    format: Option<String>,
+
+    /// JSON object of rendering settings to adjust for this conversion
+    settings: Option<String>,
+
+    /// Password to open encrypted files with
+    password: Option<String>,
+
+    /// Embed the standard PDF fonts in the output, defaults to the server
+    /// "--embed-standard-fonts" option
+    embed_standard_fonts: Option<bool>,
+
+    /// SHA-256 hex digest of the uploaded file (Alternative to the
+    /// "X-Content-Sha256" header)
+    sha256: Option<String>,
+
+    /// Base64 encoded detached Ed25519 signature of the uploaded file
+    signature: Option<String>,
+}
+
+/// Errors from background job requests
+#[derive(Debug, Error)]
+enum JobError {
+    /// Background jobs are not enabled
+    #[error("background jobs are not enabled on this server")]
+    Disabled,
+
+    /// Job does not exist or has expired
+    #[error("job not found")]
+    NotFound,
+
+    /// Result of a job that has not finished converting was requested
+    #[error("job does not have a result")]
+    NoResult(JobState),
+
+    /// Result of a job was requested after it was removed
+    #[error("job result has expired")]
+    ResultExpired,
+
+    /// Page of a job result that is not a PDF was requested
+    #[error("job result is not a pdf")]
+    NotPdf,
+
+    /// Requested page is beyond the last page of the job result
+    #[error("page {page} does not exist, the result has {page_count} page(s)")]
+    PageNotFound { page: u64, page_count: u64 },
+
+    /// Requested page render resolution is outside the allowed range
+    #[error("dpi must be between {MIN_PAGE_DPI} and {MAX_PAGE_DPI}")]
+    InvalidDpi,
+}
+
+impl HttpError for JobError {
+    fn status(&self) -> StatusCode {
+        match self {
+            JobError::Disabled | JobError::NotFound | JobError::PageNotFound { .. } => {
+                StatusCode::NOT_FOUND
+            }
+            JobError::NoResult(_) | JobError::NotPdf => StatusCode::CONFLICT,
+            JobError::ResultExpired => StatusCode::GONE,
+            JobError::InvalidDpi => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            JobError::Disabled => "jobs_disabled",
+            JobError::NotFound => "job_not_found",
+            JobError::NoResult(_) => "job_not_done",
+            JobError::ResultExpired => "job_result_expired",
+            JobError::NotPdf => "job_result_not_pdf",
+            JobError::PageNotFound { .. } => "page_not_found",
+            JobError::InvalidDpi => "invalid_dpi",
+        })
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            JobError::NoResult(state) => Some(serde_json::json!({ "state": state })),
+            JobError::PageNotFound { page_count, .. } => {
+                Some(serde_json::json!({ "page_count": page_count }))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// POST /jobs
+///
+/// Accepts a file to convert in the background responding with the queued
+/// job, the status of the job is polled from "/jobs/:id" and the result is
+/// downloaded from "/jobs/:id/result" once done
+async fn create_job(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(details): Extension<SharedDetails>,
+    admission: Option<Extension<SharedAdmission>>,
+    headers: HeaderMap,
+    TypedMultipart(JobRequest {
+        mut file,
+        format,
+        settings,
+        password,
+        embed_standard_fonts,
+        sha256,
+        signature,
+    }): TypedMultipart<JobRequest>,
+) -> Result<Response<Body>, DynHttpError> {
+    let jobs = config.jobs.clone().ok_or(JobError::Disabled)?;
+
+    file.metadata.file_name = file
+        .metadata
+        .file_name
+        .as_deref()
+        .and_then(filename::sanitize);
+
+    verify_uploads(&headers, sha256.as_slice(), &[&file.contents])?;
+    verify_signatures(&config, signature.as_slice(), &[upload(&file)])?;
+    runner::check_upload_size(&file.contents, config.min_upload_size)?;
+    let mismatch = check_format(&config, &file)?;
+
+    let mut settings = settings
+        .as_deref()
+        .map(RenderSettings::parse)
+        .transpose()?
+        .unwrap_or_default();
+
+    settings.set_embed_standard_fonts(embed_standard_fonts.unwrap_or(config.embed_standard_fonts));
+
+    if password.as_deref().is_some_and(str::is_empty) {
+        return Err(ConvertRequestError::EmptyPassword.into());
+    }
+
+    let options = ConvertOptions {
+        settings,
+        csv: CsvOptions::parse(CsvRequest {
+            format: format.as_deref(),
+            ..Default::default()
+        })?,
+        spreadsheet: SpreadsheetExport::parse(format.as_deref(), None, None)?,
+        target_format: TargetFormat::parse(
+            format.as_deref(),
+            details.load().filter_types.as_ref(),
+        )?,
+        password: password.map(DocumentPassword),
+        ..Default::default()
+    };
+
+    // Stored before responding so any server sharing the storage can report the job
+    let job = jobs
+        .submit(file.metadata.file_name.clone(), file.contents.len() as u64)
+        .await?;
+    let id = job.id.clone();
+
+    // Jobs keep the priority tier of the request that submitted them
+    let tier = priority::current();
+
+    // Jobs count against the limits of the request until they finish
+    let admission = admission.and_then(|Extension(admission)| admission.take());
+
+    tokio::spawn(priority::scope(tier, async move {
+        let _admission = admission;
+
+        let convert = async {
+            let mut converted = convert_upload(
+                &office,
+                &config,
+                UploadContents::Bytes(file.contents),
+                file.metadata.file_name.as_deref(),
+                options,
+            )
+            .await?;
+
+            if let Some(mismatch) = mismatch {
+                converted.warnings.insert(
+                    0,
+                    ConvertWarning::new("format_mismatch", mismatch.to_string()),
+                );
+            }
+
+            Ok(converted)
+        };
+
+        jobs.run(&id, convert).await;
+    }));
+
+    let response = Response::builder()
+        .status(StatusCode::ACCEPTED)
+        .header(header::LOCATION, format!("/jobs/{}", job.id))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::to_vec(&job).context("failed to serialize job")?,
+        ))
+        .context("failed to create response")?;
+
+    Ok(response)
+}
+
+/// GET /jobs/:id
+///
+/// Reports the state of a background job and its result once finished
+async fn job_status(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Path(id): Path<String>,
+) -> Result<Json<JobStatus>, DynHttpError> {
+    let jobs = config.jobs.as_ref().ok_or(JobError::Disabled)?;
+    let job = jobs.find(&id).await?.ok_or(JobError::NotFound)?;
+
+    Ok(Json(job))
+}
+
+/// Progress of a running job sent as a "progress" event
+#[derive(Serialize)]
+struct JobProgressEvent {
+    /// Phase office is processing the job in, "idle" while the job waits for
+    /// the converter
+    phase: RunnerPhase,
+    /// Progress percentage office reported for the phase
+    progress: Option<u8>,
+}
+
+/// State of the events stream of a job
+struct JobEvents {
+    jobs: Arc<JobStore>,
+    id: String,
+    /// Notified when the state of a job known to this server changes
+    changes: watch::Receiver<()>,
+    /// Status to send the next event for, fetched again when [None]
+    status: Option<JobStatus>,
+    /// Name and data of the last event sent
+    last: Option<(&'static str, String)>,
+}
+
+/// GET /jobs/:id/events
+///
+/// Streams the state of a background job as server-sent events ("queued",
+/// "progress", "done" or "failed"), an event is sent whenever the state or
+/// progress changes and the stream ends once the job has finished
+async fn job_events(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, DynHttpError> {
+    let jobs = config.jobs.clone().ok_or(JobError::Disabled)?;
+    let job = jobs.find(&id).await?.ok_or(JobError::NotFound)?;
+
+    let events = JobEvents {
+        changes: jobs.subscribe(),
+        jobs,
+        id,
+        status: Some(job),
+        last: None,
+    };
+
+    let stream = futures_util::stream::unfold(events, next_job_event);
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Provides the next event of the job, waiting for its state to change.
+/// Jobs known through the storage are polled as their changes are only
+/// notified on the server running them
+async fn next_job_event(mut events: JobEvents) -> Option<(Result<Event, Infallible>, JobEvents)> {
+    loop {
+        let status = match events.status.take() {
+            Some(status) => status,
+            None => {
+                // Progress is polled while converting
+                tokio::select! {
+                    result = events.changes.changed() => result.ok()?,
+                    _ = tokio::time::sleep(PROGRESS_INTERVAL) => {}
+                }
+
+                match events.jobs.find(&events.id).await {
+                    Ok(status) => status?,
+                    Err(cause) => {
+                        error!(?cause, "failed to read job status");
+                        return None;
+                    }
+                }
+            }
+        };
+
+        // Finished jobs end the stream after their event
+        if events
+            .last
+            .as_ref()
+            .is_some_and(|(name, _)| matches!(*name, "done" | "failed"))
+        {
+            return None;
+        }
+
+        let data = match status.state {
+            JobState::Running => {
+                let progress = events.jobs.progress(&events.id);
+                serde_json::to_string(&JobProgressEvent {
+                    phase: progress
+                        .as_ref()
+                        .map_or(RunnerPhase::Idle, |progress| progress.phase()),
+                    progress: progress.and_then(|progress| progress.progress()),
+                })
+            }
+            _ => serde_json::to_string(&status),
+        };
+
+        let data = match data {
+            Ok(value) => value,
+            Err(cause) => {
+                error!(?cause, "failed to serialize job event");
+                return None;
+            }
+        };
+
+        let name = match status.state {
+            JobState::Queued => "queued",
+            JobState::Running => "progress",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+        };
+
+        // Only changes are sent
+        if events
+            .last
+            .as_ref()
+            .is_some_and(|last| last.0 == name && last.1 == data)
+        {
+            continue;
+        }
+
+        let event = Event::default().event(name).data(&data);
+        events.last = Some((name, data));
+
+        return Some((Ok(event), events));
+    }
+}
+
+#[derive(Deserialize)]
+struct JobResultQuery {
+    /// Whether browsers should display the result inline or download it
+    disposition: Option<Disposition>,
+}
+
+/// GET /jobs/:id/result
+///
+/// Streams the result of a finished background job from storage
+async fn job_result(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Path(id): Path<String>,
+    Query(query): Query<JobResultQuery>,
+) -> Result<Response<Body>, DynHttpError> {
+    let jobs = config.jobs.as_ref().ok_or(JobError::Disabled)?;
+    let job = jobs.find(&id).await?.ok_or(JobError::NotFound)?;
+
+    if job.state != JobState::Done {
+        return Err(JobError::NoResult(job.state).into());
+    }
+
+    if job.result_expired {
+        return Err(JobError::ResultExpired.into());
+    }
+
+    // Result was removed by another server since the status was read
+    let result = jobs
+        .open_result(&id)
+        .await?
+        .ok_or(JobError::ResultExpired)?;
+
+    let mut response = Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            job.content_type.as_deref().unwrap_or("application/pdf"),
+        )
+        .header(CONTENT_SHA256, job.sha256.unwrap_or_default())
+        .body(download::stream_body(&config, result.stream, result.size))
+        .context("failed to create response")?;
+
+    insert_warnings(&mut response, &job.warnings);
+    insert_disposition(
+        &mut response,
+        query.disposition,
+        job.file_name.as_deref(),
+        OutputNaming::Name,
+    );
+
+    Ok(response)
+}
+
+/// Resolution (DPI) pages are rendered at by default
+const DEFAULT_PAGE_DPI: u32 = 96;
+
+/// Minimum resolution (DPI) pages can be rendered at
+const MIN_PAGE_DPI: u32 = 36;
+
+/// Maximum resolution (DPI) pages can be rendered at
+const MAX_PAGE_DPI: u32 = 600;
+
+/// Header containing the number of pages of the job result
+const PAGE_COUNT: &str = "x-page-count";
+
+#[derive(Deserialize)]
+struct JobPageQuery {
+    /// Format to provide the page in, defaults to "pdf"
+    #[serde(default)]
+    format: PageFormat,
+    /// Resolution (DPI) to render PNG pages at
+    dpi: Option<u32>,
+}
+
+/// DELETE /jobs/:id/result
+///
+/// Removes the result of a finished background job ahead of the retention
+/// period, the status of the job is kept and reports the result as expired
+async fn delete_job_result(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, DynHttpError> {
+    let jobs = config.jobs.as_ref().ok_or(JobError::Disabled)?;
+    let job = jobs.find(&id).await?.ok_or(JobError::NotFound)?;
+
+    // Results of failed jobs never existed
+    if job.state != JobState::Done {
+        return Err(JobError::NoResult(job.state).into());
+    }
+
+    jobs.delete_result(&id).await?.ok_or(JobError::NotFound)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /jobs/:id/pages/:n
+///
+/// Provides a single page (Starting at 1) of the PDF result of a finished
+/// background job, as a PDF of only the page or a PNG render of it
+async fn job_page(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Path((id, page)): Path<(String, u64)>,
+    Query(query): Query<JobPageQuery>,
+) -> Result<Response<Body>, DynHttpError> {
+    let jobs = config.jobs.as_ref().ok_or(JobError::Disabled)?;
+    let job = jobs.find(&id).await?.ok_or(JobError::NotFound)?;
+
+    if job.state != JobState::Done {
+        return Err(JobError::NoResult(job.state).into());
+    }
+
+    if job.result_expired {
+        return Err(JobError::ResultExpired.into());
+    }
+
+    if job
+        .content_type
+        .as_deref()
+        .is_some_and(|content_type| content_type != "application/pdf")
+    {
+        return Err(JobError::NotPdf.into());
+    }
+
+    let dpi = query.dpi.unwrap_or(DEFAULT_PAGE_DPI);
+    if !(MIN_PAGE_DPI..=MAX_PAGE_DPI).contains(&dpi) {
+        return Err(JobError::InvalidDpi.into());
+    }
+
+    // Result was removed by another server since the status was read
+    let source = jobs
+        .page_source(&id, &config.work_dir)
+        .await?
+        .ok_or(JobError::ResultExpired)?;
+
+    let page_count = jobs.page_count(&source).await?;
+    if !(1..=page_count).contains(&page) {
+        return Err(JobError::PageNotFound { page, page_count }.into());
+    }
+
+    let output = TempFile {
+        path: config
+            .work_dir
+            .join(format!("lo_native_page_{}", random_id())),
+    };
+    jobs.extract_page(&source, page, query.format, dpi, &output.path)
+        .await?;
+
+    let bytes = tokio::fs::read(&output.path)
+        .await
+        .context("failed to read extracted page")?;
+
+    let response = Response::builder()
+        .header(header::CONTENT_TYPE, query.format.content_type())
+        .header(PAGE_COUNT, page_count)
+        .body(download::bytes_body(&config, Bytes::from(bytes)).await?)
+        .context("failed to create response")?;
+
+    Ok(response)
+}
+
+/// GET /admin/cache
+///
+/// Reports the result cache usage statistics
+async fn admin_cache_stats(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(result_cache): Extension<Option<Arc<ResultCache>>>,
+    headers: HeaderMap,
+) -> Result<Json<CacheStats>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    let cache = result_cache.as_ref().ok_or(AdminError::CacheDisabled)?;
+    let stats = cache.stats().await?;
+
+    Ok(Json(stats))
+}
+
+/// Result of removing entries from the cache
+#[derive(Serialize)]
+struct CacheRemoveResponse {
+    /// Number of entries removed
+    removed: u64,
+}
+
+/// DELETE /admin/cache
+///
+/// Purges all entries from the result cache
+async fn admin_cache_purge(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(result_cache): Extension<Option<Arc<ResultCache>>>,
+    headers: HeaderMap,
+) -> Result<Json<CacheRemoveResponse>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    let cache = result_cache.as_ref().ok_or(AdminError::CacheDisabled)?;
+    let removed = cache.purge().await?;
+
+    Ok(Json(CacheRemoveResponse { removed }))
+}
+
+/// DELETE /admin/cache/:hash
+///
+/// Evicts the entry for a specific content hash from the result cache
+async fn admin_cache_evict(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(result_cache): Extension<Option<Arc<ResultCache>>>,
+    Path(hash): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<CacheRemoveResponse>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    let cache = result_cache.as_ref().ok_or(AdminError::CacheDisabled)?;
+
+    let removed = if ResultCache::is_valid_key(&hash) && cache.evict(&hash).await? {
+        1
+    } else {
+        0
+    };
+
+    Ok(Json(CacheRemoveResponse { removed }))
+}
+
+/// Result from checking the server busy state
+#[derive(Serialize)]
+struct StatusResponse {
+    /// Whether the server is busy
+    is_busy: bool,
+    /// Whether office is running, when cold office is started by the
+    /// next conversion
+    warm: bool,
+    /// Whether the server is ready to accept work, conversions are rejected
+    /// with a 503 while office is being started or recycled
+    ready: bool,
+    /// Number of requests waiting for the converter
+    queue_depth: usize,
+    /// Detailed status of each worker
+    workers: Vec<WorkerStatus>,
+    /// Totals of the work processed by the server
+    totals: WorkTotals,
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    /// Health of the server
+    status: &'static str,
+}
+
+/// GET /healthz
+///
+/// Health check for orchestrators, responds with a 503 when running
+/// in degraded mode or while maintenance is draining the server
+async fn healthz(Extension(office): Extension<OfficeHandle>) -> (StatusCode, Json<HealthResponse>) {
+    if office.in_maintenance() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(HealthResponse {
+                status: "maintenance",
+            }),
+        );
+    }
+
+    (StatusCode::OK, Json(HealthResponse { status: "ok" }))
+}
+
+/// GET /metrics
+///
+/// Telemetry of the converter in the prometheus text format
+async fn metrics(Extension(office): Extension<OfficeHandle>) -> Response<Body> {
+    let mut response = Response::new(Body::from(metrics::render(&office)));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    response
+}
+
+/// GET /status
+///
+/// Checks if the converter is currently busy, along with the detailed
+/// state of each worker and totals of the work processed
+async fn status(
+    Extension(office): Extension<OfficeHandle>,
+    Query(query): Query<StatusQuery>,
+) -> Result<Json<StatusResponse>, DynHttpError> {
+    let wait = match query.wait.as_deref() {
+        Some(value) => parse_wait_duration(value).ok_or(StatusRequestError::InvalidWait)?,
+        None => Duration::ZERO,
+    };
+
+    let is_busy = office.wait_until_available(wait).await;
+    let state = office.state();
+
+    Ok(Json(StatusResponse {
+        is_busy,
+        warm: state.warm,
+        ready: office.is_ready(),
+        queue_depth: state.queue_depth,
+        workers: office.workers(),
+        totals: office.totals(),
+    }))
+}
+
+/// GET /ws/state
+///
+/// WebSocket pushing the runner state (busy, converting, queue depth)
+/// whenever it changes, the current state is sent on connect
+async fn ws_state(
+    Extension(office): Extension<OfficeHandle>,
+    upgrade: WebSocketUpgrade,
+) -> Response<Body> {
+    upgrade.on_upgrade(move |socket| push_state(office, socket))
+}
+
+/// Pushes state transitions of the runner to the provided `socket` until
+/// the socket is closed
+async fn push_state(office: OfficeHandle, mut socket: WebSocket) {
+    let mut converting = office.converting.clone();
+    let mut waiting = office.waiting.subscribe();
+
+    let mut last_state: Option<OfficeState> = None;
+
+    loop {
+        let state = office.state();
+
+        // Only transitions are sent
+        if last_state != Some(state) {
+            let message = match serde_json::to_string(&state) {
+                Ok(value) => value,
+                Err(cause) => {
+                    error!(?cause, "failed to serialize state");
+                    return;
+                }
+            };
+
+            if socket.send(Message::Text(message)).await.is_err() {
+                return;
+            }
+
+            last_state = Some(state);
+        }
+
+        tokio::select! {
+            // Progress is polled while converting
+            _ = tokio::time::sleep(PROGRESS_INTERVAL), if state.converting => {}
+            result = converting.changed() => {
+                if result.is_err() {
+                    return;
+                }
+            }
+            result = waiting.changed() => {
+                if result.is_err() {
+                    return;
+                }
+            }
+            message = socket.recv() => match message {
+                // Incoming messages are ignored
+                Some(Ok(_)) => {}
+                // Socket closed
+                Some(Err(_)) | None => return,
+            }
+        }
+    }
+}
+
+/// Interval the state is checked for progress changes while converting
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Query parameters for the status endpoint
+#[derive(Deserialize)]
+struct StatusQuery {
+    /// Duration to wait for the converter to become available when busy
+    /// (i.e "30s", "500ms", or a number of seconds)
+    wait: Option<String>,
+}
+
+/// Maximum duration a status request is allowed to wait for
+const MAX_STATUS_WAIT: Duration = Duration::from_secs(60);
+
+/// Errors from invalid status requests
+#[derive(Debug, Error)]
+enum StatusRequestError {
+    /// Provided wait duration could not be parsed
+    #[error("invalid wait duration, expected a duration like 30s or 500ms")]
+    InvalidWait,
+}
+
+impl HttpError for StatusRequestError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("invalid_wait")
+    }
+}
+
+/// Parses a wait duration, durations are capped at [MAX_STATUS_WAIT]
+fn parse_wait_duration(value: &str) -> Option<Duration> {
+    parse_duration(value).map(|duration| duration.min(MAX_STATUS_WAIT))
+}
+
+#[derive(Serialize)]
+struct VersionResponse {
+    /// Major version of LibreOffice
+    major: u32,
+    /// Minor version of LibreOffice
+    minor: u32,
+    /// Libreoffice "Build ID"
+    build_id: String,
+}
+
+/// GET /office-version
+///
+/// Checks if the converter is currently busy
+async fn office_version(
+    Extension(details): Extension<SharedDetails>,
+) -> Result<Json<VersionResponse>, StatusCode> {
+    let details = details.load();
+    let version = details.version.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let product_version = &version.product_version;
+
+    Ok(Json(VersionResponse {
+        build_id: version.build_id.clone(),
+        major: product_version.major,
+        minor: product_version.minor,
+    }))
+}
+
+/// Build details of the server
+#[derive(Serialize)]
+pub(crate) struct ServerVersionResponse {
+    /// Version of the server
+    version: &'static str,
+    /// Git commit the server was built from
+    git_commit: &'static str,
+    /// Time the server was built at (RFC 3339, UTC)
+    build_timestamp: &'static str,
+}
+
+/// GET /version
+///
+/// Reports the build of the server itself, separate from the office
+/// version reported by "/office-version"
+pub(crate) async fn server_version() -> Json<ServerVersionResponse> {
+    Json(ServerVersionResponse {
+        version: version::VERSION,
+        git_commit: version::GIT_COMMIT,
+        build_timestamp: version::BUILD_TIMESTAMP,
+    })
+}
+
+#[derive(Serialize)]
+struct SupportedFormat {
+    /// Name of the file format
+    name: String,
+    /// Mime type of the format
+    mime: String,
+}
+
+/// Query parameters for the supported formats endpoint
+#[derive(Deserialize)]
+struct SupportedFormatsQuery {
+    /// Group the formats by document family
+    #[serde(default)]
+    grouped: bool,
+}
+
+/// GET /supported-formats
+///
+/// Provides an array of supported file formats, or the formats grouped by
+/// document family when requested
+async fn supported_formats(
+    Extension(details): Extension<SharedDetails>,
+    Query(query): Query<SupportedFormatsQuery>,
+) -> Result<Response<Body>, StatusCode> {
+    let details = details.load();
+    let types = details.filter_types.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+
+    if query.grouped {
+        return Ok(Json(formats::group(types)).into_response());
+    }
+
+    let formats: Vec<SupportedFormat> = types
+        .values
+        .iter()
+        .map(|(key, value)| SupportedFormat {
+            name: key.to_string(),
+            mime: value.media_type.to_string(),
+        })
+        .collect();
+
+    Ok(Json(formats).into_response())
+}
+
+/// Details after a refresh
+#[derive(Serialize)]
+struct RefreshDetailsResponse {
+    /// Version of LibreOffice, when known
+    version: Option<VersionResponse>,
+    /// Number of supported file formats
+    format_count: usize,
+}
+
+/// POST /admin/refresh-details
+///
+/// Re-queries the version and supported formats from the office instance,
+/// reflecting an office upgraded since the server started
+async fn admin_refresh_details(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(details): Extension<SharedDetails>,
+    headers: HeaderMap,
+) -> Result<Json<RefreshDetailsResponse>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    office.refresh_details(&details).await?;
+
+    let details = details.load();
+    let version = details.version.as_ref().map(|version| VersionResponse {
+        build_id: version.build_id.clone(),
+        major: version.product_version.major,
+        minor: version.product_version.minor,
+    });
+    let format_count = details
+        .filter_types
+        .as_ref()
+        .map(|types| types.values.len())
+        .unwrap_or_default();
+
+    Ok(Json(RefreshDetailsResponse {
+        version,
+        format_count,
+    }))
+}
+
+/// Deployment report of the server
+#[derive(Serialize)]
+struct InfoResponse {
+    /// Version of the server
+    version: &'static str,
+    /// Backend used to perform conversions
+    backend: Option<String>,
+    /// Path to the office install
+    office_path: Option<std::path::PathBuf>,
+    /// Directory of the office user profile, [None] for the default profile
+    profile_dir: Option<std::path::PathBuf>,
+    /// Directory documents are written to while converting
+    work_dir: std::path::PathBuf,
+    /// Directory converted files are stored in, [None] when storing is disabled
+    output_dir: Option<std::path::PathBuf>,
+    /// Number of conversion workers
+    workers: usize,
+    /// Optional features and whether they are enabled
+    features: InfoFeatures,
+    /// Configured limits, [None] when a limit is disabled
+    limits: InfoLimits,
+}
+
+#[derive(Serialize)]
+struct InfoFeatures {
+    /// Admin token authentication
+    auth: bool,
+    /// Admin macros
+    macros: bool,
+    /// Result caching
+    cache: bool,
+    /// Zstd compression of cached results
+    cache_compression: bool,
+    /// Standard PDF fonts embedded by default
+    embed_standard_fonts: bool,
+    /// Inputs of failed conversions are quarantined
+    quarantine: bool,
+    /// Background conversion jobs
+    jobs: bool,
+}
+
+#[derive(Serialize)]
+struct InfoLimits {
+    max_body_size: usize,
+    min_upload_size: Option<usize>,
+    spill_threshold: Option<usize>,
+    io_concurrency: Option<usize>,
+    max_embedded_depth: Option<u32>,
+    max_embedded_images: Option<u64>,
+    max_queue_wait_ms: Option<u128>,
+    queue_capacity: usize,
+    hang_timeout_ms: Option<u128>,
+    convert_timeout_ms: Option<u128>,
+    recover_after_failures: Option<u64>,
+    memory_pause_threshold: Option<u64>,
+    idle_shutdown_ms: Option<u128>,
+    output_retention_ms: Option<u128>,
+    job_retention_ms: Option<u128>,
+    job_status_retention_ms: Option<u128>,
+    throttle_windows: Vec<ThrottleWindow>,
+    queue_limits: QueueLimits,
+}
+
+/// GET /admin/info
+///
+/// Reports how the server was deployed (install paths, enabled features
+/// and configured limits) for auditing a fleet of servers
+async fn admin_info(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(result_cache): Extension<Option<Arc<ResultCache>>>,
+    headers: HeaderMap,
+) -> Result<Json<InfoResponse>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    let info = &config.info;
+
+    Ok(Json(InfoResponse {
+        version: version::VERSION,
+        backend: info
+            .backend
+            .to_possible_value()
+            .map(|value| value.get_name().to_string()),
+        office_path: info.office_path.clone(),
+        profile_dir: info.profile_dir.clone(),
+        work_dir: config.work_dir.clone(),
+        output_dir: config.output_dir.clone(),
+        workers: office.workers().len(),
+        features: InfoFeatures {
+            auth: config.admin_token.is_some(),
+            macros: config.allow_macros,
+            cache: result_cache.is_some(),
+            cache_compression: result_cache
+                .as_ref()
+                .is_some_and(|cache| cache.is_compressed()),
+            embed_standard_fonts: config.embed_standard_fonts,
+            quarantine: config.quarantine.is_some(),
+            jobs: config.jobs.is_some(),
+        },
+        limits: InfoLimits {
+            max_body_size: MAX_BODY_SIZE,
+            min_upload_size: config.min_upload_size,
+            spill_threshold: config.spill_threshold,
+            io_concurrency: info.io_concurrency,
+            max_embedded_depth: config.embedded_limits.max_depth,
+            max_embedded_images: config.embedded_limits.max_images,
+            max_queue_wait_ms: info.max_queue_wait.map(|value| value.as_millis()),
+            queue_capacity: office.queue_capacity(),
+            hang_timeout_ms: info.hang_timeout.map(|value| value.as_millis()),
+            convert_timeout_ms: info.convert_timeout.map(|value| value.as_millis()),
+            recover_after_failures: info.recover_after_failures,
+            memory_pause_threshold: info.memory_pause_threshold,
+            idle_shutdown_ms: info.idle_shutdown.map(|value| value.as_millis()),
+            output_retention_ms: info.output_retention.map(|value| value.as_millis()),
+            job_retention_ms: info.job_retention.map(|value| value.as_millis()),
+            job_status_retention_ms: info.job_status_retention.map(|value| value.as_millis()),
+            throttle_windows: info.throttle_windows.clone(),
+            queue_limits: office.limits(),
+        },
+    }))
+}
+
+/// Quarantined conversion failures
+#[derive(Serialize)]
+struct QuarantineListResponse {
+    /// Quarantined entries, newest first
+    entries: Vec<QuarantineEntry>,
+}
+
+/// GET /admin/quarantine
+///
+/// Lists the inputs quarantined after failing to convert
+async fn admin_quarantine_list(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    headers: HeaderMap,
+) -> Result<Json<QuarantineListResponse>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    let quarantine = config
+        .quarantine
+        .as_ref()
+        .ok_or(AdminError::QuarantineDisabled)?;
+    let entries = quarantine.list().await?;
+
+    Ok(Json(QuarantineListResponse { entries }))
+}
+
+/// GET /admin/quarantine/:id
+///
+/// Downloads the input of a quarantined conversion failure
+async fn admin_quarantine_input(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    let quarantine = config
+        .quarantine
+        .as_ref()
+        .ok_or(AdminError::QuarantineDisabled)?;
+
+    let Some((entry, input)) = quarantine.get(&id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    // Downloaded with the name and extension it was uploaded with
+    let file_name = entry.file_name.unwrap_or_else(|| entry.id.clone());
+    let extension = file_name
+        .rsplit_once('.')
+        .map_or("bin", |(_, extension)| extension);
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(CONTENT_SHA256, content_sha256(&input))
+        .body(Body::from(input))
+        .context("failed to create response")?;
+
+    if let Some(value) = Disposition::Attachment.header_value(Some(&file_name), extension) {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_DISPOSITION, value);
+    }
+
+    Ok(response)
+}
+
+/// Query parameters for the reproduce bundle endpoint
+#[derive(Deserialize)]
+struct BundleQuery {
+    /// Whether the quarantined input is included, when false only its
+    /// digest is included for inputs that cannot be shared
+    input: Option<bool>,
+}
+
+/// Manifest of a reproduce bundle
+#[derive(Serialize)]
+struct BundleManifest<'a> {
+    /// Version of the server
+    server_version: &'static str,
+    /// Git commit the server was built from
+    git_commit: &'static str,
+    /// Version of the running office
+    office_version: Option<VersionResponse>,
+    /// SHA-256 hex digest of the input
+    input_sha256: String,
+    /// Path of the input within the bundle, [None] when excluded
+    input_path: Option<&'a str>,
+    /// The quarantined failure
+    failure: &'a QuarantineEntry,
+}
+
+/// GET /admin/quarantine/:id/bundle
+///
+/// Downloads a reproduce bundle for a quarantined conversion failure, a zip
+/// archive of the input, the options, the office callbacks, timings and
+/// versions for attaching to bug reports
+async fn admin_quarantine_bundle(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(details): Extension<SharedDetails>,
+    Path(id): Path<String>,
+    Query(query): Query<BundleQuery>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    let quarantine = config
+        .quarantine
+        .as_ref()
+        .ok_or(AdminError::QuarantineDisabled)?;
+
+    let Some((entry, input)) = quarantine.get(&id).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    let input_path = query.input.unwrap_or(true).then(|| {
+        let extension = entry
+            .file_name
+            .as_deref()
+            .and_then(|name| name.rsplit_once('.'))
+            .map_or("bin", |(_, extension)| extension);
+
+        format!("input.{extension}")
+    });
+
+    let office_version = details
+        .load()
+        .version
+        .as_ref()
+        .map(|version| VersionResponse {
+            build_id: version.build_id.clone(),
+            major: version.product_version.major,
+            minor: version.product_version.minor,
+        });
+
+    let manifest = BundleManifest {
+        server_version: version::VERSION,
+        git_commit: version::GIT_COMMIT,
+        office_version,
+        input_sha256: content_sha256(&input),
+        input_path: input_path.as_deref(),
+        failure: &entry,
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest).context("failed to serialize manifest")?;
+
+    let mut archive = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let mut files = vec![("manifest.json".to_string(), manifest)];
+    if let Some(input_path) = input_path {
+        files.push((input_path, input));
+    }
+
+    for (name, bytes) in files {
+        archive
+            .start_file(name, SimpleFileOptions::default())
+            .context("failed to create zip entry")?;
+        archive
+            .write_all(&bytes)
+            .context("failed to write zip entry")?;
+    }
+
+    let archive = archive.finish().context("failed to finish zip")?;
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, "application/zip")
+        .body(Body::from(archive.into_inner()))
+        .context("failed to create response")?;
+
+    let file_name = format!("lo_native_repro_{}", entry.id);
+    if let Some(value) = Disposition::Attachment.header_value(Some(&file_name), "zip") {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_DISPOSITION, value);
+    }
+
+    Ok(response)
+}
+
+/// GET /.well-known/jwks.json
+///
+/// Provides the public key results are signed with as a JSON Web Key Set
+async fn signing_keys(
+    Extension(config): Extension<Arc<ServerConfig>>,
+) -> Result<Json<serde_json::Value>, DynHttpError> {
+    let key = config.signing_key.as_ref().ok_or(SigningDisabledError)?;
+
+    Ok(Json(key.jwks()))
+}
+
+/// Error when the signing key was requested without result signing enabled
+#[derive(Debug, Error)]
+#[error("result signing is not enabled on this server")]
+struct SigningDisabledError;
+
+impl HttpError for SigningDisabledError {
+    fn status(&self) -> StatusCode {
+        StatusCode::NOT_FOUND
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("signing_disabled")
+    }
+}
+
+/// POST /collect-garbage
+///
+/// Collects garbage from the office converter
+async fn collect_garbage(Extension(office): Extension<OfficeHandle>) -> StatusCode {
+    _ = office.collect_garbage().await;
+    StatusCode::OK
+}
+
+#[derive(Serialize)]
+struct MemoryResponse {
+    /// Memory of the server process (Including office)
+    process: ProcessMemory,
+    /// Memory of the container (or system when not limited)
+    container: Option<ContainerMemory>,
+    /// Temporary files in the work directory
+    work_dir: TempUsage,
+    /// Size of the result cache, [None] when caching is disabled
+    cache: Option<CacheUsage>,
+    /// Effect of the last memory trim, [None] before the first trim
+    last_trim: Option<TrimEffect>,
+}
+
+#[derive(Serialize)]
+struct ProcessMemory {
+    rss_bytes: Option<u64>,
+    peak_rss_bytes: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ContainerMemory {
+    used_bytes: u64,
+    limit_bytes: u64,
+    percent: u64,
+}
+
+#[derive(Serialize)]
+struct CacheUsage {
+    entries: u64,
+    size_bytes: u64,
+}
+
+/// GET /admin/memory
+///
+/// Reports the memory used by the server and office, the disk used by
+/// temporary files and the cache, and the effect of the last memory trim
+/// so the trim and recycling thresholds can be tuned from real data
+async fn admin_memory(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Extension(result_cache): Extension<Option<Arc<ResultCache>>>,
+    headers: HeaderMap,
+) -> Result<Json<MemoryResponse>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    let work_dir = config.work_dir.clone();
+    let work_dir = tokio::task::spawn_blocking(move || tempfiles::usage(&work_dir))
+        .await
+        .context("failed to measure work directory")?
+        .context("failed to read work directory")?;
+
+    let cache = match result_cache.as_ref() {
+        Some(cache) => {
+            let stats = cache.stats().await?;
+            Some(CacheUsage {
+                entries: stats.entries,
+                size_bytes: stats.size_bytes,
+            })
+        }
+        None => None,
+    };
+
+    Ok(Json(MemoryResponse {
+        process: ProcessMemory {
+            rss_bytes: memory::process_rss(),
+            peak_rss_bytes: memory::process_peak_rss(),
+        },
+        container: memory::current().map(|usage| ContainerMemory {
+            used_bytes: usage.used,
+            limit_bytes: usage.limit,
+            percent: usage.percent(),
+        }),
+        work_dir,
+        cache,
+        last_trim: office.last_trim(),
+    }))
+}
+
+/// GET /admin/queue
+///
+/// Reports the background jobs waiting for or holding the conversion slot
+/// along with estimates of when they start, for external schedulers to make
+/// routing decisions or cancel stale work
+async fn admin_queue(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    headers: HeaderMap,
+) -> Result<Json<QueueSnapshot>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    let jobs = config.jobs.as_ref().ok_or(JobError::Disabled)?;
+
+    Ok(Json(jobs.queue()))
+}
+
+/// Diagnostics enabled at runtime
+#[derive(Serialize)]
+struct DiagnosticsResponse {
+    /// Whether debug logging is enabled
+    debug_logging: bool,
+    /// Whether the payloads of office callbacks are logged in full
+    dump_callbacks: bool,
+}
+
+impl DiagnosticsResponse {
+    fn new(office: &OfficeHandle, config: &ServerConfig) -> Self {
+        Self {
+            debug_logging: config
+                .log_toggle
+                .as_ref()
+                .is_some_and(|toggle| toggle.is_enabled()),
+            dump_callbacks: office.activity().dumps_callbacks(),
+        }
+    }
+}
+
+/// Query parameters for changing the diagnostics, diagnostics that are
+/// not provided are left as they are
+#[derive(Deserialize)]
+struct DiagnosticsQuery {
+    debug_logging: Option<bool>,
+    dump_callbacks: Option<bool>,
+}
+
+/// GET /admin/diagnostics
+///
+/// Reports the diagnostics enabled at runtime
+async fn admin_diagnostics(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    headers: HeaderMap,
+) -> Result<Json<DiagnosticsResponse>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    Ok(Json(DiagnosticsResponse::new(&office, &config)))
+}
+
+/// POST /admin/diagnostics
+///
+/// Enables or disables debug logging and the logging of office callback
+/// payloads, so production issues can be investigated without restarting
+async fn admin_set_diagnostics(
+    Extension(office): Extension<OfficeHandle>,
+    Extension(config): Extension<Arc<ServerConfig>>,
+    Query(query): Query<DiagnosticsQuery>,
+    headers: HeaderMap,
+) -> Result<Json<DiagnosticsResponse>, DynHttpError> {
+    config.require_admin(&headers)?;
+
+    if let Some(debug_logging) = query.debug_logging {
+        let toggle = config
+            .log_toggle
+            .as_ref()
+            .ok_or(AdminError::LogToggleUnavailable)?;
+        toggle.set(debug_logging)?;
+        info!(debug_logging, "debug logging changed");
+    }
+
+    if let Some(dump_callbacks) = query.dump_callbacks {
+        office.activity().set_dump_callbacks(dump_callbacks);
+        info!(dump_callbacks, "office callback dumping changed");
+    }
+
+    Ok(Json(DiagnosticsResponse::new(&office, &config)))
+}
+
+/// Converts an error from the runner into a [DynHttpError] preserving
+/// the known [ConvertError] causes
+fn runner_error(err: anyhow::Error) -> DynHttpError {
+    match err.downcast::<ConvertError>() {
+        Ok(err) => err.into(),
+        Err(err) => err.into(),
+    }
+}
+
+/// Header used to correlate requests between clients and the server
+const REQUEST_ID: &str = "x-request-id";
+
+/// Middleware providing every request with a request ID, using the ID provided
+/// in the "X-Request-Id" header when present. Logs for the request (Including
+/// logs from the runner) include the ID and it is echoed in the response
+async fn request_id(request: Request, next: Next) -> Response<Body> {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty() && value.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(random_id);
+
+    let span = info_span!(
+        "request",
+        %request_id,
+        client_ip = field::Empty,
+        method = %request.method(),
+        path = %request.uri().path()
+    );
+
+    if let Some(ClientIp(client_ip)) = request.extensions().get() {
+        span.record("client_ip", field::display(client_ip));
+    }
+
+    let mut response = next.run(request).instrument(span).await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID, value);
+    }
+
+    response
+}
+
+/// Address of the client a request was received from, the address provided
+/// by a trusted proxy for forwarded requests
+#[derive(Debug, Clone, Copy)]
+struct ClientIp(IpAddr);
+
+/// Middleware determining the client address of requests and the origin that
+/// clients used to reach the server from the headers of trusted proxies
+async fn forwarded(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    mut request: Request,
+    next: Next,
+) -> Response<Body> {
+    // Servers started without connection info don't know the peer address
+    let Some(ConnectInfo(peer)) = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .copied()
+    else {
+        return next.run(request).await;
+    };
+
+    let proxies = &config.trusted_proxies;
+    let client_ip = proxies.client_ip(peer.ip(), request.headers());
+    let origin = proxies.public_origin(peer.ip(), request.headers());
+
+    request.extensions_mut().insert(ClientIp(client_ip));
+    proxy::scope(origin, next.run(request)).await
+}
+
+/// Middleware running requests with the priority tier of their API key
+async fn priority_tier(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let tier = config.priority_tier(request.headers());
+    priority::scope(tier, next.run(request)).await
+}
+
+/// Checks if the endpoint at `path` is available without an API key, health
+/// checks and scrapes don't have a key and pre-signed URLs carry their own
+/// signature
+fn is_public_path(path: &str) -> bool {
+    matches!(path, "/healthz" | "/metrics" | "/.well-known/jwks.json")
+        || path.starts_with("/outputs/")
+}
+
+/// Checks if the request is a conversion, conversions are admitted by the
+/// limits of their API key
+fn is_conversion(request: &Request) -> bool {
+    request.method() == Method::POST
+        && matches!(
+            request.uri().path(),
+            "/convert"
+                | "/convert-batch"
+                | "/convert-redact"
+                | "/pipeline"
+                | "/extract-assets"
+                | "/stats-extract"
+                | "/jobs"
+        )
+}
+
+/// Middleware rejecting requests without a known API key when API keys are
+/// configured, conversions are also rejected when their key is over its
+/// limits. Without API keys conversions are limited by client address. Admins
+/// are not limited.
+///
+/// When an external authorizer is configured every other request must be
+/// allowed by the authorizer
+async fn api_key_auth(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    request: Request,
+    next: Next,
+) -> Result<Response<Body>, DynHttpError> {
+    if is_public_path(request.uri().path()) || config.is_admin(request.headers()) {
+        return Ok(next.run(request).await);
+    }
+
+    let client_ip = request.extensions().get::<ClientIp>().copied();
+
+    if let Some(authorizer) = config.authorizer.as_ref() {
+        authorizer
+            .authorize(
+                request.method(),
+                request
+                    .uri()
+                    .path_and_query()
+                    .map_or("/", |uri| uri.as_str()),
+                request.headers(),
+                client_ip.map(|ClientIp(client_ip)| client_ip),
+            )
+            .await?;
+    }
+
+    if let Some(api_keys) = config.api_keys.as_ref() {
+        let key = api_keys.authenticate(request.headers())?;
+
+        let admission = match is_conversion(&request) {
+            true => Some(api_keys.admit(key)?),
+            false => None,
+        };
+
+        return Ok(run_admitted(request, next, admission).await);
+    }
+
+    let admission = match (config.client_limits.as_ref(), client_ip) {
+        (Some(client_limits), Some(ClientIp(client_ip))) if is_conversion(&request) => {
+            Some(client_limits.admit(client_ip)?)
+        }
+        _ => None,
+    };
+
+    Ok(run_admitted(request, next, admission).await)
+}
+
+/// Runs the `request` holding its `admission`, the conversion counts against
+/// its limits until the response is created unless the handler took the
+/// admission to hold it for longer (See [SharedAdmission])
+async fn run_admitted(
+    mut request: Request,
+    next: Next,
+    admission: Option<Admission>,
+) -> Response<Body> {
+    let admission = SharedAdmission::new(admission);
+    request.extensions_mut().insert(admission.clone());
+
+    next.run(request).await
+}
+
+/// Middleware writing the uploads of requests to the server work directory
+async fn upload_dir(
+    Extension(config): Extension<Arc<ServerConfig>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    upload::scope(config.work_dir.clone(), next.run(request)).await
+}
+
+/// Header containing the SHA-256 hex digest of the converted output
+pub(crate) const CONTENT_SHA256: &str = "x-content-sha256";
+
+/// Creates the SHA-256 hex digest of the provided `bytes`
+pub(crate) fn content_sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Maximum size of request bodies
+const MAX_BODY_SIZE: usize = 1024 * 1024 * 1024;
+
+/// Provides the endpoints of the server by the path they are routed at
+fn routes() -> Vec<(&'static str, MethodRouter)> {
+    vec![
+        ("/status", get(status)),
+        ("/healthz", get(healthz)),
+        ("/metrics", get(metrics)),
+        ("/ws/state", get(ws_state)),
+        ("/version", get(server_version)),
+        ("/office-version", get(office_version)),
+        ("/supported-formats", get(supported_formats)),
+        ("/convert", post(convert)),
+        ("/convert-batch", post(convert_batch_request)),
+        ("/convert-redact", post(convert_redact)),
+        ("/pipeline", post(run_pipeline)),
+        ("/extract-assets", post(extract_assets)),
+        ("/stats-extract", post(stats_extract)),
+        ("/jobs", post(create_job)),
+        ("/jobs/:id", get(job_status)),
+        ("/jobs/:id/events", get(job_events)),
+        (
+            "/jobs/:id/result",
+            get(job_result).delete(delete_job_result),
+        ),
+        ("/jobs/:id/pages/:n", get(job_page)),
+        ("/results/:hash", get(cached_result)),
+        ("/.well-known/jwks.json", get(signing_keys)),
+        (
+            "/outputs/:name",
+            get(presigned_output).delete(delete_output),
+        ),
+        (
+            "/admin/cache",
+            get(admin_cache_stats).delete(admin_cache_purge),
+        ),
+        ("/admin/cache/:hash", delete(admin_cache_evict)),
+        ("/admin/quarantine", get(admin_quarantine_list)),
+        ("/admin/quarantine/:id", get(admin_quarantine_input)),
+        ("/admin/quarantine/:id/bundle", get(admin_quarantine_bundle)),
+        ("/admin/refresh-details", post(admin_refresh_details)),
+        ("/admin/info", get(admin_info)),
+        ("/admin/memory", get(admin_memory)),
+        ("/admin/queue", get(admin_queue)),
+        (
+            "/admin/diagnostics",
+            get(admin_diagnostics).post(admin_set_diagnostics),
+        ),
+        ("/collect-garbage", post(collect_garbage)),
+    ]
+}
+
+/// Converts a routed `path` into the form endpoints are documented and
+/// disabled with (i.e "/jobs/:id" becomes "/jobs/{id}")
+fn endpoint_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(name) => format!("{{{name}}}"),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Provides the paths of every endpoint of the server (i.e "/jobs/{id}")
+pub fn endpoints() -> Vec<String> {
+    routes()
+        .into_iter()
+        .map(|(path, _)| endpoint_path(path))
+        .collect()
+}
+
+/// Creates the router for the server using the runner `office_handle`,
+/// endpoints disabled by the `server_config` are not routed
+pub fn router(
+    office_handle: OfficeHandle,
+    office_details: SharedDetails,
+    server_config: ServerConfig,
+    result_cache: Option<Arc<ResultCache>>,
+) -> Router {
+    let mut router = Router::new();
+
+    for (path, route) in routes() {
+        if !server_config.is_endpoint_disabled(&endpoint_path(path)) {
+            router = router.route(path, route);
+        }
+    }
+
+    router
+        .layer(middleware::from_fn(upload_dir))
+        .layer(middleware::from_fn(priority_tier))
+        .layer(middleware::from_fn(api_key_auth))
+        .layer(middleware::from_fn(request_id))
+        .layer(middleware::from_fn(forwarded))
+        .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
+        .layer(Extension(office_handle))
+        .layer(Extension(office_details))
+        .layer(Extension(Arc::new(server_config)))
+        .layer(Extension(result_cache))
+}
@@ -0,0 +1,151 @@
+use super::routes::{content_sha256, OutputNaming};
+use crate::{
+    convert::{self, ConvertWarning, ConvertedDocument},
+    error::DynHttpError,
+    filename,
+    presign::UrlSigner,
+    proxy,
+    signing::ResultSigner,
+    tempfiles::random_id,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::path::Path;
+
+/// Converted file written to the output directory
+#[derive(Serialize)]
+pub struct StoredOutput {
+    /// Path of the file relative to the output directory
+    path: String,
+    /// Mime type of the converted file
+    content_type: &'static str,
+    /// Size of the converted file in bytes
+    size: usize,
+    /// SHA-256 hex digest of the converted file
+    sha256: String,
+    /// Non-fatal issues observed while converting
+    warnings: Vec<ConvertWarning>,
+    /// JWS signing the converted file when result signing is enabled
+    signature: Option<String>,
+    /// Expiring pre-signed URL to download the file when enabled
+    url: Option<String>,
+}
+
+/// Report for a batch of files written to the output directory
+#[derive(Serialize)]
+pub struct StoredBatch {
+    /// Number of files that were converted and stored
+    succeeded: usize,
+    /// Number of files that failed to convert
+    failed: usize,
+    /// Outcome of each file in upload order
+    files: Vec<StoredBatchEntry>,
+}
+
+/// Outcome of a single file in the [StoredBatch]
+#[derive(Serialize)]
+pub struct StoredBatchEntry {
+    /// File name the file was uploaded with
+    input: Option<String>,
+    /// Stored output when the file was converted
+    #[serde(flatten)]
+    output: Option<StoredOutput>,
+    /// Error code when the file failed to convert
+    error_code: Option<&'static str>,
+    /// Error reason when the file failed to convert
+    error: Option<String>,
+}
+
+impl StoredBatchEntry {
+    pub fn new(input: Option<String>, result: Result<StoredOutput, DynHttpError>) -> Self {
+        match result {
+            Ok(output) => Self {
+                input,
+                output: Some(output),
+                error_code: None,
+                error: None,
+            },
+            Err(err) => {
+                err.log();
+
+                let raw = err.to_raw();
+                Self {
+                    input,
+                    output: None,
+                    error_code: raw.code,
+                    error: Some(raw.reason),
+                }
+            }
+        }
+    }
+}
+
+impl StoredBatch {
+    pub fn new(files: Vec<StoredBatchEntry>) -> Self {
+        let failed = files.iter().filter(|file| file.output.is_none()).count();
+
+        Self {
+            succeeded: files.len() - failed,
+            failed,
+            files,
+        }
+    }
+}
+
+/// Writes the `converted` file to the `output_dir` named after the uploaded
+/// `file_name` or its digest for [OutputNaming::Sha256]. The file is written
+/// under a temporary name and renamed once complete so the next stage never
+/// reads a partially written file
+pub async fn store_output(
+    output_dir: &Path,
+    file_name: Option<&str>,
+    naming: OutputNaming,
+    converted: ConvertedDocument,
+    signer: Option<&ResultSigner<'_>>,
+    url_signer: Option<&UrlSigner>,
+) -> anyhow::Result<StoredOutput> {
+    let sha256 = content_sha256(&converted.bytes);
+    let extension = convert::output_extension(converted.content_type);
+
+    let name = match naming {
+        OutputNaming::Name => {
+            let stem = file_name
+                .and_then(filename::stem)
+                .unwrap_or_else(|| "output".to_string());
+
+            // Random suffix prevents uploads with the same name replacing each other
+            format!("{stem}-{}.{extension}", random_id())
+        }
+        // Identical outputs share a name, re-running replaces the file with the same contents
+        OutputNaming::Sha256 => format!("{sha256}.{extension}"),
+    };
+
+    // Concurrent writes of the same output use their own partial file
+    let partial = output_dir.join(format!(".{name}.{}.partial", random_id()));
+    let path = output_dir.join(&name);
+
+    tokio::fs::write(&partial, &converted.bytes)
+        .await
+        .context("failed to write output")?;
+
+    if let Err(err) = tokio::fs::rename(&partial, &path).await {
+        _ = tokio::fs::remove_file(&partial).await;
+        return Err(err).context("failed to move output into place");
+    }
+
+    let signature = signer
+        .map(|signer| signer.sign(&sha256, converted.content_type))
+        .transpose()?;
+
+    let url = url_signer.map(|url_signer| proxy::public_url(url_signer.sign(&name)));
+
+    Ok(StoredOutput {
+        url,
+        path: name,
+        content_type: converted.content_type,
+        size: converted.bytes.len(),
+        sha256,
+        warnings: converted.warnings,
+        signature,
+    })
+}
@@ -0,0 +1,221 @@
+use crate::{
+    cache::ResultCache,
+    config::ServerConfig,
+    convert::DocumentInput,
+    error::DynHttpError,
+    mhtml,
+    runner::{OfficeHandle, UploadSizeError},
+    tempfiles::{random_id, TempFile},
+};
+use anyhow::Context;
+use axum::async_trait;
+use axum_typed_multipart::{FieldMetadata, TryFromChunks, TypedMultipartError};
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use sha2::{Digest, Sha256};
+use std::{
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncWriteExt, ReadBuf},
+};
+
+tokio::task_local! {
+    /// Directory uploads of the request being handled are written to
+    static UPLOAD_DIR: PathBuf;
+}
+
+/// Runs the `future` writing the uploads it receives to `dir`
+pub(crate) async fn scope<F: Future>(dir: PathBuf, future: F) -> F::Output {
+    UPLOAD_DIR.scope(dir, future).await
+}
+
+/// Uploaded file streamed to disk as it is received, the file is hashed
+/// while it is written so it never has to be held in memory
+pub(crate) struct Upload {
+    /// File the upload was written to
+    file: TempFile,
+    /// Size of the upload in bytes
+    size: u64,
+    /// Hasher of the upload contents
+    hasher: Sha256,
+    /// First bytes of the upload for checks that only need the header
+    header: Vec<u8>,
+    /// Whether the upload only contained whitespace or null bytes
+    blank: bool,
+}
+
+impl Upload {
+    /// SHA-256 hex digest of the upload
+    pub(crate) fn sha256(&self) -> String {
+        format!("{:x}", self.hasher.clone().finalize())
+    }
+
+    /// Result cache key for converting the upload with the options
+    /// `fingerprint`, the same key [ResultCache::key] creates for the bytes
+    pub(crate) fn cache_key(&self, fingerprint: &str) -> String {
+        ResultCache::hasher_key(self.hasher.clone(), fingerprint)
+    }
+
+    /// Whether the upload may be a web archive, web archives are unpacked in
+    /// memory before converting
+    pub(crate) fn may_be_web_archive(&self) -> bool {
+        mhtml::may_be_web_archive(&self.header)
+    }
+
+    /// Checks the upload is large enough to contain a document
+    pub(crate) fn check_size(&self, min_size: Option<usize>) -> Result<(), UploadSizeError> {
+        if self.blank {
+            return Err(UploadSizeError::Empty);
+        }
+
+        let size = self.size as usize;
+        if let Some(min) = min_size.filter(|min| size < *min) {
+            return Err(UploadSizeError::TooSmall { size, min });
+        }
+
+        Ok(())
+    }
+
+    /// Reads the upload into memory for checks that need the whole file
+    pub(crate) async fn read(self) -> anyhow::Result<Bytes> {
+        let bytes = tokio::fs::read(&self.file.path)
+            .await
+            .context("failed to read upload")?;
+
+        Ok(Bytes::from(bytes))
+    }
+
+    /// Takes the file the upload was written to
+    pub(crate) fn into_file(self) -> TempFile {
+        self.file
+    }
+}
+
+#[async_trait]
+impl TryFromChunks for Upload {
+    async fn try_from_chunks(
+        mut chunks: impl Stream<Item = Result<Bytes, TypedMultipartError>> + Send + Sync + Unpin,
+        _: FieldMetadata,
+    ) -> Result<Self, TypedMultipartError> {
+        let dir = UPLOAD_DIR
+            .try_with(PathBuf::clone)
+            .unwrap_or_else(|_| std::env::temp_dir());
+
+        let file = TempFile {
+            path: dir.join(format!("lo_native_upload_{}", random_id())),
+        };
+
+        let mut output = File::create(&file.path)
+            .await
+            .context("failed to create upload file")
+            .map_err(|source| TypedMultipartError::Other { source })?;
+
+        let mut upload = Upload {
+            file,
+            size: 0,
+            hasher: Sha256::new(),
+            header: Vec::new(),
+            blank: true,
+        };
+
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+
+            output
+                .write_all(&chunk)
+                .await
+                .context("failed to write upload file")
+                .map_err(|source| TypedMultipartError::Other { source })?;
+
+            let remaining = mhtml::HEADER_SEARCH_LEN.saturating_sub(upload.header.len());
+            upload
+                .header
+                .extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+
+            upload.blank = upload.blank
+                && chunk
+                    .iter()
+                    .all(|byte| byte.is_ascii_whitespace() || *byte == 0);
+            upload.size += chunk.len() as u64;
+            upload.hasher.update(&chunk);
+        }
+
+        output
+            .flush()
+            .await
+            .context("failed to write upload file")
+            .map_err(|source| TypedMultipartError::Other { source })?;
+
+        Ok(upload)
+    }
+}
+
+/// Reader of a converted file streamed to the client, the file is removed
+/// once the response has been sent
+pub(crate) struct TempFileReader {
+    reader: File,
+    _file: TempFile,
+}
+
+impl TempFileReader {
+    pub(crate) async fn open(file: TempFile) -> anyhow::Result<Self> {
+        let reader = File::open(&file.path)
+            .await
+            .context("failed to open converted file")?;
+
+        Ok(Self {
+            reader,
+            _file: file,
+        })
+    }
+}
+
+impl AsyncRead for TempFileReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.reader).poll_read(cx, buf)
+    }
+}
+
+/// Contents of an upload to convert
+pub(crate) enum UploadContents {
+    /// Upload read into memory
+    Bytes(Bytes),
+    /// Upload left on disk as it was streamed
+    Streamed(Upload),
+}
+
+impl UploadContents {
+    /// Result cache key for converting the contents with the options
+    /// `fingerprint`
+    pub(crate) fn cache_key(&self, fingerprint: &str) -> String {
+        match self {
+            UploadContents::Bytes(bytes) => ResultCache::key(bytes, fingerprint),
+            UploadContents::Streamed(upload) => upload.cache_key(fingerprint),
+        }
+    }
+
+    /// Prepares the contents to be sent to the runner, streamed uploads are
+    /// sent as the file they were written to
+    pub(crate) async fn into_input(
+        self,
+        office: &OfficeHandle,
+        config: &ServerConfig,
+    ) -> Result<DocumentInput, DynHttpError> {
+        match self {
+            UploadContents::Bytes(bytes) => office.prepare_input(bytes, config).await,
+            UploadContents::Streamed(upload) => {
+                upload.check_size(config.min_upload_size)?;
+                Ok(DocumentInput::Spilled(upload.into_file()))
+            }
+        }
+    }
+}
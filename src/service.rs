@@ -0,0 +1,61 @@
+use crate::{
+    convert::{ConvertOptions, ConvertedDocument, DocumentInput},
+    runner::OfficeHandle,
+};
+use futures_util::future::BoxFuture;
+use std::task::{Context, Poll};
+use tower::Service;
+
+/// Document to convert through a [ConvertService]
+pub struct ConvertRequest {
+    /// Document to convert
+    pub input: DocumentInput,
+    /// Options the document is converted with
+    pub options: ConvertOptions,
+}
+
+impl ConvertRequest {
+    /// Creates a request converting the `input` document with `options`
+    pub fn new(input: DocumentInput, options: ConvertOptions) -> Self {
+        Self { input, options }
+    }
+}
+
+/// [Service] converting documents with the office runner, allowing tower
+/// middleware (timeouts, retries, rate limits, load shedding) to be composed
+/// around conversions by applications embedding the runner.
+///
+/// The service is always ready, requests wait in the runner queue (subject
+/// to its max queue wait) so limits should be applied with middleware
+#[derive(Clone)]
+pub struct ConvertService {
+    /// Handle to the runner performing the conversions
+    office: OfficeHandle,
+}
+
+impl ConvertService {
+    /// Creates a service converting with the runner behind `office`
+    pub fn new(office: OfficeHandle) -> Self {
+        Self { office }
+    }
+}
+
+impl Service<ConvertRequest> for ConvertService {
+    type Response = ConvertedDocument;
+    type Error = anyhow::Error;
+    type Future = BoxFuture<'static, Result<ConvertedDocument, anyhow::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ConvertRequest) -> Self::Future {
+        let office = self.office.clone();
+
+        Box::pin(async move {
+            office
+                .convert_document(request.input, request.options)
+                .await
+        })
+    }
+}
@@ -0,0 +1,191 @@
+use crate::error::HttpError;
+use axum::http::StatusCode;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Maximum length of a language tag
+const MAX_LANGUAGE_LEN: usize = 35;
+
+/// Settings that can be adjusted per request mapped to the PDF export
+/// filter data property they control
+const PDF_SETTINGS: &[(&str, &str)] = &[
+    ("export_bookmarks", "ExportBookmarks"),
+    ("export_form_fields", "ExportFormFields"),
+    ("export_hidden_slides", "ExportHiddenSlides"),
+    ("export_notes", "ExportNotes"),
+    ("single_page_sheets", "SinglePageSheets"),
+    ("skip_empty_pages", "IsSkipEmptyPages"),
+];
+
+/// PDF export filter data property embedding the 14 standard PDF fonts
+/// (Other fonts are always embedded)
+const EMBED_STANDARD_FONTS: &str = "EmbedStandardFonts";
+
+/// Rendering settings adjusted for a single conversion, only whitelisted
+/// settings that affect the document being converted are allowed so one
+/// tenant cannot change the rendering of another
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct RenderSettings {
+    /// Language (BCP 47 tag) to load the document with, changes locale
+    /// dependent defaults such as number formats and default fonts
+    pub language: Option<String>,
+
+    /// PDF export filter data properties and their values
+    pub pdf: BTreeMap<&'static str, bool>,
+}
+
+/// Errors from invalid request settings
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    /// Settings were not a JSON object
+    #[error("settings must be a JSON object")]
+    Malformed,
+
+    /// Setting is not one of the allowed settings
+    #[error("unknown setting {0}")]
+    Unknown(String),
+
+    /// Setting had a value of the wrong type or format
+    #[error("invalid value for setting {0}")]
+    InvalidValue(String),
+}
+
+impl HttpError for SettingsError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("invalid_setting")
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            SettingsError::Malformed => None,
+            SettingsError::Unknown(setting) | SettingsError::InvalidValue(setting) => {
+                Some(json!({ "setting": setting }))
+            }
+        }
+    }
+}
+
+impl RenderSettings {
+    /// Parses the settings from a JSON object of setting names and values
+    /// (i.e {"language": "ja-JP", "export_notes": true})
+    pub fn parse(value: &str) -> Result<Self, SettingsError> {
+        let settings: Map<String, Value> =
+            serde_json::from_str(value).map_err(|_| SettingsError::Malformed)?;
+
+        let mut output = RenderSettings::default();
+
+        for (name, value) in settings {
+            if name == "language" {
+                match value.as_str().filter(|value| is_valid_language(value)) {
+                    Some(language) => output.language = Some(language.to_string()),
+                    None => return Err(SettingsError::InvalidValue(name)),
+                }
+
+                continue;
+            }
+
+            let Some((_, property)) = PDF_SETTINGS.iter().find(|(setting, _)| *setting == name)
+            else {
+                return Err(SettingsError::Unknown(name));
+            };
+
+            let Some(value) = value.as_bool() else {
+                return Err(SettingsError::InvalidValue(name));
+            };
+
+            output.pdf.insert(property, value);
+        }
+
+        Ok(output)
+    }
+
+    /// Sets whether the standard PDF fonts are embedded, embedding makes the
+    /// output larger but is required by some print workflows
+    pub fn set_embed_standard_fonts(&mut self, embed: bool) {
+        // Not embedding is the office default, omitted to keep the cache key unchanged
+        if embed {
+            self.pdf.insert(EMBED_STANDARD_FONTS, true);
+        } else {
+            self.pdf.remove(EMBED_STANDARD_FONTS);
+        }
+    }
+
+    /// Whether no settings were provided
+    pub fn is_empty(&self) -> bool {
+        self.language.is_none() && self.pdf.is_empty()
+    }
+
+    /// Creates a fingerprint of the settings for use in cache keys
+    pub(crate) fn cache_fingerprint(&self) -> String {
+        let mut fingerprint = format!("language={}", self.language.as_deref().unwrap_or_default());
+
+        for (property, value) in &self.pdf {
+            fingerprint.push_str(&format!(",{property}={value}"));
+        }
+
+        fingerprint
+    }
+
+    /// Options to load the document with, `macros_enabled` allows the macros
+    /// of the document to run (Only when an admin runs a document macro)
+    pub(crate) fn load_options(&self, macros_enabled: bool) -> String {
+        let mut options = String::from("InteractionHandler=0,Batch=1");
+
+        if macros_enabled {
+            options.push_str(",EnableMacrosExecution=true,MacroSecurityLevel=0");
+        }
+
+        if let Some(language) = self.language.as_deref() {
+            options.push_str(",Language=");
+            options.push_str(language);
+        }
+
+        options
+    }
+
+    /// Creates the JSON filter options for exporting the PDF, `extra`
+    /// properties take priority over the settings
+    pub(crate) fn pdf_filter_options(&self, extra: &[(&str, Value)]) -> Option<String> {
+        if self.pdf.is_empty() && extra.is_empty() {
+            return None;
+        }
+
+        let mut options = Map::new();
+
+        for (property, value) in &self.pdf {
+            options.insert(
+                property.to_string(),
+                json!({ "type": "boolean", "value": value.to_string() }),
+            );
+        }
+
+        for (property, value) in extra {
+            options.insert(property.to_string(), value.clone());
+        }
+
+        Some(Value::Object(options).to_string())
+    }
+}
+
+/// Checks the `value` is a language tag (i.e "en", "ja-JP" or "zh-Hant-TW"),
+/// the tag is passed within the load options so must not contain separators
+pub(crate) fn is_valid_language(value: &str) -> bool {
+    let mut subtags = value.split('-');
+
+    let is_valid_primary = subtags.next().is_some_and(|primary| {
+        (2..=3).contains(&primary.len()) && primary.chars().all(|char| char.is_ascii_alphabetic())
+    });
+
+    is_valid_primary
+        && value.len() <= MAX_LANGUAGE_LEN
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len())
+                && subtag.chars().all(|char| char.is_ascii_alphanumeric())
+        })
+}
@@ -0,0 +1,356 @@
+use crate::{
+    config::OfficeLogLevel,
+    convert::{
+        self, BundleArtifacts, ConvertBackend, ConvertError, ConvertOptions, ConvertedDocument,
+        DocumentInput, DocumentStats,
+    },
+    office_errors,
+    pipeline::{self, PipelineManifest, PipelineOptions, PipelineStep, StepManifest},
+    runner::OfficeDetails,
+    tempfiles::{random_id, TempDir},
+};
+use anyhow::{anyhow, Context};
+use bytes::Bytes;
+use libreofficekit::{OfficeVersionInfo, ProductVersion};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::Instant,
+};
+use tracing::debug;
+use url::Url;
+
+#[cfg(target_os = "windows")]
+const SOFFICE_BINARY: &str = "soffice.exe";
+#[cfg(not(target_os = "windows"))]
+const SOFFICE_BINARY: &str = "soffice";
+
+/// [ConvertBackend] running a separate `soffice --convert-to` process for
+/// each conversion, slower than the in-process office but unaffected by
+/// issues with the LibreOfficeKit bindings on some distributions
+pub struct SofficeBackend {
+    /// Path to the soffice binary
+    soffice: PathBuf,
+    /// Directory documents are written to while converting
+    work_dir: PathBuf,
+    /// URL of the user profile used by the soffice processes
+    profile_url: String,
+    /// Verbosity of the log output collected from the soffice processes
+    log_level: OfficeLogLevel,
+    /// Tail of the log output of the last soffice process
+    log: Vec<String>,
+}
+
+impl SofficeBackend {
+    /// Creates a backend using the soffice binary from the install at
+    /// `office_path`, the `profile_dir` is used as the user profile
+    /// defaulting to a profile within the `work_dir`. The error output of
+    /// the processes is collected at the `log_level`
+    pub fn new(
+        office_path: &Path,
+        work_dir: &Path,
+        profile_dir: Option<&Path>,
+        log_level: OfficeLogLevel,
+    ) -> anyhow::Result<Self> {
+        let soffice = office_path.join(SOFFICE_BINARY);
+
+        // Processes must not share the profile of a running office so a
+        // dedicated profile is used
+        let profile_dir = match profile_dir {
+            Some(value) => value.to_path_buf(),
+            None => work_dir.join("lo_native_soffice_profile"),
+        };
+        let profile_dir = std::path::absolute(&profile_dir)
+            .context("failed to determine soffice profile path")?;
+        let profile_url = Url::from_directory_path(&profile_dir)
+            .map_err(|_| anyhow!("invalid soffice profile path"))?
+            .to_string();
+
+        let version = query_version(&soffice)?;
+        debug!(%version, "using soffice backend");
+
+        Ok(Self {
+            soffice,
+            work_dir: work_dir.to_path_buf(),
+            profile_url,
+            log_level,
+            log: Vec::new(),
+        })
+    }
+
+    /// Creates a directory for the files of a single document, removed
+    /// once the document has been processed
+    fn create_dir(&self) -> anyhow::Result<TempDir> {
+        let dir = TempDir {
+            path: self
+                .work_dir
+                .join(format!("lo_native_soffice_{}", random_id())),
+        };
+        std::fs::create_dir_all(&dir.path).context("failed to create soffice directory")?;
+        Ok(dir)
+    }
+
+    /// Converts the file at `input` to `format` within `dir`, providing the
+    /// path to the converted output
+    fn convert_to(&mut self, dir: &TempDir, input: &Path, format: &str) -> anyhow::Result<PathBuf> {
+        let out_dir = dir.path.join(random_id());
+        std::fs::create_dir(&out_dir).context("failed to create output directory")?;
+
+        let mut command = Command::new(&self.soffice);
+
+        // Select the log output of the process, unless chosen by the environment
+        if let (Some(sal_log), None) = (self.log_level.sal_log(), std::env::var_os("SAL_LOG")) {
+            command.env("SAL_LOG", sal_log);
+        }
+
+        let output = command
+            .arg(format!("-env:UserInstallation={}", self.profile_url))
+            .args([
+                "--headless",
+                "--invisible",
+                "--nodefault",
+                "--nolockcheck",
+                "--nologo",
+                "--norestore",
+            ])
+            .arg("--convert-to")
+            .arg(format)
+            .arg("--outdir")
+            .arg(&out_dir)
+            .arg(input)
+            .output()
+            .context("failed to run soffice")?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        if self.log_level.is_enabled() {
+            self.log = convert::log_tail(&stderr);
+        }
+
+        // soffice reports success even when the document cannot be converted,
+        // the output is checked for instead
+        let converted = std::fs::read_dir(&out_dir)
+            .context("failed to read output directory")?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .next();
+
+        if let Some(converted) = converted {
+            return Ok(converted);
+        }
+
+        if let Some(err) = office_errors::classify(&stderr, false) {
+            return Err(err.into());
+        }
+
+        Err(anyhow!(
+            "soffice failed to convert to {format}: {}",
+            stderr.trim()
+        ))
+    }
+}
+
+/// Writes the `input` document into `dir` providing its path
+fn write_input(dir: &TempDir, input: DocumentInput) -> anyhow::Result<PathBuf> {
+    let path = dir.path.join("document");
+
+    match input {
+        DocumentInput::Bytes(bytes) => {
+            std::fs::write(&path, bytes).context("failed to write temp input")?
+        }
+        DocumentInput::Spilled(spilled) => {
+            std::fs::rename(&spilled.path, &path).context("failed to move spilled input")?
+        }
+    }
+
+    Ok(path)
+}
+
+/// Queries the version output of the `soffice` binary
+fn query_version(soffice: &Path) -> anyhow::Result<String> {
+    let output = Command::new(soffice)
+        .arg("--version")
+        .output()
+        .with_context(|| format!("failed to run {}", soffice.display()))?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Parses the output of `soffice --version` (i.e "LibreOffice 7.6.4.1 e19e193f88cd")
+fn parse_version(value: &str) -> Option<OfficeVersionInfo> {
+    let mut parts = value.split_whitespace();
+    let product_name = parts.next()?.to_string();
+    let version = parts.next()?;
+    let build_id = parts.next().unwrap_or_default().to_string();
+
+    let mut numbers = version.splitn(3, '.');
+    let major = numbers.next()?.parse().ok()?;
+    let minor = numbers.next()?.parse().ok()?;
+    let product_extension = numbers
+        .next()
+        .map(|value| format!(".{value}"))
+        .unwrap_or_default();
+
+    Some(OfficeVersionInfo {
+        product_name,
+        product_version: ProductVersion::new(major, minor),
+        product_extension,
+        build_id,
+    })
+}
+
+impl ConvertBackend for SofficeBackend {
+    fn details(&self) -> OfficeDetails {
+        OfficeDetails {
+            filter_types: None,
+            // Queried each time so upgrades of the install are reflected
+            version: query_version(&self.soffice)
+                .ok()
+                .and_then(|version| parse_version(&version)),
+        }
+    }
+
+    fn convert(
+        &mut self,
+        input: DocumentInput,
+        options: ConvertOptions,
+    ) -> anyhow::Result<ConvertedDocument> {
+        self.log.clear();
+
+        if options.split_sheets {
+            return Err(ConvertError::Unsupported("splitting sheets").into());
+        }
+
+        if options.run_macro.is_some() {
+            return Err(ConvertError::Unsupported("running macros").into());
+        }
+
+        if options.with_text {
+            return Err(ConvertError::Unsupported("extracting page text").into());
+        }
+
+        if options.password.is_some() {
+            return Err(ConvertError::Unsupported("document passwords").into());
+        }
+
+        if options.csv.is_some() {
+            return Err(ConvertError::Unsupported("csv exports").into());
+        }
+
+        if options.spreadsheet.is_some() {
+            return Err(ConvertError::Unsupported("spreadsheet exports").into());
+        }
+
+        if !options.settings.is_empty() {
+            return Err(ConvertError::Unsupported("per request settings").into());
+        }
+
+        if !options.pdf_export.is_empty() {
+            return Err(ConvertError::Unsupported("pdf export options").into());
+        }
+
+        if options.redact.is_some() {
+            return Err(ConvertError::Unsupported("redaction").into());
+        }
+
+        let dir = self.create_dir()?;
+        let input = write_input(&dir, input)?;
+
+        if let Some(target) = options.target_format {
+            let output = self.convert_to(&dir, &input, target.extension)?;
+            let bytes = std::fs::read(output).context("failed to read converted output")?;
+
+            return Ok(ConvertedDocument {
+                bytes: Bytes::from(bytes),
+                file: None,
+                content_type: target.content_type,
+                warnings: Vec::new(),
+            });
+        }
+
+        let output = self.convert_to(&dir, &input, "pdf")?;
+
+        let bytes = std::fs::read(output).context("failed to read converted output")?;
+
+        if options.with_thumbnail {
+            let thumbnail = self.convert_to(&dir, &input, "png")?;
+            let thumbnail = std::fs::read(thumbnail).context("failed to read thumbnail")?;
+
+            let artifacts = BundleArtifacts {
+                thumbnail: Some(thumbnail),
+                text: None,
+                redactions: None,
+            };
+
+            return convert::bundle(&bytes, artifacts);
+        }
+
+        Ok(ConvertedDocument {
+            bytes: Bytes::from(bytes),
+            file: None,
+            content_type: "application/pdf",
+            warnings: Vec::new(),
+        })
+    }
+
+    fn pipeline(
+        &mut self,
+        input: DocumentInput,
+        steps: Vec<PipelineStep>,
+        options: PipelineOptions,
+    ) -> anyhow::Result<Bytes> {
+        if options.output_password.is_some() {
+            return Err(ConvertError::Unsupported("output passwords").into());
+        }
+
+        let dir = self.create_dir()?;
+        let mut current = write_input(&dir, input)?;
+
+        let mut manifest = PipelineManifest::default();
+        let mut artifacts = Vec::new();
+
+        for (index, step) in steps.iter().enumerate() {
+            let number = index + 1;
+            let started = Instant::now();
+
+            let PipelineStep::Export(format) = step else {
+                return Err(ConvertError::Unsupported("csv-sheets pipeline steps").into());
+            };
+
+            // The exported document becomes the input of the next step
+            current = self
+                .convert_to(&dir, &current, format)
+                .with_context(|| format!("pipeline step {number} failed"))?;
+
+            let name = format!("step-{number}.{format}");
+            let bytes = std::fs::read(&current).context("failed to read step output")?;
+            artifacts.push((name.clone(), bytes));
+
+            manifest.steps.push(StepManifest {
+                step: number,
+                name: step.name().to_string(),
+                outputs: vec![name],
+                duration_ms: started.elapsed().as_millis(),
+            });
+        }
+
+        pipeline::bundle(&manifest, artifacts)
+    }
+
+    fn extract_assets(&mut self, _input: DocumentInput) -> anyhow::Result<Bytes> {
+        Err(ConvertError::Unsupported("extracting assets").into())
+    }
+
+    fn extract_stats(&mut self, _input: DocumentInput) -> anyhow::Result<DocumentStats> {
+        Err(ConvertError::Unsupported("extracting statistics").into())
+    }
+
+    fn trim_memory(&mut self, _target: i32) -> anyhow::Result<()> {
+        // Each soffice process exits after converting, there is nothing to trim
+        Ok(())
+    }
+
+    fn take_log(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.log)
+    }
+}
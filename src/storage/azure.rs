@@ -0,0 +1,347 @@
+use super::{
+    check_key, check_status, optional_env, required_env, uri_encode, Storage, StoredObject,
+    StoredReader, UtcTime,
+};
+use anyhow::Context;
+use axum::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use futures_util::TryStreamExt;
+use quick_xml::{events::Event, Reader};
+use reqwest::{Method, Response, StatusCode};
+use ring::hmac;
+use std::{fmt, io, time::SystemTime};
+use url::Url;
+
+/// Version of the Blob Storage API requests are made with
+const API_VERSION: &str = "2021-08-06";
+
+/// Stores objects as block blobs in an Azure Blob Storage container
+#[derive(Debug)]
+pub struct AzureStorage {
+    client: reqwest::Client,
+    /// Name of the storage account
+    account: String,
+    /// Scheme and host of the account endpoint (i.e "https://account.blob.core.windows.net")
+    origin: String,
+    /// Path of the container on the host
+    container_path: String,
+    /// Prefix of the names of stored blobs
+    prefix: String,
+    credentials: AzureCredentials,
+}
+
+/// Credentials requests are authorized with
+enum AzureCredentials {
+    /// Account access key requests are signed with (Shared Key)
+    Key(hmac::Key),
+    /// Shared access signature appended to requests
+    Sas(String),
+}
+
+impl fmt::Debug for AzureCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AzureCredentials::Key(_) => f.write_str("Key(..)"),
+            AzureCredentials::Sas(_) => f.write_str("Sas(..)"),
+        }
+    }
+}
+
+impl AzureStorage {
+    /// Creates the storage for the `container` of the account from the
+    /// "AZURE_STORAGE_ACCOUNT" environment variable storing blobs under the
+    /// `prefix`. Requests are signed with "AZURE_STORAGE_KEY" or authorized
+    /// by the "AZURE_STORAGE_SAS_TOKEN"
+    pub fn from_env(container: &str, prefix: &str) -> anyhow::Result<Self> {
+        let account = required_env("AZURE_STORAGE_ACCOUNT")?;
+
+        let credentials = match optional_env("AZURE_STORAGE_KEY") {
+            Some(key) => {
+                let key = STANDARD
+                    .decode(key.trim())
+                    .context("AZURE_STORAGE_KEY is not valid base64")?;
+                AzureCredentials::Key(hmac::Key::new(hmac::HMAC_SHA256, &key))
+            }
+            None => {
+                let token = required_env("AZURE_STORAGE_SAS_TOKEN")
+                    .context("missing AZURE_STORAGE_KEY or AZURE_STORAGE_SAS_TOKEN")?;
+                AzureCredentials::Sas(token.trim_start_matches('?').to_string())
+            }
+        };
+
+        // Endpoint differs for emulators (i.e "http://127.0.0.1:10000/devstoreaccount1")
+        let endpoint = optional_env("AZURE_STORAGE_ENDPOINT")
+            .unwrap_or_else(|| format!("https://{account}.blob.core.windows.net"));
+        let url = Url::parse(&endpoint).with_context(|| format!("invalid endpoint {endpoint}"))?;
+        let host = url
+            .host_str()
+            .with_context(|| format!("missing host in endpoint {endpoint}"))?;
+        let origin = match url.port() {
+            Some(port) => format!("{}://{host}:{port}", url.scheme()),
+            None => format!("{}://{host}", url.scheme()),
+        };
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            account,
+            origin,
+            container_path: format!(
+                "{}/{}",
+                url.path().trim_end_matches('/'),
+                uri_encode(container, false)
+            ),
+            prefix: prefix.to_string(),
+            credentials,
+        })
+    }
+
+    /// Path of the blob with the `key`
+    fn blob_path(&self, key: &str) -> anyhow::Result<String> {
+        check_key(key)?;
+        Ok(format!(
+            "{}/{}",
+            self.container_path,
+            uri_encode(&format!("{}{key}", self.prefix), true)
+        ))
+    }
+
+    /// Sends an authorized request for the `path` with the `query` parameters
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Bytes,
+    ) -> anyhow::Result<Response> {
+        let date = UtcTime::from_system(SystemTime::now()).http_date();
+
+        let mut headers = vec![
+            ("x-ms-date", date),
+            ("x-ms-version", API_VERSION.to_string()),
+        ];
+        if method == Method::PUT {
+            headers.insert(0, ("x-ms-blob-type", "BlockBlob".to_string()));
+        }
+
+        let mut url_query: Vec<String> = query
+            .iter()
+            .map(|(name, value)| {
+                format!("{}={}", uri_encode(name, false), uri_encode(value, false))
+            })
+            .collect();
+
+        match &self.credentials {
+            AzureCredentials::Key(key) => {
+                let signature = self.sign(key, &method, path, query, &headers, body.len());
+                headers.push((
+                    "authorization",
+                    format!("SharedKey {}:{signature}", self.account),
+                ));
+            }
+            AzureCredentials::Sas(token) => url_query.push(token.clone()),
+        }
+
+        let url = match url_query.is_empty() {
+            true => format!("{}{path}", self.origin),
+            false => format!("{}{path}?{}", self.origin, url_query.join("&")),
+        };
+
+        let method_has_body = method == Method::PUT;
+        let mut request = self.client.request(method, url);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        if method_has_body {
+            request = request.body(body);
+        }
+
+        request.send().await.context("storage request failed")
+    }
+
+    /// Signs a request using the account key (Shared Key authorization), the
+    /// `headers` are the "x-ms-" headers of the request in alphabetical order
+    fn sign(
+        &self,
+        key: &hmac::Key,
+        method: &Method,
+        path: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, String)],
+        content_length: usize,
+    ) -> String {
+        let content_length = match content_length {
+            0 => String::new(),
+            length => length.to_string(),
+        };
+
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+
+        let mut query = query.to_vec();
+        query.sort();
+        let canonical_query: String = query
+            .iter()
+            .map(|(name, value)| format!("\n{}:{value}", name.to_ascii_lowercase()))
+            .collect();
+
+        // Content-Encoding, Content-Language, Content-Length, Content-MD5,
+        // Content-Type, Date, If-Modified-Since, If-Match, If-None-Match,
+        // If-Unmodified-Since and Range
+        let string_to_sign = format!(
+            "{method}\n\n\n{content_length}\n\n\n\n\n\n\n\n\n{canonical_headers}/{}{path}{canonical_query}",
+            self.account
+        );
+
+        STANDARD.encode(hmac::sign(key, string_to_sign.as_bytes()))
+    }
+
+    /// Lists a page of blobs starting at the `marker`, providing the marker
+    /// of the next page
+    async fn list_page(
+        &self,
+        marker: Option<&str>,
+        objects: &mut Vec<StoredObject>,
+    ) -> anyhow::Result<Option<String>> {
+        let mut query = vec![
+            ("comp", "list"),
+            ("prefix", self.prefix.as_str()),
+            ("restype", "container"),
+        ];
+        if let Some(marker) = marker {
+            query.push(("marker", marker));
+        }
+
+        let response = self
+            .send(Method::GET, &self.container_path, &query, Bytes::new())
+            .await?;
+        let body = check_status(response).await?.text().await?;
+
+        let mut reader = Reader::from_str(&body);
+        let mut element: Vec<u8> = Vec::new();
+        let mut object: Option<StoredObject> = None;
+        let mut next = None;
+
+        loop {
+            match reader.read_event().context("failed to parse blob list")? {
+                Event::Start(start) => {
+                    element = start.name().as_ref().to_vec();
+                    if element == b"Blob" {
+                        object = Some(StoredObject {
+                            key: String::new(),
+                            size: 0,
+                            modified: None,
+                        });
+                    }
+                }
+                Event::Text(text) => {
+                    let text = text.unescape().context("failed to parse blob list")?;
+
+                    match (element.as_slice(), object.as_mut()) {
+                        (b"Name", Some(object)) => object.key = text.into_owned(),
+                        (b"Content-Length", Some(object)) => {
+                            object.size = text.parse().unwrap_or_default()
+                        }
+                        (b"Last-Modified", Some(object)) => {
+                            object.modified = UtcTime::parse_http_date(&text)
+                        }
+                        (b"NextMarker", None) => next = Some(text.into_owned()),
+                        _ => {}
+                    }
+                }
+                Event::End(end) => {
+                    element.clear();
+
+                    if end.name().as_ref() == b"Blob" {
+                        if let Some(mut object) = object.take() {
+                            if let Some(key) = object.key.strip_prefix(&self.prefix) {
+                                // Blobs in nested "directories" weren't stored by the server
+                                if !key.contains('/') {
+                                    object.key = key.to_string();
+                                    objects.push(object);
+                                }
+                            }
+                        }
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        Ok(next)
+    }
+}
+
+#[async_trait]
+impl Storage for AzureStorage {
+    async fn put(&self, key: &str, bytes: Bytes) -> anyhow::Result<()> {
+        let path = self.blob_path(key)?;
+        let response = self.send(Method::PUT, &path, &[], bytes).await?;
+        check_status(response).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>> {
+        let path = self.blob_path(key)?;
+        let response = self.send(Method::GET, &path, &[], Bytes::new()).await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let bytes = check_status(response)
+            .await?
+            .bytes()
+            .await
+            .context("failed to read stored object")?;
+        Ok(Some(bytes))
+    }
+
+    async fn open(&self, key: &str) -> anyhow::Result<Option<StoredReader>> {
+        let path = self.blob_path(key)?;
+        let response = self.send(Method::GET, &path, &[], Bytes::new()).await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let response = check_status(response).await?;
+        let size = response
+            .content_length()
+            .context("stored object missing its size")?;
+
+        Ok(Some(StoredReader {
+            size,
+            stream: Box::pin(response.bytes_stream().map_err(io::Error::other)),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        let path = self.blob_path(key)?;
+        let response = self.send(Method::DELETE, &path, &[], Bytes::new()).await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+
+        check_status(response).await?;
+        Ok(true)
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<StoredObject>> {
+        let mut objects = Vec::new();
+        let mut marker = None;
+
+        loop {
+            marker = self.list_page(marker.as_deref(), &mut objects).await?;
+
+            // Last page has an empty marker
+            if marker.as_deref().unwrap_or_default().is_empty() {
+                return Ok(objects);
+            }
+        }
+    }
+}
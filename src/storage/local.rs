@@ -0,0 +1,131 @@
+use super::{check_key, Storage, StoredObject, StoredReader};
+use anyhow::Context;
+use axum::async_trait;
+use bytes::Bytes;
+use std::path::PathBuf;
+use tokio::{fs, io::AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+/// Stores objects as files in a directory on the local disk, objects are
+/// written to a temporary file ("<key>.tmp") that is renamed once complete
+#[derive(Debug)]
+pub struct LocalStorage {
+    /// Directory objects are stored in
+    dir: PathBuf,
+}
+
+impl LocalStorage {
+    /// Creates the storage for the existing `dir`
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        check_key(key)?;
+        Ok(self.dir.join(key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, bytes: Bytes) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+        let temp_path = self.dir.join(format!("{key}.tmp"));
+
+        // Synced before renaming so a crash never leaves a renamed but incomplete file
+        let write = async {
+            let mut file = fs::File::create(&temp_path).await?;
+            file.write_all(&bytes).await?;
+            file.sync_all().await
+        };
+
+        if let Err(err) = write.await {
+            _ = fs::remove_file(&temp_path).await;
+            return Err(anyhow::Error::new(err).context("failed to write stored object"));
+        }
+
+        if let Err(err) = fs::rename(&temp_path, &path).await {
+            _ = fs::remove_file(&temp_path).await;
+            return Err(anyhow::Error::new(err).context("failed to move stored object into place"));
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>> {
+        match fs::read(self.path(key)?).await {
+            Ok(value) => Ok(Some(Bytes::from(value))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).context("failed to read stored object"),
+        }
+    }
+
+    async fn open(&self, key: &str) -> anyhow::Result<Option<StoredReader>> {
+        let file = match fs::File::open(self.path(key)?).await {
+            Ok(value) => value,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).context("failed to open stored object"),
+        };
+
+        let size = file
+            .metadata()
+            .await
+            .context("failed to read stored object")?
+            .len();
+
+        Ok(Some(StoredReader {
+            size,
+            stream: Box::pin(ReaderStream::new(file)),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        match fs::remove_file(self.path(key)?).await {
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err).context("failed to remove stored object"),
+        }
+    }
+
+    fn local_path(&self, key: &str) -> Option<PathBuf> {
+        self.path(key).ok()
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<StoredObject>> {
+        let mut objects = Vec::new();
+
+        let mut dir = match fs::read_dir(&self.dir).await {
+            Ok(value) => value,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(objects),
+            Err(err) => return Err(err).context("failed to read storage directory"),
+        };
+
+        while let Some(entry) = dir
+            .next_entry()
+            .await
+            .context("failed to read storage directory")?
+        {
+            let Ok(key) = entry.file_name().into_string() else {
+                continue;
+            };
+
+            let metadata = match entry.metadata().await {
+                Ok(value) => value,
+                // File was removed while reading the directory
+                Err(_) => continue,
+            };
+
+            if !metadata.is_file() {
+                continue;
+            }
+
+            objects.push(StoredObject {
+                key,
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+
+        Ok(objects)
+    }
+}
@@ -0,0 +1,418 @@
+use anyhow::{anyhow, Context};
+use axum::async_trait;
+use bytes::Bytes;
+use futures_util::Stream;
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Debug, Display},
+    io,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+mod azure;
+mod local;
+mod redis;
+mod s3;
+
+pub use azure::AzureStorage;
+pub use local::LocalStorage;
+pub use redis::RedisStorage;
+pub use s3::S3Storage;
+
+/// Contents of a stored object read as they are needed
+pub type ObjectStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/// Backend job results and cache entries are stored in. Keys are flat names
+/// without any "/" so every backend can list them back as they were stored
+#[async_trait]
+pub trait Storage: Debug + Send + Sync {
+    /// Stores the `bytes` as the object with the `key`, replacing any existing
+    /// object. Readers never see a partially stored object
+    async fn put(&self, key: &str, bytes: Bytes) -> anyhow::Result<()>;
+
+    /// Reads the object with the `key`, [None] when it doesn't exist
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>>;
+
+    /// Opens the object with the `key` to read its contents as they are
+    /// sent, [None] when it doesn't exist
+    async fn open(&self, key: &str) -> anyhow::Result<Option<StoredReader>>;
+
+    /// Removes the object with the `key`, returns whether it existed
+    async fn delete(&self, key: &str) -> anyhow::Result<bool>;
+
+    /// Lists every stored object
+    async fn list(&self) -> anyhow::Result<Vec<StoredObject>>;
+
+    /// Path of the object with the `key` on the local disk when the backend
+    /// stores objects as files, allowing them to be read in place
+    fn local_path(&self, _key: &str) -> Option<PathBuf> {
+        None
+    }
+
+    /// Acquires the lease stored as the object with the `key` for the `owner`
+    /// until the `ttl` passes, renewing it when the `owner` already holds it.
+    /// Returns whether the `owner` holds the lease.
+    ///
+    /// Backends without atomic writes read the lease back after storing it so
+    /// only the last of the servers acquiring it at the same time holds it
+    async fn acquire_lease(&self, key: &str, owner: &str, ttl: Duration) -> anyhow::Result<bool> {
+        let now = unix_millis();
+
+        if let Some(bytes) = self.get(key).await? {
+            let held_by_other = serde_json::from_slice::<StoredLease>(&bytes)
+                .is_ok_and(|lease| lease.owner != owner && lease.expires_at > now);
+
+            if held_by_other {
+                return Ok(false);
+            }
+        }
+
+        let lease = StoredLease {
+            owner: owner.to_string(),
+            expires_at: now + ttl.as_millis() as u64,
+        };
+        let lease = serde_json::to_vec(&lease).context("failed to serialize lease")?;
+        self.put(key, Bytes::from(lease)).await?;
+
+        let Some(bytes) = self.get(key).await? else {
+            return Ok(false);
+        };
+
+        Ok(serde_json::from_slice::<StoredLease>(&bytes).is_ok_and(|lease| lease.owner == owner))
+    }
+}
+
+/// Lease stored by [Storage::acquire_lease]
+#[derive(Serialize, Deserialize)]
+struct StoredLease {
+    /// Owner holding the lease
+    owner: String,
+    /// When the lease expires (Milliseconds since the unix epoch)
+    expires_at: u64,
+}
+
+/// Object listed from a [Storage]
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    /// Key the object is stored under
+    pub key: String,
+    /// Size of the object in bytes
+    pub size: u64,
+    /// When the object was last stored, [None] when not reported
+    pub modified: Option<SystemTime>,
+}
+
+/// Stored object opened for reading
+pub struct StoredReader {
+    /// Size of the object in bytes
+    pub size: u64,
+    /// Contents of the object
+    pub stream: ObjectStream,
+}
+
+/// Location objects are stored at, either a local directory or a bucket
+/// (or container) of an object storage service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageLocation {
+    /// Directory on the local disk
+    Local(PathBuf),
+    /// Amazon S3 bucket, or a bucket of a S3 compatible service when the
+    /// "AWS_ENDPOINT_URL" environment variable is set
+    S3 { bucket: String, prefix: String },
+    /// Google Cloud Storage bucket
+    Gcs { bucket: String, prefix: String },
+    /// Azure Blob Storage container
+    Azure { container: String, prefix: String },
+    /// Redis server at the address (i.e "redis:6379")
+    Redis { address: String, prefix: String },
+}
+
+impl StorageLocation {
+    /// Opens the storage at the location, credentials for object storage
+    /// services are read from the environment
+    pub fn open(&self) -> anyhow::Result<Arc<dyn Storage>> {
+        Ok(match self {
+            StorageLocation::Local(dir) => {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("failed to create {}", dir.display()))?;
+                Arc::new(LocalStorage::new(dir.clone()))
+            }
+            StorageLocation::S3 { bucket, prefix } => {
+                Arc::new(S3Storage::from_env(bucket, prefix)?)
+            }
+            StorageLocation::Gcs { bucket, prefix } => {
+                Arc::new(S3Storage::gcs_from_env(bucket, prefix)?)
+            }
+            StorageLocation::Azure { container, prefix } => {
+                Arc::new(AzureStorage::from_env(container, prefix)?)
+            }
+            StorageLocation::Redis { address, prefix } => {
+                Arc::new(RedisStorage::from_env(address, prefix))
+            }
+        })
+    }
+
+    /// Provides the local directory of the location, [None] for object
+    /// storage services
+    pub fn local_dir(&self) -> Option<&PathBuf> {
+        match self {
+            StorageLocation::Local(dir) => Some(dir),
+            _ => None,
+        }
+    }
+}
+
+impl Display for StorageLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageLocation::Local(dir) => write!(f, "{}", dir.display()),
+            StorageLocation::S3 { bucket, prefix } => write!(f, "s3://{bucket}/{prefix}"),
+            StorageLocation::Gcs { bucket, prefix } => write!(f, "gs://{bucket}/{prefix}"),
+            StorageLocation::Azure { container, prefix } => {
+                write!(f, "azure://{container}/{prefix}")
+            }
+            StorageLocation::Redis { address, prefix } => write!(f, "redis://{address}/{prefix}"),
+        }
+    }
+}
+
+/// Parses a storage location, either a directory path or a URL of a bucket
+/// with an optional prefix for stored objects, i.e "/var/lib/jobs",
+/// "s3://bucket/jobs", "gs://bucket/jobs" or "azure://container/jobs". Redis
+/// servers are given by their address, i.e "redis://redis:6379/jobs"
+pub fn storage_arg(value: &str) -> Result<StorageLocation, String> {
+    let Some((scheme, rest)) = value.split_once("://") else {
+        return Ok(StorageLocation::Local(PathBuf::from(value)));
+    };
+
+    if scheme == "file" {
+        return Ok(StorageLocation::Local(PathBuf::from(rest)));
+    }
+
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return Err(format!("missing bucket in \"{value}\""));
+    }
+
+    let bucket = bucket.to_string();
+    let prefix = match prefix.trim_matches('/') {
+        "" => String::new(),
+        prefix => format!("{prefix}/"),
+    };
+
+    match scheme {
+        "s3" => Ok(StorageLocation::S3 { bucket, prefix }),
+        "gs" => Ok(StorageLocation::Gcs { bucket, prefix }),
+        "azure" => Ok(StorageLocation::Azure {
+            container: bucket,
+            prefix,
+        }),
+        "redis" => Ok(StorageLocation::Redis {
+            address: match bucket.contains(':') {
+                true => bucket,
+                false => format!("{bucket}:6379"),
+            },
+            prefix,
+        }),
+        _ => Err(format!(
+            "unknown storage \"{scheme}\", expected a directory or a s3://, gs://, azure:// or redis:// URL"
+        )),
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Reads the required environment variable `name`
+fn required_env(name: &str) -> anyhow::Result<String> {
+    std::env::var(name).with_context(|| format!("missing {name} environment variable"))
+}
+
+/// Reads the environment variable `name` when it is set and not empty
+fn optional_env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Percent encodes the `value` for a URL leaving only the unreserved
+/// characters, "/" is left as-is when `keep_slash` is set
+fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    encoded
+}
+
+/// Checks the `response` was successful, failed responses are turned into
+/// an error including the body reported by the service
+async fn check_status(response: Response) -> anyhow::Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    Err(anyhow!(
+        "storage request failed with {status}: {}",
+        body.trim()
+    ))
+}
+
+/// Checks the `key` can be stored, keys are flat names
+fn check_key(key: &str) -> anyhow::Result<()> {
+    if key.is_empty() || key == "." || key == ".." || key.contains(['/', '\\']) {
+        return Err(anyhow!("invalid storage key \"{key}\""));
+    }
+
+    Ok(())
+}
+
+/// Date and time in UTC
+struct UtcTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    /// Day of the week (0 being Sunday)
+    weekday: u32,
+}
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+impl UtcTime {
+    fn from_system(time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map(|value| value.as_secs() as i64)
+            .unwrap_or_default();
+        let days = secs.div_euclid(86_400);
+        let time_of_day = secs.rem_euclid(86_400) as u32;
+
+        // Civil date from days since the epoch (Howard Hinnant's algorithm)
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: time_of_day / 3600,
+            minute: time_of_day / 60 % 60,
+            second: time_of_day % 60,
+            weekday: (days + 4).rem_euclid(7) as u32,
+        }
+    }
+
+    fn to_system(&self) -> Option<SystemTime> {
+        if !(1..=12).contains(&self.month)
+            || !(1..=31).contains(&self.day)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 60
+        {
+            return None;
+        }
+
+        // Days since the epoch from a civil date (Howard Hinnant's algorithm)
+        let year = self.year - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = i64::from((self.month + 9) % 12);
+        let doy = (153 * mp + 2) / 5 + i64::from(self.day) - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+
+        let secs = days * 86_400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second);
+
+        u64::try_from(secs)
+            .ok()
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    /// Parses a RFC 3339 UTC timestamp (i.e "2024-05-01T12:30:00.000Z")
+    fn parse_rfc3339(value: &str) -> Option<SystemTime> {
+        let value = value.strip_suffix('Z')?;
+        let (date, time) = value.split_once('T')?;
+        let mut date = date.splitn(3, '-');
+        let time = time.split('.').next()?;
+        let mut time = time.splitn(3, ':');
+
+        Self {
+            year: date.next()?.parse().ok()?,
+            month: date.next()?.parse().ok()?,
+            day: date.next()?.parse().ok()?,
+            hour: time.next()?.parse().ok()?,
+            minute: time.next()?.parse().ok()?,
+            second: time.next()?.parse().ok()?,
+            weekday: 0,
+        }
+        .to_system()
+    }
+
+    /// Parses a HTTP date (i.e "Wed, 01 May 2024 12:30:00 GMT")
+    fn parse_http_date(value: &str) -> Option<SystemTime> {
+        let (_, value) = value.split_once(", ")?;
+        let mut parts = value.split(' ');
+        let day = parts.next()?.parse().ok()?;
+        let month = parts.next()?;
+        let month = MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+        let year = parts.next()?.parse().ok()?;
+        let mut time = parts.next()?.splitn(3, ':');
+
+        Self {
+            year,
+            month,
+            day,
+            hour: time.next()?.parse().ok()?,
+            minute: time.next()?.parse().ok()?,
+            second: time.next()?.parse().ok()?,
+            weekday: 0,
+        }
+        .to_system()
+    }
+
+    /// Formats the time as a HTTP date (i.e "Wed, 01 May 2024 12:30:00 GMT")
+    fn http_date(&self) -> String {
+        format!(
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            WEEKDAYS[self.weekday as usize],
+            self.day,
+            MONTHS[self.month as usize - 1],
+            self.year,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
@@ -0,0 +1,414 @@
+use super::{check_key, optional_env, Storage, StoredObject, StoredReader};
+use anyhow::{anyhow, Context};
+use axum::async_trait;
+use bytes::Bytes;
+use futures_util::stream;
+use std::{
+    collections::HashSet,
+    future::Future,
+    pin::Pin,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::Mutex,
+};
+
+/// Number of keys requested from each SCAN
+const SCAN_COUNT: &str = "100";
+
+/// Acquires the lease (KEYS[1]) for the owner (ARGV[1]) when it is free or
+/// already held by the owner, leases are removed by redis once they expire
+/// (ARGV[2] milliseconds). The owner is stored as the data of the object
+const ACQUIRE_LEASE: &str = r#"
+local owner = redis.call('HGET', KEYS[1], 'data')
+if owner and owner ~= ARGV[1] then
+    return 0
+end
+redis.call('HSET', KEYS[1], 'data', ARGV[1], 'modified', ARGV[3])
+redis.call('PEXPIRE', KEYS[1], ARGV[2])
+return 1
+"#;
+
+/// Stores objects as hashes in Redis (Holding the "data" and the "modified"
+/// time of the object), for small deployments that share a Redis instance
+/// rather than an object storage service
+#[derive(Debug)]
+pub struct RedisStorage {
+    /// Address of the server (i.e "redis:6379")
+    address: String,
+    /// Prefix of the keys of stored objects
+    prefix: String,
+    credentials: Option<RedisCredentials>,
+    /// Connection requests are sent over, opened on first use
+    connection: Mutex<Option<Connection>>,
+}
+
+/// Credentials connections are authenticated with
+struct RedisCredentials {
+    username: Option<String>,
+    password: String,
+}
+
+impl std::fmt::Debug for RedisCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisCredentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reply to a command
+#[derive(Debug)]
+enum Reply {
+    /// Simple string reply (i.e "OK"), its contents are never needed
+    Status,
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+impl Reply {
+    fn into_bulk(self) -> anyhow::Result<Option<Vec<u8>>> {
+        match self {
+            Reply::Bulk(value) => Ok(value),
+            reply => Err(anyhow!("unexpected redis reply {reply:?}")),
+        }
+    }
+
+    fn into_integer(self) -> anyhow::Result<i64> {
+        match self {
+            Reply::Integer(value) => Ok(value),
+            reply => Err(anyhow!("unexpected redis reply {reply:?}")),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Connection {
+    stream: BufReader<TcpStream>,
+}
+
+impl Connection {
+    async fn open(address: &str, credentials: Option<&RedisCredentials>) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(address)
+            .await
+            .with_context(|| format!("failed to connect to redis at {address}"))?;
+        let mut connection = Self {
+            stream: BufReader::new(stream),
+        };
+
+        if let Some(credentials) = credentials {
+            let reply = match credentials.username.as_deref() {
+                Some(username) => {
+                    connection
+                        .command(&[
+                            b"AUTH",
+                            username.as_bytes(),
+                            credentials.password.as_bytes(),
+                        ])
+                        .await?
+                }
+                None => {
+                    connection
+                        .command(&[b"AUTH", credentials.password.as_bytes()])
+                        .await?
+                }
+            };
+
+            if let Reply::Error(message) = reply {
+                return Err(anyhow!("failed to authenticate with redis: {message}"));
+            }
+        }
+
+        Ok(connection)
+    }
+
+    /// Sends the command made up of the `args` and reads its reply
+    async fn command(&mut self, args: &[&[u8]]) -> anyhow::Result<Reply> {
+        let mut request = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            request.extend_from_slice(arg);
+            request.extend_from_slice(b"\r\n");
+        }
+
+        self.stream
+            .get_mut()
+            .write_all(&request)
+            .await
+            .context("failed to send redis command")?;
+
+        read_reply(&mut self.stream).await
+    }
+}
+
+/// Reads a reply from the `stream`, arrays are read recursively
+fn read_reply(
+    stream: &mut BufReader<TcpStream>,
+) -> Pin<Box<dyn Future<Output = anyhow::Result<Reply>> + Send + '_>> {
+    Box::pin(async move {
+        let mut line = Vec::new();
+        stream
+            .read_until(b'\n', &mut line)
+            .await
+            .context("failed to read redis reply")?;
+
+        let line = line
+            .strip_suffix(b"\r\n")
+            .context("redis connection closed")?;
+        let (kind, value) = line.split_first().context("empty redis reply")?;
+        let value = std::str::from_utf8(value).context("invalid redis reply")?;
+
+        let length = || -> anyhow::Result<i64> { value.parse().context("invalid redis reply") };
+
+        Ok(match kind {
+            b'+' => Reply::Status,
+            b'-' => Reply::Error(value.to_string()),
+            b':' => Reply::Integer(length()?),
+            b'$' => match usize::try_from(length()?) {
+                Ok(length) => {
+                    let mut data = vec![0; length + 2];
+                    stream
+                        .read_exact(&mut data)
+                        .await
+                        .context("failed to read redis reply")?;
+                    data.truncate(length);
+                    Reply::Bulk(Some(data))
+                }
+                Err(_) => Reply::Bulk(None),
+            },
+            b'*' => match usize::try_from(length()?) {
+                Ok(length) => {
+                    let mut items = Vec::with_capacity(length.min(1024));
+                    for _ in 0..length {
+                        items.push(read_reply(stream).await?);
+                    }
+                    Reply::Array(Some(items))
+                }
+                Err(_) => Reply::Array(None),
+            },
+            _ => return Err(anyhow!("unknown redis reply type")),
+        })
+    })
+}
+
+impl RedisStorage {
+    /// Creates the storage for the server at the `address` storing objects
+    /// under the `prefix`, connections are authenticated using the
+    /// "REDIS_USERNAME" and "REDIS_PASSWORD" environment variables when set
+    pub fn from_env(address: &str, prefix: &str) -> Self {
+        let credentials = optional_env("REDIS_PASSWORD").map(|password| RedisCredentials {
+            username: optional_env("REDIS_USERNAME"),
+            password,
+        });
+
+        Self {
+            address: address.to_string(),
+            prefix: prefix.to_string(),
+            credentials,
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Sends the command made up of the `args`, connections closed by the
+    /// server (i.e after an idle timeout) are reopened once
+    async fn command(&self, args: &[&[u8]]) -> anyhow::Result<Reply> {
+        let connection = &mut *self.connection.lock().await;
+
+        let reply = match connection.as_mut() {
+            Some(existing) => match existing.command(args).await {
+                Ok(reply) => reply,
+                Err(_) => {
+                    *connection = None;
+                    self.reconnect(connection, args).await?
+                }
+            },
+            None => self.reconnect(connection, args).await?,
+        };
+
+        match reply {
+            Reply::Error(message) => Err(anyhow!("redis command failed: {message}")),
+            reply => Ok(reply),
+        }
+    }
+
+    async fn reconnect(
+        &self,
+        connection: &mut Option<Connection>,
+        args: &[&[u8]],
+    ) -> anyhow::Result<Reply> {
+        let opened =
+            connection.insert(Connection::open(&self.address, self.credentials.as_ref()).await?);
+
+        let result = opened.command(args).await;
+        if result.is_err() {
+            *connection = None;
+        }
+        result
+    }
+
+    fn object_key(&self, key: &str) -> anyhow::Result<String> {
+        check_key(key)?;
+        Ok(format!("{}{key}", self.prefix))
+    }
+}
+
+#[async_trait]
+impl Storage for RedisStorage {
+    async fn put(&self, key: &str, bytes: Bytes) -> anyhow::Result<()> {
+        let key = self.object_key(key)?;
+        let modified = unix_secs().to_string();
+
+        // Both fields are set by a single command so readers never see a partial object
+        self.command(&[
+            b"HSET",
+            key.as_bytes(),
+            b"data",
+            &bytes,
+            b"modified",
+            modified.as_bytes(),
+        ])
+        .await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>> {
+        let key = self.object_key(key)?;
+        let data = self
+            .command(&[b"HGET", key.as_bytes(), b"data"])
+            .await?
+            .into_bulk()?;
+        Ok(data.map(Bytes::from))
+    }
+
+    async fn open(&self, key: &str) -> anyhow::Result<Option<StoredReader>> {
+        let Some(bytes) = self.get(key).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(StoredReader {
+            size: bytes.len() as u64,
+            stream: Box::pin(stream::iter([Ok(bytes)])),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        let key = self.object_key(key)?;
+        let removed = self
+            .command(&[b"DEL", key.as_bytes()])
+            .await?
+            .into_integer()?;
+        Ok(removed > 0)
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<StoredObject>> {
+        let mut pattern = String::new();
+        for char in self.prefix.chars() {
+            if matches!(char, '*' | '?' | '[' | ']' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(char);
+        }
+        pattern.push('*');
+
+        // Keys can be returned more than once while scanning
+        let mut keys = HashSet::new();
+        let mut cursor = "0".to_string();
+
+        loop {
+            let reply = self
+                .command(&[
+                    b"SCAN",
+                    cursor.as_bytes(),
+                    b"MATCH",
+                    pattern.as_bytes(),
+                    b"COUNT",
+                    SCAN_COUNT.as_bytes(),
+                ])
+                .await?;
+
+            let Reply::Array(Some(mut reply)) = reply else {
+                return Err(anyhow!("unexpected redis reply {reply:?}"));
+            };
+            let (Some(Reply::Array(Some(page))), Some(Reply::Bulk(Some(next)))) =
+                (reply.pop(), reply.pop())
+            else {
+                return Err(anyhow!("unexpected redis scan reply"));
+            };
+
+            for key in page {
+                if let Some(key) = key.into_bulk()?.and_then(|key| String::from_utf8(key).ok()) {
+                    keys.insert(key);
+                }
+            }
+
+            cursor = String::from_utf8(next).context("invalid redis scan cursor")?;
+            if cursor == "0" {
+                break;
+            }
+        }
+
+        let mut objects = Vec::new();
+
+        for full_key in keys {
+            let Some(key) = full_key.strip_prefix(&self.prefix) else {
+                continue;
+            };
+
+            // Keys in nested "directories" weren't stored by the server
+            if key.contains('/') {
+                continue;
+            }
+
+            let size = self
+                .command(&[b"HSTRLEN", full_key.as_bytes(), b"data"])
+                .await?
+                .into_integer()?;
+            let modified = self
+                .command(&[b"HGET", full_key.as_bytes(), b"modified"])
+                .await?
+                .into_bulk()?
+                .and_then(|value| String::from_utf8(value).ok())
+                .and_then(|value| value.parse().ok())
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+
+            objects.push(StoredObject {
+                key: key.to_string(),
+                size: size as u64,
+                modified,
+            });
+        }
+
+        Ok(objects)
+    }
+
+    async fn acquire_lease(&self, key: &str, owner: &str, ttl: Duration) -> anyhow::Result<bool> {
+        let key = self.object_key(key)?;
+        let ttl = ttl.as_millis().max(1).to_string();
+        let modified = unix_secs().to_string();
+
+        let acquired = self
+            .command(&[
+                b"EVAL",
+                ACQUIRE_LEASE.as_bytes(),
+                b"1",
+                key.as_bytes(),
+                owner.as_bytes(),
+                ttl.as_bytes(),
+                modified.as_bytes(),
+            ])
+            .await?
+            .into_integer()?;
+        Ok(acquired == 1)
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default()
+}
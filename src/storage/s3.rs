@@ -0,0 +1,409 @@
+use super::{
+    check_key, check_status, optional_env, required_env, uri_encode, Storage, StoredObject,
+    StoredReader, UtcTime,
+};
+use anyhow::Context;
+use axum::async_trait;
+use bytes::Bytes;
+use futures_util::TryStreamExt;
+use quick_xml::{events::Event, Reader};
+use reqwest::{Method, Response, StatusCode};
+use ring::hmac;
+use sha2::{Digest, Sha256};
+use std::{fmt, io, time::SystemTime};
+use url::Url;
+
+/// SHA-256 hex digest of an empty payload
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Stores objects in a S3 bucket, requests are signed using AWS Signature
+/// Version 4 which is also accepted by Google Cloud Storage (Using HMAC keys)
+/// and other S3 compatible services
+#[derive(Debug)]
+pub struct S3Storage {
+    client: reqwest::Client,
+    /// Scheme and host of the bucket endpoint (i.e "https://bucket.s3.us-east-1.amazonaws.com")
+    origin: String,
+    /// Host header of requests
+    host: String,
+    /// Path of the bucket on the host, empty for virtual hosted buckets
+    bucket_path: String,
+    /// Prefix of the keys of stored objects
+    prefix: String,
+    /// Region requests are signed for
+    region: String,
+    credentials: S3Credentials,
+}
+
+/// Access key requests are signed with
+struct S3Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    /// Token of temporary credentials
+    session_token: Option<String>,
+}
+
+impl fmt::Debug for S3Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl S3Storage {
+    /// Creates the storage for the S3 `bucket` storing objects under the
+    /// `prefix`, the credentials, region and endpoint (For S3 compatible
+    /// services) are read from the standard AWS environment variables
+    pub fn from_env(bucket: &str, prefix: &str) -> anyhow::Result<Self> {
+        let region = optional_env("AWS_REGION")
+            .or_else(|| optional_env("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| "us-east-1".to_string());
+
+        let endpoint = match optional_env("AWS_ENDPOINT_URL_S3")
+            .or_else(|| optional_env("AWS_ENDPOINT_URL"))
+        {
+            // Compatible services are addressed by path
+            Some(endpoint) => format!("{}/{bucket}", endpoint.trim_end_matches('/')),
+            // Buckets with dots don't match the wildcard certificate of virtual hosts
+            None if bucket.contains('.') => format!("https://s3.{region}.amazonaws.com/{bucket}"),
+            None => format!("https://{bucket}.s3.{region}.amazonaws.com"),
+        };
+
+        let credentials = S3Credentials {
+            access_key_id: required_env("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: required_env("AWS_SECRET_ACCESS_KEY")?,
+            session_token: optional_env("AWS_SESSION_TOKEN"),
+        };
+
+        Self::new(&endpoint, prefix, region, credentials)
+    }
+
+    /// Creates the storage for the Google Cloud Storage `bucket` storing
+    /// objects under the `prefix`, requests are signed with the HMAC key from
+    /// the "GCS_HMAC_ACCESS_ID" and "GCS_HMAC_SECRET" environment variables
+    pub fn gcs_from_env(bucket: &str, prefix: &str) -> anyhow::Result<Self> {
+        let endpoint = optional_env("GCS_ENDPOINT_URL")
+            .unwrap_or_else(|| "https://storage.googleapis.com".to_string());
+        let endpoint = format!("{}/{bucket}", endpoint.trim_end_matches('/'));
+
+        let credentials = S3Credentials {
+            access_key_id: required_env("GCS_HMAC_ACCESS_ID")?,
+            secret_access_key: required_env("GCS_HMAC_SECRET")?,
+            session_token: None,
+        };
+
+        Self::new(&endpoint, prefix, "auto".to_string(), credentials)
+    }
+
+    fn new(
+        endpoint: &str,
+        prefix: &str,
+        region: String,
+        credentials: S3Credentials,
+    ) -> anyhow::Result<Self> {
+        let url = Url::parse(endpoint).with_context(|| format!("invalid endpoint {endpoint}"))?;
+        let host = url
+            .host_str()
+            .with_context(|| format!("missing host in endpoint {endpoint}"))?;
+        let host = match url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_string(),
+        };
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            origin: format!("{}://{host}", url.scheme()),
+            host,
+            bucket_path: url.path().trim_end_matches('/').to_string(),
+            prefix: prefix.to_string(),
+            region,
+            credentials,
+        })
+    }
+
+    /// Path of the object with the `key`
+    fn object_path(&self, key: &str) -> anyhow::Result<String> {
+        check_key(key)?;
+        Ok(format!(
+            "{}/{}",
+            self.bucket_path,
+            uri_encode(&format!("{}{key}", self.prefix), true)
+        ))
+    }
+
+    /// Sends a signed request for the `path` with the `query` parameters
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Bytes,
+    ) -> anyhow::Result<Response> {
+        let payload_sha256 = match body.is_empty() {
+            true => EMPTY_SHA256.to_string(),
+            false => format!("{:x}", Sha256::digest(&body)),
+        };
+
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(name, value)| (uri_encode(name, false), uri_encode(value, false)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let headers = self.sign(
+            method.as_str(),
+            path,
+            &query,
+            &payload_sha256,
+            SystemTime::now(),
+        );
+
+        let url = match query.is_empty() {
+            true => format!("{}{path}", self.origin),
+            false => format!("{}{path}?{query}", self.origin),
+        };
+
+        let method_has_body = method == Method::PUT;
+        let mut request = self.client.request(method, url);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        if method_has_body {
+            request = request.body(body);
+        }
+
+        request.send().await.context("storage request failed")
+    }
+
+    /// Creates the headers signing a request with AWS Signature Version 4,
+    /// the `path` and `query` must already be encoded
+    fn sign(
+        &self,
+        method: &str,
+        path: &str,
+        query: &str,
+        payload_sha256: &str,
+        time: SystemTime,
+    ) -> Vec<(&'static str, String)> {
+        let time = UtcTime::from_system(time);
+        let date = format!("{:04}{:02}{:02}", time.year, time.month, time.day);
+        let amz_date = format!(
+            "{date}T{:02}{:02}{:02}Z",
+            time.hour, time.minute, time.second
+        );
+
+        // Headers to sign in alphabetical order
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", payload_sha256.to_string()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = self.credentials.session_token.as_ref() {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{method}\n{path}\n{query}\n{canonical_headers}\n{signed_headers}\n{payload_sha256}"
+        );
+
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{:x}",
+            Sha256::digest(canonical_request.as_bytes())
+        );
+
+        let sign = |key: &[u8], value: &str| {
+            hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), value.as_bytes())
+        };
+
+        let secret = format!("AWS4{}", self.credentials.secret_access_key);
+        let key = sign(secret.as_bytes(), &date);
+        let key = sign(key.as_ref(), &self.region);
+        let key = sign(key.as_ref(), "s3");
+        let key = sign(key.as_ref(), "aws4_request");
+        let signature = sign(key.as_ref(), &string_to_sign);
+        let signature: String = signature
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+
+        headers.push((
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                self.credentials.access_key_id
+            ),
+        ));
+
+        // Host is provided by the client
+        headers.remove(0);
+        headers
+    }
+
+    /// Lists a page of objects starting after the `continuation` token,
+    /// providing the token of the next page
+    async fn list_page(
+        &self,
+        continuation: Option<&str>,
+        objects: &mut Vec<StoredObject>,
+    ) -> anyhow::Result<Option<String>> {
+        let mut query = vec![("list-type", "2"), ("prefix", self.prefix.as_str())];
+        if let Some(continuation) = continuation {
+            query.push(("continuation-token", continuation));
+        }
+
+        let response = self
+            .send(
+                Method::GET,
+                &format!("{}/", self.bucket_path),
+                &query,
+                Bytes::new(),
+            )
+            .await?;
+        let body = check_status(response).await?.text().await?;
+
+        let mut reader = Reader::from_str(&body);
+        let mut element: Vec<u8> = Vec::new();
+        let mut object: Option<StoredObject> = None;
+        let mut next = None;
+
+        loop {
+            match reader.read_event().context("failed to parse object list")? {
+                Event::Start(start) => {
+                    element = start.name().as_ref().to_vec();
+                    if element == b"Contents" {
+                        object = Some(StoredObject {
+                            key: String::new(),
+                            size: 0,
+                            modified: None,
+                        });
+                    }
+                }
+                Event::Text(text) => {
+                    let text = text.unescape().context("failed to parse object list")?;
+
+                    match (element.as_slice(), object.as_mut()) {
+                        (b"Key", Some(object)) => object.key = text.into_owned(),
+                        (b"Size", Some(object)) => object.size = text.parse().unwrap_or_default(),
+                        (b"LastModified", Some(object)) => {
+                            object.modified = UtcTime::parse_rfc3339(&text)
+                        }
+                        (b"NextContinuationToken", None) => next = Some(text.into_owned()),
+                        _ => {}
+                    }
+                }
+                Event::End(end) => {
+                    element.clear();
+
+                    if end.name().as_ref() == b"Contents" {
+                        if let Some(mut object) = object.take() {
+                            if let Some(key) = object.key.strip_prefix(&self.prefix) {
+                                // Objects in nested "directories" weren't stored by the server
+                                if !key.contains('/') {
+                                    object.key = key.to_string();
+                                    objects.push(object);
+                                }
+                            }
+                        }
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        Ok(next)
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, bytes: Bytes) -> anyhow::Result<()> {
+        let path = self.object_path(key)?;
+        let response = self.send(Method::PUT, &path, &[], bytes).await?;
+        check_status(response).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>> {
+        let path = self.object_path(key)?;
+        let response = self.send(Method::GET, &path, &[], Bytes::new()).await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let bytes = check_status(response)
+            .await?
+            .bytes()
+            .await
+            .context("failed to read stored object")?;
+        Ok(Some(bytes))
+    }
+
+    async fn open(&self, key: &str) -> anyhow::Result<Option<StoredReader>> {
+        let path = self.object_path(key)?;
+        let response = self.send(Method::GET, &path, &[], Bytes::new()).await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let response = check_status(response).await?;
+        let size = response
+            .content_length()
+            .context("stored object missing its size")?;
+
+        Ok(Some(StoredReader {
+            size,
+            stream: Box::pin(response.bytes_stream().map_err(io::Error::other)),
+        }))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<bool> {
+        let path = self.object_path(key)?;
+
+        // Deleting reports success for missing objects
+        let response = self.send(Method::HEAD, &path, &[], Bytes::new()).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        check_status(response).await?;
+
+        let response = self.send(Method::DELETE, &path, &[], Bytes::new()).await?;
+        check_status(response).await?;
+        Ok(true)
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<StoredObject>> {
+        let mut objects = Vec::new();
+        let mut continuation = None;
+
+        loop {
+            continuation = self
+                .list_page(continuation.as_deref(), &mut objects)
+                .await?;
+
+            if continuation.is_none() {
+                return Ok(objects);
+            }
+        }
+    }
+}
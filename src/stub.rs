@@ -0,0 +1,203 @@
+use crate::{
+    convert::{
+        self, BundleArtifacts, ConvertBackend, ConvertError, ConvertOptions, ConvertedDocument,
+        DocumentInput, DocumentStats,
+    },
+    csv::CsvSheets,
+    odf::{DocumentStatistics, PageText},
+    pipeline::{self, PipelineManifest, PipelineOptions, PipelineStep, StepManifest},
+    runner::OfficeDetails,
+};
+use anyhow::Context;
+use bytes::Bytes;
+use std::io::Cursor;
+use tracing::debug;
+use zip::ZipWriter;
+
+/// Placeholder PDF provided as the output of every conversion
+static PLACEHOLDER_PDF: &[u8] = include_bytes!("stub.pdf");
+
+/// Placeholder PNG provided as the thumbnail of conversions
+static PLACEHOLDER_PNG: &[u8] = include_bytes!("stub.png");
+
+/// [ConvertBackend] that does not use LibreOffice, responding to every
+/// conversion with a placeholder PDF. Allows running the full API locally
+/// and in CI without a LibreOffice install.
+///
+/// Empty documents are treated as corrupted so error handling can still be
+/// exercised against the stub
+#[derive(Default)]
+pub struct StubBackend;
+
+impl StubBackend {
+    /// Reads the input document, failing for empty documents
+    fn read_input(input: DocumentInput) -> anyhow::Result<Bytes> {
+        let bytes = input.into_bytes()?;
+        if bytes.is_empty() {
+            return Err(ConvertError::Corrupted.into());
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl ConvertBackend for StubBackend {
+    fn details(&self) -> OfficeDetails {
+        OfficeDetails::default()
+    }
+
+    fn convert(
+        &mut self,
+        input: DocumentInput,
+        options: ConvertOptions,
+    ) -> anyhow::Result<ConvertedDocument> {
+        Self::read_input(input)?;
+        debug!(?options, "stub conversion");
+
+        // The placeholder spreadsheet is empty
+        if let Some(export) = options.spreadsheet.as_ref() {
+            return Ok(ConvertedDocument {
+                bytes: Bytes::new(),
+                file: None,
+                content_type: export.format.content_type(),
+                warnings: Vec::new(),
+            });
+        }
+
+        // The placeholder document is a single page image or an empty document
+        if let Some(target) = options.target_format.as_ref() {
+            let bytes = match target.extension {
+                "png" => Bytes::from_static(PLACEHOLDER_PNG),
+                _ => Bytes::new(),
+            };
+
+            return Ok(ConvertedDocument {
+                bytes,
+                file: None,
+                content_type: target.content_type,
+                warnings: Vec::new(),
+            });
+        }
+
+        // The placeholder spreadsheet has empty sheets
+        if let Some(csv) = options.csv.as_ref() {
+            if !csv.is_bundled() {
+                return Ok(ConvertedDocument {
+                    bytes: Bytes::new(),
+                    file: None,
+                    content_type: csv.content_type(),
+                    warnings: Vec::new(),
+                });
+            }
+
+            let sheets = match &csv.sheets {
+                CsvSheets::Selected(sheets) => sheets.clone(),
+                CsvSheets::All => vec![1],
+            };
+
+            let sheets = sheets
+                .into_iter()
+                .map(|sheet| (sheet, Vec::new()))
+                .collect();
+            return convert::csv_bundle(csv, sheets);
+        }
+
+        // The placeholder document has a single page without any text to redact
+        if let Some(region) = options
+            .redact
+            .iter()
+            .flat_map(|redact| &redact.regions)
+            .find(|region| region.page > 1)
+        {
+            return Err(ConvertError::InvalidPage {
+                page: region.page,
+                page_count: 1,
+            }
+            .into());
+        }
+
+        if options.is_bundled() {
+            let artifacts = BundleArtifacts {
+                thumbnail: options.with_thumbnail.then(|| PLACEHOLDER_PNG.to_vec()),
+                // The placeholder document has a single empty page
+                text: options.with_text.then(|| {
+                    vec![PageText {
+                        page: Some(1),
+                        sheet: None,
+                        text: String::new(),
+                    }]
+                }),
+                redactions: options.redact.as_ref().map(|redact| redact.report(&[])),
+            };
+
+            return convert::bundle(PLACEHOLDER_PDF, artifacts);
+        }
+
+        Ok(ConvertedDocument {
+            bytes: Bytes::from_static(PLACEHOLDER_PDF),
+            file: None,
+            content_type: "application/pdf",
+            warnings: Vec::new(),
+        })
+    }
+
+    fn pipeline(
+        &mut self,
+        input: DocumentInput,
+        steps: Vec<PipelineStep>,
+        _options: PipelineOptions,
+    ) -> anyhow::Result<Bytes> {
+        Self::read_input(input)?;
+
+        let mut manifest = PipelineManifest::default();
+        let mut artifacts = Vec::new();
+
+        // Every export produces the placeholder and every sheet export a single empty sheet
+        for (index, step) in steps.iter().enumerate() {
+            let number = index + 1;
+            let (name, bytes) = match step {
+                PipelineStep::Export(format) => {
+                    (format!("step-{number}.{format}"), PLACEHOLDER_PDF.to_vec())
+                }
+                PipelineStep::CsvSheets => (format!("step-{number}-sheet-1.csv"), Vec::new()),
+            };
+
+            manifest.steps.push(StepManifest {
+                step: number,
+                name: step.name().to_string(),
+                outputs: vec![name.clone()],
+                duration_ms: 0,
+            });
+            artifacts.push((name, bytes));
+        }
+
+        pipeline::bundle(&manifest, artifacts)
+    }
+
+    fn extract_assets(&mut self, input: DocumentInput) -> anyhow::Result<Bytes> {
+        Self::read_input(input)?;
+
+        // The placeholder document has no assets
+        let archive = ZipWriter::new(Cursor::new(Vec::new()))
+            .finish()
+            .context("failed to finish zip")?;
+
+        Ok(Bytes::from(archive.into_inner()))
+    }
+
+    fn extract_stats(&mut self, input: DocumentInput) -> anyhow::Result<DocumentStats> {
+        Self::read_input(input)?;
+
+        Ok(DocumentStats {
+            document_type: "text",
+            statistics: DocumentStatistics {
+                page_count: Some(1),
+                ..Default::default()
+            },
+        })
+    }
+
+    fn trim_memory(&mut self, _target: i32) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
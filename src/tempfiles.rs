@@ -0,0 +1,126 @@
+use libreofficekit::{DocUrl, OfficeError};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Prefix of the temporary files created by the server
+const TEMP_PREFIX: &str = "lo_native_";
+
+/// Temporary files used while converting a document
+pub struct ConvertTempFiles {
+    /// File the input document is written to
+    pub input: TempFile,
+    /// File the converted output is written to
+    pub output: TempFile,
+    /// File used for intermediate ODF package exports
+    pub package: TempFile,
+}
+
+/// Generates a random ID for use in temporary file names
+pub fn random_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(10)
+        .map(|value| value as char)
+        .collect::<String>()
+}
+
+/// Disk usage of the temporary files within a directory
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct TempUsage {
+    /// Number of temporary files
+    pub files: u64,
+    /// Total size of the temporary files in bytes
+    pub size_bytes: u64,
+}
+
+/// Measures the temporary files created by the server within `dir`, the
+/// directory may be shared so other files are not included
+pub fn usage(dir: &Path) -> std::io::Result<TempUsage> {
+    let mut usage = TempUsage::default();
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+
+        if !entry.file_name().to_string_lossy().starts_with(TEMP_PREFIX) {
+            continue;
+        }
+
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+
+        if metadata.is_file() {
+            usage.files += 1;
+            usage.size_bytes += metadata.len();
+        }
+    }
+
+    Ok(usage)
+}
+
+/// RAM-backed directory (i.e a tmpfs mount) small documents are written to
+/// while converting instead of the work directory
+#[derive(Debug, Clone)]
+pub struct MemoryWorkDir {
+    /// Path to the directory
+    pub path: PathBuf,
+    /// Largest document in bytes written to the directory
+    pub request_limit: u64,
+    /// Total size in bytes of the temporary files the directory may hold
+    pub total_limit: u64,
+}
+
+impl MemoryWorkDir {
+    /// Whether a document of `size` bytes is written to the directory, its
+    /// output is assumed to be as large as the document when checking the
+    /// space left within the total limit
+    pub fn fits(&self, size: u64) -> bool {
+        if size > self.request_limit {
+            return false;
+        }
+
+        let Ok(used) = usage(&self.path) else {
+            return false;
+        };
+
+        used.size_bytes.saturating_add(size.saturating_mul(2)) <= self.total_limit
+    }
+}
+
+/// Temporary file that will be removed when it's [Drop] is called
+#[derive(Debug)]
+pub struct TempFile {
+    /// Path to the temporary file
+    pub path: PathBuf,
+}
+
+impl TempFile {
+    pub fn doc_url(&self) -> Result<DocUrl, OfficeError> {
+        DocUrl::from_path(&self.path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if self.path.exists() {
+            dbg!(&self.path);
+            _ = std::fs::remove_file(&self.path)
+        }
+    }
+}
+
+/// Temporary directory that will be removed along with its contents when
+/// it's [Drop] is called
+pub struct TempDir {
+    /// Path to the temporary directory
+    pub path: PathBuf,
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if self.path.exists() {
+            _ = std::fs::remove_dir_all(&self.path)
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{config::parse_duration, runner::OfficeHandle};
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+
+/// Seconds in a day
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// Limits applied to the runner queue, either the configured defaults or
+/// the limits of the active [ThrottleWindow]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct QueueLimits {
+    /// Maximum time a request may wait for the runner
+    #[serde(rename = "max_queue_wait_ms", serialize_with = "serialize_millis")]
+    pub max_queue_wait: Option<Duration>,
+    /// Number of interactive requests admitted in a row while batch requests
+    /// are waiting before a batch request is admitted, [None] only admits
+    /// batch requests when no interactive requests are waiting
+    pub batch_max_skips: Option<u32>,
+}
+
+/// Daily time window (UTC) with its own queue limits, i.e restricting batch
+/// requests during business hours
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ThrottleWindow {
+    /// Start of the window in seconds since midnight
+    #[serde(serialize_with = "serialize_time")]
+    start: u64,
+    /// End of the window in seconds since midnight, windows ending before
+    /// they start end on the next day
+    #[serde(serialize_with = "serialize_time")]
+    end: u64,
+    /// Maximum time a request may wait for the runner within the window,
+    /// [None] keeps the default
+    #[serde(rename = "max_queue_wait_ms", serialize_with = "serialize_millis")]
+    max_queue_wait: Option<Duration>,
+    /// Batch admission limit within the window, [None] keeps the default
+    batch_max_skips: Option<BatchSkips>,
+}
+
+/// Batch admission limit of a [ThrottleWindow]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BatchSkips {
+    /// Batch requests are admitted after this many interactive requests
+    Limit(u32),
+    /// Batch requests wait until no interactive requests are waiting
+    Unlimited,
+}
+
+impl ThrottleWindow {
+    /// Checks if the window contains the `now` (Seconds since midnight)
+    fn contains(&self, now: u64) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&now)
+        } else {
+            now >= self.start || now < self.end
+        }
+    }
+
+    /// Applies the limits of the window on top of the `defaults`
+    fn limits(&self, defaults: QueueLimits) -> QueueLimits {
+        QueueLimits {
+            max_queue_wait: self.max_queue_wait.or(defaults.max_queue_wait),
+            batch_max_skips: match self.batch_max_skips {
+                Some(BatchSkips::Limit(limit)) => Some(limit),
+                Some(BatchSkips::Unlimited) => None,
+                None => defaults.batch_max_skips,
+            },
+        }
+    }
+}
+
+/// Parses a throttle window in the form "HH:MM-HH:MM,limit=value,..." (UTC)
+/// with the limits "max-queue-wait" (i.e "10s") and "batch-max-skips" (A
+/// number or "unlimited"), i.e "09:00-17:00,batch-max-skips=unlimited"
+pub fn window_arg(value: &str) -> Result<ThrottleWindow, String> {
+    let parse = || -> Option<ThrottleWindow> {
+        let mut parts = value.split(',');
+        let (start, end) = parts.next()?.split_once('-')?;
+
+        let mut window = ThrottleWindow {
+            start: parse_time(start.trim())?,
+            end: parse_time(end.trim())?,
+            max_queue_wait: None,
+            batch_max_skips: None,
+        };
+
+        if window.start == window.end {
+            return None;
+        }
+
+        for part in parts {
+            let (name, value) = part.split_once('=')?;
+
+            match (name.trim(), value.trim()) {
+                ("max-queue-wait", value) => window.max_queue_wait = Some(parse_duration(value)?),
+                ("batch-max-skips", "unlimited") => {
+                    window.batch_max_skips = Some(BatchSkips::Unlimited)
+                }
+                ("batch-max-skips", value) => {
+                    window.batch_max_skips = Some(BatchSkips::Limit(value.parse().ok()?))
+                }
+                _ => return None,
+            }
+        }
+
+        // Windows without limits would not change anything
+        (window.max_queue_wait.is_some() || window.batch_max_skips.is_some()).then_some(window)
+    };
+
+    parse().ok_or_else(|| {
+        "expected a UTC time window with limits like \"09:00-17:00,batch-max-skips=unlimited,max-queue-wait=10s\""
+            .to_string()
+    })
+}
+
+/// Parses a time in the form "HH:MM" into seconds since midnight
+fn parse_time(value: &str) -> Option<u64> {
+    let (hours, minutes) = value.split_once(':')?;
+    let hours: u64 = hours.parse().ok()?;
+    let minutes: u64 = minutes.parse().ok()?;
+
+    (hours < 24 && minutes < 60).then_some(hours * 60 * 60 + minutes * 60)
+}
+
+/// Provides the limits active at `now` (Seconds since midnight UTC), the
+/// first window containing `now` applies on top of the `defaults`
+pub fn active_limits(windows: &[ThrottleWindow], defaults: QueueLimits, now: u64) -> QueueLimits {
+    match windows
+        .iter()
+        .find(|window| window.contains(now % DAY_SECS))
+    {
+        Some(window) => window.limits(defaults),
+        None => defaults,
+    }
+}
+
+/// Applies the limits of the `windows` to the `office` queue as the windows
+/// start and end forever, outside of the windows the `defaults` apply
+pub async fn schedule(office: OfficeHandle, windows: Vec<ThrottleWindow>, defaults: QueueLimits) {
+    let mut current = defaults;
+
+    loop {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|value| value.as_secs() % DAY_SECS)
+            .unwrap_or_default();
+
+        let limits = active_limits(&windows, defaults, now);
+        if limits != current {
+            info!(?limits, "applying throttle limits");
+            office.set_limits(limits);
+            current = limits;
+        }
+
+        // Limits change when the next window starts or ends
+        let Some(next) = windows
+            .iter()
+            .flat_map(|window| [window.start, window.end])
+            .map(|boundary| (boundary + DAY_SECS - now) % DAY_SECS)
+            .filter(|secs| *secs > 0)
+            .min()
+        else {
+            return;
+        };
+
+        debug!(next, "waiting for next throttle window change");
+        tokio::time::sleep(Duration::from_secs(next)).await;
+    }
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    value: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    value.map(|value| value.as_millis()).serialize(serializer)
+}
+
+fn serialize_time<S: serde::Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&format_args!(
+        "{:02}:{:02}",
+        value / 3600,
+        value % 3600 / 60
+    ))
+}
+
+impl Serialize for BatchSkips {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            BatchSkips::Limit(limit) => serializer.serialize_u32(*limit),
+            BatchSkips::Unlimited => serializer.serialize_str("unlimited"),
+        }
+    }
+}
@@ -0,0 +1,9 @@
+/// Version of the server
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Git commit the server was built from, "unknown" when built outside
+/// of a git checkout without the "GIT_COMMIT" environment variable
+pub const GIT_COMMIT: &str = env!("LO_NATIVE_GIT_COMMIT");
+
+/// Time the server was built at (RFC 3339, UTC)
+pub const BUILD_TIMESTAMP: &str = env!("LO_NATIVE_BUILD_TIMESTAMP");
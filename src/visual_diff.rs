@@ -0,0 +1,159 @@
+use anyhow::{anyhow, Context};
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+
+/// Rendered page as 8-bit RGBA pixels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Raster {
+    /// Width of the page in pixels
+    pub width: u32,
+    /// Height of the page in pixels
+    pub height: u32,
+    /// RGBA pixels of the page row by row
+    pub pixels: Vec<u8>,
+}
+
+/// Differences between two renderings of a page
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    /// Pixels that differ between the renderings, pixels only covered by one
+    /// of the renderings are always counted
+    pub changed_pixels: u64,
+    /// Pixels covered by either rendering
+    pub total_pixels: u64,
+    /// Whether the renderings are different sizes
+    pub size_changed: bool,
+    /// Image highlighting the changed pixels in red over a faded copy of the
+    /// newer rendering
+    pub image: Raster,
+}
+
+impl Raster {
+    /// Decodes a PNG image, images of other color types are expanded to RGBA
+    pub fn decode_png(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut decoder = Decoder::new(bytes);
+        decoder.set_transformations(Transformations::normalize_to_color8());
+
+        let mut reader = decoder.read_info().context("invalid png image")?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut buffer)
+            .context("failed to decode png image")?;
+        let buffer = &buffer[..info.buffer_size()];
+
+        if info.bit_depth != BitDepth::Eight {
+            return Err(anyhow!("unsupported png bit depth {:?}", info.bit_depth));
+        }
+
+        let pixels = match info.color_type {
+            ColorType::Rgba => buffer.to_vec(),
+            ColorType::Rgb => buffer
+                .chunks_exact(3)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+                .collect(),
+            ColorType::GrayscaleAlpha => buffer
+                .chunks_exact(2)
+                .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+                .collect(),
+            ColorType::Grayscale => buffer
+                .iter()
+                .flat_map(|value| [*value, *value, *value, 255])
+                .collect(),
+            ColorType::Indexed => return Err(anyhow!("unexpected indexed png image")),
+        };
+
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    /// Encodes the pixels as a PNG image
+    pub fn encode_png(&self) -> anyhow::Result<Vec<u8>> {
+        let mut output = Vec::new();
+
+        let mut encoder = Encoder::new(&mut output, self.width, self.height);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_depth(BitDepth::Eight);
+
+        let mut writer = encoder
+            .write_header()
+            .context("failed to write png header")?;
+        writer
+            .write_image_data(&self.pixels)
+            .context("failed to write png image")?;
+        writer.finish().context("failed to finish png image")?;
+
+        Ok(output)
+    }
+
+    /// Provides the pixel at `x` and `y`, [None] outside of the raster
+    fn pixel(&self, x: u32, y: u32) -> Option<&[u8]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let offset = (y as usize * self.width as usize + x as usize) * 4;
+        self.pixels.get(offset..offset + 4)
+    }
+}
+
+impl Comparison {
+    /// Percentage of the pixels that changed
+    pub fn changed_percent(&self) -> f64 {
+        if self.total_pixels == 0 {
+            return 0.0;
+        }
+
+        self.changed_pixels as f64 / self.total_pixels as f64 * 100.0
+    }
+}
+
+/// Compares the `old` and `new` renderings of a page, pixels with channels
+/// differing by at most `tolerance` are treated as the same to ignore
+/// anti-aliasing noise
+pub fn compare(old: &Raster, new: &Raster, tolerance: u8) -> Comparison {
+    let width = old.width.max(new.width);
+    let height = old.height.max(new.height);
+
+    let mut changed_pixels = 0;
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+
+    for y in 0..height {
+        for x in 0..width {
+            let (old_pixel, new_pixel) = (old.pixel(x, y), new.pixel(x, y));
+
+            let changed = match (old_pixel, new_pixel) {
+                (Some(old_pixel), Some(new_pixel)) => old_pixel
+                    .iter()
+                    .zip(new_pixel)
+                    .any(|(old, new)| old.abs_diff(*new) > tolerance),
+                _ => true,
+            };
+
+            if changed {
+                changed_pixels += 1;
+                pixels.extend_from_slice(&[255, 0, 0, 255]);
+                continue;
+            }
+
+            // Unchanged pixels are faded towards white so changes stand out
+            let pixel = new_pixel.or(old_pixel).unwrap_or(&[255, 255, 255, 255]);
+            let luminance =
+                (pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000;
+            let faded = (255 - (255 - luminance) / 4) as u8;
+            pixels.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+
+    Comparison {
+        changed_pixels,
+        total_pixels: width as u64 * height as u64,
+        size_changed: old.width != new.width || old.height != new.height,
+        image: Raster {
+            width,
+            height,
+            pixels,
+        },
+    }
+}
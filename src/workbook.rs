@@ -0,0 +1,393 @@
+use crate::error::HttpError;
+use anyhow::{anyhow, Context};
+use axum::http::StatusCode;
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader, Writer,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{Cursor, Read, Write},
+    path::Path,
+};
+use thiserror::Error;
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+/// Directories of an ODF package containing macros
+const MACRO_DIRS: &[&str] = &["Basic/", "Scripts/"];
+
+/// Prefix of URLs that run macros (i.e macro hyperlinks and form actions)
+const SCRIPT_URL_PREFIX: &[u8] = b"vnd.sun.star.script:";
+
+/// Elements removed from the package along with their children when
+/// removing macros
+const MACRO_ELEMENTS: &[&[u8]] = &[b"office:scripts", b"office:event-listeners"];
+
+/// Cell attributes only meaningful for formulas
+const FORMULA_ATTRIBUTES: &[&[u8]] = &[
+    b"table:formula",
+    b"table:number-matrix-columns-spanned",
+    b"table:number-matrix-rows-spanned",
+];
+
+/// Options for exporting spreadsheets to another spreadsheet format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpreadsheetExport {
+    /// Format to export to
+    pub format: SpreadsheetFormat,
+    /// Replace formulas with their values and remove macros
+    pub data_only: bool,
+    /// Remove sheets that are hidden
+    pub remove_hidden_sheets: bool,
+}
+
+/// Spreadsheet format to export to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpreadsheetFormat {
+    Xlsx,
+    Ods,
+}
+
+/// Errors from invalid spreadsheet export options
+#[derive(Debug, Error)]
+pub enum SpreadsheetExportError {
+    /// Option was provided without exporting to a spreadsheet format
+    #[error("{0} is only used when exporting as xlsx or ods")]
+    NotSpreadsheetFormat(&'static str),
+}
+
+impl HttpError for SpreadsheetExportError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("invalid_options")
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            SpreadsheetExportError::NotSpreadsheetFormat(option) => {
+                Some(json!({ "option": option }))
+            }
+        }
+    }
+}
+
+impl SpreadsheetExport {
+    /// Parses the spreadsheet export options from a request, provides [None]
+    /// when the request `format` is not a spreadsheet format
+    pub fn parse(
+        format: Option<&str>,
+        data_only: Option<bool>,
+        remove_hidden_sheets: Option<bool>,
+    ) -> Result<Option<Self>, SpreadsheetExportError> {
+        let format = match format.map(str::to_ascii_lowercase).as_deref() {
+            Some("xlsx") => SpreadsheetFormat::Xlsx,
+            Some("ods") => SpreadsheetFormat::Ods,
+            _ => {
+                if data_only.is_some() {
+                    return Err(SpreadsheetExportError::NotSpreadsheetFormat("data_only"));
+                }
+
+                if remove_hidden_sheets.is_some() {
+                    return Err(SpreadsheetExportError::NotSpreadsheetFormat(
+                        "remove_hidden_sheets",
+                    ));
+                }
+
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(Self {
+            format,
+            data_only: data_only.unwrap_or_default(),
+            remove_hidden_sheets: remove_hidden_sheets.unwrap_or_default(),
+        }))
+    }
+
+    /// Whether the spreadsheet is sanitized before it is exported
+    pub fn is_sanitized(&self) -> bool {
+        self.data_only || self.remove_hidden_sheets
+    }
+
+    /// Creates a fingerprint of the options for use in cache keys
+    pub(crate) fn cache_fingerprint(&self) -> String {
+        format!(
+            "format={} data_only={} remove_hidden_sheets={}",
+            self.format.extension(),
+            self.data_only,
+            self.remove_hidden_sheets
+        )
+    }
+}
+
+impl SpreadsheetFormat {
+    /// File extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SpreadsheetFormat::Xlsx => "xlsx",
+            SpreadsheetFormat::Ods => "ods",
+        }
+    }
+
+    /// Mime type of the format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            SpreadsheetFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+            SpreadsheetFormat::Ods => "application/vnd.oasis.opendocument.spreadsheet",
+        }
+    }
+}
+
+/// Sanitizes the ODS package at `path` for sharing externally, creating a new
+/// package. When `data_only` is set formulas are replaced by their last
+/// calculated values and macros (Basic libraries, scripts and event bindings)
+/// are removed, when `remove_hidden_sheets` is set hidden sheets are removed
+pub fn sanitize_package(path: &Path, options: &SpreadsheetExport) -> anyhow::Result<Vec<u8>> {
+    let file = File::open(path).context("failed to open odf package")?;
+    let mut archive = ZipArchive::new(file).context("failed to read odf package")?;
+
+    let mut output = ZipWriter::new(Cursor::new(Vec::new()));
+
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .context("failed to read odf package entry")?;
+
+        let name = entry.name().to_string();
+
+        if options.data_only && MACRO_DIRS.iter().any(|dir| name.starts_with(dir)) {
+            continue;
+        }
+
+        let rewritten = match name.as_str() {
+            "content.xml" | "styles.xml" => {
+                let mut value = String::new();
+                entry
+                    .read_to_string(&mut value)
+                    .with_context(|| format!("failed to read {name} from odf package"))?;
+
+                Some(sanitize_xml(&value, options).with_context(|| format!("invalid {name}"))?)
+            }
+            "META-INF/manifest.xml" if options.data_only => {
+                let mut value = String::new();
+                entry
+                    .read_to_string(&mut value)
+                    .context("failed to read manifest from odf package")?;
+
+                Some(remove_macro_manifest_entries(&value).context("invalid manifest")?)
+            }
+            _ => None,
+        };
+
+        match rewritten {
+            Some(bytes) => {
+                output
+                    .start_file(name, SimpleFileOptions::default())
+                    .context("failed to create zip entry")?;
+                output
+                    .write_all(&bytes)
+                    .context("failed to write zip entry")?;
+            }
+            None => {
+                drop(entry);
+
+                // The "mimetype" entry must be stored first and uncompressed, raw
+                // copies keep the order and compression of the original package
+                let entry = archive
+                    .by_index_raw(index)
+                    .context("failed to read odf package entry")?;
+                output
+                    .raw_copy_file(entry)
+                    .context("failed to copy odf package entry")?;
+            }
+        }
+    }
+
+    let output = output.finish().context("failed to finish zip")?;
+
+    Ok(output.into_inner())
+}
+
+/// Sanitizes the `content.xml` or `styles.xml` of a spreadsheet package
+fn sanitize_xml(value: &str, options: &SpreadsheetExport) -> anyhow::Result<Vec<u8>> {
+    let mut reader = Reader::from_str(value);
+    let mut writer = Writer::new(Vec::new());
+
+    // Table styles that hide a sheet, styles are declared before the sheets
+    let mut hidden_styles: HashSet<String> = HashSet::new();
+    let mut current_style: Option<String> = None;
+
+    // Depth within a removed element, its children are removed along with it
+    let mut skip_depth = 0usize;
+
+    let mut sheets = 0usize;
+    let mut removed_sheets = 0usize;
+
+    loop {
+        match reader.read_event().context("failed to parse xml")? {
+            Event::Eof => break,
+            Event::Start(_) if skip_depth > 0 => skip_depth += 1,
+            Event::End(_) if skip_depth > 0 => skip_depth -= 1,
+            _ if skip_depth > 0 => {}
+            Event::Start(element) => {
+                track_style(&element, &mut current_style, &mut hidden_styles);
+
+                if is_removed(&element, options, &hidden_styles) {
+                    if element.name().as_ref() == b"table:table" {
+                        sheets += 1;
+                        removed_sheets += 1;
+                    }
+
+                    skip_depth = 1;
+                    continue;
+                }
+
+                if element.name().as_ref() == b"table:table" {
+                    sheets += 1;
+                }
+
+                writer.write_event(Event::Start(sanitize_element(&element, options)?))?;
+            }
+            Event::Empty(element) => {
+                track_style(&element, &mut current_style, &mut hidden_styles);
+
+                if is_removed(&element, options, &hidden_styles) {
+                    continue;
+                }
+
+                writer.write_event(Event::Empty(sanitize_element(&element, options)?))?;
+            }
+            Event::End(element) => {
+                if element.name().as_ref() == b"style:style" {
+                    current_style = None;
+                }
+
+                writer.write_event(Event::End(element))?;
+            }
+            event => writer.write_event(event)?,
+        }
+    }
+
+    if sheets > 0 && sheets == removed_sheets {
+        return Err(anyhow!("spreadsheet has no visible sheets"));
+    }
+
+    Ok(writer.into_inner())
+}
+
+/// Tracks the table styles that hide their sheet (table:display="false")
+fn track_style(
+    element: &BytesStart<'_>,
+    current_style: &mut Option<String>,
+    hidden_styles: &mut HashSet<String>,
+) {
+    match element.name().as_ref() {
+        b"style:style" => {
+            *current_style = (attribute(element, b"style:family").as_deref() == Some("table"))
+                .then(|| attribute(element, b"style:name"))
+                .flatten();
+        }
+        b"style:table-properties" => {
+            if let (Some(style), Some("false")) = (
+                current_style.as_ref(),
+                attribute(element, b"table:display").as_deref(),
+            ) {
+                hidden_styles.insert(style.clone());
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Checks if the `element` is removed along with its children
+fn is_removed(
+    element: &BytesStart<'_>,
+    options: &SpreadsheetExport,
+    hidden_styles: &HashSet<String>,
+) -> bool {
+    let name = element.name();
+    let name = name.as_ref();
+
+    if options.data_only && MACRO_ELEMENTS.contains(&name) {
+        return true;
+    }
+
+    options.remove_hidden_sheets
+        && name == b"table:table"
+        && attribute(element, b"table:style-name")
+            .is_some_and(|style| hidden_styles.contains(&style))
+}
+
+/// Removes the formulas and macro URLs from the attributes of the `element`
+/// when exporting data only
+fn sanitize_element(
+    element: &BytesStart<'_>,
+    options: &SpreadsheetExport,
+) -> anyhow::Result<BytesStart<'static>> {
+    if !options.data_only {
+        return Ok(element.clone().into_owned());
+    }
+
+    let mut sanitized = element.clone().into_owned();
+    sanitized.clear_attributes();
+
+    for attribute in element.attributes() {
+        let attribute = attribute.context("invalid xml attribute")?;
+
+        let is_formula = FORMULA_ATTRIBUTES.contains(&attribute.key.as_ref());
+        let is_script = attribute.value.starts_with(SCRIPT_URL_PREFIX);
+
+        if !is_formula && !is_script {
+            sanitized.push_attribute(attribute);
+        }
+    }
+
+    Ok(sanitized)
+}
+
+/// Removes the entries for macro files from the package `manifest`
+fn remove_macro_manifest_entries(manifest: &str) -> anyhow::Result<Vec<u8>> {
+    let mut reader = Reader::from_str(manifest);
+    let mut writer = Writer::new(Vec::new());
+    let mut skip_depth = 0usize;
+
+    loop {
+        match reader.read_event().context("failed to parse xml")? {
+            Event::Eof => break,
+            Event::Start(_) if skip_depth > 0 => skip_depth += 1,
+            Event::End(_) if skip_depth > 0 => skip_depth -= 1,
+            _ if skip_depth > 0 => {}
+            Event::Start(element) if is_macro_entry(&element) => skip_depth = 1,
+            Event::Empty(element) if is_macro_entry(&element) => {}
+            event => writer.write_event(event)?,
+        }
+    }
+
+    Ok(writer.into_inner())
+}
+
+/// Checks if the manifest `element` is the entry for a macro file
+fn is_macro_entry(element: &BytesStart<'_>) -> bool {
+    element.name().as_ref() == b"manifest:file-entry"
+        && attribute(element, b"manifest:full-path")
+            .is_some_and(|path| MACRO_DIRS.iter().any(|dir| path.starts_with(dir)))
+}
+
+/// Reads the unescaped value of the attribute with the `name`
+fn attribute(element: &BytesStart<'_>, name: &[u8]) -> Option<String> {
+    element
+        .try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|attribute| attribute.unescape_value().ok())
+        .map(|value| value.into_owned())
+}
@@ -0,0 +1,5542 @@
+//! HTTP integration tests running the server routes against a fake
+//! [ConvertBackend], no LibreOffice install is required
+
+use arc_swap::ArcSwap;
+use axum::{
+    http::{HeaderMap, HeaderName},
+    routing::{get, post},
+    Json,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use bytes::Bytes;
+use futures_util::{StreamExt, TryStreamExt};
+use libreofficekit::{FilterType, FilterTypes};
+use office_convert_client::{
+    load::LoadBalancerTiming, ConvertHints, ConvertOffice, ErrorCode, JobEvent,
+    JobState as ClientJobState, LoadBalanceError, LoadBalanceStrategy, OfficeConvertClient,
+    OfficeConvertLoadBalancer, RequestError, WorkTotals,
+};
+use office_convert_server::{
+    alerts::Alerts,
+    attestation::AttestationKey,
+    auth::{self, ApiKey, ApiKeys, AuthError, Authorizer, ClientLimits, KeyLimits, Rate},
+    cache::ResultCache,
+    config::{
+        self, OfficeLogLevel, QueueConfig, ServerConfig, ServerInfo, TrimConfig, TrimPolicy,
+        WatchdogConfig,
+    },
+    convert::{
+        self, ConvertBackend, ConvertError, ConvertOptions, ConvertedDocument, DocumentInput,
+        DocumentStats,
+    },
+    detect::{self, MismatchPolicy},
+    diagnostics::LogToggle,
+    embedded::EmbeddedLimits,
+    formats::TargetFormat,
+    jobs::{JobState, JobStore},
+    odf, office_errors,
+    pdf_export::PdfExportOptions,
+    pipeline::{PipelineOptions, PipelineStep},
+    presign::UrlSigner,
+    priority::{self, ApiKeyTier, PriorityGate, PriorityTier},
+    profile::{self, DictionaryKind, FontReplacement, ProfileSettings},
+    proxy::{self, TrustedProxies},
+    quarantine::Quarantine,
+    redact::{self, RedactOptions},
+    retention::OutputRetention,
+    runner::{create_office_pool, create_office_runner, warm_standby, OfficeDetails, OfficeHandle},
+    server,
+    service::{ConvertRequest, ConvertService},
+    signing::SigningKey,
+    storage::{self, LocalStorage, Storage, StorageLocation},
+    stub::StubBackend,
+    tempfiles::{random_id, MemoryWorkDir, TempFile},
+    throttle::{self, QueueLimits},
+    visual_diff::{self, Raster},
+    workbook::{self, SpreadsheetExport, SpreadsheetFormat},
+};
+use reqwest::multipart::{Form, Part};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env::temp_dir,
+    io::{Read, Write},
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{mpsc, Semaphore};
+use tower::{Service, ServiceBuilder, ServiceExt};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+/// Output produced by the fake backend for every conversion
+const FAKE_PDF: &[u8] = b"%PDF-1.4\n1 0 obj << /Type /Page >> endobj\n%%EOF";
+
+/// Input the fake backend treats as an encrypted document
+const ENCRYPTED: &[u8] = b"encrypted";
+
+/// Password the fake backend opens encrypted documents with
+const PASSWORD: &str = "secret";
+
+/// Input the fake backend treats as a corrupted document
+const CORRUPTED: &[u8] = b"corrupted";
+
+/// Input the fake backend panics on, simulating a runner failure
+const PANICS: &[u8] = b"panics";
+
+/// Backend producing fixed outputs without loading documents
+struct FakeBackend;
+
+impl FakeBackend {
+    fn check_input(input: DocumentInput) -> anyhow::Result<Bytes> {
+        let bytes = input.into_bytes()?;
+
+        match bytes.as_ref() {
+            ENCRYPTED => Err(ConvertError::Encrypted.into()),
+            CORRUPTED => Err(ConvertError::Corrupted.into()),
+            PANICS => panic!("fake backend failure"),
+            _ => Ok(bytes),
+        }
+    }
+}
+
+impl ConvertBackend for FakeBackend {
+    fn details(&self) -> OfficeDetails {
+        OfficeDetails::default()
+    }
+
+    fn convert(
+        &mut self,
+        input: DocumentInput,
+        options: ConvertOptions,
+    ) -> anyhow::Result<ConvertedDocument> {
+        let bytes = input.into_bytes()?;
+
+        match (bytes.as_ref(), options.password) {
+            (ENCRYPTED, Some(password)) if password.0 == PASSWORD => {}
+            (ENCRYPTED, Some(_)) => return Err(ConvertError::WrongPassword.into()),
+            _ => {
+                Self::check_input(DocumentInput::Bytes(bytes))?;
+            }
+        }
+
+        Ok(ConvertedDocument {
+            bytes: Bytes::from_static(FAKE_PDF),
+            file: None,
+            content_type: "application/pdf",
+            warnings: Vec::new(),
+        })
+    }
+
+    fn pipeline(
+        &mut self,
+        input: DocumentInput,
+        _steps: Vec<PipelineStep>,
+        _options: PipelineOptions,
+    ) -> anyhow::Result<Bytes> {
+        Self::check_input(input)
+    }
+
+    fn extract_assets(&mut self, input: DocumentInput) -> anyhow::Result<Bytes> {
+        Self::check_input(input)
+    }
+
+    fn extract_stats(&mut self, input: DocumentInput) -> anyhow::Result<DocumentStats> {
+        Self::check_input(input)?;
+        anyhow::bail!("stats are not supported by the fake backend")
+    }
+
+    fn trim_memory(&mut self, _target: i32) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn take_log(&mut self) -> Vec<String> {
+        vec!["Error: fake office log".to_string()]
+    }
+}
+
+/// Backend failing every conversion, simulating an unhealthy office
+struct BrokenBackend;
+
+impl ConvertBackend for BrokenBackend {
+    fn details(&self) -> OfficeDetails {
+        OfficeDetails::default()
+    }
+
+    fn convert(
+        &mut self,
+        _input: DocumentInput,
+        _options: ConvertOptions,
+    ) -> anyhow::Result<ConvertedDocument> {
+        anyhow::bail!("office crashed")
+    }
+
+    fn pipeline(
+        &mut self,
+        _input: DocumentInput,
+        _steps: Vec<PipelineStep>,
+        _options: PipelineOptions,
+    ) -> anyhow::Result<Bytes> {
+        anyhow::bail!("office crashed")
+    }
+
+    fn extract_assets(&mut self, _input: DocumentInput) -> anyhow::Result<Bytes> {
+        anyhow::bail!("office crashed")
+    }
+
+    fn extract_stats(&mut self, _input: DocumentInput) -> anyhow::Result<DocumentStats> {
+        anyhow::bail!("office crashed")
+    }
+
+    fn trim_memory(&mut self, _target: i32) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+fn server_config() -> ServerConfig {
+    ServerConfig {
+        admin_token: None,
+        allow_macros: false,
+        attestation_key: None,
+        require_attestation: false,
+        signing_key: None,
+        min_upload_size: None,
+        spill_threshold: None,
+        io_limit: None,
+        work_dir: temp_dir(),
+        download_idle_timeout: None,
+        download_spill_threshold: None,
+        embedded_limits: EmbeddedLimits::default(),
+        format_mismatch: MismatchPolicy::Ignore,
+        embed_standard_fonts: false,
+        output_dir: None,
+        url_signer: None,
+        quarantine: None,
+        jobs: None,
+        api_key_tiers: HashMap::new(),
+        api_keys: None,
+        authorizer: None,
+        client_limits: None,
+        trusted_proxies: TrustedProxies::default(),
+        disabled_endpoints: Vec::new(),
+        info: ServerInfo::default(),
+        log_toggle: None,
+        office_log: OfficeLogLevel::Off,
+    }
+}
+
+fn trim_config() -> TrimConfig {
+    TrimConfig {
+        policy: TrimPolicy::Never,
+        target: 1000,
+        gc_target: 2000,
+        every: 1,
+        idle_after: Duration::from_secs(30),
+        memory_pause_threshold: None,
+        idle_shutdown: None,
+    }
+}
+
+/// Starts a server using the fake backend on a random port, providing
+/// the base URL of the server
+async fn start_server(server_config: ServerConfig) -> String {
+    start_server_with(|| FakeBackend, server_config).await
+}
+
+/// Starts a server using the backend from `create_backend` on a random
+/// port, providing the base URL of the server
+async fn start_server_with<B, F>(create_backend: F, server_config: ServerConfig) -> String
+where
+    B: ConvertBackend + 'static,
+    F: Fn() -> B + Send + 'static,
+{
+    start_server_recovering(create_backend, server_config, None).await
+}
+
+/// Starts a server using the backend from `create_backend` that runs the
+/// self test after `recover_after` consecutive failures
+async fn start_server_recovering<B, F>(
+    create_backend: F,
+    server_config: ServerConfig,
+    recover_after: Option<u64>,
+) -> String
+where
+    B: ConvertBackend + 'static,
+    F: Fn() -> B + Send + 'static,
+{
+    let (office_details, office_handle) = create_office_runner(
+        move |_| Ok(create_backend()),
+        trim_config(),
+        QueueConfig::default(),
+        WatchdogConfig::default(),
+        recover_after,
+    )
+    .await
+    .expect("failed to start runner");
+
+    let office_details = Arc::new(ArcSwap::from_pointee(office_details));
+    let app = server::router(office_handle, office_details, server_config, None);
+
+    serve(app).await
+}
+
+/// Serves the `app` on a random port, providing the base URL of the server
+async fn serve(app: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("failed to bind server");
+    let address = listener.local_addr().expect("missing server address");
+
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+    });
+
+    format!("http://{address}")
+}
+
+fn file_part(bytes: &'static [u8], name: &'static str) -> Part {
+    Part::bytes(bytes).file_name(name)
+}
+
+#[tokio::test]
+async fn convert_responds_with_verified_output() {
+    let host = start_server(server_config()).await;
+    let client = OfficeConvertClient::new(host)
+        .unwrap()
+        .with_verify_checksum(true);
+
+    let output = client
+        .convert_with_request_id(b"document".to_vec(), "test-request")
+        .await
+        .expect("conversion failed");
+
+    assert_eq!(output.as_ref(), FAKE_PDF);
+}
+
+#[tokio::test]
+async fn convert_writes_uploads_with_io_limit() {
+    let host = start_server(ServerConfig {
+        io_limit: Some(Arc::new(Semaphore::new(1))),
+        ..server_config()
+    })
+    .await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    let (first, second) = tokio::join!(
+        client.convert_with_request_id(b"first".to_vec(), "first-request"),
+        client.convert_with_request_id(b"second".to_vec(), "second-request"),
+    );
+
+    assert_eq!(first.expect("conversion failed").as_ref(), FAKE_PDF);
+    assert_eq!(second.expect("conversion failed").as_ref(), FAKE_PDF);
+}
+
+/// Names of the downloads spilled to the `work_dir` not yet removed
+fn spilled_downloads(work_dir: &std::path::Path) -> Vec<String> {
+    std::fs::read_dir(work_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("lo_native_download_"))
+        .collect()
+}
+
+#[tokio::test]
+async fn downloads_spill_to_disk() {
+    let work_dir = temp_dir().join(format!("lo_native_test_{}", random_id()));
+    std::fs::create_dir_all(&work_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        work_dir: work_dir.clone(),
+        download_spill_threshold: Some(1),
+        ..server_config()
+    })
+    .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(Form::new().part("file", file_part(b"document", "document.docx")))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.content_length(), Some(FAKE_PDF.len() as u64));
+    assert_eq!(response.bytes().await.unwrap().as_ref(), FAKE_PDF);
+
+    // Spilled downloads are removed once sent
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(spilled_downloads(&work_dir).is_empty());
+
+    std::fs::remove_dir_all(&work_dir).unwrap();
+}
+
+#[tokio::test]
+async fn idle_downloads_expire() {
+    let work_dir = temp_dir().join(format!("lo_native_test_{}", random_id()));
+    std::fs::create_dir_all(&work_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        work_dir: work_dir.clone(),
+        download_idle_timeout: Some(Duration::from_millis(200)),
+        download_spill_threshold: Some(1),
+        ..server_config()
+    })
+    .await;
+
+    // Large enough to not fit in the socket buffers, the fake backend
+    // responds with the document it received
+    let document = vec![b'a'; 64 * 1024 * 1024];
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/extract-assets"))
+        .multipart(Form::new().part("file", Part::bytes(document).file_name("large.mht")))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.content_length(), Some(64 * 1024 * 1024));
+    assert_eq!(spilled_downloads(&work_dir).len(), 1);
+
+    // Stop reading the download until it expires
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert!(spilled_downloads(&work_dir).is_empty());
+    assert!(response.bytes().await.is_err());
+
+    std::fs::remove_dir_all(&work_dir).unwrap();
+}
+
+#[tokio::test]
+async fn convert_reports_known_errors() {
+    let host = start_server(server_config()).await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    for (input, expected) in [
+        (ENCRYPTED, ErrorCode::Encrypted),
+        (CORRUPTED, ErrorCode::Corrupted),
+    ] {
+        let err = client
+            .convert_with_request_id(input.to_vec(), "test-request")
+            .await
+            .expect_err("conversion should fail");
+
+        assert_eq!(err.status().map(|status| status.as_u16()), Some(422));
+        assert_eq!(err.code(), Some(&expected));
+    }
+}
+
+#[tokio::test]
+async fn failed_conversions_include_the_office_log() {
+    let host = start_server(ServerConfig {
+        office_log: OfficeLogLevel::Warn,
+        ..server_config()
+    })
+    .await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    let err = client
+        .convert_with_request_id(CORRUPTED.to_vec(), "test-request")
+        .await
+        .expect_err("conversion should fail");
+    assert_eq!(err.response().unwrap().log, ["Error: fake office log"]);
+
+    // The office log is not included unless enabled
+    let host = start_server(server_config()).await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    let err = client
+        .convert_with_request_id(CORRUPTED.to_vec(), "test-request")
+        .await
+        .expect_err("conversion should fail");
+    assert!(err.response().unwrap().log.is_empty());
+}
+
+#[tokio::test]
+async fn convert_opens_encrypted_files_with_password() {
+    let host = start_server(server_config()).await;
+    let client = reqwest::Client::new();
+
+    let convert = |password: &'static str| {
+        client.post(format!("{host}/convert")).multipart(
+            Form::new()
+                .part("file", file_part(ENCRYPTED, "secret.docx"))
+                .text("password", password),
+        )
+    };
+
+    let response = convert(PASSWORD).send().await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(response.bytes().await.unwrap().as_ref(), FAKE_PDF);
+
+    let response = convert("incorrect").send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "wrong_password");
+
+    let response = convert("").send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_password");
+}
+
+#[tokio::test]
+async fn convert_echoes_request_id() {
+    let host = start_server(server_config()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .header("x-request-id", "echo-me")
+        .multipart(Form::new().part("file", file_part(b"document", "a.docx")))
+        .send()
+        .await
+        .unwrap();
+
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["x-request-id"], "echo-me");
+    assert!(response.headers().contains_key("x-content-sha256"));
+}
+
+#[tokio::test]
+async fn convert_sets_requested_disposition() {
+    let host = start_server(server_config()).await;
+
+    for (disposition, name, expected) in [
+        ("inline", "report.docx", "inline; filename=\"report.pdf\""),
+        (
+            "attachment",
+            "résumé.docx",
+            "attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf",
+        ),
+    ] {
+        let response = reqwest::Client::new()
+            .post(format!("{host}/convert"))
+            .multipart(
+                Form::new()
+                    .part("file", file_part(b"document", name))
+                    .text("disposition", disposition),
+            )
+            .send()
+            .await
+            .unwrap();
+
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()["content-disposition"], expected);
+    }
+}
+
+#[tokio::test]
+async fn convert_sanitizes_hostile_file_names() {
+    let host = start_server(server_config()).await;
+    let long_name = format!("{}.docx", "a".repeat(300));
+
+    for (name, expected) in [
+        // Decomposed names are normalized to their composed form
+        (
+            "re\u{301}sume\u{301}.docx".to_string(),
+            "attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+                .to_string(),
+        ),
+        // Directories and invisible overrides are removed, reserved characters replaced
+        (
+            "../../etc/\u{202E}fdp<>.exe".to_string(),
+            "attachment; filename=\"fdp__.pdf\"".to_string(),
+        ),
+        // Long names are shortened
+        (
+            long_name,
+            format!("attachment; filename=\"{}.pdf\"", "a".repeat(195)),
+        ),
+    ] {
+        let response = reqwest::Client::new()
+            .post(format!("{host}/convert"))
+            .multipart(
+                Form::new()
+                    .part("file", Part::bytes(&b"document"[..]).file_name(name))
+                    .text("disposition", "attachment"),
+            )
+            .send()
+            .await
+            .unwrap();
+
+        assert!(response.status().is_success());
+        assert_eq!(response.headers()["content-disposition"], expected.as_str());
+    }
+
+    // Manifests record the sanitized names
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "C:\\uploads\\first.docx"))
+                .part("file", file_part(b"document", "...")),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    let bytes = response.bytes().await.unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+
+    let mut manifest = String::new();
+    archive
+        .by_name("manifest.json")
+        .unwrap()
+        .read_to_string(&mut manifest)
+        .unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+
+    assert_eq!(manifest["files"][0]["input"], "first.docx");
+    assert!(manifest["files"][1]["input"].is_null());
+    assert!(archive.by_name("first.pdf").is_ok());
+}
+
+#[tokio::test]
+async fn convert_verifies_upload_signatures() {
+    let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+    let public_key = STANDARD.encode(key_pair.public_key().as_ref());
+    let sign = |contents: &[u8]| STANDARD.encode(key_pair.sign(contents).as_ref());
+
+    let host = start_server(ServerConfig {
+        attestation_key: Some(AttestationKey::parse(&public_key).unwrap()),
+        require_attestation: true,
+        ..server_config()
+    })
+    .await;
+
+    for (signature, status, code) in [
+        (Some(sign(b"document")), 200, None),
+        (Some(sign(b"tampered")), 403, Some("invalid_signature")),
+        (
+            Some("not base64!".to_string()),
+            400,
+            Some("invalid_signature"),
+        ),
+        (None, 400, Some("missing_signature")),
+    ] {
+        let mut form = Form::new().part("file", file_part(b"document", "a.docx"));
+        if let Some(signature) = signature {
+            form = form.text("signature", signature);
+        }
+
+        let response = reqwest::Client::new()
+            .post(format!("{host}/convert"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), status);
+
+        if let Some(code) = code {
+            let body: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(body["code"], code);
+        }
+    }
+
+    // Signatures are rejected when no key is configured
+    let host = start_server(server_config()).await;
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "a.docx"))
+                .text("signature", sign(b"document")),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "signatures_disabled");
+}
+
+#[tokio::test]
+async fn convert_signs_results() {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let host = start_server(ServerConfig {
+        signing_key: Some(SigningKey::parse(&STANDARD.encode(pkcs8.as_ref())).unwrap()),
+        ..server_config()
+    })
+    .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(Form::new().part("file", file_part(b"document", "a.docx")))
+        .send()
+        .await
+        .unwrap();
+
+    assert!(response.status().is_success());
+
+    let sha256 = response.headers()["x-content-sha256"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let signature = response.headers()["x-result-signature"]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let jwks: serde_json::Value = reqwest::get(format!("{host}/.well-known/jwks.json"))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let public_key = URL_SAFE_NO_PAD
+        .decode(jwks["keys"][0]["x"].as_str().unwrap())
+        .unwrap();
+
+    let (signing_input, encoded_signature) = signature.rsplit_once('.').unwrap();
+    UnparsedPublicKey::new(&ED25519, &public_key)
+        .verify(
+            signing_input.as_bytes(),
+            &URL_SAFE_NO_PAD.decode(encoded_signature).unwrap(),
+        )
+        .expect("signature should be valid");
+
+    let (_, payload) = signing_input.split_once('.').unwrap();
+    let claims: serde_json::Value =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).unwrap()).unwrap();
+
+    assert_eq!(claims["sha256"], sha256);
+    assert_eq!(claims["content_type"], "application/pdf");
+    assert_eq!(
+        claims["input_sha256"],
+        format!("{:x}", Sha256::digest(b"document"))
+    );
+    assert_eq!(claims["options"]["split_sheets"], false);
+
+    // The key is not available without result signing
+    let host = start_server(server_config()).await;
+    let response = reqwest::get(format!("{host}/.well-known/jwks.json"))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn convert_rejects_disabled_macros() {
+    let host = start_server(server_config()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "a.docx"))
+                .text("run_macro", "Standard.Module1.Main"),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "macros_disabled");
+}
+
+#[tokio::test]
+async fn convert_rejects_macros_without_admin_token() {
+    let host = start_server(ServerConfig {
+        allow_macros: true,
+        admin_token: Some("admin-token".to_string()),
+        ..server_config()
+    })
+    .await;
+
+    for token in [None, Some("admin-toke"), Some("admin-token-2"), Some("")] {
+        let mut request = reqwest::Client::new().post(format!("{host}/convert"));
+        if let Some(token) = token {
+            request = request.header("x-admin-token", token);
+        }
+
+        let response = request
+            .multipart(
+                Form::new()
+                    .part("file", file_part(b"document", "a.docx"))
+                    .text("run_macro", "Standard.Module1.Main"),
+            )
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 403, "{token:?}");
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "macros_forbidden");
+    }
+
+    // Macros are run from the loaded document rather than the application
+    assert_eq!(
+        convert::document_macro_url(
+            Path::new("/tmp/lo_native_input_1.docx"),
+            "Standard.Module1.Main"
+        ),
+        "macro://lo_native_input_1.docx/Standard.Module1.Main"
+    );
+}
+
+#[tokio::test]
+async fn convert_rejects_checksum_mismatch() {
+    let host = start_server(server_config()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .header("x-content-sha256", "0".repeat(64))
+        .multipart(Form::new().part("file", file_part(b"document", "a.docx")))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "checksum_mismatch");
+}
+
+#[tokio::test]
+async fn convert_rejects_empty_uploads() {
+    let host = start_server(ServerConfig {
+        min_upload_size: Some(16),
+        ..server_config()
+    })
+    .await;
+
+    for (input, expected) in [(&b" \n\0"[..], "empty_file"), (b"tiny", "file_too_small")] {
+        let response = reqwest::Client::new()
+            .post(format!("{host}/convert"))
+            .multipart(Form::new().part("file", file_part(input, "a.docx")))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 400);
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], expected);
+    }
+
+    // Empty files are rejected by the client without a request
+    let client = OfficeConvertClient::new(host).unwrap();
+    let err = client.convert(Vec::new()).await.expect_err("empty file");
+    assert!(matches!(err, RequestError::EmptyFile));
+    assert!(!err.is_retryable());
+}
+
+#[tokio::test]
+async fn convert_detects_mismatched_extensions() {
+    const HTML: &[u8] = b"<!DOCTYPE html><html><body>Report</body></html>";
+
+    for (policy, expected_status) in [(MismatchPolicy::Warn, 200), (MismatchPolicy::Reject, 415)] {
+        let host = start_server(ServerConfig {
+            format_mismatch: policy,
+            ..server_config()
+        })
+        .await;
+
+        let response = reqwest::Client::new()
+            .post(format!("{host}/convert"))
+            .multipart(Form::new().part("file", file_part(HTML, "report.docx")))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), expected_status);
+
+        if policy == MismatchPolicy::Warn {
+            assert_eq!(
+                response.headers()["x-format-mismatch"],
+                "claimed=docx; detected=html; suggested=html"
+            );
+            assert_eq!(response.headers()["x-warnings-count"], "1");
+            assert_eq!(response.headers()["x-warnings"], "format_mismatch");
+            continue;
+        }
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "format_mismatch");
+        assert_eq!(body["details"]["detected"], "html");
+        assert_eq!(body["details"]["suggested_extension"], "html");
+    }
+}
+
+#[tokio::test]
+async fn web_archives_are_unpacked() {
+    const MHTML: &[u8] = b"MIME-Version: 1.0\r\n\
+Content-Type: multipart/related;\r\n\
+\tboundary=\"----boundary\"; type=\"text/html\"\r\n\
+\r\n\
+------boundary\r\n\
+Content-Type: text/html; charset=\"utf-8\"\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+Content-Location: https://example.com/page\r\n\
+\r\n\
+<html><body><img src=3D\"cid:logo@example\">Rep=\r\nort</body></html>\r\n\
+------boundary\r\n\
+Content-Type: image/png\r\n\
+Content-Transfer-Encoding: base64\r\n\
+Content-ID: <logo@example>\r\n\
+\r\n\
+iVBORw0KGgo=\r\n\
+------boundary--\r\n";
+
+    let host = start_server(server_config()).await;
+
+    // The fake backend responds with the document it received
+    let response = reqwest::Client::new()
+        .post(format!("{host}/extract-assets"))
+        .multipart(Form::new().part("file", file_part(MHTML, "page.mht")))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.bytes().await.unwrap().as_ref(),
+        b"<html><body><img src=\"data:image/png;base64,iVBORw0KGgo=\">Report</body></html>\r\n"
+    );
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(Form::new().part(
+            "file",
+            file_part(b"bplist00\xD4\x01\x02WebMainResource", "page.webarchive"),
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 415);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "unsupported_format");
+}
+
+#[tokio::test]
+async fn convert_rejects_unknown_settings() {
+    let host = start_server(server_config()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "a.docx"))
+                .text(
+                    "settings",
+                    r#"{"language":"ja-JP","user_profile":"shared"}"#,
+                ),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_setting");
+    assert_eq!(body["details"]["setting"], "user_profile");
+}
+
+#[tokio::test]
+async fn convert_validates_export_options() {
+    let host = start_server(server_config()).await;
+    let client = reqwest::Client::new();
+
+    let cases = [
+        (
+            r#"{"pdfa":"2b","page_range":"1-3,5","quality":80}"#,
+            None,
+            None,
+        ),
+        (r#"{"quality":0}"#, None, Some("quality")),
+        (r#"{"page_range":"3-1"}"#, None, Some("page_range")),
+        (
+            r#"{"max_image_resolution":100}"#,
+            None,
+            Some("max_image_resolution"),
+        ),
+        (r#"{"watermark":"draft"}"#, None, Some("watermark")),
+        (
+            r#"{"pdfa":"2b","open_password":"secret"}"#,
+            None,
+            Some("pdfa"),
+        ),
+        (r#"{"color":"grayscale"}"#, None, None),
+        (r#"{"color":"sepia"}"#, None, Some("color")),
+        (
+            r#"{"color":"cmyk","permission_password":"secret"}"#,
+            None,
+            Some("color"),
+        ),
+        (r#"{"draft":true,"page_range":"1"}"#, None, None),
+        (r#"{"draft":"yes"}"#, None, Some("draft")),
+        (r#"{"draft":true,"quality":80}"#, None, Some("draft")),
+        (r#"{"pages_per_sheet":4,"color":"grayscale"}"#, None, None),
+        (r#"{"pages_per_sheet":3}"#, None, Some("pages_per_sheet")),
+        (
+            r#"{"pages_per_sheet":2,"pdfa":"1b"}"#,
+            None,
+            Some("pages_per_sheet"),
+        ),
+        (
+            r#"{"annotations":[{"page":1,"type":"highlight","rect":[72,700,300,720],"contents":"Check this"},{"page":2,"type":"note","rect":[10,10,30,30],"color":[0,0.5,1]}]}"#,
+            None,
+            None,
+        ),
+        (
+            r#"{"annotations":[{"page":1,"type":"highlight","rect":[300,700,72,720]}]}"#,
+            None,
+            Some("annotations"),
+        ),
+        (
+            r#"{"annotations":[{"page":1,"type":"underline","rect":[72,700,300,720]}]}"#,
+            None,
+            Some("annotations"),
+        ),
+        (
+            r#"{"annotations":[{"page":1,"type":"note","rect":[1,1,2,2]}],"pages_per_sheet":2}"#,
+            None,
+            Some("annotations"),
+        ),
+        (r#"{"quality":80}"#, Some("docx"), None),
+    ];
+
+    for (export_options, format, option) in cases {
+        let mut form = Form::new()
+            .part("file", file_part(b"document", "a.docx"))
+            .text("export_options", export_options);
+
+        if let Some(format) = format {
+            form = form.text("format", format);
+        }
+
+        let response = client
+            .post(format!("{host}/convert"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+
+        if format.is_none() && option.is_none() {
+            assert_eq!(response.status().as_u16(), 200, "{export_options}");
+            continue;
+        }
+
+        assert_eq!(response.status().as_u16(), 400, "{export_options}");
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_export_option");
+
+        if let Some(option) = option {
+            assert_eq!(body["details"]["option"], option);
+        }
+    }
+
+    // Page text and thumbnails cover the whole document, not the page range
+    for field in ["with_text", "with_thumbnail"] {
+        let response = client
+            .post(format!("{host}/convert"))
+            .multipart(
+                Form::new()
+                    .part("file", file_part(b"document", "a.docx"))
+                    .text("export_options", r#"{"page_range":"2"}"#)
+                    .text(field, "true"),
+            )
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 400, "{field}");
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_export_option");
+        assert_eq!(body["details"]["option"], "page_range");
+        assert_eq!(body["details"]["conflicts_with"], field);
+    }
+
+    // Passwords are never included in logs or quarantined options
+    let options = PdfExportOptions::parse(r#"{"open_password":"hunter2"}"#).unwrap();
+    assert!(options.is_encrypted());
+    assert!(!format!("{options:?}").contains("hunter2"));
+    assert!(!serde_json::to_string(&options).unwrap().contains("hunter2"));
+}
+
+#[tokio::test]
+async fn convert_rejects_embedded_limits() {
+    let host = start_server(ServerConfig {
+        embedded_limits: EmbeddedLimits {
+            max_depth: None,
+            max_images: Some(1),
+        },
+        ..server_config()
+    })
+    .await;
+
+    let mut document = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for name in [
+        "word/document.xml",
+        "word/media/image1.png",
+        "word/media/image2.png",
+    ] {
+        document
+            .start_file(name, SimpleFileOptions::default())
+            .unwrap();
+        document.write_all(b"content").unwrap();
+    }
+    let document = document.finish().unwrap().into_inner();
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(Form::new().part("file", Part::bytes(document).file_name("a.docx")))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 422);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "embedded_limit");
+    assert_eq!(body["details"]["limit"], "images");
+    assert_eq!(body["details"]["count"], 2);
+}
+
+#[tokio::test]
+async fn convert_batch_includes_manifest() {
+    let host = start_server(server_config()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "first.docx"))
+                .part("file", file_part(ENCRYPTED, "second.docx")),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert!(response.status().is_success());
+
+    let bytes = response.bytes().await.unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+
+    let mut manifest = String::new();
+    archive
+        .by_name("manifest.json")
+        .unwrap()
+        .read_to_string(&mut manifest)
+        .unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+
+    assert_eq!(manifest["succeeded"], 1);
+    assert_eq!(manifest["failed"], 1);
+    assert_eq!(manifest["files"][1]["error_code"], "encrypted");
+    assert!(archive.by_name("first.pdf").is_ok());
+    assert!(archive.by_name("second.error.json").is_ok());
+}
+
+#[tokio::test]
+async fn convert_batch_endpoint_responds_with_archive() {
+    let host = start_server(server_config()).await;
+
+    // Every file goes through the runner queue, corrupt files don't fail the batch
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert-batch"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"first", "first.docx"))
+                .part("file", file_part(CORRUPTED, "corrupt.docx"))
+                .part("file", file_part(b"third", "third.docx")),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["content-type"], "application/zip");
+
+    let bytes = response.bytes().await.unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+
+    let mut manifest = String::new();
+    archive
+        .by_name("manifest.json")
+        .unwrap()
+        .read_to_string(&mut manifest)
+        .unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+
+    assert_eq!(manifest["succeeded"], 2);
+    assert_eq!(manifest["failed"], 1);
+    assert_eq!(manifest["files"][0]["input"], "first.docx");
+    assert_eq!(manifest["files"][1]["error_code"], "corrupted");
+    assert_eq!(manifest["files"][2]["output"], "third.pdf");
+    assert!(archive.by_name("corrupt.error.json").is_ok());
+
+    // Single files are still responded to as a batch
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert-batch"))
+        .multipart(Form::new().part("file", file_part(b"document", "only.docx")))
+        .send()
+        .await
+        .unwrap();
+
+    assert!(response.status().is_success());
+
+    let bytes = response.bytes().await.unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+    assert!(archive.by_name("manifest.json").is_ok());
+
+    let mut output = Vec::new();
+    archive
+        .by_name("only.pdf")
+        .unwrap()
+        .read_to_end(&mut output)
+        .unwrap();
+    assert_eq!(output, FAKE_PDF);
+}
+
+#[tokio::test]
+async fn pipeline_rejects_invalid_definition() {
+    let host = start_server(server_config()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/pipeline"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "a.xlsx"))
+                .text("pipeline", "csv-sheets -> pdf"),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_pipeline");
+}
+
+#[tokio::test]
+async fn pipeline_rejects_unprotectable_password() {
+    let host = start_server(server_config()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/pipeline"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "a.docx"))
+                .text("pipeline", "pdf")
+                .text("password", "secret"),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_password");
+}
+
+#[test]
+fn memory_work_dir_enforces_limits() {
+    let path = temp_dir().join(format!("lo_native_test_{}", random_id()));
+    std::fs::create_dir_all(&path).unwrap();
+
+    let memory_dir = MemoryWorkDir {
+        path: path.clone(),
+        request_limit: 100,
+        total_limit: 300,
+    };
+
+    assert!(memory_dir.fits(100));
+    assert!(!memory_dir.fits(101));
+
+    // Files already in the directory count towards the total limit
+    std::fs::write(path.join("lo_native_input_a"), [0; 150]).unwrap();
+    assert!(memory_dir.fits(75));
+    assert!(!memory_dir.fits(76));
+
+    // Other files in the directory are not counted
+    std::fs::write(path.join("other"), [0; 150]).unwrap();
+    assert!(memory_dir.fits(75));
+
+    std::fs::remove_dir_all(&path).unwrap();
+
+    // Missing directories fit nothing
+    assert!(!memory_dir.fits(1));
+}
+
+#[test]
+fn office_log_tail_keeps_the_last_lines() {
+    assert_eq!(
+        convert::log_tail("warn:sfx:1:first\n\n  \nwarn:sfx:1:second  \n"),
+        ["warn:sfx:1:first", "warn:sfx:1:second"]
+    );
+
+    let output: String = (0..60).map(|line| format!("line {line}\n")).collect();
+    let tail = convert::log_tail(&output);
+    assert_eq!(tail.len(), convert::MAX_LOG_LINES);
+    assert_eq!(tail[0], "line 10");
+    assert_eq!(tail[tail.len() - 1], "line 59");
+}
+
+#[test]
+fn load_candidates_are_ranked_by_content() {
+    assert_eq!(detect::load_candidates(b"%PDF-1.7\n"), ["pdf"]);
+    assert_eq!(
+        detect::load_candidates(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]),
+        ["doc", "xls", "ppt", "vsd", "pub"]
+    );
+    assert_eq!(
+        detect::load_candidates(b"<!DOCTYPE html><html></html>"),
+        ["html", "xls"]
+    );
+    assert_eq!(detect::load_candidates(b"name,value\n"), ["txt", "csv"]);
+}
+
+#[test]
+fn office_error_messages_are_classified() {
+    let classify = |message: &str, password_rejected: bool| {
+        office_errors::classify(message, password_rejected).map(|err| err.to_string())
+    };
+
+    let encrypted = ConvertError::Encrypted.to_string();
+    let wrong_password = ConvertError::WrongPassword.to_string();
+
+    assert_eq!(
+        classify("Unsupported URL <file:///tmp/lo_native_input>", false),
+        Some(encrypted)
+    );
+    assert_eq!(
+        classify("Unsupported URL <file:///tmp/lo_native_input>", true),
+        Some(wrong_password.clone())
+    );
+    assert_eq!(classify("Wrong password.", false), Some(wrong_password));
+    assert_eq!(
+        classify("loadComponentFromURL returned an empty reference", false),
+        Some(ConvertError::Corrupted.to_string())
+    );
+    assert_eq!(
+        classify("Error: source file could not be loaded", false),
+        Some(ConvertError::Corrupted.to_string())
+    );
+    assert_eq!(
+        classify("no output filter found for provided suffix", false),
+        Some(ConvertError::FilterMissing.to_string())
+    );
+    assert_eq!(
+        classify("write failed: No space left on device (os error 28)", false),
+        Some(ConvertError::DiskFull.to_string())
+    );
+    assert_eq!(classify("unknown failure", false), None);
+}
+
+#[tokio::test]
+async fn status_and_health_report_idle() {
+    let host = start_server(server_config()).await;
+    let client = OfficeConvertClient::new(host.clone()).unwrap();
+
+    client
+        .convert_with_request_id(CORRUPTED.to_vec(), "test-request")
+        .await
+        .expect_err("conversion should fail");
+
+    let status = client.get_status().await.unwrap();
+    assert!(!status.is_busy);
+    assert_eq!(status.totals.map(|totals| totals.failed), Some(1));
+    let failures = status.totals.and_then(|totals| totals.failures).unwrap();
+    assert_eq!(failures.corrupted, 1);
+    assert_eq!(failures.encrypted, 0);
+    assert_eq!(failures.worker_crash, 0);
+    assert_eq!(status.queue_depth, Some(0));
+    assert_eq!(status.ready, Some(true));
+    assert_eq!(status.workers.len(), 1);
+    assert_eq!(status.workers[0].state, "idle");
+    assert!(status.workers[0].job_age_ms.is_none());
+
+    let response = reqwest::get(format!("{host}/healthz")).await.unwrap();
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn metrics_report_conversion_telemetry() {
+    let host = start_server(server_config()).await;
+    let client = OfficeConvertClient::new(host.clone()).unwrap();
+
+    client.convert(b"document".to_vec()).await.unwrap();
+    client
+        .convert(CORRUPTED.to_vec())
+        .await
+        .expect_err("conversion should fail");
+
+    let response = reqwest::get(format!("{host}/metrics")).await.unwrap();
+    assert!(response.status().is_success());
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+
+    let metrics = response.text().await.unwrap();
+    let lines: Vec<&str> = metrics.lines().collect();
+
+    for expected in [
+        "office_convert_work_total{outcome=\"completed\"} 1",
+        "office_convert_work_total{outcome=\"corrupted\"} 1",
+        "office_convert_input_bytes_count 2",
+        "office_convert_input_bytes_bucket{le=\"10000\"} 2",
+        "office_convert_output_bytes_count 1",
+        "office_convert_queue_wait_seconds_count 2",
+        "office_convert_office_starts_total 1",
+        "office_convert_runner_restarts_total 0",
+        "# TYPE office_convert_work_duration_seconds histogram",
+    ] {
+        assert!(lines.contains(&expected), "missing {expected} in {metrics}");
+    }
+}
+
+#[tokio::test]
+async fn version_reports_server_build() {
+    let host = start_server(server_config()).await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    let version = client.get_server_version().await.unwrap();
+
+    assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+    assert!(!version.git_commit.is_empty());
+    assert!(version.build_timestamp.ends_with('Z'));
+}
+
+#[tokio::test]
+async fn admin_info_reports_deployment() {
+    let host = start_server(ServerConfig {
+        admin_token: Some("secret".to_string()),
+        min_upload_size: Some(16),
+        embed_standard_fonts: true,
+        ..server_config()
+    })
+    .await;
+
+    let response = reqwest::get(format!("{host}/admin/info")).await.unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let body: serde_json::Value = reqwest::Client::new()
+        .get(format!("{host}/admin/info"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(body["backend"], "libreoffice");
+    assert_eq!(body["workers"], 1);
+    assert_eq!(body["features"]["auth"], true);
+    assert_eq!(body["features"]["cache"], false);
+    assert_eq!(body["features"]["embed_standard_fonts"], true);
+    assert_eq!(body["limits"]["min_upload_size"], 16);
+    assert!(body["limits"]["max_queue_wait_ms"].is_null());
+    assert_eq!(body["limits"]["queue_capacity"], 1);
+}
+
+#[tokio::test]
+async fn admin_diagnostics_toggle_at_runtime() {
+    let applied = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let log_toggle = LogToggle::new({
+        let applied = applied.clone();
+        move |enabled| {
+            applied.lock().push(enabled);
+            Ok(())
+        }
+    });
+
+    let host = start_server(ServerConfig {
+        admin_token: Some("secret".to_string()),
+        log_toggle: Some(log_toggle),
+        ..server_config()
+    })
+    .await;
+    let http = reqwest::Client::new();
+
+    let response = http
+        .post(format!("{host}/admin/diagnostics?debug_logging=true"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let body: serde_json::Value = http
+        .get(format!("{host}/admin/diagnostics"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["debug_logging"], false);
+    assert_eq!(body["dump_callbacks"], false);
+
+    let body: serde_json::Value = http
+        .post(format!(
+            "{host}/admin/diagnostics?debug_logging=true&dump_callbacks=true"
+        ))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["debug_logging"], true);
+    assert_eq!(body["dump_callbacks"], true);
+
+    // Diagnostics that are not provided are left as they are
+    let body: serde_json::Value = http
+        .post(format!("{host}/admin/diagnostics?debug_logging=false"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["debug_logging"], false);
+    assert_eq!(body["dump_callbacks"], true);
+
+    assert_eq!(*applied.lock(), [true, false]);
+}
+
+#[tokio::test]
+async fn admin_diagnostics_require_log_toggle() {
+    let host = start_server(ServerConfig {
+        admin_token: Some("secret".to_string()),
+        ..server_config()
+    })
+    .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/admin/diagnostics?debug_logging=true"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 409);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "log_toggle_unavailable");
+}
+
+#[tokio::test]
+async fn admin_memory_reports_last_trim() {
+    let host = start_server(ServerConfig {
+        admin_token: Some("secret".to_string()),
+        ..server_config()
+    })
+    .await;
+    let http = reqwest::Client::new();
+
+    let response = reqwest::get(format!("{host}/admin/memory")).await.unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    http.post(format!("{host}/collect-garbage"))
+        .send()
+        .await
+        .unwrap();
+
+    // The runner processes messages in order so the trim has finished once converted
+    OfficeConvertClient::new(host.as_str())
+        .unwrap()
+        .convert_with_request_id(b"document".to_vec(), "test-request")
+        .await
+        .expect("conversion failed");
+
+    let body: serde_json::Value = http
+        .get(format!("{host}/admin/memory"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["last_trim"]["target"], 2000);
+    assert!(body["work_dir"]["files"].is_u64());
+    assert!(body["cache"].is_null());
+}
+
+#[tokio::test]
+async fn clients_convert_many_files_concurrently() {
+    let host = start_server(server_config()).await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    let files = vec![
+        b"document".to_vec(),
+        CORRUPTED.to_vec(),
+        b"document".to_vec(),
+    ];
+
+    let results = client.convert_many(files.clone(), 2).await;
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap().as_ref(), FAKE_PDF);
+    assert_eq!(
+        results[1].as_ref().unwrap_err().code(),
+        Some(&ErrorCode::Corrupted)
+    );
+    assert_eq!(results[2].as_ref().unwrap().as_ref(), FAKE_PDF);
+
+    let load_balancer = OfficeConvertLoadBalancer::new([client]);
+    let results = load_balancer.convert_many(files, 0).await;
+    assert_eq!(results.len(), 3);
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+    assert!(results[2].is_ok());
+}
+
+#[tokio::test]
+async fn clients_limit_uploads_in_flight() {
+    // Server tracking the most requests it was handling at once
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let max_in_flight = Arc::new(AtomicUsize::new(0));
+    let host = serve(axum::Router::new().route(
+        "/convert",
+        post({
+            let in_flight = in_flight.clone();
+            let max_in_flight = max_in_flight.clone();
+            move || async move {
+                let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(current, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                FAKE_PDF
+            }
+        }),
+    ))
+    .await;
+
+    let client = OfficeConvertClient::new(host)
+        .unwrap()
+        .with_max_in_flight(Some(2));
+
+    let results = client.convert_many(vec![b"document".to_vec(); 8], 8).await;
+    assert!(results.iter().all(|result| result.is_ok()));
+    assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn runner_failures_restart_office() {
+    let created = Arc::new(AtomicUsize::new(0));
+    let host = start_server_with(
+        {
+            let created = created.clone();
+            move || {
+                created.fetch_add(1, Ordering::SeqCst);
+                FakeBackend
+            }
+        },
+        server_config(),
+    )
+    .await;
+    let client = reqwest::Client::new();
+
+    // The in-flight request fails instead of waiting forever
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(Form::new().part("file", file_part(PANICS, "document.docx")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 500);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "converter_restarted");
+
+    // Office is started again for the next request
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(Form::new().part("file", file_part(b"document", "document.docx")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(created.load(Ordering::SeqCst), 2);
+
+    let status: serde_json::Value = client
+        .get(format!("{host}/status"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["totals"]["failures"]["worker_crash"], 1);
+}
+
+#[tokio::test]
+async fn load_balancer_rejects_converts_after_shutdown() {
+    let host = start_server(server_config()).await;
+    let load_balancer = OfficeConvertLoadBalancer::new([OfficeConvertClient::new(host).unwrap()]);
+
+    let output = load_balancer
+        .convert(b"document".to_vec())
+        .await
+        .expect("conversion failed");
+    assert_eq!(output.as_ref(), FAKE_PDF);
+
+    assert!(load_balancer.shutdown(Duration::from_secs(5)).await);
+
+    let err = load_balancer
+        .convert(b"document".to_vec())
+        .await
+        .expect_err("conversion after shutdown should fail");
+    assert!(matches!(
+        err,
+        RequestError::LoadBalance(LoadBalanceError::ShuttingDown)
+    ));
+}
+
+#[tokio::test]
+async fn load_balancer_strategies_choose_servers() {
+    let hosts = [
+        start_server(server_config()).await,
+        start_server(server_config()).await,
+    ];
+
+    for (strategy, expected) in [
+        (LoadBalanceStrategy::InOrder, [4, 0]),
+        (LoadBalanceStrategy::RoundRobin, [2, 2]),
+        (LoadBalanceStrategy::LeastRecentlyUsed, [2, 2]),
+    ] {
+        let load_balancer = OfficeConvertLoadBalancer::new_with_strategy(
+            hosts
+                .iter()
+                .map(|host| OfficeConvertClient::new(host.as_str()).unwrap()),
+            strategy,
+            LoadBalancerTiming {
+                slow_start: Duration::ZERO,
+                ..Default::default()
+            },
+        );
+
+        for _ in 0..4 {
+            load_balancer
+                .convert(b"document".to_vec())
+                .await
+                .expect("conversion failed");
+        }
+
+        let stats = load_balancer.stats();
+        assert_eq!(
+            stats
+                .iter()
+                .map(|stats| stats.successes)
+                .collect::<Vec<_>>(),
+            expected,
+            "{strategy:?}"
+        );
+        assert!(stats.iter().all(|stats| stats.failures == 0));
+    }
+}
+
+#[tokio::test]
+async fn load_balancer_health_checks_eject_unreachable_servers() {
+    // Nothing listens on the first server
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let unreachable = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let host = start_server(server_config()).await;
+
+    let load_balancer = OfficeConvertLoadBalancer::new_with_timing(
+        [
+            OfficeConvertClient::new(unreachable.as_str()).unwrap(),
+            OfficeConvertClient::new(host.as_str()).unwrap(),
+        ],
+        LoadBalancerTiming {
+            slow_start: Duration::ZERO,
+            health_check_interval: Duration::from_millis(50),
+            ..Default::default()
+        },
+    );
+    load_balancer.check_health();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !load_balancer.stats()[0].ejected {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("unreachable server was not ejected");
+
+    for _ in 0..3 {
+        load_balancer
+            .convert(b"document".to_vec())
+            .await
+            .expect("conversion failed");
+    }
+
+    let stats = load_balancer.stats();
+    assert_eq!(stats[0].host, unreachable);
+    assert_eq!(stats[0].ejections, 1);
+    assert_eq!(stats[0].successes + stats[0].failures, 0);
+    assert_eq!(stats[1].successes, 3);
+    assert!(!stats[1].ejected);
+}
+
+#[tokio::test]
+async fn stub_backend_serves_placeholder() {
+    let host = start_server_with(StubBackend::default, server_config()).await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    let output = client
+        .convert_with_request_id(b"document".to_vec(), "test-request")
+        .await
+        .expect("conversion failed");
+    assert!(output.starts_with(b"%PDF-"));
+
+    let err = client
+        .convert_with_request_id(Vec::new(), "test-request")
+        .await
+        .expect_err("empty document should fail");
+    assert!(matches!(err, RequestError::EmptyFile));
+}
+
+#[tokio::test]
+async fn runner_converts_documents_when_embedded() {
+    let (_, office_handle) = create_office_runner(
+        |_| Ok(StubBackend),
+        TrimConfig::default(),
+        QueueConfig::default(),
+        WatchdogConfig::default(),
+        None,
+    )
+    .await
+    .expect("failed to start runner");
+
+    let converted = office_handle
+        .convert_document(
+            DocumentInput::Bytes(Bytes::from_static(b"document")),
+            ConvertOptions::default(),
+        )
+        .await
+        .expect("conversion failed");
+    assert_eq!(converted.content_type, "application/pdf");
+    assert!(converted.bytes.starts_with(b"%PDF-"));
+
+    let Err(err) = office_handle
+        .convert_document(
+            DocumentInput::Bytes(Bytes::new()),
+            ConvertOptions::default(),
+        )
+        .await
+    else {
+        panic!("empty document should fail");
+    };
+    assert!(matches!(
+        err.downcast_ref::<ConvertError>(),
+        Some(ConvertError::Corrupted)
+    ));
+}
+
+#[tokio::test]
+async fn runner_starts_lazily_with_idle_shutdown() {
+    let started = Arc::new(AtomicUsize::new(0));
+
+    let (_, office_handle) = create_office_runner(
+        {
+            let started = started.clone();
+            move |_| {
+                started.fetch_add(1, Ordering::SeqCst);
+                Ok(StubBackend)
+            }
+        },
+        TrimConfig {
+            idle_shutdown: Some(Duration::from_secs(60 * 60)),
+            ..TrimConfig::default()
+        },
+        QueueConfig::default(),
+        WatchdogConfig::default(),
+        None,
+    )
+    .await
+    .expect("failed to start runner");
+
+    // Office is started by the first conversion rather than at startup
+    assert_eq!(started.load(Ordering::SeqCst), 0);
+    assert!(!office_handle.activity().is_warm());
+
+    let converted = office_handle
+        .convert_document(
+            DocumentInput::Bytes(Bytes::from_static(b"document")),
+            ConvertOptions::default(),
+        )
+        .await
+        .expect("conversion failed");
+    assert!(converted.bytes.starts_with(b"%PDF-"));
+
+    tokio::time::timeout(Duration::from_secs(5), office_handle.wait_warm())
+        .await
+        .expect("office did not start");
+    assert_eq!(started.load(Ordering::SeqCst), 1);
+}
+
+/// Waits for the spare backend of the first worker to be ready
+async fn wait_for_standby(office_handle: &OfficeHandle) {
+    for _ in 0..500 {
+        if office_handle.activity().has_standby() {
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    panic!("warm standby did not start");
+}
+
+#[tokio::test]
+async fn warm_standby_replaces_crashed_backend() {
+    let started = Arc::new(AtomicUsize::new(0));
+    let (release, released) = std::sync::mpsc::channel::<()>();
+    let released = std::sync::Mutex::new(released);
+
+    let (_, office_handle) = create_office_runner(
+        warm_standby({
+            let started = started.clone();
+            move |_| {
+                // Spares after the first are held until released
+                if started.fetch_add(1, Ordering::SeqCst) >= 2 {
+                    _ = released.lock().unwrap().recv();
+                }
+                Ok(FakeBackend)
+            }
+        }),
+        trim_config(),
+        QueueConfig::default(),
+        WatchdogConfig::default(),
+        None,
+    )
+    .await
+    .expect("failed to start runner");
+
+    wait_for_standby(&office_handle).await;
+    assert_eq!(started.load(Ordering::SeqCst), 2);
+
+    let result = office_handle
+        .convert_document(
+            DocumentInput::Bytes(Bytes::from_static(PANICS)),
+            ConvertOptions::default(),
+        )
+        .await;
+    assert!(result.is_err());
+
+    // The spare replaces the crashed backend without waiting for the next spare
+    let converted = tokio::time::timeout(
+        Duration::from_secs(5),
+        office_handle.convert_document(
+            DocumentInput::Bytes(Bytes::from_static(b"document")),
+            ConvertOptions::default(),
+        ),
+    )
+    .await
+    .expect("conversion waited for a new backend")
+    .expect("conversion failed");
+    assert_eq!(converted.bytes.as_ref(), FAKE_PDF);
+    assert!(!office_handle.activity().has_standby());
+
+    release.send(()).unwrap();
+    wait_for_standby(&office_handle).await;
+    assert_eq!(started.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn convert_service_composes_with_middleware() {
+    let (_, office_handle) = create_office_runner(
+        |_| Ok(StubBackend),
+        TrimConfig::default(),
+        QueueConfig::default(),
+        WatchdogConfig::default(),
+        None,
+    )
+    .await
+    .expect("failed to start runner");
+
+    let mut service = ServiceBuilder::new()
+        .concurrency_limit(1)
+        .service(ConvertService::new(office_handle));
+
+    let converted = service
+        .ready()
+        .await
+        .unwrap()
+        .call(ConvertRequest::new(
+            DocumentInput::Bytes(Bytes::from_static(b"document")),
+            ConvertOptions::default(),
+        ))
+        .await
+        .expect("conversion failed");
+    assert!(converted.bytes.starts_with(b"%PDF-"));
+
+    let result = service
+        .oneshot(ConvertRequest::new(
+            DocumentInput::Bytes(Bytes::new()),
+            ConvertOptions::default(),
+        ))
+        .await;
+    let Err(err) = result else {
+        panic!("empty document should fail");
+    };
+    assert!(matches!(
+        err.downcast_ref::<ConvertError>(),
+        Some(ConvertError::Corrupted)
+    ));
+}
+
+#[tokio::test]
+async fn client_service_converts_files() {
+    let host = start_server(server_config()).await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    let output = ServiceBuilder::new()
+        .concurrency_limit(1)
+        .service(client.clone())
+        .oneshot(
+            office_convert_client::ConvertRequest::new(b"document".to_vec())
+                .with_request_id("test-request"),
+        )
+        .await
+        .expect("conversion failed");
+    assert_eq!(output.as_ref(), FAKE_PDF);
+
+    let err = client
+        .oneshot(office_convert_client::ConvertRequest::new(Vec::new()))
+        .await
+        .expect_err("empty file should fail");
+    assert!(matches!(err, RequestError::EmptyFile));
+}
+
+#[tokio::test]
+async fn stub_backend_serves_thumbnail() {
+    let host = start_server_with(StubBackend::default, server_config()).await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    let output = client
+        .convert_with_thumbnail(b"document".to_vec())
+        .await
+        .expect("conversion failed");
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+
+    let mut document = Vec::new();
+    archive
+        .by_name("document.pdf")
+        .unwrap()
+        .read_to_end(&mut document)
+        .unwrap();
+    assert!(document.starts_with(b"%PDF-"));
+
+    let mut thumbnail = Vec::new();
+    archive
+        .by_name("thumbnail.png")
+        .unwrap()
+        .read_to_end(&mut thumbnail)
+        .unwrap();
+    assert!(thumbnail.starts_with(b"\x89PNG"));
+}
+
+#[tokio::test]
+async fn stub_backend_serves_page_text() {
+    let host = start_server_with(StubBackend::default, server_config()).await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    let output = client
+        .convert_with_text(b"document".to_vec())
+        .await
+        .expect("conversion failed");
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(output)).unwrap();
+    assert!(archive.by_name("document.pdf").is_ok());
+
+    let mut text = Vec::new();
+    archive
+        .by_name("text.json")
+        .unwrap()
+        .read_to_end(&mut text)
+        .unwrap();
+
+    let text: serde_json::Value = serde_json::from_slice(&text).unwrap();
+    assert_eq!(text[0]["page"], 1);
+}
+
+#[test]
+fn page_text_numbers_spreadsheet_sheets() {
+    let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+<office:body><office:spreadsheet>
+<table:table table:name="North"><table:table-row><table:table-cell><text:p>north</text:p></table:table-cell></table:table-row></table:table>
+<table:table table:name="South"><table:table-row><table:table-cell><text:p>south</text:p></table:table-cell></table:table-row></table:table>
+</office:spreadsheet></office:body>
+</office:document-content>"#;
+
+    let mut package = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    package
+        .start_file("content.xml", SimpleFileOptions::default())
+        .unwrap();
+    package.write_all(content.as_bytes()).unwrap();
+    let package = package.finish().unwrap().into_inner();
+
+    let path = temp_dir().join(format!("lo_native_test_sheets_{}.ods", std::process::id()));
+    std::fs::write(&path, package).unwrap();
+    let pages = odf::read_page_text(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // Sheets are numbered as sheets rather than pages
+    let pages = serde_json::to_value(&pages).unwrap();
+    assert_eq!(
+        pages,
+        serde_json::json!([
+            { "sheet": 1, "text": "north" },
+            { "sheet": 2, "text": "south" },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn stub_backend_exports_csv() {
+    let host = start_server_with(StubBackend::default, server_config()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "sheet.xlsx"))
+                .text("format", "tsv")
+                .text("disposition", "attachment"),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/tab-separated-values"
+    );
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"sheet.tsv\""
+    );
+
+    // Multiple sheets are bundled together
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "sheet.xlsx"))
+                .text("format", "csv")
+                .text("sheets", "1,3")
+                .text("delimiter", ";")
+                .text("quote", "all")
+                .text("encoding", "windows-1252"),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["content-type"], "application/zip");
+
+    let bytes = response.bytes().await.unwrap();
+    let archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+    let mut names: Vec<&str> = archive.file_names().collect();
+    names.sort();
+    assert_eq!(names, ["sheet-1.csv", "sheet-3.csv"]);
+
+    // CSV options are only used with csv exports
+    for (name, value) in [("delimiter", ";"), ("format", "xml")] {
+        let form = Form::new()
+            .part("file", file_part(b"document", "sheet.xlsx"))
+            .text(name, value);
+        let response = client
+            .post(format!("{host}/convert"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_csv_option");
+    }
+
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "sheet.xlsx"))
+                .text("format", "csv")
+                .text("split_sheets", "true"),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_options");
+}
+
+#[tokio::test]
+async fn stub_backend_exports_spreadsheets() {
+    let host = start_server_with(StubBackend::default, server_config()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "sheet.xlsx"))
+                .text("format", "xlsx")
+                .text("data_only", "true")
+                .text("remove_hidden_sheets", "true"),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+    );
+
+    // Data only exports require a spreadsheet format
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "sheet.xlsx"))
+                .text("data_only", "true"),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_options");
+    assert_eq!(body["details"]["option"], "data_only");
+}
+
+#[tokio::test]
+async fn stub_backend_exports_other_formats() {
+    let host = start_server_with(StubBackend::default, server_config()).await;
+    let client = reqwest::Client::new();
+
+    for (format, content_type) in [
+        ("odt", "application/vnd.oasis.opendocument.text"),
+        (
+            "DOCX",
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        ),
+        ("png", "image/png"),
+    ] {
+        let response = client
+            .post(format!("{host}/convert"))
+            .multipart(
+                Form::new()
+                    .part("file", file_part(b"document", "document.docx"))
+                    .text("format", format),
+            )
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["content-type"], content_type);
+    }
+
+    // PDF only options cannot be combined with other formats
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "document.docx"))
+                .text("format", "odt")
+                .text("with_thumbnail", "true"),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_options");
+}
+
+#[test]
+fn target_formats_are_checked_against_filter_types() {
+    let types = FilterTypes {
+        values: HashMap::from([(
+            "writer8".to_string(),
+            FilterType {
+                media_type: "application/vnd.oasis.opendocument.text".to_string(),
+            },
+        )]),
+    };
+
+    let target = TargetFormat::parse(Some("odt"), Some(&types))
+        .unwrap()
+        .unwrap();
+    assert_eq!(target.extension, "odt");
+    assert_eq!(
+        target.content_type,
+        "application/vnd.oasis.opendocument.text"
+    );
+
+    // Formats office did not report a filter for are rejected
+    assert!(TargetFormat::parse(Some("docx"), Some(&types)).is_err());
+    assert!(TargetFormat::parse(Some("docx"), None).unwrap().is_some());
+    assert!(TargetFormat::parse(Some("xml"), None).is_err());
+
+    // Formats with their own options are not target formats
+    assert!(TargetFormat::parse(Some("pdf"), Some(&types))
+        .unwrap()
+        .is_none());
+    assert!(TargetFormat::parse(Some("csv"), Some(&types))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn data_only_export_removes_formulas_macros_and_hidden_sheets() {
+    let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:script="urn:oasis:names:tc:opendocument:xmlns:script:1.0">
+<office:scripts><office:event-listeners><script:event-listener script:event-name="dom:load"/></office:event-listeners></office:scripts>
+<office:automatic-styles>
+<style:style style:name="ta1" style:family="table"><style:table-properties table:display="true"/></style:style>
+<style:style style:name="ta2" style:family="table"><style:table-properties table:display="false"/></style:style>
+</office:automatic-styles>
+<office:body><office:spreadsheet>
+<table:table table:name="Visible" table:style-name="ta1"><table:table-row><table:table-cell table:formula="of:=1+1" office:value-type="float" office:value="2"><text:p>2</text:p></table:table-cell></table:table-row></table:table>
+<table:table table:name="Secret" table:style-name="ta2"><table:table-row><table:table-cell office:value-type="string"><text:p>salaries</text:p></table:table-cell></table:table-row></table:table>
+</office:spreadsheet></office:body>
+</office:document-content>"#;
+
+    let manifest = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0">
+<manifest:file-entry manifest:full-path="/" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/>
+<manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+<manifest:file-entry manifest:full-path="Basic/Standard/Module1.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>"#;
+
+    let mut package = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let entries = [
+        ("mimetype", "application/vnd.oasis.opendocument.spreadsheet"),
+        ("META-INF/manifest.xml", manifest),
+        ("content.xml", content),
+        ("Basic/Standard/Module1.xml", "<script:module/>"),
+    ];
+    for (name, value) in entries {
+        package
+            .start_file(name, SimpleFileOptions::default())
+            .unwrap();
+        package.write_all(value.as_bytes()).unwrap();
+    }
+    let package = package.finish().unwrap().into_inner();
+
+    let path = temp_dir().join(format!(
+        "lo_native_test_workbook_{}.ods",
+        std::process::id()
+    ));
+    std::fs::write(&path, package).unwrap();
+
+    let sanitized = workbook::sanitize_package(
+        &path,
+        &SpreadsheetExport {
+            format: SpreadsheetFormat::Xlsx,
+            data_only: true,
+            remove_hidden_sheets: true,
+        },
+    )
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(sanitized)).unwrap();
+    assert!(archive.by_name("Basic/Standard/Module1.xml").is_err());
+
+    let mut content = String::new();
+    archive
+        .by_name("content.xml")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert!(content.contains("Visible"));
+    assert!(content.contains(r#"office:value="2""#));
+    assert!(!content.contains("table:formula"));
+    assert!(!content.contains("Secret"));
+    assert!(!content.contains("event-listener"));
+
+    let mut manifest = String::new();
+    archive
+        .by_name("META-INF/manifest.xml")
+        .unwrap()
+        .read_to_string(&mut manifest)
+        .unwrap();
+    assert!(manifest.contains("content.xml"));
+    assert!(!manifest.contains("Basic/"));
+}
+
+#[tokio::test]
+async fn stub_backend_redacts_conversions() {
+    let host = start_server_with(StubBackend::default, server_config()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{host}/convert-redact"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "report.docx"))
+                .text("terms", "Jane Doe")
+                .text("terms", "555-0100")
+                .text("regions", r#"[{"page": 1, "rect": [72, 700, 300, 720]}]"#),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["content-type"], "application/zip");
+
+    let bytes = response.bytes().await.unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+    assert!(archive.by_name("document.pdf").is_ok());
+
+    let mut report = String::new();
+    archive
+        .by_name("redactions.json")
+        .unwrap()
+        .read_to_string(&mut report)
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+    assert_eq!(report["terms"][0]["term"], "Jane Doe");
+    assert_eq!(report["terms"][1]["matches"], 0);
+    assert_eq!(report["regions"], 1);
+    assert_eq!(report["rasterized"], true);
+
+    // Invalid redactions are rejected before converting
+    for (field, value) in [
+        ("match_case", "true"),
+        ("terms", " "),
+        ("regions", r#"[{"page": 0, "rect": [0, 0, 1, 1]}]"#),
+    ] {
+        let response = client
+            .post(format!("{host}/convert-redact"))
+            .multipart(
+                Form::new()
+                    .part("file", file_part(b"document", "report.docx"))
+                    .text(field, value),
+            )
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status().as_u16(), 400, "{field}");
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "invalid_redaction");
+    }
+
+    // Regions beyond the last page fail the conversion
+    let response = client
+        .post(format!("{host}/convert-redact"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "report.docx"))
+                .text("regions", r#"[{"page": 3, "rect": [0, 0, 10, 10]}]"#),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 422);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_page");
+}
+
+#[test]
+fn redaction_removes_terms_from_packages() {
+    let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+<office:body><office:text>
+<text:p>Signed by JANE DOE &amp; jane doe</text:p>
+<text:p>Call Jane Doe-Smith on 555-0100</text:p>
+</office:text></office:body>
+</office:document-content>"#;
+
+    let mut package = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let entries = [
+        ("mimetype", "application/vnd.oasis.opendocument.text"),
+        ("content.xml", content),
+        ("Thumbnails/thumbnail.png", "thumbnail"),
+    ];
+    for (name, value) in entries {
+        package
+            .start_file(name, SimpleFileOptions::default())
+            .unwrap();
+        package.write_all(value.as_bytes()).unwrap();
+    }
+    let package = package.finish().unwrap().into_inner();
+
+    let path = temp_dir().join(format!("lo_native_test_redact_{}.odt", std::process::id()));
+    std::fs::write(&path, package).unwrap();
+
+    let options = RedactOptions::parse(
+        vec!["Jane Doe".to_string(), "Jane Doe-Smith".to_string()],
+        None,
+        false,
+    )
+    .unwrap();
+    let (redacted, matches) = redact::redact_package(&path, &options).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // Longer terms are matched first
+    assert_eq!(matches, vec![2, 1]);
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(redacted)).unwrap();
+    assert!(archive.by_name("Thumbnails/thumbnail.png").is_err());
+
+    let mut content = String::new();
+    archive
+        .by_name("content.xml")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert!(!content.to_lowercase().contains("jane"));
+    assert!(content.contains("Signed by ████████ &amp; ████████"));
+    assert!(content.contains("555-0100"));
+
+    // Terms are required unless regions are redacted
+    assert!(RedactOptions::parse(Vec::new(), None, false).is_err());
+    assert!(RedactOptions::parse(Vec::new(), Some("[]"), false).is_err());
+}
+
+#[test]
+fn redaction_matches_terms_across_runs_and_objects() {
+    let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:draw="urn:oasis:names:tc:opendocument:xmlns:drawing:1.0" xmlns:xlink="http://www.w3.org/1999/xlink">
+<office:body><office:text>
+<text:p>Signed by <text:span text:style-name="Bold">Ja</text:span>ne Doe</text:p>
+<text:h>Jane<text:s/>Doe</text:h>
+<text:p>Jan&#173;e Doe</text:p>
+<text:p>Jane <text:change-start text:change-id="c1"/>Doe<text:change-end text:change-id="c1"/></text:p>
+<text:p>Jane <office:annotation><text:p>Note</text:p></office:annotation>Kept</text:p>
+<text:p><draw:frame><draw:object xlink:href="./Object 1"/><draw:image xlink:href="./ObjectReplacements/Object 1"/></draw:frame></text:p>
+</office:text></office:body>
+</office:document-content>"#;
+
+    let object = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+<office:body><office:chart><text:p>Sales by Jane Doe</text:p></office:chart></office:body>
+</office:document-content>"#;
+
+    let manifest = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.2">
+ <manifest:file-entry manifest:full-path="/" manifest:media-type="application/vnd.oasis.opendocument.text"/>
+ <manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+ <manifest:file-entry manifest:full-path="Object 1/content.xml" manifest:media-type="text/xml"/>
+ <manifest:file-entry manifest:full-path="ObjectReplacements/Object 1" manifest:media-type="application/x-openoffice-gdimetafile"/>
+ <manifest:file-entry manifest:full-path="Thumbnails/thumbnail.png" manifest:media-type="image/png"/>
+</manifest:manifest>"#;
+
+    let mut package = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let entries = [
+        ("mimetype", "application/vnd.oasis.opendocument.text"),
+        ("content.xml", content),
+        ("Object 1/content.xml", object),
+        ("ObjectReplacements/Object 1", "Sales by Jane Doe"),
+        ("Thumbnails/thumbnail.png", "thumbnail"),
+        ("META-INF/manifest.xml", manifest),
+    ];
+    for (name, value) in entries {
+        package
+            .start_file(name, SimpleFileOptions::default())
+            .unwrap();
+        package.write_all(value.as_bytes()).unwrap();
+    }
+    let package = package.finish().unwrap().into_inner();
+
+    let path = temp_dir().join(format!(
+        "lo_native_test_redact_runs_{}.odt",
+        std::process::id()
+    ));
+    std::fs::write(&path, package).unwrap();
+
+    let options = RedactOptions::parse(vec!["Jane Doe".to_string()], None, false).unwrap();
+    let (redacted, matches) = redact::redact_package(&path, &options).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // Split by a span, a spaces element, a soft hyphen, a tracked change and
+    // within the embedded object
+    assert_eq!(matches, vec![5]);
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(redacted)).unwrap();
+    assert!(archive.by_name("ObjectReplacements/Object 1").is_err());
+
+    let mut read = |name: &str| {
+        let mut value = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut value)
+            .unwrap();
+        value
+    };
+
+    let content = read("content.xml");
+    assert!(content
+        .contains(r#"Signed by <text:span text:style-name="Bold">██</text:span>██████</text:p>"#));
+    assert!(content.contains("<text:h>████<text:s/>███</text:h>"));
+    assert!(!content.contains("Doe"));
+
+    // Annotations are paragraphs of their own, text around them is not joined
+    assert!(
+        content.contains("Jane <office:annotation><text:p>Note</text:p></office:annotation>Kept")
+    );
+
+    assert!(read("Object 1/content.xml").contains("Sales by ████████"));
+
+    let manifest = read("META-INF/manifest.xml");
+    assert!(manifest.contains("Object 1/content.xml"));
+    assert!(!manifest.contains("ObjectReplacements/"));
+    assert!(!manifest.contains("Thumbnails/"));
+}
+
+#[tokio::test]
+async fn stub_backend_dry_runs_batch() {
+    let host = start_server_with(StubBackend::default, server_config()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "report.docx"))
+                .part("file", file_part(b"   ", "blank.docx"))
+                .text("dry_run", "true"),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["valid"], 1);
+    assert_eq!(report["invalid"], 1);
+
+    assert_eq!(report["files"][0]["input"], "report.docx");
+    assert_eq!(report["files"][0]["outcome"], "valid");
+    assert_eq!(report["files"][0]["document_type"], "text");
+    assert_eq!(report["files"][0]["estimated_page_count"], 1);
+
+    assert_eq!(report["files"][1]["outcome"], "invalid");
+    assert_eq!(report["files"][1]["error_code"], "empty_file");
+}
+
+#[tokio::test]
+async fn convert_stores_output_in_output_dir() {
+    let output_dir = temp_dir().join(format!("lo_native_test_output_{}", std::process::id()));
+    std::fs::create_dir_all(&output_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        output_dir: Some(output_dir.clone()),
+        ..server_config()
+    })
+    .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "report.docx"))
+                .text("store", "true"),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let path = body["path"].as_str().unwrap();
+    assert!(path.starts_with("report-") && path.ends_with(".pdf"));
+    assert_eq!(body["size"], FAKE_PDF.len());
+    assert_eq!(std::fs::read(output_dir.join(path)).unwrap(), FAKE_PDF);
+
+    std::fs::remove_dir_all(&output_dir).unwrap();
+
+    let host = start_server(server_config()).await;
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "report.docx"))
+                .text("store", "true"),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "store_disabled");
+}
+
+/// Waits for the server to report `selftests` self tests, the self test runs
+/// after the response for the failing conversion has been sent
+async fn wait_for_selftests(client: &OfficeConvertClient, selftests: u64) -> WorkTotals {
+    for _ in 0..50 {
+        let totals = client.get_status().await.unwrap().totals.unwrap();
+        if totals.selftests >= selftests {
+            return totals;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("self test did not run");
+}
+
+#[tokio::test]
+async fn repeated_failures_run_selftest() {
+    // Failures caused by the documents do not recycle office
+    let host = start_server_recovering(|| FakeBackend, server_config(), Some(2)).await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    for _ in 0..2 {
+        client
+            .convert_with_request_id(CORRUPTED.to_vec(), "test-request")
+            .await
+            .expect_err("conversion should fail");
+    }
+
+    let totals = wait_for_selftests(&client, 1).await;
+    assert_eq!(totals.recoveries, 0);
+
+    // Office failing the self test is recycled
+    let created = Arc::new(AtomicUsize::new(0));
+    let host = start_server_recovering(
+        {
+            let created = created.clone();
+            move || {
+                created.fetch_add(1, Ordering::Relaxed);
+                BrokenBackend
+            }
+        },
+        server_config(),
+        Some(2),
+    )
+    .await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    for _ in 0..2 {
+        client
+            .convert_with_request_id(b"document".to_vec(), "test-request")
+            .await
+            .expect_err("conversion should fail");
+    }
+
+    let totals = wait_for_selftests(&client, 1).await;
+    assert_eq!(totals.recoveries, 1);
+    assert_eq!(created.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test]
+async fn alerts_post_runner_events_to_webhook() {
+    let (alert_tx, mut alert_rx) = mpsc::unbounded_channel::<serde_json::Value>();
+    let webhook = serve(axum::Router::new().route(
+        "/",
+        post(move |Json(body): Json<serde_json::Value>| async move {
+            _ = alert_tx.send(body);
+        }),
+    ))
+    .await;
+
+    let (office_details, office_handle) = create_office_runner(
+        |_| Ok(BrokenBackend),
+        trim_config(),
+        QueueConfig::default(),
+        WatchdogConfig::default(),
+        Some(1),
+    )
+    .await
+    .expect("failed to start runner");
+
+    let alerts = Alerts {
+        office: office_handle.clone(),
+        webhook: webhook.parse().unwrap(),
+        interval: Duration::from_millis(20),
+        dirs: vec![temp_dir()],
+    };
+    tokio::spawn(alerts.monitor());
+
+    let office_details = Arc::new(ArcSwap::from_pointee(office_details));
+    let host = serve(server::router(
+        office_handle,
+        office_details,
+        server_config(),
+        None,
+    ))
+    .await;
+
+    OfficeConvertClient::new(host)
+        .unwrap()
+        .convert_with_request_id(b"document".to_vec(), "test-request")
+        .await
+        .expect_err("conversion should fail");
+
+    let mut events = Vec::new();
+    while events.len() < 2 {
+        let alert = tokio::time::timeout(Duration::from_secs(5), alert_rx.recv())
+            .await
+            .expect("alert was not sent")
+            .unwrap();
+
+        assert!(alert["text"].as_str().is_some());
+        events.push(alert["event"].as_str().unwrap().to_string());
+    }
+
+    assert!(events.contains(&"repeated_failures".to_string()));
+    assert!(events.contains(&"worker_restart".to_string()));
+}
+
+#[tokio::test]
+async fn stored_outputs_are_served_from_presigned_urls() {
+    let output_dir = temp_dir().join(format!("lo_native_test_presign_{}", std::process::id()));
+    std::fs::create_dir_all(&output_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        output_dir: Some(output_dir.clone()),
+        url_signer: Some(UrlSigner::new(b"secret", Duration::from_secs(60))),
+        ..server_config()
+    })
+    .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "report.docx"))
+                .text("store", "true"),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let url = body["url"].as_str().unwrap();
+
+    let response = reqwest::get(format!("{host}{url}")).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.bytes().await.unwrap().as_ref(), FAKE_PDF);
+
+    // Changing the expiry invalidates the signature
+    let (path, query) = url.split_once('?').unwrap();
+    let signature = query.split_once("&signature=").unwrap().1;
+    let response = reqwest::get(format!(
+        "{host}{path}?expires=99999999999&signature={signature}"
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid_url_signature");
+
+    // Expired URLs are rejected
+    let expired = UrlSigner::new(b"secret", Duration::ZERO);
+    let expired_url = expired.sign(path.trim_start_matches("/outputs/"));
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = reqwest::get(format!("{host}{expired_url}")).await.unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "url_expired");
+
+    std::fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[tokio::test]
+async fn load_balancer_provides_stored_result_locations() {
+    let output_dir = temp_dir().join(format!("lo_native_test_location_{}", random_id()));
+    std::fs::create_dir_all(&output_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        output_dir: Some(output_dir.clone()),
+        url_signer: Some(UrlSigner::new(b"secret", Duration::from_secs(60))),
+        ..server_config()
+    })
+    .await;
+
+    let client = OfficeConvertClient::new(host.clone())
+        .unwrap()
+        .with_verify_checksum(true);
+    let load_balancer = OfficeConvertLoadBalancer::new([client]);
+
+    let location = load_balancer
+        .convert_stored_with_hints(b"document".to_vec(), &ConvertHints::default())
+        .await
+        .expect("conversion failed");
+
+    assert_eq!(location.backend, host);
+    assert_eq!(location.output.size, FAKE_PDF.len() as u64);
+    assert!(output_dir.join(&location.output.path).exists());
+
+    // Results are only downloaded through the load balancer when requested
+    let url = location.url().unwrap();
+    assert!(url.starts_with(&format!("{host}/outputs/")));
+    assert_eq!(location.download().await.unwrap().as_ref(), FAKE_PDF);
+
+    std::fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[tokio::test]
+async fn stored_results_without_urls_cannot_be_downloaded() {
+    let output_dir = temp_dir().join(format!("lo_native_test_location_{}", random_id()));
+    std::fs::create_dir_all(&output_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        output_dir: Some(output_dir.clone()),
+        ..server_config()
+    })
+    .await;
+
+    let load_balancer = OfficeConvertLoadBalancer::new([OfficeConvertClient::new(host).unwrap()]);
+
+    let location = load_balancer
+        .convert_stored_with_hints(b"document".to_vec(), &ConvertHints::default())
+        .await
+        .expect("conversion failed");
+
+    assert!(location.url().is_none());
+    assert!(matches!(
+        location.download().await,
+        Err(RequestError::NoDownloadUrl { .. })
+    ));
+
+    std::fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[tokio::test]
+async fn presigned_urls_use_the_forwarded_origin() {
+    let output_dir = temp_dir().join(format!("lo_native_test_forwarded_{}", std::process::id()));
+    std::fs::create_dir_all(&output_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        output_dir: Some(output_dir.clone()),
+        url_signer: Some(UrlSigner::new(b"secret", Duration::from_secs(60))),
+        trusted_proxies: TrustedProxies::new(vec![proxy::cidr_arg("127.0.0.0/8").unwrap()]),
+        ..server_config()
+    })
+    .await;
+
+    let store = |forwarded: bool| {
+        let mut request = reqwest::Client::new().post(format!("{host}/convert"));
+        if forwarded {
+            request = request
+                .header("x-forwarded-proto", "https")
+                .header("x-forwarded-host", "convert.example.com");
+        }
+
+        request.multipart(
+            Form::new()
+                .part("file", file_part(b"document", "report.docx"))
+                .text("store", "true"),
+        )
+    };
+
+    let body: serde_json::Value = store(true).send().await.unwrap().json().await.unwrap();
+    let url = body["url"].as_str().unwrap();
+    assert!(url.starts_with("https://convert.example.com/outputs/"));
+
+    // Requests not forwarded by a proxy keep relative URLs
+    let body: serde_json::Value = store(false).send().await.unwrap().json().await.unwrap();
+    assert!(body["url"].as_str().unwrap().starts_with("/outputs/"));
+
+    std::fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[tokio::test]
+async fn client_addresses_are_rate_limited_behind_trusted_proxies() {
+    let host = start_server(ServerConfig {
+        client_limits: ClientLimits::new(KeyLimits {
+            rate: Some(Rate {
+                count: 1,
+                period: Duration::from_secs(60),
+            }),
+            max_concurrent: None,
+        }),
+        trusted_proxies: TrustedProxies::new(vec![proxy::cidr_arg("127.0.0.1").unwrap()]),
+        ..server_config()
+    })
+    .await;
+
+    let convert = |client_ip: &'static str| {
+        reqwest::Client::new()
+            .post(format!("{host}/convert"))
+            .header("x-forwarded-for", format!("{client_ip}, 127.0.0.1"))
+            .multipart(Form::new().part("file", file_part(b"document", "report.docx")))
+            .send()
+    };
+
+    assert_eq!(convert("203.0.113.1").await.unwrap().status().as_u16(), 200);
+
+    let response = convert("203.0.113.1").await.unwrap();
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("retry-after"));
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "rate_limited");
+
+    // Each forwarded client has its own limit
+    assert_eq!(convert("203.0.113.2").await.unwrap().status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn stored_outputs_are_removed_early_or_after_retention() {
+    let output_dir = temp_dir().join(format!("lo_native_test_retention_{}", std::process::id()));
+    std::fs::create_dir_all(&output_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        admin_token: Some("secret".to_string()),
+        output_dir: Some(output_dir.clone()),
+        ..server_config()
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+    let mut paths = Vec::new();
+
+    for file_name in ["first.docx", "second.docx"] {
+        let body: serde_json::Value = client
+            .post(format!("{host}/convert"))
+            .multipart(
+                Form::new()
+                    .part("file", file_part(b"document", file_name))
+                    .text("store", "true"),
+            )
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        paths.push(body["path"].as_str().unwrap().to_string());
+    }
+
+    // Only admins can remove outputs
+    let response = client
+        .delete(format!("{host}/outputs/{}", paths[0]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = client
+        .delete(format!("{host}/outputs/{}", paths[0]))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    assert!(!output_dir.join(&paths[0]).exists());
+
+    let response = client
+        .delete(format!("{host}/outputs/{}", paths[0]))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    // Outputs older than the retention are removed
+    let retention = OutputRetention {
+        output_dir: output_dir.clone(),
+        max_age: Duration::from_secs(60 * 60),
+    };
+    assert_eq!(retention.remove_expired().await.unwrap(), 0);
+    assert!(output_dir.join(&paths[1]).exists());
+
+    let retention = OutputRetention {
+        output_dir: output_dir.clone(),
+        max_age: Duration::ZERO,
+    };
+    assert_eq!(retention.remove_expired().await.unwrap(), 1);
+    assert!(!output_dir.join(&paths[1]).exists());
+
+    std::fs::remove_dir_all(&output_dir).unwrap();
+}
+
+#[test]
+fn profile_settings_are_applied_on_every_start() {
+    let profile_dir = temp_dir().join(format!("lo_native_test_profile_{}", std::process::id()));
+    let registry = profile_dir.join("user/registrymodifications.xcu");
+    let _ = std::fs::remove_dir_all(&profile_dir);
+
+    let settings = ProfileSettings {
+        ctl_locale: Some("ar-SA".to_string()),
+        ctl_font: Some("Noto Sans Arabic".to_string()),
+        font_replacements: vec![FontReplacement {
+            font: "Arial".to_string(),
+            replacement: "Noto Sans & Co".to_string(),
+        }],
+        registry_settings: Vec::new(),
+        dictionaries: Vec::new(),
+    };
+
+    profile::bootstrap(&profile_dir, &settings).unwrap();
+    let first = std::fs::read_to_string(&registry).unwrap();
+
+    assert!(
+        first.contains("<prop oor:name=\"DefaultLocale_CTL\" oor:op=\"fuse\"><value>ar-SA</value>")
+    );
+    assert!(first.contains(
+        "<prop oor:name=\"StandardComplex\" oor:op=\"fuse\"><value>Noto Sans Arabic</value>"
+    ));
+    assert!(first.contains("LocalizedDefaultFonts['ar']"));
+    assert!(first.contains("<value>Noto Sans &amp; Co</value>"));
+    // Defaults are kept alongside the settings
+    assert!(first.contains("Recovery/AutoSave"));
+    assert!(first.trim_end().ends_with("</oor:items>"));
+
+    // Starting again with the same settings doesn't duplicate them
+    profile::bootstrap(&profile_dir, &settings).unwrap();
+    assert_eq!(std::fs::read_to_string(&registry).unwrap(), first);
+
+    // Removed settings are removed from the profile
+    let settings = ProfileSettings {
+        ctl_font: Some("Noto Sans Hebrew".to_string()),
+        ..Default::default()
+    };
+    profile::bootstrap(&profile_dir, &settings).unwrap();
+    let second = std::fs::read_to_string(&registry).unwrap();
+
+    assert!(second.contains("<value>Noto Sans Hebrew</value>"));
+    assert!(!second.contains("Noto Sans Arabic"));
+    assert!(!second.contains("DefaultLocale_CTL"));
+    assert!(!second.contains("FontPairs"));
+    assert!(!second.contains("LocalizedDefaultFonts['ar']"));
+    assert!(second.contains("Recovery/AutoSave"));
+
+    profile::reset(&profile_dir, &ProfileSettings::default()).unwrap();
+    let reset = std::fs::read_to_string(&registry).unwrap();
+    assert!(!reset.contains("Noto Sans"));
+
+    std::fs::remove_dir_all(&profile_dir).unwrap();
+}
+
+#[test]
+fn profile_registry_settings_are_managed() {
+    let profile_dir = temp_dir().join(format!(
+        "lo_native_test_profile_registry_{}",
+        std::process::id()
+    ));
+    let registry = profile_dir.join("user/registrymodifications.xcu");
+    let _ = std::fs::remove_dir_all(&profile_dir);
+
+    profile::bootstrap(&profile_dir, &ProfileSettings::default()).unwrap();
+
+    // Values saved by office are replaced by the configured value
+    let saved = std::fs::read_to_string(&registry).unwrap().replace(
+        "</oor:items>",
+        "<item oor:path=\"/org.openoffice.Office.Writer/Layout/Other\"><prop oor:name=\"MeasureUnit\" oor:op=\"fuse\"><value>8</value></prop></item>\n</oor:items>",
+    );
+    std::fs::write(&registry, saved).unwrap();
+
+    let settings = ProfileSettings {
+        registry_settings: vec![
+            profile::registry_setting_arg("/org.openoffice.Office.Writer/Layout/Other/MeasureUnit=2")
+                .unwrap(),
+            profile::registry_setting_arg(
+                "/org.openoffice.Office.Autocorrect/AutoCorrect/Options/UseReplacementTable = false",
+            )
+            .unwrap(),
+        ],
+        ..Default::default()
+    };
+
+    profile::bootstrap(&profile_dir, &settings).unwrap();
+    let first = std::fs::read_to_string(&registry).unwrap();
+    assert!(first.contains(
+        "<item oor:path=\"/org.openoffice.Office.Writer/Layout/Other\"><prop oor:name=\"MeasureUnit\" oor:op=\"fuse\"><value>2</value></prop></item>"
+    ));
+    assert!(!first.contains("<value>8</value>"));
+    assert!(first
+        .contains("<prop oor:name=\"UseReplacementTable\" oor:op=\"fuse\"><value>false</value>"));
+
+    profile::bootstrap(&profile_dir, &settings).unwrap();
+    assert_eq!(std::fs::read_to_string(&registry).unwrap(), first);
+
+    // Settings that are no longer configured are removed
+    let settings = ProfileSettings {
+        registry_settings: settings.registry_settings[1..].to_vec(),
+        ..Default::default()
+    };
+    profile::bootstrap(&profile_dir, &settings).unwrap();
+    let second = std::fs::read_to_string(&registry).unwrap();
+    assert!(!second.contains("MeasureUnit"));
+    assert!(second.contains("UseReplacementTable"));
+    assert!(second.contains("Recovery/AutoSave"));
+
+    assert!(profile::registry_setting_arg("MeasureUnit=2").is_err());
+    assert!(profile::registry_setting_arg("/org.openoffice.Office.Writer/Layout/Other/").is_err());
+
+    std::fs::remove_dir_all(&profile_dir).unwrap();
+}
+
+#[test]
+fn profile_dictionaries_are_registered() {
+    let dictionary_dir = temp_dir().join(format!(
+        "lo_native_test_dictionaries_{}",
+        std::process::id()
+    ));
+    let profile_dir = temp_dir().join(format!(
+        "lo_native_test_profile_dictionaries_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dictionary_dir);
+    let _ = std::fs::remove_dir_all(&profile_dir);
+    std::fs::create_dir_all(&dictionary_dir).unwrap();
+
+    for name in [
+        "de_DE_frami.aff",
+        "de_DE_frami.dic",
+        "hyph_en_GB.dic",
+        // Spelling dictionaries without an affix file are skipped
+        "fr_FR.dic",
+        "README.txt",
+    ] {
+        std::fs::write(dictionary_dir.join(name), b"").unwrap();
+    }
+
+    let dictionaries = profile::find_dictionaries(&dictionary_dir).unwrap();
+    let names: Vec<(&str, DictionaryKind, &str, usize)> = dictionaries
+        .iter()
+        .map(|dictionary| {
+            (
+                dictionary.name.as_str(),
+                dictionary.kind,
+                dictionary.locale.as_str(),
+                dictionary.locations.len(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("de_DE_frami", DictionaryKind::Spelling, "de-DE", 2),
+            ("hyph_en_GB", DictionaryKind::Hyphenation, "en-GB", 1),
+        ]
+    );
+
+    let settings = ProfileSettings {
+        dictionaries,
+        ..Default::default()
+    };
+    profile::bootstrap(&profile_dir, &settings).unwrap();
+
+    let registry =
+        std::fs::read_to_string(profile_dir.join("user/registrymodifications.xcu")).unwrap();
+    assert!(registry.contains("<node oor:name=\"lo_native_hyph_en_GB\" oor:op=\"replace\"><prop oor:name=\"Format\" oor:op=\"fuse\"><value>DICT_HYPH</value></prop><prop oor:name=\"Locales\" oor:op=\"fuse\"><value><it>en-GB</it></value></prop>"));
+    assert!(registry.contains("de_DE_frami.aff</it><it>file://"));
+
+    // Dictionaries that are removed are unregistered
+    profile::bootstrap(&profile_dir, &ProfileSettings::default()).unwrap();
+    let registry =
+        std::fs::read_to_string(profile_dir.join("user/registrymodifications.xcu")).unwrap();
+    assert!(!registry.contains("lo_native_"));
+
+    std::fs::remove_dir_all(&dictionary_dir).unwrap();
+    std::fs::remove_dir_all(&profile_dir).unwrap();
+}
+
+#[test]
+fn font_replacement_args_are_parsed() {
+    assert_eq!(
+        profile::font_replacement_arg("Arial = Noto Sans").unwrap(),
+        FontReplacement {
+            font: "Arial".to_string(),
+            replacement: "Noto Sans".to_string(),
+        }
+    );
+    assert!(profile::font_replacement_arg("Arial").is_err());
+    assert!(profile::font_replacement_arg("=Noto Sans").is_err());
+    assert!(profile::locale_arg("he-IL").is_ok());
+    assert!(profile::locale_arg("he IL").is_err());
+}
+
+#[test]
+fn api_key_tier_args_are_parsed() {
+    assert_eq!(
+        priority::api_key_tier_arg("reports-team = batch").unwrap(),
+        ApiKeyTier {
+            key: "reports-team".to_string(),
+            tier: PriorityTier::Batch,
+        }
+    );
+    assert!(priority::api_key_tier_arg("reports-team").is_err());
+    assert!(priority::api_key_tier_arg("=batch").is_err());
+    assert!(priority::api_key_tier_arg("reports-team=urgent").is_err());
+}
+
+#[test]
+fn api_key_args_are_parsed() {
+    assert_eq!(
+        auth::api_key_arg("web-app, rate=600/m, max-concurrent=2").unwrap(),
+        ApiKey {
+            key: "web-app".to_string(),
+            limits: KeyLimits {
+                rate: Some(Rate {
+                    count: 600,
+                    period: Duration::from_secs(60),
+                }),
+                max_concurrent: Some(2),
+            },
+        }
+    );
+    assert_eq!(
+        auth::rate_arg("100/30s").unwrap(),
+        Rate {
+            count: 100,
+            period: Duration::from_secs(30),
+        }
+    );
+    assert!(auth::api_key_arg("web-app,burst=2").is_err());
+    assert!(auth::api_key_arg("web-app,max-concurrent=0").is_err());
+    assert!(auth::api_key_arg(",rate=10/s").is_err());
+    assert!(auth::rate_arg("0/s").is_err());
+    assert!(auth::rate_arg("10/week").is_err());
+}
+
+#[test]
+fn trusted_proxies_provide_client_addresses() {
+    let proxies = TrustedProxies::new(vec![
+        proxy::cidr_arg("10.0.0.0/8").unwrap(),
+        proxy::cidr_arg("fd00::/8").unwrap(),
+    ]);
+
+    assert!(proxy::cidr_arg("10.0.0.0/33").is_err());
+    assert!(proxy::cidr_arg("ingress").is_err());
+    assert!(proxies.is_trusted("10.1.2.3".parse().unwrap()));
+    assert!(proxies.is_trusted("::ffff:10.1.2.3".parse().unwrap()));
+    assert!(proxies.is_trusted("fd12::1".parse().unwrap()));
+    assert!(!proxies.is_trusted("192.168.0.1".parse().unwrap()));
+
+    let mut headers = axum::http::HeaderMap::new();
+    headers.insert(
+        "x-forwarded-for",
+        "198.51.100.7, 203.0.113.1, 10.0.0.2".parse().unwrap(),
+    );
+    headers.insert("x-forwarded-proto", "https".parse().unwrap());
+    headers.insert("host", "convert.example.com".parse().unwrap());
+
+    // Addresses before the last untrusted address may be set by the client
+    let proxy_ip = "10.0.0.1".parse().unwrap();
+    assert_eq!(
+        proxies.client_ip(proxy_ip, &headers),
+        "203.0.113.1".parse::<std::net::IpAddr>().unwrap()
+    );
+    assert_eq!(
+        proxies.public_origin(proxy_ip, &headers).as_deref(),
+        Some("https://convert.example.com")
+    );
+
+    // Headers from untrusted peers are ignored
+    let client_ip = "192.168.0.1".parse().unwrap();
+    assert_eq!(proxies.client_ip(client_ip, &headers), client_ip);
+    assert_eq!(proxies.public_origin(client_ip, &headers), None);
+}
+
+#[test]
+fn api_keys_limit_concurrent_conversions() {
+    let api_keys = ApiKeys::new(
+        vec![auth::api_key_arg("web-app").unwrap()],
+        KeyLimits {
+            rate: None,
+            max_concurrent: Some(1),
+        },
+    );
+
+    let running = api_keys.admit("web-app").expect("conversion not admitted");
+    assert!(matches!(
+        api_keys.admit("web-app"),
+        Err(AuthError::TooManyConversions { max: 1 })
+    ));
+
+    drop(running);
+    assert!(api_keys.admit("web-app").is_ok());
+    assert!(matches!(
+        api_keys.admit("unknown"),
+        Err(AuthError::Unauthorized)
+    ));
+}
+
+/// Backend holding each conversion until it is released
+struct GatedBackend {
+    release: Arc<std::sync::Mutex<std::sync::mpsc::Receiver<()>>>,
+}
+
+impl ConvertBackend for GatedBackend {
+    fn details(&self) -> OfficeDetails {
+        OfficeDetails::default()
+    }
+
+    fn convert(
+        &mut self,
+        _input: DocumentInput,
+        _options: ConvertOptions,
+    ) -> anyhow::Result<ConvertedDocument> {
+        self.release.lock().unwrap().recv()?;
+
+        Ok(ConvertedDocument {
+            bytes: Bytes::from_static(FAKE_PDF),
+            file: None,
+            content_type: "application/pdf",
+            warnings: Vec::new(),
+        })
+    }
+
+    fn pipeline(
+        &mut self,
+        _input: DocumentInput,
+        _steps: Vec<PipelineStep>,
+        _options: PipelineOptions,
+    ) -> anyhow::Result<Bytes> {
+        anyhow::bail!("pipelines are not supported by the gated backend")
+    }
+
+    fn extract_assets(&mut self, _input: DocumentInput) -> anyhow::Result<Bytes> {
+        anyhow::bail!("assets are not supported by the gated backend")
+    }
+
+    fn extract_stats(&mut self, _input: DocumentInput) -> anyhow::Result<DocumentStats> {
+        anyhow::bail!("stats are not supported by the gated backend")
+    }
+
+    fn trim_memory(&mut self, _target: i32) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn api_keys_limit_concurrent_jobs() {
+    let jobs_dir = temp_dir().join(format!(
+        "lo_native_test_jobs_admission_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let (release, released) = std::sync::mpsc::channel();
+    let released = Arc::new(std::sync::Mutex::new(released));
+
+    let host = start_server_with(
+        move || GatedBackend {
+            release: released.clone(),
+        },
+        ServerConfig {
+            jobs: Some(Arc::new(JobStore::new(
+                jobs_dir.clone(),
+                Duration::from_secs(60 * 60),
+            ))),
+            api_keys: Some(ApiKeys::new(
+                vec![auth::api_key_arg("web-app,max-concurrent=1").unwrap()],
+                KeyLimits::default(),
+            )),
+            ..server_config()
+        },
+    )
+    .await;
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("x-api-key", "web-app".parse().unwrap());
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+    let submit = || {
+        client
+            .post(format!("{host}/jobs"))
+            .multipart(Form::new().part("file", file_part(b"document", "report.docx")))
+            .send()
+    };
+
+    let response = submit().await.unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+    let location = response.headers()["location"].to_str().unwrap().to_string();
+
+    // The job counts against the key after its response until it finishes
+    let response = submit().await.unwrap();
+    assert_eq!(response.status().as_u16(), 429);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "too_many_conversions");
+
+    release.send(()).unwrap();
+    let job = wait_for_job(&client, &host, &location).await;
+    assert_eq!(job["state"], "done");
+
+    // The admission is released once the finished job has stored its status
+    let mut response = submit().await.unwrap();
+    for _ in 0..100 {
+        if response.status().as_u16() != 429 {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        response = submit().await.unwrap();
+    }
+    assert_eq!(response.status().as_u16(), 202);
+    let location = response.headers()["location"].to_str().unwrap().to_string();
+
+    release.send(()).unwrap();
+    wait_for_job(&client, &host, &location).await;
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn worker_pool_converts_concurrently() {
+    let (release, released) = std::sync::mpsc::channel();
+    let released = Arc::new(std::sync::Mutex::new(released));
+
+    let create_backends = (0..2)
+        .map(|_| {
+            let released = released.clone();
+            move |_| {
+                Ok(GatedBackend {
+                    release: released.clone(),
+                })
+            }
+        })
+        .collect();
+
+    let (office_details, office_handle) = create_office_pool(
+        create_backends,
+        trim_config(),
+        QueueConfig::default(),
+        WatchdogConfig::default(),
+        None,
+    )
+    .await
+    .expect("failed to start runner");
+
+    let office_details = Arc::new(ArcSwap::from_pointee(office_details));
+    let app = server::router(office_handle, office_details, server_config(), None);
+    let host = serve(app).await;
+
+    let client = reqwest::Client::new();
+    let conversions: Vec<_> = (0..3)
+        .map(|_| {
+            let request = client
+                .post(format!("{host}/convert"))
+                .multipart(Form::new().part("file", file_part(b"document", "report.docx")));
+            tokio::spawn(request.send())
+        })
+        .collect();
+
+    // Both workers take a conversion while the third waits in the queue
+    let mut status = serde_json::Value::Null;
+    for _ in 0..200 {
+        status = client
+            .get(format!("{host}/status"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let converting = status["workers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|worker| worker["state"] == "converting")
+            .count();
+        if converting == 2 && status["queue_depth"] == 1 {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(status["queue_depth"], 1);
+    assert_eq!(status["is_busy"], true);
+    let workers = status["workers"].as_array().unwrap();
+    assert_eq!(workers.len(), 2);
+    assert!(workers.iter().all(|worker| worker["state"] == "converting"));
+
+    for _ in 0..3 {
+        release.send(()).unwrap();
+    }
+
+    for conversion in conversions {
+        let response = conversion.await.unwrap().unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.bytes().await.unwrap().as_ref(), FAKE_PDF);
+    }
+
+    let status: serde_json::Value = client
+        .get(format!("{host}/status"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["totals"]["completed"], 3);
+    assert_eq!(status["is_busy"], false);
+}
+
+#[tokio::test]
+async fn disabled_endpoints_are_not_found() {
+    let host = start_server(ServerConfig {
+        disabled_endpoints: vec![
+            config::endpoint_arg("/collect-garbage").unwrap(),
+            config::endpoint_arg("/jobs/{id}").unwrap(),
+            config::endpoint_arg("/admin/*").unwrap(),
+        ],
+        admin_token: Some("admin".to_string()),
+        ..server_config()
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{host}/collect-garbage"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    for path in ["/jobs/abc", "/admin/info", "/admin/cache/abc"] {
+        let response = client
+            .get(format!("{host}{path}"))
+            .header("x-admin-token", "admin")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 404, "{path}");
+    }
+
+    // Endpoints that aren't matched are still routed
+    let response = client.get(format!("{host}/status")).send().await.unwrap();
+    assert!(response.status().is_success());
+    let response = client
+        .get(format!("{host}/jobs/abc/result"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "jobs_disabled");
+
+    let endpoints = server::endpoints();
+    assert!(endpoints.iter().any(|path| path == "/jobs/{id}/result"));
+    assert!(config::endpoint_arg("collect-garbage").is_err());
+}
+
+#[tokio::test]
+async fn api_keys_are_required_when_configured() {
+    let host = start_server(ServerConfig {
+        api_keys: Some(ApiKeys::new(
+            vec![auth::api_key_arg("web-app").unwrap()],
+            KeyLimits::default(),
+        )),
+        ..server_config()
+    })
+    .await;
+
+    for client in [
+        OfficeConvertClient::new(host.clone()).unwrap(),
+        OfficeConvertClient::new(host.clone())
+            .unwrap()
+            .with_api_key(Some("unknown")),
+    ] {
+        let err = client
+            .convert_with_request_id(b"document".to_vec(), "test-request")
+            .await
+            .expect_err("conversion should fail");
+
+        assert_eq!(err.status().map(|status| status.as_u16()), Some(401));
+        assert_eq!(err.code(), Some(&ErrorCode::Unauthorized));
+        assert!(!err.is_retryable());
+        assert!(client.get_status().await.is_err());
+    }
+
+    // Health checks don't have a key
+    let response = reqwest::get(format!("{host}/healthz")).await.unwrap();
+    assert!(response.status().is_success());
+
+    let client = OfficeConvertClient::new(host)
+        .unwrap()
+        .with_api_key(Some("web-app"));
+
+    let output = client
+        .convert_with_request_id(b"document".to_vec(), "test-request")
+        .await
+        .expect("conversion failed");
+    assert_eq!(output.as_ref(), FAKE_PDF);
+    assert!(client.get_status().await.is_ok());
+}
+
+#[tokio::test]
+async fn requests_are_authorized_by_external_authorizer() {
+    let requests = Arc::new(parking_lot::Mutex::new(Vec::<String>::new()));
+
+    let authorizer = serve(axum::Router::new().route(
+        "/authorize",
+        get({
+            let requests = requests.clone();
+            move |headers: HeaderMap| async move {
+                let header = |name: &str| {
+                    headers
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string()
+                };
+
+                requests.lock().push(format!(
+                    "{} {}",
+                    header("x-forwarded-method"),
+                    header("x-forwarded-uri")
+                ));
+
+                match (header("x-api-key").as_str(), header("x-tenant-id").as_str()) {
+                    ("", _) => axum::http::StatusCode::UNAUTHORIZED,
+                    ("web-app", "blocked") => axum::http::StatusCode::FORBIDDEN,
+                    ("web-app", _) => axum::http::StatusCode::NO_CONTENT,
+                    _ => axum::http::StatusCode::UNAUTHORIZED,
+                }
+            }
+        }),
+    ))
+    .await;
+
+    let host = start_server(ServerConfig {
+        authorizer: Some(
+            Authorizer::new(
+                format!("{authorizer}/authorize").parse().unwrap(),
+                Duration::from_secs(60),
+                Duration::from_secs(5),
+            )
+            .unwrap(),
+        ),
+        ..server_config()
+    })
+    .await;
+
+    let err = OfficeConvertClient::new(host.clone())
+        .unwrap()
+        .convert_with_request_id(b"document".to_vec(), "test-request")
+        .await
+        .expect_err("conversion should fail");
+    assert_eq!(err.status().map(|status| status.as_u16()), Some(401));
+    assert_eq!(err.code(), Some(&ErrorCode::Unauthorized));
+
+    let client = OfficeConvertClient::new(host.clone())
+        .unwrap()
+        .with_api_key(Some("web-app"));
+
+    // Decisions are cached for the same request details
+    for _ in 0..2 {
+        let output = client
+            .convert_with_request_id(b"document".to_vec(), "test-request")
+            .await
+            .expect("conversion failed");
+        assert_eq!(output.as_ref(), FAKE_PDF);
+    }
+    assert_eq!(
+        requests.lock().as_slice(),
+        ["POST /convert", "POST /convert"]
+    );
+
+    let response = reqwest::Client::new()
+        .get(format!("{host}/status"))
+        .header("x-api-key", "web-app")
+        .header("x-tenant-id", "blocked")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "forbidden");
+    assert_eq!(
+        requests.lock().last().map(String::as_str),
+        Some("GET /status")
+    );
+
+    // Health checks are not authorized
+    let response = reqwest::get(format!("{host}/healthz")).await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(requests.lock().len(), 3);
+
+    // Nothing listens on the unreachable authorizer
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let unreachable = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let host = start_server(ServerConfig {
+        authorizer: Some(
+            Authorizer::new(
+                unreachable.parse().unwrap(),
+                Duration::from_secs(60),
+                Duration::from_secs(5),
+            )
+            .unwrap(),
+        ),
+        ..server_config()
+    })
+    .await;
+
+    let err = OfficeConvertClient::new(host)
+        .unwrap()
+        .with_api_key(Some("web-app"))
+        .convert_with_request_id(b"document".to_vec(), "test-request")
+        .await
+        .expect_err("conversion should fail");
+    assert_eq!(err.status().map(|status| status.as_u16()), Some(503));
+    assert_eq!(err.code(), Some(&ErrorCode::AuthorizerUnavailable));
+    assert!(err.is_retryable());
+}
+
+#[tokio::test]
+async fn authorizer_is_sent_credential_headers() {
+    let requests = Arc::new(parking_lot::Mutex::new(Vec::<String>::new()));
+
+    let authorizer = serve(axum::Router::new().route(
+        "/authorize",
+        get({
+            let requests = requests.clone();
+            move |headers: HeaderMap| async move {
+                let header = |name: &str| {
+                    headers
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string()
+                };
+
+                requests.lock().push(format!(
+                    "{} {} {} {} {}",
+                    header("x-forwarded-method"),
+                    header("x-forwarded-uri"),
+                    header("authorization"),
+                    header("cookie"),
+                    header("x-session-token"),
+                ));
+
+                match header("authorization").as_str() {
+                    "Bearer valid" => axum::http::StatusCode::NO_CONTENT,
+                    _ => axum::http::StatusCode::UNAUTHORIZED,
+                }
+            }
+        }),
+    ))
+    .await;
+
+    let host = start_server(ServerConfig {
+        authorizer: Some(
+            Authorizer::new(
+                format!("{authorizer}/authorize").parse().unwrap(),
+                Duration::from_secs(60),
+                Duration::from_secs(5),
+            )
+            .unwrap()
+            .with_forwarded_headers(vec![HeaderName::from_static("x-session-token")]),
+        ),
+        ..server_config()
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+    let status = |authorization: &'static str| {
+        client
+            .get(format!("{host}/status?verbose=true"))
+            .header("authorization", authorization)
+            .header("cookie", "session=abc")
+            .header("x-session-token", "token")
+            .send()
+    };
+
+    let response = status("Bearer valid").await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(
+        requests.lock().last().map(String::as_str),
+        Some("GET /status?verbose=true Bearer valid session=abc token")
+    );
+
+    // Decisions are cached by the forwarded headers
+    let response = status("Bearer other").await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = status("Bearer valid").await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(requests.lock().len(), 2);
+}
+
+#[tokio::test]
+async fn api_keys_are_rate_limited() {
+    let host = start_server(ServerConfig {
+        api_keys: Some(ApiKeys::new(
+            vec![
+                auth::api_key_arg("web-app").unwrap(),
+                auth::api_key_arg("reports-team,rate=1/m").unwrap(),
+            ],
+            KeyLimits {
+                rate: Some(Rate {
+                    count: 2,
+                    period: Duration::from_secs(60),
+                }),
+                max_concurrent: None,
+            },
+        )),
+        ..server_config()
+    })
+    .await;
+
+    let client = OfficeConvertClient::new(host.clone())
+        .unwrap()
+        .with_api_key(Some("web-app"));
+
+    for _ in 0..2 {
+        client
+            .convert_with_request_id(b"document".to_vec(), "test-request")
+            .await
+            .expect("conversion failed");
+    }
+
+    let err = client
+        .convert_with_request_id(b"document".to_vec(), "test-request")
+        .await
+        .expect_err("conversion should be rate limited");
+
+    assert_eq!(err.status().map(|status| status.as_u16()), Some(429));
+    assert_eq!(err.code(), Some(&ErrorCode::RateLimited));
+    assert!(err.is_retryable());
+
+    let retry_after = err.response().and_then(|response| response.retry_after());
+    assert!(retry_after.is_some_and(|retry_after| retry_after <= Duration::from_secs(30)));
+
+    // Requests other than conversions are not limited
+    assert!(client.get_status().await.is_ok());
+
+    // Limits are kept separately for each key, keys can set their own rate
+    let client = OfficeConvertClient::new(host)
+        .unwrap()
+        .with_api_key(Some("reports-team"));
+
+    assert!(client.convert(b"document".to_vec()).await.is_ok());
+    assert!(client.convert(b"document".to_vec()).await.is_err());
+}
+
+#[tokio::test]
+async fn priority_gate_admits_higher_tiers_first() {
+    let gate = Arc::new(PriorityGate::new(1));
+    let (order_tx, mut order_rx) = mpsc::unbounded_channel();
+
+    let permit = gate.admit(PriorityTier::Interactive).await;
+
+    // Waiters that stop waiting don't hold up the waiters behind them
+    let abandoned =
+        tokio::time::timeout(Duration::from_millis(10), gate.admit(PriorityTier::Batch)).await;
+    assert!(abandoned.is_err());
+
+    for (name, tier) in [
+        ("batch", PriorityTier::Batch),
+        ("interactive-1", PriorityTier::Interactive),
+        ("interactive-2", PriorityTier::Interactive),
+    ] {
+        let gate = gate.clone();
+        let order_tx = order_tx.clone();
+        tokio::spawn(async move {
+            let _permit = gate.admit(tier).await;
+            _ = order_tx.send(name);
+        });
+
+        // Waiters are queued in the order they arrive
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    drop(permit);
+
+    let mut order = Vec::new();
+    for _ in 0..3 {
+        order.push(order_rx.recv().await.unwrap());
+    }
+
+    // Batch is admitted after one interactive request so it is not starved
+    assert_eq!(order, ["interactive-1", "batch", "interactive-2"]);
+}
+
+#[test]
+fn throttle_windows_apply_limits_by_time_of_day() {
+    let business_hours =
+        throttle::window_arg("09:00-17:00,batch-max-skips=unlimited,max-queue-wait=10s").unwrap();
+    let overnight = throttle::window_arg("22:00-06:00,batch-max-skips=2").unwrap();
+    let windows = [business_hours, overnight];
+
+    let defaults = QueueLimits {
+        max_queue_wait: Some(Duration::from_secs(30)),
+        batch_max_skips: Some(priority::DEFAULT_MAX_SKIPS),
+    };
+    let at = |hours: u64, minutes: u64| hours * 3600 + minutes * 60;
+
+    assert_eq!(
+        throttle::active_limits(&windows, defaults, at(12, 0)),
+        QueueLimits {
+            max_queue_wait: Some(Duration::from_secs(10)),
+            batch_max_skips: None,
+        }
+    );
+
+    // Windows end at their end time and wrap around midnight
+    assert_eq!(
+        throttle::active_limits(&windows, defaults, at(17, 0)),
+        defaults
+    );
+    for now in [at(23, 0), at(5, 59)] {
+        assert_eq!(
+            throttle::active_limits(&windows, defaults, now),
+            QueueLimits {
+                max_queue_wait: Some(Duration::from_secs(30)),
+                batch_max_skips: Some(2),
+            }
+        );
+    }
+
+    for invalid in [
+        "09:00-17:00",
+        "09:00-09:00,batch-max-skips=1",
+        "9am-5pm,batch-max-skips=1",
+        "09:00-17:00,batch-max-skips=-1",
+        "09:00-17:00,max-concurrency=1",
+    ] {
+        assert!(throttle::window_arg(invalid).is_err(), "{invalid}");
+    }
+}
+
+#[tokio::test]
+async fn priority_gate_with_unlimited_skips_admits_batch_last() {
+    let gate = Arc::new(PriorityGate::new(1));
+    gate.set_max_skips(None);
+    assert_eq!(gate.max_skips(), None);
+
+    let (order_tx, mut order_rx) = mpsc::unbounded_channel();
+    let permit = gate.admit(PriorityTier::Interactive).await;
+
+    for (name, tier) in [
+        ("batch", PriorityTier::Batch),
+        ("interactive-1", PriorityTier::Interactive),
+        ("interactive-2", PriorityTier::Interactive),
+    ] {
+        let gate = gate.clone();
+        let order_tx = order_tx.clone();
+        tokio::spawn(async move {
+            let _permit = gate.admit(tier).await;
+            _ = order_tx.send(name);
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    drop(permit);
+
+    let mut order = Vec::new();
+    for _ in 0..3 {
+        order.push(order_rx.recv().await.unwrap());
+    }
+
+    assert_eq!(order, ["interactive-1", "interactive-2", "batch"]);
+}
+
+#[tokio::test]
+async fn api_keys_assign_priority_tiers() {
+    let host = start_server(ServerConfig {
+        api_key_tiers: HashMap::from([("reports-team".to_string(), PriorityTier::Batch)]),
+        ..server_config()
+    })
+    .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .header("x-api-key", "reports-team")
+        .multipart(Form::new().part("file", file_part(b"document", "report.docx")))
+        .send()
+        .await
+        .unwrap();
+
+    assert!(response.status().is_success());
+    assert_eq!(response.bytes().await.unwrap().as_ref(), FAKE_PDF);
+
+    let config = ServerConfig {
+        api_key_tiers: HashMap::from([("reports-team".to_string(), PriorityTier::Batch)]),
+        ..server_config()
+    };
+    let mut headers = axum::http::HeaderMap::new();
+    assert_eq!(config.priority_tier(&headers), PriorityTier::Interactive);
+    headers.insert("x-api-key", "reports-team".parse().unwrap());
+    assert_eq!(config.priority_tier(&headers), PriorityTier::Batch);
+    headers.insert("x-api-key", "unknown".parse().unwrap());
+    assert_eq!(config.priority_tier(&headers), PriorityTier::Interactive);
+}
+
+#[tokio::test]
+async fn failed_conversion_inputs_are_quarantined() {
+    let quarantine_dir =
+        temp_dir().join(format!("lo_native_test_quarantine_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&quarantine_dir);
+
+    let quarantine = Arc::new(Quarantine {
+        dir: quarantine_dir.clone(),
+        max_age: Duration::from_secs(60 * 60),
+    });
+
+    let host = start_server_with(
+        || BrokenBackend,
+        ServerConfig {
+            admin_token: Some("secret".to_string()),
+            quarantine: Some(quarantine.clone()),
+            ..server_config()
+        },
+    )
+    .await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(Form::new().part("file", file_part(b"always fails", "report.docx")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 500);
+
+    // Listing quarantined inputs is admin only
+    let response = client
+        .get(format!("{host}/admin/quarantine"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let body: serde_json::Value = client
+        .get(format!("{host}/admin/quarantine"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let entries = body["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+
+    let entry = &entries[0];
+    assert_eq!(entry["file_name"], "report.docx");
+    assert_eq!(entry["size"], 12);
+    assert!(entry["error"].as_str().unwrap().contains("office crashed"));
+    assert_eq!(entry["options"]["split_sheets"], false);
+
+    let id = entry["id"].as_str().unwrap();
+    let response = client
+        .get(format!("{host}/admin/quarantine/{id}"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"report.docx\""
+    );
+    assert_eq!(response.bytes().await.unwrap().as_ref(), b"always fails");
+
+    let response = client
+        .get(format!("{host}/admin/quarantine/missing"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    // Entries are kept for the retention period
+    assert_eq!(quarantine.remove_expired().await.unwrap(), 0);
+
+    let expired = Quarantine {
+        dir: quarantine_dir.clone(),
+        max_age: Duration::ZERO,
+    };
+    assert_eq!(expired.remove_expired().await.unwrap(), 1);
+    assert!(quarantine.list().await.unwrap().is_empty());
+
+    std::fs::remove_dir_all(&quarantine_dir).unwrap();
+}
+
+#[tokio::test]
+async fn request_failures_are_not_quarantined() {
+    let quarantine_dir = temp_dir().join(format!(
+        "lo_native_test_quarantine_encrypted_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&quarantine_dir);
+
+    let quarantine = Arc::new(Quarantine {
+        dir: quarantine_dir.clone(),
+        max_age: Duration::from_secs(60 * 60),
+    });
+
+    let host = start_server(ServerConfig {
+        quarantine: Some(quarantine.clone()),
+        ..server_config()
+    })
+    .await;
+    let client = OfficeConvertClient::new(host).unwrap();
+
+    client
+        .convert(ENCRYPTED.to_vec())
+        .await
+        .expect_err("conversion should fail");
+
+    assert!(quarantine.list().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn quarantined_failures_export_reproduce_bundles() {
+    let quarantine_dir = temp_dir().join(format!(
+        "lo_native_test_quarantine_bundle_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&quarantine_dir);
+
+    let quarantine = Arc::new(Quarantine {
+        dir: quarantine_dir.clone(),
+        max_age: Duration::from_secs(60 * 60),
+    });
+
+    let host = start_server_with(
+        || BrokenBackend,
+        ServerConfig {
+            admin_token: Some("secret".to_string()),
+            quarantine: Some(quarantine.clone()),
+            ..server_config()
+        },
+    )
+    .await;
+
+    let client = reqwest::Client::new();
+    client
+        .post(format!("{host}/convert"))
+        .multipart(Form::new().part("file", file_part(b"always fails", "report.docx")))
+        .send()
+        .await
+        .unwrap();
+
+    let entries = quarantine.list().await.unwrap();
+    let entry = &entries[0];
+    let diagnostics = entry.diagnostics.as_ref().expect("missing diagnostics");
+    assert!(diagnostics.events.is_empty());
+
+    let read_bundle = |bytes: Bytes| {
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut manifest = String::new();
+        archive
+            .by_name("manifest.json")
+            .unwrap()
+            .read_to_string(&mut manifest)
+            .unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+        let input = archive.by_name("input.docx").ok().map(|mut file| {
+            let mut input = Vec::new();
+            file.read_to_end(&mut input).unwrap();
+            input
+        });
+        (manifest, input)
+    };
+
+    let response = client
+        .get(format!("{host}/admin/quarantine/{}/bundle", entry.id))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["content-type"], "application/zip");
+
+    let (manifest, input) = read_bundle(response.bytes().await.unwrap());
+    assert_eq!(input.as_deref(), Some(b"always fails".as_slice()));
+    assert_eq!(manifest["input_path"], "input.docx");
+    assert_eq!(
+        manifest["input_sha256"],
+        format!("{:x}", Sha256::digest(b"always fails"))
+    );
+    assert_eq!(manifest["failure"]["id"], entry.id.as_str());
+    assert!(manifest["failure"]["error"]
+        .as_str()
+        .unwrap()
+        .contains("office crashed"));
+    assert!(manifest["failure"]["diagnostics"]["events"].is_array());
+    assert!(manifest["server_version"].is_string());
+
+    // Inputs that cannot be shared are only identified by their digest
+    let response = client
+        .get(format!(
+            "{host}/admin/quarantine/{}/bundle?input=false",
+            entry.id
+        ))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap();
+    let (manifest, input) = read_bundle(response.bytes().await.unwrap());
+    assert!(input.is_none());
+    assert!(manifest["input_path"].is_null());
+    assert!(manifest["input_sha256"].is_string());
+
+    std::fs::remove_dir_all(&quarantine_dir).unwrap();
+}
+
+#[tokio::test]
+async fn outputs_can_be_named_by_content_hash() {
+    let output_dir = temp_dir().join(format!("lo_native_test_naming_{}", std::process::id()));
+    std::fs::create_dir_all(&output_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        output_dir: Some(output_dir.clone()),
+        ..server_config()
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+    let sha256 = format!("{:x}", Sha256::digest(FAKE_PDF));
+
+    // Batch entries are named by their digest, failed entries keep their name
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "first.docx"))
+                .part("file", file_part(b"document", "second.docx"))
+                .part("file", file_part(ENCRYPTED, "secret.docx"))
+                .text("naming", "sha256"),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    let bytes = response.bytes().await.unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+    let mut manifest = String::new();
+    archive
+        .by_name("manifest.json")
+        .unwrap()
+        .read_to_string(&mut manifest)
+        .unwrap();
+    let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+
+    assert_eq!(manifest["files"][0]["output"], format!("{sha256}.pdf"));
+    assert_eq!(manifest["files"][0]["sha256"], sha256.as_str());
+    assert_eq!(manifest["files"][1]["output"], format!("{sha256}-2.pdf"));
+    assert_eq!(manifest["files"][2]["output"], "secret.error.json");
+    assert!(manifest["files"][2]["sha256"].is_null());
+    assert!(archive.by_name(&format!("{sha256}.pdf")).is_ok());
+
+    // Storing the same output again reuses its name
+    for _ in 0..2 {
+        let body: serde_json::Value = client
+            .post(format!("{host}/convert"))
+            .multipart(
+                Form::new()
+                    .part("file", file_part(b"document", "report.docx"))
+                    .text("store", "true")
+                    .text("naming", "sha256"),
+            )
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(body["path"], format!("{sha256}.pdf"));
+    }
+
+    let stored: Vec<_> = std::fs::read_dir(&output_dir).unwrap().collect();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(
+        std::fs::read(output_dir.join(format!("{sha256}.pdf"))).unwrap(),
+        FAKE_PDF
+    );
+
+    // Single file downloads are named by their digest
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "report.docx"))
+                .text("disposition", "attachment")
+                .text("naming", "sha256"),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["content-disposition"],
+        format!("attachment; filename=\"{sha256}.pdf\"").as_str()
+    );
+
+    let response = client
+        .post(format!("{host}/convert"))
+        .multipart(
+            Form::new()
+                .part("file", file_part(b"document", "report.docx"))
+                .text("naming", "random"),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    std::fs::remove_dir_all(&output_dir).unwrap();
+}
+
+/// Polls the job at `location` until it has finished converting
+async fn wait_for_job(client: &reqwest::Client, host: &str, location: &str) -> serde_json::Value {
+    for _ in 0..100 {
+        let job: serde_json::Value = client
+            .get(format!("{host}{location}"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        if job["state"] == "done" || job["state"] == "failed" {
+            return job;
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    panic!("job did not finish");
+}
+
+#[tokio::test]
+async fn jobs_convert_in_the_background() {
+    let jobs_dir = temp_dir().join(format!("lo_native_test_jobs_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        jobs: Some(Arc::new(JobStore::new(
+            jobs_dir.clone(),
+            Duration::from_secs(60 * 60),
+        ))),
+        ..server_config()
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{host}/jobs"))
+        .multipart(Form::new().part("file", file_part(b"document", "report.docx")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+    let location = response.headers()["location"].to_str().unwrap().to_string();
+    let job: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(job["state"], "queued");
+    assert_eq!(location, format!("/jobs/{}", job["id"].as_str().unwrap()));
+
+    let job = wait_for_job(&client, &host, &location).await;
+    assert_eq!(job["state"], "done");
+    assert_eq!(job["file_name"], "report.docx");
+    assert_eq!(job["content_type"], "application/pdf");
+    assert_eq!(job["size"], FAKE_PDF.len());
+    assert!(job["queued_ms"].is_u64());
+    assert!(job["duration_ms"].is_u64());
+
+    let response = client
+        .get(format!("{host}{location}/result"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["content-type"], "application/pdf");
+    assert_eq!(
+        response.headers()["x-content-sha256"].to_str().unwrap(),
+        format!("{:x}", Sha256::digest(FAKE_PDF))
+    );
+    assert_eq!(response.bytes().await.unwrap().as_ref(), FAKE_PDF);
+
+    // Failed jobs report their error and have no result
+    let response = client
+        .post(format!("{host}/jobs"))
+        .multipart(Form::new().part("file", file_part(CORRUPTED, "broken.docx")))
+        .send()
+        .await
+        .unwrap();
+    let location = response.headers()["location"].to_str().unwrap().to_string();
+
+    let job = wait_for_job(&client, &host, &location).await;
+    assert_eq!(job["state"], "failed");
+    assert_eq!(job["error_code"], "corrupted");
+
+    let response = client
+        .get(format!("{host}{location}/result"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 409);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "job_not_done");
+    assert_eq!(body["details"]["state"], "failed");
+
+    let response = client
+        .get(format!("{host}/jobs/missing"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "job_not_found");
+
+    // Jobs are rejected when not enabled
+    let disabled = start_server(server_config()).await;
+    let response = client
+        .post(format!("{disabled}/jobs"))
+        .multipart(Form::new().part("file", file_part(b"document", "report.docx")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "jobs_disabled");
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn clients_convert_files_as_jobs() {
+    let jobs_dir = temp_dir().join(format!("lo_native_test_client_jobs_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        jobs: Some(Arc::new(JobStore::new(
+            jobs_dir.clone(),
+            Duration::from_secs(60 * 60),
+        ))),
+        ..server_config()
+    })
+    .await;
+
+    let client = OfficeConvertClient::new(host.as_str())
+        .unwrap()
+        .with_verify_checksum(true);
+    let poll_interval = Duration::from_millis(10);
+
+    let job = client.submit_job(b"document".to_vec()).await.unwrap();
+    assert_eq!(job.state, ClientJobState::Queued);
+
+    let job = client.wait_for_job(&job.id, poll_interval).await.unwrap();
+    assert_eq!(job.state, ClientJobState::Done);
+    assert_eq!(job.content_type.as_deref(), Some("application/pdf"));
+    assert_eq!(
+        client.download_result(&job.id).await.unwrap().as_ref(),
+        FAKE_PDF
+    );
+
+    // Failed jobs report their error code and have no result
+    let job = client.submit_job(CORRUPTED.to_vec()).await.unwrap();
+    let job = client.wait_for_job(&job.id, poll_interval).await.unwrap();
+    assert_eq!(job.state, ClientJobState::Failed);
+    assert_eq!(job.code(), Some(ErrorCode::Corrupted));
+
+    let err = client.download_result(&job.id).await.unwrap_err();
+    assert_eq!(err.code(), Some(&ErrorCode::JobNotDone));
+
+    let err = client.job_status("missing").await.unwrap_err();
+    assert_eq!(err.code(), Some(&ErrorCode::JobNotFound));
+
+    // Jobs submitted through the load balancer are polled on their server
+    let disabled = start_server(server_config()).await;
+    let load_balancer = OfficeConvertLoadBalancer::new([
+        OfficeConvertClient::new(disabled.as_str()).unwrap(),
+        OfficeConvertClient::new(host.as_str()).unwrap(),
+    ]);
+
+    let hints = ConvertHints {
+        exclude_backends: vec![disabled.clone()],
+        ..Default::default()
+    };
+    let location = load_balancer
+        .submit_job_with_hints(b"document".to_vec(), &hints)
+        .await
+        .unwrap();
+    assert_eq!(location.backend, host);
+
+    let job = location.wait(poll_interval).await.unwrap();
+    assert_eq!(job.id, location.id());
+    assert_eq!(job.state, ClientJobState::Done);
+    assert_eq!(location.download().await.unwrap().as_ref(), FAKE_PDF);
+
+    // Servers without jobs reject the submission
+    let err = OfficeConvertClient::new(disabled.as_str())
+        .unwrap()
+        .submit_job(b"document".to_vec())
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), Some(&ErrorCode::JobsDisabled));
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn job_events_stream_state_changes() {
+    let jobs_dir = temp_dir().join(format!("lo_native_test_job_events_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let jobs = Arc::new(JobStore::new(
+        jobs_dir.clone(),
+        Duration::from_secs(60 * 60),
+    ));
+
+    let host = start_server(ServerConfig {
+        jobs: Some(jobs.clone()),
+        ..server_config()
+    })
+    .await;
+
+    let client = OfficeConvertClient::new(host.as_str()).unwrap();
+
+    let job = jobs.create(Some("report.docx".to_string()), 8);
+    let mut events = client.job_events(&job.id).await.unwrap();
+
+    let Some(Ok(JobEvent::Queued(queued))) = events.next().await else {
+        panic!("expected a queued event");
+    };
+    assert_eq!(queued.id, job.id);
+
+    // Hold the job running until released
+    let (release, released) = tokio::sync::oneshot::channel::<()>();
+    let task = tokio::spawn({
+        let jobs = jobs.clone();
+        let id = job.id.clone();
+        async move {
+            jobs.run(&id, async {
+                _ = released.await;
+                Ok(ConvertedDocument {
+                    bytes: Bytes::from_static(FAKE_PDF),
+                    file: None,
+                    content_type: "application/pdf",
+                    warnings: Vec::new(),
+                })
+            })
+            .await
+        }
+    });
+
+    let Some(Ok(JobEvent::Progress(progress))) = events.next().await else {
+        panic!("expected a progress event");
+    };
+    assert_eq!(progress.phase, "idle");
+    assert_eq!(progress.progress, None);
+
+    release.send(()).unwrap();
+    task.await.unwrap();
+
+    let Some(Ok(JobEvent::Done(done))) = events.next().await else {
+        panic!("expected a done event");
+    };
+    assert_eq!(done.size, Some(FAKE_PDF.len() as u64));
+
+    // The stream ends once the job has finished
+    assert!(events.next().await.is_none());
+
+    // Failed jobs end with a failed event
+    let job = client.submit_job(CORRUPTED.to_vec()).await.unwrap();
+    let events: Vec<_> = client.job_events(&job.id).await.unwrap().collect().await;
+    let Some(Ok(JobEvent::Failed(failed))) = events.last() else {
+        panic!("expected a failed event");
+    };
+    assert_eq!(failed.code(), Some(ErrorCode::Corrupted));
+
+    let Err(err) = client.job_events("missing").await else {
+        panic!("unknown jobs should fail");
+    };
+    assert_eq!(err.code(), Some(&ErrorCode::JobNotFound));
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn job_pages_are_validated() {
+    let jobs_dir = temp_dir().join(format!("lo_native_test_job_pages_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        jobs: Some(Arc::new(JobStore::new(
+            jobs_dir.clone(),
+            Duration::from_secs(60 * 60),
+        ))),
+        ..server_config()
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+
+    let mut locations = Vec::new();
+    for file in [b"document".as_slice(), CORRUPTED] {
+        let response = client
+            .post(format!("{host}/jobs"))
+            .multipart(Form::new().part("file", file_part(file, "report.docx")))
+            .send()
+            .await
+            .unwrap();
+        let location = response.headers()["location"].to_str().unwrap().to_string();
+        wait_for_job(&client, &host, &location).await;
+        locations.push(location);
+    }
+
+    let cases = [
+        (
+            format!("{}/pages/1?format=png&dpi=5000", locations[0]),
+            400,
+            "invalid_dpi",
+        ),
+        (format!("{}/pages/1", locations[1]), 409, "job_not_done"),
+        ("/jobs/missing/pages/1".to_string(), 404, "job_not_found"),
+    ];
+
+    for (path, status, code) in cases {
+        let response = client.get(format!("{host}{path}")).send().await.unwrap();
+        assert_eq!(response.status().as_u16(), status, "{path}");
+
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], code, "{path}");
+    }
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn job_page_sources_are_read_in_place_once() {
+    let jobs_dir = temp_dir().join(format!(
+        "lo_native_test_page_sources_{}",
+        std::process::id()
+    ));
+    let work_dir = jobs_dir.join("work");
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&work_dir).unwrap();
+
+    let jobs = JobStore::new(jobs_dir.clone(), Duration::from_secs(60 * 60));
+
+    let job = jobs.create(None, 8);
+    jobs.run(&job.id, async {
+        Ok(ConvertedDocument {
+            bytes: Bytes::from_static(FAKE_PDF),
+            file: None,
+            content_type: "application/pdf",
+            warnings: Vec::new(),
+        })
+    })
+    .await;
+
+    // Results in local storage are read in place and shared between requests
+    let first = jobs.page_source(&job.id, &work_dir).await.unwrap().unwrap();
+    let second = jobs.page_source(&job.id, &work_dir).await.unwrap().unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert!(first.path().starts_with(&jobs_dir));
+    assert_eq!(std::fs::read_dir(&work_dir).unwrap().count(), 0);
+
+    assert!(jobs
+        .page_source("missing", &work_dir)
+        .await
+        .unwrap()
+        .is_none());
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn job_queue_reports_estimated_starts() {
+    let jobs_dir = temp_dir().join(format!("lo_native_test_jobs_queue_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let jobs = Arc::new(JobStore::new(jobs_dir.clone(), Duration::from_secs(60)));
+
+    let convert = || async {
+        Ok(ConvertedDocument {
+            bytes: Bytes::from_static(FAKE_PDF),
+            file: None,
+            content_type: "application/pdf",
+            warnings: Vec::new(),
+        })
+    };
+
+    // The next job starts right away when the slot is free
+    let first = jobs.create(Some("first.docx".to_string()), 8);
+    assert_eq!(
+        jobs.queue().jobs[0].estimated_start_at,
+        Some(first.created_at)
+    );
+    jobs.run(&first.id, convert()).await;
+
+    let running = jobs.create(Some("running.docx".to_string()), 16);
+    let queued = priority::scope(PriorityTier::Batch, async {
+        jobs.create(Some("queued.docx".to_string()), 32)
+    })
+    .await;
+
+    // Hold the slot with a job that doesn't finish until released
+    let (release, released) = tokio::sync::oneshot::channel::<()>();
+    let task = tokio::spawn({
+        let jobs = jobs.clone();
+        let id = running.id.clone();
+        async move {
+            jobs.run(&id, async {
+                _ = released.await;
+                convert().await
+            })
+            .await
+        }
+    });
+
+    while jobs.get(&running.id).unwrap().state != JobState::Running {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    let snapshot = jobs.queue();
+    assert!(snapshot.average_duration_ms.is_some());
+    assert_eq!(snapshot.jobs.len(), 2);
+    assert_eq!(snapshot.jobs[0].id, running.id);
+    assert_eq!(snapshot.jobs[0].state, JobState::Running);
+    assert_eq!(snapshot.jobs[0].size, 16);
+    assert_eq!(snapshot.jobs[1].id, queued.id);
+    assert_eq!(snapshot.jobs[1].state, JobState::Queued);
+    assert_eq!(snapshot.jobs[1].tier, PriorityTier::Batch);
+    assert_eq!(snapshot.jobs[1].file_name.as_deref(), Some("queued.docx"));
+    assert!(snapshot.jobs[1].estimated_start_at >= snapshot.jobs[0].estimated_start_at);
+
+    release.send(()).unwrap();
+    task.await.unwrap();
+
+    // The queue is served to admins of servers with jobs enabled
+    let host = start_server(ServerConfig {
+        admin_token: Some("secret".to_string()),
+        jobs: Some(jobs),
+        ..server_config()
+    })
+    .await;
+    let http = reqwest::Client::new();
+
+    let response = http
+        .get(format!("{host}/admin/queue"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let body: serde_json::Value = http
+        .get(format!("{host}/admin/queue"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["jobs"].as_array().unwrap().len(), 1);
+    assert_eq!(body["jobs"][0]["id"], queued.id);
+    assert_eq!(body["jobs"][0]["tier"], "batch");
+    assert!(body["average_duration_ms"].is_u64());
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn finished_jobs_are_removed_after_retention() {
+    let jobs_dir = temp_dir().join(format!("lo_native_test_jobs_expiry_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let jobs = JobStore::new(jobs_dir.clone(), Duration::ZERO);
+    let job = jobs.create(Some("report.docx".to_string()), 8);
+
+    jobs.run(&job.id, async {
+        Ok(ConvertedDocument {
+            bytes: Bytes::from_static(FAKE_PDF),
+            file: None,
+            content_type: "application/pdf",
+            warnings: Vec::new(),
+        })
+    })
+    .await;
+
+    assert_eq!(jobs.get(&job.id).unwrap().state, JobState::Done);
+    assert!(jobs_dir.join(&job.id).exists());
+
+    assert_eq!(jobs.remove_expired().await.unwrap(), 1);
+    assert!(jobs.get(&job.id).is_none());
+    assert!(!jobs_dir.join(&job.id).exists());
+    assert!(!jobs_dir.join(format!("{}.json", job.id)).exists());
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn job_statuses_outlive_expired_results() {
+    let jobs_dir = temp_dir().join(format!(
+        "lo_native_test_jobs_status_retention_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let storage: Arc<dyn Storage> = Arc::new(LocalStorage::new(jobs_dir.clone()));
+    let jobs = JobStore::with_storage(storage.clone(), Duration::ZERO)
+        .with_status_max_age(Duration::from_secs(60 * 60));
+    let other = JobStore::with_storage(storage, Duration::ZERO);
+
+    let job = jobs.create(Some("report.docx".to_string()), 8);
+    jobs.run(&job.id, async {
+        Ok(ConvertedDocument {
+            bytes: Bytes::from_static(FAKE_PDF),
+            file: None,
+            content_type: "application/pdf",
+            warnings: Vec::new(),
+        })
+    })
+    .await;
+
+    // Results are removed first, the status reports them expired
+    assert_eq!(jobs.remove_expired().await.unwrap(), 0);
+    assert!(!jobs_dir.join(&job.id).exists());
+    assert!(jobs_dir.join(format!("{}.json", job.id)).exists());
+
+    let status = jobs.get(&job.id).unwrap();
+    assert_eq!(status.state, JobState::Done);
+    assert!(status.result_expired);
+    assert!(status.status_expires_at > status.expires_at);
+
+    // Servers sharing the storage keep the status past the result too
+    let status = other.find(&job.id).await.unwrap().unwrap();
+    assert!(status.result_expired);
+    other.remove_expired().await.unwrap();
+    assert!(jobs_dir.join(format!("{}.json", job.id)).exists());
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn job_results_are_deleted_on_request() {
+    let jobs_dir = temp_dir().join(format!(
+        "lo_native_test_jobs_delete_result_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let host = start_server(ServerConfig {
+        jobs: Some(Arc::new(JobStore::new(
+            jobs_dir.clone(),
+            Duration::from_secs(60 * 60),
+        ))),
+        ..server_config()
+    })
+    .await;
+
+    let client = OfficeConvertClient::new(host.as_str()).unwrap();
+    let poll_interval = Duration::from_millis(10);
+
+    let job = client.submit_job(b"document".to_vec()).await.unwrap();
+    let job = client.wait_for_job(&job.id, poll_interval).await.unwrap();
+    assert_eq!(job.state, ClientJobState::Done);
+    assert!(!job.result_expired);
+    assert!(jobs_dir.join(&job.id).exists());
+
+    let http = reqwest::Client::new();
+    let response = http
+        .delete(format!("{host}/jobs/{}/result", job.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    assert!(!jobs_dir.join(&job.id).exists());
+
+    // The status remains and reports the result expired
+    let status = client.job_status(&job.id).await.unwrap();
+    assert_eq!(status.state, ClientJobState::Done);
+    assert!(status.result_expired);
+
+    let response = http
+        .get(format!("{host}/jobs/{}/result", job.id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "job_result_expired");
+
+    let err = client.download_result(&job.id).await.unwrap_err();
+    assert_eq!(err.code(), Some(&ErrorCode::JobResultExpired));
+
+    // Deleting is repeatable, failed jobs never had a result
+    client.delete_job_result(&job.id).await.unwrap();
+
+    let failed = client.submit_job(CORRUPTED.to_vec()).await.unwrap();
+    let failed = client
+        .wait_for_job(&failed.id, poll_interval)
+        .await
+        .unwrap();
+    let err = client.delete_job_result(&failed.id).await.unwrap_err();
+    assert_eq!(err.code(), Some(&ErrorCode::JobNotDone));
+
+    let err = client.delete_job_result("missing").await.unwrap_err();
+    assert_eq!(err.code(), Some(&ErrorCode::JobNotFound));
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn finished_jobs_are_shared_through_storage() {
+    let jobs_dir = temp_dir().join(format!("lo_native_test_jobs_shared_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let storage: Arc<dyn Storage> = Arc::new(LocalStorage::new(jobs_dir.clone()));
+    let first = JobStore::with_storage(storage.clone(), Duration::from_secs(60));
+    let second = JobStore::with_storage(storage, Duration::from_secs(60));
+
+    let job = first.create(Some("report.docx".to_string()), 8);
+    first
+        .run(&job.id, async {
+            Ok(ConvertedDocument {
+                bytes: Bytes::from_static(FAKE_PDF),
+                file: None,
+                content_type: "application/pdf",
+                warnings: Vec::new(),
+            })
+        })
+        .await;
+
+    // Servers sharing the storage report jobs finished by each other
+    assert!(second.get(&job.id).is_none());
+    let status = second.find(&job.id).await.unwrap().unwrap();
+    assert_eq!(status.state, JobState::Done);
+    assert_eq!(status.file_name.as_deref(), Some("report.docx"));
+    assert_eq!(status.content_type.as_deref(), Some("application/pdf"));
+
+    let result = second.open_result(&job.id).await.unwrap().unwrap();
+    assert_eq!(result.size, FAKE_PDF.len() as u64);
+    let bytes: Vec<Bytes> = result.stream.try_collect().await.unwrap();
+    assert_eq!(bytes.concat(), FAKE_PDF);
+
+    assert!(second.find("missing").await.unwrap().is_none());
+    assert!(second.find("../escape").await.unwrap().is_none());
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn pending_jobs_are_shared_through_storage() {
+    let jobs_dir = temp_dir().join(format!(
+        "lo_native_test_jobs_pending_{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let storage: Arc<dyn Storage> = Arc::new(LocalStorage::new(jobs_dir.clone()));
+    let first = Arc::new(JobStore::with_storage(
+        storage.clone(),
+        Duration::from_secs(60),
+    ));
+    let second = JobStore::with_storage(storage, Duration::from_secs(60));
+
+    // Submitted jobs are reported by other servers before they start
+    let job = first.submit(None, 8).await.unwrap();
+    let status = second.find(&job.id).await.unwrap().unwrap();
+    assert_eq!(status.state, JobState::Queued);
+
+    let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+    let (finish_tx, finish_rx) = tokio::sync::oneshot::channel::<()>();
+
+    let run = tokio::spawn({
+        let first = first.clone();
+        let id = job.id.clone();
+        async move {
+            first
+                .run(&id, async {
+                    started_tx.send(()).unwrap();
+                    finish_rx.await.unwrap();
+                    Ok(ConvertedDocument {
+                        bytes: Bytes::from_static(FAKE_PDF),
+                        file: None,
+                        content_type: "application/pdf",
+                        warnings: Vec::new(),
+                    })
+                })
+                .await
+        }
+    });
+
+    started_rx.await.unwrap();
+    let status = second.find(&job.id).await.unwrap().unwrap();
+    assert_eq!(status.state, JobState::Running);
+    assert!(second.open_result(&job.id).await.unwrap().is_none());
+
+    finish_tx.send(()).unwrap();
+    run.await.unwrap();
+
+    let status = second.find(&job.id).await.unwrap().unwrap();
+    assert_eq!(status.state, JobState::Done);
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[tokio::test]
+async fn storage_leases_are_held_by_one_owner() {
+    let dir = temp_dir().join(format!("lo_native_test_leases_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let storage = LocalStorage::new(dir.clone());
+    let ttl = Duration::from_secs(60);
+
+    assert!(storage
+        .acquire_lease("test.lease", "first", ttl)
+        .await
+        .unwrap());
+    assert!(!storage
+        .acquire_lease("test.lease", "second", ttl)
+        .await
+        .unwrap());
+
+    // Owners renew their own lease
+    assert!(storage
+        .acquire_lease("test.lease", "first", ttl)
+        .await
+        .unwrap());
+
+    // Expired leases are taken over
+    assert!(storage
+        .acquire_lease("test.lease", "first", Duration::ZERO)
+        .await
+        .unwrap());
+    assert!(storage
+        .acquire_lease("test.lease", "second", ttl)
+        .await
+        .unwrap());
+    assert!(!storage
+        .acquire_lease("test.lease", "first", ttl)
+        .await
+        .unwrap());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn shared_job_storage_is_swept_by_one_server() {
+    let jobs_dir = temp_dir().join(format!("lo_native_test_jobs_sweep_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&jobs_dir);
+    std::fs::create_dir_all(&jobs_dir).unwrap();
+
+    let storage: Arc<dyn Storage> = Arc::new(LocalStorage::new(jobs_dir.clone()));
+    let first = JobStore::with_storage(storage.clone(), Duration::ZERO);
+    let second = JobStore::with_storage(storage, Duration::ZERO);
+
+    std::fs::write(jobs_dir.join("leftover"), b"result").unwrap();
+    first.remove_expired().await.unwrap();
+    assert!(!jobs_dir.join("leftover").exists());
+    assert!(jobs_dir.join("cleanup.lease").exists());
+
+    // Results left behind are only removed by the server holding the lease
+    std::fs::write(jobs_dir.join("leftover"), b"result").unwrap();
+    second.remove_expired().await.unwrap();
+    assert!(jobs_dir.join("leftover").exists());
+
+    first.remove_expired().await.unwrap();
+    assert!(!jobs_dir.join("leftover").exists());
+
+    std::fs::remove_dir_all(&jobs_dir).unwrap();
+}
+
+#[test]
+fn storage_locations_are_parsed() {
+    assert_eq!(
+        storage::storage_arg("/var/lib/jobs"),
+        Ok(StorageLocation::Local("/var/lib/jobs".into()))
+    );
+    assert_eq!(
+        storage::storage_arg("file:///var/lib/jobs"),
+        Ok(StorageLocation::Local("/var/lib/jobs".into()))
+    );
+    assert_eq!(
+        storage::storage_arg("s3://bucket/results/jobs/"),
+        Ok(StorageLocation::S3 {
+            bucket: "bucket".to_string(),
+            prefix: "results/jobs/".to_string()
+        })
+    );
+    assert_eq!(
+        storage::storage_arg("gs://bucket"),
+        Ok(StorageLocation::Gcs {
+            bucket: "bucket".to_string(),
+            prefix: String::new()
+        })
+    );
+    assert_eq!(
+        storage::storage_arg("azure://container/cache"),
+        Ok(StorageLocation::Azure {
+            container: "container".to_string(),
+            prefix: "cache/".to_string()
+        })
+    );
+    assert_eq!(
+        storage::storage_arg("redis://redis/jobs"),
+        Ok(StorageLocation::Redis {
+            address: "redis:6379".to_string(),
+            prefix: "jobs/".to_string()
+        })
+    );
+    assert_eq!(
+        storage::storage_arg("redis://10.0.0.5:6380"),
+        Ok(StorageLocation::Redis {
+            address: "10.0.0.5:6380".to_string(),
+            prefix: String::new()
+        })
+    );
+    assert!(storage::storage_arg("s3:///jobs").is_err());
+    assert!(storage::storage_arg("ftp://host/jobs").is_err());
+}
+
+#[tokio::test]
+async fn result_cache_validates_entries_after_restart() {
+    let cache_dir = temp_dir().join(format!("lo_native_test_cache_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache_dir);
+
+    let sha256 = format!("{:x}", Sha256::digest(FAKE_PDF));
+    let valid = ResultCache::key(b"document", "valid");
+    let damaged = ResultCache::key(b"document", "damaged");
+    let missing = ResultCache::key(b"document", "missing");
+
+    for level in [None, Some(3)] {
+        let cache = ResultCache::new(cache_dir.clone(), Duration::from_secs(60), level).unwrap();
+        for key in [&valid, &damaged, &missing] {
+            cache
+                .put(
+                    key,
+                    Bytes::from_static(FAKE_PDF),
+                    "application/pdf",
+                    &sha256,
+                )
+                .await
+                .unwrap();
+        }
+
+        // Damage the stored results while the server is not running
+        let damaged_path = cache_dir.join(format!("{damaged}.bin"));
+        let mut bytes = std::fs::read(&damaged_path).unwrap();
+        bytes[0] ^= 0xff;
+        std::fs::write(&damaged_path, bytes).unwrap();
+        std::fs::remove_file(cache_dir.join(format!("{missing}.bin"))).unwrap();
+
+        let cache = ResultCache::new(cache_dir.clone(), Duration::from_secs(60), level).unwrap();
+
+        let result = cache.get(&valid).await.unwrap().expect("entry was lost");
+        let result = result.decompress().await.unwrap();
+        assert_eq!(result.bytes.as_ref(), FAKE_PDF);
+        assert_eq!(result.sha256.as_deref(), Some(sha256.as_str()));
+
+        assert!(cache.get(&damaged).await.unwrap().is_none());
+        assert!(cache.get(&missing).await.unwrap().is_none());
+        assert!(!cache_dir.join(format!("{damaged}.json")).exists());
+        assert!(!cache_dir.join(format!("{missing}.json")).exists());
+
+        let stats = cache.stats().await.unwrap();
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.invalid, 2);
+
+        cache.purge().await.unwrap();
+    }
+
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+#[tokio::test]
+async fn result_cache_index_survives_restarts() {
+    let cache_dir = temp_dir().join(format!("lo_native_test_cache_index_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    std::fs::create_dir_all(&cache_dir).unwrap();
+
+    let sha256 = format!("{:x}", Sha256::digest(FAKE_PDF));
+    let legacy = ResultCache::key(b"document", "legacy");
+    let indexed = ResultCache::key(b"document", "indexed");
+    let leftover = ResultCache::key(b"document", "leftover");
+
+    // Entries stored with their metadata alongside are moved into the index
+    let storage: Arc<dyn Storage> = Arc::new(LocalStorage::new(cache_dir.clone()));
+    ResultCache::with_storage(storage, Duration::from_secs(60), None)
+        .put(
+            &legacy,
+            Bytes::from_static(FAKE_PDF),
+            "application/pdf",
+            &sha256,
+        )
+        .await
+        .unwrap();
+    assert!(cache_dir.join(format!("{legacy}.json")).exists());
+
+    let cache = ResultCache::new(cache_dir.clone(), Duration::from_secs(60), None).unwrap();
+    assert!(!cache_dir.join(format!("{legacy}.json")).exists());
+    assert!(cache_dir.join("index.sqlite").exists());
+    assert!(cache.get(&legacy).await.unwrap().is_some());
+
+    cache
+        .put(
+            &indexed,
+            Bytes::from_static(FAKE_PDF),
+            "application/pdf",
+            &sha256,
+        )
+        .await
+        .unwrap();
+    assert!(!cache_dir.join(format!("{indexed}.json")).exists());
+    drop(cache);
+
+    // The index is kept across restarts
+    let cache = ResultCache::new(cache_dir.clone(), Duration::from_secs(60), None).unwrap();
+    let stats = cache.stats().await.unwrap();
+    assert_eq!(stats.entries, 2);
+    assert_eq!(stats.size_bytes, 2 * FAKE_PDF.len() as u64);
+
+    let result = cache.get(&indexed).await.unwrap().expect("entry was lost");
+    assert_eq!(result.bytes.as_ref(), FAKE_PDF);
+    assert_eq!(result.content_type, "application/pdf");
+
+    // Data without an entry and entries without data are cleaned up
+    std::fs::write(cache_dir.join(format!("{leftover}.bin")), FAKE_PDF).unwrap();
+    std::fs::remove_file(cache_dir.join(format!("{legacy}.bin"))).unwrap();
+    assert_eq!(cache.cleanup().await.unwrap(), 2);
+    assert!(!cache_dir.join(format!("{leftover}.bin")).exists());
+    assert_eq!(cache.stats().await.unwrap().entries, 1);
+
+    assert!(cache.evict(&indexed).await.unwrap());
+    assert!(!cache.evict(&indexed).await.unwrap());
+    assert!(cache.get(&indexed).await.unwrap().is_none());
+    assert_eq!(cache.purge().await.unwrap(), 0);
+    assert_eq!(cache.stats().await.unwrap().size_bytes, 0);
+
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+/// Backend recording whether inputs were streamed to disk, writing outputs
+/// to files in `dir` when streaming the output was requested
+struct StreamingBackend {
+    dir: std::path::PathBuf,
+    spilled: Arc<AtomicUsize>,
+}
+
+impl ConvertBackend for StreamingBackend {
+    fn details(&self) -> OfficeDetails {
+        OfficeDetails::default()
+    }
+
+    fn convert(
+        &mut self,
+        input: DocumentInput,
+        options: ConvertOptions,
+    ) -> anyhow::Result<ConvertedDocument> {
+        if matches!(input, DocumentInput::Spilled(_)) {
+            self.spilled.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let bytes = input.into_bytes()?;
+        if !options.stream_output {
+            return Ok(ConvertedDocument {
+                bytes,
+                file: None,
+                content_type: "application/pdf",
+                warnings: Vec::new(),
+            });
+        }
+
+        let file = TempFile {
+            path: self.dir.join("lo_native_result_streamed.pdf"),
+        };
+        std::fs::write(&file.path, &bytes)?;
+
+        Ok(ConvertedDocument {
+            bytes: Bytes::new(),
+            file: Some(file),
+            content_type: "application/pdf",
+            warnings: Vec::new(),
+        })
+    }
+
+    fn pipeline(
+        &mut self,
+        _input: DocumentInput,
+        _steps: Vec<PipelineStep>,
+        _options: PipelineOptions,
+    ) -> anyhow::Result<Bytes> {
+        anyhow::bail!("pipelines are not supported by the streaming backend")
+    }
+
+    fn extract_assets(&mut self, _input: DocumentInput) -> anyhow::Result<Bytes> {
+        anyhow::bail!("assets are not supported by the streaming backend")
+    }
+
+    fn extract_stats(&mut self, _input: DocumentInput) -> anyhow::Result<DocumentStats> {
+        anyhow::bail!("stats are not supported by the streaming backend")
+    }
+
+    fn trim_memory(&mut self, _target: i32) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn convert_streams_uploads_and_outputs_from_disk() {
+    let work_dir = temp_dir().join(format!("lo_native_test_streaming_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&work_dir);
+    std::fs::create_dir_all(&work_dir).unwrap();
+
+    let spilled = Arc::new(AtomicUsize::new(0));
+    let host = start_server_with(
+        {
+            let dir = work_dir.clone();
+            let spilled = spilled.clone();
+            move || StreamingBackend {
+                dir: dir.clone(),
+                spilled: spilled.clone(),
+            }
+        },
+        ServerConfig {
+            work_dir: work_dir.clone(),
+            ..server_config()
+        },
+    )
+    .await;
+
+    // Output echoes the input so the whole upload must reach the backend
+    let input: Vec<u8> = (0..512 * 1024).map(|index| (index % 251) as u8).collect();
+    let digest = format!("{:x}", Sha256::digest(&input));
+
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .header("x-content-sha256", &digest)
+        .multipart(Form::new().part("file", Part::bytes(input.clone()).file_name("large.docx")))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-content-sha256"], digest.as_str());
+    assert_eq!(
+        response.headers()["content-length"],
+        input.len().to_string().as_str()
+    );
+    assert_eq!(response.bytes().await.unwrap().as_ref(), input.as_slice());
+    assert_eq!(spilled.load(Ordering::SeqCst), 1);
+
+    // Both the upload and the streamed output are removed once sent
+    let remaining = std::fs::read_dir(&work_dir).unwrap().count();
+    assert_eq!(remaining, 0);
+
+    // Truncated uploads are still rejected against their checksum
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .header("x-content-sha256", &digest)
+        .multipart(Form::new().part(
+            "file",
+            Part::bytes(input[..1024].to_vec()).file_name("large.docx"),
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "checksum_mismatch");
+
+    std::fs::remove_dir_all(&work_dir).unwrap();
+}
+
+#[test]
+fn visual_diff_reports_changed_pixels() {
+    let white = |width: u32, height: u32| Raster {
+        width,
+        height,
+        pixels: vec![255; width as usize * height as usize * 4],
+    };
+
+    let old = white(10, 10);
+
+    // Rendered pages survive a round trip through png
+    let decoded = Raster::decode_png(&old.encode_png().unwrap()).unwrap();
+    assert_eq!(decoded, old);
+
+    // Anti-aliasing noise within the tolerance is ignored
+    let mut noisy = old.clone();
+    noisy.pixels[0] = 250;
+    let comparison = visual_diff::compare(&old, &noisy, 16);
+    assert_eq!(comparison.changed_pixels, 0);
+    assert!(!comparison.size_changed);
+
+    // A changed row of pixels is counted and highlighted
+    let mut changed = old.clone();
+    for pixel in changed.pixels[..10 * 4].chunks_exact_mut(4) {
+        pixel.copy_from_slice(&[0, 0, 0, 255]);
+    }
+    let comparison = visual_diff::compare(&old, &changed, 16);
+    assert_eq!(comparison.changed_pixels, 10);
+    assert_eq!(comparison.changed_percent(), 10.0);
+    assert_eq!(&comparison.image.pixels[..4], &[255, 0, 0, 255]);
+
+    // Pixels only covered by one rendering count as changed
+    let comparison = visual_diff::compare(&old, &white(10, 12), 16);
+    assert!(comparison.size_changed);
+    assert_eq!(comparison.changed_pixels, 20);
+    assert_eq!(comparison.total_pixels, 120);
+}
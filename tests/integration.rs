@@ -0,0 +1,660 @@
+//! End-to-end tests running the server binary against a real LibreOffice
+//! install from the "LIBREOFFICE_SDK_PATH" environment variable, enabled
+//! with the "integration-tests" feature
+
+use futures_util::StreamExt;
+use office_convert_client::{JobEvent, OfficeConvertClient};
+use office_convert_server::{convert::pdf_page_count, tempfiles::random_id};
+use reqwest::multipart::{Form, Part};
+use std::{
+    env::temp_dir,
+    io::{Cursor, Read},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+use zip::ZipArchive;
+
+/// Maximum time to wait for office to start
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Maximum time to wait for a background job to finish
+const JOB_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Plain text document
+const TEXT: &[u8] = b"Quarterly report\n\nRevenue grew across every region.\n";
+
+/// Comma separated values
+const CSV: &[u8] = b"region,total\nnorth,120\nsouth,80\n";
+
+/// HTML document
+const HTML: &[u8] = b"<!DOCTYPE html><html><head><title>Report</title></head>\
+<body><h1>Quarterly report</h1><p>Revenue grew across every region.</p></body></html>";
+
+/// RTF document
+const RTF: &[u8] = br"{\rtf1\ansi\deff0{\fonttbl{\f0 Times New Roman;}}\f0 Quarterly report\par}";
+
+/// Flat ODF text document with three pages, each page after the first
+/// starts with a page break
+const FLAT_TEXT: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document
+    xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0"
+    xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0"
+    xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"
+    xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0"
+    office:version="1.2"
+    office:mimetype="application/vnd.oasis.opendocument.text">
+  <office:automatic-styles>
+    <style:style style:name="Break" style:family="paragraph">
+      <style:paragraph-properties fo:break-before="page"/>
+    </style:style>
+  </office:automatic-styles>
+  <office:body>
+    <office:text>
+      <text:p>First page</text:p>
+      <text:p text:style-name="Break">Second page</text:p>
+      <text:p text:style-name="Break">Third page</text:p>
+    </office:text>
+  </office:body>
+</office:document>
+"#;
+
+/// Flat ODF spreadsheet with two sheets
+const FLAT_SPREADSHEET: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document
+    xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0"
+    xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0"
+    xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"
+    office:version="1.2"
+    office:mimetype="application/vnd.oasis.opendocument.spreadsheet">
+  <office:body>
+    <office:spreadsheet>
+      <table:table table:name="North">
+        <table:table-row>
+          <table:table-cell office:value-type="string"><text:p>north</text:p></table:table-cell>
+          <table:table-cell office:value-type="float" office:value="120"><text:p>120</text:p></table:table-cell>
+        </table:table-row>
+      </table:table>
+      <table:table table:name="South">
+        <table:table-row>
+          <table:table-cell office:value-type="string"><text:p>south</text:p></table:table-cell>
+          <table:table-cell office:value-type="float" office:value="80"><text:p>80</text:p></table:table-cell>
+        </table:table-row>
+      </table:table>
+    </office:spreadsheet>
+  </office:body>
+</office:document>
+"#;
+
+/// Documents of each format converted by the corpus tests along with the
+/// number of pages their PDF must have
+const CORPUS: &[(&str, &[u8], usize)] = &[
+    ("report.txt", TEXT, 1),
+    ("report.csv", CSV, 1),
+    ("report.html", HTML, 1),
+    ("report.rtf", RTF, 1),
+    ("report.fodt", FLAT_TEXT, 3),
+    ("report.fods", FLAT_SPREADSHEET, 2),
+];
+
+/// Server process started for a test, stopped when dropped
+struct Server {
+    /// Server process
+    process: Child,
+    /// Directory holding the work, profile and jobs directories
+    dir: PathBuf,
+    /// Base URL of the server
+    host: String,
+}
+
+impl Server {
+    /// Starts the server binary on a free port, waiting until office has
+    /// started and the server is accepting requests
+    async fn start() -> Self {
+        Self::start_with(&[]).await
+    }
+
+    /// Starts the server binary with the additional `args`
+    async fn start_with(args: &[&str]) -> Self {
+        let office_path = std::env::var("LIBREOFFICE_SDK_PATH").expect(
+            "LIBREOFFICE_SDK_PATH must be set to the office install to run the integration tests",
+        );
+
+        let dir = temp_dir().join(format!("lo_native_integration_{}", random_id()));
+        for name in ["work", "profile", "jobs"] {
+            std::fs::create_dir_all(dir.join(name)).unwrap();
+        }
+
+        // Office lives in the server process so each test runs its own server
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let process = Command::new(env!("CARGO_BIN_EXE_office-convert-server"))
+            .arg("--office-path")
+            .arg(&office_path)
+            .args(["--host", "127.0.0.1", "--port", &port.to_string()])
+            .arg("--work-dir")
+            .arg(dir.join("work"))
+            .arg("--profile-dir")
+            .arg(dir.join("profile"))
+            .arg("--jobs-dir")
+            .arg(dir.join("jobs"))
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .expect("failed to start server");
+
+        let mut server = Self {
+            process,
+            dir,
+            host: format!("http://127.0.0.1:{port}"),
+        };
+        server.wait_until_ready().await;
+        server
+    }
+
+    /// Waits for the health check to succeed, office is started before the
+    /// server accepts connections (Unless idle shutdown is enabled)
+    async fn wait_until_ready(&mut self) {
+        let client = reqwest::Client::new();
+        let started = std::time::Instant::now();
+
+        while started.elapsed() < STARTUP_TIMEOUT {
+            if let Some(status) = self.process.try_wait().unwrap() {
+                panic!("server exited during startup: {status}");
+            }
+
+            let response = client.get(format!("{}/healthz", self.host)).send().await;
+            if response.is_ok_and(|response| response.status().is_success()) {
+                return;
+            }
+
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+
+        panic!("server did not start within {STARTUP_TIMEOUT:?}");
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        _ = self.process.kill();
+        _ = self.process.wait();
+        _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Creates a multipart part for the `bytes` of a file named `name`
+fn file_part(bytes: &'static [u8], name: &'static str) -> Part {
+    Part::bytes(bytes).file_name(name)
+}
+
+/// Asserts the `bytes` are a complete PDF, providing its page count
+fn assert_pdf(bytes: &[u8], name: &str) -> usize {
+    assert!(bytes.starts_with(b"%PDF-"), "{name} is not a pdf");
+
+    // Trailing end of file marker is missing from truncated outputs
+    let tail = &bytes[bytes.len().saturating_sub(64)..];
+    assert!(
+        tail.windows(5).any(|window| window == b"%%EOF"),
+        "{name} is truncated"
+    );
+
+    let pages = pdf_page_count(bytes);
+    assert!(pages > 0, "{name} has no pages");
+    pages
+}
+
+/// Reads the file named `name` from a zip `archive`
+fn read_entry(archive: &mut ZipArchive<Cursor<bytes::Bytes>>, name: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    archive
+        .by_name(name)
+        .unwrap_or_else(|_| panic!("archive is missing {name}"))
+        .read_to_end(&mut bytes)
+        .unwrap();
+    bytes
+}
+
+/// Posts the multipart `form` to the `/convert` endpoint, providing the
+/// successful response body
+async fn convert_form(host: &str, form: Form) -> bytes::Bytes {
+    let response = reqwest::Client::new()
+        .post(format!("{host}/convert"))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = response.bytes().await.unwrap();
+    assert!(
+        status.is_success(),
+        "conversion failed with {status}: {}",
+        String::from_utf8_lossy(&body)
+    );
+    body
+}
+
+#[tokio::test]
+async fn corpus_converts_to_valid_pdfs() {
+    let server = Server::start().await;
+
+    for (name, bytes, expected_pages) in CORPUS {
+        let form = Form::new().part("file", file_part(bytes, name));
+        let output = convert_form(&server.host, form).await;
+
+        let pages = assert_pdf(&output, name);
+        assert_eq!(pages, *expected_pages, "{name} has the wrong page count");
+    }
+}
+
+#[tokio::test]
+async fn external_corpus_converts_to_valid_pdfs() {
+    // Additional documents (i.e real world docx, xlsx and pptx files) are
+    // converted when a corpus directory is provided
+    let Ok(dir) = std::env::var("INTEGRATION_CORPUS_DIR") else {
+        return;
+    };
+
+    let server = Server::start().await;
+    let client = OfficeConvertClient::new(server.host.clone()).unwrap();
+
+    let mut files: Vec<PathBuf> = std::fs::read_dir(Path::new(&dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+
+    for path in files {
+        let name = path.display().to_string();
+        let bytes = std::fs::read(&path).unwrap();
+
+        let output = client
+            .convert_with_request_id(bytes, "integration")
+            .await
+            .unwrap_or_else(|err| panic!("{name} failed to convert: {err}"));
+        assert_pdf(&output, &name);
+    }
+}
+
+#[tokio::test]
+async fn client_converts_and_reports_office_details() {
+    let server = Server::start().await;
+    let client = OfficeConvertClient::new(server.host.clone())
+        .unwrap()
+        .with_verify_checksum(true);
+
+    let version = client.get_office_version().await.unwrap();
+    assert!(version.major > 0);
+
+    let formats = client.get_supported_formats().await.unwrap();
+    assert!(formats.iter().any(|format| format.mime
+        == "application/vnd.openxmlformats-officedocument.wordprocessingml.document"));
+
+    let output = client
+        .convert_with_request_id(FLAT_TEXT.to_vec(), "integration")
+        .await
+        .unwrap();
+    assert_eq!(assert_pdf(&output, "report.fodt"), 3);
+
+    // Bytes office cannot load are reported as corrupted
+    let err = client
+        .convert_with_request_id(vec![0xFF, 0x00, 0xFE, 0x13, 0x37], "integration")
+        .await
+        .expect_err("garbage should fail to convert");
+    assert!(err.response().is_some(), "{err}");
+}
+
+#[tokio::test]
+async fn jobs_convert_in_the_background() {
+    let server = Server::start().await;
+    let host = &server.host;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{host}/jobs"))
+        .multipart(Form::new().part("file", file_part(FLAT_TEXT, "report.fodt")))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+    let location = response.headers()["location"].to_str().unwrap().to_string();
+
+    let started = std::time::Instant::now();
+    let job = loop {
+        let job: serde_json::Value = client
+            .get(format!("{host}{location}"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        if job["state"] == "done" || job["state"] == "failed" {
+            break job;
+        }
+
+        assert!(started.elapsed() < JOB_TIMEOUT, "job did not finish");
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    };
+    assert_eq!(job["state"], "done", "{job}");
+    assert_eq!(job["content_type"], "application/pdf");
+
+    let result = client
+        .get(format!("{host}{location}/result"))
+        .send()
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    assert_eq!(job["size"], result.len());
+    assert_eq!(assert_pdf(&result, "job result"), 3);
+
+    // Single pages are split from the result
+    let page = client
+        .get(format!("{host}{location}/pages/2"))
+        .send()
+        .await
+        .unwrap();
+    assert!(page.status().is_success());
+    let page = page.bytes().await.unwrap();
+    assert_eq!(assert_pdf(&page, "job page"), 1);
+
+    // Events are pushed until the job has finished
+    let convert_client = OfficeConvertClient::new(host.as_str()).unwrap();
+    let job = convert_client.submit_job(FLAT_TEXT.to_vec()).await.unwrap();
+    let events = convert_client.job_events(&job.id).await.unwrap();
+    let events: Vec<_> = tokio::time::timeout(JOB_TIMEOUT, events.collect())
+        .await
+        .expect("job did not finish");
+    assert!(
+        matches!(events.last(), Some(Ok(JobEvent::Done(_)))),
+        "{events:?}"
+    );
+}
+
+#[tokio::test]
+async fn batches_convert_each_file() {
+    let server = Server::start().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/convert-batch", server.host))
+        .multipart(
+            Form::new()
+                .part("file", file_part(FLAT_TEXT, "letter.fodt"))
+                .part("file", file_part(&[0xFF, 0x00, 0xFE], "broken.docx"))
+                .part("file", file_part(HTML, "page.html")),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let bytes = response.bytes().await.unwrap();
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&read_entry(&mut archive, "manifest.json")).unwrap();
+    assert_eq!(manifest["succeeded"], 2, "{manifest}");
+    assert_eq!(manifest["failed"], 1, "{manifest}");
+    assert_eq!(manifest["files"][0]["page_count"], 3, "{manifest}");
+
+    assert_eq!(
+        assert_pdf(&read_entry(&mut archive, "letter.pdf"), "letter"),
+        3
+    );
+    assert_pdf(&read_entry(&mut archive, "page.pdf"), "page");
+    assert!(archive.by_name("broken.error.json").is_ok());
+}
+
+#[tokio::test]
+async fn options_shape_the_output() {
+    let server = Server::start().await;
+    let host = &server.host;
+
+    // Each sheet is exported as a separate PDF
+    let output = convert_form(
+        host,
+        Form::new()
+            .part("file", file_part(FLAT_SPREADSHEET, "sheets.fods"))
+            .text("split_sheets", "true"),
+    )
+    .await;
+    let mut archive = ZipArchive::new(Cursor::new(output)).unwrap();
+    assert_eq!(archive.len(), 2);
+    for index in 0..archive.len() {
+        let mut bytes = Vec::new();
+        archive
+            .by_index(index)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(assert_pdf(&bytes, "sheet"), 1);
+    }
+
+    // Thumbnails and page text are produced along with the PDF
+    let output = convert_form(
+        host,
+        Form::new()
+            .part("file", file_part(FLAT_TEXT, "report.fodt"))
+            .text("with_thumbnail", "true")
+            .text("with_text", "true"),
+    )
+    .await;
+    let mut archive = ZipArchive::new(Cursor::new(output)).unwrap();
+    assert_eq!(
+        assert_pdf(&read_entry(&mut archive, "document.pdf"), "document"),
+        3
+    );
+    assert!(read_entry(&mut archive, "thumbnail.png").starts_with(b"\x89PNG"));
+    let text: serde_json::Value =
+        serde_json::from_slice(&read_entry(&mut archive, "text.json")).unwrap();
+    assert_eq!(text.as_array().unwrap().len(), 3);
+    assert!(text[1]["text"].as_str().unwrap().contains("Second page"));
+
+    // Spreadsheet page text is numbered by sheet
+    let output = convert_form(
+        host,
+        Form::new()
+            .part("file", file_part(FLAT_SPREADSHEET, "sheets.fods"))
+            .text("with_text", "true"),
+    )
+    .await;
+    let mut archive = ZipArchive::new(Cursor::new(output)).unwrap();
+    let text: serde_json::Value =
+        serde_json::from_slice(&read_entry(&mut archive, "text.json")).unwrap();
+    assert_eq!(text[1]["sheet"], 2);
+    assert!(text[1]["text"].as_str().unwrap().contains("south"));
+
+    // Other formats are exported by office
+    let output = convert_form(
+        host,
+        Form::new()
+            .part("file", file_part(FLAT_TEXT, "report.fodt"))
+            .text("format", "docx"),
+    )
+    .await;
+    let mut archive = ZipArchive::new(Cursor::new(output)).unwrap();
+    assert!(archive.by_name("word/document.xml").is_ok());
+
+    // Sheets are exported as CSV
+    let output = convert_form(
+        host,
+        Form::new()
+            .part("file", file_part(FLAT_SPREADSHEET, "sheets.fods"))
+            .text("format", "csv")
+            .text("sheets", "2"),
+    )
+    .await;
+    assert_eq!(String::from_utf8_lossy(&output).trim(), "south,80");
+
+    // PDF export options are applied
+    let output = convert_form(
+        host,
+        Form::new()
+            .part("file", file_part(FLAT_TEXT, "report.fodt"))
+            .text("export_options", r#"{"page_range":"2-3"}"#),
+    )
+    .await;
+    assert_eq!(assert_pdf(&output, "page range"), 2);
+}
+
+/// Office is started by the first conversion when idle shutdown is enabled,
+/// it cannot be started again within the process so once idle the server
+/// exits to be restarted cold by its supervisor
+#[tokio::test]
+async fn idle_shutdown_starts_lazily_and_exits_for_restart() {
+    let mut server = Server::start_with(&["--idle-shutdown", "2s"]).await;
+
+    // Cold servers don't exit while idle
+    let status: serde_json::Value = reqwest::get(format!("{}/status", server.host))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["warm"], false);
+
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    assert!(server.process.try_wait().unwrap().is_none());
+
+    let output = convert_form(
+        &server.host,
+        Form::new().part("file", file_part(FLAT_TEXT, "report.fodt")),
+    )
+    .await;
+    assert_pdf(&output, "lazy start");
+
+    let started = std::time::Instant::now();
+    let status = loop {
+        if let Some(status) = server.process.try_wait().unwrap() {
+            break status;
+        }
+
+        assert!(
+            started.elapsed() < Duration::from_secs(60),
+            "server did not exit once idle"
+        );
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    };
+
+    assert!(!status.success(), "server exited with {status}");
+}
+
+/// Terms split across runs of text are redacted and checked against the
+/// text of the exported PDF
+#[tokio::test]
+async fn redaction_removes_terms_split_across_runs() {
+    const DOCUMENT: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document
+    xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0"
+    xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"
+    office:version="1.2"
+    office:mimetype="application/vnd.oasis.opendocument.text">
+  <office:body>
+    <office:text>
+      <text:p>Signed by <text:span>Ja</text:span>ne Doe</text:p>
+    </office:text>
+  </office:body>
+</office:document>
+"#;
+
+    let server = Server::start().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/convert-redact", server.host))
+        .multipart(
+            Form::new()
+                .part("file", file_part(DOCUMENT, "signed.fodt"))
+                .text("terms", "Jane Doe"),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = response.bytes().await.unwrap();
+    assert!(
+        status.is_success(),
+        "redaction failed with {status}: {}",
+        String::from_utf8_lossy(&body)
+    );
+
+    let mut archive = ZipArchive::new(Cursor::new(body)).unwrap();
+    assert_pdf(&read_entry(&mut archive, "document.pdf"), "document.pdf");
+
+    let report: serde_json::Value =
+        serde_json::from_slice(&read_entry(&mut archive, "redactions.json")).unwrap();
+    assert_eq!(report["terms"][0]["matches"], 1);
+}
+
+/// Flat ODF text document with a Basic macro replacing its text
+const MACRO_DOCUMENT: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document
+    xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0"
+    xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"
+    xmlns:script="urn:oasis:names:tc:opendocument:xmlns:script:1.0"
+    xmlns:ooo="http://openoffice.org/2004/office"
+    office:version="1.2"
+    office:mimetype="application/vnd.oasis.opendocument.text">
+  <office:scripts>
+    <office:script script:language="ooo:Basic">
+      <ooo:libraries>
+        <ooo:library-embedded ooo:name="Standard">
+          <ooo:module ooo:name="Report">
+            <ooo:source-code>Sub Stamp
+  ThisComponent.getText().setString("Stamped by the document macro")
+End Sub</ooo:source-code>
+          </ooo:module>
+        </ooo:library-embedded>
+      </ooo:libraries>
+    </office:script>
+  </office:scripts>
+  <office:body>
+    <office:text>
+      <text:p>Unstamped</text:p>
+    </office:text>
+  </office:body>
+</office:document>
+"#;
+
+/// Requested macros are run from the document being converted rather than
+/// the application Basic libraries
+#[tokio::test]
+async fn run_macro_runs_document_macros() {
+    let server = Server::start_with(&["--allow-macros", "--admin-token", "admin-token"]).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/convert", server.host))
+        .header("x-admin-token", "admin-token")
+        .multipart(
+            Form::new()
+                .part("file", file_part(MACRO_DOCUMENT, "macro.fodt"))
+                .text("format", "txt")
+                .text("run_macro", "Standard.Report.Stamp"),
+        )
+        .send()
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = response.bytes().await.unwrap();
+    assert!(
+        status.is_success(),
+        "macro conversion failed with {status}: {}",
+        String::from_utf8_lossy(&body)
+    );
+
+    let text = String::from_utf8_lossy(&body);
+    assert!(text.contains("Stamped by the document macro"), "{text}");
+    assert!(!text.contains("Unstamped"));
+}